@@ -7,7 +7,7 @@ function widget:GetInfo()
         name    = "Agent Bootstrap",
         desc    = "Hands control of configured players to AgentBridge AI",
         author  = "afcomech",
-        version = "0.1",
+        version = "0.2",
         date    = "2026",
         license = "MIT",
         layer   = 0,
@@ -60,3 +60,21 @@ function widget:GameStart()
         Spring.Echo("[AgentBootstrap] No entry for player '" .. tostring(myName) .. "'")
     end
 end
+
+-- Turn mode: a paused engine sends AIs no UPDATE events, so while paused
+-- poke the AgentBridge AIs hosted here to let them poll for end_turn.
+local TURN_HEARTBEAT = "agent_turn_poll"
+
+function widget:Update()
+    local _, _, paused = Spring.GetGameSpeed()
+    if not paused then
+        return
+    end
+    local myPlayerID = Spring.GetMyPlayerID()
+    for _, teamID in ipairs(Spring.GetTeamList()) do
+        local _, _, hostingPlayerID, shortName = Spring.GetAIInfo(teamID)
+        if shortName == "AgentBridge" and hostingPlayerID == myPlayerID then
+            Spring.SendSkirmishAIMessage(teamID, TURN_HEARTBEAT)
+        end
+    end
+end
@@ -0,0 +1,202 @@
+//! Post-game analysis: a summary of one game computed from its recorded
+//! event stream, for the agent to learn from after the game is over.
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::benchmark::{BenchmarkTracker, BridgeCounts};
+use crate::sai_ipc::SaiEvent;
+use sai_protocol::Economy;
+
+/// Width of a damage bucket: one game minute.
+pub const DAMAGE_BUCKET_FRAMES: i32 = 30 * 60;
+
+/// Minimum spacing of economy samples. Updates arrive every second, far
+/// more often than a curve needs.
+pub const ECONOMY_SAMPLE_FRAMES: i32 = 30 * 10;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DamageBucket {
+    pub start_frame: i32,
+    pub dealt: f32,
+    pub taken: f32,
+    /// Paralyzer (EMP) damage, which stuns instead of destroying.
+    pub emp_dealt: f32,
+    pub emp_taken: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EconomySample {
+    pub frame: i32,
+    #[serde(flatten)]
+    pub economy: Economy,
+}
+
+/// Commands sent during the game, from the log's `gm_command` lines.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommandCounts {
+    pub sent: u32,
+    pub by_type: BTreeMap<String, u32>,
+    pub by_source: BTreeMap<String, u32>,
+    /// Commands that couldn't be sent, plus those the bridge reported
+    /// errors for.
+    pub errors: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GameSummary {
+    pub outcome: &'static str,
+    pub frames: i32,
+    /// Finished units by def name.
+    pub units_built: BTreeMap<String, u32>,
+    pub units_lost: BTreeMap<String, u32>,
+    pub enemies_killed: BTreeMap<String, u32>,
+    /// Damage per game minute, with paralyzer damage apart.
+    pub damage: Vec<DamageBucket>,
+    pub economy: Vec<EconomySample>,
+    pub commands: CommandCounts,
+    /// The bridge's counters, when its final release had them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bridge: Option<BridgeCounts>,
+}
+
+/// Summarize a game from its events, in the order they were received.
+/// Events carry no frame of their own; each counts at the frame of the
+/// latest init/update before it.
+pub fn analyze(events: &[SaiEvent]) -> GameSummary {
+    let mut tracker = BenchmarkTracker::default();
+    let mut frame = 0;
+    let mut units_built = BTreeMap::new();
+    let mut units_lost = BTreeMap::new();
+    let mut enemies_killed = BTreeMap::new();
+    let mut damage: Vec<DamageBucket> = Vec::new();
+    let mut economy: Vec<EconomySample> = Vec::new();
+    let mut commands = CommandCounts::default();
+
+    let count = |map: &mut BTreeMap<String, u32>, name: &Option<String>| {
+        *map.entry(name.clone().unwrap_or_else(|| "unknown".into())).or_insert(0) += 1;
+    };
+
+    for event in events {
+        tracker.observe(event);
+        match event {
+            SaiEvent::Init { frame: f, .. } => frame = *f,
+            SaiEvent::Update { frame: f, economy: e, .. } => {
+                frame = *f;
+                let due = economy.last().is_none_or(|s| frame - s.frame >= ECONOMY_SAMPLE_FRAMES);
+                if let (Some(e), true) = (e, due) {
+                    economy.push(EconomySample { frame, economy: *e });
+                }
+            }
+            SaiEvent::UnitFinished { unit_name, .. } => count(&mut units_built, unit_name),
+            SaiEvent::UnitDestroyed { unit_name, .. } | SaiEvent::UnitCaptured { unit_name, .. } => {
+                count(&mut units_lost, unit_name)
+            }
+            SaiEvent::EnemyDestroyed { enemy_name, .. } => count(&mut enemies_killed, enemy_name),
+            SaiEvent::EnemyDamaged { damage: d, paralyzer, .. } => {
+                let b = bucket(&mut damage, frame);
+                *(if *paralyzer { &mut b.emp_dealt } else { &mut b.dealt }) += d;
+            }
+            SaiEvent::UnitDamaged { damage: d, paralyzer, .. } => {
+                let b = bucket(&mut damage, frame);
+                *(if *paralyzer { &mut b.emp_taken } else { &mut b.taken }) += d;
+            }
+            SaiEvent::Unknown { raw } if raw["type"] == crate::command_history::LOG_TYPE => {
+                if raw["status"] != "sent" {
+                    commands.errors += 1;
+                    continue;
+                }
+                commands.sent += 1;
+                let kind = raw["command"]["type"].as_str().unwrap_or("unknown");
+                *commands.by_type.entry(kind.to_string()).or_insert(0) += 1;
+                let source = raw["source"].as_str().unwrap_or("unknown");
+                *commands.by_source.entry(source.to_string()).or_insert(0) += 1;
+            }
+            SaiEvent::CommandError { .. } => commands.errors += 1,
+            _ => {}
+        }
+    }
+
+    GameSummary {
+        outcome: tracker.outcome().as_str(),
+        frames: frame.max(tracker.last_frame),
+        units_built,
+        units_lost,
+        enemies_killed,
+        damage,
+        economy,
+        commands,
+        bridge: tracker.bridge,
+    }
+}
+
+/// The damage bucket covering `frame`, adding empty ones up to it.
+fn bucket(damage: &mut Vec<DamageBucket>, frame: i32) -> &mut DamageBucket {
+    let index = (frame.max(0) / DAMAGE_BUCKET_FRAMES) as usize;
+    while damage.len() <= index {
+        let start_frame = damage.len() as i32 * DAMAGE_BUCKET_FRAMES;
+        damage.push(DamageBucket { start_frame, ..Default::default() });
+    }
+    &mut damage[index]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> Vec<SaiEvent> {
+        let raw = match name {
+            "short_game" => include_str!("../tests/fixtures/sessions/short_game.jsonl"),
+            other => panic!("unknown session fixture '{}'", other),
+        };
+        raw.lines().map(|l| SaiEvent::from_line(l).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_analyze_short_game() {
+        let summary = analyze(&fixture("short_game"));
+        assert_eq!(summary.outcome, "loss");
+        assert_eq!(summary.frames, 3700);
+        assert_eq!(summary.units_built["cloakraid"], 2);
+        assert_eq!(summary.units_built["staticmex"], 1);
+        assert_eq!(summary.units_lost["cloakraid"], 1);
+        assert_eq!(summary.enemies_killed["spiderscout"], 1);
+
+        // Minute 0 is quiet, minute 1 has the skirmish (the EMP hit counts
+        // apart), minute 2 the raider's death.
+        let damage: Vec<(i32, f32, f32, f32)> =
+            summary.damage.iter().map(|b| (b.start_frame, b.dealt, b.taken, b.emp_taken)).collect();
+        assert_eq!(damage, [(0, 0.0, 0.0, 0.0), (1800, 45.0, 20.0, 900.0), (3600, 0.0, 200.0, 0.0)]);
+
+        // The frame-60 update is too close to frame 30 to be sampled.
+        let frames: Vec<i32> = summary.economy.iter().map(|s| s.frame).collect();
+        assert_eq!(frames, [30, 1830]);
+        assert_eq!(summary.economy[1].economy.metal.income, 6.0);
+
+        // The commands logged alongside: one the bridge rejected.
+        assert_eq!(summary.commands.sent, 2);
+        assert_eq!(summary.commands.by_type["fight"], 1);
+        assert_eq!(summary.commands.by_source["tool"], 1);
+        assert_eq!(summary.commands.errors, 1);
+
+        let json = serde_json::to_value(&summary).unwrap();
+        assert_eq!(json["unitsBuilt"]["cloakraid"], 2);
+        assert_eq!(json["economy"][0]["metal"]["storage"], 500.0);
+        assert_eq!(json["damage"][1]["startFrame"], 1800);
+        assert_eq!(json["damage"][1]["empTaken"], 900.0);
+        assert_eq!(json["bridge"]["eventsSent"], 21);
+    }
+
+    #[test]
+    fn test_analyze_empty_stream() {
+        let summary = analyze(&[]);
+        assert_eq!(summary.outcome, "unknown");
+        assert_eq!(summary.frames, 0);
+        assert!(summary.damage.is_empty() && summary.economy.is_empty());
+        assert!(serde_json::to_value(&summary).unwrap().get("bridge").is_none());
+    }
+}
@@ -0,0 +1,319 @@
+//! Army composition for the state stream: what a channel fields, counted by
+//! def and grouped into rough roles, with its metal value and what changed
+//! since the last line.
+//!
+//! Roles come from a table of def-name patterns in the game's profile (see
+//! `profiles`), or the config's `army` for every game. A def no pattern matches falls
+//! back to its engine flags when the channel's unit defs are cached, and to
+//! `other` when they aren't. Like the rest of the stream this only reads
+//! what's cached; it never asks the engine.
+
+use std::collections::{BTreeMap, HashMap};
+
+use serde::Deserialize;
+
+use crate::sai_ipc::{SaiEvent, UnitId};
+use crate::scope::glob_match;
+use crate::unit_defs::{Role, UnitDefCatalog};
+
+/// Role of a def that neither the table nor the engine flags place.
+pub const OTHER: &str = "other";
+
+/// `army` section of the config file and of game profiles.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ArmyConfig {
+    /// Roles in order; a def takes the first whose patterns match its name.
+    /// Order matters: Zero-K's `staticcon` is a builder before it's a
+    /// structure, `staticarty` static defense before artillery.
+    #[serde(default)]
+    pub roles: Vec<RoleRule>,
+}
+
+impl ArmyConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        for rule in &self.roles {
+            if rule.role.trim().is_empty() || rule.defs.is_empty() {
+                return Err("army.roles: every entry needs a role and at least one def pattern".into());
+            }
+        }
+        Ok(())
+    }
+
+    /// The role of def `name`.
+    pub fn role_of(&self, name: &str, catalog: Option<&UnitDefCatalog>) -> String {
+        if let Some(rule) = self.roles.iter().find(|r| r.defs.iter().any(|p| glob_match(p, name))) {
+            return rule.role.clone();
+        }
+        match catalog.and_then(|c| c.named(name)).map(Role::of) {
+            Some(Role::Factory) => "factories".into(),
+            Some(Role::Constructor) => "builders".into(),
+            Some(Role::Structure) => "structures".into(),
+            Some(Role::Unit) | None => OTHER.into(),
+        }
+    }
+}
+
+/// One role and the def names (`*` wildcards) that have it.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RoleRule {
+    pub role: String,
+    pub defs: Vec<String>,
+}
+
+/// The def of each of a channel's units, from its events.
+#[derive(Debug, Default)]
+pub struct ArmyTracker {
+    /// Def name, and whether the unit is finished.
+    units: HashMap<UnitId, (String, bool)>,
+}
+
+impl ArmyTracker {
+    pub fn observe(&mut self, event: &SaiEvent) {
+        let def = |name: &Option<String>| name.clone().unwrap_or_else(|| "unknown".into());
+        match event {
+            SaiEvent::Roster { units, .. } => {
+                self.units = units.iter().map(|u| (u.unit, (def(&u.unit_name), true))).collect();
+            }
+            SaiEvent::UnitCreated { unit, unit_name, .. } => {
+                self.units.insert(*unit, (def(unit_name), false));
+            }
+            SaiEvent::UnitFinished { unit, unit_name, .. } | SaiEvent::UnitGiven { unit, unit_name, .. } => {
+                self.units.insert(*unit, (def(unit_name), true));
+            }
+            SaiEvent::UnitDestroyed { unit, .. } | SaiEvent::UnitCaptured { unit, .. } => {
+                self.units.remove(unit);
+            }
+            _ => {}
+        }
+    }
+
+    /// A unit's def name, finished or not.
+    pub fn def_of(&self, unit: UnitId) -> Option<&str> {
+        self.units.get(&unit).map(|(name, _)| name.as_str()).filter(|name| *name != "unknown")
+    }
+
+    /// Finished units counted by role and def. The metal value is left
+    /// out unless the unit defs are cached.
+    pub fn composition(&self, config: &ArmyConfig, catalog: Option<&UnitDefCatalog>) -> Composition {
+        let mut composition = Composition { metal_value: catalog.map(|_| 0.0), ..Default::default() };
+        for (name, _) in self.units.values().filter(|(_, finished)| *finished) {
+            let role = config.role_of(name, catalog);
+            *composition.roles.entry(role).or_default().entry(name.clone()).or_default() += 1;
+            composition.total += 1;
+            if let (Some(value), Some(def)) = (&mut composition.metal_value, catalog.and_then(|c| c.named(name))) {
+                *value += def.metal_cost;
+            }
+        }
+        composition
+    }
+}
+
+/// A channel's finished units at one point.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Composition {
+    pub total: usize,
+    pub metal_value: Option<f32>,
+    /// Unit counts by role, then def name.
+    pub roles: BTreeMap<String, BTreeMap<String, usize>>,
+}
+
+impl Composition {
+    pub fn count(&self, role: &str) -> usize {
+        self.roles.get(role).map(|defs| defs.values().sum()).unwrap_or(0)
+    }
+
+    /// The army part of the state line, with changes since `previous`.
+    /// Roles go in table order; a role that's gone but was there before
+    /// shows as 0 with its loss.
+    pub fn report(&self, config: &ArmyConfig, previous: Option<&Composition>) -> (String, serde_json::Value) {
+        let mut order: Vec<&str> = config.roles.iter().map(|r| r.role.as_str()).collect();
+        for role in self.roles.keys().chain(previous.iter().flat_map(|p| p.roles.keys())) {
+            if !order.contains(&role.as_str()) {
+                order.push(role);
+            }
+        }
+        let delta = |now: usize, before: usize| now as i64 - before as i64;
+        let signed = |d: i64| if d == 0 { String::new() } else { format!(" ({:+})", d) };
+
+        let mut parts = Vec::new();
+        let mut roles = serde_json::Map::new();
+        for role in order {
+            let count = self.count(role);
+            let change = previous.map(|p| delta(count, p.count(role))).unwrap_or(0);
+            if count == 0 && change == 0 {
+                continue;
+            }
+            parts.push(format!("{} {}{}", role, count, signed(change)));
+            let mut entry = serde_json::json!({
+                "count": count,
+                "defs": self.roles.get(role).cloned().unwrap_or_default(),
+            });
+            if previous.is_some() {
+                entry["delta"] = change.into();
+            }
+            roles.insert(role.to_string(), entry);
+        }
+
+        let mut head = format!("army {}{}", self.total, signed(previous.map(|p| delta(self.total, p.total)).unwrap_or(0)));
+        if let Some(value) = self.metal_value {
+            head.push_str(&format!(" ({:.0} metal)", value));
+        }
+        let mut data = serde_json::json!({
+            "total": self.total,
+            "metalValue": self.metal_value,
+            "roles": roles,
+        });
+        if let Some(previous) = previous {
+            data["delta"] = serde_json::json!({
+                "total": delta(self.total, previous.total),
+                "metalValue": self.metal_value.zip(previous.metal_value).map(|(now, before)| now - before),
+            });
+        }
+        if parts.is_empty() {
+            return (head, data);
+        }
+        (format!("{}: {}", head, parts.join(", ")), data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sai_ipc::{UnitDefId, UnitDefInfo};
+    use sai_protocol::{RosterUnit, WeaponDefId};
+
+    fn roster(units: &[(i32, &str)]) -> SaiEvent {
+        SaiEvent::Roster {
+            frame: 30,
+            units: units
+                .iter()
+                .map(|(id, name)| RosterUnit { unit: UnitId(*id), unit_name: Some(name.to_string()), pos: [0.0; 3] })
+                .collect(),
+        }
+    }
+
+    fn destroyed(id: i32) -> SaiEvent {
+        SaiEvent::UnitDestroyed {
+            unit: UnitId(id), unit_name: None, attacker: UnitId(0), attacker_name: None,
+            attacker_team: None, attacker_relation: None, weapon_def_id: WeaponDefId(1),
+        }
+    }
+
+    fn catalog() -> UnitDefCatalog {
+        let def = |id, name: &str, metal_cost, speed, builder| UnitDefInfo {
+            id: UnitDefId(id),
+            name: name.into(),
+            human_name: name.into(),
+            description: None,
+            metal_cost,
+            energy_cost: 0.0,
+            build_time: 0.0,
+            health: 100.0,
+            speed,
+            builder,
+            build_options: Vec::new(),
+            metal_make: 0.0,
+            energy_make: 0.0,
+            extracts_metal: 0.0,
+            build_speed: 0.0,
+        };
+        UnitDefCatalog::new(vec![
+            def(1, "cloakcon", 120.0, 2.0, true),
+            def(2, "cloakraid", 65.0, 3.0, false),
+            def(3, "staticmex", 50.0, 0.0, false),
+            def(4, "turretlaser", 90.0, 0.0, false),
+            def(5, "staticradar", 55.0, 0.0, false),
+            def(6, "mysterybot", 200.0, 2.0, false),
+        ])
+    }
+
+    /// The Zero-K profile's table.
+    fn zero_k() -> ArmyConfig {
+        crate::profiles::Profiles::default().select("Zero-K v1.12.1.0").army.clone()
+    }
+
+    #[test]
+    fn test_zero_k_roles() {
+        let config = zero_k();
+        let cases = [
+            ("cloakcon", "builders"),
+            ("staticcon", "builders"),
+            ("dyntrainer_strike_base", "builders"),
+            ("factorycloak", "factories"),
+            ("staticmex", "economy"),
+            ("energysolar", "economy"),
+            ("turretlaser", "static_defense"),
+            ("staticarty", "static_defense"),
+            ("gunshipbomb", "air"),
+            ("planefighter", "air"),
+            ("cloakraid", "raiders"),
+            ("spiderscout", "raiders"),
+            ("shieldskirm", "skirmishers"),
+            ("hoverriot", "riots"),
+            ("tankheavyassault", "assaults"),
+            ("cloakarty", "artillery"),
+            ("mysterybot", OTHER),
+        ];
+        for (name, role) in cases {
+            assert_eq!(config.role_of(name, None), role, "{}", name);
+        }
+        // Unmatched defs fall back to their engine flags when known.
+        assert_eq!(config.role_of("staticradar", Some(&catalog())), "structures");
+        assert_eq!(config.role_of("mysterybot", Some(&catalog())), OTHER);
+    }
+
+    #[test]
+    fn test_composition_and_metal_value() {
+        let config = zero_k();
+        let mut army = ArmyTracker::default();
+        army.observe(&roster(&[(1, "cloakcon"), (2, "cloakraid"), (3, "cloakraid"), (4, "staticmex")]));
+        // A nanoframe isn't fielded until it's finished.
+        army.observe(&SaiEvent::UnitCreated {
+            unit: UnitId(5), unit_name: Some("turretlaser".into()), builder: UnitId(1), builder_name: None, pos: None,
+        });
+        let composition = army.composition(&config, Some(&catalog()));
+        assert_eq!(composition.total, 4);
+        assert_eq!(composition.count("raiders"), 2);
+        assert_eq!(composition.count("static_defense"), 0);
+        assert_eq!(composition.metal_value, Some(300.0));
+        assert_eq!(composition.roles["raiders"]["cloakraid"], 2);
+
+        army.observe(&SaiEvent::UnitFinished { unit: UnitId(5), unit_name: Some("turretlaser".into()), pos: None });
+        army.observe(&destroyed(2));
+        let composition = army.composition(&config, None);
+        assert_eq!((composition.total, composition.count("static_defense"), composition.count("raiders")), (4, 1, 1));
+        assert_eq!(composition.metal_value, None, "no metal value without unit defs");
+    }
+
+    #[test]
+    fn test_report_deltas() {
+        let config = zero_k();
+        let mut army = ArmyTracker::default();
+        army.observe(&roster(&[(1, "cloakcon"), (2, "cloakraid"), (3, "cloakraid"), (4, "staticmex")]));
+        let first = army.composition(&config, Some(&catalog()));
+        let (line, data) = first.report(&config, None);
+        assert_eq!(line, "army 4 (300 metal): builders 1, economy 1, raiders 2");
+        assert!(data.get("delta").is_none());
+
+        army.observe(&destroyed(2));
+        army.observe(&destroyed(3));
+        army.observe(&SaiEvent::UnitFinished { unit: UnitId(6), unit_name: Some("turretlaser".into()), pos: None });
+        army.observe(&SaiEvent::UnitFinished { unit: UnitId(7), unit_name: Some("mysterybot".into()), pos: None });
+        let second = army.composition(&config, Some(&catalog()));
+        let (line, data) = second.report(&config, Some(&first));
+        assert_eq!(line, "army 4 (460 metal): builders 1, economy 1, static_defense 1 (+1), raiders 0 (-2), other 1 (+1)");
+        assert_eq!(data["delta"]["total"], 0);
+        assert_eq!(data["delta"]["metalValue"], 160.0);
+        assert_eq!(data["roles"]["raiders"]["delta"], -2);
+        assert_eq!(data["roles"]["other"]["defs"]["mysterybot"], 1);
+        assert_eq!(data["roles"]["builders"]["delta"], 0);
+
+        // A custom table for another game; unmatched units land in other.
+        let custom = ArmyConfig { roles: vec![RoleRule { role: "workers".into(), defs: vec!["*con".into()] }] };
+        let (line, _) = army.composition(&custom, None).report(&custom, None);
+        assert_eq!(line, "army 4: workers 1, other 3");
+        assert!(ArmyConfig { roles: vec![RoleRule { role: "x".into(), defs: vec![] }] }.validate().is_err());
+    }
+}
@@ -0,0 +1,211 @@
+//! `game_assist`: put every idle constructor on one job at once — helping
+//! a factory, reclaiming an area, or repairing one.
+//!
+//! Which builders are idle comes from the channel's [`IdleWatch`]; this
+//! module only picks the constructors and writes their orders, so it can be
+//! tested over made-up rosters.
+//!
+//! [`IdleWatch`]: crate::idle_builders::IdleWatch
+
+use crate::idle_builders::BuilderKind;
+use crate::sai_ipc::{SaiCommand, UnitId};
+
+/// Engine command id for area repair: the plain repair command with a
+/// position and radius as its params. The protocol's `repair` takes only a
+/// unit, so it goes out as `custom`.
+const CMD_REPAIR: i32 = 40;
+
+/// Radius around each builder when no area is given, in elmos.
+pub const DEFAULT_RADIUS: f32 = 400.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AssistMode {
+    /// Guard (assist) a factory.
+    Factory,
+    Reclaim,
+    RepairArea,
+}
+
+impl AssistMode {
+    pub fn parse(mode: &str) -> Result<Self, String> {
+        match mode {
+            "factory" => Ok(AssistMode::Factory),
+            "reclaim" => Ok(AssistMode::Reclaim),
+            "repair_area" => Ok(AssistMode::RepairArea),
+            other => Err(format!("Unknown mode '{}' (expected factory, reclaim or repair_area)", other)),
+        }
+    }
+}
+
+/// A circle on the map.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Area {
+    pub x: f32,
+    pub z: f32,
+    pub radius: f32,
+}
+
+/// One of our builders as the idle watch knows it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Builder {
+    pub unit: UnitId,
+    pub kind: BuilderKind,
+    pub pos: Option<[f32; 3]>,
+    pub idle: bool,
+}
+
+/// One idle constructor's new order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Task {
+    pub unit: UnitId,
+    pub command: SaiCommand,
+    /// What it was told to do, for the reply.
+    pub text: String,
+}
+
+fn distance(a: [f32; 3], b: [f32; 3]) -> f32 {
+    (a[0] - b[0]).hypot(a[2] - b[2])
+}
+
+/// Orders for every idle constructor in `builders`, in unit order.
+/// `target` is the factory to assist (the nearest known one without it);
+/// `area` where to reclaim or repair (around each builder without it).
+/// `ground` is the ground height at an x/z, for the area's centre.
+pub fn plan(
+    mode: AssistMode,
+    builders: &[Builder],
+    target: Option<UnitId>,
+    area: Option<Area>,
+    ground: impl Fn(f32, f32) -> f32,
+) -> Result<Vec<Task>, String> {
+    if target.is_some() && mode != AssistMode::Factory {
+        return Err("target is only for mode factory; reclaim and repair_area take an area".into());
+    }
+    if area.is_some() && mode == AssistMode::Factory {
+        return Err("area is only for modes reclaim and repair_area".into());
+    }
+    let mut idle: Vec<&Builder> = builders.iter().filter(|b| b.idle && b.kind == BuilderKind::Constructor).collect();
+    idle.sort_by_key(|b| b.unit);
+    let mut factories: Vec<&Builder> = builders.iter().filter(|b| b.kind == BuilderKind::Factory).collect();
+    factories.sort_by_key(|b| b.unit);
+
+    let mut tasks = Vec::new();
+    for builder in idle {
+        let unit = builder.unit;
+        let task = match mode {
+            AssistMode::Factory => {
+                let factory = match target {
+                    Some(target) => target,
+                    None => nearest(builder, &factories)
+                        .ok_or("No factory of ours is known; give target")?,
+                };
+                Task {
+                    unit,
+                    command: SaiCommand::Guard { unit_id: unit, guard_id: factory, queue: false },
+                    text: format!("{} assists factory {}", unit, factory),
+                }
+            }
+            AssistMode::Reclaim | AssistMode::RepairArea => {
+                let (centre, radius) = match (area, builder.pos) {
+                    (Some(a), _) => ([a.x, ground(a.x, a.z), a.z], a.radius),
+                    (None, Some(pos)) => (pos, DEFAULT_RADIUS),
+                    // Nowhere to centre it: leave the unit be.
+                    (None, None) => continue,
+                };
+                let [x, y, z] = centre;
+                let (command, verb) = if mode == AssistMode::Reclaim {
+                    (SaiCommand::ReclaimArea { unit_id: unit, x, y, z, radius, queue: false }, "reclaims")
+                } else {
+                    let params = vec![x, y, z, radius];
+                    (SaiCommand::Custom { unit_id: unit, command_id: CMD_REPAIR, params, queue: false }, "repairs")
+                };
+                Task {
+                    unit,
+                    command,
+                    text: format!("{} {} within {:.0} of ({:.0}, {:.0})", unit, verb, radius, centre[0], centre[2]),
+                }
+            }
+        };
+        tasks.push(task);
+    }
+    Ok(tasks)
+}
+
+/// The factory nearest `builder`; the first one if either position is
+/// unknown.
+fn nearest(builder: &Builder, factories: &[&Builder]) -> Option<UnitId> {
+    let by_distance = builder.pos.and_then(|pos| {
+        factories
+            .iter()
+            .filter_map(|f| Some((f.unit, distance(pos, f.pos?))))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(unit, _)| unit)
+    });
+    by_distance.or_else(|| factories.first().map(|f| f.unit))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn builder(unit: i32, kind: BuilderKind, x: f32, idle: bool) -> Builder {
+        Builder { unit: UnitId(unit), kind, pos: Some([x, 10.0, 0.0]), idle }
+    }
+
+    /// Idle constructors 5 and 7 near opposite factories, busy constructor
+    /// 6, and factories 20 (at x 0) and 21 (at x 2000).
+    fn roster() -> Vec<Builder> {
+        vec![
+            builder(7, BuilderKind::Constructor, 1800.0, true),
+            builder(5, BuilderKind::Constructor, 100.0, true),
+            builder(6, BuilderKind::Constructor, 100.0, false),
+            builder(20, BuilderKind::Factory, 0.0, true),
+            builder(21, BuilderKind::Factory, 2000.0, false),
+        ]
+    }
+
+    fn units(tasks: &[Task]) -> Vec<UnitId> {
+        tasks.iter().map(|t| t.unit).collect()
+    }
+
+    #[test]
+    fn test_idle_constructors_assist_nearest_factory() {
+        let tasks = plan(AssistMode::Factory, &roster(), None, None, |_, _| 0.0).unwrap();
+        assert_eq!(units(&tasks), [UnitId(5), UnitId(7)]);
+        assert_eq!(tasks[0].command, SaiCommand::Guard { unit_id: UnitId(5), guard_id: UnitId(20), queue: false });
+        assert_eq!(tasks[1].text, "7 assists factory 21");
+
+        let tasks = plan(AssistMode::Factory, &roster(), Some(UnitId(21)), None, |_, _| 0.0).unwrap();
+        assert!(tasks.iter().all(|t| matches!(t.command, SaiCommand::Guard { guard_id: UnitId(21), .. })));
+
+        let no_factories: Vec<Builder> = roster().into_iter().filter(|b| b.kind == BuilderKind::Constructor).collect();
+        assert_eq!(
+            plan(AssistMode::Factory, &no_factories, None, None, |_, _| 0.0).unwrap_err(),
+            "No factory of ours is known; give target"
+        );
+        assert!(plan(AssistMode::Factory, &[], None, None, |_, _| 0.0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_area_orders() {
+        let area = Area { x: 500.0, z: 600.0, radius: 300.0 };
+        let tasks = plan(AssistMode::Reclaim, &roster(), None, Some(area), |_, _| 42.0).unwrap();
+        assert_eq!(
+            tasks[0].command,
+            SaiCommand::ReclaimArea { unit_id: UnitId(5), x: 500.0, y: 42.0, z: 600.0, radius: 300.0, queue: false }
+        );
+        assert_eq!(tasks[1].text, "7 reclaims within 300 of (500, 600)");
+
+        // Without an area, each works around itself; one with no known
+        // position is left out.
+        let mut builders = roster();
+        builders[1].pos = None;
+        let tasks = plan(AssistMode::RepairArea, &builders, None, None, |_, _| 0.0).unwrap();
+        assert_eq!(units(&tasks), [UnitId(7)]);
+        assert!(matches!(&tasks[0].command, SaiCommand::Custom { command_id: CMD_REPAIR, params, .. } if params[3] == DEFAULT_RADIUS));
+
+        assert!(plan(AssistMode::Reclaim, &roster(), Some(UnitId(20)), None, |_, _| 0.0).is_err());
+        assert!(plan(AssistMode::Factory, &roster(), None, Some(area), |_, _| 0.0).is_err());
+        assert!(AssistMode::parse("patrol").is_err());
+    }
+}
@@ -0,0 +1,290 @@
+//! Audit log: every action taken through the GameManager, one JSON line
+//! each, in `audit/audit.jsonl` under the write dir. That covers tool
+//! calls, channel operations (open, close, publish), what the GameManager
+//! does on its own (auto-join, auto-respond) and the commands the command
+//! policy stopped, with their outcomes.
+//!
+//! Fields whose names contain a redacted word (password, token, ...) are
+//! replaced before anything is written. Lines go through a buffered writer
+//! that the main loop flushes on its tick. When the file outgrows
+//! `max_bytes` it rotates to `audit.1.jsonl`, `audit.2.jsonl`, ...
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+/// What a redacted value is replaced with.
+pub const REDACTED: &str = "[redacted]";
+
+/// Config file `audit`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AuditConfig {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// Size at which the log rotates.
+    #[serde(default = "default_max_bytes")]
+    pub max_bytes: u64,
+    /// Rotated files kept; older ones are deleted.
+    #[serde(default = "default_keep")]
+    pub keep: usize,
+    /// Field names containing any of these (ignoring case) are redacted.
+    #[serde(default = "default_redact")]
+    pub redact: Vec<String>,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_enabled(),
+            max_bytes: default_max_bytes(),
+            keep: default_keep(),
+            redact: default_redact(),
+        }
+    }
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_max_bytes() -> u64 {
+    10 * 1024 * 1024
+}
+
+fn default_keep() -> usize {
+    3
+}
+
+fn default_redact() -> Vec<String> {
+    ["password", "token", "secret"].map(String::from).to_vec()
+}
+
+/// What kind of action a line records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// `tools/call`.
+    Tool,
+    /// `channels/open`, `channels/close` or `channels/publish`.
+    Channel,
+    /// Joining a battle for `auto_join_founders`.
+    AutoJoin,
+    /// An auto-respond rule firing.
+    AutoRespond,
+    /// A command the command policy stopped.
+    Policy,
+}
+
+impl Kind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Kind::Tool => "tool",
+            Kind::Channel => "channel",
+            Kind::AutoJoin => "auto_join",
+            Kind::AutoRespond => "auto_respond",
+            Kind::Policy => "policy",
+        }
+    }
+}
+
+/// `value` with every field named like a `redact` word replaced.
+pub fn redact(value: &serde_json::Value, redact: &[String]) -> serde_json::Value {
+    match value {
+        serde_json::Value::Object(map) => map
+            .iter()
+            .map(|(key, v)| {
+                let key_lower = key.to_lowercase();
+                let v = if redact.iter().any(|word| key_lower.contains(&word.to_lowercase())) {
+                    REDACTED.into()
+                } else {
+                    self::redact(v, redact)
+                };
+                (key.clone(), v)
+            })
+            .collect(),
+        serde_json::Value::Array(items) => items.iter().map(|v| self::redact(v, redact)).collect(),
+        other => other.clone(),
+    }
+}
+
+/// The audit file and its rotations.
+pub struct AuditLog {
+    dir: PathBuf,
+    config: AuditConfig,
+    writer: BufWriter<File>,
+    /// Bytes in the current file, written or buffered.
+    size: u64,
+}
+
+impl AuditLog {
+    /// Open (appending to) the audit file in `dir`.
+    pub fn open(dir: &Path, config: AuditConfig) -> std::io::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        let (writer, size) = Self::open_current(dir)?;
+        Ok(Self { dir: dir.to_path_buf(), config, writer, size })
+    }
+
+    fn open_current(dir: &Path) -> std::io::Result<(BufWriter<File>, u64)> {
+        let file = OpenOptions::new().create(true).append(true).open(dir.join("audit.jsonl"))?;
+        let size = file.metadata()?.len();
+        Ok((BufWriter::new(file), size))
+    }
+
+    /// The current file (0) or a rotated one.
+    fn path(&self, n: usize) -> PathBuf {
+        match n {
+            0 => self.dir.join("audit.jsonl"),
+            n => self.dir.join(format!("audit.{}.jsonl", n)),
+        }
+    }
+
+    /// Append an action. `name` is the tool or method, `details` its
+    /// arguments (redacted here). Failing to write is logged, not returned:
+    /// auditing never fails the action.
+    pub fn record(&mut self, kind: Kind, name: &str, details: &serde_json::Value, outcome: &Result<(), String>) {
+        let line = serde_json::json!({
+            "at": chrono::Utc::now().to_rfc3339(),
+            "kind": kind.as_str(),
+            "name": name,
+            "details": redact(details, &self.config.redact),
+            "outcome": if outcome.is_ok() { "ok" } else { "error" },
+            "error": outcome.as_ref().err(),
+        })
+        .to_string();
+        if let Err(e) = self.write_line(&line) {
+            tracing::warn!("Failed to write audit log: {}", e);
+        }
+    }
+
+    fn write_line(&mut self, line: &str) -> std::io::Result<()> {
+        let len = line.len() as u64 + 1;
+        if self.size > 0 && self.size + len > self.config.max_bytes {
+            self.rotate()?;
+        }
+        writeln!(self.writer, "{}", line)?;
+        self.size += len;
+        Ok(())
+    }
+
+    /// Shift `audit.jsonl` to `audit.1.jsonl` (and so on) and start afresh.
+    fn rotate(&mut self) -> std::io::Result<()> {
+        self.writer.flush()?;
+        if self.config.keep == 0 {
+            std::fs::remove_file(self.path(0))?;
+        } else {
+            for n in (0..self.config.keep).rev() {
+                let from = self.path(n);
+                if from.exists() {
+                    std::fs::rename(&from, self.path(n + 1))?;
+                }
+            }
+        }
+        (self.writer, self.size) = Self::open_current(&self.dir)?;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) {
+        if let Err(e) = self.writer.flush() {
+            tracing::warn!("Failed to flush audit log: {}", e);
+        }
+    }
+
+    /// The newest `lines` lines, oldest first, reading into rotated files
+    /// as needed.
+    pub fn tail(&mut self, lines: usize) -> std::io::Result<Vec<String>> {
+        self.writer.flush()?;
+        let mut tail: Vec<String> = Vec::new();
+        for n in 0..=self.config.keep {
+            if tail.len() >= lines {
+                break;
+            }
+            let raw = match std::fs::read_to_string(self.path(n)) {
+                Ok(raw) => raw,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => break,
+                Err(e) => return Err(e),
+            };
+            let wanted = lines - tail.len();
+            let file_lines: Vec<&str> = raw.lines().collect();
+            let newest = &file_lines[file_lines.len().saturating_sub(wanted)..];
+            tail.splice(0..0, newest.iter().map(|l| l.to_string()));
+        }
+        Ok(tail)
+    }
+}
+
+impl Drop for AuditLog {
+    fn drop(&mut self) {
+        let _ = self.writer.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redaction() {
+        let words = default_redact();
+        let args = serde_json::json!({
+            "username": "agent",
+            "password": "hunter2",
+            "Lobby_Password": "x",
+            "nested": {"api_token": "abc", "tokens": [1, 2], "map": "Tundra"},
+            "list": [{"client_secret": "s", "name": "n"}]
+        });
+        assert_eq!(
+            redact(&args, &words),
+            serde_json::json!({
+                "username": "agent",
+                "password": REDACTED,
+                "Lobby_Password": REDACTED,
+                "nested": {"api_token": REDACTED, "tokens": REDACTED, "map": "Tundra"},
+                "list": [{"client_secret": REDACTED, "name": "n"}]
+            })
+        );
+        // Only keys are matched, not values.
+        let text = serde_json::json!({"text": "my password is hunter2"});
+        assert_eq!(redact(&text, &words), text);
+        assert_eq!(redact(&serde_json::json!({"pin": 1}), &["PIN".into()]), serde_json::json!({"pin": REDACTED}));
+    }
+
+    #[test]
+    fn test_rotation_and_tail() {
+        let dir = std::env::temp_dir().join(format!("gm-audit-{}", uuid::Uuid::new_v4()));
+        let config = AuditConfig { max_bytes: 600, keep: 1, ..Default::default() };
+        let mut log = AuditLog::open(&dir, config.clone()).unwrap();
+        for battle_id in 0..12 {
+            let outcome = if battle_id == 11 { Err("Not connected".to_string()) } else { Ok(()) };
+            log.record(
+                Kind::Tool,
+                "lobby_join_battle",
+                &serde_json::json!({"battle_id": battle_id, "password": "secret"}),
+                &outcome,
+            );
+        }
+        assert!(dir.join("audit.1.jsonl").exists());
+        assert!(!dir.join("audit.2.jsonl").exists(), "only `keep` rotations are kept");
+
+        let tail = log.tail(3).unwrap();
+        let lines: Vec<serde_json::Value> = tail.iter().map(|l| serde_json::from_str(l).unwrap()).collect();
+        let ids: Vec<i64> = lines.iter().map(|l| l["details"]["battle_id"].as_i64().unwrap()).collect();
+        assert_eq!(ids, [9, 10, 11]);
+        assert_eq!(lines[2]["kind"], "tool");
+        assert_eq!(lines[2]["details"]["password"], REDACTED);
+        assert_eq!((lines[2]["outcome"].as_str(), lines[2]["error"].as_str()), (Some("error"), Some("Not connected")));
+        assert_eq!((lines[1]["outcome"].as_str(), lines[1]["error"].as_str()), (Some("ok"), None));
+
+        // A tail longer than the current file reaches into the rotated one,
+        // and a reopened log appends.
+        let all = log.tail(100).unwrap();
+        assert!(all.len() > tail.len() && all.len() < 12);
+        assert_eq!(all.last(), tail.last());
+        drop(log);
+        let mut log = AuditLog::open(&dir, config).unwrap();
+        log.record(Kind::AutoJoin, "lobby_join_battle", &serde_json::json!({}), &Ok(()));
+        assert_eq!(log.tail(2).unwrap()[0], tail[2]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
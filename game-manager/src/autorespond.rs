@@ -0,0 +1,277 @@
+//! Auto-respond rules: in-game chat that matches a configured pattern gets
+//! a canned reply or a map marker straight from the GameManager, without a
+//! round trip through the agent. The original message is still forwarded,
+//! and each automatic action is reported to the agent as well.
+
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::sai_ipc::{ChatDestination, SaiCommand};
+
+/// Minimum time between two firings of one rule on one channel. Keeps a
+/// rule from answering itself when its reply matches its own pattern.
+pub const DEFAULT_COOLDOWN_SECS: u64 = 10;
+
+/// One rule as written in the config file. `reply`, `marker` and the
+/// marker's fields may use `$1`/`${name}` for the pattern's captures.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct RuleConfig {
+    pub pattern: String,
+    #[serde(default)]
+    pub reply: Option<String>,
+    #[serde(default)]
+    pub destination: ChatDestination,
+    #[serde(default)]
+    pub marker: Option<MarkerConfig>,
+    #[serde(default = "default_cooldown")]
+    pub cooldown_secs: u64,
+}
+
+/// A map marker. Coordinates are strings so they can come from captures.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct MarkerConfig {
+    pub x: String,
+    pub z: String,
+    #[serde(default)]
+    pub label: String,
+}
+
+fn default_cooldown() -> u64 {
+    DEFAULT_COOLDOWN_SECS
+}
+
+struct Rule {
+    pattern: Regex,
+    config: RuleConfig,
+}
+
+/// What a rule did for one message.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AutoResponse {
+    pub rule: usize,
+    pub pattern: String,
+    pub commands: Vec<SaiCommand>,
+}
+
+impl AutoResponse {
+    /// One line per action, for the notice sent to the agent.
+    pub fn describe(&self) -> String {
+        self.commands
+            .iter()
+            .map(|cmd| match cmd {
+                SaiCommand::SendChat { text, destination } => {
+                    format!("said to {}: {}", destination.as_str(), text)
+                }
+                SaiCommand::DrawPoint { x, z, label } => {
+                    format!("marked ({:.0}, {:.0}): {}", x, z, label)
+                }
+                other => other.type_name().to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+}
+
+/// The compiled rules plus per-channel state.
+#[derive(Default)]
+pub struct AutoResponder {
+    rules: Vec<Rule>,
+    last_fired: HashMap<(String, usize), Instant>,
+    disabled: HashSet<String>,
+}
+
+impl AutoResponder {
+    /// Compile the configured rules. A bad pattern or a rule with nothing
+    /// to do is an error, so mistakes show up at startup.
+    pub fn new(configs: Vec<RuleConfig>) -> Result<Self, String> {
+        let mut rules = Vec::new();
+        for (i, config) in configs.into_iter().enumerate() {
+            if config.reply.is_none() && config.marker.is_none() {
+                return Err(format!("auto_respond rule {} has neither reply nor marker", i));
+            }
+            let pattern = Regex::new(&config.pattern)
+                .map_err(|e| format!("auto_respond rule {}: {}", i, e))?;
+            rules.push(Rule { pattern, config });
+        }
+        Ok(Self { rules, ..Default::default() })
+    }
+
+    /// Swap in reloaded rules, keeping these if any is bad. Channels keep
+    /// their on/off switch; cooldowns start over, as rules may have moved.
+    pub fn replace_rules(&mut self, configs: Vec<RuleConfig>) -> Result<(), String> {
+        self.rules = Self::new(configs)?.rules;
+        self.last_fired.clear();
+        Ok(())
+    }
+
+    pub fn rule_count(&self) -> usize {
+        self.rules.len()
+    }
+
+    /// Rules apply to every channel unless turned off for it.
+    pub fn set_enabled(&mut self, channel_id: &str, enabled: bool) {
+        if enabled {
+            self.disabled.remove(channel_id);
+        } else {
+            self.disabled.insert(channel_id.to_string());
+        }
+    }
+
+    pub fn is_enabled(&self, channel_id: &str) -> bool {
+        !self.disabled.contains(channel_id)
+    }
+
+    pub fn close_channel(&mut self, channel_id: &str) {
+        self.disabled.remove(channel_id);
+        self.last_fired.retain(|(c, _), _| c != channel_id);
+    }
+
+    /// Match a chat message against the rules. The first matching rule
+    /// that is not cooling down fires.
+    pub fn respond(&mut self, channel_id: &str, text: &str, now: Instant) -> Option<AutoResponse> {
+        if !self.is_enabled(channel_id) {
+            return None;
+        }
+        for (i, rule) in self.rules.iter().enumerate() {
+            let Some(caps) = rule.pattern.captures(text) else { continue };
+            let key = (channel_id.to_string(), i);
+            let cooldown = Duration::from_secs(rule.config.cooldown_secs);
+            if self.last_fired.get(&key).is_some_and(|t| now.duration_since(*t) < cooldown) {
+                continue;
+            }
+            let expand = |template: &str| {
+                let mut out = String::new();
+                caps.expand(template, &mut out);
+                out
+            };
+
+            let mut commands = Vec::new();
+            if let Some(reply) = &rule.config.reply {
+                commands.push(SaiCommand::SendChat {
+                    text: expand(reply),
+                    destination: rule.config.destination,
+                });
+            }
+            if let Some(marker) = &rule.config.marker {
+                match (expand(&marker.x).trim().parse(), expand(&marker.z).trim().parse()) {
+                    (Ok(x), Ok(z)) => commands.push(SaiCommand::DrawPoint {
+                        x,
+                        z,
+                        label: expand(&marker.label),
+                    }),
+                    _ => tracing::warn!(
+                        "auto_respond rule {}: marker position is not numeric for '{}'",
+                        i, text
+                    ),
+                }
+            }
+            if commands.is_empty() {
+                continue;
+            }
+            self.last_fired.insert(key, now);
+            return Some(AutoResponse {
+                rule: i,
+                pattern: rule.config.pattern.clone(),
+                commands,
+            });
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn responder(rules: serde_json::Value) -> AutoResponder {
+        AutoResponder::new(serde_json::from_value(rules).unwrap()).unwrap()
+    }
+
+    fn chat(response: &AutoResponse) -> (&str, ChatDestination) {
+        match &response.commands[0] {
+            SaiCommand::SendChat { text, destination } => (text, *destination),
+            other => panic!("expected send_chat, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_reply_with_captures() {
+        let mut r = responder(serde_json::json!([
+            {"pattern": "(?i)^start\\?*$", "reply": "ready when you are"},
+            {"pattern": "(?i)need (?P<what>\\w+)", "reply": "sending ${what}", "destination": "allies"},
+        ]));
+        let now = Instant::now();
+        let response = r.respond("game:local-1", "START??", now).unwrap();
+        assert_eq!(response.rule, 0);
+        assert_eq!(chat(&response), ("ready when you are", ChatDestination::All));
+
+        let response = r.respond("game:local-1", "we need energy now", now).unwrap();
+        assert_eq!(chat(&response), ("sending energy", ChatDestination::Allies));
+        assert_eq!(response.describe(), "said to allies: sending energy");
+
+        assert!(r.respond("game:local-1", "gg", now).is_none());
+    }
+
+    #[test]
+    fn test_marker_from_captures() {
+        let mut r = responder(serde_json::json!([
+            {"pattern": "^help at (\\d+) (\\d+)", "marker": {"x": "$1", "z": "$2", "label": "coming"}},
+        ]));
+        let now = Instant::now();
+        let response = r.respond("game:local-1", "help at 1200 800", now).unwrap();
+        assert_eq!(
+            response.commands,
+            vec![SaiCommand::DrawPoint { x: 1200.0, z: 800.0, label: "coming".into() }]
+        );
+        assert_eq!(response.describe(), "marked (1200, 800): coming");
+    }
+
+    #[test]
+    fn test_cooldown_and_channel_toggle() {
+        let mut r = responder(serde_json::json!([
+            {"pattern": "^start\\?$", "reply": "yes", "cooldown_secs": 5},
+        ]));
+        let now = Instant::now();
+        assert!(r.respond("game:local-1", "start?", now).is_some());
+        assert!(r.respond("game:local-1", "start?", now + Duration::from_secs(2)).is_none());
+        // Cooldowns are per channel.
+        assert!(r.respond("game:local-2", "start?", now).is_some());
+        assert!(r.respond("game:local-1", "start?", now + Duration::from_secs(6)).is_some());
+
+        r.set_enabled("game:local-2", false);
+        assert!(r.respond("game:local-2", "start?", now + Duration::from_secs(60)).is_none());
+        r.close_channel("game:local-2");
+        assert!(r.is_enabled("game:local-2"));
+    }
+
+    #[test]
+    fn test_bad_rules_rejected() {
+        let bad = |rules: serde_json::Value| {
+            AutoResponder::new(serde_json::from_value(rules).unwrap()).err().unwrap()
+        };
+        assert!(bad(serde_json::json!([{"pattern": "(", "reply": "x"}])).starts_with("auto_respond rule 0:"));
+        assert_eq!(
+            bad(serde_json::json!([{"pattern": "x"}])),
+            "auto_respond rule 0 has neither reply nor marker"
+        );
+    }
+
+    #[test]
+    fn test_replace_rules() {
+        let mut r = responder(serde_json::json!([{"pattern": "^start\\?$", "reply": "yes"}]));
+        r.set_enabled("game:local-2", false);
+        let bad = serde_json::from_value(serde_json::json!([{"pattern": "(", "reply": "x"}])).unwrap();
+        assert!(r.replace_rules(bad).is_err());
+        assert_eq!(r.rule_count(), 1, "a bad reload keeps the old rules");
+
+        let rules = serde_json::from_value(serde_json::json!([{"pattern": "^gg$", "reply": "gg wp"}])).unwrap();
+        r.replace_rules(rules).unwrap();
+        let now = Instant::now();
+        assert!(r.respond("game:local-1", "start?", now).is_none());
+        assert_eq!(chat(&r.respond("game:local-1", "gg", now).unwrap()).0, "gg wp");
+        assert!(!r.is_enabled("game:local-2"));
+    }
+}
@@ -0,0 +1,489 @@
+//! Benchmark runs: headless games at unlocked speed, played to completion
+//! and summarized, for comparing agent strategies and opponent AIs.
+//!
+//! The GameManager drives the engines from its tick, one game of a run at
+//! a time; this module holds the pure parts — argument parsing, what a run
+//! keeps from the event stream, its progress, and the report.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::engine::GameStatus;
+use crate::sai_ipc::SaiEvent;
+use sai_protocol::{BridgeStats, Economy};
+
+/// Wall-clock limit per game unless the tool call sets `timeout_secs`.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// Games one run may play; at the default timeout this is already more
+/// than a day of engine time.
+pub const MAX_GAMES: usize = 50;
+
+/// Finished runs kept for `game_benchmark_status`; their reports stay on
+/// disk.
+pub const KEEP_FINISHED: usize = 10;
+
+/// Push event carrying a finished run's report.
+pub const EVENT: &str = "gm.benchmark_finished";
+
+/// Engine release reasons (SReleaseEvent).
+const RELEASE_GAME_ENDED: i32 = 1;
+const RELEASE_TEAM_DIED: i32 = 2;
+
+/// One game to run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchmarkSpec {
+    pub map: String,
+    pub game: String,
+    pub opponent: String,
+}
+
+impl BenchmarkSpec {
+    /// Parse `game_run_benchmark` arguments: either a `games` list of
+    /// `{map, game?, opponent?}`, or one `map`/`game`/`opponent` repeated
+    /// `runs` times. Top-level `game`/`opponent` are defaults for the list.
+    pub fn from_args(args: &serde_json::Value) -> Result<Vec<Self>, String> {
+        let str_arg = |v: &serde_json::Value, key: &str| {
+            v.get(key).and_then(|v| v.as_str()).map(String::from)
+        };
+        let game = str_arg(args, "game").unwrap_or_else(|| "Zero-K $VERSION".into());
+        let opponent = str_arg(args, "opponent").unwrap_or_else(|| crate::opponents::DEFAULT_OPPONENT.into());
+        let spec = |v: &serde_json::Value| -> Result<Self, String> {
+            Ok(Self {
+                map: str_arg(v, "map").ok_or("Missing map name")?,
+                game: str_arg(v, "game").unwrap_or_else(|| game.clone()),
+                opponent: str_arg(v, "opponent").unwrap_or_else(|| opponent.clone()),
+            })
+        };
+
+        let specs = match args.get("games") {
+            Some(serde_json::Value::Array(games)) => {
+                games.iter().map(spec).collect::<Result<Vec<_>, _>>()?
+            }
+            Some(_) => return Err("games must be a list".into()),
+            None => {
+                let runs = args.get("runs").and_then(|v| v.as_u64()).unwrap_or(1);
+                if runs > MAX_GAMES as u64 {
+                    return Err(format!("At most {} games per benchmark run, got {}", MAX_GAMES, runs));
+                }
+                vec![spec(args)?; runs as usize]
+            }
+        };
+        if specs.is_empty() {
+            return Err("Nothing to run".into());
+        }
+        if specs.len() > MAX_GAMES {
+            return Err(format!("At most {} games per benchmark run, got {}", MAX_GAMES, specs.len()));
+        }
+        Ok(specs)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Win,
+    Loss,
+    /// The game ended without the engine saying who won.
+    Unknown,
+    Timeout,
+    Crashed,
+}
+
+impl Outcome {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Outcome::Win => "win",
+            Outcome::Loss => "loss",
+            Outcome::Unknown => "unknown",
+            Outcome::Timeout => "timeout",
+            Outcome::Crashed => "crashed",
+        }
+    }
+}
+
+/// The bridge's counters from its final release, as channel metadata and
+/// game summaries show them.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BridgeCounts {
+    pub frames: u32,
+    pub events_sent: u64,
+    pub events_dropped: u64,
+    pub panics: u32,
+}
+
+impl From<BridgeStats> for BridgeCounts {
+    fn from(stats: BridgeStats) -> Self {
+        Self {
+            frames: stats.frames,
+            events_sent: stats.events_sent,
+            events_dropped: stats.events_dropped,
+            panics: stats.panics,
+        }
+    }
+}
+
+/// What a run keeps from its channel's event stream.
+#[derive(Debug, Default)]
+pub struct BenchmarkTracker {
+    pub last_frame: i32,
+    pub economy: Option<Economy>,
+    release_reason: Option<i32>,
+    /// From the bridge's final release; older bridges send none.
+    pub bridge: Option<BridgeCounts>,
+}
+
+impl BenchmarkTracker {
+    pub fn observe(&mut self, event: &SaiEvent) {
+        match event {
+            SaiEvent::Update { frame, economy, .. } => {
+                self.last_frame = self.last_frame.max(*frame);
+                if economy.is_some() {
+                    self.economy = *economy;
+                }
+            }
+            // One release carrying the reason and the bridge's counters.
+            // Older bridges sent the engine's release first, then a
+            // shutdown notice of their own (reason 0).
+            SaiEvent::Release { reason, stats } => {
+                if *reason != 0 && self.release_reason.is_none() {
+                    self.release_reason = Some(*reason);
+                }
+                if let Some(stats) = stats {
+                    self.bridge = Some((*stats).into());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// The agent lost if its team died; a game that ended with the team
+    /// still alive was won (1v1 — the opponent's team died).
+    pub fn outcome(&self) -> Outcome {
+        match self.release_reason {
+            Some(RELEASE_TEAM_DIED) => Outcome::Loss,
+            Some(RELEASE_GAME_ENDED) => Outcome::Win,
+            _ => Outcome::Unknown,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BenchmarkResult {
+    pub spec: BenchmarkSpec,
+    pub channel_id: Option<String>,
+    pub outcome: Outcome,
+    pub frames: i32,
+    pub wall_clock: Duration,
+    pub economy: Option<Economy>,
+    pub error: Option<String>,
+}
+
+impl BenchmarkResult {
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "map": self.spec.map,
+            "game": self.spec.game,
+            "opponent": self.spec.opponent,
+            "channel": self.channel_id,
+            "outcome": self.outcome.as_str(),
+            "winner": match self.outcome {
+                Outcome::Win => Some("agent"),
+                Outcome::Loss => Some(self.spec.opponent.as_str()),
+                _ => None,
+            },
+            "frames": self.frames,
+            // 30 simulation frames per game second.
+            "gameSeconds": self.frames / 30,
+            "wallClockSecs": (self.wall_clock.as_secs_f64() * 10.0).round() / 10.0,
+            "finalEconomy": self.economy,
+            "error": self.error,
+        })
+    }
+}
+
+/// The game of a run being played.
+#[derive(Debug)]
+pub struct ActiveGame {
+    pub spec: BenchmarkSpec,
+    pub channel_id: String,
+    pub started: Instant,
+    pub tracker: BenchmarkTracker,
+}
+
+/// One `game_run_benchmark` call: its games, played one after another.
+#[derive(Debug)]
+pub struct BenchmarkRun {
+    pub id: u64,
+    pub timeout: Duration,
+    pending: VecDeque<BenchmarkSpec>,
+    pub current: Option<ActiveGame>,
+    pub results: Vec<BenchmarkResult>,
+    /// Where the report went once the run finished, or why it didn't.
+    pub saved: Option<Result<String, String>>,
+}
+
+impl BenchmarkRun {
+    pub fn new(id: u64, specs: Vec<BenchmarkSpec>, timeout: Duration) -> Self {
+        Self { id, timeout, pending: specs.into(), current: None, results: Vec::new(), saved: None }
+    }
+
+    /// Every game is done with.
+    pub fn is_done(&self) -> bool {
+        self.current.is_none() && self.pending.is_empty()
+    }
+
+    /// The next game to launch, if none is being played.
+    pub fn next_spec(&mut self) -> Option<BenchmarkSpec> {
+        if self.current.is_some() {
+            return None;
+        }
+        self.pending.pop_front()
+    }
+
+    pub fn started(&mut self, spec: BenchmarkSpec, channel_id: String, now: Instant) {
+        self.current = Some(ActiveGame { spec, channel_id, started: now, tracker: BenchmarkTracker::default() });
+    }
+
+    /// A game that couldn't be launched.
+    pub fn launch_failed(&mut self, spec: BenchmarkSpec, error: String) {
+        self.results.push(BenchmarkResult {
+            spec,
+            channel_id: None,
+            outcome: Outcome::Crashed,
+            frames: 0,
+            wall_clock: Duration::ZERO,
+            economy: None,
+            error: Some(error),
+        });
+    }
+
+    pub fn observe(&mut self, channel_id: &str, event: &SaiEvent) {
+        if let Some(game) = self.current.as_mut().filter(|g| g.channel_id == channel_id) {
+            game.tracker.observe(event);
+        }
+    }
+
+    /// The current game's channel, if its time is up.
+    pub fn timed_out(&self, now: Instant) -> Option<&str> {
+        let game = self.current.as_ref()?;
+        (now.duration_since(game.started) >= self.timeout).then_some(game.channel_id.as_str())
+    }
+
+    /// Close the current game with the engine's final status; None if it
+    /// timed out.
+    pub fn finish_game(&mut self, status: Option<&GameStatus>, now: Instant) {
+        let Some(game) = self.current.take() else { return };
+        let (outcome, error) = match status {
+            Some(GameStatus::Crashed(e)) => (Outcome::Crashed, Some(e.clone())),
+            Some(_) => (game.tracker.outcome(), None),
+            None => (Outcome::Timeout, None),
+        };
+        self.results.push(BenchmarkResult {
+            spec: game.spec,
+            channel_id: Some(game.channel_id),
+            outcome,
+            frames: game.tracker.last_frame,
+            wall_clock: now.duration_since(game.started),
+            economy: game.tracker.economy,
+            error,
+        });
+    }
+
+    /// Progress for `game_benchmark_status`; the report once finished.
+    pub fn status(&self, now: Instant) -> serde_json::Value {
+        let games = self.results.len() + self.pending.len() + usize::from(self.current.is_some());
+        let mut status = serde_json::json!({
+            "run": self.id,
+            "state": if self.is_done() { "finished" } else { "running" },
+            "games": games,
+            "done": self.results.len(),
+        });
+        if let Some(game) = &self.current {
+            status["current"] = serde_json::json!({
+                "channel": game.channel_id,
+                "map": game.spec.map,
+                "opponent": game.spec.opponent,
+                "frame": game.tracker.last_frame,
+                "wallClockSecs": now.duration_since(game.started).as_secs(),
+            });
+        }
+        if self.is_done() {
+            status["report"] = report(&self.results);
+            match &self.saved {
+                Some(Ok(path)) => status["savedTo"] = path.clone().into(),
+                Some(Err(e)) => status["saveError"] = e.clone().into(),
+                None => {}
+            }
+        }
+        status
+    }
+}
+
+/// Collect results into the report returned by the tool and saved to disk.
+pub fn report(results: &[BenchmarkResult]) -> serde_json::Value {
+    let count = |outcome: Outcome| results.iter().filter(|r| r.outcome == outcome).count();
+    let decided: Vec<&BenchmarkResult> = results
+        .iter()
+        .filter(|r| matches!(r.outcome, Outcome::Win | Outcome::Loss))
+        .collect();
+    let avg_frames = if decided.is_empty() {
+        None
+    } else {
+        Some(decided.iter().map(|r| r.frames as i64).sum::<i64>() / decided.len() as i64)
+    };
+    serde_json::json!({
+        "summary": {
+            "games": results.len(),
+            "wins": count(Outcome::Win),
+            "losses": count(Outcome::Loss),
+            "unknown": count(Outcome::Unknown),
+            "timeouts": count(Outcome::Timeout),
+            "crashes": count(Outcome::Crashed),
+            "avgFramesDecided": avg_frames,
+        },
+        "results": results.iter().map(|r| r.to_json()).collect::<Vec<_>>(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sai_protocol::ResourceState;
+
+    fn update(frame: i32, metal_income: f32) -> SaiEvent {
+        SaiEvent::Update {
+            frame,
+            awaiting_commands: false,
+            economy: Some(Economy {
+                metal: ResourceState { income: metal_income, ..Default::default() },
+                energy: ResourceState::default(),
+            }),
+            allies: Vec::new(),
+            counters: Default::default(),
+            command_backlog: 0,
+            under_construction: Vec::new(),
+            update_interval: None,
+        }
+    }
+
+    fn spec() -> BenchmarkSpec {
+        BenchmarkSpec {
+            map: "Comet Catcher Redux".into(),
+            game: "Zero-K $VERSION".into(),
+            opponent: "CircuitAINovice".into(),
+        }
+    }
+
+    #[test]
+    fn test_specs_from_args() {
+        let specs = BenchmarkSpec::from_args(&serde_json::json!({"map": "Tundra", "runs": 3})).unwrap();
+        assert_eq!(specs.len(), 3);
+        assert_eq!(specs[0].opponent, "CircuitAINovice");
+
+        let specs = BenchmarkSpec::from_args(&serde_json::json!({
+            "opponent": "NullAI",
+            "games": [{"map": "Tundra"}, {"map": "Fields", "opponent": "CircuitAIHard"}]
+        }))
+        .unwrap();
+        assert_eq!(specs[0].opponent, "NullAI");
+        assert_eq!((specs[1].map.as_str(), specs[1].opponent.as_str()), ("Fields", "CircuitAIHard"));
+
+        assert_eq!(BenchmarkSpec::from_args(&serde_json::json!({})).unwrap_err(), "Missing map name");
+        assert!(BenchmarkSpec::from_args(&serde_json::json!({"games": []})).is_err());
+        assert!(BenchmarkSpec::from_args(&serde_json::json!({"map": "Tundra", "runs": 0})).is_err());
+        assert_eq!(
+            BenchmarkSpec::from_args(&serde_json::json!({"map": "Tundra", "runs": 1_000_000})).unwrap_err(),
+            "At most 50 games per benchmark run, got 1000000"
+        );
+        let games = vec![serde_json::json!({"map": "Tundra"}); MAX_GAMES + 1];
+        assert!(BenchmarkSpec::from_args(&serde_json::json!({"games": games})).is_err());
+    }
+
+    #[test]
+    fn test_run_plays_games_in_turn() {
+        let start = Instant::now();
+        let mut run = BenchmarkRun::new(1, vec![spec(), spec(), spec()], Duration::from_secs(60));
+        let first = run.next_spec().unwrap();
+        run.started(first, "game:local-1".into(), start);
+        assert_eq!(run.next_spec(), None, "one game at a time");
+        run.observe("game:local-1", &update(900, 3.0));
+        run.observe("game:local-2", &update(5000, 1.0));
+        assert_eq!(run.timed_out(start + Duration::from_secs(59)), None);
+        let status = run.status(start + Duration::from_secs(10));
+        assert_eq!((status["state"].as_str(), status["games"].as_u64()), (Some("running"), Some(3)));
+        assert_eq!(status["current"]["frame"], 900);
+        assert!(status["report"].is_null());
+
+        run.observe("game:local-1", &SaiEvent::Release { reason: RELEASE_GAME_ENDED, stats: None });
+        run.finish_game(Some(&GameStatus::Ended), start + Duration::from_secs(20));
+        let second = run.next_spec().unwrap();
+        run.launch_failed(second, "no engine".into());
+        let third = run.next_spec().unwrap();
+        run.started(third, "game:local-2".into(), start + Duration::from_secs(20));
+        assert_eq!(run.timed_out(start + Duration::from_secs(80)), Some("game:local-2"));
+        run.finish_game(None, start + Duration::from_secs(80));
+
+        assert!(run.is_done());
+        run.saved = Some(Ok("/tmp/benchmark-1.json".into()));
+        let status = run.status(start + Duration::from_secs(80));
+        assert_eq!(status["state"], "finished");
+        assert_eq!(status["savedTo"], "/tmp/benchmark-1.json");
+        let outcomes: Vec<&str> =
+            status["report"]["results"].as_array().unwrap().iter().map(|r| r["outcome"].as_str().unwrap()).collect();
+        assert_eq!(outcomes, ["win", "crashed", "timeout"]);
+        assert_eq!(status["report"]["results"][0]["frames"], 900);
+        assert_eq!(status["report"]["results"][2]["wallClockSecs"], 60.0);
+    }
+
+    #[test]
+    fn test_tracker_outcome_and_final_state() {
+        let mut tracker = BenchmarkTracker::default();
+        tracker.observe(&update(900, 3.0));
+        tracker.observe(&update(1800, 7.5));
+        assert_eq!(tracker.outcome(), Outcome::Unknown);
+
+        tracker.observe(&SaiEvent::Release { reason: RELEASE_TEAM_DIED, stats: None });
+        tracker.observe(&SaiEvent::Release { reason: 0, stats: None });
+        assert_eq!(tracker.outcome(), Outcome::Loss);
+        assert_eq!(tracker.bridge, None);
+        assert_eq!(tracker.last_frame, 1800);
+        assert_eq!(tracker.economy.unwrap().metal.income, 7.5);
+
+        let mut won = BenchmarkTracker::default();
+        let stats = BridgeStats { frames: 1800, events_sent: 60, events_dropped: 2, panics: 0 };
+        won.observe(&SaiEvent::Release { reason: RELEASE_GAME_ENDED, stats: Some(stats) });
+        assert_eq!(won.outcome(), Outcome::Win);
+        assert_eq!(won.bridge.map(|b| (b.events_sent, b.events_dropped)), Some((60, 2)));
+    }
+
+    #[test]
+    fn test_report_summary() {
+        let result = |outcome, frames| BenchmarkResult {
+            spec: spec(),
+            channel_id: Some("game:local-1".into()),
+            outcome,
+            frames,
+            wall_clock: Duration::from_millis(41_260),
+            economy: None,
+            error: None,
+        };
+        let report = report(&[
+            result(Outcome::Win, 18000),
+            result(Outcome::Loss, 9000),
+            result(Outcome::Timeout, 54000),
+        ]);
+        assert_eq!(report["summary"]["wins"], 1);
+        assert_eq!(report["summary"]["losses"], 1);
+        assert_eq!(report["summary"]["timeouts"], 1);
+        assert_eq!(report["summary"]["avgFramesDecided"], 13500);
+        let first = &report["results"][0];
+        assert_eq!(first["winner"], "agent");
+        assert_eq!(first["gameSeconds"], 600);
+        assert_eq!(first["wallClockSecs"], 41.3);
+        assert_eq!(report["results"][1]["winner"], "CircuitAINovice");
+        assert!(report["results"][2]["winner"].is_null());
+    }
+}
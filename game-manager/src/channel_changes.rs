@@ -0,0 +1,222 @@
+//! Coalescing `channels/changed` notifications.
+//!
+//! A game that fails to start goes starting → running → crashed → removed
+//! in well under a second, and a client that sees every step may act on
+//! states that are already gone. Changes to one channel are held for a
+//! short window from the first of them, then sent as that channel's final
+//! state: an add removed again is never sent, an update of a pending add
+//! is sent as the add, and a run of updates as the last one. A removal
+//! keeps the channel's last state alongside, as an update.
+//!
+//! The window starts at a channel's first held change, so no change waits
+//! longer than the window. Each channel's window is its own: a busy channel
+//! doesn't hold back another's changes, and channels come out in the order
+//! they first changed.
+
+use std::time::{Duration, Instant};
+
+use mcpl_core::types::ChannelDescriptor;
+
+/// One channel's held changes.
+#[derive(Debug)]
+struct Held {
+    channel: String,
+    since: Instant,
+    /// The client knew the channel before the first held change.
+    existed: bool,
+    /// The channel's latest descriptor, if it changed.
+    latest: Option<ChannelDescriptor>,
+    removed: bool,
+}
+
+/// Changes ready to send, as `channels/changed` lists.
+#[derive(Debug, Default)]
+pub struct Changes {
+    pub added: Vec<ChannelDescriptor>,
+    pub removed: Vec<String>,
+    pub updated: Vec<ChannelDescriptor>,
+}
+
+impl Changes {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.updated.is_empty()
+    }
+}
+
+#[derive(Debug)]
+pub struct ChannelChanges {
+    window: Duration,
+    held: Vec<Held>,
+}
+
+impl ChannelChanges {
+    /// A zero window sends every change as it comes.
+    pub fn new(window: Duration) -> Self {
+        Self { window, held: Vec::new() }
+    }
+
+    pub fn set_window(&mut self, window: Duration) {
+        self.window = window;
+    }
+
+    /// Hold a `channels/changed` notification's changes.
+    pub fn push(&mut self, now: Instant, changes: Changes) {
+        for descriptor in changes.added {
+            let held = self.held(now, &descriptor.id, false);
+            (held.latest, held.removed) = (Some(descriptor), false);
+        }
+        for descriptor in changes.updated {
+            let held = self.held(now, &descriptor.id, true);
+            (held.latest, held.removed) = (Some(descriptor), false);
+        }
+        for channel in changes.removed {
+            self.held(now, &channel, true).removed = true;
+        }
+    }
+
+    /// `channel`'s held changes, starting them if there are none.
+    fn held(&mut self, now: Instant, channel: &str, existed: bool) -> &mut Held {
+        let at = match self.held.iter().position(|h| h.channel == channel) {
+            Some(at) => at,
+            None => {
+                self.held.push(Held { channel: channel.into(), since: now, existed, latest: None, removed: false });
+                self.held.len() - 1
+            }
+        };
+        &mut self.held[at]
+    }
+
+    /// The changes whose window has passed by `now`.
+    pub fn due(&mut self, now: Instant) -> Changes {
+        let mut changes = Changes::default();
+        let window = self.window;
+        let (due, held): (Vec<Held>, Vec<Held>) =
+            std::mem::take(&mut self.held).into_iter().partition(|h| now.duration_since(h.since) >= window);
+        self.held = held;
+        for held in due {
+            match (held.existed, held.removed) {
+                // Added and removed again: the client never hears of it.
+                (false, true) => {}
+                (false, false) => changes.added.extend(held.latest),
+                (true, removed) => {
+                    if removed {
+                        changes.removed.push(held.channel);
+                    }
+                    changes.updated.extend(held.latest);
+                }
+            }
+        }
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mcpl_core::types::ChannelDirection;
+
+    const WINDOW: Duration = Duration::from_millis(250);
+
+    fn channel(id: &str, status: &str) -> ChannelDescriptor {
+        ChannelDescriptor {
+            id: id.into(),
+            channel_type: "game".into(),
+            label: id.into(),
+            direction: ChannelDirection::Bidirectional,
+            address: None,
+            metadata: Some(serde_json::json!({"status": status})),
+        }
+    }
+
+    fn status(descriptor: &ChannelDescriptor) -> &str {
+        descriptor.metadata.as_ref().unwrap()["status"].as_str().unwrap()
+    }
+
+    fn added(d: ChannelDescriptor) -> Changes {
+        Changes { added: vec![d], ..Default::default() }
+    }
+
+    fn updated(d: ChannelDescriptor) -> Changes {
+        Changes { updated: vec![d], ..Default::default() }
+    }
+
+    fn removed(id: &str) -> Changes {
+        Changes { removed: vec![id.into()], ..Default::default() }
+    }
+
+    #[test]
+    fn test_add_then_remove() {
+        let start = Instant::now();
+        let mut changes = ChannelChanges::new(WINDOW);
+        changes.push(start, added(channel("game:1", "starting")));
+        changes.push(start + Duration::from_millis(50), updated(channel("game:1", "crashed")));
+        changes.push(start + Duration::from_millis(100), removed("game:1"));
+        assert!(changes.due(start + Duration::from_millis(200)).is_empty());
+        assert!(changes.due(start + WINDOW).is_empty(), "a channel added and removed again is never sent");
+
+        // A known channel's removal does go out, with its last state.
+        changes.push(start + WINDOW, removed("game:0"));
+        assert_eq!(changes.due(start + WINDOW * 2).removed, ["game:0"]);
+        changes.push(start + WINDOW * 2, updated(channel("game:0", "ended")));
+        changes.push(start + WINDOW * 2, removed("game:0"));
+        let due = changes.due(start + WINDOW * 3);
+        assert_eq!(due.removed, ["game:0"]);
+        assert_eq!(due.updated.iter().map(status).collect::<Vec<_>>(), ["ended"]);
+    }
+
+    #[test]
+    fn test_update_then_update() {
+        let start = Instant::now();
+        let mut changes = ChannelChanges::new(WINDOW);
+        changes.push(start, updated(channel("game:1", "starting")));
+        changes.push(start + Duration::from_millis(100), updated(channel("game:1", "running")));
+        let due = changes.due(start + WINDOW);
+        assert_eq!(due.updated.iter().map(status).collect::<Vec<_>>(), ["running"]);
+        assert!(due.added.is_empty() && due.removed.is_empty());
+
+        // An update of a pending add goes out as the add, with the new state.
+        changes.push(start + WINDOW, added(channel("game:2", "starting")));
+        changes.push(start + WINDOW, updated(channel("game:2", "running")));
+        let due = changes.due(start + WINDOW * 2);
+        assert_eq!(due.added.iter().map(status).collect::<Vec<_>>(), ["running"]);
+        assert!(due.updated.is_empty());
+
+        // Changes after the window has passed are a new transition.
+        changes.push(start + WINDOW * 2, updated(channel("game:2", "crashed")));
+        assert_eq!(changes.due(start + WINDOW * 3).updated.iter().map(status).collect::<Vec<_>>(), ["crashed"]);
+    }
+
+    #[test]
+    fn test_channels_are_independent() {
+        let start = Instant::now();
+        let mut changes = ChannelChanges::new(WINDOW);
+        changes.push(start, added(channel("game:1", "starting")));
+        changes.push(start + Duration::from_millis(100), added(channel("game:2", "starting")));
+        changes.push(start + Duration::from_millis(200), updated(channel("game:1", "running")));
+        changes.push(start + Duration::from_millis(200), added(channel("game:3", "starting")));
+
+        // game:1's window ends first; its later update doesn't extend it, and
+        // the others' changes don't hold it back.
+        let due = changes.due(start + WINDOW);
+        assert_eq!(due.added.iter().map(|d| (d.id.as_str(), status(d))).collect::<Vec<_>>(), [("game:1", "running")]);
+        changes.push(start + Duration::from_millis(300), removed("game:2"));
+        assert!(changes.due(start + Duration::from_millis(350)).is_empty());
+        let due = changes.due(start + Duration::from_millis(450));
+        assert_eq!(due.added.iter().map(|d| d.id.as_str()).collect::<Vec<_>>(), ["game:3"]);
+
+        // Channels come out in the order they first changed.
+        changes.push(start, added(channel("game:5", "starting")));
+        changes.push(start, added(channel("game:4", "starting")));
+        changes.push(start, updated(channel("game:5", "running")));
+        let due = changes.due(start + WINDOW);
+        assert_eq!(due.added.iter().map(|d| d.id.as_str()).collect::<Vec<_>>(), ["game:5", "game:4"]);
+    }
+
+    #[test]
+    fn test_zero_window() {
+        let now = Instant::now();
+        let mut changes = ChannelChanges::new(Duration::ZERO);
+        changes.push(now, added(channel("game:1", "starting")));
+        assert_eq!(changes.due(now).added.len(), 1);
+    }
+}
@@ -0,0 +1,101 @@
+//! Caller-chosen game channel ids. `channels/open` and `lobby_start_game`
+//! take an optional `channel_id` such as `game:practice`, so a rematch can
+//! reuse the last game's name instead of getting a fresh `game:local-N`.
+//!
+//! A name is free unless its game is still running or queued. With
+//! `preserve_state`, the new game keeps what the agent set up on the old
+//! one: its unit groups (emptied, since unit ids don't carry over), event
+//! verbosity and state stream settings.
+
+use crate::groups::UnitGroups;
+use crate::observer::StreamSettings;
+use crate::sai_ipc::Verbosity;
+
+/// Every game channel id starts with this.
+pub const PREFIX: &str = "game:";
+
+/// Longest name after the prefix.
+pub const MAX_NAME_LEN: usize = 48;
+
+/// Names the GameManager generates itself; a chosen id can't start with them.
+const GENERATED: &[&str] = &["local-", "mp-"];
+
+/// Check a requested id: `game:` then 1 to [`MAX_NAME_LEN`] letters,
+/// digits, `-` or `_`, and not one the GameManager could generate.
+pub fn validate(id: &str) -> Result<(), String> {
+    let Some(name) = id.strip_prefix(PREFIX) else {
+        return Err(format!("channel_id '{}' must start with '{}'", id, PREFIX));
+    };
+    if name.is_empty() || name.len() > MAX_NAME_LEN {
+        return Err(format!("channel_id '{}' needs a name of 1 to {} characters after '{}'", id, MAX_NAME_LEN, PREFIX));
+    }
+    if let Some(c) = name.chars().find(|c| !(c.is_ascii_alphanumeric() || *c == '-' || *c == '_')) {
+        return Err(format!("channel_id '{}' may only use letters, digits, '-' and '_' (found '{}')", id, c));
+    }
+    if let Some(prefix) = GENERATED.iter().find(|p| name.starts_with(*p)) {
+        return Err(format!("channel_id '{}': names starting with '{}' are reserved", id, prefix));
+    }
+    Ok(())
+}
+
+/// Whether `id` was chosen by a caller rather than generated. Co-op
+/// sub-channels (`/` isn't allowed) never are: they go with their game.
+pub fn is_chosen(id: &str) -> bool {
+    validate(id).is_ok()
+}
+
+/// What a closed channel leaves for the next game of the same name.
+#[derive(Debug, Default)]
+pub struct KeptState {
+    pub verbosity: Option<Verbosity>,
+    pub stream: Option<StreamSettings>,
+    pub groups: UnitGroups,
+}
+
+impl KeptState {
+    pub fn new(verbosity: Option<Verbosity>, stream: Option<StreamSettings>, mut groups: UnitGroups) -> Self {
+        groups.forget_members();
+        Self { verbosity, stream, groups }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sai_ipc::UnitId;
+
+    #[test]
+    fn test_validate() {
+        assert!(validate("game:practice").is_ok());
+        assert!(validate("game:rematch_2-b").is_ok());
+        assert!(validate(&format!("game:{}", "x".repeat(MAX_NAME_LEN))).is_ok());
+
+        assert_eq!(validate("practice").unwrap_err(), "channel_id 'practice' must start with 'game:'");
+        assert!(validate("game:").unwrap_err().contains("1 to 48 characters"));
+        assert!(validate(&format!("game:{}", "x".repeat(MAX_NAME_LEN + 1))).is_err());
+        assert_eq!(
+            validate("game:a/team2").unwrap_err(),
+            "channel_id 'game:a/team2' may only use letters, digits, '-' and '_' (found '/')"
+        );
+        assert!(validate("game:prác").is_err());
+        assert_eq!(
+            validate("game:local-7").unwrap_err(),
+            "channel_id 'game:local-7': names starting with 'local-' are reserved"
+        );
+        assert!(validate("game:mp-1").is_err());
+
+        assert!(is_chosen("game:practice"));
+        assert!(!is_chosen("game:local-3"));
+        assert!(!is_chosen("game:practice/team2"));
+    }
+
+    #[test]
+    fn test_kept_groups_lose_their_units() {
+        let mut groups = UnitGroups::default();
+        groups.create("raiders", &[UnitId(4), UnitId(5)], Some(2)).unwrap();
+        let kept = KeptState::new(Some(Verbosity::Terse), None, groups);
+        let raiders = kept.groups.get("raiders").unwrap();
+        assert!(raiders.members.is_empty());
+        assert_eq!(raiders.alert_below, Some(2));
+    }
+}
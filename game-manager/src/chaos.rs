@@ -0,0 +1,192 @@
+//! Chaos mode: faults injected into SAI connections, to rehearse a flaky
+//! game link without breaking one. Off unless the config file has a
+//! `chaos` section.
+//!
+//! Each connection's reads go through a [`Chaos`] that drops a share of the
+//! events and holds the rest back for the configured latency, in the order
+//! they came. The server cuts connections that have lasted
+//! `disconnect_every_secs`; the bridge reconnects and replays its opening.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::sai_ipc::SaiEvent;
+
+/// `chaos` section of the config file.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChaosConfig {
+    /// Milliseconds each event is held before the GameManager sees it.
+    #[serde(default)]
+    pub latency_ms: u64,
+    /// Share of events dropped, in percent.
+    #[serde(default)]
+    pub drop_percent: f64,
+    /// Seconds a connection lasts before it's cut. Unset means never.
+    #[serde(default)]
+    pub disconnect_every_secs: Option<f64>,
+    /// Seed for choosing the dropped events, for repeatable runs.
+    #[serde(default)]
+    pub seed: Option<u64>,
+}
+
+impl ChaosConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(0.0..=100.0).contains(&self.drop_percent) {
+            return Err("chaos.drop_percent must be between 0 and 100".into());
+        }
+        if self.disconnect_every_secs.is_some_and(|secs| secs <= 0.0) {
+            return Err("chaos.disconnect_every_secs must be positive".into());
+        }
+        Ok(())
+    }
+}
+
+/// What chaos mode did to a channel, across its connections.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChaosCounts {
+    pub dropped: u64,
+    pub delayed: u64,
+    pub disconnects: u64,
+}
+
+/// Fault injection for one SAI connection.
+#[derive(Debug)]
+pub struct Chaos {
+    channel_id: String,
+    config: ChaosConfig,
+    rng: u64,
+    /// Events admitted and when they're due, oldest first.
+    delayed: VecDeque<(Instant, SaiEvent)>,
+    connected_at: Instant,
+    pub counts: ChaosCounts,
+}
+
+impl Chaos {
+    pub fn new(channel_id: &str, config: ChaosConfig, counts: ChaosCounts, now: Instant) -> Self {
+        let seed = config.seed.unwrap_or_else(|| {
+            std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(1, |d| d.as_nanos() as u64)
+        });
+        Self {
+            channel_id: channel_id.to_string(),
+            config,
+            // Xorshift needs a non-zero state.
+            rng: seed | 1,
+            delayed: VecDeque::new(),
+            connected_at: now,
+            counts,
+        }
+    }
+
+    /// Take an event off the wire: drop it, or hold it for the latency.
+    pub fn admit(&mut self, event: SaiEvent, now: Instant) {
+        if self.roll() < self.config.drop_percent {
+            self.counts.dropped += 1;
+            tracing::info!("Chaos: dropped {} event on {}", event.type_name(), self.channel_id);
+            return;
+        }
+        if self.config.latency_ms > 0 {
+            self.counts.delayed += 1;
+        }
+        self.delayed.push_back((now + Duration::from_millis(self.config.latency_ms), event));
+    }
+
+    /// The oldest held event, once it's due.
+    pub fn release(&mut self, now: Instant) -> Option<SaiEvent> {
+        if self.next_due()? > now {
+            return None;
+        }
+        self.delayed.pop_front().map(|(_, event)| event)
+    }
+
+    /// When the oldest held event is due.
+    pub fn next_due(&self) -> Option<Instant> {
+        self.delayed.front().map(|(due, _)| *due)
+    }
+
+    /// Whether the connection has lasted long enough to be cut.
+    pub fn disconnect_due(&self, now: Instant) -> bool {
+        self.config
+            .disconnect_every_secs
+            .is_some_and(|secs| now.duration_since(self.connected_at) >= Duration::from_secs_f64(secs))
+    }
+
+    /// A number in [0, 100).
+    fn roll(&mut self) -> f64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        (self.rng >> 11) as f64 / (1u64 << 53) as f64 * 100.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update(frame: i32) -> SaiEvent {
+        SaiEvent::Update { frame, awaiting_commands: false, economy: None, allies: Vec::new(), counters: Default::default(), command_backlog: 0, under_construction: Vec::new(), update_interval: None }
+    }
+
+    fn frame(event: SaiEvent) -> i32 {
+        match event {
+            SaiEvent::Update { frame, .. } => frame,
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_latency_keeps_order() {
+        let config = ChaosConfig { latency_ms: 100, ..Default::default() };
+        let now = Instant::now();
+        let mut chaos = Chaos::new("game:local-1", config, ChaosCounts::default(), now);
+        chaos.admit(update(1), now);
+        chaos.admit(update(2), now + Duration::from_millis(50));
+        assert_eq!(chaos.release(now + Duration::from_millis(99)), None);
+        assert_eq!(chaos.next_due(), Some(now + Duration::from_millis(100)));
+        assert_eq!(frame(chaos.release(now + Duration::from_millis(100)).unwrap()), 1);
+        assert_eq!(chaos.release(now + Duration::from_millis(100)), None);
+        assert_eq!(frame(chaos.release(now + Duration::from_millis(200)).unwrap()), 2);
+        assert_eq!(chaos.counts, ChaosCounts { dropped: 0, delayed: 2, disconnects: 0 });
+    }
+
+    #[test]
+    fn test_drops_share_of_events() {
+        let config = ChaosConfig { drop_percent: 25.0, seed: Some(7), ..Default::default() };
+        let now = Instant::now();
+        let mut chaos = Chaos::new("game:local-1", config.clone(), ChaosCounts::default(), now);
+        for i in 0..1000 {
+            chaos.admit(update(i), now);
+        }
+        assert!((200..300).contains(&chaos.counts.dropped), "{:?}", chaos.counts);
+        // What's left comes through in order.
+        let frames: Vec<i32> = std::iter::from_fn(|| chaos.release(now)).map(frame).collect();
+        assert_eq!(frames.len() as u64, 1000 - chaos.counts.dropped);
+        assert!(frames.windows(2).all(|w| w[0] < w[1]));
+
+        // The same seed drops the same events.
+        let mut again = Chaos::new("game:local-1", config, ChaosCounts::default(), now);
+        for i in 0..1000 {
+            again.admit(update(i), now);
+        }
+        assert_eq!(std::iter::from_fn(|| again.release(now)).map(frame).collect::<Vec<_>>(), frames);
+    }
+
+    #[test]
+    fn test_disconnect_due_and_validation() {
+        let now = Instant::now();
+        let config = ChaosConfig { disconnect_every_secs: Some(30.0), ..Default::default() };
+        let chaos = Chaos::new("game:local-1", config, ChaosCounts::default(), now);
+        assert!(!chaos.disconnect_due(now + Duration::from_secs(29)));
+        assert!(chaos.disconnect_due(now + Duration::from_secs(30)));
+        assert!(!Chaos::new("game:local-1", ChaosConfig::default(), ChaosCounts::default(), now)
+            .disconnect_due(now + Duration::from_secs(3600)));
+
+        assert!(ChaosConfig { drop_percent: 101.0, ..Default::default() }.validate().is_err());
+        assert!(ChaosConfig { disconnect_every_secs: Some(0.0), ..Default::default() }.validate().is_err());
+        assert!(ChaosConfig { latency_ms: 500, drop_percent: 10.0, ..Default::default() }.validate().is_ok());
+    }
+}
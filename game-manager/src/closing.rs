@@ -0,0 +1,125 @@
+//! Graceful channel close. `channels/close` asks the engine to quit and
+//! keeps the bridge connected for a grace period, so the game's last
+//! events (the release and its reason) still reach the agent and the demo
+//! the engine finishes on exit can be reported. The channel is torn down
+//! when the engine exits or the grace period runs out, whichever comes
+//! first; `force: true` skips the wait.
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::benchmark::BenchmarkTracker;
+use crate::engine::GameStatus;
+use crate::sai_ipc::SaiEvent;
+
+/// How long a closing engine gets to exit before it is killed.
+pub const GRACE_PERIOD: Duration = Duration::from_secs(10);
+
+/// Demos the engine writes, relative to the write dir.
+const DEMO_DIR: &str = "demos";
+const DEMO_EXTENSION: &str = "sdfz";
+
+/// A channel waiting for its engine to exit.
+#[derive(Debug)]
+pub struct Closing {
+    deadline: Instant,
+    /// The release reason, for the outcome.
+    tracker: BenchmarkTracker,
+}
+
+impl Closing {
+    pub fn new(now: Instant, grace: Duration) -> Self {
+        Self { deadline: now + grace, tracker: BenchmarkTracker::default() }
+    }
+
+    /// Note an event the bridge sent while closing.
+    pub fn observe(&mut self, event: &SaiEvent) {
+        self.tracker.observe(event);
+    }
+
+    pub fn expired(&self, now: Instant) -> bool {
+        now >= self.deadline
+    }
+
+    /// The removed channel's last metadata. An engine that was killed, or
+    /// died of the quit signal, is still a stop we asked for: only the
+    /// release reason decides the outcome.
+    pub fn final_metadata(
+        &self,
+        status: &GameStatus,
+        demo: Option<&Path>,
+        last_frame: Option<i32>,
+        duration: Option<Duration>,
+    ) -> serde_json::Value {
+        let status = match status {
+            GameStatus::Ended => "ended",
+            _ => "stopped",
+        };
+        serde_json::json!({
+            "status": status,
+            "outcome": self.tracker.outcome().as_str(),
+            "demoPath": demo.map(|p| p.display().to_string()),
+            "lastFrame": last_frame,
+            "durationSecs": duration.map(|d| d.as_secs()),
+            "bridge": self.tracker.bridge,
+        })
+    }
+}
+
+/// The newest demo in the write dir's demo folder touched at or after
+/// `since`: the one the closing game was recording.
+pub fn find_demo(write_dir: &Path, since: SystemTime) -> Option<PathBuf> {
+    std::fs::read_dir(write_dir.join(DEMO_DIR))
+        .ok()?
+        .flatten()
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == DEMO_EXTENSION))
+        .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+        .filter(|(modified, _)| *modified >= since)
+        .max()
+        .map(|(_, path)| path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_final_metadata() {
+        let now = Instant::now();
+        let mut closing = Closing::new(now, GRACE_PERIOD);
+        assert!(!closing.expired(now) && closing.expired(now + GRACE_PERIOD));
+
+        let crashed = GameStatus::Crashed("Exit code: None".into());
+        let meta = closing.final_metadata(&crashed, None, None, None);
+        assert_eq!(
+            meta,
+            serde_json::json!({
+                "status": "stopped", "outcome": "unknown", "demoPath": null, "lastFrame": null, "durationSecs": null,
+                "bridge": null
+            })
+        );
+
+        let stats = sai_protocol::BridgeStats { frames: 9000, events_sent: 400, events_dropped: 0, panics: 1 };
+        closing.observe(&SaiEvent::Release { reason: 2, stats: Some(stats) });
+        let demo = Path::new("/write/demos/game.sdfz");
+        let meta = closing.final_metadata(&GameStatus::Ended, Some(demo), Some(9000), Some(Duration::from_secs(312)));
+        assert_eq!(meta["status"], "ended");
+        assert_eq!(meta["outcome"], "loss");
+        assert_eq!(meta["demoPath"], "/write/demos/game.sdfz");
+        assert_eq!((meta["lastFrame"].as_i64(), meta["durationSecs"].as_u64()), (Some(9000), Some(312)));
+        assert_eq!(meta["bridge"], serde_json::json!({"frames": 9000, "eventsSent": 400, "eventsDropped": 0, "panics": 1}));
+    }
+
+    #[test]
+    fn test_find_demo() {
+        let dir = std::env::temp_dir().join(format!("gm-closing-{}", uuid::Uuid::new_v4()));
+        assert_eq!(find_demo(&dir, SystemTime::UNIX_EPOCH), None);
+        let demos = dir.join(DEMO_DIR);
+        std::fs::create_dir_all(&demos).unwrap();
+        std::fs::write(demos.join("old.sdfz"), "").unwrap();
+        std::fs::write(demos.join("notes.txt"), "").unwrap();
+        assert_eq!(find_demo(&dir, SystemTime::UNIX_EPOCH), Some(demos.join("old.sdfz")));
+        assert_eq!(find_demo(&dir, SystemTime::now() + Duration::from_secs(60)), None);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
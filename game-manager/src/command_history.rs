@@ -0,0 +1,402 @@
+//! Command history: the last commands sent to each game channel's bridge,
+//! so the agent (or its operator) can review what was actually ordered.
+//!
+//! Each entry records where the command came from and what became of it.
+//! Commands go to the bridge with their sequence number as `command_ref`,
+//! which the bridge echoes in the `command_error` a command fails with and
+//! in the `command_finished` of an order the unit carried out. Bridges
+//! older than protocol 6 echo only the command's debug form; the newest
+//! sent entry with that echo is marked rejected. Entries also go to the
+//! session log, as sent, where post-game analysis counts them.
+
+use std::collections::VecDeque;
+
+use serde::Deserialize;
+
+use crate::sai_ipc::{SaiCommand, SaiEvent, UnitId};
+
+/// Entries kept per channel unless the config says otherwise.
+pub const DEFAULT_SIZE: usize = 200;
+
+/// `type` of the session log lines that record sent commands.
+pub const LOG_TYPE: &str = "gm_command";
+
+/// Config file `command_history`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CommandHistoryConfig {
+    /// Entries kept per channel; older ones are dropped.
+    #[serde(default = "default_size")]
+    pub size: usize,
+}
+
+impl Default for CommandHistoryConfig {
+    fn default() -> Self {
+        Self { size: default_size() }
+    }
+}
+
+fn default_size() -> usize {
+    DEFAULT_SIZE
+}
+
+/// What issued a command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    /// `channels/publish`.
+    Publish,
+    /// A tool call (game_command, game_expand, game_pause, ...).
+    Tool,
+    /// The GameManager on its own (auto-respond rules, turn mode setup).
+    Automation,
+}
+
+impl Source {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Source::Publish => "publish",
+            Source::Tool => "tool",
+            Source::Automation => "automation",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "publish" => Some(Source::Publish),
+            "tool" => Some(Source::Tool),
+            "automation" => Some(Source::Automation),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Status {
+    /// Written to the bridge; no error reported for it (yet).
+    Sent,
+    /// Couldn't be written to the bridge.
+    SendFailed(String),
+    /// The bridge reported a `command_error` for it.
+    Rejected(String),
+    /// The unit finished the order.
+    Completed,
+    /// A later order for the unit replaced its queue first.
+    Superseded,
+    /// The unit was lost before it finished the order.
+    Failed(String),
+}
+
+impl Status {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Status::Sent => "sent",
+            Status::SendFailed(_) => "send_failed",
+            Status::Rejected(_) => "rejected",
+            Status::Completed => "completed",
+            Status::Superseded => "superseded",
+            Status::Failed(_) => "failed",
+        }
+    }
+
+    fn error(&self) -> Option<&str> {
+        match self {
+            Status::Sent | Status::Completed | Status::Superseded => None,
+            Status::SendFailed(e) | Status::Rejected(e) | Status::Failed(e) => Some(e),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    /// Position in the channel's history, from 1.
+    pub seq: u64,
+    pub at: chrono::DateTime<chrono::Utc>,
+    /// The last frame the bridge had reported when the command was sent.
+    pub frame: Option<i32>,
+    pub source: Source,
+    pub command: SaiCommand,
+    pub status: Status,
+    /// The agent asked to hear when the command is done with.
+    pub notify: bool,
+}
+
+impl Entry {
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "seq": self.seq,
+            "at": self.at.to_rfc3339(),
+            "frame": self.frame,
+            "source": self.source.as_str(),
+            "command": self.command,
+            "status": self.status.as_str(),
+            "error": self.status.error(),
+        })
+    }
+
+    /// Read back a session log's `gm_command` line.
+    pub fn from_log(raw: &serde_json::Value) -> Option<Self> {
+        let error = raw["error"].as_str().unwrap_or_default().to_string();
+        let status = match raw["status"].as_str()? {
+            "sent" => Status::Sent,
+            "send_failed" => Status::SendFailed(error),
+            "rejected" => Status::Rejected(error),
+            "completed" => Status::Completed,
+            "superseded" => Status::Superseded,
+            "failed" => Status::Failed(error),
+            _ => return None,
+        };
+        Some(Self {
+            seq: raw["seq"].as_u64()?,
+            at: raw["at"].as_str()?.parse().ok()?,
+            frame: raw["frame"].as_i64().map(|f| f as i32),
+            source: Source::parse(raw["source"].as_str()?)?,
+            command: serde_json::from_value(raw["command"].clone()).ok()?,
+            status,
+            notify: false,
+        })
+    }
+
+    /// One line for the agent, e.g.
+    /// `#3 frame 930 publish: {"type":"stop","unit_id":5} — sent`.
+    pub fn line(&self) -> String {
+        let frame = self.frame.map(|f| format!(" frame {}", f)).unwrap_or_default();
+        let command = serde_json::to_string(&self.command).unwrap_or_default();
+        let mut line = format!("#{}{} {}: {} — {}", self.seq, frame, self.source.as_str(), command, self.status.as_str());
+        if let Some(error) = self.status.error() {
+            line += &format!(" ({})", error);
+        }
+        line
+    }
+}
+
+/// The recent commands of one game channel, oldest first.
+#[derive(Debug)]
+pub struct CommandHistory {
+    entries: VecDeque<Entry>,
+    size: usize,
+    next_seq: u64,
+    /// Entries to notify about that were settled while recording, for the
+    /// next `observe`.
+    superseded: Vec<Entry>,
+}
+
+impl CommandHistory {
+    pub fn new(size: usize) -> Self {
+        Self { entries: VecDeque::new(), size, next_seq: 1, superseded: Vec::new() }
+    }
+
+    /// The sequence number the next recorded command gets, which goes to
+    /// the bridge as its `command_ref`.
+    pub fn next_seq(&self) -> u64 {
+        self.next_seq
+    }
+
+    /// Record a command and the outcome of sending it. An order that went
+    /// out unqueued supersedes the unit's earlier orders still pending.
+    /// A stop is never pending itself: it empties the queue without
+    /// joining it, so no `command_finished` ever comes for it.
+    pub fn record(
+        &mut self,
+        command: &SaiCommand,
+        source: Source,
+        frame: Option<i32>,
+        sent: &Result<(), String>,
+    ) -> &Entry {
+        let status = match sent {
+            Ok(()) => Status::Sent,
+            Err(e) => Status::SendFailed(e.clone()),
+        };
+        if let (Status::Sent, Some((unit, false))) = (&status, command.order()) {
+            let mut superseded = Vec::new();
+            for entry in self.pending_orders(unit) {
+                entry.status = Status::Superseded;
+                if entry.notify {
+                    superseded.push(entry.clone());
+                }
+            }
+            self.superseded.extend(superseded);
+        }
+        self.entries.push_back(Entry {
+            seq: self.next_seq,
+            at: chrono::Utc::now(),
+            frame,
+            source,
+            command: command.clone(),
+            status,
+            notify: false,
+        });
+        self.next_seq += 1;
+        while self.entries.len() > self.size {
+            self.entries.pop_front();
+        }
+        self.entries.back().expect("just pushed")
+    }
+
+    /// Ask to hear about the entries from `seq` on (see `observe`).
+    pub fn notify_from(&mut self, seq: u64) {
+        for entry in self.entries.iter_mut().filter(|e| e.seq >= seq) {
+            entry.notify = true;
+        }
+    }
+
+    /// Follow commands through the bridge's events: a `command_error`
+    /// rejects the command it is for, a `command_finished` completes it,
+    /// and a unit lost fails its pending orders. Returns the entries
+    /// settled since the last call that were to be notified about.
+    pub fn observe(&mut self, event: &SaiEvent) -> Vec<Entry> {
+        let mut settled: Vec<&mut Entry> = Vec::new();
+        match event {
+            SaiEvent::CommandError { error, command, command_ref } => {
+                let rejected = self.entries.iter_mut().rev().find(|e| {
+                    e.status == Status::Sent
+                        && match command_ref {
+                            Some(seq) => e.seq == *seq,
+                            None => format!("{:?}", e.command) == *command,
+                        }
+                });
+                if let Some(entry) = rejected {
+                    entry.status = Status::Rejected(error.clone());
+                    settled.push(entry);
+                }
+            }
+            SaiEvent::CommandFinished { command_ref: Some(seq), .. } => {
+                if let Some(entry) = self.entries.iter_mut().find(|e| e.seq == *seq && e.status == Status::Sent) {
+                    entry.status = Status::Completed;
+                    settled.push(entry);
+                }
+            }
+            SaiEvent::UnitDestroyed { unit, .. } | SaiEvent::UnitGiven { unit, .. } | SaiEvent::UnitCaptured { unit, .. } => {
+                let reason = match event {
+                    SaiEvent::UnitDestroyed { .. } => format!("unit {} was destroyed", unit),
+                    _ => format!("unit {} changed hands", unit),
+                };
+                for entry in self.pending_orders(*unit) {
+                    entry.status = Status::Failed(reason.clone());
+                    settled.push(entry);
+                }
+            }
+            _ => {}
+        }
+        let settled: Vec<Entry> = settled.into_iter().filter(|e| e.notify).map(|e| e.clone()).collect();
+        std::mem::take(&mut self.superseded).into_iter().chain(settled).collect()
+    }
+
+    /// The sent orders for `unit` still awaiting their `command_finished`.
+    fn pending_orders(&mut self, unit: UnitId) -> impl Iterator<Item = &mut Entry> {
+        self.entries.iter_mut().filter(move |e| {
+            e.status == Status::Sent
+                && !matches!(e.command, SaiCommand::Stop { .. })
+                && e.command.order().is_some_and(|(u, _)| u == unit)
+        })
+    }
+
+    /// The newest `limit` entries, oldest first.
+    pub fn recent(&self, limit: usize) -> impl Iterator<Item = &Entry> {
+        self.entries.iter().skip(self.entries.len().saturating_sub(limit))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stop(unit_id: i32) -> SaiCommand {
+        SaiCommand::Stop { unit_id: UnitId(unit_id) }
+    }
+
+    #[test]
+    fn test_history_is_bounded() {
+        let mut history = CommandHistory::new(3);
+        for unit in 1..=5 {
+            history.record(&stop(unit), Source::Publish, Some(unit * 30), &Ok(()));
+        }
+        let seqs: Vec<u64> = history.recent(10).map(|e| e.seq).collect();
+        assert_eq!(seqs, [3, 4, 5]);
+        let seqs: Vec<u64> = history.recent(2).map(|e| e.seq).collect();
+        assert_eq!(seqs, [4, 5]);
+    }
+
+    #[test]
+    fn test_command_errors_correlated() {
+        let mut history = CommandHistory::new(10);
+        history.record(&stop(4), Source::Tool, Some(30), &Ok(()));
+        history.record(&stop(5), Source::Publish, Some(60), &Ok(()));
+        history.record(&stop(4), Source::Automation, None, &Err("No SAI connection".into()));
+        history.record(&stop(4), Source::Publish, Some(90), &Ok(()));
+
+        history.observe(&SaiEvent::CommandError {
+            error: "unit 4 does not exist".into(),
+            command: format!("{:?}", stop(4)),
+            command_ref: None,
+        });
+        let statuses: Vec<&str> = history.recent(10).map(|e| e.status.as_str()).collect();
+        // The newest matching command that got through is the one blamed.
+        assert_eq!(statuses, ["sent", "sent", "send_failed", "rejected"]);
+
+        let entries: Vec<&Entry> = history.recent(10).collect();
+        assert_eq!(
+            entries[3].line(),
+            "#4 frame 90 publish: {\"type\":\"stop\",\"unit_id\":4} — rejected (unit 4 does not exist)"
+        );
+        assert_eq!(entries[2].to_json()["source"], "automation");
+        assert_eq!(entries[2].to_json()["error"], "No SAI connection");
+        assert_eq!(entries[1].to_json()["command"], serde_json::json!({"type": "stop", "unit_id": 5}));
+
+        // Session logs read back to the same entries.
+        let mut logged = entries[3].to_json();
+        logged["type"] = LOG_TYPE.into();
+        assert_eq!(Entry::from_log(&logged).as_ref(), Some(entries[3]));
+        assert_eq!(Entry::from_log(&entries[2].to_json()).unwrap().status, entries[2].status);
+    }
+
+    #[test]
+    fn test_orders_followed_by_ref() {
+        let moved = |unit, queue| SaiCommand::Move { unit_id: UnitId(unit), x: 100.0, y: 0.0, z: 100.0, queue };
+        let finished = |seq| SaiEvent::CommandFinished {
+            unit: UnitId(4),
+            unit_name: None,
+            command_id: -1,
+            command_topic: 0,
+            command_ref: Some(seq),
+        };
+        let mut history = CommandHistory::new(10);
+        assert_eq!(history.next_seq(), 1);
+        history.record(&moved(4, false), Source::Tool, Some(30), &Ok(()));
+        history.record(&moved(4, true), Source::Tool, Some(30), &Ok(()));
+        history.record(&moved(5, false), Source::Tool, Some(30), &Ok(()));
+        history.notify_from(2);
+        assert!(history.observe(&finished(1)).is_empty(), "#1 wasn't to be notified");
+        let notified = history.observe(&finished(2));
+        assert_eq!(notified.iter().map(|e| (e.seq, e.status.as_str())).collect::<Vec<_>>(), [(2, "completed")]);
+        assert!(history.observe(&finished(2)).is_empty(), "settled once");
+
+        // An unqueued order replaces the unit's queue; a queued one joins it.
+        history.record(&moved(4, false), Source::Tool, Some(60), &Ok(()));
+        history.record(&moved(4, true), Source::Tool, Some(60), &Ok(()));
+        history.record(&SaiCommand::SetFireState { unit_id: UnitId(4), state: 2 }, Source::Tool, Some(60), &Ok(()));
+        history.notify_from(4);
+        history.record(&SaiCommand::Stop { unit_id: UnitId(4) }, Source::Tool, Some(90), &Ok(()));
+        let notified = history.observe(&SaiEvent::UnitIdle { unit: UnitId(4), unit_name: None });
+        assert_eq!(notified.iter().map(|e| e.seq).collect::<Vec<_>>(), [4, 5]);
+
+        history.observe(&SaiEvent::UnitDestroyed {
+            unit: UnitId(5),
+            unit_name: None,
+            attacker: UnitId(90),
+            attacker_name: None,
+            attacker_team: None,
+            attacker_relation: None,
+            weapon_def_id: sai_protocol::WeaponDefId(0),
+        });
+        history.observe(&SaiEvent::CommandError {
+            error: "unit 4 does not exist".into(),
+            command: "Stop".into(),
+            command_ref: Some(7),
+        });
+        let statuses: Vec<&str> = history.recent(10).map(|e| e.status.as_str()).collect();
+        assert_eq!(statuses, ["completed", "completed", "failed", "superseded", "superseded", "sent", "rejected"]);
+        let entries: Vec<&Entry> = history.recent(10).collect();
+        assert_eq!(entries[2].to_json()["error"], "unit 5 was destroyed");
+        assert_eq!(Entry::from_log(&entries[3].to_json()).unwrap().status, Status::Superseded);
+    }
+}
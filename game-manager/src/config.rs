@@ -0,0 +1,281 @@
+//! GameManager config file: `gm_config.json` in the write dir, or the path
+//! given with `--config`. Everything in it is optional; without the file
+//! the defaults apply.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use sai_protocol::{UpdateCadence, AGGREGATABLE_EVENTS};
+use serde::Deserialize;
+
+use crate::army::ArmyConfig;
+use crate::audit::AuditConfig;
+use crate::autorespond::RuleConfig;
+use crate::chaos::ChaosConfig;
+use crate::command_history::CommandHistoryConfig;
+use crate::credentials::StoredAccount;
+use crate::engine_env::EngineEnvConfig;
+use crate::lobby_reconnect::ReconnectConfig;
+use crate::mcpl_link::DeliveryConfig;
+use crate::mcpl_server::StdioConfig;
+use crate::observer::StreamObserverConfig;
+use crate::opponents::Opponent;
+use crate::profiles::GameProfile;
+use crate::pacing::PacingConfig;
+use crate::scope::{ScopeConfig, CHANNEL_OPS};
+use crate::status_page::StatusPageConfig;
+
+pub const CONFIG_FILE: &str = "gm_config.json";
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GmConfig {
+    /// Chat rules answered without the agent (see `autorespond`).
+    #[serde(default)]
+    pub auto_respond: Vec<RuleConfig>,
+    /// Periodic state lines as MCPL stream events (see `observer`).
+    #[serde(default)]
+    pub stream_observer: StreamObserverConfig,
+    /// Named sets of allowed tools and channel operations (see `scope`).
+    #[serde(default)]
+    pub scopes: BTreeMap<String, ScopeConfig>,
+    /// Scope for clients that don't ask for one. Unset means unrestricted.
+    #[serde(default)]
+    pub default_scope: Option<String>,
+    /// Join battles these players open in the lobby (names ignore case).
+    #[serde(default)]
+    pub auto_join_founders: Vec<String>,
+    /// Commands kept per channel for `game_command_history`.
+    #[serde(default)]
+    pub command_history: CommandHistoryConfig,
+    /// Timeouts and queueing for messages to the MCPL client (see `mcpl_link`).
+    #[serde(default)]
+    pub mcpl_delivery: DeliveryConfig,
+    /// What happens when a `--stdio` client closes stdin.
+    #[serde(default)]
+    pub stdio: StdioConfig,
+    /// Custom AIs for the opponent catalog (see `opponents`).
+    #[serde(default)]
+    pub opponents: Vec<Opponent>,
+    /// Environment and resource limits for engine processes (see `engine_env`).
+    #[serde(default)]
+    pub engine_env: EngineEnvConfig,
+    /// Where and how actions are audited (see `audit`).
+    #[serde(default)]
+    pub audit: AuditConfig,
+    /// Lobby accounts for `lobby_login_stored`, by name (see `credentials`).
+    #[serde(default)]
+    pub credentials: BTreeMap<String, StoredAccount>,
+    /// Event types the bridge counts per unit into updates instead of
+    /// forwarding. Unset means `weapon_fired` and `command_finished`.
+    #[serde(default)]
+    pub aggregate_events: Option<Vec<String>>,
+    /// How often bridges send updates: fixed, or adaptive to the fighting
+    /// (see `sai_protocol::UpdateCadence`).
+    #[serde(default)]
+    pub update_cadence: UpdateCadence,
+    /// Faults injected into SAI connections, for testing (see `chaos`).
+    #[serde(default)]
+    pub chaos: Option<ChaosConfig>,
+    /// Orders per interval sent to each SAI bridge (see `pacing`).
+    #[serde(default)]
+    pub pacing: PacingConfig,
+    /// Read-only HTTP status page for operators (see `status_page`).
+    #[serde(default)]
+    pub status_page: StatusPageConfig,
+    /// Reconnecting to the lobby, and the calls queued meanwhile (see
+    /// `lobby_reconnect`).
+    #[serde(default)]
+    pub lobby_reconnect: ReconnectConfig,
+    /// Roles the state stream groups units into, for every game; unset
+    /// means each game profile's (see `army`).
+    #[serde(default)]
+    pub army: Option<ArmyConfig>,
+    /// Game profiles added to or replacing the built-in ones (see
+    /// `profiles`).
+    #[serde(default)]
+    pub profiles: Vec<GameProfile>,
+}
+
+impl GmConfig {
+    /// The config file to use: `explicit` if given, else the write dir's.
+    pub fn path(explicit: Option<&str>, write_dir: &Path) -> PathBuf {
+        explicit.map(PathBuf::from).unwrap_or_else(|| write_dir.join(CONFIG_FILE))
+    }
+
+    /// Load the config. A missing file is the default config; a file that
+    /// doesn't parse is an error.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let raw = match std::fs::read_to_string(path) {
+            Ok(raw) => raw,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+        };
+        let config: Self = serde_json::from_str(&raw)
+            .map_err(|e| format!("Invalid config {}: {}", path.display(), e))?;
+        config.validate().map_err(|e| format!("Invalid config {}: {}", path.display(), e))?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.stream_observer.interval_secs < 1.0 {
+            return Err("stream_observer.interval_secs must be at least 1".into());
+        }
+        if self.command_history.size == 0 {
+            return Err("command_history.size must be at least 1".into());
+        }
+        if self.mcpl_delivery.timeout_secs <= 0.0 {
+            return Err("mcpl_delivery.timeout_secs must be positive".into());
+        }
+        if self.mcpl_delivery.queue_size == 0 || self.mcpl_delivery.max_failures == 0 || self.mcpl_delivery.replay_size == 0 {
+            return Err("mcpl_delivery.queue_size, max_failures and replay_size must be at least 1".into());
+        }
+        if !(0.0..=5.0).contains(&self.mcpl_delivery.changes_window_secs) {
+            return Err("mcpl_delivery.changes_window_secs must be between 0 and 5".into());
+        }
+        if self.stdio.linger_secs < 0.0 {
+            return Err("stdio.linger_secs must not be negative".into());
+        }
+        if self.engine_env.limits.memory_mb == Some(0) {
+            return Err("engine_env.limits.memory_mb must be at least 1".into());
+        }
+        if self.audit.max_bytes == 0 {
+            return Err("audit.max_bytes must be at least 1".into());
+        }
+        if self.opponents.iter().any(|o| o.name.trim().is_empty()) {
+            return Err("opponents: every entry needs a name".into());
+        }
+        for (name, account) in &self.credentials {
+            account.validate(name)?;
+        }
+        if let Some(name) = self.aggregate_events.iter().flatten().find(|e| !AGGREGATABLE_EVENTS.contains(&e.as_str())) {
+            return Err(format!(
+                "aggregate_events: '{}' can't be aggregated (expected {})",
+                name,
+                AGGREGATABLE_EVENTS.join(", ")
+            ));
+        }
+        self.update_cadence.validate()?;
+        if let Some(chaos) = &self.chaos {
+            chaos.validate()?;
+        }
+        self.pacing.validate()?;
+        self.status_page.validate()?;
+        self.lobby_reconnect.validate()?;
+        if let Some(army) = &self.army {
+            army.validate()?;
+        }
+        for profile in &self.profiles {
+            profile.validate()?;
+        }
+        if let Some(name) = &self.default_scope {
+            if !self.scopes.contains_key(name) {
+                return Err(format!("default_scope '{}' is not defined in scopes", name));
+            }
+        }
+        for (name, scope) in &self.scopes {
+            if let Some(op) = scope.channels.iter().find(|op| *op != "*" && !CHANNEL_OPS.contains(&op.as_str())) {
+                return Err(format!(
+                    "scope '{}': unknown channel operation '{}' (expected {})",
+                    name, op, CHANNEL_OPS.join(", ")
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_config() {
+        let dir = std::env::temp_dir().join(format!("gm-config-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = GmConfig::path(None, &dir);
+        assert!(GmConfig::load(&path).unwrap().auto_respond.is_empty());
+
+        std::fs::write(&path, r#"{"auto_respond": [{"pattern": "^start\\?$", "reply": "yes"}]}"#).unwrap();
+        let config = GmConfig::load(&path).unwrap();
+        assert_eq!(config.auto_respond[0].reply.as_deref(), Some("yes"));
+        assert_eq!(config.command_history.size, crate::command_history::DEFAULT_SIZE);
+        assert_eq!(config.auto_respond[0].cooldown_secs, crate::autorespond::DEFAULT_COOLDOWN_SECS);
+
+        std::fs::write(&path, r#"{"stream_observer": {"enabled": true, "interval_secs": 0.1}}"#).unwrap();
+        assert!(GmConfig::load(&path).unwrap_err().ends_with("interval_secs must be at least 1"));
+
+        std::fs::write(&path, r#"{"scopes": {"game": {"tools": ["game_*"]}}, "default_scope": "play"}"#).unwrap();
+        assert!(GmConfig::load(&path).unwrap_err().ends_with("default_scope 'play' is not defined in scopes"));
+        std::fs::write(&path, r#"{"scopes": {"game": {"channels": ["publish", "rollback"]}}}"#).unwrap();
+        assert!(GmConfig::load(&path).unwrap_err().contains("unknown channel operation 'rollback'"));
+
+        std::fs::write(&path, r#"{"command_history": {"size": 0}}"#).unwrap();
+        assert!(GmConfig::load(&path).unwrap_err().ends_with("command_history.size must be at least 1"));
+        std::fs::write(&path, r#"{"mcpl_delivery": {"timeout_secs": 0}}"#).unwrap();
+        assert!(GmConfig::load(&path).unwrap_err().ends_with("mcpl_delivery.timeout_secs must be positive"));
+        std::fs::write(&path, r#"{"mcpl_delivery": {"max_failures": 3}}"#).unwrap();
+        let delivery = GmConfig::load(&path).unwrap().mcpl_delivery;
+        assert_eq!((delivery.timeout_secs, delivery.queue_size, delivery.max_failures), (5.0, 256, 3));
+        assert_eq!(delivery.changes_window_secs, 0.25);
+        std::fs::write(&path, r#"{"mcpl_delivery": {"changes_window_secs": 10}}"#).unwrap();
+        assert!(GmConfig::load(&path).unwrap_err().ends_with("changes_window_secs must be between 0 and 5"));
+
+        std::fs::write(&path, r#"{"opponents": [{"name": "MyAI", "games": ["Zero-K"]}]}"#).unwrap();
+        assert_eq!(GmConfig::load(&path).unwrap().opponents[0].games, ["Zero-K"]);
+        std::fs::write(&path, r#"{"opponents": [{"name": "MyAI", "installed": true}]}"#).unwrap();
+        assert!(GmConfig::load(&path).unwrap_err().contains("unknown field `installed`"));
+
+        std::fs::write(&path, r#"{"stdio": {}}"#).unwrap();
+        assert_eq!(GmConfig::load(&path).unwrap().stdio.linger_secs, 600.0);
+        std::fs::write(&path, r#"{"stdio": {"linger_secs": -1}}"#).unwrap();
+        assert!(GmConfig::load(&path).unwrap_err().ends_with("stdio.linger_secs must not be negative"));
+
+        std::fs::write(&path, r#"{"engine_env": {"set": {"OMP_NUM_THREADS": "4"}, "limits": {"core_dump_mb": 0}}}"#).unwrap();
+        let engine_env = GmConfig::load(&path).unwrap().engine_env;
+        assert!(engine_env.defaults);
+        assert_eq!(engine_env.limits.core_dump_mb, Some(0));
+        std::fs::write(&path, r#"{"engine_env": {"limits": {"memory_mb": 0}}}"#).unwrap();
+        assert!(GmConfig::load(&path).unwrap_err().ends_with("engine_env.limits.memory_mb must be at least 1"));
+
+        std::fs::write(&path, r#"{"audit": {"redact": ["pin"]}}"#).unwrap();
+        let audit = GmConfig::load(&path).unwrap().audit;
+        assert_eq!((audit.enabled, audit.keep, audit.redact), (true, 3, vec!["pin".to_string()]));
+        std::fs::write(&path, r#"{"audit": {"max_bytes": 0}}"#).unwrap();
+        assert!(GmConfig::load(&path).unwrap_err().ends_with("audit.max_bytes must be at least 1"));
+
+        std::fs::write(&path, r#"{"credentials": {"main": {"username": "agent", "password_env": "ZK_PASSWORD"}}}"#).unwrap();
+        assert_eq!(GmConfig::load(&path).unwrap().credentials["main"].password_env.as_deref(), Some("ZK_PASSWORD"));
+        std::fs::write(&path, r#"{"credentials": {"main": {"username": "agent"}}}"#).unwrap();
+        assert!(GmConfig::load(&path).unwrap_err().ends_with("credentials.main: set one of password_env and password_file"));
+        std::fs::write(&path, r#"{"credentials": {"main": {"username": "agent", "password": "hunter2"}}}"#).unwrap();
+        assert!(GmConfig::load(&path).unwrap_err().contains("unknown field `password`"));
+
+        std::fs::write(&path, r#"{"aggregate_events": []}"#).unwrap();
+        assert_eq!(GmConfig::load(&path).unwrap().aggregate_events, Some(Vec::new()));
+        std::fs::write(&path, r#"{"aggregate_events": ["unit_idle"]}"#).unwrap();
+        assert!(GmConfig::load(&path).unwrap_err().contains("aggregate_events: 'unit_idle' can't be aggregated"));
+
+        std::fs::write(&path, r#"{"update_cadence": {"mode": "adaptive", "max_frames": 60}}"#).unwrap();
+        let cadence = GmConfig::load(&path).unwrap().update_cadence;
+        assert_eq!((cadence.mode, cadence.min_frames, cadence.max_frames), (sai_protocol::UpdateMode::Adaptive, 10, 60));
+        std::fs::write(&path, r#"{"update_cadence": {"busy_rate": 1, "quiet_rate": 2}}"#).unwrap();
+        assert!(GmConfig::load(&path).unwrap_err().ends_with("quiet_rate must be at least 0 and below busy_rate"));
+
+        std::fs::write(&path, r#"{"chaos": {"latency_ms": 200, "drop_percent": 5}}"#).unwrap();
+        let chaos = GmConfig::load(&path).unwrap().chaos.unwrap();
+        assert_eq!((chaos.latency_ms, chaos.drop_percent, chaos.disconnect_every_secs), (200, 5.0, None));
+        std::fs::write(&path, r#"{"chaos": {"drop_percent": 150}}"#).unwrap();
+        assert!(GmConfig::load(&path).unwrap_err().ends_with("chaos.drop_percent must be between 0 and 100"));
+
+        std::fs::write(&path, r#"{"pacing": {"max_commands": 50}}"#).unwrap();
+        let pacing = GmConfig::load(&path).unwrap().pacing;
+        assert_eq!((pacing.max_commands, pacing.interval_ms), (50, 100));
+        std::fs::write(&path, r#"{"pacing": {"interval_ms": 0}}"#).unwrap();
+        assert!(GmConfig::load(&path).unwrap_err().ends_with("pacing.max_commands and interval_ms must be at least 1"));
+
+        std::fs::write(&path, r#"{"auto_respnd": []}"#).unwrap();
+        assert!(GmConfig::load(&path).unwrap_err().starts_with("Invalid config"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,281 @@
+//! Battle content: whether the map, game and engine a battle needs are
+//! installed, and fetching missing archives with pr-downloader.
+//!
+//! Downloads run as background tasks; the GameManager polls them from its
+//! main loop and turns their progress into push events.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::sync::mpsc;
+
+/// Downloads that take longer than this are abandoned.
+pub const DEFAULT_TIMEOUT_SECS: u64 = 600;
+
+/// Progress is reported in steps of this many percent.
+const PROGRESS_STEP: u32 = 10;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Archive {
+    Map(String),
+    Game(String),
+}
+
+impl Archive {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Archive::Map(_) => "map",
+            Archive::Game(_) => "game",
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Archive::Map(name) | Archive::Game(name) => name,
+        }
+    }
+
+    fn download_flag(&self) -> &'static str {
+        match self {
+            Archive::Map(_) => "--download-map",
+            Archive::Game(_) => "--download-game",
+        }
+    }
+}
+
+impl std::fmt::Display for Archive {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.kind(), self.name())
+    }
+}
+
+/// What a battle needs that isn't installed.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ContentCheck {
+    pub missing: Vec<Archive>,
+    /// An engine version that isn't installed. pr-downloader doesn't fetch
+    /// these for us; the agent has to get it installed.
+    pub missing_engine: Option<String>,
+}
+
+impl ContentCheck {
+    pub fn is_complete(&self) -> bool {
+        self.missing.is_empty() && self.missing_engine.is_none()
+    }
+}
+
+/// Check a battle's map, game and engine (empty ones are skipped) against
+/// the maps directory, the engine's archive cache and the installed engines.
+pub fn check(write_dir: &Path, spring_home: &Path, map: &str, game: &str, engine: &str) -> ContentCheck {
+    let mut known = archive_cache_names(write_dir);
+    known.extend(archive_files(&spring_home.join("maps")));
+    let mut missing = Vec::new();
+    if !map.is_empty() && !known.contains(&normalize(map)) {
+        missing.push(Archive::Map(map.to_string()));
+    }
+    if !game.is_empty() && !known.contains(&normalize(game)) {
+        missing.push(Archive::Game(game.to_string()));
+    }
+    let missing_engine = (!engine.is_empty()
+        && crate::engine::find_engine_dir(spring_home, Some(engine)).is_err())
+    .then(|| engine.to_string());
+    ContentCheck { missing, missing_engine }
+}
+
+/// Compare archive names loosely: "Comet Catcher Redux v3.1" is the map in
+/// `comet_catcher_redux_v3.1.sd7`.
+fn normalize(name: &str) -> String {
+    let stem = [".sd7", ".sdz", ".sdd", ".sdp"]
+        .iter()
+        .find_map(|ext| name.strip_suffix(ext))
+        .unwrap_or(name);
+    stem.chars().filter(|c| c.is_alphanumeric()).flat_map(char::to_lowercase).collect()
+}
+
+/// Archive names the engine found on its last scan, from the archive cache
+/// (`cache/ArchiveCache*.lua`) in the write dir. Empty before any scan.
+fn archive_cache_names(write_dir: &Path) -> HashSet<String> {
+    let Ok(entries) = std::fs::read_dir(write_dir.join("cache")) else {
+        return HashSet::new();
+    };
+    let mut names = HashSet::new();
+    for entry in entries.flatten() {
+        let file_name = entry.file_name();
+        let file_name = file_name.to_string_lossy();
+        if !(file_name.starts_with("ArchiveCache") && file_name.ends_with(".lua")) {
+            continue;
+        }
+        let Ok(cache) = std::fs::read_to_string(entry.path()) else { continue };
+        for line in cache.lines() {
+            let Some(value) = line.trim().strip_prefix("name = \"") else { continue };
+            if let Some(name) = value.strip_suffix("\",") {
+                names.insert(normalize(name));
+            }
+        }
+    }
+    names
+}
+
+fn archive_files(dir: &Path) -> HashSet<String> {
+    std::fs::read_dir(dir)
+        .map(|entries| {
+            entries.flatten().map(|e| normalize(&e.file_name().to_string_lossy())).collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Percentage from a pr-downloader progress line such as
+/// `[Progress]  69% [=======     ] 6402174/9220917`.
+fn parse_progress(line: &str) -> Option<u32> {
+    let rest = line.trim().strip_prefix("[Progress]")?.trim_start();
+    rest.split_once('%')?.0.parse().ok()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DownloadUpdate {
+    Progress { archive: Archive, percent: u32 },
+    Done { archive: Archive },
+    Failed { archive: Archive, reason: String },
+}
+
+/// Background pr-downloader runs.
+#[derive(Debug)]
+pub struct Downloads {
+    /// The pr-downloader binary.
+    pub binary: PathBuf,
+    /// Where downloads go: the shared Spring home, which the write dir links to.
+    pub write_path: PathBuf,
+    pub timeout: Duration,
+    active: HashSet<Archive>,
+    tx: mpsc::UnboundedSender<DownloadUpdate>,
+    rx: mpsc::UnboundedReceiver<DownloadUpdate>,
+}
+
+impl Downloads {
+    pub fn new(binary: PathBuf, write_path: PathBuf) -> Self {
+        let (tx, rx) = mpsc::unbounded_channel();
+        Self {
+            binary,
+            write_path,
+            timeout: Duration::from_secs(DEFAULT_TIMEOUT_SECS),
+            active: HashSet::new(),
+            tx,
+            rx,
+        }
+    }
+
+    /// Start downloading `archive` unless it already is.
+    pub fn start(&mut self, archive: Archive) {
+        if !self.active.insert(archive.clone()) {
+            return;
+        }
+        tracing::info!("Downloading {}", archive);
+        tokio::spawn(run(
+            self.binary.clone(),
+            self.write_path.clone(),
+            archive,
+            self.timeout,
+            self.tx.clone(),
+        ));
+    }
+
+    /// Updates since the last poll.
+    pub fn poll(&mut self) -> Vec<DownloadUpdate> {
+        let mut updates = Vec::new();
+        while let Ok(update) = self.rx.try_recv() {
+            if let DownloadUpdate::Done { archive } | DownloadUpdate::Failed { archive, .. } = &update {
+                self.active.remove(archive);
+            }
+            updates.push(update);
+        }
+        updates
+    }
+}
+
+async fn run(
+    binary: PathBuf,
+    write_path: PathBuf,
+    archive: Archive,
+    timeout: Duration,
+    tx: mpsc::UnboundedSender<DownloadUpdate>,
+) {
+    let spawned = tokio::process::Command::new(&binary)
+        .arg("--filesystem-writepath")
+        .arg(&write_path)
+        .arg(archive.download_flag())
+        .arg(archive.name())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::null())
+        .kill_on_drop(true)
+        .spawn();
+    let mut child = match spawned {
+        Ok(child) => child,
+        Err(e) => {
+            let reason = format!("cannot run {}: {}", binary.display(), e);
+            let _ = tx.send(DownloadUpdate::Failed { archive, reason });
+            return;
+        }
+    };
+
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let progress = async {
+        let mut lines = BufReader::new(stdout).lines();
+        let mut reported = 0;
+        while let Ok(Some(line)) = lines.next_line().await {
+            match parse_progress(&line) {
+                Some(percent) if percent >= reported + PROGRESS_STEP && percent < 100 => {
+                    reported = percent - percent % PROGRESS_STEP;
+                    let _ = tx.send(DownloadUpdate::Progress { archive: archive.clone(), percent });
+                }
+                _ => {}
+            }
+        }
+        child.wait().await
+    };
+    let update = match tokio::time::timeout(timeout, progress).await {
+        Ok(Ok(status)) if status.success() => DownloadUpdate::Done { archive },
+        Ok(Ok(status)) => DownloadUpdate::Failed { archive, reason: format!("pr-downloader {}", status) },
+        Ok(Err(e)) => DownloadUpdate::Failed { archive, reason: e.to_string() },
+        Err(_) => DownloadUpdate::Failed { archive, reason: format!("timed out after {}s", timeout.as_secs()) },
+    };
+    let _ = tx.send(update);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_content() {
+        let dir = std::env::temp_dir().join(format!("gm-content-{}", uuid::Uuid::new_v4()));
+        let (write_dir, home) = (dir.join("write"), dir.join("home"));
+        let check_all = || check(&write_dir, &home, "Comet Catcher Redux v3.1", "Zero-K v1.12.7.0", "105.1.1");
+        let first = check_all();
+        assert_eq!(
+            first.missing,
+            [Archive::Map("Comet Catcher Redux v3.1".into()), Archive::Game("Zero-K v1.12.7.0".into())]
+        );
+        assert_eq!(first.missing_engine.as_deref(), Some("105.1.1"));
+
+        std::fs::create_dir_all(home.join("maps")).unwrap();
+        std::fs::write(home.join("maps/comet_catcher_redux_v3.1.sd7"), "").unwrap();
+        std::fs::create_dir_all(write_dir.join("cache")).unwrap();
+        std::fs::write(
+            write_dir.join("cache/ArchiveCache20.lua"),
+            "local archiveCache = {\n\tarchives = {\n\t\t{\n\t\t\tname = \"Zero-K v1.12.7.0\",\n",
+        )
+        .unwrap();
+        std::fs::create_dir_all(home.join("engine/linux64/105.1.1")).unwrap();
+        assert!(check_all().is_complete());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_parse_progress() {
+        assert_eq!(parse_progress("[Progress]  69% [=======     ] 6402174/9220917"), Some(69));
+        assert_eq!(parse_progress("[Progress] 100% [============] 9220917/9220917"), Some(100));
+        assert_eq!(parse_progress("[Info] Download complete!"), None);
+    }
+}
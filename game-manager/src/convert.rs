@@ -0,0 +1,243 @@
+//! `game-manager convert-log`: turn a recorded session log into a clean
+//! event dataset without re-running the game.
+//!
+//! Each event gets the text the agent would have read live (the same
+//! summarizer), the frame it came at, and every
+//! [`analysis::ECONOMY_SAMPLE_FRAMES`] a state line rebuilt the way the
+//! stream observer builds it. The post-game summary closes the output.
+//! Logs cut short by a crash are converted as far as they go: a torn last
+//! line is dropped and the output says the game never ended.
+
+use std::fmt::Write as _;
+
+use crate::analysis::{self, GameSummary};
+use crate::command_history::{self, Entry};
+use crate::observer::{ChannelState, Include};
+use crate::recording;
+use crate::sai_ipc::{self, BuildInfo, SaiEvent};
+
+/// State line parts in converted logs: what a log can reconstruct.
+const STATE_PARTS: Include =
+    Include { frame: true, economy: true, units: true, threats: false, army: false, factories: false };
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// JSON lines: one per event, state line and the summary.
+    Json,
+    /// A readable timeline.
+    Markdown,
+}
+
+impl Format {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "json" => Some(Format::Json),
+            "markdown" | "md" => Some(Format::Markdown),
+            _ => None,
+        }
+    }
+}
+
+/// One entry of the converted timeline.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    pub frame: i32,
+    pub kind: String,
+    pub text: String,
+    /// The event, command or state as structured data.
+    pub data: serde_json::Value,
+}
+
+/// A session log, converted.
+#[derive(Debug)]
+pub struct Converted {
+    pub records: Vec<Record>,
+    pub summary: GameSummary,
+    /// Lines that weren't JSON, the torn last line of a crashed run included.
+    pub skipped_lines: usize,
+    /// The log stops before the bridge's release.
+    pub truncated: bool,
+    /// The log's `session_header`; logs from before headers have none.
+    pub header: Option<serde_json::Value>,
+}
+
+/// Convert a session log's text.
+pub fn convert(log: &str) -> Converted {
+    let mut events = Vec::new();
+    let mut skipped_lines = 0;
+    for line in log.lines().filter(|l| !l.trim().is_empty()) {
+        match SaiEvent::from_line(line) {
+            Ok(event) => events.push(event),
+            Err(_) => skipped_lines += 1,
+        }
+    }
+
+    let mut state = ChannelState::default();
+    let mut records = Vec::new();
+    let mut last_state: Option<i32> = None;
+    let mut header = None;
+    for event in &events {
+        state.observe(event);
+        if let SaiEvent::Unknown { raw } = event {
+            if raw["type"] == recording::HEADER_TYPE {
+                header = Some(raw.clone());
+                continue;
+            }
+            if raw["type"] == command_history::LOG_TYPE {
+                let text = Entry::from_log(raw).map_or_else(|| format!("Command: {}", raw["command"]), |e| e.line());
+                let frame = raw["frame"].as_i64().map_or(state.frame, |f| f as i32);
+                records.push(Record { frame, kind: command_history::LOG_TYPE.into(), text, data: raw.clone() });
+                continue;
+            }
+        }
+        // Updates are the live stream's ticks; they show as state lines.
+        if let SaiEvent::Update { awaiting_commands: false, .. } = event {
+            if last_state.is_none_or(|f| state.frame - f >= analysis::ECONOMY_SAMPLE_FRAMES) {
+                last_state = Some(state.frame);
+                let (text, data) = state.line(STATE_PARTS, &[], None, None);
+                records.push(Record { frame: state.frame, kind: "state".into(), text, data });
+            }
+            continue;
+        }
+        records.push(Record {
+            frame: state.frame,
+            kind: event.type_name().to_string(),
+            text: sai_ipc::summarize_event(event),
+            data: sai_ipc::event_metadata(event).map_or(serde_json::Value::Null, |m| m["event"].clone()),
+        });
+    }
+
+    let truncated = !events.iter().any(|e| matches!(e, SaiEvent::Release { .. }));
+    Converted { summary: analysis::analyze(&events), records, skipped_lines, truncated, header }
+}
+
+/// Game time of `frame` as m:ss.
+fn clock(frame: i32) -> String {
+    let secs = frame.max(0) / 30;
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
+
+impl Converted {
+    pub fn render(&self, format: Format) -> String {
+        match format {
+            Format::Json => self.to_jsonl(),
+            Format::Markdown => self.to_markdown(),
+        }
+    }
+
+    fn to_jsonl(&self) -> String {
+        let mut out = String::new();
+        for r in &self.records {
+            let line = serde_json::json!({"frame": r.frame, "type": r.kind, "text": r.text, "data": r.data});
+            let _ = writeln!(out, "{}", line);
+        }
+        let mut summary = serde_json::json!({
+            "type": "summary",
+            "truncated": self.truncated,
+            "skippedLines": self.skipped_lines,
+            "summary": self.summary,
+        });
+        if let Some(header) = &self.header {
+            summary["session"] = header.clone();
+        }
+        let _ = writeln!(out, "{}", summary);
+        out
+    }
+
+    fn to_markdown(&self) -> String {
+        let s = &self.summary;
+        let mut out = String::from("# Session log\n\n");
+        let _ = writeln!(out, "Outcome: {} after {} frames ({}).", s.outcome, s.frames, clock(s.frames));
+        if let Some(header) = &self.header {
+            let bridge = serde_json::from_value::<BuildInfo>(header["bridge"].clone())
+                .map_or_else(|_| "an unknown bridge".to_string(), |b| format!("bridge {}", b.describe()));
+            let gm = header["game_manager_version"].as_str().unwrap_or("?");
+            let _ = writeln!(out, "\nRecorded by GameManager {} with {}.", gm, bridge);
+        }
+        if self.truncated {
+            out += "\n> The log ends without the bridge's release: the game or the GameManager stopped mid-game.\n";
+        }
+        if self.skipped_lines > 0 {
+            let _ = writeln!(out, "\n> {} unreadable line(s) skipped.", self.skipped_lines);
+        }
+
+        out += "\n## Timeline\n\n";
+        for r in &self.records {
+            let text = match r.kind.as_str() {
+                "state" => format!("*{}*", r.text),
+                command_history::LOG_TYPE => format!("Command {}", r.text),
+                _ => r.text.clone(),
+            };
+            let _ = writeln!(out, "- `{}` {}", clock(r.frame), text);
+        }
+
+        out += "\n## Summary\n\n";
+        let counts = |map: &std::collections::BTreeMap<String, u32>| {
+            if map.is_empty() {
+                return "none".to_string();
+            }
+            map.iter().map(|(name, n)| format!("{} {}", n, name)).collect::<Vec<_>>().join(", ")
+        };
+        let _ = writeln!(out, "- Built: {}", counts(&s.units_built));
+        let _ = writeln!(out, "- Lost: {}", counts(&s.units_lost));
+        let _ = writeln!(out, "- Killed: {}", counts(&s.enemies_killed));
+        let _ = writeln!(
+            out,
+            "- Commands: {} sent ({}), {} failed",
+            s.commands.sent,
+            counts(&s.commands.by_type),
+            s.commands.errors
+        );
+        if !s.economy.is_empty() {
+            out += "\n| Time | Metal income | Energy income |\n|------|------|------|\n";
+            for sample in &s.economy {
+                let e = &sample.economy;
+                let _ = writeln!(out, "| {} | {:.1} | {:.1} |", clock(sample.frame), e.metal.income, e.energy.income);
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHORT_GAME: &str = include_str!("../tests/fixtures/sessions/short_game.jsonl");
+
+    #[test]
+    fn test_golden_outputs() {
+        let converted = convert(SHORT_GAME);
+        assert!(!converted.truncated);
+        assert_eq!(converted.skipped_lines, 0);
+        assert_eq!(converted.render(Format::Json), include_str!("../tests/fixtures/sessions/short_game.converted.jsonl"));
+        assert_eq!(converted.render(Format::Markdown), include_str!("../tests/fixtures/sessions/short_game.md"));
+    }
+
+    #[test]
+    fn test_truncated_log() {
+        // A crash mid-write: the last line is torn and no release follows.
+        let cut = SHORT_GAME.find(r#"{"type":"unit_damaged","unit":103"#).unwrap() + 30;
+        let converted = convert(&SHORT_GAME[..cut]);
+        assert!(converted.truncated);
+        assert_eq!(converted.skipped_lines, 1);
+        assert_eq!(converted.summary.outcome, "unknown");
+        let last = converted.records.last().unwrap();
+        assert_eq!((last.kind.as_str(), last.frame), ("unit_damaged", 1830));
+        assert!(converted.render(Format::Markdown).contains("stopped mid-game"));
+        assert!(converted.render(Format::Json).lines().last().unwrap().contains("\"truncated\":true"));
+    }
+
+    #[test]
+    fn test_session_header() {
+        let header = r#"{"type":"session_header","channel":"game:local-1","game_manager_version":"0.1.0","bridge":{"bridge_version":"0.1.0","git_hash":"abc1234","engine_version":"105.1.1-2511-g747f18b"}}"#;
+        let converted = convert(&format!("{}\n{}", header, SHORT_GAME));
+        assert_eq!(converted.records, convert(SHORT_GAME).records);
+        assert!(converted
+            .render(Format::Markdown)
+            .contains("Recorded by GameManager 0.1.0 with bridge 0.1.0 (abc1234), engine 105.1.1-2511-g747f18b."));
+        let json = converted.render(Format::Json);
+        let summary: serde_json::Value = serde_json::from_str(json.lines().last().unwrap()).unwrap();
+        assert_eq!(summary["session"]["bridge"]["git_hash"], "abc1234");
+    }
+}
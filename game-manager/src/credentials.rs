@@ -0,0 +1,157 @@
+//! Stored lobby credentials, so the agent can log in with
+//! `lobby_login_stored` without a password ever passing through its
+//! context, a tool result or the audit log.
+//!
+//! The config file's `credentials` names accounts. Each has a username and
+//! says where its password lives: an environment variable or a file. The
+//! password is read only when logging in, so rotating it needs no restart.
+//! A password file must not be readable by group or others.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+/// Account used when `lobby_login_stored` names none and several are stored.
+pub const DEFAULT_ACCOUNT: &str = "default";
+
+/// One config file `credentials` entry.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StoredAccount {
+    pub username: String,
+    /// Environment variable holding the password.
+    #[serde(default)]
+    pub password_env: Option<String>,
+    /// File holding the password; surrounding whitespace is ignored.
+    #[serde(default)]
+    pub password_file: Option<PathBuf>,
+}
+
+impl StoredAccount {
+    /// Exactly one password source, and a username.
+    pub fn validate(&self, name: &str) -> Result<(), String> {
+        if self.username.trim().is_empty() {
+            return Err(format!("credentials.{}: username must not be empty", name));
+        }
+        match (&self.password_env, &self.password_file) {
+            (Some(_), None) | (None, Some(_)) => Ok(()),
+            _ => Err(format!("credentials.{}: set one of password_env and password_file", name)),
+        }
+    }
+
+    /// Read the password. Errors name where it was looked for, never what
+    /// was found.
+    pub fn password(&self, name: &str) -> Result<String, String> {
+        if let Some(var) = &self.password_env {
+            return match std::env::var(var) {
+                Ok(password) if !password.is_empty() => Ok(password),
+                _ => Err(format!("Environment variable {} for account '{}' is not set", var, name)),
+            };
+        }
+        let Some(path) = &self.password_file else {
+            return Err(format!("Account '{}' has no password source", name));
+        };
+        check_private(path).map_err(|e| format!("Password file for account '{}': {}", name, e))?;
+        let password = std::fs::read_to_string(path)
+            .map_err(|e| format!("Password file for account '{}': {}: {}", name, path.display(), e))?;
+        let password = password.trim();
+        if password.is_empty() {
+            return Err(format!("Password file for account '{}' is empty: {}", name, path.display()));
+        }
+        Ok(password.to_string())
+    }
+}
+
+/// A password file must be readable by its owner only.
+#[cfg(unix)]
+fn check_private(path: &Path) -> Result<(), String> {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = std::fs::metadata(path).map_err(|e| format!("{}: {}", path.display(), e))?.permissions().mode();
+    if mode & 0o077 != 0 {
+        return Err(format!(
+            "{} is accessible by group or others (mode {:o}); chmod 600 it",
+            path.display(),
+            mode & 0o777
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn check_private(_path: &Path) -> Result<(), String> {
+    Ok(())
+}
+
+/// The account to log in with: `name` if given, else the only one, else
+/// [`DEFAULT_ACCOUNT`].
+pub fn select<'a>(
+    accounts: &'a BTreeMap<String, StoredAccount>,
+    name: Option<&str>,
+) -> Result<(&'a str, &'a StoredAccount), String> {
+    let known = || accounts.keys().map(String::as_str).collect::<Vec<_>>().join(", ");
+    if accounts.is_empty() {
+        return Err("No stored credentials (config credentials); use lobby_login".into());
+    }
+    let name = match name {
+        Some(name) => name,
+        None if accounts.len() == 1 => accounts.keys().next().unwrap(),
+        None => DEFAULT_ACCOUNT,
+    };
+    accounts
+        .get_key_value(name)
+        .map(|(name, account)| (name.as_str(), account))
+        .ok_or_else(|| format!("No stored account '{}' (stored: {})", name, known()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn accounts() -> BTreeMap<String, StoredAccount> {
+        serde_json::from_value(serde_json::json!({
+            "main": {"username": "agent", "password_env": "GM_CREDENTIALS_TEST_UNSET"},
+            "smurf": {"username": "agent2", "password_file": "/nonexistent/smurf.pw"}
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_select() {
+        let accounts = accounts();
+        assert_eq!(select(&accounts, Some("smurf")).unwrap().1.username, "agent2");
+        assert_eq!(select(&accounts, None).unwrap_err(), "No stored account 'default' (stored: main, smurf)");
+        assert_eq!(select(&accounts, Some("alt")).unwrap_err(), "No stored account 'alt' (stored: main, smurf)");
+        let one: BTreeMap<_, _> = accounts.into_iter().take(1).collect();
+        assert_eq!(select(&one, None).unwrap().0, "main");
+        assert!(select(&BTreeMap::new(), None).unwrap_err().starts_with("No stored credentials"));
+
+        let both: StoredAccount = serde_json::from_value(serde_json::json!(
+            {"username": "agent", "password_env": "A", "password_file": "/b"}
+        ))
+        .unwrap();
+        assert!(both.validate("x").unwrap_err().ends_with("set one of password_env and password_file"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_password_sources() {
+        use std::os::unix::fs::PermissionsExt;
+        let accounts = accounts();
+        assert_eq!(
+            accounts["main"].password("main").unwrap_err(),
+            "Environment variable GM_CREDENTIALS_TEST_UNSET for account 'main' is not set"
+        );
+
+        let path = std::env::temp_dir().join(format!("gm-credentials-{}.pw", uuid::Uuid::new_v4()));
+        std::fs::write(&path, "hunter2\n").unwrap();
+        let account = StoredAccount { username: "agent".into(), password_env: None, password_file: Some(path.clone()) };
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+        let err = account.password("main").unwrap_err();
+        assert!(err.contains("(mode 644); chmod 600 it"), "{}", err);
+        assert!(!err.contains("hunter2"));
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).unwrap();
+        assert_eq!(account.password("main").unwrap(), "hunter2");
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,147 @@
+//! `game-manager doctor`: which builds this installation would play with.
+//! Prints the GameManager's version, the engine, and the installed SAI
+//! bridge as recorded when it was installed next to the bridge built in
+//! the workspace, and says so when the two differ.
+
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use crate::engine;
+use crate::sai_ipc::BuildInfo;
+use crate::write_dir::{self, InstalledBridge, WriteDirConfig};
+
+/// What a doctor pass finds; the report prints it, and the `gm.ready`
+/// banner carries it.
+#[derive(Debug, Clone)]
+pub struct Checks {
+    /// The engine's version and directory, or why there is none.
+    pub engine: Result<(String, PathBuf), String>,
+    /// Engine versions installed under the spring home.
+    pub engines: Vec<String>,
+    pub write_dir: PathBuf,
+    /// A file could be created in the write dir.
+    pub write_dir_writable: bool,
+    pub installed: Option<InstalledBridge>,
+    /// The bridge library the GameManager installs from, and its build.
+    pub bridge_lib: PathBuf,
+    pub built: Option<BuildInfo>,
+}
+
+impl Checks {
+    /// The installed bridge's build and the library's, when they differ.
+    pub fn stale_bridge(&self) -> Option<(&BuildInfo, &BuildInfo)> {
+        let installed = self.installed.as_ref()?.build.as_ref()?;
+        let built = self.built.as_ref()?;
+        (installed != built).then_some((installed, built))
+    }
+}
+
+/// Look the installation over: cheap file checks only.
+pub fn check(wdc: &WriteDirConfig, engine_dir: Result<PathBuf, String>) -> Checks {
+    let engine = engine_dir.map(|dir| (engine::engine_version_of(&dir).unwrap_or_else(|| "?".into()), dir));
+    let mut engines: Vec<String> = std::fs::read_dir(wdc.spring_home.join("engine/linux64"))
+        .into_iter()
+        .flatten()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().is_dir())
+        .filter_map(|e| engine::engine_version_of(&e.path()))
+        .collect();
+    engines.sort();
+    let probe = wdc.write_dir.join(format!(".doctor-{}", std::process::id()));
+    let write_dir_writable = std::fs::write(&probe, b"").is_ok() && std::fs::remove_file(&probe).is_ok();
+    Checks {
+        engine,
+        engines,
+        write_dir: wdc.write_dir.clone(),
+        write_dir_writable,
+        installed: write_dir::installed_bridge(&wdc.write_dir),
+        bridge_lib: wdc.sai_bridge_lib.clone(),
+        built: write_dir::embedded_build(&wdc.sai_bridge_lib),
+    }
+}
+
+/// The report, one line per component.
+pub fn report(wdc: &WriteDirConfig, engine_dir: Result<PathBuf, String>) -> String {
+    let checks = check(wdc, engine_dir);
+    let mut out = String::new();
+    let _ = writeln!(out, "GameManager {}", env!("CARGO_PKG_VERSION"));
+    let _ = match &checks.engine {
+        Ok((version, dir)) => writeln!(out, "Engine: {} ({})", version, dir.display()),
+        Err(e) => writeln!(out, "Engine: not found ({})", e),
+    };
+
+    let ai_dir = wdc.write_dir.join(write_dir::SAI_DATA_DIR);
+    let _ = match &checks.installed {
+        Some(record) => writeln!(
+            out,
+            "Installed bridge: {}, from {}, installed {}",
+            record.build.as_ref().map_or_else(|| "no build marker".to_string(), |b| b.describe()),
+            record.source.display(),
+            record.installed_at
+        ),
+        None if ai_dir.join(write_dir::SAI_LIB).exists() => writeln!(
+            out,
+            "Installed bridge: in {} without a build record; starting the GameManager reinstalls it with one",
+            ai_dir.display()
+        ),
+        None => writeln!(out, "Installed bridge: none in {}", ai_dir.display()),
+    };
+
+    let _ = match &checks.built {
+        Some(build) => writeln!(out, "Built bridge: {} at {}", build.describe(), checks.bridge_lib.display()),
+        None => writeln!(out, "Built bridge: {}", describe_missing(&checks.bridge_lib)),
+    };
+
+    if let Some((installed, built)) = checks.stale_bridge() {
+        let _ = writeln!(
+            out,
+            "Warning: the installed bridge ({}) is not the build ({}); starting the GameManager or a game installs the build",
+            installed.describe(),
+            built.describe()
+        );
+    }
+    out
+}
+
+fn describe_missing(lib: &Path) -> String {
+    if lib.exists() {
+        format!("{} has no build marker", lib.display())
+    } else {
+        format!("none at {} (set SAI_BRIDGE_LIB, or build sai-bridge)", lib.display())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_report_flags_a_stale_install() {
+        let dir = std::env::temp_dir().join(format!("gm-doctor-{}", uuid::Uuid::new_v4()));
+        let mut wdc = WriteDirConfig::from_env(Some(dir.join("write").to_str().unwrap()), None, None);
+        wdc.sai_bridge_lib = dir.join("build").join(write_dir::SAI_LIB);
+        wdc.sai_bridge_data = dir.join("data");
+        let engine_dir = dir.join("engine/linux64/engine_linux64_105.1.1-2511-g747f18b");
+
+        let text = report(&wdc, Err("no engines".into()));
+        assert!(text.starts_with(&format!("GameManager {}\nEngine: not found (no engines)\n", env!("CARGO_PKG_VERSION"))));
+        assert!(text.contains("Installed bridge: none in ") && text.contains("Built bridge: none at "), "{}", text);
+
+        std::fs::create_dir_all(dir.join("build")).unwrap();
+        std::fs::create_dir_all(&wdc.sai_bridge_data).unwrap();
+        std::fs::write(wdc.sai_bridge_data.join("AIInfo.lua"), "return {}").unwrap();
+        std::fs::write(&wdc.sai_bridge_lib, b"sai-bridge-build:0.1.0+aaaaaaa\0").unwrap();
+        write_dir::ensure_sai_installed(&wdc.write_dir, &wdc.sai_bridge()).await.unwrap();
+        let text = report(&wdc, Ok(engine_dir.clone()));
+        assert!(text.contains("Engine: 105.1.1-2511-g747f18b ("), "{}", text);
+        assert!(text.contains("Installed bridge: 0.1.0 (aaaaaaa), from "), "{}", text);
+        assert!(!text.contains("Warning"), "{}", text);
+
+        // Rebuilt since: the install is stale until the next start.
+        std::fs::write(&wdc.sai_bridge_lib, b"sai-bridge-build:0.1.0+bbbbbbb\0").unwrap();
+        let text = report(&wdc, Ok(engine_dir));
+        assert!(text.contains("Built bridge: 0.1.0 (bbbbbbb) at "), "{}", text);
+        assert!(text.contains("Warning: the installed bridge (0.1.0 (aaaaaaa)) is not the build (0.1.0 (bbbbbbb))"), "{}", text);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,325 @@
+//! Economy alerts: trends in the economy snapshots (carried by `update`
+//! events) that the agent would otherwise have to spot itself — stalling
+//! energy, metal piling up at the storage cap, and a sudden income drop.
+//!
+//! A pure analyzer over each channel's recent snapshots; the GameManager
+//! feeds it updates and pushes whatever alerts come back.
+
+use std::collections::{HashMap, VecDeque};
+
+use sai_protocol::Economy;
+
+/// Snapshots kept per channel. Updates arrive about once a second.
+pub const HISTORY_LEN: usize = 10;
+
+/// Metal income below which a drop is noise rather than lost extractors.
+const MIN_INCOME_FOR_DROP: f32 = 1.0;
+
+const FRAMES_PER_MINUTE: i32 = 30 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AlertKind {
+    EnergyStall,
+    MetalExcess,
+    IncomeDrop,
+}
+
+impl AlertKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AlertKind::EnergyStall => "energy_stall",
+            AlertKind::MetalExcess => "metal_excess",
+            AlertKind::IncomeDrop => "income_drop",
+        }
+    }
+}
+
+/// Per-channel thresholds, from `channels/open` `metadata.economy_alerts`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Thresholds {
+    pub enabled: bool,
+    /// Energy counts as empty at or below this fraction of storage.
+    pub energy_stall_fraction: f32,
+    /// Metal counts as excessing at or above this fraction of storage.
+    pub metal_excess_fraction: f32,
+    /// Alert when metal income falls by this fraction of its recent peak.
+    pub income_drop_fraction: f32,
+    /// Game minutes before the same condition alerts again.
+    pub cooldown_minutes: f32,
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            energy_stall_fraction: 0.05,
+            metal_excess_fraction: 0.98,
+            income_drop_fraction: 0.3,
+            cooldown_minutes: 2.0,
+        }
+    }
+}
+
+impl Thresholds {
+    /// Parse `metadata.economy_alerts`: `false` turns alerts off, an object
+    /// overrides individual thresholds, absent means the defaults.
+    pub fn from_metadata(metadata: Option<&serde_json::Value>) -> Result<Self, String> {
+        let mut thresholds = Self::default();
+        let config = match metadata.and_then(|m| m.get("economy_alerts")) {
+            None => return Ok(thresholds),
+            Some(serde_json::Value::Bool(enabled)) => {
+                thresholds.enabled = *enabled;
+                return Ok(thresholds);
+            }
+            Some(v @ serde_json::Value::Object(_)) => v,
+            Some(_) => return Err("economy_alerts must be a boolean or an object".into()),
+        };
+        let fraction = |key: &str, value: &mut f32| -> Result<(), String> {
+            if let Some(v) = config.get(key) {
+                *value = v
+                    .as_f64()
+                    .map(|f| f as f32)
+                    .filter(|f| (0.0..=1.0).contains(f))
+                    .ok_or_else(|| format!("economy_alerts.{} must be between 0 and 1", key))?;
+            }
+            Ok(())
+        };
+        fraction("energy_stall_fraction", &mut thresholds.energy_stall_fraction)?;
+        fraction("metal_excess_fraction", &mut thresholds.metal_excess_fraction)?;
+        fraction("income_drop_fraction", &mut thresholds.income_drop_fraction)?;
+        if let Some(v) = config.get("cooldown_minutes") {
+            thresholds.cooldown_minutes = v
+                .as_f64()
+                .map(|f| f as f32)
+                .filter(|f| *f >= 0.0)
+                .ok_or("economy_alerts.cooldown_minutes must be a non-negative number")?;
+        }
+        Ok(thresholds)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EconomyAlert {
+    pub kind: AlertKind,
+    pub frame: i32,
+    /// One line for the agent, numbers included.
+    pub text: String,
+    /// The numbers behind the alert.
+    pub details: serde_json::Value,
+}
+
+/// One channel's recent snapshots and when each condition last alerted.
+#[derive(Debug, Default)]
+pub struct EconomyWatch {
+    pub thresholds: Thresholds,
+    history: VecDeque<(i32, Economy)>,
+    last_alert: HashMap<AlertKind, i32>,
+}
+
+impl EconomyWatch {
+    pub fn new(thresholds: Thresholds) -> Self {
+        Self { thresholds, ..Default::default() }
+    }
+
+    /// Add a snapshot and return the alerts it trips.
+    pub fn observe(&mut self, frame: i32, economy: Economy) -> Vec<EconomyAlert> {
+        if !self.thresholds.enabled {
+            return Vec::new();
+        }
+        self.history.push_back((frame, economy));
+        while self.history.len() > HISTORY_LEN {
+            self.history.pop_front();
+        }
+
+        let mut alerts = Vec::new();
+        alerts.extend(self.energy_stall(frame));
+        alerts.extend(self.metal_excess(frame, &economy));
+        alerts.extend(self.income_drop(frame, &economy));
+        alerts.retain(|a| self.take_slot(a.kind, frame));
+        alerts
+    }
+
+    /// Rate limit: true if `kind` may alert at `frame`, claiming the slot.
+    fn take_slot(&mut self, kind: AlertKind, frame: i32) -> bool {
+        let cooldown = (self.thresholds.cooldown_minutes * FRAMES_PER_MINUTE as f32) as i32;
+        if self.last_alert.get(&kind).is_some_and(|last| frame - last < cooldown) {
+            return false;
+        }
+        self.last_alert.insert(kind, frame);
+        true
+    }
+
+    /// Usage above income with storage near empty, two snapshots running.
+    /// Energy allies share with us counts as income: in a team game the
+    /// overdrive payout can cover a deficit our storage alone wouldn't.
+    fn energy_stall(&self, frame: i32) -> Option<EconomyAlert> {
+        let stalling = |e: &Economy| {
+            e.energy.usage > e.energy.income + e.energy.received
+                && e.energy.current <= self.thresholds.energy_stall_fraction * e.energy.storage
+        };
+        let mut recent = self.history.iter().rev().take(2);
+        let (Some((_, now)), Some((_, before))) = (recent.next(), recent.next()) else {
+            return None;
+        };
+        if !(stalling(now) && stalling(before)) {
+            return None;
+        }
+        let energy = now.energy;
+        let shared = if energy.received > 0.0 { format!(" (+{:.1}/s from allies)", energy.received) } else { String::new() };
+        Some(EconomyAlert {
+            kind: AlertKind::EnergyStall,
+            frame,
+            text: format!(
+                "Energy stalling: using {:.1}/s with {:.1}/s income{}, {:.0}/{:.0} stored",
+                energy.usage, energy.income, shared, energy.current, energy.storage
+            ),
+            details: serde_json::json!({ "energy": energy }),
+        })
+    }
+
+    fn metal_excess(&self, frame: i32, economy: &Economy) -> Option<EconomyAlert> {
+        let metal = economy.metal;
+        if metal.storage <= 0.0 || metal.current < self.thresholds.metal_excess_fraction * metal.storage {
+            return None;
+        }
+        Some(EconomyAlert {
+            kind: AlertKind::MetalExcess,
+            frame,
+            text: format!(
+                "Metal excessing: storage full at {:.0}/{:.0}, {:.1}/s income going to waste",
+                metal.current, metal.storage, metal.income
+            ),
+            details: serde_json::json!({ "metal": metal }),
+        })
+    }
+
+    /// Metal income well below its peak over the kept snapshots, timed
+    /// from the last snapshot at that peak.
+    fn income_drop(&self, frame: i32, economy: &Economy) -> Option<EconomyAlert> {
+        let (peak_frame, peak) = self
+            .history
+            .iter()
+            .map(|(f, e)| (*f, e.metal.income))
+            .max_by(|a, b| a.1.total_cmp(&b.1))?;
+        let income = economy.metal.income;
+        if peak < MIN_INCOME_FOR_DROP || income > peak * (1.0 - self.thresholds.income_drop_fraction) {
+            return None;
+        }
+        Some(EconomyAlert {
+            kind: AlertKind::IncomeDrop,
+            frame,
+            text: format!(
+                "Metal income dropped from {:.1}/s to {:.1}/s in {}s (lost extractors?)",
+                peak, income, (frame - peak_frame) / 30
+            ),
+            details: serde_json::json!({
+                "metalIncome": income,
+                "peakIncome": peak,
+                "peakFrame": peak_frame,
+            }),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sai_protocol::ResourceState;
+
+    fn economy(metal: (f32, f32, f32), energy: (f32, f32, f32, f32)) -> Economy {
+        Economy {
+            metal: ResourceState { current: metal.0, income: metal.1, usage: 0.0, storage: metal.2, received: 0.0 },
+            energy: ResourceState {
+                current: energy.0,
+                income: energy.1,
+                usage: energy.2,
+                storage: energy.3,
+                received: 0.0,
+            },
+        }
+    }
+
+    fn healthy() -> Economy {
+        economy((100.0, 10.0, 500.0), (400.0, 30.0, 20.0, 1000.0))
+    }
+
+    fn kinds(alerts: &[EconomyAlert]) -> Vec<AlertKind> {
+        alerts.iter().map(|a| a.kind).collect()
+    }
+
+    #[test]
+    fn test_energy_stall_needs_two_snapshots() {
+        let mut watch = EconomyWatch::default();
+        assert!(watch.observe(30, healthy()).is_empty());
+        let stalled = economy((100.0, 10.0, 500.0), (10.0, 20.0, 35.0, 1000.0));
+        assert!(watch.observe(60, stalled).is_empty());
+        let alerts = watch.observe(90, stalled);
+        assert_eq!(kinds(&alerts), [AlertKind::EnergyStall]);
+        assert_eq!(alerts[0].text, "Energy stalling: using 35.0/s with 20.0/s income, 10/1000 stored");
+        assert_eq!(alerts[0].details["energy"]["usage"], 35.0);
+    }
+
+    #[test]
+    fn test_energy_shared_by_allies_is_not_a_stall() {
+        let mut watch = EconomyWatch::default();
+        let mut covered = economy((100.0, 10.0, 500.0), (10.0, 20.0, 35.0, 1000.0));
+        covered.energy.received = 18.0;
+        assert!(watch.observe(30, covered).is_empty());
+        assert!(watch.observe(60, covered).is_empty());
+
+        covered.energy.received = 5.0;
+        assert!(watch.observe(90, covered).is_empty());
+        let alerts = watch.observe(120, covered);
+        assert_eq!(alerts[0].text, "Energy stalling: using 35.0/s with 20.0/s income (+5.0/s from allies), 10/1000 stored");
+    }
+
+    #[test]
+    fn test_metal_excess_rate_limited() {
+        let mut watch = EconomyWatch::default();
+        let full = economy((495.0, 12.0, 500.0), (400.0, 30.0, 20.0, 1000.0));
+        assert_eq!(kinds(&watch.observe(30, full)), [AlertKind::MetalExcess]);
+        // Still full a minute later: within the 2-minute cooldown.
+        assert!(watch.observe(30 + FRAMES_PER_MINUTE, full).is_empty());
+        assert_eq!(kinds(&watch.observe(30 + 2 * FRAMES_PER_MINUTE, full)), [AlertKind::MetalExcess]);
+    }
+
+    #[test]
+    fn test_income_drop() {
+        let mut watch = EconomyWatch::default();
+        for frame in [30, 60, 90] {
+            assert!(watch.observe(frame, healthy()).is_empty());
+        }
+        // 10/s down to 8/s is within the 30% allowance; 6/s is not.
+        let dip = economy((100.0, 8.0, 500.0), (400.0, 30.0, 20.0, 1000.0));
+        assert!(watch.observe(120, dip).is_empty());
+        let lost = economy((100.0, 6.0, 500.0), (400.0, 30.0, 20.0, 1000.0));
+        let alerts = watch.observe(150, lost);
+        assert_eq!(kinds(&alerts), [AlertKind::IncomeDrop]);
+        assert_eq!(alerts[0].text, "Metal income dropped from 10.0/s to 6.0/s in 2s (lost extractors?)");
+        // Timed from the last snapshot at the peak.
+        assert_eq!(alerts[0].details["peakFrame"], 90);
+    }
+
+    #[test]
+    fn test_thresholds_from_metadata() {
+        assert_eq!(Thresholds::from_metadata(None).unwrap(), Thresholds::default());
+        let off = serde_json::json!({"economy_alerts": false});
+        let thresholds = Thresholds::from_metadata(Some(&off)).unwrap();
+        assert!(!thresholds.enabled);
+        let mut watch = EconomyWatch::new(thresholds);
+        let full = economy((500.0, 12.0, 500.0), (0.0, 0.0, 10.0, 0.0));
+        assert!(watch.observe(30, full).is_empty());
+
+        let custom = serde_json::json!({"economy_alerts": {"metal_excess_fraction": 0.8, "cooldown_minutes": 0}});
+        let thresholds = Thresholds::from_metadata(Some(&custom)).unwrap();
+        assert_eq!((thresholds.metal_excess_fraction, thresholds.cooldown_minutes), (0.8, 0.0));
+        assert_eq!(thresholds.income_drop_fraction, Thresholds::default().income_drop_fraction);
+
+        let bad = serde_json::json!({"economy_alerts": {"income_drop_fraction": 2}});
+        assert_eq!(
+            Thresholds::from_metadata(Some(&bad)).unwrap_err(),
+            "economy_alerts.income_drop_fraction must be between 0 and 1"
+        );
+        assert!(Thresholds::from_metadata(Some(&serde_json::json!({"economy_alerts": "on"}))).is_err());
+    }
+}
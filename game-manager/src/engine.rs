@@ -1,16 +1,26 @@
 //! Engine process management — launching and monitoring Recoil/Spring game instances.
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::path::{Path, PathBuf};
+use sai_protocol::UpdateCadence;
+use serde::{Deserialize, Serialize};
 use tokio::process::{Child, Command};
 
+use crate::engine_env::{EngineEnv, EngineEnvConfig};
 use crate::lobby::protocol::ConnectSpringData;
+use crate::socket_dir::SocketDir;
+use crate::write_dir;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum GameStatus {
+    /// Waiting for a free slot under the concurrency limit.
+    Queued,
     Starting,
     Running,
+    /// Stopped by the GameManager.
     Stopped,
+    /// The engine exited cleanly on its own (game over).
+    Ended,
     Crashed(String),
 }
 
@@ -20,9 +30,11 @@ pub struct EngineInstance {
     pub status: GameStatus,
     pub config: GameConfig,
     pub checkpoints: Vec<String>,
+    /// When the engine was launched; None while queued.
+    pub started_at: Option<std::time::SystemTime>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GameConfig {
     pub map: String,
     pub game: String,
@@ -42,9 +54,84 @@ pub struct GameConfig {
     pub player_mode: bool,
     // Agent player name (must match agent_bootstrap.json whitelist)
     pub agent_name: String,
+    // Benchmark: unlocked game speed, bridge sends only game-level events
+    pub benchmark: bool,
+    // Environment changes and resource limits for the engine process
+    #[serde(default)]
+    pub env: EngineEnv,
+    // Event types the bridge counts per unit instead of forwarding
+    #[serde(default)]
+    pub aggregate_events: Vec<String>,
+    // How often the bridge sends updates
+    #[serde(default)]
+    pub update_cadence: UpdateCadence,
+    // Co-op: teams of further AgentBridge AIs allied with the agent, each
+    // with its own socket (local AI-mode games only)
+    #[serde(default)]
+    pub coop_teams: Vec<i32>,
+    // The game profile's mod options (local games only)
+    #[serde(default)]
+    pub mod_options: BTreeMap<String, String>,
 }
 
-#[derive(Debug, Clone)]
+impl GameConfig {
+    /// Self-play: the opponent is another AgentBridge, driven through a
+    /// sub-channel of its own.
+    pub fn self_play(&self) -> bool {
+        self.opponent_ai.as_deref() == Some(self.agent_ai.as_str())
+    }
+
+    /// Teams played by an AgentBridge: the agent's, the opponent's in
+    /// self-play, then the co-op ones.
+    pub fn bridge_teams(&self) -> Vec<i32> {
+        std::iter::once(self.agent_team)
+            .chain(self.self_play().then_some(self.opponent_team))
+            .chain(self.coop_teams.iter().copied())
+            .collect()
+    }
+
+    /// The ally team `team` plays in: the opponent's is 1, everyone else
+    /// is on the agent's side.
+    pub fn ally_team_of(&self, team: i32) -> i32 {
+        i32::from(team == self.opponent_team)
+    }
+
+    /// The socket of the AgentBridge playing `team`: `socket_path` for the
+    /// agent's team, one next to it for each other bridge's.
+    pub fn socket_path_for(&self, team: i32) -> String {
+        if team == self.agent_team {
+            return self.socket_path.clone();
+        }
+        let base = self.socket_path.strip_suffix(".sock").unwrap_or(&self.socket_path);
+        format!("{}_team{}.sock", base, team)
+    }
+
+    /// connection.json `socket_paths`: every AgentBridge's socket, keyed
+    /// `team:<n>`, so bridges sharing one engine each find their own.
+    pub fn socket_paths(&self) -> BTreeMap<String, String> {
+        self.bridge_teams()
+            .into_iter()
+            .map(|team| (format!("team:{}", team), self.socket_path_for(team)))
+            .collect()
+    }
+
+    /// The SAI connections to listen for, as (channel, socket): the game
+    /// channel's own, then a sub-channel per other bridge's team.
+    pub fn sai_sockets(&self, channel_id: &str) -> Vec<(String, String)> {
+        self.bridge_teams()
+            .into_iter()
+            .map(|team| {
+                if team == self.agent_team {
+                    (channel_id.to_string(), self.socket_path.clone())
+                } else {
+                    (crate::sai_ipc::coop_channel_id(channel_id, team), self.socket_path_for(team))
+                }
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MultiplayerConfig {
     pub host_ip: String,
     pub host_port: i32,
@@ -52,6 +139,36 @@ pub struct MultiplayerConfig {
     pub script_password: String,
 }
 
+/// Most co-op AgentBridges a local game can add next to the agent's own.
+pub const MAX_COOP_AGENTS: usize = 3;
+
+/// Event types bridges count per unit unless the config file says
+/// otherwise: several a second per busy unit, and rarely worth a line each.
+pub const DEFAULT_AGGREGATE_EVENTS: &[&str] = &["weapon_fired", "command_finished"];
+
+/// Game speed a benchmark startscript pins the engine to. Far above what
+/// the simulation can reach, so the engine runs as fast as the CPU allows.
+pub const BENCHMARK_SPEED: f32 = 100.0;
+
+/// Engines allowed to run at once unless MAX_CONCURRENT_GAMES says otherwise.
+pub const DEFAULT_MAX_CONCURRENT_GAMES: usize = 2;
+
+/// Session state file in the write dir, so queued games survive a restart.
+const SESSION_FILE: &str = "gm_session.json";
+
+/// A game waiting for a slot, as saved in the session file.
+#[derive(Debug, Serialize, Deserialize)]
+struct QueuedGame {
+    channel_id: String,
+    config: GameConfig,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SessionState {
+    next_id: u32,
+    queue: Vec<QueuedGame>,
+}
+
 /// Resolve the engine binary path from an engine directory.
 pub fn resolve_engine_binary(engine_dir: &Path, headless: bool) -> PathBuf {
     if headless {
@@ -61,7 +178,60 @@ pub fn resolve_engine_binary(engine_dir: &Path, headless: bool) -> PathBuf {
     }
 }
 
+/// Ensure engine binaries in a directory are executable.
+pub fn chmod_executable(engine_dir: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    for name in &["spring", "spring-headless"] {
+        let bin = engine_dir.join(name);
+        if bin.exists() {
+            let meta = std::fs::metadata(&bin)?;
+            let mut perms = meta.permissions();
+            perms.set_mode(perms.mode() | 0o111);
+            std::fs::set_permissions(&bin, perms)?;
+        }
+    }
+    Ok(())
+}
+
+/// Run the engine briefly to populate the archive cache in the write-dir.
+pub async fn warm_archive_cache(write_dir: &Path, engine_dir: &Path) {
+    let headless = resolve_engine_binary(engine_dir, true);
+    if !headless.exists() {
+        tracing::warn!("Cannot warm cache: {} not found", headless.display());
+        return;
+    }
+
+    tracing::info!("Warming archive cache with {}...", engine_dir.display());
+    let dummy_script = write_dir.join("temp/cache_warm.txt");
+    let _ = tokio::fs::write(&dummy_script, "[GAME]\n{\n}\n").await;
+
+    let result = tokio::process::Command::new(&headless)
+        .arg("--write-dir")
+        .arg(write_dir)
+        .arg(&dummy_script)
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .await;
+
+    let _ = tokio::fs::remove_file(&dummy_script).await;
+
+    match result {
+        Ok(status) => tracing::info!("Cache warm-up done (exit: {})", status),
+        Err(e) => tracing::warn!("Cache warm-up failed: {}", e),
+    }
+}
+
+/// Suggestion appended when no usable engine is found.
+const INSTALL_HINT: &str = "install one with `game-manager --install-engine <version>`";
+
 /// Find the engine directory, either by explicit version or by picking the latest.
+/// The engine version an engine dir holds, going by its name.
+pub fn engine_version_of(dir: &Path) -> Option<String> {
+    let name = dir.file_name()?.to_str()?;
+    Some(name.strip_prefix("engine_linux64_").unwrap_or(name).to_string())
+}
+
 pub fn find_engine_dir(spring_home: &Path, version: Option<&str>) -> anyhow::Result<PathBuf> {
     let engines_base = spring_home.join("engine/linux64");
 
@@ -77,16 +247,17 @@ pub fn find_engine_dir(spring_home: &Path, version: Option<&str>) -> anyhow::Res
             return Ok(prefixed);
         }
         anyhow::bail!(
-            "Engine version '{}' not found in {}",
+            "Engine version '{}' not found in {}; {}",
             ver,
-            engines_base.display()
+            engines_base.display(),
+            INSTALL_HINT.replace("<version>", ver)
         );
     }
 
     // Find latest by modification time — most recently written engine dir wins.
     // This naturally picks the engine the lobby server last downloaded.
     let mut entries: Vec<(PathBuf, std::time::SystemTime)> = std::fs::read_dir(&engines_base)
-        .map_err(|e| anyhow::anyhow!("Cannot read engine dir {}: {}", engines_base.display(), e))?
+        .map_err(|e| anyhow::anyhow!("Cannot read engine dir {}: {}; {}", engines_base.display(), e, INSTALL_HINT))?
         .filter_map(|e| e.ok())
         .filter_map(|e| {
             let path = e.path();
@@ -100,7 +271,7 @@ pub fn find_engine_dir(spring_home: &Path, version: Option<&str>) -> anyhow::Res
 
     let latest = &entries
         .last()
-        .ok_or_else(|| anyhow::anyhow!("No engine versions found in {}", engines_base.display()))?
+        .ok_or_else(|| anyhow::anyhow!("No engine versions found in {}; {}", engines_base.display(), INSTALL_HINT))?
         .0;
 
     // Verify spring-headless exists
@@ -123,30 +294,38 @@ impl EngineInstance {
             status: GameStatus::Starting,
             config,
             checkpoints: Vec::new(),
+            started_at: None,
         }
     }
 
-    /// Write connection.json into the SAI bridge's data directory.
-    /// The SAI bridge reads this as a fallback when AI options aren't available
-    /// (e.g. player mode where /aicontrol creates the AI dynamically).
-    async fn write_connection_config(&self) -> Result<(), String> {
-        let config_path = self
-            .config
-            .write_dir
-            .join("AI/Skirmish/AgentBridge/0.1/connection.json");
-        let config = serde_json::json!({
-            "socket_path": self.config.socket_path,
+    /// Hand the SAI bridge its connection before launch: connection.json
+    /// always (harmless in AI mode, required in player mode), and in player
+    /// mode the bootstrap whitelist entry that makes the widget call
+    /// /aicontrol for our player.
+    fn prepare_handoff(&self) -> Result<(), String> {
+        let data_dir = self.config.write_dir.join(write_dir::SAI_DATA_DIR);
+        let mut extra = serde_json::json!({
+            "log_file": data_dir.join("sai-bridge.log"),
+            "log_level": std::env::var("SAI_LOG_LEVEL").unwrap_or_else(|_| "info".into()),
+            "benchmark": self.config.benchmark,
+            "aggregate_events": self.config.aggregate_events,
+            "update_cadence": self.config.update_cadence,
         });
-        tokio::fs::write(&config_path, serde_json::to_string_pretty(&config).unwrap())
-            .await
+        if self.config.bridge_teams().len() > 1 {
+            extra["socket_paths"] = serde_json::json!(self.config.socket_paths());
+        }
+        write_dir::write_connection_json(&data_dir, &self.config.socket_path, &extra)
             .map_err(|e| format!("Failed to write connection.json: {}", e))?;
+        if self.config.player_mode {
+            write_dir::ensure_player_whitelisted(&self.config.write_dir, &self.config.agent_name)
+                .map_err(|e| format!("Failed to whitelist player '{}': {}", self.config.agent_name, e))?;
+        }
         Ok(())
     }
 
     /// Launch the engine process.
     pub async fn start(&mut self) -> Result<(), String> {
-        // Write connection config before engine launch (harmless in AI mode, required in player mode)
-        self.write_connection_config().await?;
+        self.prepare_handoff()?;
 
         let script = if self.config.multiplayer.is_some() {
             self.generate_multiplayer_script()
@@ -160,6 +339,8 @@ impl EngineInstance {
             .config
             .write_dir
             .join(format!("temp/gm_script_{}.txt", self.channel_id.replace(':', "_")));
+        // Catch malformed scripts here rather than as an opaque engine exit.
+        startscript_validate(&script).map_err(|e| format!("Invalid startscript: {}", e))?;
         tokio::fs::write(&script_path, &script)
             .await
             .map_err(|e| format!("Failed to write script.txt: {}", e))?;
@@ -172,7 +353,11 @@ impl EngineInstance {
             script_path.display()
         );
 
-        let child = Command::new(&engine_bin)
+        tracing::info!("Engine environment for {}: {}", self.channel_id, self.config.env.describe());
+
+        let mut command = Command::new(&engine_bin);
+        self.config.env.apply(&mut command);
+        let child = command
             .arg("--write-dir")
             .arg(&self.config.write_dir)
             .arg(&script_path)
@@ -184,9 +369,23 @@ impl EngineInstance {
 
         self.process = Some(child);
         self.status = GameStatus::Starting;
+        self.started_at = Some(std::time::SystemTime::now());
         Ok(())
     }
 
+    /// What to log when the engine dies before its bridge connected: the
+    /// environment is the usual suspect on a new host.
+    fn startup_crash_report(&self, code: Option<i32>) -> String {
+        format!(
+            "Engine for {} died during startup (exit code {:?}). Engine: {}. Environment: {}. Infolog: {}",
+            self.channel_id,
+            code,
+            resolve_engine_binary(&self.config.engine_dir, self.config.headless).display(),
+            self.config.env.describe(),
+            self.config.write_dir.join("infolog.txt").display()
+        )
+    }
+
     /// Stop the engine process.
     pub async fn stop(&mut self) {
         if let Some(ref mut child) = self.process {
@@ -196,14 +395,27 @@ impl EngineInstance {
         self.status = GameStatus::Stopped;
     }
 
+    /// Ask the engine to quit (SIGTERM) rather than killing it, so it can
+    /// release its AIs and finish the demo.
+    pub fn request_quit(&self) -> Result<(), String> {
+        let pid = self.process.as_ref().and_then(|child| child.id()).ok_or("Engine is not running")?;
+        if unsafe { libc::kill(pid as libc::pid_t, libc::SIGTERM) } != 0 {
+            return Err(format!("Failed to signal engine: {}", std::io::Error::last_os_error()));
+        }
+        Ok(())
+    }
+
     /// Check if the engine process is still running.
     pub async fn check_alive(&mut self) -> bool {
         if let Some(ref mut child) = self.process {
             match child.try_wait() {
                 Ok(Some(status)) => {
                     if status.success() {
-                        self.status = GameStatus::Stopped;
+                        self.status = GameStatus::Ended;
                     } else {
+                        if self.status == GameStatus::Starting {
+                            tracing::error!("{}", self.startup_crash_report(status.code()));
+                        }
                         self.status =
                             GameStatus::Crashed(format!("Exit code: {:?}", status.code()));
                     }
@@ -222,13 +434,54 @@ impl EngineInstance {
         }
     }
 
-    /// Generate a local scrimmage script: spectator GameManager + AgentBridge vs opponent AI.
+    /// Generate a local scrimmage script: spectator GameManager + AgentBridge
+    /// vs opponent AI, or vs a second AgentBridge in self-play.
     fn generate_local_script(&self) -> String {
         let opponent = self
             .config
             .opponent_ai
             .as_deref()
-            .unwrap_or("CircuitAINovice");
+            .unwrap_or(crate::opponents::DEFAULT_OPPONENT);
+        let speed_limits = if self.config.benchmark {
+            format!("\n    MinSpeed={0};\n    MaxSpeed={0};", BENCHMARK_SPEED)
+        } else {
+            String::new()
+        };
+        // Co-op AgentBridges follow the opponent, each on its own team in
+        // our ally team and with its own socket.
+        let opponent_options = if self.config.self_play() {
+            format!(
+                "\n        Version=0.1;\n        [Options]\n        {{\n            socket_path={};\n        }}",
+                self.config.socket_path_for(self.config.opponent_team)
+            )
+        } else {
+            String::new()
+        };
+        let mut coop_ais = String::new();
+        let mut coop_teams = String::new();
+        for (i, team) in self.config.coop_teams.iter().enumerate() {
+            coop_ais += &format!(
+                r#"
+
+    [AI{ai}]
+    {{
+        Name=AgentBridge{team};
+        ShortName={agent_ai};
+        Version=0.1;
+        Team={team};
+        Host=0;
+        [Options]
+        {{
+            socket_path={socket_path};
+        }}
+    }}"#,
+                ai = i + 2,
+                agent_ai = self.config.agent_ai,
+                team = team,
+                socket_path = self.config.socket_path_for(*team),
+            );
+            coop_teams += &format!("\n    [TEAM{}] {{ TeamLeader=0; AllyTeam=0; }}", team);
+        }
 
         format!(
             r#"[GAME]
@@ -238,10 +491,10 @@ impl EngineInstance {
     IsHost=1;
     MyPlayerNum=0;
     MyPlayerName=GameManager;
-    StartPosType=2;
+    StartPosType=2;{speed_limits}
     NumPlayers=1;
-    NumUsers=3;
-    NumTeams=2;
+    NumUsers={num_users};
+    NumTeams={num_teams};
     NumAllyTeams=2;
 
     [PLAYER0]
@@ -266,24 +519,36 @@ impl EngineInstance {
 
     [AI1]
     {{
-        Name={opponent};
+        Name={opponent_name};
         ShortName={opponent};
         Team={opponent_team};
-        Host=0;
-    }}
+        Host=0;{opponent_options}
+    }}{coop_ais}
 
     [TEAM0] {{ TeamLeader=0; AllyTeam=0; }}
-    [TEAM1] {{ TeamLeader=0; AllyTeam=1; }}
+    [TEAM1] {{ TeamLeader=0; AllyTeam=1; }}{coop_teams}
     [ALLYTEAM0] {{ NumAllies=0; }}
-    [ALLYTEAM1] {{ NumAllies=0; }}
+    [ALLYTEAM1] {{ NumAllies=0; }}{mod_options}
 }}"#,
             map = self.config.map,
             game = self.config.game,
             agent_ai = self.config.agent_ai,
             agent_team = self.config.agent_team,
             opponent = opponent,
+            opponent_name = if self.config.self_play() {
+                format!("{}{}", opponent, self.config.opponent_team)
+            } else {
+                opponent.to_string()
+            },
             opponent_team = self.config.opponent_team,
+            opponent_options = opponent_options,
             socket_path = self.config.socket_path,
+            speed_limits = speed_limits,
+            num_users = 3 + self.config.coop_teams.len(),
+            num_teams = 2 + self.config.coop_teams.len(),
+            coop_ais = coop_ais,
+            coop_teams = coop_teams,
+            mod_options = self.mod_options_section(),
         )
     }
 
@@ -295,7 +560,7 @@ impl EngineInstance {
             .config
             .opponent_ai
             .as_deref()
-            .unwrap_or("CircuitAINovice");
+            .unwrap_or(crate::opponents::DEFAULT_OPPONENT);
 
         format!(
             r#"[GAME]
@@ -329,7 +594,7 @@ impl EngineInstance {
     [TEAM0] {{ TeamLeader=0; AllyTeam=0; StartPosX=1000; StartPosZ=1000; }}
     [TEAM1] {{ TeamLeader=0; AllyTeam=1; StartPosX=7000; StartPosZ=7000; }}
     [ALLYTEAM0] {{ NumAllies=0; }}
-    [ALLYTEAM1] {{ NumAllies=0; }}
+    [ALLYTEAM1] {{ NumAllies=0; }}{mod_options}
 }}"#,
             map = self.config.map,
             game = self.config.game,
@@ -337,9 +602,21 @@ impl EngineInstance {
             agent_team = self.config.agent_team,
             opponent = opponent,
             opponent_team = self.config.opponent_team,
+            mod_options = self.mod_options_section(),
         )
     }
 
+    /// The `[MODOPTIONS]` section for the game profile's mod options, if
+    /// it has any.
+    fn mod_options_section(&self) -> String {
+        if self.config.mod_options.is_empty() {
+            return String::new();
+        }
+        let options: String =
+            self.config.mod_options.iter().map(|(key, value)| format!("\n        {}={};", key, value)).collect();
+        format!("\n\n    [MODOPTIONS]\n    {{{}\n    }}", options)
+    }
+
     /// Generate a multiplayer client script — connects to a remote game server.
     fn generate_multiplayer_script(&self) -> String {
         let mp = self.config.multiplayer.as_ref().unwrap();
@@ -360,27 +637,315 @@ impl EngineInstance {
     }
 }
 
+/// A `[NAME] { key=value; ... }` block of a startscript.
+/// Section names and keys are lowercased — the engine ignores case.
+#[derive(Debug, Default)]
+pub struct ScriptSection {
+    pub name: String,
+    pub keys: HashMap<String, String>,
+    pub children: Vec<ScriptSection>,
+}
+
+impl ScriptSection {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.keys.get(key).map(|s| s.as_str())
+    }
+
+    fn get_int(&self, key: &str) -> Result<Option<i64>, String> {
+        match self.get(key) {
+            None => Ok(None),
+            Some(v) => v
+                .trim()
+                .parse()
+                .map(Some)
+                .map_err(|_| format!("[{}] {}={} is not a number", self.name, key, v)),
+        }
+    }
+
+    fn require(&self, key: &str) -> Result<&str, String> {
+        self.get(key)
+            .ok_or_else(|| format!("[{}] is missing {}", self.name, key))
+    }
+
+    /// Children named `{prefix}N`, indexed by N.
+    fn numbered(&self, prefix: &str) -> Result<Vec<&ScriptSection>, String> {
+        let mut found: Vec<(usize, &ScriptSection)> = Vec::new();
+        for child in &self.children {
+            let Some(index) = child.name.strip_prefix(prefix) else { continue };
+            let Ok(index) = index.parse::<usize>() else { continue };
+            found.push((index, child));
+        }
+        found.sort_by_key(|(i, _)| *i);
+        for (expected, (index, _)) in found.iter().enumerate() {
+            if *index != expected {
+                return Err(format!(
+                    "[{}{}] sections are not numbered 0..{} (found [{}{}])",
+                    prefix, expected, found.len(), prefix, index
+                ));
+            }
+        }
+        Ok(found.into_iter().map(|(_, s)| s).collect())
+    }
+}
+
+/// Parse startscript text into its section tree. Returns a synthetic root
+/// whose children are the top-level sections.
+pub fn parse_startscript(text: &str) -> Result<ScriptSection, String> {
+    let mut stack = vec![ScriptSection::default()];
+    // Header seen, waiting for its opening brace.
+    let mut pending: Option<String> = None;
+    let mut rest = text;
+
+    loop {
+        rest = rest.trim_start();
+        let Some(c) = rest.chars().next() else { break };
+        match c {
+            '[' => {
+                if let Some(name) = &pending {
+                    return Err(format!("[{}] has no {{ ... }} body", name));
+                }
+                let end = rest
+                    .find(']')
+                    .ok_or_else(|| "unterminated section header".to_string())?;
+                let name = &rest[1..end];
+                if name.is_empty() || name.contains(['[', '{', '}', ';', '\n']) {
+                    return Err(format!("bad section header [{}]", name));
+                }
+                pending = Some(name.to_ascii_lowercase());
+                rest = &rest[end + 1..];
+            }
+            '{' => {
+                let name = pending
+                    .take()
+                    .ok_or_else(|| "'{' without a section header".to_string())?;
+                stack.push(ScriptSection {
+                    name,
+                    ..Default::default()
+                });
+                rest = &rest[1..];
+            }
+            '}' => {
+                if let Some(name) = &pending {
+                    return Err(format!("[{}] has no {{ ... }} body", name));
+                }
+                if stack.len() == 1 {
+                    return Err("unbalanced '}'".into());
+                }
+                let section = stack.pop().unwrap();
+                let parent = stack.last_mut().unwrap();
+                if parent.children.iter().any(|c| c.name == section.name) {
+                    return Err(format!("duplicate section [{}]", section.name));
+                }
+                parent.children.push(section);
+                rest = &rest[1..];
+            }
+            _ => {
+                let end = rest
+                    .find(';')
+                    .ok_or_else(|| format!("unterminated line: {}", rest.lines().next().unwrap_or("")))?;
+                let line = &rest[..end];
+                let (key, value) = line
+                    .split_once('=')
+                    .ok_or_else(|| format!("expected key=value, got '{}'", line.trim()))?;
+                let key = key.trim().to_ascii_lowercase();
+                // The engine's parser has no escaping: a brace in a value
+                // (e.g. from a map or player name) corrupts the nesting.
+                if let Some(bad) = value.chars().find(|c| matches!(c, '{' | '}' | '\n')) {
+                    return Err(format!("value of {} contains {:?}: '{}'", key, bad, value.trim()));
+                }
+                if stack.len() == 1 || pending.is_some() {
+                    return Err(format!("{} is outside any section", key));
+                }
+                let section = stack.last_mut().unwrap();
+                if section.keys.insert(key.clone(), value.trim().to_string()).is_some() {
+                    return Err(format!("[{}] sets {} twice", section.name, key));
+                }
+                rest = &rest[end + 1..];
+            }
+        }
+    }
+
+    if let Some(name) = pending {
+        return Err(format!("[{}] has no {{ ... }} body", name));
+    }
+    if stack.len() > 1 {
+        return Err(format!("[{}] is never closed", stack.last().unwrap().name));
+    }
+    Ok(stack.pop().unwrap())
+}
+
+/// Check a generated startscript for structural mistakes the engine would
+/// only report by exiting: brace nesting, missing keys, and player/AI/team
+/// counts that disagree with the sections actually present.
+pub fn startscript_validate(text: &str) -> Result<(), String> {
+    let root = parse_startscript(text)?;
+    if root.children.len() != 1 || root.children[0].name != "game" {
+        return Err("script must contain exactly one top-level [GAME] section".into());
+    }
+    let game = &root.children[0];
+    if game.get_int("ishost")? == Some(0) {
+        // Client scripts just point at the host; it sends the real setup.
+        for key in ["hostip", "hostport", "myplayername"] {
+            game.require(key)?;
+        }
+        return Ok(());
+    }
+
+    for key in ["mapname", "gametype", "myplayername"] {
+        if game.require(key)?.is_empty() {
+            return Err(format!("[game] {} is empty", key));
+        }
+    }
+
+    let players = game.numbered("player")?;
+    let ais = game.numbered("ai")?;
+    let teams = game.numbered("team")?;
+    let ally_teams = game.numbered("allyteam")?;
+
+    let counts = [
+        ("numplayers", players.len(), "[PLAYERn]"),
+        ("numusers", players.len() + ais.len(), "[PLAYERn] + [AIn]"),
+        ("numteams", teams.len(), "[TEAMn]"),
+        ("numallyteams", ally_teams.len(), "[ALLYTEAMn]"),
+    ];
+    for (key, actual, what) in counts {
+        if let Some(declared) = game.get_int(key)? {
+            if declared != actual as i64 {
+                return Err(format!(
+                    "[game] {}={} but the script has {} {} sections",
+                    key, declared, actual, what
+                ));
+            }
+        }
+    }
+    if teams.is_empty() {
+        return Err("no [TEAMn] sections".into());
+    }
+
+    let in_range = |v: i64, len: usize| v >= 0 && (v as usize) < len;
+    for p in &players {
+        p.require("name")?;
+        let team = p.get_int("team")?.unwrap_or(-1);
+        let spectator = p.get_int("spectator")?.unwrap_or(0) != 0;
+        if !spectator && !in_range(team, teams.len()) {
+            return Err(format!("[{}] Team={} does not exist", p.name, team));
+        }
+    }
+    for ai in &ais {
+        ai.require("shortname")?;
+        ai.require("team")?;
+        let team = ai.get_int("team")?.unwrap_or(-1);
+        if !in_range(team, teams.len()) {
+            return Err(format!("[{}] Team={} does not exist", ai.name, team));
+        }
+        let host = ai.get_int("host")?.unwrap_or(-1);
+        if !in_range(host, players.len()) {
+            return Err(format!("[{}] Host={} is not a player", ai.name, host));
+        }
+    }
+    for t in &teams {
+        let leader = t.get_int("teamleader")?.unwrap_or(-1);
+        if !in_range(leader, players.len()) {
+            return Err(format!("[{}] TeamLeader={} is not a player", t.name, leader));
+        }
+        let ally = t.get_int("allyteam")?.unwrap_or(-1);
+        if !in_range(ally, ally_teams.len()) {
+            return Err(format!("[{}] AllyTeam={} does not exist", t.name, ally));
+        }
+    }
+    Ok(())
+}
+
 /// Manages all active engine instances.
 pub struct EngineManager {
     pub instances: HashMap<String, EngineInstance>,
     next_id: u32,
     pub engine_dir: PathBuf,
     pub write_dir: PathBuf,
-    pub socket_dir: String,
+    /// This run's socket dir, where each game's bridge socket goes.
+    pub sockets: SocketDir,
+    /// Engines allowed to run at once; further local games wait in `queue`.
+    pub max_concurrent_games: usize,
+    /// Channels waiting to launch, oldest first.
+    queue: VecDeque<String>,
+    /// Where the SAI bridge is installed from before a launch.
+    pub sai_bridge: write_dir::SaiBridgeSource,
+    /// Environment for new engines (config `engine_env`).
+    pub engine_env: EngineEnvConfig,
+    /// Event types new games' bridges aggregate (config `aggregate_events`).
+    pub aggregate_events: Vec<String>,
+    /// New games' bridges' update cadence (config `update_cadence`).
+    pub update_cadence: UpdateCadence,
+    /// SHA-256 of the installed bridge once the preflight passed this run.
+    sai_installed: Option<String>,
 }
 
 impl EngineManager {
-    pub fn new(engine_dir: PathBuf, write_dir: PathBuf, socket_dir: String) -> Self {
+    pub fn new(
+        engine_dir: PathBuf,
+        write_dir: PathBuf,
+        sockets: SocketDir,
+        sai_bridge: write_dir::SaiBridgeSource,
+    ) -> Self {
         Self {
             instances: HashMap::new(),
             next_id: 1,
             engine_dir,
             write_dir,
-            socket_dir,
+            sockets,
+            max_concurrent_games: DEFAULT_MAX_CONCURRENT_GAMES,
+            queue: VecDeque::new(),
+            sai_bridge,
+            engine_env: EngineEnvConfig::default(),
+            aggregate_events: DEFAULT_AGGREGATE_EVENTS.iter().map(|e| e.to_string()).collect(),
+            update_cadence: UpdateCadence::default(),
+            sai_installed: None,
+        }
+    }
+
+    /// Make sure the SAI bridge is installed before an engine launches.
+    /// Checked once per run; after a failure the next launch checks again.
+    async fn preflight(&mut self) -> Result<(), String> {
+        if self.sai_installed.is_none() {
+            let digest = write_dir::ensure_sai_installed(&self.write_dir, &self.sai_bridge).await?;
+            tracing::info!("SAI bridge ready (sha256 {})", digest);
+            self.sai_installed = Some(digest);
         }
+        Ok(())
+    }
+
+    /// Engines currently running (or launched and not yet reaped).
+    fn running_count(&self) -> usize {
+        self.instances.values().filter(|i| i.process.is_some()).count()
+    }
+
+    /// Whether any game is still running or waiting to launch.
+    pub fn has_live_games(&self) -> bool {
+        self.instances.values().any(|i| i.process.is_some() || i.status == GameStatus::Queued)
     }
 
-    /// Start a local scrimmage game: AgentBridge vs opponent AI.
+    /// 1-based position of a channel in the launch queue.
+    pub fn queue_position(&self, channel_id: &str) -> Option<usize> {
+        self.queue.iter().position(|id| id == channel_id).map(|i| i + 1)
+    }
+
+    /// Whether a new game may take `channel_id`: a valid chosen id whose
+    /// last game, if any, is neither running nor queued.
+    pub fn check_channel_id(&self, channel_id: &str) -> Result<(), String> {
+        crate::channel_ids::validate(channel_id)?;
+        match self.instances.get(channel_id) {
+            Some(instance) if instance.process.is_some() || instance.status == GameStatus::Queued => {
+                Err(format!("{} is still running; close it or choose another channel_id", channel_id))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Start a local scrimmage game: AgentBridge vs opponent AI, with
+    /// `coop_agents` more AgentBridges on our side. The channel is
+    /// `channel_id` if given (replacing an ended game of that id), else
+    /// the next `game:local-N`.
+    #[allow(clippy::too_many_arguments)]
     pub async fn start_local_game(
         &mut self,
         map: &str,
@@ -389,11 +954,18 @@ impl EngineManager {
         headless: bool,
         player_mode: bool,
         agent_name: &str,
+        benchmark: bool,
+        coop_agents: usize,
+        channel_id: Option<&str>,
+        mod_options: &BTreeMap<String, String>,
     ) -> Result<String, String> {
+        if let Some(channel_id) = channel_id {
+            self.check_channel_id(channel_id)?;
+        }
         let id = self.next_id;
         self.next_id += 1;
-        let channel_id = format!("game:local-{}", id);
-        let socket_path = format!("{}/sai_{}.sock", self.socket_dir, id);
+        let channel_id = channel_id.map_or_else(|| format!("game:local-{}", id), String::from);
+        let socket_path = self.sockets.socket_for(&channel_id)?;
 
         let config = GameConfig {
             map: map.to_string(),
@@ -405,20 +977,126 @@ impl EngineManager {
             agent_ai: "AgentBridge".to_string(),
             agent_team: 0,
             opponent_ai: Some(
-                opponent.unwrap_or("CircuitAINovice").to_string(),
+                opponent.unwrap_or(crate::opponents::DEFAULT_OPPONENT).to_string(),
             ),
             opponent_team: 1,
             multiplayer: None,
             player_mode,
             agent_name: agent_name.to_string(),
+            benchmark,
+            env: self.engine_env.resolve(headless),
+            aggregate_events: self.aggregate_events.clone(),
+            update_cadence: self.update_cadence.clone(),
+            // Teams 0 and 1 are the agent's and the opponent's.
+            coop_teams: (2..2 + coop_agents as i32).collect(),
+            mod_options: mod_options.clone(),
         };
 
         let mut instance = EngineInstance::new(channel_id.clone(), config);
+        if self.running_count() >= self.max_concurrent_games {
+            tracing::info!("{} queued: {} games already running", channel_id, self.running_count());
+            instance.status = GameStatus::Queued;
+            self.instances.insert(channel_id.clone(), instance);
+            self.queue.push_back(channel_id.clone());
+            self.save_session();
+            return Ok(channel_id);
+        }
+        self.preflight().await?;
         instance.start().await?;
         self.instances.insert(channel_id.clone(), instance);
         Ok(channel_id)
     }
 
+    /// Launch queued games while there are free slots. Returns each launched
+    /// channel with its start result; a failed start leaves it Crashed.
+    pub async fn launch_queued(&mut self) -> Vec<(String, Result<(), String>)> {
+        let mut launched = Vec::new();
+        while self.running_count() < self.max_concurrent_games {
+            let Some(channel_id) = self.queue.pop_front() else { break };
+            let preflight = self.preflight().await;
+            let Some(instance) = self.instances.get_mut(&channel_id) else { continue };
+            let result = match preflight {
+                Ok(()) => instance.start().await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = &result {
+                instance.status = GameStatus::Crashed(e.clone());
+            }
+            launched.push((channel_id, result));
+        }
+        if !launched.is_empty() {
+            self.save_session();
+        }
+        launched
+    }
+
+    /// Drop a game that hasn't launched yet.
+    pub fn cancel_queued(&mut self, channel_id: &str) -> Result<(), String> {
+        let index = self
+            .queue
+            .iter()
+            .position(|id| id == channel_id)
+            .ok_or_else(|| format!("{} is not queued", channel_id))?;
+        self.queue.remove(index);
+        self.instances.remove(channel_id);
+        self.save_session();
+        Ok(())
+    }
+
+    /// Write the launch queue to the session file.
+    fn save_session(&self) {
+        let state = SessionState {
+            next_id: self.next_id,
+            queue: self
+                .queue
+                .iter()
+                .filter_map(|id| {
+                    Some(QueuedGame {
+                        channel_id: id.clone(),
+                        config: self.instances.get(id)?.config.clone(),
+                    })
+                })
+                .collect(),
+        };
+        let path = self.write_dir.join(SESSION_FILE);
+        if let Err(e) = std::fs::write(&path, serde_json::to_string_pretty(&state).unwrap()) {
+            tracing::warn!("Failed to save {}: {}", path.display(), e);
+        }
+    }
+
+    /// Re-queue the games a previous run left waiting. Returns their channel ids.
+    pub fn restore_session(&mut self) -> Vec<String> {
+        let path = self.write_dir.join(SESSION_FILE);
+        let Ok(text) = std::fs::read_to_string(&path) else {
+            return Vec::new();
+        };
+        let state: SessionState = match serde_json::from_str(&text) {
+            Ok(state) => state,
+            Err(e) => {
+                tracing::warn!("Ignoring unreadable {}: {}", path.display(), e);
+                return Vec::new();
+            }
+        };
+        self.next_id = self.next_id.max(state.next_id);
+        let mut restored = Vec::new();
+        for mut game in state.queue {
+            // The previous run's socket dir went with it.
+            match self.sockets.socket_for(&game.channel_id) {
+                Ok(socket_path) => game.config.socket_path = socket_path,
+                Err(e) => {
+                    tracing::warn!("Not restoring queued game {}: {}", game.channel_id, e);
+                    continue;
+                }
+            }
+            let mut instance = EngineInstance::new(game.channel_id.clone(), game.config);
+            instance.status = GameStatus::Queued;
+            self.instances.insert(game.channel_id.clone(), instance);
+            self.queue.push_back(game.channel_id.clone());
+            restored.push(game.channel_id);
+        }
+        restored
+    }
+
     /// Start a multiplayer game from a ConnectSpring lobby event.
     pub async fn start_multiplayer_game(
         &mut self,
@@ -429,7 +1107,7 @@ impl EngineManager {
         let id = self.next_id;
         self.next_id += 1;
         let channel_id = format!("game:mp-{}", id);
-        let socket_path = format!("{}/sai_mp_{}.sock", self.socket_dir, id);
+        let socket_path = self.sockets.socket_for(&channel_id)?;
 
         // Use the engine version from the server, not the default
         let engine_dir = if !data.engine.is_empty() {
@@ -458,8 +1136,15 @@ impl EngineManager {
             }),
             player_mode: true, // multiplayer is always player mode
             agent_name: player_name.to_string(),
+            benchmark: false,
+            env: self.engine_env.resolve(false),
+            aggregate_events: self.aggregate_events.clone(),
+            update_cadence: self.update_cadence.clone(),
+            coop_teams: Vec::new(),
+            mod_options: BTreeMap::new(),
         };
 
+        self.preflight().await?;
         let mut instance = EngineInstance::new(channel_id.clone(), config);
         instance.start().await?;
         self.instances.insert(channel_id.clone(), instance);
@@ -474,9 +1159,21 @@ impl EngineManager {
             .ok_or_else(|| format!("No game instance: {}", channel_id))?;
         instance.stop().await;
         self.instances.remove(channel_id);
+        if let Some(index) = self.queue.iter().position(|id| id == channel_id) {
+            self.queue.remove(index);
+            self.save_session();
+        }
         Ok(())
     }
 
+    /// Ask a game's engine to quit; see [`EngineInstance::request_quit`].
+    pub fn request_quit(&self, channel_id: &str) -> Result<(), String> {
+        self.instances
+            .get(channel_id)
+            .ok_or_else(|| format!("No game instance: {}", channel_id))?
+            .request_quit()
+    }
+
     /// Check all instances for crashes/exits.
     pub async fn check_all(&mut self) -> Vec<(String, GameStatus)> {
         let mut changed = Vec::new();
@@ -490,3 +1187,293 @@ impl EngineManager {
         changed
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Map names in the ZK pool routinely contain spaces and parentheses.
+    const MAP: &str = "Comet Catcher Redux v3.1 (remake)";
+
+    fn instance(player_mode: bool, multiplayer: bool) -> EngineInstance {
+        let config = GameConfig {
+            map: MAP.into(),
+            game: "Zero-K v1.12.7.0".into(),
+            engine_dir: PathBuf::from("/engines/105.1.1"),
+            write_dir: PathBuf::from("/tmp/write"),
+            headless: true,
+            socket_path: "/tmp/sai_1.sock".into(),
+            agent_ai: "AgentBridge".into(),
+            agent_team: 0,
+            opponent_ai: Some("CircuitAINovice".into()),
+            opponent_team: 1,
+            multiplayer: multiplayer.then(|| MultiplayerConfig {
+                host_ip: "203.0.113.7".into(),
+                host_port: 8452,
+                player_name: "Agent (bot)".into(),
+                script_password: "a1b2c3d4".into(),
+            }),
+            player_mode,
+            agent_name: "Agent".into(),
+            benchmark: false,
+            env: EngineEnv::default(),
+            aggregate_events: Vec::new(),
+            update_cadence: UpdateCadence::default(),
+            coop_teams: Vec::new(),
+            mod_options: BTreeMap::new(),
+        };
+        EngineInstance::new("game:local-1".into(), config)
+    }
+
+    /// Compare against tests/fixtures/startscripts/<name>.txt.
+    /// Run with UPDATE_GOLDEN=1 to rewrite the file after an intended change.
+    fn assert_golden(name: &str, script: &str) {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/startscripts")
+            .join(format!("{}.txt", name));
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            std::fs::write(&path, script).unwrap();
+        }
+        let expected = std::fs::read_to_string(&path)
+            .unwrap_or_else(|e| panic!("missing golden file {}: {}", path.display(), e));
+        assert_eq!(script, expected, "{} differs from {}", name, path.display());
+    }
+
+    #[test]
+    fn test_local_script_golden() {
+        let script = instance(false, false).generate_local_script();
+        assert_golden("local", &script);
+        startscript_validate(&script).unwrap();
+    }
+
+    #[test]
+    fn test_mod_options_section() {
+        let mut inst = instance(false, false);
+        inst.config.mod_options = BTreeMap::from([("startmetal".into(), "500".into()), ("deathmode".into(), "com".into())]);
+        for script in [inst.generate_local_script(), { inst.config.player_mode = true; inst.generate_player_script() }] {
+            startscript_validate(&script).unwrap();
+            let root = parse_startscript(&script).unwrap();
+            let options = root.children[0].children.iter().find(|c| c.name == "modoptions").unwrap();
+            assert_eq!((options.get("startmetal"), options.get("deathmode")), (Some("500"), Some("com")));
+        }
+    }
+
+    #[test]
+    fn test_benchmark_script_golden() {
+        let mut inst = instance(false, false);
+        inst.config.benchmark = true;
+        let script = inst.generate_local_script();
+        assert_golden("benchmark", &script);
+        startscript_validate(&script).unwrap();
+        let root = parse_startscript(&script).unwrap();
+        assert_eq!(root.children[0].get("minspeed"), Some("100"));
+    }
+
+    #[test]
+    fn test_coop_script_golden() {
+        let mut inst = instance(false, false);
+        inst.config.coop_teams = vec![2, 3];
+        let script = inst.generate_local_script();
+        assert_golden("coop", &script);
+        startscript_validate(&script).unwrap();
+        let root = parse_startscript(&script).unwrap();
+        let ai3 = root.children[0].children.iter().find(|c| c.name == "ai3").unwrap();
+        assert_eq!(ai3.children[0].get("socket_path"), Some("/tmp/sai_1_team3.sock"));
+
+        let paths = inst.config.socket_paths();
+        assert_eq!(paths.len(), 3);
+        assert_eq!((paths["team:0"].as_str(), paths["team:2"].as_str()), ("/tmp/sai_1.sock", "/tmp/sai_1_team2.sock"));
+        let sockets = inst.config.sai_sockets("game:local-1");
+        assert_eq!(sockets[2], ("game:local-1/team3".to_string(), "/tmp/sai_1_team3.sock".to_string()));
+    }
+
+    #[test]
+    fn test_self_play_script_golden() {
+        let mut inst = instance(false, false);
+        inst.config.opponent_ai = Some("AgentBridge".into());
+        assert!(inst.config.self_play());
+        let script = inst.generate_local_script();
+        assert_golden("self_play", &script);
+        startscript_validate(&script).unwrap();
+        let root = parse_startscript(&script).unwrap();
+        let ai1 = root.children[0].children.iter().find(|c| c.name == "ai1").unwrap();
+        assert_eq!((ai1.get("shortname"), ai1.get("team")), (Some("AgentBridge"), Some("1")));
+        assert_eq!(ai1.children[0].get("socket_path"), Some("/tmp/sai_1_team1.sock"));
+
+        assert_eq!(inst.config.socket_paths()["team:1"], "/tmp/sai_1_team1.sock");
+        let sockets = inst.config.sai_sockets("game:local-1");
+        assert_eq!(sockets[1], ("game:local-1/team1".to_string(), "/tmp/sai_1_team1.sock".to_string()));
+        assert_eq!((inst.config.ally_team_of(0), inst.config.ally_team_of(1)), (0, 1));
+    }
+
+    #[test]
+    fn test_startup_crash_report_names_the_environment() {
+        let mut inst = instance(false, false);
+        inst.config.env = EngineEnvConfig::default().resolve(true);
+        let report = inst.startup_crash_report(Some(134));
+        assert!(report.starts_with("Engine for game:local-1 died during startup (exit code Some(134))"), "{}", report);
+        assert!(report.contains("Environment: LANG=C LC_ALL=C LIBGL_ALWAYS_SOFTWARE=1"), "{}", report);
+        assert!(report.ends_with("Infolog: /tmp/write/infolog.txt"), "{}", report);
+    }
+
+    #[test]
+    fn test_player_script_golden() {
+        let script = instance(true, false).generate_player_script();
+        assert_golden("player", &script);
+        startscript_validate(&script).unwrap();
+    }
+
+    #[test]
+    fn test_player_script_leaves_agent_to_aicontrol() {
+        let root = parse_startscript(&instance(true, false).generate_player_script()).unwrap();
+        let game = &root.children[0];
+        let player = game.children.iter().find(|c| c.name == "player0").unwrap();
+        assert_eq!((player.get("name"), player.get("spectator")), (Some("Agent"), Some("0")));
+        // The only AI is the opponent; AgentBridge arrives via /aicontrol.
+        let ais = game.numbered("ai").unwrap();
+        assert_eq!(ais.len(), 1);
+        assert_eq!(ais[0].get("name"), Some("CircuitAINovice"));
+        assert!(ais[0].children.is_empty(), "no [Options] block");
+    }
+
+    #[tokio::test]
+    async fn test_player_mode_start_hands_off_connection() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = std::env::temp_dir().join(format!("gm-player-{}", uuid::Uuid::new_v4()));
+        let mut inst = instance(true, false);
+        inst.config.write_dir = dir.join("write");
+        inst.config.engine_dir = dir.join("engine");
+        for sub in [write_dir::SAI_DATA_DIR, "LuaUI/Config", "temp"] {
+            std::fs::create_dir_all(inst.config.write_dir.join(sub)).unwrap();
+        }
+        std::fs::create_dir_all(&inst.config.engine_dir).unwrap();
+        let bin = inst.config.engine_dir.join("spring-headless");
+        std::fs::write(&bin, "#!/bin/sh\nexit 0\n").unwrap();
+        std::fs::set_permissions(&bin, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        inst.start().await.unwrap();
+        inst.stop().await;
+        let read = |path: &str| std::fs::read_to_string(inst.config.write_dir.join(path)).unwrap();
+        let connection: serde_json::Value =
+            serde_json::from_str(&read(&format!("{}/connection.json", write_dir::SAI_DATA_DIR))).unwrap();
+        assert_eq!(connection["socket_path"], "/tmp/sai_1.sock");
+        let whitelist: serde_json::Value = serde_json::from_str(&read("LuaUI/Config/agent_bootstrap.json")).unwrap();
+        assert_eq!(whitelist["players"]["Agent"]["ai"], "AgentBridge");
+        assert!(!read("temp/gm_script_game_local-1.txt").contains("socket_path"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_multiplayer_script_golden() {
+        let script = instance(true, true).generate_multiplayer_script();
+        assert_golden("multiplayer", &script);
+        startscript_validate(&script).unwrap();
+    }
+
+    #[test]
+    fn test_parse_startscript_sections() {
+        let script = instance(false, false).generate_local_script();
+        let root = parse_startscript(&script).unwrap();
+        let game = &root.children[0];
+        assert_eq!(game.get("mapname"), Some(MAP));
+        assert_eq!(game.numbered("ai").unwrap().len(), 2);
+        let ai0 = game.children.iter().find(|c| c.name == "ai0").unwrap();
+        assert_eq!(ai0.children[0].name, "options");
+        assert_eq!(ai0.children[0].get("socket_path"), Some("/tmp/sai_1.sock"));
+    }
+
+    #[test]
+    fn test_validate_rejects_braces_in_names() {
+        let mut inst = instance(false, false);
+        inst.config.map = "Evil {Map} v1".into();
+        let err = startscript_validate(&inst.generate_local_script()).unwrap_err();
+        assert!(err.contains("mapname"), "{}", err);
+
+        let mut inst = instance(true, false);
+        inst.config.agent_name = "Agent;Team=5".into();
+        assert!(startscript_validate(&inst.generate_player_script()).is_err());
+    }
+
+    #[test]
+    fn test_validate_structural_errors() {
+        let valid = instance(false, false).generate_local_script();
+
+        let err = startscript_validate(&valid.replace("NumTeams=2", "NumTeams=3")).unwrap_err();
+        assert_eq!(err, "[game] numteams=3 but the script has 2 [TEAMn] sections");
+
+        let err = startscript_validate(&valid.replace("NumUsers=3", "NumUsers=2")).unwrap_err();
+        assert!(err.starts_with("[game] numusers=2"), "{}", err);
+
+        let err = startscript_validate(&valid.replace("Team=1;\n        Host=0;", "Team=4;\n        Host=0;"))
+            .unwrap_err();
+        assert_eq!(err, "[ai1] Team=4 does not exist");
+
+        let err = startscript_validate(&valid.replace("[AI1]", "[AI2]")).unwrap_err();
+        assert!(err.contains("not numbered"), "{}", err);
+
+        let err = startscript_validate(&valid.replace("    Mapname=", "    Map=")).unwrap_err();
+        assert_eq!(err, "[game] is missing mapname");
+
+        let unclosed = valid.trim_end().trim_end_matches('}');
+        assert_eq!(
+            startscript_validate(unclosed).unwrap_err(),
+            "[game] is never closed"
+        );
+        assert_eq!(
+            startscript_validate(&format!("{}\n}}", valid)).unwrap_err(),
+            "unbalanced '}'"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_queue_survives_restart() {
+        let dir = std::env::temp_dir().join(format!("gm-queue-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let sai_bridge = write_dir::SaiBridgeSource { lib: dir.join(write_dir::SAI_LIB), data: dir.join("data") };
+        let manager = || {
+            let sockets = SocketDir::create(&std::env::temp_dir()).unwrap();
+            EngineManager::new(dir.join("engine"), dir.clone(), sockets, sai_bridge.clone())
+        };
+
+        let mut engines = manager();
+        engines.max_concurrent_games = 0;
+        for map in ["Tundra", "Fields", "Comet"] {
+            engines
+                .start_local_game(map, "Zero-K $VERSION", None, true, false, "agent", false, 0, None, &BTreeMap::new())
+                .await
+                .unwrap();
+        }
+        assert_eq!(engines.instances["game:local-2"].status, GameStatus::Queued);
+        assert_eq!(engines.queue_position("game:local-3"), Some(3));
+
+        engines.cancel_queued("game:local-2").unwrap();
+        assert!(engines.cancel_queued("game:local-2").is_err());
+        assert_eq!(engines.queue_position("game:local-3"), Some(2));
+
+        let mut restarted = manager();
+        restarted.max_concurrent_games = 0;
+        assert_eq!(restarted.restore_session(), ["game:local-1", "game:local-3"]);
+        assert_eq!(restarted.instances["game:local-3"].config.map, "Comet");
+        assert_eq!(
+            restarted.instances["game:local-3"].config.socket_path,
+            restarted.sockets.socket_for("game:local-3").unwrap(),
+            "restored games get this run's sockets"
+        );
+        // Ids keep counting past the restored games.
+        let id = restarted
+            .start_local_game("Tundra", "Zero-K $VERSION", None, true, false, "agent", false, 0, None, &BTreeMap::new())
+            .await
+            .unwrap();
+        assert_eq!(id, "game:local-4");
+
+        // Nothing is installed in the write dir, so every launch fails and
+        // frees its slot for the next one.
+        restarted.max_concurrent_games = 1;
+        let launched = restarted.launch_queued().await;
+        let ids: Vec<&str> = launched.iter().map(|(id, _)| id.as_str()).collect();
+        assert_eq!(ids, ["game:local-1", "game:local-3", "game:local-4"]);
+        assert!(launched.iter().all(|(_, result)| result.is_err()));
+        assert!(matches!(restarted.instances["game:local-4"].status, GameStatus::Crashed(_)));
+        assert!(manager().restore_session().is_empty());
+    }
+}
@@ -0,0 +1,213 @@
+//! Engine launch environment. An engine would otherwise inherit whatever
+//! the GameManager has, which works on a desktop and breaks headless
+//! servers: a stale DISPLAY, hardware GL probing, one OpenMP thread per
+//! core, a locale that writes decimal commas.
+//!
+//! The config file's `engine_env` adds variables, removes inherited ones
+//! and sets resource limits on top of a curated default set. The resolved
+//! environment is part of each game's config, so queued games launch with
+//! the environment they were opened with.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+
+/// Set for every engine: numbers in configs and Lua parse the same
+/// everywhere.
+const LOCALE_DEFAULTS: &[(&str, &str)] = &[("LC_ALL", "C"), ("LANG", "C")];
+
+/// Set for headless engines: software GL, no video device, one OpenMP
+/// thread per engine.
+const HEADLESS_DEFAULTS: &[(&str, &str)] = &[
+    ("LIBGL_ALWAYS_SOFTWARE", "1"),
+    ("SDL_VIDEODRIVER", "dummy"),
+    ("OMP_NUM_THREADS", "1"),
+];
+
+/// Removed for headless engines.
+const HEADLESS_REMOVED: &[&str] = &["DISPLAY", "WAYLAND_DISPLAY"];
+
+/// Config file `engine_env`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EngineEnvConfig {
+    /// Apply the locale and headless defaults.
+    #[serde(default = "default_true")]
+    pub defaults: bool,
+    /// Variables for every engine; these win over the defaults.
+    #[serde(default)]
+    pub set: BTreeMap<String, String>,
+    /// Inherited variables to remove.
+    #[serde(default)]
+    pub remove: Vec<String>,
+    #[serde(default)]
+    pub limits: Limits,
+}
+
+impl Default for EngineEnvConfig {
+    fn default() -> Self {
+        Self { defaults: true, set: BTreeMap::new(), remove: Vec::new(), limits: Limits::default() }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Resource limits for the engine process (Unix rlimits). Unset leaves
+/// the inherited limit.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Limits {
+    /// Address space, in MiB.
+    #[serde(default)]
+    pub memory_mb: Option<u64>,
+    /// Core dump size, in MiB; 0 turns core dumps off.
+    #[serde(default)]
+    pub core_dump_mb: Option<u64>,
+}
+
+impl Limits {
+    fn is_empty(&self) -> bool {
+        self.memory_mb.is_none() && self.core_dump_mb.is_none()
+    }
+}
+
+impl EngineEnvConfig {
+    /// The environment for one engine.
+    pub fn resolve(&self, headless: bool) -> EngineEnv {
+        let mut env = EngineEnv { limits: self.limits.clone(), ..Default::default() };
+        if self.defaults {
+            let mut defaults = LOCALE_DEFAULTS.to_vec();
+            if headless {
+                defaults.extend_from_slice(HEADLESS_DEFAULTS);
+                env.remove.extend(HEADLESS_REMOVED.iter().map(|v| v.to_string()));
+            }
+            env.set.extend(defaults.iter().map(|(k, v)| (k.to_string(), v.to_string())));
+        }
+        env.set.extend(self.set.clone());
+        for name in &self.remove {
+            env.set.remove(name);
+            if !env.remove.contains(name) {
+                env.remove.push(name.clone());
+            }
+        }
+        env.remove.retain(|name| !env.set.contains_key(name));
+        env
+    }
+}
+
+/// What an engine's environment differs in from the GameManager's.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EngineEnv {
+    pub set: BTreeMap<String, String>,
+    pub remove: Vec<String>,
+    pub limits: Limits,
+}
+
+impl EngineEnv {
+    pub fn apply(&self, command: &mut Command) {
+        for name in &self.remove {
+            command.env_remove(name);
+        }
+        command.envs(&self.set);
+        #[cfg(unix)]
+        if !self.limits.is_empty() {
+            let limits = self.limits.clone();
+            // SAFETY: only setrlimit runs between fork and exec, and it is
+            // async-signal-safe.
+            unsafe {
+                command.pre_exec(move || apply_limits(&limits));
+            }
+        }
+    }
+
+    /// One line for logs and crash reports, e.g.
+    /// `LC_ALL=C OMP_NUM_THREADS=1 -DISPLAY memory 4096 MiB`.
+    pub fn describe(&self) -> String {
+        let mut parts: Vec<String> = self.set.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+        parts.extend(self.remove.iter().map(|name| format!("-{}", name)));
+        if let Some(mb) = self.limits.memory_mb {
+            parts.push(format!("memory {} MiB", mb));
+        }
+        if let Some(mb) = self.limits.core_dump_mb {
+            parts.push(format!("core dumps {} MiB", mb));
+        }
+        if parts.is_empty() {
+            "inherited unchanged".into()
+        } else {
+            parts.join(" ")
+        }
+    }
+}
+
+#[cfg(unix)]
+fn apply_limits(limits: &Limits) -> std::io::Result<()> {
+    let set = |resource, mb: u64| {
+        let bytes = mb.saturating_mul(1024 * 1024) as libc::rlim_t;
+        let limit = libc::rlimit { rlim_cur: bytes, rlim_max: bytes };
+        // SAFETY: a valid resource and a pointer to a live rlimit.
+        if unsafe { libc::setrlimit(resource, &limit) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    };
+    if let Some(mb) = limits.memory_mb {
+        set(libc::RLIMIT_AS, mb)?;
+    }
+    if let Some(mb) = limits.core_dump_mb {
+        set(libc::RLIMIT_CORE, mb)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve() {
+        let config = EngineEnvConfig::default();
+        let headless = config.resolve(true);
+        assert_eq!(headless.set["LC_ALL"], "C");
+        assert_eq!(headless.set["OMP_NUM_THREADS"], "1");
+        assert_eq!(headless.remove, ["DISPLAY", "WAYLAND_DISPLAY"]);
+        let windowed = config.resolve(false);
+        assert!(!windowed.set.contains_key("LIBGL_ALWAYS_SOFTWARE") && windowed.remove.is_empty());
+
+        // Configured variables win over the defaults, in both directions.
+        let config: EngineEnvConfig = serde_json::from_value(serde_json::json!({
+            "set": {"OMP_NUM_THREADS": "2", "DISPLAY": ":99"},
+            "remove": ["LANG", "HTTP_PROXY"],
+            "limits": {"memory_mb": 4096}
+        }))
+        .unwrap();
+        let env = config.resolve(true);
+        assert_eq!((env.set["OMP_NUM_THREADS"].as_str(), env.set["DISPLAY"].as_str()), ("2", ":99"));
+        assert!(!env.set.contains_key("LANG"));
+        assert_eq!(env.remove, ["WAYLAND_DISPLAY", "LANG", "HTTP_PROXY"]);
+        assert_eq!(
+            env.describe(),
+            "DISPLAY=:99 LC_ALL=C LIBGL_ALWAYS_SOFTWARE=1 OMP_NUM_THREADS=2 SDL_VIDEODRIVER=dummy \
+             -WAYLAND_DISPLAY -LANG -HTTP_PROXY memory 4096 MiB"
+        );
+
+        let off: EngineEnvConfig = serde_json::from_value(serde_json::json!({"defaults": false})).unwrap();
+        assert_eq!(off.resolve(true).describe(), "inherited unchanged");
+    }
+
+    #[tokio::test]
+    async fn test_applied_to_the_process() {
+        let env = EngineEnv {
+            set: BTreeMap::from([("GM_ENV_TEST".to_string(), "set".to_string())]),
+            remove: vec!["HOME".into()],
+            limits: Limits { memory_mb: None, core_dump_mb: Some(0) },
+        };
+        let mut command = Command::new("sh");
+        command.arg("-c").arg("echo \"$GM_ENV_TEST ${HOME:-unset} $(ulimit -c)\"");
+        env.apply(&mut command);
+        let output = command.output().await.unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "set unset 0");
+    }
+}
@@ -0,0 +1,327 @@
+//! Installing a Recoil engine release: download the linux64 archive from a
+//! mirror, check it against the mirror's `.sha256`, unpack it into
+//! `{spring_home}/engine/linux64/{version}` and make sure it runs.
+//!
+//! Used by `--install-engine <version>` on the command line and by the
+//! `engine_install` tool, which reports progress as push events.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use tokio::process::Command;
+use tokio::sync::mpsc;
+
+/// Where releases come from unless `ENGINE_MIRROR` says otherwise.
+/// `{version}` is replaced with the engine version.
+pub const DEFAULT_MIRROR: &str =
+    "https://github.com/beyond-all-reason/spring/releases/download/{version}/recoil_{version}_amd64-linux.tar.gz";
+
+/// Progress is reported each time this much more has been downloaded.
+const PROGRESS_BYTES: u64 = 10 * 1024 * 1024;
+
+/// How long `spring-headless --version` may take.
+const VERSION_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The mirror from `ENGINE_MIRROR`, or the default.
+pub fn mirror_from_env() -> String {
+    std::env::var("ENGINE_MIRROR").unwrap_or_else(|_| DEFAULT_MIRROR.into())
+}
+
+/// Versions become directory names, so keep them to plain characters.
+fn validate_version(version: &str) -> Result<(), String> {
+    let plain = |c: char| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_');
+    if version.is_empty() || version.starts_with('.') || !version.chars().all(plain) {
+        return Err(format!("Invalid engine version '{}'", version));
+    }
+    Ok(())
+}
+
+/// Install `version` and return its directory. `report` gets a line for
+/// each step. A download that fails is kept and resumed next time; one
+/// that fails its checksum is deleted.
+pub async fn install(
+    mirror: &str,
+    spring_home: &Path,
+    version: &str,
+    report: impl Fn(String),
+) -> Result<PathBuf, String> {
+    validate_version(version)?;
+    let engines = spring_home.join("engine/linux64");
+    let target = engines.join(version);
+    if target.join("spring-headless").exists() {
+        report(format!("Engine {} is already installed in {}", version, target.display()));
+        return Ok(target);
+    }
+    std::fs::create_dir_all(&engines).map_err(|e| format!("Cannot create {}: {}", engines.display(), e))?;
+
+    let url = mirror.replace("{version}", version);
+    let expected = fetch_checksum(&format!("{}.sha256", url)).await?;
+    let part = engines.join(format!(".{}.download", version));
+    if part.exists() && sha256(&part).await? == expected {
+        report(format!("Using the complete download in {}", part.display()));
+    } else {
+        if part.exists() {
+            report(format!("Resuming download of {}", url));
+        } else {
+            report(format!("Downloading {}", url));
+        }
+        download(&url, &part, &report).await?;
+        let actual = sha256(&part).await?;
+        if actual != expected {
+            let _ = std::fs::remove_file(&part);
+            return Err(format!("Checksum mismatch for {}: expected {}, got {}", url, expected, actual));
+        }
+    }
+    report("Checksum verified; unpacking".into());
+
+    unpack(&part, &engines, &target).await?;
+    let _ = std::fs::remove_file(&part);
+    match check_runs(&target).await {
+        Ok(line) => {
+            report(format!("Installed {} in {}", line, target.display()));
+            Ok(target)
+        }
+        Err(e) => {
+            let _ = std::fs::remove_dir_all(&target);
+            Err(e)
+        }
+    }
+}
+
+/// The hex digest from a `.sha256` file (`<digest>  <file name>` or just
+/// the digest).
+async fn fetch_checksum(url: &str) -> Result<String, String> {
+    let output = Command::new("curl")
+        .args(["-fsSL", url])
+        .output()
+        .await
+        .map_err(|e| format!("Cannot run curl: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("Cannot fetch checksum {}: curl {}", url, output.status));
+    }
+    let text = String::from_utf8_lossy(&output.stdout);
+    let digest = text.split_whitespace().next().unwrap_or_default().to_lowercase();
+    if digest.len() != 64 || !digest.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("No SHA-256 digest in {}", url));
+    }
+    Ok(digest)
+}
+
+pub async fn sha256(path: &Path) -> Result<String, String> {
+    let output = Command::new("sha256sum")
+        .arg(path)
+        .output()
+        .await
+        .map_err(|e| format!("Cannot run sha256sum: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("sha256sum {} failed: {}", path.display(), output.status));
+    }
+    let text = String::from_utf8_lossy(&output.stdout);
+    Ok(text.split_whitespace().next().unwrap_or_default().to_lowercase())
+}
+
+/// Download `url` into `part`, continuing whatever is already there.
+async fn download(url: &str, part: &Path, report: &impl Fn(String)) -> Result<(), String> {
+    let mut child = Command::new("curl")
+        .args(["-fsSL", "--retry", "3", "-C", "-", "-o"])
+        .arg(part)
+        .arg(url)
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("Cannot run curl: {}", e))?;
+    let mut tick = tokio::time::interval(Duration::from_secs(1));
+    let mut reported = 0;
+    let status = loop {
+        tokio::select! {
+            status = child.wait() => break status.map_err(|e| e.to_string())?,
+            _ = tick.tick() => {
+                let size = std::fs::metadata(part).map(|m| m.len()).unwrap_or(0);
+                if size >= reported + PROGRESS_BYTES {
+                    reported = size - size % PROGRESS_BYTES;
+                    report(format!("Downloaded {} MB", size / (1024 * 1024)));
+                }
+            }
+        }
+    };
+    if !status.success() {
+        return Err(format!("Download of {} failed (curl {}); run again to resume", url, status));
+    }
+    Ok(())
+}
+
+/// Unpack `archive` and move the engine (the directory holding
+/// spring-headless, at the top or one level down) to `target`.
+async fn unpack(archive: &Path, engines: &Path, target: &Path) -> Result<(), String> {
+    let name = target.file_name().unwrap_or_default().to_string_lossy();
+    let staging = engines.join(format!(".{}.unpack", name));
+    let _ = std::fs::remove_dir_all(&staging);
+    std::fs::create_dir_all(&staging).map_err(|e| format!("Cannot create {}: {}", staging.display(), e))?;
+    let result = async {
+        let status = Command::new("tar")
+            .arg("-xf")
+            .arg(archive)
+            .arg("-C")
+            .arg(&staging)
+            .status()
+            .await
+            .map_err(|e| format!("Cannot run tar: {}", e))?;
+        if !status.success() {
+            return Err(format!("Cannot unpack {}: tar {}", archive.display(), status));
+        }
+        let root = engine_root(&staging)
+            .ok_or_else(|| format!("No spring-headless in {}", archive.display()))?;
+        std::fs::rename(&root, target).map_err(|e| format!("Cannot move engine to {}: {}", target.display(), e))
+    }
+    .await;
+    let _ = std::fs::remove_dir_all(&staging);
+    result
+}
+
+fn engine_root(dir: &Path) -> Option<PathBuf> {
+    if dir.join("spring-headless").is_file() {
+        return Some(dir.to_path_buf());
+    }
+    let mut subdirs = std::fs::read_dir(dir).ok()?.flatten().filter(|e| e.path().is_dir());
+    let only = subdirs.next()?.path();
+    (subdirs.next().is_none() && only.join("spring-headless").is_file()).then_some(only)
+}
+
+/// Run `spring-headless --version`; its first line on success.
+async fn check_runs(engine_dir: &Path) -> Result<String, String> {
+    use std::os::unix::fs::PermissionsExt;
+    let bin = engine_dir.join("spring-headless");
+    for name in ["spring", "spring-headless"] {
+        let path = engine_dir.join(name);
+        if let Ok(meta) = std::fs::metadata(&path) {
+            let mode = meta.permissions().mode() | 0o111;
+            let _ = std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode));
+        }
+    }
+    let output = tokio::time::timeout(VERSION_TIMEOUT, Command::new(&bin).arg("--version").kill_on_drop(true).output())
+        .await
+        .map_err(|_| format!("{} --version did not finish", bin.display()))?
+        .map_err(|e| format!("Cannot run {}: {}", bin.display(), e))?;
+    if !output.status.success() {
+        return Err(format!("{} --version failed: {}", bin.display(), output.status));
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    Ok(stdout.lines().next().unwrap_or("engine").trim().to_string())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum InstallUpdate {
+    Progress { version: String, text: String },
+    Done { version: String, dir: PathBuf },
+    Failed { version: String, reason: String },
+}
+
+/// Installs running in the background for the `engine_install` tool.
+#[derive(Debug)]
+pub struct EngineInstalls {
+    pub mirror: String,
+    active: HashSet<String>,
+    tx: mpsc::UnboundedSender<InstallUpdate>,
+    rx: mpsc::UnboundedReceiver<InstallUpdate>,
+}
+
+impl EngineInstalls {
+    pub fn new(mirror: String) -> Self {
+        let (tx, rx) = mpsc::unbounded_channel();
+        Self { mirror, active: HashSet::new(), tx, rx }
+    }
+
+    /// Start installing `version`. False if it already is being installed.
+    pub fn start(&mut self, spring_home: PathBuf, version: String) -> bool {
+        if !self.active.insert(version.clone()) {
+            return false;
+        }
+        let (mirror, tx) = (self.mirror.clone(), self.tx.clone());
+        tokio::spawn(async move {
+            let progress = |text| {
+                let _ = tx.send(InstallUpdate::Progress { version: version.clone(), text });
+            };
+            let update = match install(&mirror, &spring_home, &version, progress).await {
+                Ok(dir) => InstallUpdate::Done { version, dir },
+                Err(reason) => InstallUpdate::Failed { version, reason },
+            };
+            let _ = tx.send(update);
+        });
+        true
+    }
+
+    /// Updates since the last poll.
+    pub fn poll(&mut self) -> Vec<InstallUpdate> {
+        let mut updates = Vec::new();
+        while let Ok(update) = self.rx.try_recv() {
+            if let InstallUpdate::Done { version, .. } | InstallUpdate::Failed { version, .. } = &update {
+                self.active.remove(version);
+            }
+            updates.push(update);
+        }
+        updates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A mirror directory serving `<version>.tar.gz` with a fake engine
+    /// that prints `version_line`, next to its `.sha256`.
+    fn fake_mirror(dir: &Path, version: &str, version_line: &str) -> String {
+        use std::os::unix::fs::PermissionsExt;
+        let engine = dir.join("build").join(format!("recoil_{}", version));
+        std::fs::create_dir_all(&engine).unwrap();
+        let bin = engine.join("spring-headless");
+        std::fs::write(&bin, format!("#!/bin/sh\necho '{}'\n", version_line)).unwrap();
+        std::fs::set_permissions(&bin, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let archive = dir.join(format!("{}.tar.gz", version));
+        let status = std::process::Command::new("tar")
+            .arg("-czf")
+            .arg(&archive)
+            .arg("-C")
+            .arg(dir.join("build"))
+            .arg(format!("recoil_{}", version))
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let digest = std::process::Command::new("sha256sum").arg(&archive).output().unwrap().stdout;
+        std::fs::write(dir.join(format!("{}.tar.gz.sha256", version)), digest).unwrap();
+        format!("file://{}/{{version}}.tar.gz", dir.display())
+    }
+
+    #[tokio::test]
+    async fn test_install_engine() {
+        let dir = std::env::temp_dir().join(format!("gm-engine-install-{}", uuid::Uuid::new_v4()));
+        let home = dir.join("home");
+        let mirror = fake_mirror(&dir.join("mirror"), "2025.04.10", "Recoil 2025.04.10");
+        let lines = std::sync::Mutex::new(Vec::new());
+        let report = |line: String| lines.lock().unwrap().push(line);
+
+        let installed = install(&mirror, &home, "2025.04.10", report).await.unwrap();
+        assert_eq!(installed, home.join("engine/linux64/2025.04.10"));
+        assert!(installed.join("spring-headless").is_file());
+        assert_eq!(lines.lock().unwrap().last().unwrap(), &format!("Installed Recoil 2025.04.10 in {}", installed.display()));
+        let leftovers: Vec<_> = std::fs::read_dir(home.join("engine/linux64")).unwrap().flatten().collect();
+        assert_eq!(leftovers.len(), 1, "download and staging dirs are cleaned up");
+        assert!(crate::engine::find_engine_dir(&home, Some("2025.04.10")).is_ok());
+
+        // A corrupt download is thrown away rather than resumed.
+        let mirror_dir = dir.join("mirror");
+        fake_mirror(&mirror_dir, "2025.05.01", "Recoil 2025.05.01");
+        std::fs::write(mirror_dir.join("2025.05.01.tar.gz.sha256"), format!("{}  x\n", "0".repeat(64))).unwrap();
+        let err = install(&mirror, &home, "2025.05.01", |_| {}).await.unwrap_err();
+        assert!(err.starts_with("Checksum mismatch"), "{}", err);
+        assert!(!home.join("engine/linux64/.2025.05.01.download").exists());
+        assert!(!home.join("engine/linux64/2025.05.01").exists());
+
+        assert_eq!(
+            install(&mirror, &home, "../etc", |_| {}).await.unwrap_err(),
+            "Invalid engine version '../etc'"
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
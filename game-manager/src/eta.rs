@@ -0,0 +1,227 @@
+//! Arrival estimates for move, fight and patrol orders, and reminders when
+//! the time is up.
+//!
+//! An estimate is the straight-line distance from the unit's last known
+//! position over its def's top speed: terrain, turning, traffic and fights
+//! on the way all make the real trip longer, so it is only ever given as
+//! an estimate. Units whose position or def speed isn't known, and queued
+//! orders (which start once the unit's queue gets there), get none.
+//!
+//! Reminders count game frames, so a paused game holds them too. A unit
+//! that dies, changes hands or gets a new unqueued order drops out of its
+//! reminder; one with no units left is cancelled.
+
+use serde::Serialize;
+
+use crate::sai_ipc::{SaiCommand, SaiEvent, UnitId};
+
+const FRAMES_PER_SECOND: f32 = 30.0;
+
+/// Reminders pending at once on one channel; more replace the oldest.
+pub const MAX_REMINDERS: usize = 32;
+
+/// One unit's estimated trip.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct UnitEta {
+    pub unit: UnitId,
+    /// Straight-line distance in elmos.
+    pub distance: f32,
+    pub secs: f32,
+}
+
+/// The order's target and its estimate, if `cmd` is an unqueued move,
+/// fight or patrol. `locate` gives a unit's x/z and top speed in elmos a
+/// second.
+pub fn estimate(cmd: &SaiCommand, locate: &impl Fn(UnitId) -> Option<([f32; 2], f32)>) -> Option<(UnitEta, [f32; 2])> {
+    let (unit, x, z) = match *cmd {
+        SaiCommand::Move { unit_id, x, z, queue: false, .. }
+        | SaiCommand::Fight { unit_id, x, z, queue: false, .. }
+        | SaiCommand::Patrol { unit_id, x, z, queue: false, .. } => (unit_id, x, z),
+        _ => return None,
+    };
+    let ([from_x, from_z], speed) = locate(unit)?;
+    if speed <= 0.0 {
+        return None;
+    }
+    let distance = (x - from_x).hypot(z - from_z);
+    Some((UnitEta { unit, distance, secs: distance / speed }, [x, z]))
+}
+
+/// The estimates of a batch of commands, as text and JSON; None if none
+/// of them has one.
+pub fn summary(etas: &[UnitEta]) -> Option<(String, serde_json::Value)> {
+    let fastest = etas.iter().map(|e| e.secs).min_by(f32::total_cmp)?;
+    let slowest = etas.iter().map(|e| e.secs).max_by(f32::total_cmp)?;
+    let span = if slowest.round() > fastest.round() {
+        format!("{:.0}-{:.0}s", fastest, slowest)
+    } else {
+        format!("{:.0}s", slowest)
+    };
+    let who = if etas.len() == 1 { format!("unit {}", etas[0].unit) } else { format!("{} units", etas.len()) };
+    let text = format!("Estimated arrival of {} in ~{} (straight line at top speed; likely later)", who, span);
+    let json = serde_json::json!({
+        "estimate": true,
+        "method": "straight_line",
+        "secs": slowest,
+        "units": etas,
+    });
+    Some((text, json))
+}
+
+/// A reminder due when the slowest unit should have arrived.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reminder {
+    pub units: Vec<UnitId>,
+    pub target: [f32; 2],
+    pub due_frame: i32,
+    /// The command, for the reminder's text.
+    pub label: String,
+}
+
+impl Reminder {
+    pub fn text(&self) -> String {
+        let ids: Vec<String> = self.units.iter().map(|u| u.to_string()).collect();
+        let who = if ids.len() == 1 { format!("Unit {}", ids[0]) } else { format!("Units {}", ids.join(", ")) };
+        format!(
+            "{} should be arriving at ({:.0}, {:.0}) about now ({}; estimated)",
+            who, self.target[0], self.target[1], self.label
+        )
+    }
+}
+
+/// One channel's pending reminders.
+#[derive(Debug, Default)]
+pub struct Reminders {
+    frame: i32,
+    pending: Vec<Reminder>,
+}
+
+impl Reminders {
+    /// Remind about `etas` once the slowest should be there.
+    pub fn schedule(&mut self, etas: &[UnitEta], target: [f32; 2], label: String) {
+        let Some(slowest) = etas.iter().map(|e| e.secs).max_by(f32::total_cmp) else { return };
+        if self.pending.len() >= MAX_REMINDERS {
+            self.pending.remove(0);
+        }
+        self.pending.push(Reminder {
+            units: etas.iter().map(|e| e.unit).collect(),
+            target,
+            due_frame: self.frame + (slowest * FRAMES_PER_SECOND).ceil() as i32,
+            label,
+        });
+    }
+
+    /// A command went out: units it gives a new unqueued order leave
+    /// their reminders.
+    pub fn ordered(&mut self, cmd: &SaiCommand) {
+        if let Some((unit, false)) = cmd.order() {
+            self.drop_unit(unit);
+        }
+    }
+
+    /// Track an event; updates return the reminders now due.
+    pub fn observe(&mut self, event: &SaiEvent) -> Vec<Reminder> {
+        match event {
+            SaiEvent::Update { frame, .. } => {
+                self.frame = *frame;
+                let (due, pending): (Vec<_>, Vec<_>) =
+                    std::mem::take(&mut self.pending).into_iter().partition(|r| r.due_frame <= *frame);
+                self.pending = pending;
+                return due;
+            }
+            SaiEvent::UnitDestroyed { unit, .. } | SaiEvent::UnitCaptured { unit, .. } => {
+                self.drop_unit(*unit);
+            }
+            _ => {}
+        }
+        Vec::new()
+    }
+
+    fn drop_unit(&mut self, unit: UnitId) {
+        for reminder in &mut self.pending {
+            reminder.units.retain(|&u| u != unit);
+        }
+        self.pending.retain(|r| !r.units.is_empty());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sai_protocol::WeaponDefId;
+
+    fn moved(unit: i32, x: f32, queue: bool) -> SaiCommand {
+        SaiCommand::Move { unit_id: UnitId(unit), x, y: 0.0, z: 0.0, queue }
+    }
+
+    /// Unit 1 at the origin and 2 at (300, 400), both at 100 elmos a
+    /// second; 3 is a structure.
+    fn locate(unit: UnitId) -> Option<([f32; 2], f32)> {
+        match unit.0 {
+            1 => Some(([0.0, 0.0], 100.0)),
+            2 => Some(([300.0, 400.0], 100.0)),
+            3 => Some(([0.0, 0.0], 0.0)),
+            _ => None,
+        }
+    }
+
+    fn update(frame: i32) -> SaiEvent {
+        SaiEvent::Update { frame, awaiting_commands: false, economy: None, allies: Vec::new(), counters: Default::default(), command_backlog: 0, under_construction: Vec::new(), update_interval: None }
+    }
+
+    fn destroyed(unit: i32) -> SaiEvent {
+        SaiEvent::UnitDestroyed {
+            unit: UnitId(unit), unit_name: None, attacker: UnitId(90), attacker_name: None,
+            attacker_team: None, attacker_relation: None, weapon_def_id: WeaponDefId(1),
+        }
+    }
+
+    #[test]
+    fn test_estimates() {
+        let (eta, target) = estimate(&moved(1, 4500.0, false), &locate).unwrap();
+        assert_eq!((eta.distance, eta.secs, target), (4500.0, 45.0, [4500.0, 0.0]));
+        // 500 elmos from the origin.
+        assert_eq!(estimate(&moved(2, 0.0, false), &locate).unwrap().0.secs, 5.0);
+        let fight = SaiCommand::Fight { unit_id: UnitId(1), x: 0.0, y: 0.0, z: 1000.0, queue: false };
+        assert_eq!(estimate(&fight, &locate).unwrap().0.secs, 10.0);
+
+        // Queued, a structure, an unknown unit, not a movement.
+        assert!(estimate(&moved(1, 4500.0, true), &locate).is_none());
+        assert!(estimate(&moved(3, 4500.0, false), &locate).is_none());
+        assert!(estimate(&moved(9, 4500.0, false), &locate).is_none());
+        assert!(estimate(&SaiCommand::Stop { unit_id: UnitId(1) }, &locate).is_none());
+
+        let etas: Vec<UnitEta> = [moved(1, 4500.0, false), moved(2, 4500.0, false)]
+            .iter()
+            .filter_map(|cmd| estimate(cmd, &locate).map(|(eta, _)| eta))
+            .collect();
+        let (text, json) = summary(&etas).unwrap();
+        assert_eq!(text, "Estimated arrival of 2 units in ~42-45s (straight line at top speed; likely later)");
+        assert_eq!((json["estimate"].as_bool(), json["secs"].as_f64()), (Some(true), Some(45.0)));
+        assert_eq!(summary(&etas[..1]).unwrap().0, "Estimated arrival of unit 1 in ~45s (straight line at top speed; likely later)");
+        assert!(summary(&[]).is_none());
+    }
+
+    #[test]
+    fn test_reminders_due_and_cancelled() {
+        let mut reminders = Reminders::default();
+        reminders.observe(&update(300));
+        let eta = |unit, secs| UnitEta { unit: UnitId(unit), distance: secs * 100.0, secs };
+        reminders.schedule(&[eta(1, 10.0), eta(2, 20.0)], [4500.0, 0.0], "move".into());
+        reminders.schedule(&[eta(3, 5.0)], [100.0, 100.0], "fight".into());
+        reminders.schedule(&[eta(4, 5.0)], [100.0, 100.0], "move".into());
+
+        // 3 dies on the way, 4 is sent elsewhere: both reminders go.
+        reminders.observe(&destroyed(3));
+        reminders.ordered(&moved(4, 0.0, false));
+        // A queued order leaves it be.
+        reminders.ordered(&moved(2, 0.0, true));
+
+        assert!(reminders.observe(&update(600)).is_empty());
+        reminders.observe(&destroyed(1));
+        let due = reminders.observe(&update(900));
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].text(), "Unit 2 should be arriving at (4500, 0) about now (move; estimated)");
+        assert!(reminders.observe(&update(9000)).is_empty());
+    }
+}
@@ -0,0 +1,379 @@
+//! Expansion planning for `game_expand`: which metal spots are still free,
+//! and the order a constructor should take them in.
+//!
+//! Built from the event stream only: metal spots arrive with `init`, our
+//! own mexes with the roster and unit events, enemy mexes as they enter
+//! line of sight. A spot counts as claimed while a mex stands on it, or
+//! while a constructor has been sent there and hasn't given up (gone idle
+//! or died).
+
+use std::collections::HashMap;
+
+use crate::sai_ipc::{Relation, SaiCommand, SaiEvent, UnitDefId, UnitId};
+use sai_protocol::MetalSpot;
+
+/// Unit def name of the Zero-K metal extractor.
+pub const MEX_DEF_NAME: &str = "staticmex";
+
+/// A mex this close to a spot (in elmos, on the ground plane) stands on it.
+const CLAIM_RADIUS: f32 = 80.0;
+
+fn distance(a: [f32; 3], b: [f32; 3]) -> f32 {
+    (a[0] - b[0]).hypot(a[2] - b[2])
+}
+
+fn spot_pos(spot: &MetalSpot) -> [f32; 3] {
+    [spot.x, spot.y, spot.z]
+}
+
+/// Spots, claims and own unit positions for one game channel.
+#[derive(Debug, Default)]
+pub struct MexPlanner {
+    spots: Vec<MetalSpot>,
+    /// Spot index of each of our mexes, finished or not.
+    own_mexes: HashMap<UnitId, usize>,
+    /// Spot index of each enemy mex we've seen.
+    enemy_mexes: HashMap<UnitId, usize>,
+    /// Spots a constructor has been sent to, by spot index.
+    reserved: HashMap<usize, UnitId>,
+    /// Last known position of each of our units.
+    positions: HashMap<UnitId, [f32; 3]>,
+}
+
+impl MexPlanner {
+    pub fn observe(&mut self, event: &SaiEvent) {
+        match event {
+            SaiEvent::Init { metal_spots: Some(spots), .. } => {
+                self.spots = spots.clone();
+                self.own_mexes.clear();
+                self.enemy_mexes.clear();
+                self.reserved.clear();
+            }
+            SaiEvent::Roster { units, .. } => {
+                self.positions = units.iter().map(|u| (u.unit, u.pos)).collect();
+                self.own_mexes.clear();
+                for unit in units {
+                    self.claim_own(unit.unit, &unit.unit_name, unit.pos);
+                }
+            }
+            SaiEvent::UnitCreated { unit, unit_name, pos: Some(pos), .. }
+            | SaiEvent::UnitFinished { unit, unit_name, pos: Some(pos) } => {
+                self.positions.insert(*unit, *pos);
+                self.claim_own(*unit, unit_name, *pos);
+            }
+            SaiEvent::UnitDamaged { unit, pos: Some(pos), .. } => {
+                self.positions.insert(*unit, *pos);
+            }
+            SaiEvent::UnitIdle { unit, .. } => self.release(*unit),
+            SaiEvent::UnitDestroyed { unit, .. } => {
+                self.positions.remove(unit);
+                self.own_mexes.remove(unit);
+                self.release(*unit);
+            }
+            // A mex taken by the enemy still stands on its spot, now theirs.
+            SaiEvent::UnitCaptured { unit, new_relation, .. } => {
+                self.positions.remove(unit);
+                if let (Some(spot), Some(Relation::Enemy)) = (self.own_mexes.remove(unit), new_relation) {
+                    self.enemy_mexes.insert(*unit, spot);
+                }
+                self.release(*unit);
+            }
+            SaiEvent::EnemyEnterLos { enemy, enemy_name, pos: Some(pos), .. } if is_mex(enemy_name) => {
+                if let Some(spot) = self.spot_at(*pos) {
+                    self.enemy_mexes.insert(*enemy, spot);
+                }
+            }
+            SaiEvent::EnemyDestroyed { enemy, .. } => {
+                self.enemy_mexes.remove(enemy);
+            }
+            // Ours now, recaptured mexes included: those keep their spot
+            // even when the bridge sends no position.
+            SaiEvent::UnitGiven { unit, unit_name, pos, .. } => {
+                let spot = self.enemy_mexes.remove(unit);
+                match (pos, spot) {
+                    (Some(pos), _) => {
+                        self.positions.insert(*unit, *pos);
+                        self.claim_own(*unit, unit_name, *pos);
+                    }
+                    (None, Some(spot)) if is_mex(unit_name) => {
+                        self.own_mexes.insert(*unit, spot);
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn claim_own(&mut self, unit: UnitId, name: &Option<String>, pos: [f32; 3]) {
+        if !is_mex(name) {
+            return;
+        }
+        if let Some(spot) = self.spot_at(pos) {
+            self.own_mexes.insert(unit, spot);
+            self.reserved.remove(&spot);
+        }
+    }
+
+    /// Every metal spot on the map.
+    pub fn spots(&self) -> &[MetalSpot] {
+        &self.spots
+    }
+
+    /// Give up the spots `builder` was sent to.
+    fn release(&mut self, builder: UnitId) {
+        self.reserved.retain(|_, b| *b != builder);
+    }
+
+    fn spot_at(&self, pos: [f32; 3]) -> Option<usize> {
+        spot_under(&self.spots, pos)
+    }
+
+    fn is_free(&self, spot: usize) -> bool {
+        !self.reserved.contains_key(&spot)
+            && !self.own_mexes.values().any(|s| *s == spot)
+            && !self.enemy_mexes.values().any(|s| *s == spot)
+    }
+
+    /// Pick up to `count` free spots for `builder`: the nearest to it, then
+    /// the nearest to that one, and so on. The spots are reserved for it.
+    pub fn plan(&mut self, builder: UnitId, count: usize) -> Result<Vec<MetalSpot>, String> {
+        if self.spots.is_empty() {
+            return Err("No metal spots known yet (they arrive with the init event)".into());
+        }
+        let mut from = *self
+            .positions
+            .get(&builder)
+            .ok_or_else(|| format!("Unit {} is not one of ours, or its position is unknown", builder))?;
+        let mut chosen = Vec::new();
+        while chosen.len() < count {
+            let next = (0..self.spots.len())
+                .filter(|i| self.is_free(*i) && !chosen.contains(i))
+                .min_by(|a, b| {
+                    distance(spot_pos(&self.spots[*a]), from).total_cmp(&distance(spot_pos(&self.spots[*b]), from))
+                });
+            let Some(next) = next else { break };
+            from = spot_pos(&self.spots[next]);
+            chosen.push(next);
+        }
+        if chosen.is_empty() {
+            return Err(format!("All {} metal spots are claimed", self.spots.len()));
+        }
+        for spot in &chosen {
+            self.reserved.insert(*spot, builder);
+        }
+        Ok(chosen.iter().map(|i| self.spots[*i].clone()).collect())
+    }
+
+    /// Forget a plan whose commands couldn't be sent.
+    pub fn cancel(&mut self, builder: UnitId) {
+        self.release(builder);
+    }
+}
+
+/// The index of the spot a mex at `pos` would stand on, if any.
+pub fn spot_under(spots: &[MetalSpot], pos: [f32; 3]) -> Option<usize> {
+    spots
+        .iter()
+        .enumerate()
+        .map(|(i, s)| (i, distance(spot_pos(s), pos)))
+        .filter(|(_, d)| *d <= CLAIM_RADIUS)
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(i, _)| i)
+}
+
+fn is_mex(name: &Option<String>) -> bool {
+    name.as_deref() == Some(MEX_DEF_NAME)
+}
+
+/// Queued build orders for a mex on each of `spots`.
+pub fn build_commands(builder: UnitId, spots: &[MetalSpot]) -> Vec<SaiCommand> {
+    spots
+        .iter()
+        .map(|spot| SaiCommand::Build {
+            unit_id: builder,
+            build_def_id: UnitDefId(0),
+            build_def_name: Some(MEX_DEF_NAME.into()),
+            x: spot.x,
+            y: spot.y,
+            z: spot.z,
+            facing: 0,
+            queue: true,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sai_protocol::{RosterUnit, TeamId, WeaponDefId};
+
+    fn spot(x: f32, z: f32) -> MetalSpot {
+        MetalSpot { x, y: 10.0, z, metal: 2.0 }
+    }
+
+    /// Spots along a line at x = 0, 1000, 1800, 2700, 4000; a constructor
+    /// (#5) at x = 950.
+    fn planner() -> MexPlanner {
+        let mut planner = MexPlanner::default();
+        planner.observe(&SaiEvent::Init {
+            frame: 0,
+            saved_game: false,
+            protocol_version: None,
+            metal_spots: Some([0.0, 1000.0, 1800.0, 2700.0, 4000.0].map(|x| spot(x, 500.0)).to_vec()),
+            map_width: None,
+            map_height: None,
+            start_pos: None,
+            teams: Vec::new(),
+            build: None,
+        });
+        let unit = |unit, name: &str, x| RosterUnit { unit, unit_name: Some(name.into()), pos: [x, 10.0, 500.0] };
+        planner.observe(&SaiEvent::Roster { frame: 1, units: vec![unit(UnitId(5), "cloakcon", 950.0)] });
+        planner
+    }
+
+    fn xs(spots: &[MetalSpot]) -> Vec<f32> {
+        spots.iter().map(|s| s.x).collect()
+    }
+
+    fn mex_created(unit: i32, x: f32) -> SaiEvent {
+        SaiEvent::UnitCreated {
+            unit: UnitId(unit),
+            unit_name: Some(MEX_DEF_NAME.into()),
+            builder: UnitId(5),
+            builder_name: None,
+            pos: Some([x + 20.0, 10.0, 510.0]),
+        }
+    }
+
+    #[test]
+    fn test_plan_chains_nearest_spots() {
+        let mut planner = planner();
+        // Nearest first, then onward from each chosen spot.
+        assert_eq!(xs(&planner.plan(UnitId(5), 3).unwrap()), [1000.0, 1800.0, 2700.0]);
+        // Those are reserved now; another plan gets what's left.
+        assert_eq!(xs(&planner.plan(UnitId(5), 5).unwrap()), [0.0, 4000.0]);
+        assert_eq!(planner.plan(UnitId(5), 1).unwrap_err(), "All 5 metal spots are claimed");
+    }
+
+    #[test]
+    fn test_claims_follow_mexes() {
+        let mut planner = planner();
+        // Our mex under construction and an enemy one in sight.
+        planner.observe(&mex_created(40, 1000.0));
+        planner.observe(&SaiEvent::EnemyEnterLos {
+            enemy: UnitId(700),
+            enemy_name: Some(MEX_DEF_NAME.into()),
+            team: None,
+            relation: None,
+            pos: Some([1800.0, 10.0, 480.0]),
+        });
+        // Enemy non-mex units don't claim anything.
+        planner.observe(&SaiEvent::EnemyEnterLos {
+            enemy: UnitId(701),
+            enemy_name: Some("cloakraid".into()),
+            team: None,
+            relation: None,
+            pos: Some([0.0, 10.0, 500.0]),
+        });
+        assert_eq!(xs(&planner.plan(UnitId(5), 2).unwrap()), [0.0, 2700.0]);
+        planner.cancel(UnitId(5));
+
+        // Losing our mex and killing theirs frees both spots again.
+        planner.observe(&SaiEvent::UnitDestroyed {
+            unit: UnitId(40), unit_name: None, attacker: UnitId(0), attacker_name: None,
+            attacker_team: None, attacker_relation: None, weapon_def_id: WeaponDefId(0),
+        });
+        planner.observe(&SaiEvent::EnemyDestroyed {
+            enemy: UnitId(700), enemy_name: None, team: None, relation: None,
+            attacker: UnitId(5), attacker_name: None, attacker_team: None, attacker_relation: None,
+        });
+        assert_eq!(xs(&planner.plan(UnitId(5), 2).unwrap()), [1000.0, 1800.0]);
+    }
+
+    #[test]
+    fn test_transfers_move_claims() {
+        let mut planner = planner();
+        planner.observe(&mex_created(40, 1000.0));
+        let captured = SaiEvent::UnitCaptured {
+            unit: UnitId(40), unit_name: Some(MEX_DEF_NAME.into()), old_team: TeamId(0), new_team: TeamId(1),
+            new_relation: Some(Relation::Enemy),
+        };
+        let recaptured = SaiEvent::UnitGiven {
+            unit: UnitId(40), unit_name: Some(MEX_DEF_NAME.into()), old_team: TeamId(1), new_team: TeamId(0),
+            old_relation: Some(Relation::Enemy), pos: None,
+        };
+        // Captured, the mex stays on its spot as the enemy's.
+        planner.observe(&captured);
+        assert!(planner.own_mexes.is_empty() && planner.enemy_mexes[&UnitId(40)] == 1);
+        assert_eq!(xs(&planner.plan(UnitId(5), 1).unwrap()), [1800.0]);
+        planner.cancel(UnitId(5));
+        // Taken back, it's ours again, even without a position.
+        planner.observe(&recaptured);
+        assert!(planner.enemy_mexes.is_empty() && planner.own_mexes[&UnitId(40)] == 1);
+
+        // A constructor handed over by an ally can be planned for.
+        planner.observe(&SaiEvent::UnitGiven {
+            unit: UnitId(41), unit_name: Some("cloakcon".into()), old_team: TeamId(2), new_team: TeamId(0),
+            old_relation: Some(Relation::Ally), pos: Some([2600.0, 10.0, 500.0]),
+        });
+        assert_eq!(xs(&planner.plan(UnitId(41), 1).unwrap()), [2700.0]);
+        // Given away, it drops its reservation.
+        planner.observe(&SaiEvent::UnitCaptured {
+            unit: UnitId(41), unit_name: None, old_team: TeamId(0), new_team: TeamId(2), new_relation: Some(Relation::Ally),
+        });
+        assert!(planner.reserved.is_empty() && !planner.positions.contains_key(&UnitId(41)));
+    }
+
+    #[test]
+    fn test_reservations_released() {
+        let mut planner = planner();
+        let roster_con = RosterUnit { unit: UnitId(6), unit_name: Some("cloakcon".into()), pos: [1100.0, 10.0, 500.0] };
+        planner.observe(&SaiEvent::Roster {
+            frame: 2,
+            units: vec![RosterUnit { unit: UnitId(5), unit_name: None, pos: [950.0, 10.0, 500.0] }, roster_con],
+        });
+        assert_eq!(xs(&planner.plan(UnitId(5), 1).unwrap()), [1000.0]);
+        assert_eq!(xs(&planner.plan(UnitId(6), 1).unwrap()), [1800.0]);
+
+        // The mex going up turns the reservation into a claim.
+        planner.observe(&mex_created(41, 1000.0));
+        assert!(!planner.reserved.contains_key(&1) && planner.own_mexes[&UnitId(41)] == 1);
+        // An idle constructor gave up on its spot.
+        planner.observe(&SaiEvent::UnitIdle { unit: UnitId(6), unit_name: None });
+        assert_eq!(xs(&planner.plan(UnitId(5), 1).unwrap()), [1800.0]);
+        // A dead one too, and its position is forgotten.
+        planner.observe(&SaiEvent::UnitDestroyed {
+            unit: UnitId(5), unit_name: None, attacker: UnitId(0), attacker_name: None,
+            attacker_team: None, attacker_relation: None, weapon_def_id: WeaponDefId(0),
+        });
+        assert!(planner.reserved.is_empty());
+        assert!(planner.plan(UnitId(5), 1).unwrap_err().starts_with("Unit 5 is not one of ours"));
+    }
+
+    #[test]
+    fn test_roster_mexes_and_commands() {
+        let mut planner = planner();
+        let mex = RosterUnit { unit: UnitId(30), unit_name: Some(MEX_DEF_NAME.into()), pos: [1000.0, 10.0, 500.0] };
+        let con = RosterUnit { unit: UnitId(5), unit_name: None, pos: [950.0, 10.0, 500.0] };
+        planner.observe(&SaiEvent::Roster { frame: 3, units: vec![con, mex] });
+        let spots = planner.plan(UnitId(5), 1).unwrap();
+        assert_eq!(xs(&spots), [1800.0]);
+
+        let commands = build_commands(UnitId(5), &spots);
+        assert_eq!(
+            commands,
+            [SaiCommand::Build {
+                unit_id: UnitId(5),
+                build_def_id: UnitDefId(0),
+                build_def_name: Some("staticmex".into()),
+                x: 1800.0,
+                y: 10.0,
+                z: 500.0,
+                facing: 0,
+                queue: true,
+            }]
+        );
+        assert!(MexPlanner::default().plan(UnitId(5), 1).unwrap_err().starts_with("No metal spots known"));
+    }
+}
@@ -0,0 +1,394 @@
+//! Factory tracker: what each of our factories is making, how long until
+//! it's done and how much is queued behind it.
+//!
+//! Factories are recognised by the unit def catalog when the channel has
+//! one, by def name otherwise, as for idle builder alerts. The unit a
+//! factory is making comes from `unit_created` and its progress from the
+//! `update`s. The queue behind it is read from the bridge at most every
+//! [`QUERY_FRAMES`] of game time, on an `update`, so a paused game asks
+//! nothing. Times are a def's build time over the factory's build speed,
+//! from the catalog.
+//!
+//! The engine keeps a factory's move, patrol and fight orders for the
+//! units it makes apart from its build queue, out of the bridge's reach;
+//! the rally point is the last of them the GameManager sent.
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::idle_builders::BuilderKind;
+use crate::positions;
+use crate::sai_ipc::{SaiCommand, SaiEvent, UnitDefId, UnitId};
+use crate::unit_defs::{Role, UnitDefCatalog};
+use sai_protocol::UnitQueue;
+
+/// Frames of game time between queue queries.
+pub const QUERY_FRAMES: i32 = 30 * 10;
+
+#[derive(Debug, Clone)]
+struct Factory {
+    unit_name: String,
+    pos: Option<[f32; 3]>,
+    /// The unit being made, its def name and progress in percent.
+    building: Option<(UnitId, String, f32)>,
+    /// Build orders as of the last query, the one in progress first.
+    queue: Vec<UnitDefId>,
+    rally: Option<[f32; 3]>,
+}
+
+/// One unit a factory is making or will make.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Item {
+    pub unit_name: String,
+    pub name: String,
+    /// Game seconds until it's done: the rest of its build for the one in
+    /// progress, the whole of it for queued ones. None without build times.
+    pub secs: Option<f32>,
+}
+
+/// A factory's rally point.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Rally {
+    pub pos: [f32; 3],
+    /// Eight-point compass direction from the factory.
+    pub direction: Option<&'static str>,
+}
+
+/// What one factory is doing.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Summary {
+    pub unit: UnitId,
+    pub unit_name: String,
+    pub name: String,
+    pub building: Option<Item>,
+    /// Percent done of the one in progress.
+    pub progress: Option<f32>,
+    pub queued: Vec<Item>,
+    /// Game seconds until the queue is empty, if every item has a time.
+    pub queue_secs: Option<f32>,
+    pub rally: Option<Rally>,
+}
+
+impl Summary {
+    /// "Cloakbot Factory: building Glaive (8s), 3 more queued, rally set north-east"
+    pub fn text(&self) -> String {
+        let mut text = format!("{}: ", self.name);
+        match &self.building {
+            Some(item) => {
+                text += &format!("building {}", item.name);
+                match (item.secs, self.progress) {
+                    (Some(secs), _) => text += &format!(" ({:.0}s)", secs.ceil()),
+                    (None, Some(progress)) => text += &format!(" ({:.0}%)", progress),
+                    (None, None) => {}
+                }
+                if !self.queued.is_empty() {
+                    text += &format!(", {} more queued", self.queued.len());
+                }
+            }
+            None if self.queued.is_empty() => text += "idle",
+            None => text += &format!("not building, {} queued", self.queued.len()),
+        }
+        match &self.rally {
+            Some(Rally { direction: Some(direction), .. }) => text += &format!(", rally set {}", direction),
+            Some(Rally { pos, .. }) => text += &format!(", rally set at ({:.0}, {:.0})", pos[0], pos[2]),
+            None => {}
+        }
+        text
+    }
+}
+
+/// One game channel's factories.
+#[derive(Debug, Default)]
+pub struct FactoryTracker {
+    factories: BTreeMap<UnitId, Factory>,
+    frame: i32,
+    /// When the last query went out and the factories it asked about.
+    queried: Option<i32>,
+    asked: Vec<UnitId>,
+}
+
+fn is_factory(unit_name: &str, catalog: Option<&UnitDefCatalog>) -> bool {
+    match catalog.and_then(|c| c.named(unit_name)) {
+        Some(def) => Role::of(def) == Role::Factory,
+        None => BuilderKind::classify(unit_name) == Some(BuilderKind::Factory),
+    }
+}
+
+impl FactoryTracker {
+    pub fn is_empty(&self) -> bool {
+        self.factories.is_empty()
+    }
+
+    fn track(&mut self, unit: UnitId, unit_name: &Option<String>, pos: Option<[f32; 3]>, catalog: Option<&UnitDefCatalog>) {
+        let Some(name) = unit_name.as_ref().filter(|n| is_factory(n, catalog)) else { return };
+        let factory = self.factories.entry(unit).or_insert_with(|| Factory {
+            unit_name: name.clone(),
+            pos: None,
+            building: None,
+            queue: Vec::new(),
+            rally: None,
+        });
+        factory.pos = pos.or(factory.pos);
+    }
+
+    pub fn observe(&mut self, event: &SaiEvent, catalog: Option<&UnitDefCatalog>) {
+        match event {
+            SaiEvent::Update { frame, under_construction, .. } => {
+                self.frame = *frame;
+                for factory in self.factories.values_mut() {
+                    let Some((unit, _, progress)) = &mut factory.building else { continue };
+                    if let Some(c) = under_construction.iter().find(|c| c.unit == *unit) {
+                        *progress = c.progress;
+                    }
+                }
+            }
+            SaiEvent::Roster { frame, units } => {
+                self.frame = *frame;
+                for unit in units {
+                    self.track(unit.unit, &unit.unit_name, Some(unit.pos), catalog);
+                }
+            }
+            SaiEvent::UnitFinished { unit, unit_name, pos } | SaiEvent::UnitGiven { unit, unit_name, pos, .. } => {
+                self.finished(*unit);
+                self.track(*unit, unit_name, *pos, catalog);
+            }
+            SaiEvent::UnitCreated { unit, unit_name, builder, .. } => {
+                if let Some(factory) = self.factories.get_mut(builder) {
+                    let name = unit_name.clone().unwrap_or_else(|| format!("unit #{}", unit));
+                    factory.building = Some((*unit, name, 0.0));
+                }
+            }
+            SaiEvent::UnitDestroyed { unit, .. } | SaiEvent::UnitCaptured { unit, .. } => {
+                self.factories.remove(unit);
+                self.finished(*unit);
+            }
+            _ => {}
+        }
+    }
+
+    /// `unit` is no longer in production, built or lost.
+    fn finished(&mut self, unit: UnitId) {
+        for factory in self.factories.values_mut() {
+            if factory.building.as_ref().is_some_and(|(u, _, _)| *u == unit) {
+                factory.building = None;
+            }
+        }
+    }
+
+    /// A command went out: a move, patrol or fight order to a factory sets
+    /// its rally point.
+    pub fn ordered(&mut self, command: &SaiCommand) {
+        let (SaiCommand::Move { unit_id, x, y, z, .. }
+        | SaiCommand::Patrol { unit_id, x, y, z, .. }
+        | SaiCommand::Fight { unit_id, x, y, z, .. }) = command
+        else {
+            return;
+        };
+        if let Some(factory) = self.factories.get_mut(unit_id) {
+            factory.rally = Some([*x, *y, *z]);
+        }
+    }
+
+    /// The factories to ask the bridge about, when a query is due.
+    pub fn due(&mut self) -> Option<Vec<UnitId>> {
+        if self.factories.is_empty() || self.queried.is_some_and(|f| self.frame - f < QUERY_FRAMES) {
+            return None;
+        }
+        self.queried = Some(self.frame);
+        self.asked = self.factories.keys().copied().collect();
+        Some(self.asked.clone())
+    }
+
+    /// Take in the bridge's answer. A factory asked about and left out is
+    /// gone, even if the event saying so hasn't arrived.
+    pub fn answered(&mut self, queues: &[UnitQueue]) {
+        for unit in std::mem::take(&mut self.asked) {
+            match queues.iter().find(|q| q.unit == unit) {
+                Some(queue) => {
+                    if let Some(factory) = self.factories.get_mut(&unit) {
+                        factory.queue = queue.commands.iter().filter_map(|c| c.build_def()).collect();
+                    }
+                }
+                None => {
+                    self.factories.remove(&unit);
+                }
+            }
+        }
+    }
+
+    /// Every factory, in unit id order.
+    pub fn summaries(&self, catalog: Option<&UnitDefCatalog>) -> Vec<Summary> {
+        self.factories.iter().map(|(unit, factory)| summarize(*unit, factory, catalog)).collect()
+    }
+}
+
+/// The state stream's factory part: one clause per factory, and the
+/// summaries. None without factories.
+pub fn digest(summaries: &[Summary]) -> Option<(String, serde_json::Value)> {
+    if summaries.is_empty() {
+        return None;
+    }
+    let text = summaries.iter().map(Summary::text).collect::<Vec<_>>().join("; ");
+    Some((text, serde_json::to_value(summaries).unwrap()))
+}
+
+fn summarize(unit: UnitId, factory: &Factory, catalog: Option<&UnitDefCatalog>) -> Summary {
+    let def = catalog.and_then(|c| c.named(&factory.unit_name));
+    let build_speed = def.map_or(0.0, |d| d.build_speed);
+    let item = |unit_name: &str, done: f32| {
+        let def = catalog.and_then(|c| c.named(unit_name));
+        Item {
+            unit_name: unit_name.to_string(),
+            name: def.map_or_else(|| unit_name.to_string(), |d| d.human_name.clone()),
+            secs: def.filter(|_| build_speed > 0.0).map(|d| d.build_time * (1.0 - done / 100.0) / build_speed),
+        }
+    };
+    let names: Vec<String> = factory
+        .queue
+        .iter()
+        .map(|id| catalog.and_then(|c| c.by_id(*id)).map_or_else(|| format!("def #{}", id.0), |d| d.name.clone()))
+        .collect();
+    let building = factory.building.as_ref().map(|(_, name, progress)| item(name, *progress));
+    // The engine's queue starts with the order in progress.
+    let skip = match &factory.building {
+        Some((_, name, _)) if names.first() == Some(name) => 1,
+        _ => 0,
+    };
+    let queued: Vec<Item> = names.iter().skip(skip).map(|name| item(name, 0.0)).collect();
+    let queue_secs = building.iter().chain(&queued).map(|i| i.secs).sum();
+    let rally = factory.rally.map(|pos| Rally {
+        pos,
+        direction: factory.pos.map(|from| positions::compass(pos[0] - from[0], pos[2] - from[2])),
+    });
+    Summary {
+        unit,
+        unit_name: factory.unit_name.clone(),
+        name: def.map_or_else(|| factory.unit_name.clone(), |d| d.human_name.clone()),
+        building,
+        progress: factory.building.as_ref().map(|(_, _, progress)| *progress),
+        queued,
+        queue_secs,
+        rally,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sai_ipc::UnitDefInfo;
+    use sai_protocol::{Construction, QueuedCommand};
+
+    fn catalog() -> UnitDefCatalog {
+        let def = |id, name: &str, human_name: &str, build_time, speed, build_speed| UnitDefInfo {
+            id: UnitDefId(id),
+            name: name.into(),
+            human_name: human_name.into(),
+            description: None,
+            metal_cost: build_time,
+            energy_cost: build_time,
+            build_time,
+            health: 100.0,
+            speed,
+            builder: build_speed > 0.0,
+            build_options: if id == 1 { vec![UnitDefId(2), UnitDefId(3)] } else { Vec::new() },
+            metal_make: 0.0,
+            energy_make: 0.0,
+            extracts_metal: 0.0,
+            build_speed,
+        };
+        UnitDefCatalog::new(vec![
+            def(1, "factorycloak", "Cloakbot Factory", 400.0, 0.0, 10.0),
+            def(2, "cloakraid", "Glaive", 65.0, 3.0, 0.0),
+            def(3, "cloakriot", "Reaver", 250.0, 2.0, 0.0),
+        ])
+    }
+
+    fn update(frame: i32, progress: f32) -> SaiEvent {
+        SaiEvent::Update {
+            frame,
+            awaiting_commands: false,
+            economy: None,
+            allies: Vec::new(),
+            counters: Default::default(),
+            command_backlog: 0,
+            under_construction: vec![Construction { unit: UnitId(20), unit_name: Some("cloakraid".into()), progress, builders: 1 }],
+            update_interval: None,
+        }
+    }
+
+    fn queue(unit: i32, defs: &[i32]) -> UnitQueue {
+        let mut commands: Vec<QueuedCommand> = defs.iter().map(|d| QueuedCommand { id: -d, params: Vec::new() }).collect();
+        // Other orders in the queue aren't build orders.
+        commands.push(QueuedCommand { id: 5, params: Vec::new() });
+        UnitQueue { unit: UnitId(unit), commands }
+    }
+
+    #[test]
+    fn test_factory_summary() {
+        let catalog = catalog();
+        let mut tracker = FactoryTracker::default();
+        let finished = SaiEvent::UnitFinished { unit: UnitId(10), unit_name: Some("factorycloak".into()), pos: Some([1000.0, 0.0, 1000.0]) };
+        tracker.observe(&finished, Some(&catalog));
+        // Not a factory.
+        tracker.observe(&SaiEvent::UnitFinished { unit: UnitId(11), unit_name: Some("cloakraid".into()), pos: None }, Some(&catalog));
+        assert_eq!(tracker.summaries(Some(&catalog))[0].text(), "Cloakbot Factory: idle");
+
+        tracker.observe(
+            &SaiEvent::UnitCreated { unit: UnitId(20), unit_name: Some("cloakraid".into()), builder: UnitId(10), builder_name: None, pos: None },
+            Some(&catalog),
+        );
+        tracker.observe(&update(300, 40.0), Some(&catalog));
+        assert_eq!(tracker.due(), Some(vec![UnitId(10)]));
+        tracker.answered(&[queue(10, &[2, 2, 3, 2])]);
+        tracker.ordered(&SaiCommand::Move { unit_id: UnitId(10), x: 1500.0, y: 0.0, z: 500.0, queue: false });
+
+        let summary = &tracker.summaries(Some(&catalog))[0];
+        assert_eq!(summary.text(), "Cloakbot Factory: building Glaive (4s), 3 more queued, rally set north-east");
+        assert_eq!(summary.building.as_ref().unwrap().secs, Some(3.9));
+        let queued: Vec<&str> = summary.queued.iter().map(|i| i.name.as_str()).collect();
+        assert_eq!(queued, ["Glaive", "Reaver", "Glaive"]);
+        assert_eq!(summary.queue_secs, Some(3.9 + 6.5 + 25.0 + 6.5));
+
+        // Done: the factory waits on its next order.
+        tracker.observe(&SaiEvent::UnitFinished { unit: UnitId(20), unit_name: Some("cloakraid".into()), pos: None }, Some(&catalog));
+        let summary = &tracker.summaries(Some(&catalog))[0];
+        assert!(summary.building.is_none());
+        assert!(summary.text().starts_with("Cloakbot Factory: not building, 4 queued"));
+
+        // Without a catalog, names stand in and times are unknown.
+        let text = tracker.summaries(None)[0].text();
+        assert!(text.starts_with("factorycloak: not building, 4 queued"), "{}", text);
+    }
+
+    #[test]
+    fn test_query_cadence_and_losses() {
+        let catalog = catalog();
+        let mut tracker = FactoryTracker::default();
+        assert_eq!(tracker.due(), None, "no factories, nothing to ask");
+        let roster = SaiEvent::Roster {
+            frame: 0,
+            units: vec![
+                sai_protocol::RosterUnit { unit: UnitId(10), unit_name: Some("factorycloak".into()), pos: [0.0; 3] },
+                sai_protocol::RosterUnit { unit: UnitId(12), unit_name: Some("factoryshield".into()), pos: [0.0; 3] },
+            ],
+        };
+        tracker.observe(&roster, Some(&catalog));
+        assert_eq!(tracker.due(), Some(vec![UnitId(10), UnitId(12)]), "known by name when the catalog hasn't it");
+        tracker.observe(&update(QUERY_FRAMES - 1, 0.0), Some(&catalog));
+        assert_eq!(tracker.due(), None);
+        tracker.observe(&update(QUERY_FRAMES, 0.0), Some(&catalog));
+        assert!(tracker.due().is_some());
+
+        // 12 died while the query was out: the answer leaves it out.
+        tracker.answered(&[queue(10, &[2])]);
+        assert_eq!(tracker.summaries(None).len(), 1);
+        // An answer with no query out changes nothing.
+        tracker.answered(&[]);
+        assert_eq!(tracker.summaries(None).len(), 1);
+        tracker.observe(&SaiEvent::UnitCaptured { unit: UnitId(10), unit_name: None, old_team: sai_protocol::TeamId(0), new_team: sai_protocol::TeamId(1), new_relation: None }, None);
+        assert!(tracker.is_empty());
+    }
+}
@@ -0,0 +1,286 @@
+//! The `game_started` summary: where the agent starts and what it has, in
+//! one message, once the bridge's `init` and `roster` are both in.
+//!
+//! The bridge sends the roster right after init, but an older bridge sends
+//! none; after [`ROSTER_WAIT`] the summary goes out with what init alone
+//! says.
+
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::sai_ipc::{Relation, SaiEvent, UnitId};
+use sai_protocol::{MetalSpot, RosterUnit, TeamSlot};
+
+/// How long init waits for its roster.
+pub const ROSTER_WAIT: Duration = Duration::from_secs(5);
+
+/// Metal spots listed, nearest first.
+const NEAREST_SPOTS: usize = 5;
+
+/// Elmos per map square; the bridge reports the map size in squares.
+pub const SQUARE_SIZE: i32 = 8;
+
+/// Collects init and roster for one game channel until the summary is sent.
+#[derive(Debug, Default)]
+pub struct StartWatch {
+    init: Option<SaiEvent>,
+    roster: Option<Vec<RosterUnit>>,
+    /// When the first of the two arrived.
+    since: Option<Instant>,
+    sent: bool,
+}
+
+impl StartWatch {
+    /// Track an event; returns the summary once init and roster are both in.
+    pub fn observe(&mut self, event: &SaiEvent, now: Instant) -> Option<GameStart> {
+        if self.sent {
+            return None;
+        }
+        match event {
+            SaiEvent::Init { .. } => self.init = Some(event.clone()),
+            SaiEvent::Roster { units, .. } => self.roster = Some(units.clone()),
+            _ => return None,
+        }
+        self.since.get_or_insert(now);
+        if self.roster.is_some() {
+            self.finish()
+        } else {
+            None
+        }
+    }
+
+    /// The summary without a roster, once init has waited [`ROSTER_WAIT`].
+    pub fn poll(&mut self, now: Instant) -> Option<GameStart> {
+        if self.sent || self.since.is_none_or(|since| now.duration_since(since) < ROSTER_WAIT) {
+            return None;
+        }
+        self.finish()
+    }
+
+    /// None until init has arrived: without it there's nothing to say.
+    fn finish(&mut self) -> Option<GameStart> {
+        let init = self.init.as_ref()?;
+        self.sent = true;
+        Some(GameStart::new(init, self.roster.as_deref()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Commander {
+    pub unit: UnitId,
+    pub def_name: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NearbySpot {
+    pub x: f32,
+    pub z: f32,
+    pub metal: f32,
+    pub distance: f32,
+}
+
+/// What the agent starts with.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GameStart {
+    /// Set by the caller, which knows what the game was launched with.
+    pub map: Option<String>,
+    /// Width and height in elmos.
+    pub map_size: Option<[i32; 2]>,
+    /// The commander's position, else the engine's start position.
+    pub start_pos: Option<[f32; 3]>,
+    pub commander: Option<Commander>,
+    /// Every unit in the roster, the commander included; None without one.
+    pub starting_units: Option<usize>,
+    pub teams: Vec<TeamSlot>,
+    pub nearest_metal: Vec<NearbySpot>,
+}
+
+impl GameStart {
+    fn new(init: &SaiEvent, roster: Option<&[RosterUnit]>) -> Self {
+        let SaiEvent::Init { metal_spots, map_width, map_height, start_pos, teams, .. } = init else {
+            unreachable!("StartWatch only keeps init events");
+        };
+        let commander = roster.and_then(find_commander);
+        let start_pos = commander.map(|c| c.pos).or(*start_pos);
+        let nearest_metal = match (start_pos, metal_spots) {
+            (Some(pos), Some(spots)) => nearest_spots(pos, spots),
+            _ => Vec::new(),
+        };
+        Self {
+            map: None,
+            map_size: map_width.zip(*map_height).map(|(w, h)| [w * SQUARE_SIZE, h * SQUARE_SIZE]),
+            start_pos,
+            commander: commander.map(|c| Commander { unit: c.unit, def_name: c.unit_name.clone() }),
+            starting_units: roster.map(|units| units.len()),
+            teams: teams.clone(),
+            nearest_metal,
+        }
+    }
+
+    pub fn text(&self) -> String {
+        let mut s = format!("Game started on {}", self.map.as_deref().unwrap_or("an unknown map"));
+        if let Some([w, h]) = self.map_size {
+            s += &format!(" ({}x{} elmos)", w, h);
+        }
+        s += ".";
+        if let Some(me) = self.teams.iter().find(|t| t.relation == Relation::Mine) {
+            s += &format!(" You are team {}", me.team);
+            s += &match self.start_pos {
+                Some([x, _, z]) => format!(", starting at ({:.0}, {:.0}).", x, z),
+                None => ".".into(),
+            };
+        } else if let Some([x, _, z]) = self.start_pos {
+            s += &format!(" You start at ({:.0}, {:.0}).", x, z);
+        }
+        match (&self.commander, self.starting_units) {
+            (Some(c), Some(n)) => {
+                let name = c.def_name.as_deref().unwrap_or("unknown");
+                s += &format!(" Commander: {} (#{})", name, c.unit);
+                if n > 1 {
+                    s += &format!(", plus {} other starting units", n - 1);
+                }
+                s += ".";
+            }
+            (None, Some(n)) => s += &format!(" No commander found among your {} starting units.", n),
+            (_, None) => s += " Your starting units are unknown (the bridge sent no roster).",
+        }
+        if !self.teams.is_empty() {
+            s += &format!(
+                " Allies: {}. Enemies: {}.",
+                team_list(&self.teams, Relation::Ally),
+                team_list(&self.teams, Relation::Enemy)
+            );
+        }
+        if !self.nearest_metal.is_empty() {
+            let spots: Vec<String> = self
+                .nearest_metal
+                .iter()
+                .map(|spot| format!("({:.0}, {:.0}) {:.0} away", spot.x, spot.z, spot.distance))
+                .collect();
+            s += &format!(" Nearest metal spots: {}.", spots.join(", "));
+        }
+        s
+    }
+}
+
+/// Zero-K commanders are the `dyn*` modular ones or a `comm*` def; failing
+/// that, a roster of one unit is taken to be the commander.
+fn find_commander(roster: &[RosterUnit]) -> Option<&RosterUnit> {
+    let is_commander = |u: &&RosterUnit| {
+        u.unit_name.as_deref().is_some_and(|name| name.starts_with("dyn") || name.contains("comm"))
+    };
+    roster.iter().find(is_commander).or(match roster {
+        [only] => Some(only),
+        _ => None,
+    })
+}
+
+fn nearest_spots(pos: [f32; 3], spots: &[MetalSpot]) -> Vec<NearbySpot> {
+    let mut nearby: Vec<NearbySpot> = spots
+        .iter()
+        .map(|spot| NearbySpot {
+            x: spot.x,
+            z: spot.z,
+            metal: spot.metal,
+            distance: (spot.x - pos[0]).hypot(spot.z - pos[2]),
+        })
+        .collect();
+    nearby.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+    nearby.truncate(NEAREST_SPOTS);
+    nearby
+}
+
+/// "team 1, team 3", or "none".
+fn team_list(teams: &[TeamSlot], relation: Relation) -> String {
+    let listed: Vec<String> = teams.iter().filter(|t| t.relation == relation).map(|t| format!("team {}", t.team)).collect();
+    if listed.is_empty() {
+        "none".into()
+    } else {
+        listed.join(", ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sai_protocol::TeamId;
+
+    fn init() -> SaiEvent {
+        SaiEvent::Init {
+            frame: 0,
+            saved_game: false,
+            protocol_version: None,
+            metal_spots: Some(vec![
+                MetalSpot { x: 3000.0, y: 0.0, z: 3000.0, metal: 2.0 },
+                MetalSpot { x: 1100.0, y: 0.0, z: 800.0, metal: 1.5 },
+                MetalSpot { x: 1000.0, y: 0.0, z: 1000.0, metal: 2.0 },
+            ]),
+            map_width: Some(512),
+            map_height: Some(256),
+            start_pos: Some([900.0, 0.0, 900.0]),
+            teams: vec![
+                TeamSlot { team: TeamId(0), ally_team: 0, relation: Relation::Mine },
+                TeamSlot { team: TeamId(1), ally_team: 1, relation: Relation::Enemy },
+                TeamSlot { team: TeamId(2), ally_team: 0, relation: Relation::Ally },
+                TeamSlot { team: TeamId(3), ally_team: 1, relation: Relation::Enemy },
+                TeamSlot { team: TeamId(4), ally_team: 2, relation: Relation::Gaia },
+            ],
+            build: None,
+        }
+    }
+
+    fn roster(units: &[(i32, &str)]) -> SaiEvent {
+        let units = units
+            .iter()
+            .map(|&(unit, name)| RosterUnit { unit: UnitId(unit), unit_name: Some(name.into()), pos: [1000.0, 10.0, 900.0] })
+            .collect();
+        SaiEvent::Roster { frame: 0, units }
+    }
+
+    #[test]
+    fn test_summary_waits_for_init_and_roster() {
+        let now = Instant::now();
+        let mut watch = StartWatch::default();
+        assert_eq!(watch.observe(&init(), now), None);
+        let mut start = watch.observe(&roster(&[(7, "factorycloak"), (5, "dyntrainer_strike")]), now).unwrap();
+        assert_eq!(start.commander, Some(Commander { unit: UnitId(5), def_name: Some("dyntrainer_strike".into()) }));
+        assert_eq!(start.start_pos, Some([1000.0, 10.0, 900.0]), "the commander stands on the start position");
+        assert_eq!(start.nearest_metal.iter().map(|s| s.x).collect::<Vec<_>>(), [1000.0, 1100.0, 3000.0]);
+
+        start.map = Some("Tundra".into());
+        assert_eq!(
+            start.text(),
+            "Game started on Tundra (4096x2048 elmos). You are team 0, starting at (1000, 900). \
+             Commander: dyntrainer_strike (#5), plus 1 other starting units. \
+             Allies: team 2. Enemies: team 1, team 3. \
+             Nearest metal spots: (1000, 1000) 100 away, (1100, 800) 141 away, (3000, 3000) 2900 away."
+        );
+
+        assert_eq!(watch.observe(&init(), now), None, "sent once");
+        assert_eq!(watch.poll(now + ROSTER_WAIT), None);
+    }
+
+    #[test]
+    fn test_summary_without_roster_after_timeout() {
+        let now = Instant::now();
+        let mut watch = StartWatch::default();
+        assert_eq!(watch.poll(now + ROSTER_WAIT), None, "nothing arrived yet");
+        watch.observe(&init(), now);
+        assert_eq!(watch.poll(now + ROSTER_WAIT / 2), None);
+        let start = watch.poll(now + ROSTER_WAIT).unwrap();
+        assert_eq!((start.commander.as_ref(), start.starting_units), (None, None));
+        assert_eq!(start.start_pos, Some([900.0, 0.0, 900.0]), "the engine's start position");
+        assert!(start.text().contains("You are team 0, starting at (900, 900). Your starting units are unknown"));
+
+        // A roster without init waits for it.
+        let mut watch = StartWatch::default();
+        assert_eq!(watch.observe(&roster(&[(5, "cloakcon")]), now), None);
+        assert_eq!(watch.poll(now + ROSTER_WAIT), None);
+        let start = watch.observe(&init(), now + ROSTER_WAIT).unwrap();
+        assert_eq!(start.commander.unwrap().unit, UnitId(5), "a lone unit is the commander");
+    }
+}
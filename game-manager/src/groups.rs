@@ -0,0 +1,214 @@
+//! Unit groups: named sets of unit ids per game channel, so the agent can
+//! order "raiders" instead of re-listing ids. Dead units drop out as their
+//! unit_destroyed events arrive, and a group can ask to be reported once
+//! deaths shrink it below a size.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::sai_ipc::{SaiCommand, SaiEvent, UnitId};
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Group {
+    pub members: BTreeSet<UnitId>,
+    /// Report when deaths take the group below this many units.
+    pub alert_below: Option<usize>,
+}
+
+impl Group {
+    fn to_json(&self, name: &str) -> serde_json::Value {
+        serde_json::json!({
+            "name": name,
+            "units": self.members,
+            "size": self.members.len(),
+            "alertBelow": self.alert_below,
+        })
+    }
+}
+
+/// A group that deaths took below its alert size.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShrinkAlert {
+    pub group: String,
+    pub size: usize,
+    pub alert_below: usize,
+    pub lost: UnitId,
+}
+
+impl ShrinkAlert {
+    pub fn text(&self) -> String {
+        format!(
+            "Group {} is down to {} units (below {}) after losing unit {}",
+            self.group, self.size, self.alert_below, self.lost
+        )
+    }
+}
+
+/// The groups of one game channel.
+#[derive(Debug, Default)]
+pub struct UnitGroups {
+    groups: BTreeMap<String, Group>,
+}
+
+impl UnitGroups {
+    /// Create `name`, replacing any group of that name.
+    pub fn create(&mut self, name: &str, units: &[UnitId], alert_below: Option<usize>) -> Result<&Group, String> {
+        if name.is_empty() {
+            return Err("Group name must not be empty".into());
+        }
+        let group = Group { members: units.iter().copied().collect(), alert_below };
+        self.groups.insert(name.to_string(), group);
+        Ok(&self.groups[name])
+    }
+
+    pub fn add(&mut self, name: &str, units: &[UnitId]) -> Result<&Group, String> {
+        let group = self.get_mut(name)?;
+        group.members.extend(units);
+        Ok(group)
+    }
+
+    /// Take `units` out of `name`; without units, disband the group.
+    pub fn remove(&mut self, name: &str, units: Option<&[UnitId]>) -> Result<Option<&Group>, String> {
+        let Some(units) = units else {
+            self.groups.remove(name).ok_or_else(|| unknown_group(name))?;
+            return Ok(None);
+        };
+        let group = self.get_mut(name)?;
+        for unit in units {
+            group.members.remove(unit);
+        }
+        Ok(Some(group))
+    }
+
+    pub fn list(&self) -> serde_json::Value {
+        self.groups.iter().map(|(name, group)| group.to_json(name)).collect()
+    }
+
+    /// Empty every group, keeping names and alert sizes.
+    pub fn forget_members(&mut self) {
+        for group in self.groups.values_mut() {
+            group.members.clear();
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Group> {
+        self.groups.get(name)
+    }
+
+    fn get_mut(&mut self, name: &str) -> Result<&mut Group, String> {
+        self.groups.get_mut(name).ok_or_else(|| unknown_group(name))
+    }
+
+    /// Drop dead and captured units; alerts for groups that just fell
+    /// below their size.
+    pub fn observe(&mut self, event: &SaiEvent) -> Vec<ShrinkAlert> {
+        let (SaiEvent::UnitDestroyed { unit, .. } | SaiEvent::UnitCaptured { unit, .. }) = event else {
+            return Vec::new();
+        };
+        let mut alerts = Vec::new();
+        for (name, group) in &mut self.groups {
+            let before = group.members.len();
+            if !group.members.remove(unit) {
+                continue;
+            }
+            let size = group.members.len();
+            if let Some(alert_below) = group.alert_below.filter(|n| size < *n && before >= *n) {
+                alerts.push(ShrinkAlert { group: name.clone(), size, alert_below, lost: *unit });
+            }
+        }
+        alerts
+    }
+
+    /// Expand a command addressed to `group` instead of `unit_id` into one
+    /// command per living member. Payloads without `group` parse as is.
+    pub fn expand(&self, mut payload: serde_json::Value) -> Result<Vec<SaiCommand>, String> {
+        let Some(name) = payload.as_object_mut().and_then(|o| o.remove("group")) else {
+            return serde_json::from_value(payload).map(|c| vec![c]).map_err(invalid);
+        };
+        let name = name.as_str().ok_or("group must be a string")?;
+        let group = self.get(name).ok_or_else(|| unknown_group(name))?;
+        if group.members.is_empty() {
+            return Err(format!("Group {} has no living units", name));
+        }
+        let mut commands = Vec::new();
+        for unit in &group.members {
+            payload["unit_id"] = unit.0.into();
+            let command: SaiCommand = serde_json::from_value(payload.clone()).map_err(invalid)?;
+            // Commands without a unit (chat, markers) can't go to a group.
+            if serde_json::to_value(&command).map_err(invalid)?.get("unit_id").is_none() {
+                return Err(format!("{} commands can't be addressed to a group", command.type_name()));
+            }
+            commands.push(command);
+        }
+        Ok(commands)
+    }
+}
+
+fn unknown_group(name: &str) -> String {
+    format!("Unknown group {}", name)
+}
+
+fn invalid(e: serde_json::Error) -> String {
+    format!("Invalid command JSON: {}", e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sai_protocol::WeaponDefId;
+
+    fn destroyed(unit: i32) -> SaiEvent {
+        SaiEvent::UnitDestroyed {
+            unit: UnitId(unit), unit_name: None, attacker: UnitId(90), attacker_name: None,
+            attacker_team: None, attacker_relation: None, weapon_def_id: WeaponDefId(1),
+        }
+    }
+
+    #[test]
+    fn test_groups_follow_deaths() {
+        let mut groups = UnitGroups::default();
+        groups.create("raiders", &[UnitId(11), UnitId(12), UnitId(13)], Some(3)).unwrap();
+        groups.add("raiders", &[UnitId(14)]).unwrap();
+        assert_eq!(groups.add("scouts", &[UnitId(1)]).unwrap_err(), "Unknown group scouts");
+
+        assert!(groups.observe(&destroyed(11)).is_empty(), "still at the alert size");
+        let alerts = groups.observe(&destroyed(12));
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].text(), "Group raiders is down to 2 units (below 3) after losing unit 12");
+        assert!(groups.observe(&destroyed(13)).is_empty(), "only the crossing is reported");
+        assert_eq!(groups.list()[0]["units"], serde_json::json!([14]));
+
+        groups.remove("raiders", Some(&[UnitId(14)])).unwrap();
+        assert_eq!(groups.get("raiders").unwrap().members.len(), 0);
+        groups.remove("raiders", None).unwrap();
+        assert_eq!(groups.list(), serde_json::json!([]));
+    }
+
+    #[test]
+    fn test_expand_group_command() {
+        let mut groups = UnitGroups::default();
+        groups.create("raiders", &[UnitId(12), UnitId(11)], None).unwrap();
+
+        let commands = groups
+            .expand(serde_json::json!({"type": "fight", "group": "raiders", "x": 100.0, "z": 200.0}))
+            .unwrap();
+        let units: Vec<UnitId> = commands
+            .iter()
+            .map(|c| match c {
+                SaiCommand::Fight { unit_id, x, .. } if *x == 100.0 => *unit_id,
+                other => panic!("unexpected {:?}", other),
+            })
+            .collect();
+        assert_eq!(units, [UnitId(11), UnitId(12)]);
+
+        let single = groups.expand(serde_json::json!({"type": "stop", "unit_id": 5})).unwrap();
+        assert_eq!(single, [SaiCommand::Stop { unit_id: UnitId(5) }]);
+        assert_eq!(
+            groups.expand(serde_json::json!({"type": "stop", "group": "tanks"})).unwrap_err(),
+            "Unknown group tanks"
+        );
+        assert_eq!(
+            groups.expand(serde_json::json!({"type": "send_chat", "group": "raiders", "text": "hi"})).unwrap_err(),
+            "send_chat commands can't be addressed to a group"
+        );
+    }
+}
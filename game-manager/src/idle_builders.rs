@@ -0,0 +1,402 @@
+//! Idle builder alerts: a constructor or factory that went idle and got no
+//! new orders within a grace period is wasted build power, and the agent
+//! should hear about it.
+//!
+//! Units are classified by def name, since events carry no builder flags.
+//! Timers run on game frames from `update` events, so a paused game never
+//! alerts.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::assist::Builder;
+use crate::sai_ipc::{SaiCommand, SaiEvent, UnitId};
+
+const FRAMES_PER_SECOND: f32 = 30.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuilderKind {
+    Constructor,
+    Factory,
+}
+
+impl BuilderKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BuilderKind::Constructor => "constructor",
+            BuilderKind::Factory => "factory",
+        }
+    }
+
+    /// Zero-K naming: `factory*` and `plate*` build units, `*con` and the
+    /// `dyn*` commanders build structures, as do a few specials.
+    pub fn classify(def_name: &str) -> Option<Self> {
+        if def_name.starts_with("factory") || def_name.starts_with("plate") || def_name == "striderhub" {
+            Some(BuilderKind::Factory)
+        } else if def_name.ends_with("con") || def_name.starts_with("dyn") || def_name == "athena" {
+            Some(BuilderKind::Constructor)
+        } else {
+            None
+        }
+    }
+}
+
+/// Per-channel settings, from `channels/open` `metadata.idle_builders`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IdleSettings {
+    pub enabled: bool,
+    /// Game seconds a builder must stay idle before it's reported.
+    pub grace_secs: f32,
+    /// Game seconds before the same unit is reported again.
+    pub cooldown_secs: f32,
+}
+
+impl Default for IdleSettings {
+    fn default() -> Self {
+        Self { enabled: true, grace_secs: 5.0, cooldown_secs: 60.0 }
+    }
+}
+
+impl IdleSettings {
+    /// Parse `metadata.idle_builders`: `false` turns alerts off, an object
+    /// overrides `grace_secs` and `cooldown_secs`, absent means the defaults.
+    pub fn from_metadata(metadata: Option<&serde_json::Value>) -> Result<Self, String> {
+        let mut settings = Self::default();
+        let config = match metadata.and_then(|m| m.get("idle_builders")) {
+            None => return Ok(settings),
+            Some(serde_json::Value::Bool(enabled)) => {
+                settings.enabled = *enabled;
+                return Ok(settings);
+            }
+            Some(v @ serde_json::Value::Object(_)) => v,
+            Some(_) => return Err("idle_builders must be a boolean or an object".into()),
+        };
+        let secs = |key: &str, value: &mut f32| -> Result<(), String> {
+            if let Some(v) = config.get(key) {
+                *value = v
+                    .as_f64()
+                    .map(|f| f as f32)
+                    .filter(|f| *f >= 0.0)
+                    .ok_or_else(|| format!("idle_builders.{} must be a non-negative number", key))?;
+            }
+            Ok(())
+        };
+        secs("grace_secs", &mut settings.grace_secs)?;
+        secs("cooldown_secs", &mut settings.cooldown_secs)?;
+        Ok(settings)
+    }
+}
+
+/// A builder that stayed idle through the grace period.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IdleAlert {
+    pub unit: UnitId,
+    pub unit_name: String,
+    pub kind: BuilderKind,
+    pub pos: Option<[f32; 3]>,
+    /// Frames since it went idle.
+    pub idle_frames: i32,
+}
+
+impl IdleAlert {
+    pub fn text(&self) -> String {
+        let kind = match self.kind {
+            BuilderKind::Constructor => "Constructor",
+            BuilderKind::Factory => "Factory",
+        };
+        let mut text = format!(
+            "{} {} (unit {}) idle for {:.0}s",
+            kind,
+            self.unit_name,
+            self.unit,
+            self.idle_frames as f32 / FRAMES_PER_SECOND
+        );
+        if let Some([x, _, z]) = self.pos {
+            text += &format!(" at ({:.0}, {:.0})", x, z);
+        }
+        text
+    }
+}
+
+/// Our builders, and which of them are idle, for one game channel.
+#[derive(Debug, Default)]
+pub struct IdleWatch {
+    pub settings: IdleSettings,
+    frame: i32,
+    /// Def name and kind of each of our builders.
+    builders: HashMap<UnitId, (String, BuilderKind)>,
+    /// Kinds from the channel's unit def catalog, when it was fetched;
+    /// defs missing here are classified by name.
+    kinds: HashMap<String, Option<BuilderKind>>,
+    positions: HashMap<UnitId, [f32; 3]>,
+    /// Frame each idle builder went idle at.
+    idle_since: HashMap<UnitId, i32>,
+    /// Idle builders already reported this idle spell.
+    reported: HashSet<UnitId>,
+    last_alert: HashMap<UnitId, i32>,
+}
+
+impl IdleWatch {
+    pub fn new(settings: IdleSettings) -> Self {
+        Self { settings, ..Default::default() }
+    }
+
+    /// Track an event; updates return the builders whose grace ran out.
+    /// Builders are tracked with alerts off too, for `game_assist`.
+    pub fn observe(&mut self, event: &SaiEvent) -> Vec<IdleAlert> {
+        match event {
+            SaiEvent::Update { frame, counters, .. } => {
+                self.frame = *frame;
+                // Aggregated command_finished events: those units got busy.
+                for (unit, counts) in counters {
+                    if counts.contains_key("command_finished") {
+                        if let Ok(unit) = unit.parse().map(UnitId) {
+                            self.busy(unit);
+                        }
+                    }
+                }
+                if self.settings.enabled {
+                    return self.due();
+                }
+            }
+            SaiEvent::Roster { frame, units } => {
+                self.frame = *frame;
+                for unit in units {
+                    self.track(unit.unit, &unit.unit_name, Some(unit.pos));
+                }
+            }
+            SaiEvent::UnitFinished { unit, unit_name, pos } | SaiEvent::UnitGiven { unit, unit_name, pos, .. } => {
+                self.track(*unit, unit_name, *pos)
+            }
+            SaiEvent::UnitCreated { builder, .. } => {
+                // Started on something: not idle after all.
+                self.busy(*builder);
+            }
+            SaiEvent::UnitDamaged { unit, pos: Some(pos), .. } if self.builders.contains_key(unit) => {
+                self.positions.insert(*unit, *pos);
+            }
+            SaiEvent::UnitIdle { unit, unit_name } => {
+                self.track(*unit, unit_name, None);
+                if self.builders.contains_key(unit) {
+                    self.idle_since.insert(*unit, self.frame);
+                    self.reported.remove(unit);
+                }
+            }
+            SaiEvent::CommandFinished { unit, .. } => {
+                self.busy(*unit);
+            }
+            SaiEvent::UnitDestroyed { unit, .. } | SaiEvent::UnitCaptured { unit, .. } => {
+                self.builders.remove(unit);
+                self.positions.remove(unit);
+                self.busy(*unit);
+                self.last_alert.remove(unit);
+            }
+            _ => {}
+        }
+        Vec::new()
+    }
+
+    /// Classify builders by the unit def catalog from now on.
+    pub fn learn_kinds(&mut self, kinds: HashMap<String, Option<BuilderKind>>) {
+        self.kinds = kinds;
+    }
+
+    /// A command went out: the unit it addresses has orders again.
+    pub fn ordered(&mut self, command: &SaiCommand) {
+        let unit = serde_json::to_value(command)
+            .ok()
+            .and_then(|v| v.get("unit_id").and_then(|u| u.as_i64()));
+        if let Some(unit) = unit {
+            self.busy(UnitId(unit as i32));
+        }
+    }
+
+    /// Whether `unit` is one of our builders and idle, as far as events
+    /// and commands sent since tell.
+    pub fn is_idle(&self, unit: UnitId) -> bool {
+        self.idle_since.contains_key(&unit)
+    }
+
+    /// Our builders and whether each is idle.
+    pub fn builders(&self) -> Vec<Builder> {
+        self.builders
+            .iter()
+            .map(|(&unit, (_, kind))| Builder {
+                unit,
+                kind: *kind,
+                pos: self.positions.get(&unit).copied(),
+                idle: self.is_idle(unit),
+            })
+            .collect()
+    }
+
+    fn busy(&mut self, unit: UnitId) {
+        self.idle_since.remove(&unit);
+        self.reported.remove(&unit);
+    }
+
+    fn track(&mut self, unit: UnitId, name: &Option<String>, pos: Option<[f32; 3]>) {
+        let Some(name) = name else { return };
+        let kind = self.kinds.get(name).copied().unwrap_or_else(|| BuilderKind::classify(name));
+        let Some(kind) = kind else { return };
+        self.builders.insert(unit, (name.clone(), kind));
+        if let Some(pos) = pos {
+            self.positions.insert(unit, pos);
+        }
+    }
+
+    /// Idle builders past their grace period and out of their cooldown.
+    fn due(&mut self) -> Vec<IdleAlert> {
+        let grace = (self.settings.grace_secs * FRAMES_PER_SECOND) as i32;
+        let cooldown = (self.settings.cooldown_secs * FRAMES_PER_SECOND) as i32;
+        let frame = self.frame;
+        let mut due: Vec<UnitId> = self
+            .idle_since
+            .iter()
+            .filter(|(unit, since)| {
+                !self.reported.contains(unit)
+                    && frame - **since >= grace
+                    && self.last_alert.get(unit).is_none_or(|last| frame - last >= cooldown)
+            })
+            .map(|(unit, _)| *unit)
+            .collect();
+        due.sort_unstable();
+        due.into_iter()
+            .filter_map(|unit| {
+                let since = *self.idle_since.get(&unit)?;
+                let (unit_name, kind) = self.builders.get(&unit)?.clone();
+                self.reported.insert(unit);
+                self.last_alert.insert(unit, frame);
+                Some(IdleAlert { unit, unit_name, kind, pos: self.positions.get(&unit).copied(), idle_frames: frame - since })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sai_protocol::{RosterUnit, UnitDefId, WeaponDefId};
+
+    fn update(frame: i32) -> SaiEvent {
+        SaiEvent::Update { frame, awaiting_commands: false, economy: None, allies: Vec::new(), counters: Default::default(), command_backlog: 0, under_construction: Vec::new(), update_interval: None }
+    }
+
+    fn idle(unit: i32) -> SaiEvent {
+        SaiEvent::UnitIdle { unit: UnitId(unit), unit_name: None }
+    }
+
+    /// A constructor (#5), a factory (#6) and a raider (#7) at frame 30.
+    fn watch() -> IdleWatch {
+        let mut watch = IdleWatch::default();
+        let unit = |unit, name: &str| RosterUnit { unit, unit_name: Some(name.into()), pos: [100.0, 10.0, 200.0] };
+        watch.observe(&SaiEvent::Roster {
+            frame: 30,
+            units: vec![unit(UnitId(5), "cloakcon"), unit(UnitId(6), "factorycloak"), unit(UnitId(7), "cloakraid")],
+        });
+        watch
+    }
+
+    #[test]
+    fn test_classify() {
+        assert_eq!(BuilderKind::classify("shieldcon"), Some(BuilderKind::Constructor));
+        assert_eq!(BuilderKind::classify("dyntrainer_strike_base"), Some(BuilderKind::Constructor));
+        assert_eq!(BuilderKind::classify("platehover"), Some(BuilderKind::Factory));
+        assert_eq!(BuilderKind::classify("factoryjump"), Some(BuilderKind::Factory));
+        assert_eq!(BuilderKind::classify("cloakraid"), None);
+
+        // The unit def catalog wins over the naming conventions.
+        let mut watch = IdleWatch::default();
+        watch.learn_kinds(HashMap::from([
+            ("cloakcon".to_string(), None),
+            ("turretcon".to_string(), Some(BuilderKind::Constructor)),
+        ]));
+        let unit = |unit, name: &str| RosterUnit { unit, unit_name: Some(name.into()), pos: [0.0; 3] };
+        watch.observe(&SaiEvent::Roster {
+            frame: 0,
+            units: vec![unit(UnitId(5), "cloakcon"), unit(UnitId(6), "turretcon"), unit(UnitId(7), "factorycloak")],
+        });
+        let mut tracked: Vec<UnitId> = watch.builders.keys().copied().collect();
+        tracked.sort_unstable();
+        assert_eq!(tracked, [UnitId(6), UnitId(7)]);
+    }
+
+    #[test]
+    fn test_idle_past_grace_alerts_once() {
+        let mut watch = watch();
+        for unit in [5, 6, 7] {
+            watch.observe(&idle(unit));
+        }
+        assert!(watch.observe(&update(120)).is_empty(), "still in the grace period");
+        let alerts = watch.observe(&update(180));
+        assert_eq!(alerts.iter().map(|a| a.unit).collect::<Vec<_>>(), [UnitId(5), UnitId(6)]);
+        assert_eq!(alerts[0].text(), "Constructor cloakcon (unit 5) idle for 5s at (100, 200)");
+        assert_eq!(alerts[1].kind, BuilderKind::Factory);
+        assert!(watch.observe(&update(300)).is_empty(), "reported once per idle spell");
+        // Reported, but still idle for game_assist.
+        assert!(watch.builders().iter().any(|b| b.unit == UnitId(5) && b.idle));
+
+        // Idle again within the cooldown: held back until it ends.
+        watch.observe(&idle(5));
+        assert!(watch.observe(&update(600)).is_empty());
+        assert_eq!(watch.observe(&update(1980))[0].idle_frames, 1680);
+    }
+
+    #[test]
+    fn test_orders_and_deaths_clear_timers() {
+        let mut watch = watch();
+        watch.observe(&update(60));
+        for unit in [5, 6] {
+            watch.observe(&idle(unit));
+        }
+        // The factory got a build order, the constructor started building.
+        watch.ordered(&SaiCommand::Build {
+            unit_id: UnitId(6),
+            build_def_id: UnitDefId(0),
+            build_def_name: Some("cloakraid".into()),
+            x: 0.0,
+            y: 0.0,
+            z: 0.0,
+            facing: 0,
+            queue: false,
+        });
+        watch.observe(&SaiEvent::UnitCreated {
+            unit: UnitId(40), unit_name: Some("staticmex".into()), builder: UnitId(5), builder_name: None, pos: None,
+        });
+        assert!(watch.observe(&update(600)).is_empty());
+
+        // A command_finished counted into an update clears the timer too.
+        watch.observe(&idle(6));
+        let counters = [("6".to_string(), [("command_finished".to_string(), 1)].into())].into();
+        watch.observe(&SaiEvent::Update { frame: 630, awaiting_commands: false, economy: None, allies: Vec::new(), counters, command_backlog: 0, under_construction: Vec::new(), update_interval: None });
+        assert!(!watch.idle_since.contains_key(&UnitId(6)));
+
+        watch.observe(&idle(5));
+        watch.observe(&SaiEvent::UnitDestroyed {
+            unit: UnitId(5), unit_name: None, attacker: UnitId(0), attacker_name: None,
+            attacker_team: None, attacker_relation: None, weapon_def_id: WeaponDefId(0),
+        });
+        assert!(watch.observe(&update(1200)).is_empty());
+        assert!(watch.idle_since.is_empty() && !watch.builders.contains_key(&UnitId(5)));
+    }
+
+    #[test]
+    fn test_settings_from_metadata() {
+        let parse = |v| IdleSettings::from_metadata(Some(&serde_json::json!({ "idle_builders": v })));
+        assert!(!parse(serde_json::json!(false)).unwrap().enabled);
+        let custom = parse(serde_json::json!({"grace_secs": 10})).unwrap();
+        assert_eq!((custom.grace_secs, custom.cooldown_secs), (10.0, 60.0));
+        assert_eq!(
+            parse(serde_json::json!({"cooldown_secs": -1})).unwrap_err(),
+            "idle_builders.cooldown_secs must be a non-negative number"
+        );
+        assert_eq!(IdleSettings::from_metadata(None).unwrap(), IdleSettings::default());
+
+        let mut off = IdleWatch::new(IdleSettings { enabled: false, ..Default::default() });
+        off.observe(&SaiEvent::Roster {
+            frame: 0,
+            units: vec![RosterUnit { unit: UnitId(5), unit_name: Some("cloakcon".into()), pos: [0.0; 3] }],
+        });
+        off.observe(&idle(5));
+        assert!(off.observe(&update(9000)).is_empty());
+    }
+}
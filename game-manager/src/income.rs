@@ -0,0 +1,372 @@
+//! Income attribution: what each of our economy structures earns, so a
+//! lost one can be reported with the income it took along ("staticmex at
+//! (3400, 210) destroyed: -2.1 metal/s").
+//!
+//! Every own unit is kept with its def and position, from the roster and
+//! unit events; units given to us count from then on, captured ones are
+//! lost like destroyed ones. A unit's income comes from the channel's unit
+//! def cache when it's there: what the def makes, plus the metal of the
+//! spot under it for extractors. Without the cache only mexes are known,
+//! by name. Estimates are set against the income the snapshots report.
+
+use std::collections::{HashMap, VecDeque};
+
+use serde::Serialize;
+
+use crate::economy_alerts::{AlertKind, EconomyAlert};
+use crate::expansion::{spot_under, MEX_DEF_NAME};
+use crate::sai_ipc::{SaiEvent, UnitId};
+use crate::unit_defs::UnitDefCatalog;
+use sai_protocol::MetalSpot;
+
+/// Losses kept for economy alerts to cite.
+const RECENT_LOSSES: usize = 20;
+
+/// Income below this (per second) isn't worth a line.
+const MIN_INCOME: f32 = 0.05;
+
+/// An own unit, as last reported.
+#[derive(Debug, Clone)]
+struct Owned {
+    def: String,
+    pos: Option<[f32; 3]>,
+}
+
+/// An economy structure we lost, and its estimated income.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IncomeLoss {
+    pub unit: UnitId,
+    pub def: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pos: Option<[f32; 3]>,
+    pub frame: i32,
+    pub metal: f32,
+    pub energy: f32,
+    /// Taken by another team rather than destroyed.
+    pub captured: bool,
+    /// Share of the metal and energy income last reported.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub share: Option<[f32; 2]>,
+}
+
+impl IncomeLoss {
+    pub fn text(&self) -> String {
+        let mut amounts = Vec::new();
+        if self.metal >= MIN_INCOME {
+            amounts.push(format!("-{:.1} metal/s", self.metal));
+        }
+        if self.energy >= MIN_INCOME {
+            amounts.push(format!("-{:.1} energy/s", self.energy));
+        }
+        let place = match self.pos {
+            Some(p) => format!(" at ({:.0}, {:.0})", p[0], p[2]),
+            None => String::new(),
+        };
+        let share = match self.share {
+            Some([metal, _]) if self.metal >= MIN_INCOME => format!(" ({:.0}% of metal income)", metal * 100.0),
+            Some([_, energy]) => format!(" ({:.0}% of energy income)", energy * 100.0),
+            None => String::new(),
+        };
+        format!(
+            "{}{} {}: {} income{}",
+            self.def,
+            place,
+            if self.captured { "captured" } else { "destroyed" },
+            amounts.join(", "),
+            share
+        )
+    }
+}
+
+/// The registry's estimate next to the snapshot's figures.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Reconciliation {
+    pub estimated_metal: f32,
+    pub estimated_energy: f32,
+    pub reported_metal: f32,
+    pub reported_energy: f32,
+}
+
+/// One channel's own units, metal spots and recent losses.
+#[derive(Debug, Default)]
+pub struct IncomeRegistry {
+    spots: Vec<MetalSpot>,
+    units: HashMap<UnitId, Owned>,
+    frame: i32,
+    /// Metal and energy income from the last snapshot.
+    reported: Option<[f32; 2]>,
+    recent: VecDeque<IncomeLoss>,
+}
+
+impl IncomeRegistry {
+    /// Take in an event; returns the income lost if an economy structure
+    /// died or was captured.
+    pub fn observe(&mut self, event: &SaiEvent, catalog: Option<&UnitDefCatalog>) -> Option<IncomeLoss> {
+        match event {
+            SaiEvent::Init { metal_spots: Some(spots), .. } => self.spots = spots.clone(),
+            SaiEvent::Update { frame, economy, .. } => {
+                self.frame = *frame;
+                if let Some(e) = economy {
+                    self.reported = Some([e.metal.income, e.energy.income]);
+                }
+            }
+            SaiEvent::Roster { frame, units } => {
+                self.frame = *frame;
+                self.units = units
+                    .iter()
+                    .map(|u| (u.unit, Owned { def: def_name(&u.unit_name), pos: Some(u.pos) }))
+                    .collect();
+            }
+            SaiEvent::UnitFinished { unit, unit_name, pos } => {
+                self.units.insert(*unit, Owned { def: def_name(unit_name), pos: *pos });
+            }
+            SaiEvent::UnitGiven { unit, unit_name, pos, .. } => {
+                self.units.insert(*unit, Owned { def: def_name(unit_name), pos: *pos });
+            }
+            SaiEvent::UnitDestroyed { unit, .. } => return self.lose(*unit, false, catalog),
+            SaiEvent::UnitCaptured { unit, .. } => return self.lose(*unit, true, catalog),
+            _ => {}
+        }
+        None
+    }
+
+    fn lose(&mut self, unit: UnitId, captured: bool, catalog: Option<&UnitDefCatalog>) -> Option<IncomeLoss> {
+        let owned = self.units.remove(&unit)?;
+        let [metal, energy] = self.income_of(&owned, catalog);
+        if metal < MIN_INCOME && energy < MIN_INCOME {
+            return None;
+        }
+        let share = self.reported.map(|[m, e]| [ratio(metal, m), ratio(energy, e)]);
+        let loss = IncomeLoss { unit, def: owned.def, pos: owned.pos, frame: self.frame, metal, energy, captured, share };
+        self.recent.push_back(loss.clone());
+        while self.recent.len() > RECENT_LOSSES {
+            self.recent.pop_front();
+        }
+        Some(loss)
+    }
+
+    /// Estimated metal and energy per second of one unit.
+    fn income_of(&self, owned: &Owned, catalog: Option<&UnitDefCatalog>) -> [f32; 2] {
+        let spot_metal = || {
+            owned
+                .pos
+                .and_then(|pos| spot_under(&self.spots, pos))
+                .map_or(0.0, |i| self.spots[i].metal)
+        };
+        match catalog.and_then(|c| c.named(&owned.def)) {
+            Some(def) => {
+                let extracted = if def.extracts_metal > 0.0 { spot_metal() } else { 0.0 };
+                [def.metal_make + extracted, def.energy_make]
+            }
+            None if owned.def == MEX_DEF_NAME => [spot_metal(), 0.0],
+            None => [0.0, 0.0],
+        }
+    }
+
+    /// What our structures should earn, next to what the last snapshot
+    /// says we do. None before the first snapshot.
+    pub fn reconcile(&self, catalog: Option<&UnitDefCatalog>) -> Option<Reconciliation> {
+        let [reported_metal, reported_energy] = self.reported?;
+        let (estimated_metal, estimated_energy) = self
+            .units
+            .values()
+            .map(|u| self.income_of(u, catalog))
+            .fold((0.0, 0.0), |(m, e), [um, ue]| (m + um, e + ue));
+        Some(Reconciliation { estimated_metal, estimated_energy, reported_metal, reported_energy })
+    }
+
+    /// Losses at or after `frame`, oldest first.
+    pub fn losses_since(&self, frame: i32) -> impl Iterator<Item = &IncomeLoss> {
+        self.recent.iter().filter(move |l| l.frame >= frame)
+    }
+
+    /// Name the structures behind an income drop alert, if we lost any
+    /// since its peak.
+    pub fn annotate(&self, alert: &mut EconomyAlert, catalog: Option<&UnitDefCatalog>) {
+        if alert.kind != AlertKind::IncomeDrop {
+            return;
+        }
+        let peak_frame = alert.details["peakFrame"].as_i64().unwrap_or(0) as i32;
+        let losses: Vec<&IncomeLoss> = self.losses_since(peak_frame).collect();
+        if !losses.is_empty() {
+            let metal: f32 = losses.iter().map(|l| l.metal).sum();
+            let names: Vec<String> = losses
+                .iter()
+                .filter(|l| l.metal >= MIN_INCOME)
+                .map(|l| match l.pos {
+                    Some(p) => format!("{} at ({:.0}, {:.0})", l.def, p[0], p[2]),
+                    None => l.def.clone(),
+                })
+                .collect();
+            if !names.is_empty() {
+                alert.text = alert.text.replace(
+                    " (lost extractors?)",
+                    &format!("; lost {} for -{:.1} metal/s", names.join(", "), metal),
+                );
+            }
+            alert.details["losses"] = serde_json::to_value(&losses).unwrap();
+        }
+        if let Some(reconciliation) = self.reconcile(catalog) {
+            alert.details["reconciliation"] = serde_json::to_value(reconciliation).unwrap();
+        }
+    }
+}
+
+fn def_name(name: &Option<String>) -> String {
+    name.clone().unwrap_or_else(|| "unknown".into())
+}
+
+fn ratio(part: f32, whole: f32) -> f32 {
+    if whole > 0.0 {
+        (part / whole).min(1.0)
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sai_ipc::{UnitDefId, UnitDefInfo};
+    use sai_protocol::{Economy, ResourceState, RosterUnit, TeamId, WeaponDefId};
+
+    fn init() -> SaiEvent {
+        SaiEvent::Init {
+            frame: 0, saved_game: false, protocol_version: None,
+            metal_spots: Some(vec![
+                MetalSpot { x: 3400.0, y: 0.0, z: 210.0, metal: 2.1 },
+                MetalSpot { x: 1000.0, y: 0.0, z: 1000.0, metal: 1.5 },
+            ]),
+            map_width: None, map_height: None, start_pos: None, teams: Vec::new(), build: None,
+        }
+    }
+
+    fn update(frame: i32, metal_income: f32, energy_income: f32) -> SaiEvent {
+        let resource = |income| ResourceState { current: 100.0, income, usage: 0.0, storage: 1000.0, received: 0.0 };
+        SaiEvent::Update {
+            frame,
+            awaiting_commands: false,
+            economy: Some(Economy { metal: resource(metal_income), energy: resource(energy_income) }),
+            allies: Vec::new(),
+            counters: Default::default(),
+            command_backlog: 0,
+            under_construction: Vec::new(),
+            update_interval: None,
+        }
+    }
+
+    fn finished(id: i32, name: &str, pos: [f32; 3]) -> SaiEvent {
+        SaiEvent::UnitFinished { unit: UnitId(id), unit_name: Some(name.into()), pos: Some(pos) }
+    }
+
+    fn destroyed(id: i32) -> SaiEvent {
+        SaiEvent::UnitDestroyed {
+            unit: UnitId(id), unit_name: None, attacker: UnitId(90), attacker_name: None,
+            attacker_team: None, attacker_relation: None, weapon_def_id: WeaponDefId(1),
+        }
+    }
+
+    fn catalog() -> UnitDefCatalog {
+        let def = |id, name: &str, metal_make, energy_make, extracts_metal| UnitDefInfo {
+            id: UnitDefId(id),
+            name: name.into(),
+            human_name: name.into(),
+            description: None,
+            metal_cost: 50.0,
+            energy_cost: 0.0,
+            build_time: 50.0,
+            health: 500.0,
+            speed: 0.0,
+            builder: false,
+            build_options: Vec::new(),
+            metal_make,
+            energy_make,
+            extracts_metal,
+            build_speed: 0.0,
+        };
+        UnitDefCatalog::new(vec![
+            def(1, "staticmex", 0.0, 0.0, 0.001),
+            def(2, "energysolar", 0.0, 2.0, 0.0),
+            def(3, "turretlaser", 0.0, 0.0, 0.0),
+        ])
+    }
+
+    #[test]
+    fn test_destroyed_mex_without_unit_defs() {
+        let mut registry = IncomeRegistry::default();
+        registry.observe(&init(), None);
+        registry.observe(&finished(10, "staticmex", [3410.0, 0.0, 200.0]), None);
+        registry.observe(&finished(11, "energysolar", [500.0, 0.0, 500.0]), None);
+        registry.observe(&update(900, 10.5, 20.0), None);
+
+        let loss = registry.observe(&destroyed(10), None).unwrap();
+        assert_eq!((loss.metal, loss.frame), (2.1, 900));
+        assert_eq!(loss.text(), "staticmex at (3410, 200) destroyed: -2.1 metal/s income (20% of metal income)");
+        assert_eq!(registry.observe(&destroyed(11), None), None, "solar income is unknown without unit defs");
+        assert_eq!(registry.observe(&destroyed(10), None), None, "already gone");
+    }
+
+    #[test]
+    fn test_def_based_income_and_transfers() {
+        let catalog = catalog();
+        let mut registry = IncomeRegistry::default();
+        registry.observe(&init(), Some(&catalog));
+        registry.observe(&SaiEvent::Roster {
+            frame: 1,
+            units: vec![
+                RosterUnit { unit: UnitId(1), unit_name: Some("staticmex".into()), pos: [1000.0, 0.0, 1040.0] },
+                RosterUnit { unit: UnitId(2), unit_name: Some("turretlaser".into()), pos: [0.0; 3] },
+            ],
+        }, Some(&catalog));
+        // A solar given to us earns for us; one captured from us is lost.
+        let given = SaiEvent::UnitGiven {
+            unit: UnitId(3), unit_name: Some("energysolar".into()), old_team: TeamId(1), new_team: TeamId(0),
+            old_relation: None, pos: None,
+        };
+        registry.observe(&given, Some(&catalog));
+        registry.observe(&update(300, 4.0, 5.0), Some(&catalog));
+        assert_eq!(
+            registry.reconcile(Some(&catalog)),
+            Some(Reconciliation { estimated_metal: 1.5, estimated_energy: 2.0, reported_metal: 4.0, reported_energy: 5.0 })
+        );
+
+        let captured = SaiEvent::UnitCaptured {
+            unit: UnitId(3), unit_name: Some("energysolar".into()), old_team: TeamId(0), new_team: TeamId(1),
+            new_relation: None,
+        };
+        let loss = registry.observe(&captured, Some(&catalog)).unwrap();
+        assert!(loss.captured && loss.energy == 2.0 && loss.metal == 0.0);
+        assert_eq!(loss.text(), "energysolar captured: -2.0 energy/s income (40% of energy income)");
+        assert_eq!(registry.observe(&destroyed(2), Some(&catalog)), None, "turrets earn nothing");
+        // A mex off every spot extracts nothing worth reporting.
+        registry.observe(&finished(4, "staticmex", [5000.0, 0.0, 5000.0]), Some(&catalog));
+        assert_eq!(registry.observe(&destroyed(4), Some(&catalog)), None);
+        assert_eq!(registry.observe(&destroyed(1), Some(&catalog)).unwrap().metal, 1.5);
+        assert_eq!(registry.reconcile(Some(&catalog)).unwrap().estimated_metal, 0.0);
+    }
+
+    #[test]
+    fn test_income_drop_alert_names_losses() {
+        let mut registry = IncomeRegistry::default();
+        registry.observe(&init(), None);
+        registry.observe(&finished(10, "staticmex", [3400.0, 0.0, 210.0]), None);
+        registry.observe(&finished(11, "staticmex", [1000.0, 0.0, 1000.0]), None);
+        registry.observe(&update(600, 5.0, 0.0), None);
+        registry.observe(&destroyed(11), None);
+        registry.observe(&update(900, 5.0, 0.0), None);
+        registry.observe(&destroyed(10), None);
+
+        let mut alert = EconomyAlert {
+            kind: AlertKind::IncomeDrop,
+            frame: 930,
+            text: "Metal income dropped from 5.0/s to 1.4/s in 10s (lost extractors?)".into(),
+            details: serde_json::json!({"peakFrame": 900}),
+        };
+        registry.annotate(&mut alert, None);
+        assert_eq!(alert.text, "Metal income dropped from 5.0/s to 1.4/s in 10s; lost staticmex at (3400, 210) for -2.1 metal/s");
+        assert_eq!(alert.details["losses"].as_array().unwrap().len(), 1, "the earlier loss predates the peak");
+        assert_eq!(alert.details["reconciliation"]["reportedMetal"], 5.0);
+    }
+}
@@ -0,0 +1,124 @@
+//! Lobby chat as MCPL channels: each joined lobby room is `lobby:#<room>`,
+//! each DM conversation `lobby:@<user>` and the battle we're in
+//! `lobby:battle:<id>`, so chat arrives through channels/incoming next to
+//! the game channels instead of as push events.
+
+use mcpl_core::types::{ChannelDescriptor, ChannelDirection};
+
+use super::protocol::{SayCommand, PLACE_BATTLE, PLACE_CHANNEL, PLACE_USER};
+
+/// Prefix of every lobby chat channel id, keeping them apart from `game:`.
+pub const CHANNEL_PREFIX: &str = "lobby:";
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ChatChannel {
+    /// A lobby room such as `zk`.
+    Room(String),
+    /// Direct messages with one user.
+    Direct(String),
+    /// The battle room we're in: its chat, roster and lifecycle.
+    Battle(i64),
+}
+
+impl ChatChannel {
+    /// Parse a channel id; None for ids outside the lobby namespace.
+    pub fn parse(channel_id: &str) -> Option<Self> {
+        let rest = channel_id.strip_prefix(CHANNEL_PREFIX)?;
+        if let Some(room) = rest.strip_prefix('#').filter(|r| !r.is_empty()) {
+            Some(Self::Room(room.to_string()))
+        } else if let Some(id) = rest.strip_prefix("battle:") {
+            id.parse().ok().map(Self::Battle)
+        } else {
+            rest.strip_prefix('@').filter(|u| !u.is_empty()).map(|u| Self::Direct(u.to_string()))
+        }
+    }
+
+    /// The conversation a Say belongs to, seen from `me` in `battle`.
+    /// Server messages, and battle chat outside a battle, give None.
+    pub fn of_said(place: i32, user: &str, target: &str, me: &str, battle: Option<i64>) -> Option<Self> {
+        match place {
+            PLACE_CHANNEL => Some(Self::Room(target.to_string())),
+            PLACE_BATTLE => battle.map(Self::Battle),
+            PLACE_USER if user == me => Some(Self::Direct(target.to_string())),
+            PLACE_USER => Some(Self::Direct(user.to_string())),
+            _ => None,
+        }
+    }
+
+    pub fn id(&self) -> String {
+        match self {
+            Self::Room(room) => format!("{}#{}", CHANNEL_PREFIX, room),
+            Self::Direct(user) => format!("{}@{}", CHANNEL_PREFIX, user),
+            Self::Battle(id) => format!("{}battle:{}", CHANNEL_PREFIX, id),
+        }
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            Self::Room(room) => format!("#{}", room),
+            Self::Direct(user) => format!("DM with {}", user),
+            Self::Battle(id) => format!("Battle {}", id),
+        }
+    }
+
+    /// The Say that sends `text` here.
+    pub fn say(&self, text: &str) -> SayCommand {
+        let (place, target) = match self {
+            Self::Room(room) => (PLACE_CHANNEL, room.clone()),
+            Self::Direct(user) => (PLACE_USER, user.clone()),
+            Self::Battle(_) => (PLACE_BATTLE, String::new()),
+        };
+        SayCommand { place, target, text: text.to_string(), is_emote: false }
+    }
+
+    pub fn descriptor(&self, metadata: Option<serde_json::Value>) -> ChannelDescriptor {
+        let address = match self {
+            Self::Room(room) => serde_json::json!({ "room": room }),
+            Self::Direct(user) => serde_json::json!({ "user": user }),
+            Self::Battle(id) => serde_json::json!({ "battleId": id }),
+        };
+        ChannelDescriptor {
+            id: self.id(),
+            channel_type: "lobby".into(),
+            label: self.label(),
+            direction: ChannelDirection::Bidirectional,
+            address: Some(address),
+            metadata,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chat_channel_ids() {
+        let room = ChatChannel::Room("zk".into());
+        assert_eq!(room.id(), "lobby:#zk");
+        assert_eq!(ChatChannel::parse("lobby:#zk"), Some(room.clone()));
+        assert_eq!(ChatChannel::parse("lobby:@Godde"), Some(ChatChannel::Direct("Godde".into())));
+        assert_eq!(ChatChannel::parse("game:local-1"), None);
+        assert_eq!(ChatChannel::parse("lobby:#"), None);
+        assert_eq!(ChatChannel::parse("lobby:zk"), None);
+
+        assert_eq!(ChatChannel::of_said(PLACE_CHANNEL, "Godde", "zk", "agent", None), Some(room));
+        // Our own DMs echo back with the other user as target.
+        let dm = ChatChannel::Direct("Godde".into());
+        assert_eq!(ChatChannel::of_said(PLACE_USER, "Godde", "agent", "agent", None), Some(dm.clone()));
+        assert_eq!(ChatChannel::of_said(PLACE_USER, "agent", "Godde", "agent", None), Some(dm.clone()));
+        assert_eq!(ChatChannel::of_said(PLACE_BATTLE, "Godde", "", "agent", None), None);
+        assert_eq!(ChatChannel::of_said(3, "Godde", "", "agent", Some(38219)), None);
+
+        let battle = ChatChannel::Battle(38219);
+        assert_eq!(battle.id(), "lobby:battle:38219");
+        assert_eq!(ChatChannel::parse("lobby:battle:38219"), Some(battle.clone()));
+        assert_eq!(ChatChannel::parse("lobby:battle:x"), None);
+        assert_eq!(ChatChannel::of_said(PLACE_BATTLE, "Godde", "", "agent", Some(38219)), Some(battle.clone()));
+        let say = battle.say("!start");
+        assert_eq!((say.place, say.target.as_str()), (PLACE_BATTLE, ""));
+
+        let say = dm.say("hi");
+        assert_eq!((say.place, say.target.as_str()), (PLACE_USER, "Godde"));
+    }
+}
@@ -1,8 +1,14 @@
+use std::io::Write;
+use std::path::Path;
+
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 use tokio::net::TcpStream;
 
 use super::protocol::LobbyMessage;
 
+/// Environment variable naming a file to capture lobby traffic to.
+pub const CAPTURE_ENV: &str = "LOBBY_CAPTURE";
+
 #[derive(Debug, thiserror::Error)]
 pub enum LobbyError {
     #[error("IO error: {0}")]
@@ -17,6 +23,10 @@ pub enum LobbyError {
 pub struct LobbyConnection {
     writer: tokio::io::WriteHalf<TcpStream>,
     reader: BufReader<tokio::io::ReadHalf<TcpStream>>,
+    /// When the server last sent anything.
+    last_received: Option<chrono::DateTime<chrono::Utc>>,
+    /// Where traffic is copied, for refreshing the protocol fixtures.
+    capture: Option<std::fs::File>,
 }
 
 impl LobbyConnection {
@@ -26,16 +36,43 @@ impl LobbyConnection {
         tracing::info!("Connecting to lobby server at {}", addr);
         let stream = TcpStream::connect(&addr).await?;
         let (reader, writer) = tokio::io::split(stream);
-        Ok(Self {
+        let mut conn = Self {
             writer,
             reader: BufReader::new(reader),
-        })
+            last_received: None,
+            capture: None,
+        };
+        if let Some(path) = std::env::var_os(CAPTURE_ENV) {
+            if let Err(e) = conn.capture(Path::new(&path)) {
+                tracing::warn!("Can't capture lobby traffic to {}: {}", Path::new(&path).display(), e);
+            }
+        }
+        Ok(conn)
+    }
+
+    /// Append every message from now on to `path`, one per line: `> ` and
+    /// the message for what we send, `< ` for what the server sends.
+    /// Passwords, tokens and other credentials are masked as in the logs.
+    pub fn capture(&mut self, path: &Path) -> std::io::Result<()> {
+        let file = std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+        tracing::info!("Capturing lobby traffic to {}", path.display());
+        self.capture = Some(file);
+        Ok(())
+    }
+
+    fn tee(&mut self, direction: char, msg: &LobbyMessage) {
+        let Some(file) = &mut self.capture else { return };
+        if let Err(e) = writeln!(file, "{} {}", direction, msg.to_log()) {
+            tracing::warn!("Stopped capturing lobby traffic: {}", e);
+            self.capture = None;
+        }
     }
 
     /// Send a lobby message.
     pub async fn send(&mut self, msg: &LobbyMessage) -> Result<(), LobbyError> {
         let wire = msg.to_wire();
-        tracing::debug!("→ {}", wire.trim());
+        tracing::debug!("→ {}", msg.to_log());
+        self.tee('>', msg);
         self.writer.write_all(wire.as_bytes()).await?;
         self.writer.flush().await?;
         Ok(())
@@ -51,6 +88,10 @@ impl LobbyConnection {
         self.send(&msg).await
     }
 
+    pub fn last_received(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.last_received
+    }
+
     /// Read the next message from the lobby server.
     /// Returns None on clean disconnect.
     pub async fn recv(&mut self) -> Result<LobbyMessage, LobbyError> {
@@ -60,8 +101,10 @@ impl LobbyConnection {
             if bytes == 0 {
                 return Err(LobbyError::Closed);
             }
+            self.last_received = Some(chrono::Utc::now());
             if let Some(msg) = LobbyMessage::from_line(&line) {
-                tracing::debug!("← {} {}", msg.command, &msg.data.to_string()[..msg.data.to_string().len().min(200)]);
+                tracing::debug!("← {}", msg.to_log().chars().take(200).collect::<String>());
+                self.tee('<', &msg);
                 return Ok(msg);
             }
         }
@@ -0,0 +1,390 @@
+//! In-process stand-in for the ZK lobby server, for tests.
+//!
+//! Listens on an ephemeral local port and speaks the `Command JSON\n` wire
+//! format. Replies are canned messages from `tests/fixtures/lobby/`, shaped
+//! after real server traffic (extra fields, nulls, partial headers) so the
+//! deserializers see what zero-k.info actually sends. Tests can also inject
+//! arbitrary messages, Pings, ConnectSpring and disconnects; each injection
+//! returns only once the bytes are on the socket (or queued for a client
+//! whose connection hasn't been accepted yet), so ordering relative to
+//! subsequent tool calls is deterministic.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::tcp::OwnedWriteHalf;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, oneshot};
+
+use super::protocol::*;
+
+/// Battle the fake server lets clients join (matches login_ok.txt).
+pub const OPEN_BATTLE_ID: i64 = 38219;
+/// Channel the fake server refuses to let anyone join.
+pub const RESTRICTED_CHANNEL: &str = "zkadmin";
+/// Account the fake server says is banned (login_banned.txt).
+pub const BANNED_USER: &str = "griefer";
+/// Account the fake server doesn't know.
+pub const UNKNOWN_USER: &str = "nobody";
+
+/// Load a fixture file, substituting `$KEY` placeholders, and parse each line.
+pub fn fixture(name: &str, vars: &[(&str, &str)]) -> Vec<LobbyMessage> {
+    let raw = match name {
+        "welcome" => include_str!("../../tests/fixtures/lobby/welcome.txt"),
+        "login_ok" => include_str!("../../tests/fixtures/lobby/login_ok.txt"),
+        "login_failed" => include_str!("../../tests/fixtures/lobby/login_failed.txt"),
+        "login_banned" => include_str!("../../tests/fixtures/lobby/login_banned.txt"),
+        "login_invalid_name" => include_str!("../../tests/fixtures/lobby/login_invalid_name.txt"),
+        "register_ok" => include_str!("../../tests/fixtures/lobby/register_ok.txt"),
+        "join_channel" => include_str!("../../tests/fixtures/lobby/join_channel.txt"),
+        "join_channel_denied" => {
+            include_str!("../../tests/fixtures/lobby/join_channel_denied.txt")
+        }
+        "join_battle" => include_str!("../../tests/fixtures/lobby/join_battle.txt"),
+        "connect_spring" => include_str!("../../tests/fixtures/lobby/connect_spring.txt"),
+        "matchmaker_found" => include_str!("../../tests/fixtures/lobby/matchmaker_found.txt"),
+        "matchmaker_start" => include_str!("../../tests/fixtures/lobby/matchmaker_start.txt"),
+        "matchmaker_declined" => include_str!("../../tests/fixtures/lobby/matchmaker_declined.txt"),
+        other => panic!("unknown lobby fixture '{}'", other),
+    };
+    let mut text = raw.to_string();
+    for (key, value) in vars {
+        text = text.replace(&format!("${}", key), value);
+    }
+    text.lines().filter_map(LobbyMessage::from_line).collect()
+}
+
+enum Control {
+    Send(LobbyMessage),
+    Disconnect,
+}
+
+/// Handle to a running fake lobby server. The server task stops when the
+/// handle is dropped.
+pub struct FakeLobbyServer {
+    pub port: u16,
+    control: mpsc::UnboundedSender<(Control, oneshot::Sender<()>)>,
+    received: Arc<Mutex<Vec<LobbyMessage>>>,
+    connections: Arc<Mutex<usize>>,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl FakeLobbyServer {
+    /// Start a server that accepts logins with `password`.
+    pub async fn start(password: &str) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (control, control_rx) = mpsc::unbounded_channel();
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let connections = Arc::new(Mutex::new(0));
+
+        let task = tokio::spawn(serve(
+            listener,
+            hash_password(password),
+            control_rx,
+            received.clone(),
+            connections.clone(),
+        ));
+
+        Self {
+            port,
+            control,
+            received,
+            connections,
+            task,
+        }
+    }
+
+    /// Push a message to the connected client.
+    pub async fn send(&self, msg: LobbyMessage) {
+        self.control(Control::Send(msg)).await;
+    }
+
+    /// Send a keepalive Ping, as the server does periodically.
+    pub async fn ping(&self) {
+        self.send(LobbyMessage::new("Ping", serde_json::json!({}))).await;
+    }
+
+    /// Tell the client to launch the engine.
+    pub async fn connect_spring(&self) {
+        for msg in fixture("connect_spring", &[]) {
+            self.send(msg).await;
+        }
+    }
+
+    /// Offer the client a match: a ready-check with 10 seconds to answer.
+    /// Accepting starts the game; declining bans the client from queueing.
+    pub async fn match_found(&self) {
+        for msg in fixture("matchmaker_found", &[]) {
+            self.send(msg).await;
+        }
+    }
+
+    /// Drop the current client connection. The server keeps listening, so
+    /// the client can reconnect.
+    pub async fn disconnect(&self) {
+        self.control(Control::Disconnect).await;
+    }
+
+    /// Every message received from clients so far, in order.
+    pub fn received(&self) -> Vec<LobbyMessage> {
+        self.received.lock().unwrap().clone()
+    }
+
+    /// Number of client connections accepted so far.
+    pub fn connections(&self) -> usize {
+        *self.connections.lock().unwrap()
+    }
+
+    /// Wait until a client has sent `command`, returning the latest one.
+    pub async fn wait_for(&self, command: &str) -> LobbyMessage {
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+        loop {
+            let found = self
+                .received
+                .lock()
+                .unwrap()
+                .iter()
+                .rev()
+                .find(|m| m.command == command)
+                .cloned();
+            if let Some(msg) = found {
+                return msg;
+            }
+            if tokio::time::Instant::now() >= deadline {
+                panic!("fake lobby never received {}", command);
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    async fn control(&self, ctl: Control) {
+        let (ack, done) = oneshot::channel();
+        self.control.send((ctl, ack)).unwrap();
+        done.await.unwrap();
+    }
+}
+
+impl Drop for FakeLobbyServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn serve(
+    listener: TcpListener,
+    password_hash: String,
+    mut control: mpsc::UnboundedReceiver<(Control, oneshot::Sender<()>)>,
+    received: Arc<Mutex<Vec<LobbyMessage>>>,
+    connections: Arc<Mutex<usize>>,
+) {
+    // Messages injected before the server has accepted the client's
+    // connection go out right after its Welcome.
+    let mut backlog = Vec::new();
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let Ok((stream, _)) = accepted else { return };
+                *connections.lock().unwrap() += 1;
+                let pending = std::mem::take(&mut backlog);
+                session(stream, &password_hash, pending, &mut control, &received).await;
+            }
+            ctl = control.recv() => match ctl {
+                Some((Control::Send(msg), ack)) => {
+                    backlog.push(msg);
+                    let _ = ack.send(());
+                }
+                // Nobody connected — nothing to drop.
+                Some((Control::Disconnect, ack)) => { let _ = ack.send(()); }
+                None => return,
+            },
+        }
+    }
+}
+
+/// Serve one client until it hangs up or a disconnect is injected.
+async fn session(
+    stream: TcpStream,
+    password_hash: &str,
+    backlog: Vec<LobbyMessage>,
+    control: &mut mpsc::UnboundedReceiver<(Control, oneshot::Sender<()>)>,
+    received: &Mutex<Vec<LobbyMessage>>,
+) {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    let mut client = Client::default();
+
+    let mut greeting = fixture("welcome", &[]);
+    greeting.extend(backlog);
+    if write_all(&mut writer, &greeting).await.is_err() {
+        return;
+    }
+
+    loop {
+        tokio::select! {
+            line = lines.next_line() => {
+                let Ok(Some(line)) = line else { return };
+                let Some(msg) = LobbyMessage::from_line(&line) else { continue };
+                received.lock().unwrap().push(msg.clone());
+                let replies = respond(&msg, password_hash, &mut client);
+                if write_all(&mut writer, &replies).await.is_err() {
+                    return;
+                }
+            }
+            ctl = control.recv() => {
+                let Some((ctl, ack)) = ctl else { return };
+                match ctl {
+                    Control::Send(msg) => {
+                        let _ = write_all(&mut writer, &[msg]).await;
+                        let _ = ack.send(());
+                    }
+                    Control::Disconnect => {
+                        let _ = writer.shutdown().await;
+                        drop(writer);
+                        drop(lines);
+                        let _ = ack.send(());
+                        return;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// What the server remembers about a connected client.
+#[derive(Default)]
+struct Client {
+    username: String,
+    /// Declined a ready-check; queue requests are refused.
+    matchmaker_banned: bool,
+}
+
+/// Canned server replies to a client command.
+fn respond(msg: &LobbyMessage, password_hash: &str, client: &mut Client) -> Vec<LobbyMessage> {
+    let username = &mut client.username;
+    let str_field = |key: &str| {
+        msg.data
+            .get(key)
+            .and_then(|v| v.as_str())
+            .unwrap_or_default()
+            .to_string()
+    };
+
+    match msg.command.as_str() {
+        "Login" => {
+            match str_field("Name").as_str() {
+                BANNED_USER => return fixture("login_banned", &[]),
+                UNKNOWN_USER => return fixture("login_invalid_name", &[]),
+                _ => {}
+            }
+            if str_field("PasswordHash") != password_hash {
+                return fixture("login_failed", &[]);
+            }
+            *username = str_field("Name");
+            fixture("login_ok", &[("NAME", username)])
+        }
+        "Register" => fixture("register_ok", &[]),
+        "JoinChannel" => {
+            let channel = str_field("ChannelName");
+            let name = if channel == RESTRICTED_CHANNEL {
+                "join_channel_denied"
+            } else {
+                "join_channel"
+            };
+            fixture(name, &[("CHANNEL", &channel), ("NAME", username)])
+        }
+        "JoinBattle" => {
+            // The real server stays silent when a join is refused.
+            let battle_id = msg.data.get("BattleID").and_then(|v| v.as_i64());
+            if battle_id != Some(OPEN_BATTLE_ID) {
+                return Vec::new();
+            }
+            fixture(
+                "join_battle",
+                &[("BATTLE", &OPEN_BATTLE_ID.to_string()), ("NAME", username)],
+            )
+        }
+        "Say" => {
+            // Channel messages are echoed back to the sender like any member.
+            let mut data = msg.data.clone();
+            data["User"] = serde_json::json!(username);
+            data["Time"] = serde_json::json!("2026-10-16T12:00:00Z");
+            vec![LobbyMessage::new("Say", data)]
+        }
+        "MatchMakerQueueRequest" => {
+            let status = if client.matchmaker_banned {
+                serde_json::json!({"JoinedQueues": [], "BannedSeconds": 60})
+            } else {
+                serde_json::json!({
+                    "JoinedQueues": msg.data["Queues"],
+                    "QueueCounts": {"1v1": 3, "Teams": 5},
+                })
+            };
+            vec![LobbyMessage::new("MatchMakerStatus", status)]
+        }
+        "AreYouReadyResponse" => {
+            if msg.data["Ready"] == true {
+                fixture("matchmaker_start", &[])
+            } else {
+                client.matchmaker_banned = true;
+                fixture("matchmaker_declined", &[])
+            }
+        }
+        _ => Vec::new(),
+    }
+}
+
+async fn write_all(writer: &mut OwnedWriteHalf, msgs: &[LobbyMessage]) -> std::io::Result<()> {
+    for msg in msgs {
+        writer.write_all(msg.to_wire().as_bytes()).await?;
+    }
+    writer.flush().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lobby::state::{LobbyEvent, LobbyState};
+
+    #[test]
+    fn test_fixtures_deserialize() {
+        let vars = [("NAME", "agent"), ("CHANNEL", "zk"), ("BATTLE", "38219")];
+        let mut state = LobbyState::new();
+        let mut events = Vec::new();
+        for name in [
+            "welcome",
+            "login_ok",
+            "join_channel",
+            "join_battle",
+            "connect_spring",
+        ] {
+            for msg in fixture(name, &vars) {
+                assert!(msg.data.is_object(), "{} line did not parse as JSON", name);
+                events.extend(state.handle_message(&msg));
+            }
+        }
+
+        assert!(state.connected && state.logged_in);
+        assert_eq!(state.server_engine, "105.1.1-2511-g747f18b");
+        assert_eq!(state.users.len(), 3);
+        assert_eq!(state.users["Godde"].battle_id, Some(38219));
+        assert_eq!(state.matchmaker_queues.len(), 2);
+        // The second header carries only a handful of fields.
+        let private = &state.battles[&38240];
+        assert!(private.is_password_protected);
+        assert_eq!(private.engine, "");
+        assert_eq!(state.battles[&38219].mode.as_deref(), Some("Teams"));
+        assert_eq!(state.channels["zk"].users.len(), 3);
+        assert_eq!(state.my_battle, Some(38219));
+        assert!(events.iter().any(|e| matches!(
+            e,
+            LobbyEvent::ConnectSpring(d) if d.port == 8452 && d.mode == 5
+        )));
+
+        let failed = &fixture("login_failed", &[])[0];
+        let data: LoginResponseData = serde_json::from_value(failed.data.clone()).unwrap();
+        assert_eq!(data.result_code, LOGIN_INVALID_PASSWORD);
+        assert_eq!(data.ban_reason, None);
+    }
+}
@@ -1,4 +1,9 @@
+pub mod chat;
+#[cfg(test)]
+mod conformance;
 pub mod connection;
+#[cfg(test)]
+pub mod fake_server;
 pub mod protocol;
 pub mod state;
 
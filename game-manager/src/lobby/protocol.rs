@@ -22,6 +22,13 @@ impl LobbyMessage {
         format!("{} {}\n", self.command, self.data)
     }
 
+    /// The message for logs and captures: string fields named like a
+    /// credential (`PasswordHash`, `ScriptPassword`, `SessionToken`, ...)
+    /// are masked unless empty.
+    pub fn to_log(&self) -> String {
+        format!("{} {}", self.command, mask_credentials(&self.data))
+    }
+
     /// Parse from a single line (without trailing newline).
     pub fn from_line(line: &str) -> Option<Self> {
         let line = line.trim();
@@ -137,8 +144,10 @@ pub struct OpenBattleCommand {
     pub header: BattleHeader,
 }
 
-// Client → Server: add or update a bot in the current battle
-#[derive(Debug, Clone, Serialize, Deserialize)]
+// Client → Server: add or update a bot in the current battle. The server
+// sends the same message to everyone in the battle, and in
+// JoinBattleSuccess's Bots.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct UpdateBotStatusCommand {
     pub name: String,
@@ -148,7 +157,8 @@ pub struct UpdateBotStatusCommand {
     pub owner: String,
 }
 
-// Client → Server: remove a bot from the current battle
+// Client → Server: remove a bot from the current battle (and back to
+// everyone in it)
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct RemoveBotCommand {
@@ -362,6 +372,39 @@ pub struct ChannelUserRemovedData {
     pub user_name: String,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct JoinedBattleData {
+    #[serde(rename = "BattleID")]
+    pub battle_id: i64,
+    pub user: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct LeftBattleData {
+    #[serde(rename = "BattleID")]
+    pub battle_id: i64,
+    pub user: String,
+}
+
+// Server → Client: a player's status in our battle. Also the shape of
+// JoinBattleSuccess's Players. Sync is 0 unknown, 1 synced, 2 unsynced.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct UserBattleStatusData {
+    pub name: String,
+    #[serde(default)]
+    pub is_spectator: Option<bool>,
+    #[serde(default)]
+    pub sync: Option<i32>,
+    #[serde(default)]
+    pub ally_number: Option<i32>,
+}
+
+/// Sync status of a player missing the battle's content.
+pub const SYNC_UNSYNCED: i32 = 2;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct ConnectSpringData {
@@ -503,6 +546,29 @@ pub struct AreYouReadyResultData {
     pub are_you_banned: bool,
 }
 
+/// Field names containing any of these (ignoring case) hold credentials.
+const CREDENTIAL_WORDS: [&str; 4] = ["password", "token", "secret", "credential"];
+
+fn mask_credentials(value: &serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Object(map) => map
+            .iter()
+            .map(|(key, v)| {
+                let key_lower = key.to_lowercase();
+                let credential = CREDENTIAL_WORDS.iter().any(|word| key_lower.contains(word));
+                let v = if credential && v.as_str().is_some_and(|s| !s.is_empty()) {
+                    crate::audit::REDACTED.into()
+                } else {
+                    mask_credentials(v)
+                };
+                (key.clone(), v)
+            })
+            .collect(),
+        serde_json::Value::Array(items) => items.iter().map(mask_credentials).collect(),
+        other => other.clone(),
+    }
+}
+
 /// Create MD5 password hash for login.
 pub fn hash_password(password: &str) -> String {
     use base64::Engine;
@@ -540,6 +606,39 @@ mod tests {
         assert_eq!(msg.to_wire(), "Ping {}\n");
     }
 
+    #[test]
+    fn test_log_masks_passwords() {
+        let login = LobbyMessage::new("Login", serde_json::json!({"Name": "bot", "PasswordHash": "abc123=="}));
+        assert_eq!(login.to_log(), r#"Login {"Name":"bot","PasswordHash":"[redacted]"}"#);
+        let battle = LobbyMessage::new(
+            "ConnectSpring",
+            serde_json::json!({"Map": "Tundra", "ScriptPassword": "a1b2", "IsPasswordProtected": true}),
+        );
+        assert!(!battle.to_log().contains("a1b2"));
+        assert!(battle.to_log().contains(r#""IsPasswordProtected":true"#));
+    }
+
+    #[test]
+    fn test_log_masks_tokens() {
+        let response = LobbyMessage::new(
+            "LoginResponse",
+            serde_json::json!({"ResultCode": 0, "SessionToken": "3f1b9d3c", "BanReason": null}),
+        );
+        assert_eq!(
+            response.to_log(),
+            r#"LoginResponse {"BanReason":null,"ResultCode":0,"SessionToken":"[redacted]"}"#
+        );
+        let login = LobbyMessage::new(
+            "Login",
+            serde_json::json!({"Name": "bot", "SteamAuthToken": "14000000", "Dlc": "", "Password": ""}),
+        );
+        assert!(!login.to_log().contains("14000000"));
+        // Nothing to hide in an empty field.
+        assert!(login.to_log().contains(r#""Password":"""#));
+        let nested = LobbyMessage::new("Custom", serde_json::json!({"Auth": {"ClientSecret": "s3cr3t"}}));
+        assert!(!nested.to_log().contains("s3cr3t"));
+    }
+
     #[test]
     fn test_password_hash() {
         let hash = hash_password("test");
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 use super::protocol::*;
 
@@ -15,11 +15,40 @@ pub struct LobbyState {
     pub battles: HashMap<i64, BattleInfo>,
     pub channels: HashMap<String, ChannelInfo>,
     pub my_battle: Option<i64>,
+    /// Who is in `my_battle`.
+    pub battle_roster: BattleRoster,
     // Matchmaker state
     pub matchmaker_queues: Vec<QueueInfo>,
     pub matchmaker_joined: Vec<String>,
     pub matchmaker_queue_counts: HashMap<String, i32>,
     pub matchmaker_ready_pending: bool,
+    /// When the pending ready-check stops accepting a response.
+    pub matchmaker_ready_deadline: Option<chrono::DateTime<chrono::Utc>>,
+    /// Seconds left on a ban from queueing, from the last MatchMakerStatus.
+    pub matchmaker_banned_seconds: Option<i32>,
+    /// The match from the last ready-check, kept until its game launches.
+    pub matchmaker_match: Option<MatchContext>,
+}
+
+/// What we know about a match found by the matchmaker.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MatchContext {
+    /// Queues we were in when the match was found.
+    pub queues: Vec<String>,
+    pub battle_size: Option<i32>,
+    pub battle_ready: Option<i32>,
+    pub accepted: bool,
+}
+
+impl MatchContext {
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "queues": self.queues,
+            "battleSize": self.battle_size,
+            // Matchmaker battles are two even teams.
+            "expectedOpponents": self.battle_size.map(|n| n / 2),
+        })
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -52,6 +81,77 @@ pub struct BattleInfo {
     pub mode: Option<String>,
 }
 
+impl BattleInfo {
+    /// One line for `lobby_list_battles`:
+    /// `#12 "Newbies 1v1" by Alice: Comet, 1/2 players, 3 specs, Team, running, password`.
+    pub fn summary_line(&self) -> String {
+        let mut line = format!(
+            "#{} \"{}\" by {}: {}, {}/{} players, {} specs",
+            self.battle_id, self.title, self.founder, self.map, self.player_count, self.max_players, self.spectator_count
+        );
+        if let Some(mode) = &self.mode {
+            line.push_str(&format!(", {}", mode));
+        }
+        if self.is_running {
+            line.push_str(", running");
+        }
+        if self.is_password_protected {
+            line.push_str(", password");
+        }
+        line
+    }
+}
+
+/// Which battles `lobby_list_battles` shows; the defaults let all through.
+/// Text filters ignore case.
+#[derive(Debug, Clone, Default)]
+pub struct BattleFilter {
+    pub running: Option<bool>,
+    /// Only battles with a free player slot.
+    pub has_space: bool,
+    pub no_password: bool,
+    pub mode: Option<String>,
+    /// Part of the title or the founder's name.
+    pub text: Option<String>,
+    /// Part of the map name.
+    pub map: Option<String>,
+}
+
+impl BattleFilter {
+    pub fn matches(&self, battle: &BattleInfo) -> bool {
+        let contains = |haystack: &str, needle: &Option<String>| {
+            needle.as_ref().is_none_or(|n| haystack.to_lowercase().contains(&n.to_lowercase()))
+        };
+        self.running.is_none_or(|running| battle.is_running == running)
+            && (!self.has_space || battle.player_count < battle.max_players)
+            && (!self.no_password || !battle.is_password_protected)
+            && self.mode.as_ref().is_none_or(|m| battle.mode.as_ref().is_some_and(|bm| bm.eq_ignore_ascii_case(m)))
+            && (contains(&battle.title, &self.text) || contains(&battle.founder, &self.text))
+            && contains(&battle.map, &self.map)
+    }
+}
+
+/// Order of `lobby_list_battles`; ties, and the default, go by battle id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BattleSort {
+    #[default]
+    Id,
+    /// Most players first.
+    Players,
+    Title,
+}
+
+impl BattleSort {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "id" => Some(BattleSort::Id),
+            "players" => Some(BattleSort::Players),
+            "title" => Some(BattleSort::Title),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct ChannelInfo {
     pub name: String,
@@ -59,13 +159,136 @@ pub struct ChannelInfo {
     pub users: Vec<String>,
 }
 
+/// A player in the battle we're in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BattlePlayer {
+    pub name: String,
+    pub is_spectator: bool,
+    pub ally_number: i32,
+    pub sync: i32,
+}
+
+impl BattlePlayer {
+    fn new(name: &str) -> Self {
+        Self { name: name.to_string(), is_spectator: true, ally_number: 0, sync: 0 }
+    }
+
+    /// Apply a status update; its missing fields stay as they were.
+    fn update(&mut self, status: &UserBattleStatusData) {
+        self.is_spectator = status.is_spectator.unwrap_or(self.is_spectator);
+        self.ally_number = status.ally_number.unwrap_or(self.ally_number);
+        self.sync = status.sync.unwrap_or(self.sync);
+    }
+
+    /// "spectating", or "playing in ally team 1"; "(missing content)" when unsynced.
+    pub fn describe(&self) -> String {
+        let mut text = if self.is_spectator {
+            "spectating".to_string()
+        } else {
+            format!("playing in ally team {}", self.ally_number)
+        };
+        if self.sync == SYNC_UNSYNCED {
+            text.push_str(" (missing content)");
+        }
+        text
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "name": self.name,
+            "spectator": self.is_spectator,
+            "allyNumber": self.ally_number,
+            "synced": self.sync != SYNC_UNSYNCED,
+        })
+    }
+}
+
+/// Players and bots in the battle we're in.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BattleRoster {
+    pub players: BTreeMap<String, BattlePlayer>,
+    pub bots: BTreeMap<String, UpdateBotStatusCommand>,
+}
+
+impl BattleRoster {
+    /// The roster JoinBattleSuccess brings.
+    fn from_join(data: &JoinBattleSuccessData) -> Self {
+        let mut roster = Self::default();
+        for status in data.players.iter().filter_map(|p| serde_json::from_value::<UserBattleStatusData>(p.clone()).ok()) {
+            roster.players.entry(status.name.clone()).or_insert_with(|| BattlePlayer::new(&status.name)).update(&status);
+        }
+        for bot in data.bots.iter().filter_map(|b| serde_json::from_value::<UpdateBotStatusCommand>(b.clone()).ok()) {
+            roster.bots.insert(bot.name.clone(), bot);
+        }
+        roster
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        let players: Vec<serde_json::Value> = self.players.values().map(BattlePlayer::to_json).collect();
+        let bots: Vec<serde_json::Value> = self
+            .bots
+            .values()
+            .map(|b| serde_json::json!({ "name": b.name, "aiLib": b.ai_lib, "allyNumber": b.ally_number, "owner": b.owner }))
+            .collect();
+        serde_json::json!({ "players": players, "bots": bots })
+    }
+}
+
+/// What changed in the battle we're in.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BattleRoomChange {
+    PlayerJoined(String),
+    PlayerLeft(String),
+    /// A player's status changed (and was or became this).
+    PlayerStatus(BattlePlayer),
+    BotUpdated(UpdateBotStatusCommand),
+    BotRemoved(String),
+    MapChanged(String),
+    /// The game is starting; ConnectSpring follows.
+    Started,
+    /// The server took us out of the battle without our asking.
+    Removed,
+}
+
+impl BattleRoomChange {
+    /// One line for the agent, e.g. "Godde joined the battle".
+    pub fn text(&self) -> String {
+        match self {
+            Self::PlayerJoined(name) => format!("{} joined the battle", name),
+            Self::PlayerLeft(name) => format!("{} left the battle", name),
+            Self::PlayerStatus(player) => format!("{} is {}", player.name, player.describe()),
+            Self::BotUpdated(bot) => format!("Bot {} ({}) is in ally team {}", bot.name, bot.ai_lib, bot.ally_number),
+            Self::BotRemoved(name) => format!("Bot {} was removed", name),
+            Self::MapChanged(map) => format!("The map is now {}", map),
+            Self::Started => "The battle is starting".into(),
+            Self::Removed => "You were removed from the battle".into(),
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        match self {
+            Self::PlayerJoined(name) => serde_json::json!({ "change": "player_joined", "name": name }),
+            Self::PlayerLeft(name) => serde_json::json!({ "change": "player_left", "name": name }),
+            Self::PlayerStatus(player) => serde_json::json!({ "change": "player_status", "player": player.to_json() }),
+            Self::BotUpdated(bot) => serde_json::json!({
+                "change": "bot_updated",
+                "bot": { "name": bot.name, "aiLib": bot.ai_lib, "allyNumber": bot.ally_number, "owner": bot.owner },
+            }),
+            Self::BotRemoved(name) => serde_json::json!({ "change": "bot_removed", "name": name }),
+            Self::MapChanged(map) => serde_json::json!({ "change": "map_changed", "map": map }),
+            Self::Started => serde_json::json!({ "change": "started" }),
+            Self::Removed => serde_json::json!({ "change": "removed" }),
+        }
+    }
+}
+
 /// Event emitted when lobby state changes, for forwarding as MCPL push events.
 #[derive(Debug, Clone)]
 pub enum LobbyEvent {
     Connected { engine: String, game: String },
     Disconnected { reason: String },
     LoggedIn { username: String },
-    LoginFailed { code: i32, message: String },
+    LoginFailed(LoginResponseData),
     RegisterSuccess,
     RegisterFailed { code: i32, reason: String },
     UserJoined(UserInfo),
@@ -78,6 +301,8 @@ pub enum LobbyEvent {
     ChannelUserJoined { channel: String, user: String },
     ChannelUserLeft { channel: String, user: String },
     BattleJoined { battle_id: i64, player_count: usize, bot_count: usize },
+    /// Something changed in the battle we're in.
+    BattleRoom { battle_id: i64, change: BattleRoomChange },
     ConnectSpring(ConnectSpringData),
     // Matchmaker events
     MatchMakerSetup { queues: Vec<QueueInfo> },
@@ -92,6 +317,87 @@ impl LobbyState {
         Self::default()
     }
 
+    /// Find a user by name: the exact name, else a case-insensitive match,
+    /// else every user whose name starts with `name` (ignoring case).
+    pub fn find_users(&self, name: &str) -> Vec<&UserInfo> {
+        if let Some(user) = self.users.get(name) {
+            return vec![user];
+        }
+        let lower = name.to_lowercase();
+        let mut matches: Vec<&UserInfo> =
+            self.users.values().filter(|u| u.name.to_lowercase() == lower).collect();
+        if matches.is_empty() {
+            matches = self.users.values().filter(|u| u.name.to_lowercase().starts_with(&lower)).collect();
+        }
+        matches.sort_by(|a, b| a.name.cmp(&b.name));
+        matches
+    }
+
+    /// Open battles founded by `founder` (ignoring case) whose title
+    /// matches `title`, by id.
+    pub fn find_battles(&self, founder: Option<&str>, title: Option<&regex::Regex>) -> Vec<&BattleInfo> {
+        let mut matches: Vec<&BattleInfo> = self
+            .battles
+            .values()
+            .filter(|b| founder.is_none_or(|f| b.founder.eq_ignore_ascii_case(f)))
+            .filter(|b| title.is_none_or(|re| re.is_match(&b.title)))
+            .collect();
+        matches.sort_by_key(|b| b.battle_id);
+        matches
+    }
+
+    /// The battles `filter` lets through, in `sort` order.
+    pub fn list_battles(&self, filter: &BattleFilter, sort: BattleSort) -> Vec<&BattleInfo> {
+        let mut battles: Vec<&BattleInfo> = self.battles.values().filter(|b| filter.matches(b)).collect();
+        battles.sort_by_key(|b| b.battle_id);
+        match sort {
+            BattleSort::Id => {}
+            BattleSort::Players => battles.sort_by_key(|b| std::cmp::Reverse(b.player_count)),
+            BattleSort::Title => battles.sort_by_cached_key(|b| b.title.to_lowercase()),
+        }
+        battles
+    }
+
+    /// Snapshot of connection, login and presence for `lobby_status`.
+    pub fn status(&self) -> serde_json::Value {
+        let mut channels: Vec<serde_json::Value> = self
+            .channels
+            .values()
+            .map(|c| serde_json::json!({ "name": c.name, "users": c.users.len() }))
+            .collect();
+        channels.sort_by(|a, b| a["name"].as_str().cmp(&b["name"].as_str()));
+        let battle = self.my_battle.map(|id| {
+            let info = self.battles.get(&id);
+            serde_json::json!({
+                "id": id,
+                "title": info.map(|b| b.title.as_str()),
+                "map": info.map(|b| b.map.as_str()),
+                // Users the lobby reports in the battle, us included.
+                "rosterSize": self.users.values().filter(|u| u.battle_id == Some(id)).count(),
+                "players": info.map(|b| b.player_count),
+                "spectators": info.map(|b| b.spectator_count),
+            })
+        });
+        serde_json::json!({
+            "connected": self.connected,
+            "loggedIn": self.logged_in,
+            "username": self.my_username,
+            "server": {
+                "engine": self.server_engine,
+                "game": self.server_game,
+                "userCount": self.user_count,
+            },
+            "channels": channels,
+            "battle": battle,
+            "matchmaker": {
+                "joinedQueues": self.matchmaker_joined,
+                "readyCheckPending": self.matchmaker_ready_pending,
+                "readyDeadline": self.matchmaker_ready_deadline.map(|t| t.to_rfc3339()),
+                "bannedSeconds": self.matchmaker_banned_seconds,
+            },
+        })
+    }
+
     /// Process a lobby message and update state. Returns events to forward.
     pub fn handle_message(&mut self, msg: &LobbyMessage) -> Vec<LobbyEvent> {
         let mut events = Vec::new();
@@ -116,10 +422,7 @@ impl LobbyState {
                         self.my_username = Some(data.name.clone());
                         events.push(LobbyEvent::LoggedIn { username: data.name });
                     } else {
-                        events.push(LobbyEvent::LoginFailed {
-                            code: data.result_code,
-                            message: data.message,
-                        });
+                        events.push(LobbyEvent::LoginFailed(data));
                     }
                 }
             }
@@ -187,6 +490,14 @@ impl LobbyState {
             "BattleUpdate" => {
                 if let Ok(data) = serde_json::from_value::<BattleUpdateData>(msg.data.clone()) {
                     let info = battle_info_from_header(&data.header);
+                    if let (Some(old), true) = (self.battles.get(&info.battle_id), self.my_battle == Some(info.battle_id)) {
+                        if !info.map.is_empty() && info.map != old.map {
+                            events.push(self.battle_room(BattleRoomChange::MapChanged(info.map.clone())));
+                        }
+                        if info.is_running && !old.is_running {
+                            events.push(self.battle_room(BattleRoomChange::Started));
+                        }
+                    }
                     self.battles.insert(info.battle_id, info.clone());
                     events.push(LobbyEvent::BattleUpdated(info));
                 }
@@ -194,6 +505,10 @@ impl LobbyState {
             "BattleRemoved" => {
                 if let Ok(data) = serde_json::from_value::<BattleRemovedData>(msg.data.clone()) {
                     self.battles.remove(&data.battle_id);
+                    if self.my_battle == Some(data.battle_id) {
+                        self.my_battle = None;
+                        self.battle_roster = BattleRoster::default();
+                    }
                     events.push(LobbyEvent::BattleClosed {
                         battle_id: data.battle_id,
                     });
@@ -202,6 +517,7 @@ impl LobbyState {
             "JoinBattleSuccess" => {
                 if let Ok(data) = serde_json::from_value::<JoinBattleSuccessData>(msg.data.clone()) {
                     self.my_battle = Some(data.battle_id);
+                    self.battle_roster = BattleRoster::from_join(&data);
                     events.push(LobbyEvent::BattleJoined {
                         battle_id: data.battle_id,
                         player_count: data.players.len(),
@@ -209,6 +525,70 @@ impl LobbyState {
                     });
                 }
             }
+            "JoinedBattle" => {
+                if let Ok(data) = serde_json::from_value::<JoinedBattleData>(msg.data.clone()) {
+                    if let Some(user) = self.users.get_mut(&data.user) {
+                        user.battle_id = Some(data.battle_id);
+                    }
+                    let me = self.my_username.as_deref() == Some(data.user.as_str());
+                    if self.my_battle == Some(data.battle_id) && !me {
+                        self.battle_roster.players.insert(data.user.clone(), BattlePlayer::new(&data.user));
+                        events.push(self.battle_room(BattleRoomChange::PlayerJoined(data.user)));
+                    }
+                }
+            }
+            "LeftBattle" => {
+                if let Ok(data) = serde_json::from_value::<LeftBattleData>(msg.data.clone()) {
+                    if let Some(user) = self.users.get_mut(&data.user) {
+                        user.battle_id = None;
+                    }
+                    if self.my_battle == Some(data.battle_id) {
+                        if self.my_username.as_deref() == Some(data.user.as_str()) {
+                            // lobby_leave_battle forgets the battle before
+                            // the server confirms, so this is a kick.
+                            events.push(self.battle_room(BattleRoomChange::Removed));
+                            self.my_battle = None;
+                            self.battle_roster = BattleRoster::default();
+                        } else {
+                            self.battle_roster.players.remove(&data.user);
+                            events.push(self.battle_room(BattleRoomChange::PlayerLeft(data.user)));
+                        }
+                    }
+                }
+            }
+            "UpdateUserBattleStatus" => {
+                if let Ok(data) = serde_json::from_value::<UserBattleStatusData>(msg.data.clone()) {
+                    // Only sent for the battle we're in.
+                    if self.my_battle.is_some() {
+                        let player = self
+                            .battle_roster
+                            .players
+                            .entry(data.name.clone())
+                            .or_insert_with(|| BattlePlayer::new(&data.name));
+                        let before = player.clone();
+                        player.update(&data);
+                        if *player != before {
+                            let change = BattleRoomChange::PlayerStatus(player.clone());
+                            events.push(self.battle_room(change));
+                        }
+                    }
+                }
+            }
+            "UpdateBotStatus" => {
+                if let Ok(data) = serde_json::from_value::<UpdateBotStatusCommand>(msg.data.clone()) {
+                    if self.my_battle.is_some() {
+                        self.battle_roster.bots.insert(data.name.clone(), data.clone());
+                        events.push(self.battle_room(BattleRoomChange::BotUpdated(data)));
+                    }
+                }
+            }
+            "RemoveBot" => {
+                if let Ok(data) = serde_json::from_value::<RemoveBotCommand>(msg.data.clone()) {
+                    if self.my_battle.is_some() && self.battle_roster.bots.remove(&data.name).is_some() {
+                        events.push(self.battle_room(BattleRoomChange::BotRemoved(data.name)));
+                    }
+                }
+            }
             "JoinChannelResponse" => {
                 if let Ok(data) = serde_json::from_value::<JoinChannelResponseData>(msg.data.clone()) {
                     if data.success {
@@ -269,12 +649,20 @@ impl LobbyState {
                 if let Ok(data) = serde_json::from_value::<MatchMakerStatusData>(msg.data.clone()) {
                     self.matchmaker_joined = data.joined_queues.clone();
                     self.matchmaker_queue_counts = data.queue_counts.clone();
+                    self.matchmaker_banned_seconds = data.banned_seconds.filter(|s| *s > 0);
                     events.push(LobbyEvent::MatchMakerStatus(data));
                 }
             }
             "AreYouReady" => {
                 if let Ok(data) = serde_json::from_value::<AreYouReadyData>(msg.data.clone()) {
                     self.matchmaker_ready_pending = true;
+                    self.matchmaker_ready_deadline = Some(
+                        chrono::Utc::now() + chrono::Duration::seconds(data.seconds_remaining.into()),
+                    );
+                    self.matchmaker_match = Some(MatchContext {
+                        queues: self.matchmaker_joined.clone(),
+                        ..Default::default()
+                    });
                     events.push(LobbyEvent::MatchMakerReady {
                         seconds_remaining: data.seconds_remaining,
                         quick_play: data.quick_play,
@@ -283,14 +671,22 @@ impl LobbyState {
             }
             "AreYouReadyUpdate" => {
                 if let Ok(data) = serde_json::from_value::<AreYouReadyUpdateData>(msg.data.clone()) {
+                    if let Some(context) = &mut self.matchmaker_match {
+                        context.battle_size = data.your_battle_size.or(context.battle_size);
+                        context.battle_ready = data.your_battle_ready.or(context.battle_ready);
+                        context.accepted = data.ready_accepted;
+                    }
                     events.push(LobbyEvent::MatchMakerReadyUpdate(data));
                 }
             }
             "AreYouReadyResult" => {
                 if let Ok(data) = serde_json::from_value::<AreYouReadyResultData>(msg.data.clone()) {
                     self.matchmaker_ready_pending = false;
+                    self.matchmaker_ready_deadline = None;
                     if data.is_battle_starting {
                         self.matchmaker_joined.clear();
+                    } else {
+                        self.matchmaker_match = None;
                     }
                     events.push(LobbyEvent::MatchMakerResult {
                         is_battle_starting: data.is_battle_starting,
@@ -310,6 +706,13 @@ impl LobbyState {
     }
 }
 
+impl LobbyState {
+    /// A change in `my_battle`; only called while we're in one.
+    fn battle_room(&self, change: BattleRoomChange) -> LobbyEvent {
+        LobbyEvent::BattleRoom { battle_id: self.my_battle.unwrap_or_default(), change }
+    }
+}
+
 fn battle_info_from_header(h: &BattleHeader) -> BattleInfo {
     BattleInfo {
         battle_id: h.battle_id,
@@ -336,3 +739,118 @@ impl Default for ChannelData {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn battle(battle_id: i64, title: &str, founder: &str, map: &str, players: (i32, i32)) -> BattleInfo {
+        BattleInfo {
+            battle_id,
+            title: title.into(),
+            founder: founder.into(),
+            map: map.into(),
+            game: "Zero-K v1.12.7.0".into(),
+            engine: "105.1.1".into(),
+            max_players: players.1,
+            player_count: players.0,
+            spectator_count: 0,
+            is_running: false,
+            is_password_protected: false,
+            mode: Some("Custom".into()),
+        }
+    }
+
+    fn lobby() -> LobbyState {
+        let mut state = LobbyState::new();
+        let mut teams = battle(3, "Teams All Welcome", "Bob", "Comet Catcher Redux", (8, 16));
+        teams.is_running = true;
+        teams.mode = Some("Teams".into());
+        let mut private = battle(7, "clan practice", "Carol", "Altair_Crossing", (2, 2));
+        private.is_password_protected = true;
+        for b in [teams, private, battle(5, "Newbies 1v1", "Alice", "Comet Catcher Redux", (1, 2))] {
+            state.battles.insert(b.battle_id, b);
+        }
+        state
+    }
+
+    fn ids(battles: Vec<&BattleInfo>) -> Vec<i64> {
+        battles.iter().map(|b| b.battle_id).collect()
+    }
+
+    #[test]
+    fn test_battle_filters() {
+        let state = lobby();
+        let list = |filter: BattleFilter| ids(state.list_battles(&filter, BattleSort::Id));
+        assert_eq!(list(BattleFilter::default()), [3, 5, 7]);
+        assert_eq!(list(BattleFilter { running: Some(false), ..Default::default() }), [5, 7]);
+        assert_eq!(list(BattleFilter { has_space: true, ..Default::default() }), [3, 5]);
+        assert_eq!(list(BattleFilter { no_password: true, ..Default::default() }), [3, 5]);
+        assert_eq!(list(BattleFilter { mode: Some("teams".into()), ..Default::default() }), [3]);
+        assert_eq!(list(BattleFilter { text: Some("carol".into()), ..Default::default() }), [7]);
+        assert_eq!(list(BattleFilter { text: Some("WELCOME".into()), ..Default::default() }), [3]);
+        assert_eq!(list(BattleFilter { map: Some("comet".into()), running: Some(false), ..Default::default() }), [5]);
+    }
+
+    #[test]
+    fn test_battle_sort_and_summary() {
+        let state = lobby();
+        let all = BattleFilter::default();
+        assert_eq!(ids(state.list_battles(&all, BattleSort::Players)), [3, 7, 5]);
+        assert_eq!(ids(state.list_battles(&all, BattleSort::Title)), [7, 5, 3]);
+        assert_eq!(BattleSort::parse("size"), None);
+        assert_eq!(
+            state.battles[&3].summary_line(),
+            "#3 \"Teams All Welcome\" by Bob: Comet Catcher Redux, 8/16 players, 0 specs, Teams, running"
+        );
+        assert_eq!(
+            state.battles[&7].summary_line(),
+            "#7 \"clan practice\" by Carol: Altair_Crossing, 2/2 players, 0 specs, Custom, password"
+        );
+    }
+
+    #[test]
+    fn test_battle_roster() {
+        let mut state = lobby();
+        state.my_username = Some("agent".into());
+        let mut apply = |line: &str| state.handle_message(&LobbyMessage::from_line(line).unwrap());
+        apply(r#"JoinBattleSuccess {"BattleID":5,"Players":[{"Name":"Alice","IsSpectator":false,"Sync":1},{"Name":"agent","Sync":0}],"Bots":[]}"#);
+        let changes = |events: Vec<LobbyEvent>| -> Vec<String> {
+            events
+                .into_iter()
+                .filter_map(|e| match e {
+                    LobbyEvent::BattleRoom { battle_id: 5, change } => Some(change.text()),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        assert_eq!(changes(apply(r#"JoinedBattle {"BattleID":5,"User":"Godde"}"#)), ["Godde joined the battle"]);
+        assert!(changes(apply(r#"JoinedBattle {"BattleID":3,"User":"Bob"}"#)).is_empty());
+        assert_eq!(
+            changes(apply(r#"UpdateUserBattleStatus {"Name":"Godde","IsSpectator":false,"AllyNumber":1,"Sync":2}"#)),
+            ["Godde is playing in ally team 1 (missing content)"]
+        );
+        // Repeats of the same status aren't news.
+        assert!(changes(apply(r#"UpdateUserBattleStatus {"Name":"Godde","AllyNumber":1}"#)).is_empty());
+        assert_eq!(
+            changes(apply(r#"UpdateBotStatus {"Name":"Bot1","AiLib":"CircuitAIBeginner","Owner":"Alice","AllyNumber":1}"#)),
+            ["Bot Bot1 (CircuitAIBeginner) is in ally team 1"]
+        );
+        assert_eq!(changes(apply(r#"LeftBattle {"BattleID":5,"User":"Alice"}"#)), ["Alice left the battle"]);
+        let roster = state.battle_roster.to_json();
+        assert_eq!(roster["players"].as_array().unwrap().len(), 2);
+        assert_eq!(roster["players"][0], serde_json::json!({"name": "Godde", "spectator": false, "allyNumber": 1, "synced": false}));
+        assert_eq!(roster["bots"][0]["owner"], "Alice");
+
+        let mut apply = |line: &str| state.handle_message(&LobbyMessage::from_line(line).unwrap());
+        let header = r#"{"Header":{"BattleID":5,"Founder":"Alice","Map":"Altair_Crossing","Title":"Newbies 1v1","IsRunning":true}}"#;
+        assert_eq!(
+            changes(apply(&format!("BattleUpdate {}", header))),
+            ["The map is now Altair_Crossing", "The battle is starting"]
+        );
+        assert_eq!(changes(apply(r#"LeftBattle {"BattleID":5,"User":"agent"}"#)), ["You were removed from the battle"]);
+        assert_eq!(state.my_battle, None);
+        assert!(state.battle_roster.players.is_empty());
+    }
+}
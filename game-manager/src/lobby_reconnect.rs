@@ -0,0 +1,235 @@
+//! Lobby reconnects, and the lobby tool calls queued while one is under
+//! way. When the server drops us, the GameManager connects again every
+//! `retry_secs` and logs back in with the last login. Calls to the tools in
+//! [`QUEUEABLE_TOOLS`] made meanwhile wait in a bounded queue, and run in
+//! order once we're logged in again; a call waiting longer than
+//! `max_age_secs` is dropped. Everything else still fails straight away.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+/// Tools whose lobby command can wait for a reconnect: chat, channel joins
+/// and matchmaker queueing. Logins, battle joins (which may carry a
+/// password) and ready-check answers are not among them.
+pub const QUEUEABLE_TOOLS: &[&str] = &["lobby_say", "lobby_join_channel", "lobby_matchmaker_join", "lobby_matchmaker_leave"];
+
+/// `lobby_reconnect` section of the config file.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReconnectConfig {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// Seconds between connection attempts.
+    #[serde(default = "default_retry_secs")]
+    pub retry_secs: u64,
+    /// Attempts before giving up; queued calls are dropped then.
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    /// Calls that can wait at once.
+    #[serde(default = "default_queue_size")]
+    pub queue_size: usize,
+    /// Seconds a call may wait before it's dropped.
+    #[serde(default = "default_max_age_secs")]
+    pub max_age_secs: u64,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_retry_secs() -> u64 {
+    5
+}
+
+fn default_max_attempts() -> u32 {
+    12
+}
+
+fn default_queue_size() -> usize {
+    20
+}
+
+fn default_max_age_secs() -> u64 {
+    60
+}
+
+impl Default for ReconnectConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_enabled(),
+            retry_secs: default_retry_secs(),
+            max_attempts: default_max_attempts(),
+            queue_size: default_queue_size(),
+            max_age_secs: default_max_age_secs(),
+        }
+    }
+}
+
+impl ReconnectConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.retry_secs == 0 || self.max_attempts == 0 || self.max_age_secs == 0 {
+            return Err("lobby_reconnect.retry_secs, max_attempts and max_age_secs must be at least 1".into());
+        }
+        Ok(())
+    }
+}
+
+/// A tool call waiting for the lobby.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueuedCall {
+    pub id: u64,
+    pub tool: String,
+    pub args: serde_json::Value,
+    pub queued_at: Instant,
+}
+
+/// The lobby server we connected to, the login to repeat, and the calls
+/// waiting for it. Lives from `lobby_connect` until `lobby_disconnect`.
+#[derive(Debug)]
+pub struct LobbySession {
+    pub host: String,
+    pub port: u16,
+    /// Username and password hash of the last successful login.
+    pub login: Option<(String, String)>,
+    config: ReconnectConfig,
+    /// Failed attempts so far and when to try next, while reconnecting.
+    reconnect: Option<(u32, Instant)>,
+    queue: VecDeque<QueuedCall>,
+    next_id: u64,
+}
+
+impl LobbySession {
+    pub fn new(host: &str, port: u16, config: ReconnectConfig) -> Self {
+        Self {
+            host: host.to_string(),
+            port,
+            login: None,
+            config,
+            reconnect: None,
+            queue: VecDeque::new(),
+            next_id: 1,
+        }
+    }
+
+    /// The connection dropped: start reconnecting, if the config allows.
+    /// Returns the seconds until the first attempt.
+    pub fn lost(&mut self, now: Instant) -> Option<u64> {
+        if !self.config.enabled {
+            return None;
+        }
+        self.reconnect = Some((0, now + self.retry()));
+        Some(self.config.retry_secs)
+    }
+
+    pub fn reconnecting(&self) -> bool {
+        self.reconnect.is_some()
+    }
+
+    /// Whether a connection attempt is due.
+    pub fn attempt_due(&self, now: Instant) -> bool {
+        self.reconnect.is_some_and(|(_, next)| now >= next)
+    }
+
+    /// An attempt failed. Returns false once the last one has, which ends
+    /// the reconnect.
+    pub fn attempt_failed(&mut self, now: Instant) -> bool {
+        let Some((attempts, _)) = self.reconnect else { return false };
+        if attempts + 1 >= self.config.max_attempts {
+            self.reconnect = None;
+            return false;
+        }
+        self.reconnect = Some((attempts + 1, now + self.retry()));
+        true
+    }
+
+    /// We're connected again (by the reconnect or by hand).
+    pub fn connected(&mut self) {
+        self.reconnect = None;
+    }
+
+    /// Whether a call to `tool` has to wait: it's queueable, and the
+    /// lobby is away or calls before it still wait.
+    pub fn must_queue(&self, tool: &str, connected: bool) -> bool {
+        QUEUEABLE_TOOLS.contains(&tool) && ((!connected && self.reconnecting()) || !self.queue.is_empty())
+    }
+
+    /// Queue a call. Returns its id, or why it can't wait.
+    pub fn queue(&mut self, tool: &str, args: &serde_json::Value, now: Instant) -> Result<u64, String> {
+        if self.queue.len() >= self.config.queue_size {
+            return Err(format!("{} lobby calls are already waiting for the reconnect", self.queue.len()));
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        self.queue.push_back(QueuedCall { id, tool: tool.to_string(), args: args.clone(), queued_at: now });
+        Ok(id)
+    }
+
+    pub fn max_age_secs(&self) -> u64 {
+        self.config.max_age_secs
+    }
+
+    /// Take the calls that have waited too long, oldest first.
+    pub fn expire(&mut self, now: Instant) -> Vec<QueuedCall> {
+        let max_age = Duration::from_secs(self.config.max_age_secs);
+        let mut expired = Vec::new();
+        while self.queue.front().is_some_and(|c| now.duration_since(c.queued_at) >= max_age) {
+            expired.extend(self.queue.pop_front());
+        }
+        expired
+    }
+
+    /// Take every waiting call, oldest first.
+    pub fn take_queue(&mut self) -> Vec<QueuedCall> {
+        self.queue.drain(..).collect()
+    }
+
+    fn retry(&self) -> Duration {
+        Duration::from_secs(self.config.retry_secs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_queue_order_and_expiry() {
+        let now = Instant::now();
+        let config = ReconnectConfig { queue_size: 2, max_age_secs: 60, ..Default::default() };
+        let mut session = LobbySession::new("localhost", 8200, config);
+        assert!(!session.must_queue("lobby_say", false));
+        session.lost(now);
+        assert!(session.must_queue("lobby_say", false) && !session.must_queue("lobby_login", false));
+
+        let say = serde_json::json!({"target": "zk", "text": "brb"});
+        assert_eq!(session.queue("lobby_say", &say, now), Ok(1));
+        assert_eq!(session.queue("lobby_join_channel", &serde_json::json!({"channel": "zk"}), now + Duration::from_secs(30)), Ok(2));
+        assert!(session.queue("lobby_say", &say, now).unwrap_err().contains("2 lobby calls"));
+
+        // Back online, later calls still line up behind the waiting ones.
+        session.connected();
+        assert!(session.must_queue("lobby_say", true));
+        let expired = session.expire(now + Duration::from_secs(60));
+        assert_eq!(expired.iter().map(|c| c.id).collect::<Vec<_>>(), [1]);
+        assert_eq!(session.take_queue()[0].tool, "lobby_join_channel");
+        assert!(!session.must_queue("lobby_say", true));
+    }
+
+    #[test]
+    fn test_attempts_run_out() {
+        let now = Instant::now();
+        let mut session = LobbySession::new("localhost", 8200, ReconnectConfig { max_attempts: 2, ..Default::default() });
+        assert_eq!(session.lost(now), Some(5));
+        assert!(!session.attempt_due(now) && session.attempt_due(now + Duration::from_secs(5)));
+        assert!(session.attempt_failed(now + Duration::from_secs(5)));
+        assert!(!session.attempt_due(now + Duration::from_secs(9)));
+        assert!(!session.attempt_failed(now + Duration::from_secs(10)));
+        assert!(!session.reconnecting());
+
+        let off = ReconnectConfig { enabled: false, ..Default::default() };
+        assert_eq!(LobbySession::new("localhost", 8200, off).lost(now), None);
+        assert!(ReconnectConfig { retry_secs: 0, ..Default::default() }.validate().is_err());
+    }
+}
@@ -0,0 +1,216 @@
+//! Positions a command can name instead of giving x/z: a unit's last
+//! known position, an offset from it, or a named location.
+//!
+//! A command carries them under `location`, e.g.
+//! `{"type": "move", "unit_id": 5, "location": {"named": "base"}}`, and
+//! [`Places::resolve`] turns that into x/z before the command is parsed, so
+//! a unit we've never seen or a name nobody set fails before anything
+//! reaches the bridge. Names are set per channel with `game_set_location`;
+//! `start` is set from the start position when the game begins.
+
+use std::collections::{BTreeMap, HashMap};
+
+use serde::Serialize;
+
+use crate::game_start::SQUARE_SIZE;
+use crate::positions::{MapContext, PositionStyle};
+use crate::sai_ipc::{SaiEvent, UnitId};
+
+/// Location defined for every game from its start position.
+pub const START: &str = "start";
+
+/// A named point on the map.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Place {
+    pub x: f32,
+    pub z: f32,
+}
+
+/// One game channel's named locations and last known unit positions, and
+/// how its event text places points.
+#[derive(Debug, Default)]
+pub struct Places {
+    named: BTreeMap<String, Place>,
+    /// Width and height in elmos, from init.
+    map_size: Option<[f32; 2]>,
+    style: PositionStyle,
+    /// Own units, as last reported.
+    own: HashMap<UnitId, [f32; 3]>,
+    /// Enemies, where last seen.
+    enemies: HashMap<UnitId, [f32; 3]>,
+}
+
+impl Places {
+    pub fn new(style: PositionStyle) -> Self {
+        Self { style, ..Default::default() }
+    }
+
+    pub fn observe(&mut self, event: &SaiEvent) {
+        match event {
+            SaiEvent::Init { start_pos, map_width, map_height, .. } => {
+                if let Some([x, _, z]) = start_pos {
+                    self.named.entry(START.into()).or_insert(Place { x: *x, z: *z });
+                }
+                if let (Some(w), Some(h)) = (map_width, map_height) {
+                    self.map_size = Some([(w * SQUARE_SIZE) as f32, (h * SQUARE_SIZE) as f32]);
+                }
+            }
+            SaiEvent::Roster { units, .. } => {
+                // Our first roster holds the commander, where we started.
+                if let (false, Some(first)) = (self.named.contains_key(START), units.first()) {
+                    self.named.insert(START.into(), Place { x: first.pos[0], z: first.pos[2] });
+                }
+                self.own = units.iter().map(|u| (u.unit, u.pos)).collect();
+            }
+            SaiEvent::UnitCreated { unit, pos: Some(pos), .. }
+            | SaiEvent::UnitFinished { unit, pos: Some(pos), .. }
+            | SaiEvent::UnitGiven { unit, pos: Some(pos), .. }
+            | SaiEvent::UnitDamaged { unit, pos: Some(pos), .. } => {
+                self.own.insert(*unit, *pos);
+            }
+            SaiEvent::UnitDestroyed { unit, .. } | SaiEvent::UnitCaptured { unit, .. } => {
+                self.own.remove(unit);
+            }
+            SaiEvent::EnemyEnterLos { enemy, pos: Some(pos), .. } => {
+                self.enemies.insert(*enemy, *pos);
+            }
+            SaiEvent::EnemyDestroyed { enemy, .. } => {
+                self.enemies.remove(enemy);
+            }
+            _ => {}
+        }
+    }
+
+    pub fn named(&self) -> &BTreeMap<String, Place> {
+        &self.named
+    }
+
+    pub fn style(&self) -> PositionStyle {
+        self.style
+    }
+
+    /// What [`crate::positions::describe`] places points with.
+    pub fn context(&self) -> MapContext<'_> {
+        MapContext { style: self.style, map_size: self.map_size, named: &self.named }
+    }
+
+    /// Name a point, replacing any point of that name.
+    pub fn set(&mut self, name: &str, place: Place) -> Result<(), String> {
+        if name.trim().is_empty() {
+            return Err("A location needs a name".into());
+        }
+        self.named.insert(name.to_string(), place);
+        Ok(())
+    }
+
+    /// Where a location spec points: `{"at_unit": id}`,
+    /// `{"offset_from_unit": id, "dx": .., "dz": ..}` or `{"named": name}`.
+    pub fn locate(&self, spec: &serde_json::Value) -> Result<Place, String> {
+        let unit = |key: &str| -> Result<Option<Place>, String> {
+            let Some(v) = spec.get(key) else { return Ok(None) };
+            let id = v.as_i64().and_then(|id| i32::try_from(id).ok()).ok_or_else(|| format!("{} must be a unit id", key))?;
+            let unit = UnitId(id);
+            let pos = self.own.get(&unit).or(self.enemies.get(&unit)).ok_or_else(|| format!("No known position for unit {}", id))?;
+            Ok(Some(Place { x: pos[0], z: pos[2] }))
+        };
+        if let Some(place) = unit("at_unit")? {
+            return Ok(place);
+        }
+        if let Some(place) = unit("offset_from_unit")? {
+            let offset = |key: &str| match spec.get(key) {
+                None => Ok(0.0),
+                Some(v) => v.as_f64().map(|d| d as f32).ok_or_else(|| format!("{} must be a number", key)),
+            };
+            return Ok(Place { x: place.x + offset("dx")?, z: place.z + offset("dz")? });
+        }
+        if let Some(name) = spec.get("named") {
+            let name = name.as_str().ok_or("named must be a string")?;
+            return self.named.get(name).copied().ok_or_else(|| {
+                let known: Vec<&str> = self.named.keys().map(|k| k.as_str()).collect();
+                if known.is_empty() {
+                    format!("No location named {} (none are set)", name)
+                } else {
+                    format!("No location named {} (known: {})", name, known.join(", "))
+                }
+            });
+        }
+        Err("location needs at_unit, offset_from_unit or named".into())
+    }
+
+    /// Replace a command's `location` with the x/z it points at.
+    pub fn resolve(&self, payload: &mut serde_json::Value) -> Result<(), String> {
+        let Some(fields) = payload.as_object_mut() else { return Ok(()) };
+        let Some(spec) = fields.remove("location") else { return Ok(()) };
+        if fields.contains_key("x") || fields.contains_key("z") {
+            return Err("Give either location or x/z, not both".into());
+        }
+        let place = self.locate(&spec)?;
+        fields.insert("x".into(), place.x.into());
+        fields.insert("z".into(), place.z.into());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sai_protocol::RosterUnit;
+    use serde_json::json;
+
+    fn places() -> Places {
+        let mut places = Places::default();
+        places.observe(&SaiEvent::Roster {
+            frame: 30,
+            units: vec![
+                RosterUnit { unit: UnitId(1), unit_name: None, pos: [1000.0, 10.0, 2000.0] },
+                RosterUnit { unit: UnitId(2), unit_name: None, pos: [1500.0, 10.0, 2500.0] },
+            ],
+        });
+        places.observe(&SaiEvent::EnemyEnterLos {
+            enemy: UnitId(812), enemy_name: None, team: None, relation: None, pos: Some([4000.0, 0.0, 3000.0]),
+        });
+        places
+    }
+
+    #[test]
+    fn test_locate() {
+        let mut places = places();
+        assert_eq!(places.named()[START], Place { x: 1000.0, z: 2000.0 }, "start falls back to the first roster");
+        assert_eq!(places.locate(&json!({"at_unit": 812})), Ok(Place { x: 4000.0, z: 3000.0 }));
+        assert_eq!(
+            places.locate(&json!({"offset_from_unit": 2, "dx": -200, "dz": 50.5})),
+            Ok(Place { x: 1300.0, z: 2550.5 })
+        );
+        assert_eq!(places.locate(&json!({"at_unit": 9})).unwrap_err(), "No known position for unit 9");
+        assert_eq!(places.locate(&json!({"named": "base"})).unwrap_err(), "No location named base (known: start)");
+        places.set("base", Place { x: 800.0, z: 900.0 }).unwrap();
+        assert_eq!(places.locate(&json!({"named": "base"})), Ok(Place { x: 800.0, z: 900.0 }));
+        assert!(places.locate(&json!({"offset_from_unit": 2, "dx": "far"})).unwrap_err().contains("dx must be a number"));
+        assert!(places.locate(&json!({})).is_err());
+
+        places.observe(&SaiEvent::EnemyDestroyed {
+            enemy: UnitId(812), enemy_name: None, team: None, relation: None,
+            attacker: UnitId(1), attacker_name: None, attacker_team: None, attacker_relation: None,
+        });
+        assert!(places.locate(&json!({"at_unit": 812})).is_err(), "dead units have no position");
+    }
+
+    #[test]
+    fn test_resolve_command() {
+        let mut places = places();
+        places.observe(&SaiEvent::Init {
+            frame: 0, saved_game: false, protocol_version: None, metal_spots: None,
+            map_width: None, map_height: None, start_pos: Some([50.0, 0.0, 60.0]), teams: Vec::new(), build: None,
+        });
+        assert_eq!(places.named()[START].x, 1000.0, "an earlier start stays");
+
+        let mut command = json!({"type": "move", "unit_id": 1, "location": {"at_unit": 812}});
+        places.resolve(&mut command).unwrap();
+        assert_eq!(command, json!({"type": "move", "unit_id": 1, "x": 4000.0, "z": 3000.0}));
+        let mut plain = json!({"type": "stop", "unit_id": 1});
+        places.resolve(&mut plain).unwrap();
+        assert_eq!(plain, json!({"type": "stop", "unit_id": 1}));
+        let mut both = json!({"type": "move", "unit_id": 1, "x": 5, "location": {"named": "start"}});
+        assert_eq!(places.resolve(&mut both).unwrap_err(), "Give either location or x/z, not both");
+    }
+}
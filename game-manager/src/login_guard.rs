@@ -0,0 +1,191 @@
+//! What a failed lobby login means for the agent, and a cool-down that
+//! keeps it from hammering the server. Zero-K bans addresses that fail to
+//! log in too often, and an agent told "code 2" tends to try again at once.
+//!
+//! Each `LoginResponse` code maps to a reason and whether trying again can
+//! help. After a failure the account cools down, twice as long for each
+//! failure in a row up to [`MAX_COOLDOWN`]; a login for it meanwhile fails
+//! here without reaching the server. A successful login clears it.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::lobby::protocol::{LoginResponseData, LOGIN_BANNED, LOGIN_INVALID_NAME, LOGIN_INVALID_PASSWORD};
+
+/// Cool-down after a first failure.
+pub const BASE_COOLDOWN: Duration = Duration::from_secs(15);
+/// Longest cool-down, however many failures in a row.
+pub const MAX_COOLDOWN: Duration = Duration::from_secs(300);
+
+/// Why a login failed, and what the agent can do about it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoginFailure {
+    /// The server's result code; None for failures decided here.
+    pub code: Option<i32>,
+    pub reason: &'static str,
+    pub message: String,
+    /// The server's ban reason, verbatim.
+    pub ban_reason: Option<String>,
+    /// Trying again with the same username and password can work.
+    pub retryable: bool,
+    /// Seconds before the next attempt is let through.
+    pub retry_after: Option<u64>,
+}
+
+impl LoginFailure {
+    /// Classify a failed `LoginResponse`.
+    pub fn from_response(resp: &LoginResponseData) -> Self {
+        let (reason, retryable) = match resp.result_code {
+            LOGIN_INVALID_NAME => ("invalid_name", false),
+            LOGIN_INVALID_PASSWORD => ("invalid_password", false),
+            LOGIN_BANNED => ("banned", false),
+            _ => ("rejected", true),
+        };
+        Self {
+            code: Some(resp.result_code),
+            reason,
+            message: resp.message.clone(),
+            ban_reason: resp.ban_reason.clone().filter(|r| !r.is_empty()),
+            retryable,
+            retry_after: None,
+        }
+    }
+
+    /// A login for an account that is still cooling down.
+    pub fn cooling_down(username: &str, left: Duration) -> Self {
+        Self {
+            code: None,
+            reason: "cooling_down",
+            message: format!("Login for '{}' failed recently; not sent to the server", username),
+            ban_reason: None,
+            retryable: true,
+            retry_after: Some(left.as_secs().max(1)),
+        }
+    }
+
+    /// A login while already logged in.
+    pub fn already_logged_in(username: &str) -> Self {
+        Self {
+            code: None,
+            reason: "already_logged_in",
+            message: format!("Already logged in as '{}'", username),
+            ban_reason: None,
+            retryable: false,
+            retry_after: None,
+        }
+    }
+
+    /// The tool result text: what happened and what to do next.
+    pub fn text(&self) -> String {
+        let mut text = match self.code {
+            Some(code) => format!("Login failed (code {}): {}", code, self.message),
+            None => self.message.clone(),
+        };
+        if let Some(ban_reason) = &self.ban_reason {
+            text += &format!(". Ban reason: {}", ban_reason);
+        }
+        let advice = match self.reason {
+            "invalid_name" => "No such account: check the username, or lobby_register it",
+            "invalid_password" => "Check the password; retrying the same one won't help",
+            "banned" => "Do not retry: the account is banned",
+            "already_logged_in" => "lobby_disconnect first to log in as someone else",
+            _ => "Wait before retrying",
+        };
+        text += &format!(". {}", advice);
+        if let Some(secs) = self.retry_after {
+            text += &format!(" (next attempt allowed in {}s)", secs);
+        }
+        text
+    }
+
+    /// The tool result's `structuredContent`.
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "loggedIn": false,
+            "code": self.code,
+            "reason": self.reason,
+            "message": self.message,
+            "banReason": self.ban_reason,
+            "retryable": self.retryable,
+            "retryAfter": self.retry_after,
+        })
+    }
+}
+
+/// Failures in a row per account, and when each may try again.
+#[derive(Debug, Default)]
+pub struct LoginGuard {
+    accounts: HashMap<String, (u32, Instant)>,
+}
+
+impl LoginGuard {
+    /// The cool-down left for `username`, if it is cooling down.
+    pub fn cooling_down(&self, username: &str, now: Instant) -> Option<Duration> {
+        let (_, until) = self.accounts.get(username)?;
+        until.checked_duration_since(now).filter(|left| !left.is_zero())
+    }
+
+    /// Record a failed login; returns the cool-down it starts.
+    pub fn failed(&mut self, username: &str, now: Instant) -> Duration {
+        let failures = self.accounts.get(username).map_or(0, |(n, _)| *n) + 1;
+        let cooldown = BASE_COOLDOWN.saturating_mul(1 << (failures - 1).min(8)).min(MAX_COOLDOWN);
+        self.accounts.insert(username.to_string(), (failures, now + cooldown));
+        cooldown
+    }
+
+    pub fn succeeded(&mut self, username: &str) {
+        self.accounts.remove(username);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(code: i32, message: &str, ban_reason: Option<&str>) -> LoginResponseData {
+        LoginResponseData {
+            result_code: code,
+            name: String::new(),
+            message: message.into(),
+            ban_reason: ban_reason.map(String::from),
+            session_token: None,
+        }
+    }
+
+    #[test]
+    fn test_failure_reasons() {
+        let banned = LoginFailure::from_response(&response(LOGIN_BANNED, "Banned", Some("Spamming  ranked games ")));
+        assert_eq!((banned.reason, banned.retryable), ("banned", false));
+        assert_eq!(banned.ban_reason.as_deref(), Some("Spamming  ranked games "));
+        assert_eq!(
+            banned.text(),
+            "Login failed (code 4): Banned. Ban reason: Spamming  ranked games . Do not retry: the account is banned"
+        );
+        assert_eq!(LoginFailure::from_response(&response(LOGIN_INVALID_NAME, "", None)).reason, "invalid_name");
+        let unknown = LoginFailure::from_response(&response(9, "Try later", Some("")));
+        assert_eq!((unknown.reason, unknown.retryable, unknown.ban_reason), ("rejected", true, None));
+
+        let cooling = LoginFailure::cooling_down("agent", Duration::from_millis(14_200));
+        assert_eq!(cooling.to_json()["retryAfter"], 14);
+        assert!(cooling.text().ends_with("Wait before retrying (next attempt allowed in 14s)"), "{}", cooling.text());
+    }
+
+    #[test]
+    fn test_cooldown_doubles_and_clears() {
+        let mut guard = LoginGuard::default();
+        let now = Instant::now();
+        assert_eq!(guard.cooling_down("agent", now), None);
+        assert_eq!(guard.failed("agent", now), BASE_COOLDOWN);
+        assert_eq!(guard.cooling_down("agent", now + Duration::from_secs(5)), Some(Duration::from_secs(10)));
+        assert_eq!(guard.cooling_down("other", now), None);
+        assert_eq!(guard.cooling_down("agent", now + BASE_COOLDOWN), None);
+
+        assert_eq!(guard.failed("agent", now), BASE_COOLDOWN * 2);
+        for _ in 0..10 {
+            guard.failed("agent", now);
+        }
+        assert_eq!(guard.cooling_down("agent", now), Some(MAX_COOLDOWN));
+        guard.succeeded("agent");
+        assert_eq!(guard.cooling_down("agent", now), None);
+    }
+}
@@ -0,0 +1,361 @@
+//! Command macros: named lists of command templates, defined with
+//! `gm_macro_define` and sent with `gm_macro_run`.
+//!
+//! A template is command JSON, as `game_command` takes it, with `${name}`
+//! placeholders anywhere in it. A string that is exactly one placeholder
+//! becomes the parameter's value, typed; a placeholder inside a longer
+//! string is spliced in as text. Every placeholder has a declared type, so
+//! a definition whose placeholders can't fill the fields they sit in is
+//! refused up front, and so is a run whose parameters don't match.
+//!
+//! Macros are kept in `gm_macros.json` in the write dir.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::groups::UnitGroups;
+use crate::locations::Places;
+use crate::profiles::GameProfile;
+use crate::sai_ipc::SaiCommand;
+
+/// Macro file in the write dir.
+pub const MACROS_FILE: &str = "gm_macros.json";
+
+/// Commands a macro may hold.
+pub const MAX_COMMANDS: usize = 50;
+
+/// What a parameter can be given.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ParamType {
+    /// A unit id.
+    Unit,
+    /// A coordinate or any other number.
+    Number,
+    /// A def name, group name or text.
+    String,
+    Bool,
+}
+
+impl ParamType {
+    fn as_str(self) -> &'static str {
+        match self {
+            ParamType::Unit => "a unit id",
+            ParamType::Number => "a number",
+            ParamType::String => "a string",
+            ParamType::Bool => "a boolean",
+        }
+    }
+
+    fn accepts(self, value: &serde_json::Value) -> bool {
+        match self {
+            ParamType::Unit => value.as_u64().is_some_and(|id| id <= i32::MAX as u64),
+            ParamType::Number => value.is_number(),
+            ParamType::String => value.is_string(),
+            ParamType::Bool => value.is_boolean(),
+        }
+    }
+
+    /// A value of this type, to check a template's shape with.
+    fn sample(self) -> serde_json::Value {
+        match self {
+            ParamType::Unit => 1.into(),
+            ParamType::Number => 0.0.into(),
+            ParamType::String => "sample".into(),
+            ParamType::Bool => false.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Macro {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub params: BTreeMap<String, ParamType>,
+    pub commands: Vec<serde_json::Value>,
+}
+
+impl Macro {
+    /// Check that every placeholder is declared and every template, with
+    /// sample values in, is a command.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.commands.is_empty() || self.commands.len() > MAX_COMMANDS {
+            return Err(format!("A macro needs 1 to {} commands", MAX_COMMANDS));
+        }
+        let samples: BTreeMap<String, serde_json::Value> =
+            self.params.iter().map(|(name, ty)| (name.clone(), ty.sample())).collect();
+        for (i, template) in self.commands.iter().enumerate() {
+            let mut payload = fill(template, &samples).map_err(|e| format!("Command {}: {}", i + 1, e))?;
+            // Groups and locations are checked when the macro runs; here
+            // stand in a unit and a point. Whether the game has a
+            // convenience command is too.
+            if let Some(o) = payload.as_object_mut() {
+                if o.remove("group").is_some() {
+                    o.insert("unit_id".into(), 1.into());
+                }
+                if o.remove("location").is_some() {
+                    o.insert("x".into(), 0.0.into());
+                    o.insert("z".into(), 0.0.into());
+                }
+            }
+            GameProfile::stand_in().resolve(&mut payload).map_err(|e| format!("Command {}: {}", i + 1, e))?;
+            serde_json::from_value::<SaiCommand>(payload)
+                .map_err(|e| format!("Command {} is not a valid command: {}", i + 1, e))?;
+        }
+        Ok(())
+    }
+
+    /// The macro's commands with `params` filled in. Locations are
+    /// resolved with the channel's `places`, convenience commands with its
+    /// game's `profile`, and groups expanded with its `groups`.
+    pub fn expand(
+        &self,
+        params: &serde_json::Map<String, serde_json::Value>,
+        groups: Option<&UnitGroups>,
+        places: Option<&Places>,
+        profile: &GameProfile,
+    ) -> Result<Vec<SaiCommand>, String> {
+        if let Some(name) = params.keys().find(|name| !self.params.contains_key(*name)) {
+            return Err(format!("Unknown parameter {}", name));
+        }
+        let mut values = BTreeMap::new();
+        for (name, ty) in &self.params {
+            let value = params.get(name).ok_or_else(|| format!("Missing parameter {}", name))?;
+            if !ty.accepts(value) {
+                return Err(format!("Parameter {} must be {}, got {}", name, ty.as_str(), value));
+            }
+            values.insert(name.clone(), value.clone());
+        }
+        let groups = match groups {
+            Some(groups) => groups,
+            None => &UnitGroups::default(),
+        };
+        let places = match places {
+            Some(places) => places,
+            None => &Places::default(),
+        };
+        let mut commands = Vec::new();
+        for (i, template) in self.commands.iter().enumerate() {
+            let mut payload = fill(template, &values)?;
+            places.resolve(&mut payload).map_err(|e| format!("Command {}: {}", i + 1, e))?;
+            profile.resolve(&mut payload).map_err(|e| format!("Command {}: {}", i + 1, e))?;
+            commands.extend(groups.expand(payload).map_err(|e| format!("Command {}: {}", i + 1, e))?);
+        }
+        Ok(commands)
+    }
+}
+
+/// Replace the placeholders in `template` with `values`.
+fn fill(
+    template: &serde_json::Value,
+    values: &BTreeMap<String, serde_json::Value>,
+) -> Result<serde_json::Value, String> {
+    use serde_json::Value;
+    Ok(match template {
+        Value::String(s) => {
+            if let Some(name) = whole_placeholder(s) {
+                return values.get(name).cloned().ok_or_else(|| undeclared(name));
+            }
+            let mut out = String::new();
+            let mut rest = s.as_str();
+            while let Some(start) = rest.find("${") {
+                let end = rest[start..].find('}').ok_or_else(|| format!("Unclosed placeholder in \"{}\"", s))?;
+                let name = &rest[start + 2..start + end];
+                let value = values.get(name).ok_or_else(|| undeclared(name))?;
+                out.push_str(&rest[..start]);
+                match value {
+                    Value::String(text) => out.push_str(text),
+                    other => out.push_str(&other.to_string()),
+                }
+                rest = &rest[start + end + 1..];
+            }
+            out.push_str(rest);
+            Value::String(out)
+        }
+        Value::Array(items) => Value::Array(items.iter().map(|v| fill(v, values)).collect::<Result<_, _>>()?),
+        Value::Object(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|(k, v)| Ok((k.clone(), fill(v, values)?)))
+                .collect::<Result<_, String>>()?,
+        ),
+        other => other.clone(),
+    })
+}
+
+/// `name` if `s` is exactly `${name}`.
+fn whole_placeholder(s: &str) -> Option<&str> {
+    let name = s.strip_prefix("${")?.strip_suffix('}')?;
+    (!name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')).then_some(name)
+}
+
+fn undeclared(name: &str) -> String {
+    format!("Placeholder ${{{}}} is not a declared parameter", name)
+}
+
+/// Every macro, as saved in the write dir.
+#[derive(Debug, Default)]
+pub struct MacroBook {
+    macros: BTreeMap<String, Macro>,
+    path: Option<PathBuf>,
+}
+
+impl MacroBook {
+    /// The macros saved in `write_dir`. A missing or unreadable file is an
+    /// empty book; the latter is logged.
+    pub fn load(write_dir: &Path) -> Self {
+        let path = write_dir.join(MACROS_FILE);
+        let macros = match std::fs::read_to_string(&path) {
+            Ok(text) => serde_json::from_str(&text).unwrap_or_else(|e| {
+                tracing::warn!("Ignoring unreadable {}: {}", path.display(), e);
+                BTreeMap::new()
+            }),
+            Err(_) => BTreeMap::new(),
+        };
+        Self { macros, path: Some(path) }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Macro> {
+        self.macros.get(name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Macro)> {
+        self.macros.iter()
+    }
+
+    /// Add or replace a macro and save the book. Returns whether one was
+    /// replaced.
+    pub fn define(&mut self, name: &str, definition: Macro) -> Result<bool, String> {
+        if name.trim().is_empty() {
+            return Err("A macro needs a name".into());
+        }
+        definition.validate()?;
+        let replaced = self.macros.insert(name.to_string(), definition).is_some();
+        if let Some(path) = &self.path {
+            path.parent()
+                .map_or(Ok(()), std::fs::create_dir_all)
+                .and_then(|_| std::fs::write(path, serde_json::to_string_pretty(&self.macros).unwrap()))
+                .map_err(|e| format!("Failed to save {}: {}", path.display(), e))?;
+        }
+        Ok(replaced)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sai_ipc::UnitId;
+    use serde_json::json;
+
+    fn rally() -> Macro {
+        serde_json::from_value(json!({
+            "description": "Queue raiders and send them to a point",
+            "params": {"factory": "unit", "raider": "string", "x": "number", "z": "number", "note": "string"},
+            "commands": [
+                {"type": "build", "unit_id": "${factory}", "build_def_name": "${raider}", "queue": true},
+                {"type": "move", "unit_id": "${factory}", "x": "${x}", "z": "${z}"},
+                {"type": "send_chat", "text": "Raiders of ${raider} to ${x},${z}: ${note}"}
+            ]
+        }))
+        .unwrap()
+    }
+
+    fn params(value: serde_json::Value) -> serde_json::Map<String, serde_json::Value> {
+        value.as_object().unwrap().clone()
+    }
+
+    #[test]
+    fn test_expand_typed_and_spliced() {
+        let rally = rally();
+        rally.validate().unwrap();
+        let commands = rally
+            .expand(&params(json!({"factory": 7, "raider": "cloakraid", "x": 1200.5, "z": 800, "note": "go"})), None, None, &GameProfile::default())
+            .unwrap();
+        assert_eq!(commands.len(), 3);
+        assert!(matches!(&commands[0], SaiCommand::Build { unit_id: UnitId(7), build_def_name: Some(n), queue: true, .. } if n == "cloakraid"));
+        assert!(matches!(commands[1], SaiCommand::Move { unit_id: UnitId(7), x, z, .. } if x == 1200.5 && z == 800.0));
+        assert!(matches!(&commands[2], SaiCommand::SendChat { text, .. } if text == "Raiders of cloakraid to 1200.5,800: go"));
+    }
+
+    #[test]
+    fn test_nested_templates() {
+        let values: BTreeMap<String, serde_json::Value> =
+            [("unit".to_string(), json!(3)), ("name".to_string(), json!("a"))].into();
+        let template = json!({"outer": {"list": ["${unit}", {"deep": "${name}-${unit}"}], "keep": 1.5}});
+        assert_eq!(
+            fill(&template, &values).unwrap(),
+            json!({"outer": {"list": [3, {"deep": "a-3"}], "keep": 1.5}})
+        );
+        assert_eq!(fill(&json!(["${nope}"]), &values).unwrap_err(), "Placeholder ${nope} is not a declared parameter");
+        assert!(fill(&json!("${unit"), &values).unwrap_err().starts_with("Unclosed placeholder"));
+    }
+
+    #[test]
+    fn test_parameter_errors() {
+        let rally = rally();
+        let ok = json!({"factory": 7, "raider": "cloakraid", "x": 1, "z": 2, "note": ""});
+        let mut missing = ok.clone();
+        missing.as_object_mut().unwrap().remove("z");
+        assert_eq!(rally.expand(&params(missing), None, None, &GameProfile::default()).unwrap_err(), "Missing parameter z");
+        let mut wrong = ok.clone();
+        wrong["x"] = json!("left");
+        assert_eq!(rally.expand(&params(wrong), None, None, &GameProfile::default()).unwrap_err(), "Parameter x must be a number, got \"left\"");
+        let mut negative = ok.clone();
+        negative["factory"] = json!(-1);
+        assert!(rally.expand(&params(negative), None, None, &GameProfile::default()).unwrap_err().contains("must be a unit id"));
+        let mut extra = ok;
+        extra["speed"] = json!(2);
+        assert_eq!(rally.expand(&params(extra), None, None, &GameProfile::default()).unwrap_err(), "Unknown parameter speed");
+    }
+
+    #[test]
+    fn test_definitions_checked() {
+        // A string placeholder can't fill a coordinate.
+        let bad: Macro = serde_json::from_value(json!({
+            "params": {"u": "unit", "where": "string"},
+            "commands": [{"type": "move", "unit_id": "${u}", "x": "${where}", "z": 0}]
+        }))
+        .unwrap();
+        assert!(bad.validate().unwrap_err().starts_with("Command 1 is not a valid command"));
+        let undeclared: Macro =
+            serde_json::from_value(json!({"commands": [{"type": "stop", "unit_id": "${u}"}]})).unwrap();
+        assert_eq!(undeclared.validate().unwrap_err(), "Command 1: Placeholder ${u} is not a declared parameter");
+        let grouped: Macro = serde_json::from_value(json!({
+            "params": {"g": "string"},
+            "commands": [{"type": "stop", "group": "${g}"}]
+        }))
+        .unwrap();
+        grouped.validate().unwrap();
+        assert_eq!(grouped.expand(&params(json!({"g": "raiders"})), None, None, &GameProfile::default()).unwrap_err(), "Command 1: Unknown group raiders");
+
+        // Locations resolve at run time, from the channel's places.
+        let retreat: Macro = serde_json::from_value(json!({
+            "params": {"u": "unit", "spot": "string"},
+            "commands": [{"type": "move", "unit_id": "${u}", "location": {"named": "${spot}"}}]
+        }))
+        .unwrap();
+        retreat.validate().unwrap();
+        let mut places = Places::default();
+        places.set("base", crate::locations::Place { x: 300.0, z: 400.0 }).unwrap();
+        let run = params(json!({"u": 4, "spot": "base"}));
+        let commands = retreat.expand(&run, None, Some(&places), &GameProfile::default()).unwrap();
+        assert!(matches!(commands[0], SaiCommand::Move { x, z, .. } if x == 300.0 && z == 400.0));
+        let elsewhere = params(json!({"u": 4, "spot": "hill"}));
+        assert!(retreat.expand(&elsewhere, None, Some(&places), &GameProfile::default()).unwrap_err().starts_with("Command 1: No location named hill"));
+    }
+
+    #[test]
+    fn test_book_persists() {
+        let dir = std::env::temp_dir().join(format!("gm-macros-{}", uuid::Uuid::new_v4()));
+        let mut book = MacroBook::load(&dir);
+        assert_eq!(book.define("rally", rally()), Ok(false));
+        assert_eq!(book.define("rally", rally()), Ok(true));
+        let reloaded = MacroBook::load(&dir);
+        assert_eq!(reloaded.get("rally"), Some(&rally()));
+        assert_eq!(reloaded.iter().count(), 1);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
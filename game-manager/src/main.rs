@@ -1,1666 +1,146 @@
+// The tools/list schema in mcpl_server.rs is one large json! literal.
+#![recursion_limit = "256"]
+
+mod analysis;
+mod army;
+mod assist;
+mod audit;
+mod autorespond;
+mod benchmark;
+mod channel_changes;
+mod channel_ids;
+mod chaos;
+mod closing;
+mod command_history;
+mod config;
+mod content;
+mod convert;
+mod credentials;
+mod doctor;
+mod economy_alerts;
 mod engine;
+mod engine_env;
+mod engine_install;
+mod eta;
+mod expansion;
+mod factories;
+mod game_start;
+mod groups;
+mod mcpl_link;
+mod idle_builders;
+mod income;
 mod lobby;
+mod lobby_reconnect;
+mod locations;
+mod login_guard;
+mod macros;
+mod map_grid;
 mod mcpl_server;
+#[cfg(test)]
+mod mcpl_test_client;
+mod observer;
+mod opponents;
+mod queries;
+mod pacing;
+mod policy;
+mod positions;
+mod profiles;
+mod ready;
+mod recording;
+mod reload;
+mod replay;
 mod sai_ipc;
+mod scope;
+mod self_test;
+mod service;
+mod socket_dir;
+mod status_page;
+mod threats;
+mod unit_defs;
+mod unit_history;
+mod waiters;
 mod write_dir;
 
-use engine::EngineManager;
-use lobby::*;
-use mcpl_core::connection::IncomingMessage as McplIncoming;
-use mcpl_core::methods::*;
 use mcpl_core::types::*;
-use sai_ipc::SaiIpcServer;
+use service::GameManager;
 use write_dir::WriteDirConfig;
 
-use std::path::PathBuf;
 use tokio::net::TcpListener;
 
-struct GameManager {
-    mcpl: Option<mcpl_core::McplConnection>,
-    lobby_conn: Option<LobbyConnection>,
-    lobby_state: LobbyState,
-    engines: EngineManager,
-    sai: SaiIpcServer,
-    write_dir: PathBuf,
-    spring_home: PathBuf,
-    agent_name: String,
-}
-
-impl GameManager {
-    fn new(write_dir_config: &WriteDirConfig, engine_dir: PathBuf, socket_dir: String) -> Self {
-        Self {
-            mcpl: None,
-            lobby_conn: None,
-            lobby_state: LobbyState::new(),
-            engines: EngineManager::new(
-                engine_dir,
-                write_dir_config.write_dir.clone(),
-                socket_dir,
-            ),
-            sai: SaiIpcServer::new(),
-            write_dir: write_dir_config.write_dir.clone(),
-            spring_home: write_dir_config.spring_home.clone(),
-            agent_name: write_dir_config.agent_name.clone(),
-        }
-    }
-
-    /// Handle an MCPL tool call from the AF client.
-    async fn handle_tool_call(
-        &mut self,
-        name: &str,
-        args: &serde_json::Value,
-    ) -> serde_json::Value {
-        match name {
-            "lobby_connect" => self.tool_lobby_connect(args).await,
-            "lobby_login" => self.tool_lobby_login(args).await,
-            "lobby_register" => self.tool_lobby_register(args).await,
-            "lobby_disconnect" => self.tool_lobby_disconnect().await,
-            "lobby_say" => self.tool_lobby_say(args).await,
-            "lobby_join_channel" => self.tool_lobby_join_channel(args).await,
-            "lobby_leave_channel" => self.tool_lobby_leave_channel(args).await,
-            "lobby_list_battles" => self.tool_lobby_list_battles().await,
-            "lobby_list_users" => self.tool_lobby_list_users(args).await,
-            "lobby_join_battle" => self.tool_lobby_join_battle(args).await,
-            "lobby_leave_battle" => self.tool_lobby_leave_battle().await,
-            "lobby_matchmaker_join" => self.tool_lobby_matchmaker_join(args).await,
-            "lobby_matchmaker_leave" => self.tool_lobby_matchmaker_leave().await,
-            "lobby_matchmaker_accept" => self.tool_lobby_matchmaker_accept(args).await,
-            "lobby_matchmaker_status" => self.tool_lobby_matchmaker_status().await,
-            "lobby_start_game" => self.tool_lobby_start_game(args).await,
-            "lobby_open_battle" => self.tool_lobby_open_battle(args).await,
-            "lobby_add_bot" => self.tool_lobby_add_bot(args).await,
-            "lobby_remove_bot" => self.tool_lobby_remove_bot(args).await,
-            "lobby_start_battle" => self.tool_lobby_start_battle().await,
-            _ => serde_json::json!({
-                "content": [{"type": "text", "text": format!("Unknown tool: {}", name)}],
-                "isError": true
-            }),
-        }
-    }
-
-    // ── MCPL channel methods ──
-
-    async fn handle_channels_open(
-        &mut self,
-        params: &serde_json::Value,
-    ) -> serde_json::Value {
-        let map = params
-            .get("address")
-            .and_then(|a| a.get("map"))
-            .and_then(|v| v.as_str())
-            .unwrap_or("Chicken Defence 1.56");
-        let game = params
-            .get("address")
-            .and_then(|a| a.get("game"))
-            .and_then(|v| v.as_str())
-            .unwrap_or("Zero-K v1.12.1.0");
-        let opponent = params
-            .get("address")
-            .and_then(|a| a.get("opponent"))
-            .and_then(|v| v.as_str());
-        let player_mode = params
-            .get("address")
-            .and_then(|a| a.get("player_mode"))
-            .and_then(|v| v.as_bool())
-            .unwrap_or(false);
-        let headless = if player_mode {
-            false
-        } else {
-            params
-                .get("address")
-                .and_then(|a| a.get("headless"))
-                .and_then(|v| v.as_bool())
-                .unwrap_or(true)
-        };
-
-        match self.engines.start_local_game(map, game, opponent, headless, player_mode, &self.agent_name).await {
-            Ok(channel_id) => {
-                // Set up SAI IPC listener for this channel
-                let socket_path = self
-                    .engines
-                    .instances
-                    .get(&channel_id)
-                    .map(|i| i.config.socket_path.clone())
-                    .unwrap_or_default();
-
-                if let Err(e) = self.sai.listen_for(&channel_id, &socket_path) {
-                    tracing::error!("Failed to set up SAI listener: {}", e);
-                }
-
-                // Send channels/changed notification
-                self.send_channels_changed(
-                    vec![ChannelDescriptor {
-                        id: channel_id.clone(),
-                        channel_type: "game".into(),
-                        label: format!("Game on {}", map),
-                        direction: ChannelDirection::Bidirectional,
-                        address: None,
-                        metadata: Some(serde_json::json!({
-                            "map": map,
-                            "game": game,
-                            "status": "starting",
-                        })),
-                    }],
-                    vec![],
-                    vec![],
-                )
-                .await;
-
-                serde_json::json!({
-                    "channel": {
-                        "id": channel_id,
-                        "type": "game",
-                        "label": format!("Game on {}", map),
-                        "direction": "bidirectional",
-                        "metadata": {
-                            "map": map,
-                            "game": game,
-                            "status": "starting"
-                        }
-                    }
-                })
-            }
-            Err(e) => serde_json::json!({
-                "error": { "code": -32000, "message": e }
-            }),
-        }
-    }
-
-    async fn handle_channels_close(
-        &mut self,
-        params: &serde_json::Value,
-    ) -> serde_json::Value {
-        let channel_id = match params.get("channelId").and_then(|v| v.as_str()) {
-            Some(id) => id.to_string(),
-            None => {
-                return serde_json::json!({
-                    "closed": false,
-                    "error": "Missing channelId"
-                })
-            }
-        };
-
-        self.sai.close_channel(&channel_id);
-        if let Err(e) = self.engines.stop_game(&channel_id).await {
-            return serde_json::json!({
-                "closed": false,
-                "error": e
-            });
-        }
-
-        // Notify channels/changed
-        self.send_channels_changed(vec![], vec![channel_id], vec![])
-            .await;
-
-        serde_json::json!({ "closed": true })
-    }
-
-    async fn handle_channels_list(&self) -> serde_json::Value {
-        let channels: Vec<serde_json::Value> = self
-            .engines
-            .instances
-            .iter()
-            .map(|(id, inst)| {
-                let connected = self.sai.connections.contains_key(id);
-                serde_json::json!({
-                    "id": id,
-                    "type": "game",
-                    "label": format!("Game on {}", inst.config.map),
-                    "direction": "bidirectional",
-                    "metadata": {
-                        "map": inst.config.map,
-                        "game": inst.config.game,
-                        "status": format!("{:?}", inst.status),
-                        "saiConnected": connected,
-                    }
-                })
-            })
-            .collect();
-
-        serde_json::json!({ "channels": channels })
-    }
-
-    async fn handle_channels_publish(
-        &mut self,
-        params: &serde_json::Value,
-    ) -> serde_json::Value {
-        let channel_id = match params.get("channelId").and_then(|v| v.as_str()) {
-            Some(id) => id,
-            None => {
-                return serde_json::json!({
-                    "delivered": false,
-                    "error": "Missing channelId"
-                })
-            }
-        };
-
-        let content = params
-            .get("content")
-            .and_then(|c| c.as_array())
-            .and_then(|arr| arr.first())
-            .and_then(|block| block.get("text"))
-            .map(|v| {
-                if let Some(s) = v.as_str() {
-                    s.to_string()
-                } else {
-                    // Agent sent JSON object instead of string — stringify it
-                    v.to_string()
-                }
-            })
-            .unwrap_or_default();
-
-        let cmd = match sai_ipc::parse_publish_command(&content) {
-            Ok(c) => c,
-            Err(e) => {
-                return serde_json::json!({
-                    "delivered": false,
-                    "error": e
-                })
-            }
-        };
-
-        match self.sai.send_to(channel_id, &cmd).await {
-            Ok(()) => serde_json::json!({
-                "delivered": true,
-                "messageId": uuid::Uuid::new_v4().to_string()
-            }),
-            Err(e) => serde_json::json!({
-                "delivered": false,
-                "error": e
-            }),
-        }
-    }
-
-    async fn handle_state_rollback(
-        &mut self,
-        params: &serde_json::Value,
-    ) -> serde_json::Value {
-        let _feature_set = params
-            .get("featureSet")
-            .and_then(|v| v.as_str())
-            .unwrap_or("game");
-        let checkpoint = params
-            .get("checkpoint")
-            .and_then(|v| v.as_str())
-            .unwrap_or("");
-
-        // For now, rollback is a placeholder — full implementation
-        // requires engine savestate support
-        serde_json::json!({
-            "success": false,
-            "checkpoint": checkpoint,
-            "reason": "Rollback not yet implemented — requires engine savestate support"
-        })
-    }
-
-    // ── Notification helpers ──
-
-    async fn send_channels_changed(
-        &mut self,
-        added: Vec<ChannelDescriptor>,
-        removed: Vec<String>,
-        updated: Vec<ChannelDescriptor>,
-    ) {
-        if let Some(mcpl) = &mut self.mcpl {
-            let params = ChannelsChangedParams {
-                added: if added.is_empty() {
-                    None
-                } else {
-                    Some(added)
-                },
-                removed: if removed.is_empty() {
-                    None
-                } else {
-                    Some(removed)
-                },
-                updated: if updated.is_empty() {
-                    None
-                } else {
-                    Some(updated)
-                },
-            };
-            let _ = mcpl
-                .send_notification(
-                    method::CHANNELS_CHANGED,
-                    Some(serde_json::to_value(&params).unwrap()),
-                )
-                .await;
-        }
-    }
-
-    /// Forward a SAI event as channels/incoming to the MCPL client.
-    async fn forward_sai_event(
-        &mut self,
-        channel_id: &str,
-        event: &sai_ipc::SaiEvent,
-    ) {
-        let mcpl = match &mut self.mcpl {
-            Some(c) => c,
-            None => return,
-        };
-
-        let content_text = sai_ipc::event_to_content(event);
-        let msg_id = uuid::Uuid::new_v4().to_string();
-
-        let params = ChannelsIncomingParams {
-            messages: vec![mcpl_core::methods::IncomingChannelMessage {
-                channel_id: channel_id.to_string(),
-                message_id: msg_id,
-                thread_id: None,
-                author: MessageAuthor {
-                    id: "engine".into(),
-                    name: "Game Engine".into(),
-                },
-                content: vec![ContentBlock::text(content_text)],
-                timestamp: chrono::Utc::now().to_rfc3339(),
-                metadata: None,
-            }],
-        };
-
-        let _ = mcpl
-            .send_request(
-                method::CHANNELS_INCOMING,
-                Some(serde_json::to_value(&params).unwrap()),
-            )
-            .await;
-    }
-
-    // ── Lobby tool implementations (unchanged) ──
-
-    async fn tool_lobby_connect(&mut self, args: &serde_json::Value) -> serde_json::Value {
-        let host = args
-            .get("host")
-            .and_then(|v| v.as_str())
-            .unwrap_or("zero-k.info");
-        let port = args.get("port").and_then(|v| v.as_u64()).unwrap_or(8200) as u16;
-
-        match LobbyConnection::connect(host, port).await {
-            Ok(conn) => {
-                self.lobby_conn = Some(conn);
-                serde_json::json!({
-                    "content": [{"type": "text", "text": format!("Connected to {}:{}", host, port)}]
-                })
-            }
-            Err(e) => serde_json::json!({
-                "content": [{"type": "text", "text": format!("Connection failed: {}", e)}],
-                "isError": true
-            }),
-        }
-    }
-
-    /// Read lobby messages until we see `response_command`, or timeout.
-    /// Handles pings and updates lobby state for other messages while waiting.
-    /// Takes the connection out of self to satisfy the borrow checker.
-    async fn await_lobby_response(
-        &mut self,
-        response_command: &str,
-        timeout_secs: u64,
-    ) -> Result<serde_json::Value, String> {
-        let mut conn = match self.lobby_conn.take() {
-            Some(c) => c,
-            None => return Err("Not connected".into()),
-        };
-
-        let deadline = tokio::time::Instant::now()
-            + tokio::time::Duration::from_secs(timeout_secs);
-
-        let result = loop {
-            let remaining = deadline.saturating_duration_since(tokio::time::Instant::now());
-            if remaining.is_zero() {
-                break Err(format!("Timed out waiting for {}", response_command));
-            }
-
-            match tokio::time::timeout(remaining, conn.recv()).await {
-                Ok(Ok(msg)) => {
-                    if msg.command == response_command {
-                        break Ok(msg.data);
-                    }
-                    // Handle keepalive
-                    if msg.command == "Ping" {
-                        let pong = LobbyMessage::new("Ping", serde_json::json!({}));
-                        let _ = conn.send(&pong).await;
-                        continue;
-                    }
-                    // Process other messages for state updates
-                    self.lobby_state.handle_message(&msg);
-                }
-                Ok(Err(e)) => {
-                    self.lobby_conn = Some(conn);
-                    return Err(format!("Connection error: {}", e));
-                }
-                Err(_) => {
-                    break Err(format!("Timed out waiting for {}", response_command));
-                }
-            }
-        };
-
-        self.lobby_conn = Some(conn);
-        result
-    }
-
-    async fn tool_lobby_login(&mut self, args: &serde_json::Value) -> serde_json::Value {
-        let username = match args.get("username").and_then(|v| v.as_str()) {
-            Some(u) => u.to_string(),
-            None => {
-                return serde_json::json!({
-                    "content": [{"type": "text", "text": "Missing username"}],
-                    "isError": true
-                })
-            }
-        };
-        let password = match args.get("password").and_then(|v| v.as_str()) {
-            Some(p) => p,
-            None => {
-                return serde_json::json!({
-                    "content": [{"type": "text", "text": "Missing password"}],
-                    "isError": true
-                })
-            }
-        };
-
-        if self.lobby_conn.is_none() {
-            return serde_json::json!({
-                "content": [{"type": "text", "text": "Not connected to lobby. Call lobby_connect first."}],
-                "isError": true
-            });
-        }
-
-        let cmd = LoginCommand {
-            name: username.clone(),
-            password_hash: hash_password(password),
-            user_id: 0,
-            install_id: 0,
-            lobby_version: 0,
-            steam_auth_token: String::new(),
-            dlc: String::new(),
-        };
-
-        if let Some(conn) = &mut self.lobby_conn {
-            if let Err(e) = conn.send_command("Login", &cmd).await {
-                return serde_json::json!({
-                    "content": [{"type": "text", "text": format!("Failed to send login: {}", e)}],
-                    "isError": true
-                });
-            }
-        }
-
-        // Wait for the correlated response
-        match self.await_lobby_response("LoginResponse", 10).await {
-            Ok(data) => {
-                if let Ok(resp) = serde_json::from_value::<LoginResponseData>(data) {
-                    if resp.result_code == LOGIN_OK {
-                        self.lobby_state.logged_in = true;
-                        self.lobby_state.my_username = Some(resp.name.clone());
-                        serde_json::json!({
-                            "content": [{"type": "text", "text": format!("Logged in as '{}'", resp.name)}]
-                        })
-                    } else {
-                        serde_json::json!({
-                            "content": [{"type": "text", "text": format!("Login failed (code {}): {}", resp.result_code, resp.message)}],
-                            "isError": true
-                        })
-                    }
-                } else {
-                    serde_json::json!({
-                        "content": [{"type": "text", "text": "Login response unparseable"}],
-                        "isError": true
-                    })
-                }
-            }
-            Err(e) => serde_json::json!({
-                "content": [{"type": "text", "text": e}],
-                "isError": true
-            }),
-        }
-    }
-
-    async fn tool_lobby_register(&mut self, args: &serde_json::Value) -> serde_json::Value {
-        let username = match args.get("username").and_then(|v| v.as_str()) {
-            Some(u) => u.to_string(),
-            None => {
-                return serde_json::json!({
-                    "content": [{"type": "text", "text": "Missing username"}],
-                    "isError": true
-                })
-            }
-        };
-        let password = match args.get("password").and_then(|v| v.as_str()) {
-            Some(p) => p,
-            None => {
-                return serde_json::json!({
-                    "content": [{"type": "text", "text": "Missing password"}],
-                    "isError": true
-                })
-            }
-        };
-        let email = match args.get("email").and_then(|v| v.as_str()) {
-            Some(e) => e,
-            None => {
-                return serde_json::json!({
-                    "content": [{"type": "text", "text": "Missing email"}],
-                    "isError": true
-                })
-            }
-        };
-
-        if self.lobby_conn.is_none() {
-            return serde_json::json!({
-                "content": [{"type": "text", "text": "Not connected to lobby. Call lobby_connect first."}],
-                "isError": true
-            });
-        }
-
-        let cmd = RegisterCommand {
-            name: username.clone(),
-            password_hash: hash_password(password),
-            email: email.to_string(),
-            user_id: 0,
-            install_id: String::new(),
-            steam_auth_token: String::new(),
-            dlc: String::new(),
-        };
-
-        if let Some(conn) = &mut self.lobby_conn {
-            if let Err(e) = conn.send_command("Register", &cmd).await {
-                return serde_json::json!({
-                    "content": [{"type": "text", "text": format!("Failed to send register: {}", e)}],
-                    "isError": true
-                });
-            }
-        }
-
-        // Wait for the correlated response
-        match self.await_lobby_response("RegisterResponse", 10).await {
-            Ok(data) => {
-                if let Ok(resp) = serde_json::from_value::<RegisterResponseData>(data) {
-                    if resp.result_code == REGISTER_OK {
-                        serde_json::json!({
-                            "content": [{"type": "text", "text": format!("Account '{}' registered successfully", username)}]
-                        })
-                    } else {
-                        let reason = resp.ban_reason.unwrap_or_default();
-                        serde_json::json!({
-                            "content": [{"type": "text", "text": format!("Registration failed (code {}): {}", resp.result_code, reason)}],
-                            "isError": true
-                        })
-                    }
-                } else {
-                    serde_json::json!({
-                        "content": [{"type": "text", "text": "Register response unparseable"}],
-                        "isError": true
-                    })
-                }
-            }
-            Err(e) => serde_json::json!({
-                "content": [{"type": "text", "text": e}],
-                "isError": true
-            }),
-        }
-    }
-
-    async fn tool_lobby_disconnect(&mut self) -> serde_json::Value {
-        self.lobby_conn = None;
-        self.lobby_state = LobbyState::new();
-        serde_json::json!({
-            "content": [{"type": "text", "text": "Disconnected from lobby"}]
-        })
-    }
-
-    async fn tool_lobby_say(&mut self, args: &serde_json::Value) -> serde_json::Value {
-        let target = match args.get("target").and_then(|v| v.as_str()) {
-            Some(t) => t,
-            None => {
-                return serde_json::json!({
-                    "content": [{"type": "text", "text": "Missing target"}],
-                    "isError": true
-                })
-            }
-        };
-        let text = match args.get("text").and_then(|v| v.as_str()) {
-            Some(t) => t,
-            None => {
-                return serde_json::json!({
-                    "content": [{"type": "text", "text": "Missing text"}],
-                    "isError": true
-                })
-            }
-        };
-        let place = args
-            .get("place")
-            .and_then(|v| v.as_i64())
-            .unwrap_or(0) as i32;
-
-        let conn = match &mut self.lobby_conn {
-            Some(c) => c,
-            None => {
-                return serde_json::json!({
-                    "content": [{"type": "text", "text": "Not connected"}],
-                    "isError": true
-                })
-            }
-        };
-
-        let cmd = SayCommand {
-            place,
-            target: target.to_string(),
-            text: text.to_string(),
-            is_emote: false,
-        };
-
-        match conn.send_command("Say", &cmd).await {
-            Ok(()) => serde_json::json!({
-                "content": [{"type": "text", "text": format!("Sent to {}: {}", target, text)}]
-            }),
-            Err(e) => serde_json::json!({
-                "content": [{"type": "text", "text": format!("Send failed: {}", e)}],
-                "isError": true
-            }),
-        }
-    }
-
-    async fn tool_lobby_join_channel(
-        &mut self,
-        args: &serde_json::Value,
-    ) -> serde_json::Value {
-        let channel = match args.get("channel").and_then(|v| v.as_str()) {
-            Some(c) => c.to_string(),
-            None => {
-                return serde_json::json!({
-                    "content": [{"type": "text", "text": "Missing channel"}],
-                    "isError": true
-                })
-            }
-        };
-
-        if self.lobby_conn.is_none() {
-            return serde_json::json!({
-                "content": [{"type": "text", "text": "Not connected"}],
-                "isError": true
-            });
-        }
-
-        let cmd = JoinChannelCommand {
-            channel_name: channel.clone(),
-            password: String::new(),
-        };
-
-        if let Some(conn) = &mut self.lobby_conn {
-            if let Err(e) = conn.send_command("JoinChannel", &cmd).await {
-                return serde_json::json!({
-                    "content": [{"type": "text", "text": format!("Failed: {}", e)}],
-                    "isError": true
-                });
-            }
-        }
-
-        match self.await_lobby_response("JoinChannelResponse", 10).await {
-            Ok(data) => {
-                if let Ok(resp) = serde_json::from_value::<JoinChannelResponseData>(data) {
-                    if resp.success {
-                        let user_count = resp.channel.as_ref()
-                            .map(|c| c.users.len())
-                            .unwrap_or(0);
-                        let topic = resp.channel.as_ref()
-                            .and_then(|c| c.topic.as_ref())
-                            .map(|t| t.text.clone())
-                            .unwrap_or_default();
-                        // State update is handled by await_lobby_response via handle_message
-                        serde_json::json!({
-                            "content": [{"type": "text", "text": format!("Joined #{} ({} users). Topic: {}", channel, user_count, if topic.is_empty() { "(none)".into() } else { topic })}]
-                        })
-                    } else {
-                        serde_json::json!({
-                            "content": [{"type": "text", "text": format!("Failed to join #{}: rejected by server", channel)}],
-                            "isError": true
-                        })
-                    }
-                } else {
-                    serde_json::json!({
-                        "content": [{"type": "text", "text": "Join response unparseable"}],
-                        "isError": true
-                    })
-                }
-            }
-            Err(e) => serde_json::json!({
-                "content": [{"type": "text", "text": e}],
-                "isError": true
-            }),
-        }
-    }
-
-    async fn tool_lobby_leave_channel(
-        &mut self,
-        args: &serde_json::Value,
-    ) -> serde_json::Value {
-        let channel = match args.get("channel").and_then(|v| v.as_str()) {
-            Some(c) => c,
-            None => {
-                return serde_json::json!({
-                    "content": [{"type": "text", "text": "Missing channel"}],
-                    "isError": true
-                })
-            }
-        };
-
-        let conn = match &mut self.lobby_conn {
-            Some(c) => c,
-            None => {
-                return serde_json::json!({
-                    "content": [{"type": "text", "text": "Not connected"}],
-                    "isError": true
-                })
-            }
-        };
-
-        let cmd = LeaveChannelCommand {
-            channel_name: channel.to_string(),
-        };
-
-        match conn.send_command("LeaveChannel", &cmd).await {
-            Ok(()) => {
-                self.lobby_state.channels.remove(channel);
-                serde_json::json!({
-                    "content": [{"type": "text", "text": format!("Left #{}", channel)}]
-                })
-            }
-            Err(e) => serde_json::json!({
-                "content": [{"type": "text", "text": format!("Failed: {}", e)}],
-                "isError": true
-            }),
-        }
-    }
-
-    async fn tool_lobby_list_battles(&mut self) -> serde_json::Value {
-        let battles: Vec<serde_json::Value> = self
-            .lobby_state
-            .battles
-            .values()
-            .map(|b| {
-                serde_json::json!({
-                    "id": b.battle_id,
-                    "title": b.title,
-                    "founder": b.founder,
-                    "map": b.map,
-                    "players": b.player_count,
-                    "maxPlayers": b.max_players,
-                    "spectators": b.spectator_count,
-                    "running": b.is_running,
-                    "passwordProtected": b.is_password_protected,
-                    "mode": b.mode,
-                })
-            })
-            .collect();
-
-        serde_json::json!({
-            "content": [{"type": "text", "text": serde_json::to_string_pretty(&battles).unwrap()}]
-        })
-    }
-
-    async fn tool_lobby_list_users(
-        &mut self,
-        args: &serde_json::Value,
-    ) -> serde_json::Value {
-        let limit = args
-            .get("limit")
-            .and_then(|v| v.as_u64())
-            .unwrap_or(50) as usize;
-
-        let users: Vec<serde_json::Value> = self
-            .lobby_state
-            .users
-            .values()
-            .take(limit)
-            .map(|u| {
-                serde_json::json!({
-                    "name": u.name,
-                    "level": u.level,
-                    "elo": u.elo,
-                    "clan": u.clan,
-                    "country": u.country,
-                    "isBot": u.is_bot,
-                    "isAdmin": u.is_admin,
-                    "battleId": u.battle_id,
-                })
-            })
-            .collect();
-
-        serde_json::json!({
-            "content": [{"type": "text", "text": format!("{} users (showing {})\n{}", self.lobby_state.users.len(), users.len(), serde_json::to_string_pretty(&users).unwrap())}]
-        })
-    }
-
-    async fn tool_lobby_join_battle(
-        &mut self,
-        args: &serde_json::Value,
-    ) -> serde_json::Value {
-        let battle_id = match args.get("battle_id").and_then(|v| v.as_i64()) {
-            Some(id) => id,
-            None => {
-                return serde_json::json!({
-                    "content": [{"type": "text", "text": "Missing battle_id"}],
-                    "isError": true
-                })
-            }
-        };
-        let password = args
-            .get("password")
-            .and_then(|v| v.as_str())
-            .unwrap_or("");
-
-        if self.lobby_conn.is_none() {
-            return serde_json::json!({
-                "content": [{"type": "text", "text": "Not connected"}],
-                "isError": true
-            });
-        }
-
-        let cmd = JoinBattleCommand {
-            battle_id,
-            password: password.to_string(),
-        };
-
-        if let Some(conn) = &mut self.lobby_conn {
-            if let Err(e) = conn.send_command("JoinBattle", &cmd).await {
-                return serde_json::json!({
-                    "content": [{"type": "text", "text": format!("Failed: {}", e)}],
-                    "isError": true
-                });
-            }
-        }
-
-        // ZKLS has no explicit failure message for JoinBattle — only JoinBattleSuccess on success.
-        // Timeout means rejection (wrong password, battle full, etc.)
-        match self.await_lobby_response("JoinBattleSuccess", 10).await {
-            Ok(data) => {
-                if let Ok(resp) = serde_json::from_value::<JoinBattleSuccessData>(data) {
-                    self.lobby_state.my_battle = Some(resp.battle_id);
-                    let player_count = resp.players.len();
-                    let bot_count = resp.bots.len();
-
-                    // Report sync status
-                    self.send_battle_sync().await;
-
-                    serde_json::json!({
-                        "content": [{"type": "text", "text": format!("Joined battle {} ({} players, {} bots)", resp.battle_id, player_count, bot_count)}]
-                    })
-                } else {
-                    self.lobby_state.my_battle = Some(battle_id);
-                    self.send_battle_sync().await;
-                    serde_json::json!({
-                        "content": [{"type": "text", "text": format!("Joined battle {}", battle_id)}]
-                    })
-                }
-            }
-            Err(e) => serde_json::json!({
-                "content": [{"type": "text", "text": format!("Failed to join battle {}: {}", battle_id, e)}],
-                "isError": true
-            }),
-        }
-    }
-
-    async fn tool_lobby_leave_battle(&mut self) -> serde_json::Value {
-        let conn = match &mut self.lobby_conn {
-            Some(c) => c,
-            None => {
-                return serde_json::json!({
-                    "content": [{"type": "text", "text": "Not connected"}],
-                    "isError": true
-                })
-            }
-        };
-
-        let cmd = LeaveBattleCommand { battle_id: None };
-
-        match conn.send_command("LeaveBattle", &cmd).await {
-            Ok(()) => {
-                self.lobby_state.my_battle = None;
-                serde_json::json!({
-                    "content": [{"type": "text", "text": "Left battle"}]
-                })
-            }
-            Err(e) => serde_json::json!({
-                "content": [{"type": "text", "text": format!("Failed: {}", e)}],
-                "isError": true
-            }),
-        }
-    }
-
-    // ── Matchmaker tool implementations ──
-
-    async fn tool_lobby_matchmaker_join(
-        &mut self,
-        args: &serde_json::Value,
-    ) -> serde_json::Value {
-        let queues: Vec<String> = match args.get("queues").and_then(|v| v.as_array()) {
-            Some(arr) => arr
-                .iter()
-                .filter_map(|v| v.as_str().map(String::from))
-                .collect(),
-            None => {
-                return serde_json::json!({
-                    "content": [{"type": "text", "text": "Missing queues array"}],
-                    "isError": true
-                })
-            }
-        };
-
-        if queues.is_empty() {
-            return serde_json::json!({
-                "content": [{"type": "text", "text": "Queues array is empty. Use lobby_matchmaker_leave to leave all queues."}],
-                "isError": true
-            });
-        }
-
-        if self.lobby_conn.is_none() {
-            return serde_json::json!({
-                "content": [{"type": "text", "text": "Not connected"}],
-                "isError": true
-            });
-        }
-
-        let cmd = MatchMakerQueueRequestCommand {
-            queues: queues.clone(),
-        };
-
-        if let Some(conn) = &mut self.lobby_conn {
-            if let Err(e) = conn.send_command("MatchMakerQueueRequest", &cmd).await {
-                return serde_json::json!({
-                    "content": [{"type": "text", "text": format!("Failed: {}", e)}],
-                    "isError": true
-                });
-            }
-        }
-
-        // Wait for MatchMakerStatus response confirming our queue state
-        match self.await_lobby_response("MatchMakerStatus", 10).await {
-            Ok(data) => {
-                if let Ok(status) = serde_json::from_value::<MatchMakerStatusData>(data) {
-                    self.lobby_state.matchmaker_joined = status.joined_queues.clone();
-                    self.lobby_state.matchmaker_queue_counts = status.queue_counts.clone();
-                    let joined = status.joined_queues.join(", ");
-                    let counts: Vec<String> = status
-                        .queue_counts
-                        .iter()
-                        .filter(|(name, _)| status.joined_queues.contains(name))
-                        .map(|(name, count)| format!("{}: {} queued", name, count))
-                        .collect();
-                    if status.joined_queues.is_empty() {
-                        serde_json::json!({
-                            "content": [{"type": "text", "text": format!("Failed to join queues (may be banned for {}s)", status.banned_seconds.unwrap_or(0))}],
-                            "isError": true
-                        })
-                    } else {
-                        serde_json::json!({
-                            "content": [{"type": "text", "text": format!("Joined matchmaker queues: [{}]. {}", joined, counts.join(", "))}]
-                        })
-                    }
-                } else {
-                    serde_json::json!({
-                        "content": [{"type": "text", "text": "MatchMakerStatus unparseable"}],
-                        "isError": true
-                    })
-                }
-            }
-            Err(e) => serde_json::json!({
-                "content": [{"type": "text", "text": e}],
-                "isError": true
-            }),
-        }
-    }
-
-    async fn tool_lobby_matchmaker_leave(&mut self) -> serde_json::Value {
-        if self.lobby_conn.is_none() {
-            return serde_json::json!({
-                "content": [{"type": "text", "text": "Not connected"}],
-                "isError": true
-            });
-        }
-
-        let cmd = MatchMakerQueueRequestCommand {
-            queues: vec![],
-        };
-
-        if let Some(conn) = &mut self.lobby_conn {
-            if let Err(e) = conn.send_command("MatchMakerQueueRequest", &cmd).await {
-                return serde_json::json!({
-                    "content": [{"type": "text", "text": format!("Failed: {}", e)}],
-                    "isError": true
-                });
-            }
-        }
-
-        match self.await_lobby_response("MatchMakerStatus", 10).await {
-            Ok(data) => {
-                if let Ok(status) = serde_json::from_value::<MatchMakerStatusData>(data) {
-                    self.lobby_state.matchmaker_joined = status.joined_queues.clone();
-                    self.lobby_state.matchmaker_queue_counts = status.queue_counts.clone();
-                    serde_json::json!({
-                        "content": [{"type": "text", "text": "Left all matchmaker queues"}]
-                    })
-                } else {
-                    self.lobby_state.matchmaker_joined.clear();
-                    serde_json::json!({
-                        "content": [{"type": "text", "text": "Left matchmaker queues"}]
-                    })
-                }
-            }
-            Err(e) => {
-                // Even on timeout, we likely left — the server might not send status
-                // if we weren't in any queue
-                self.lobby_state.matchmaker_joined.clear();
-                tracing::debug!("MatchMakerStatus await after leave: {}", e);
-                serde_json::json!({
-                    "content": [{"type": "text", "text": "Left matchmaker queues"}]
-                })
-            }
-        }
-    }
-
-    async fn tool_lobby_matchmaker_accept(
-        &mut self,
-        args: &serde_json::Value,
-    ) -> serde_json::Value {
-        let ready = args
-            .get("ready")
-            .and_then(|v| v.as_bool())
-            .unwrap_or(true);
-
-        let conn = match &mut self.lobby_conn {
-            Some(c) => c,
-            None => {
-                return serde_json::json!({
-                    "content": [{"type": "text", "text": "Not connected"}],
-                    "isError": true
-                })
-            }
-        };
-
-        if !self.lobby_state.matchmaker_ready_pending {
-            return serde_json::json!({
-                "content": [{"type": "text", "text": "No ready-check pending"}],
-                "isError": true
-            });
-        }
-
-        let cmd = AreYouReadyResponseCommand { ready };
-
-        match conn.send_command("AreYouReadyResponse", &cmd).await {
-            Ok(()) => {
-                self.lobby_state.matchmaker_ready_pending = false;
-                let action = if ready { "Accepted" } else { "Declined" };
-                serde_json::json!({
-                    "content": [{"type": "text", "text": format!("{} matchmaker ready-check", action)}]
-                })
-            }
-            Err(e) => serde_json::json!({
-                "content": [{"type": "text", "text": format!("Failed: {}", e)}],
-                "isError": true
-            }),
-        }
-    }
-
-    /// Tell the lobby server we have the map/game/engine files.
-    async fn send_battle_sync(&mut self) {
-        let username = self.lobby_state.my_username.clone().unwrap_or_default();
-        let cmd = UpdateUserBattleStatusCommand {
-            name: username,
-            is_spectator: Some(false),
-            sync: Some("Synced".into()),
-            ally_number: Some(0),
-        };
-        if let Some(conn) = &mut self.lobby_conn {
-            if let Err(e) = conn.send_command("UpdateUserBattleStatus", &cmd).await {
-                tracing::warn!("Failed to send sync status: {}", e);
-            }
-        }
-    }
-
-    // ── Battle hosting tool implementations ──
-
-    async fn tool_lobby_open_battle(
-        &mut self,
-        args: &serde_json::Value,
-    ) -> serde_json::Value {
-        let title = match args.get("title").and_then(|v| v.as_str()) {
-            Some(t) => t.to_string(),
-            None => {
-                return serde_json::json!({
-                    "content": [{"type": "text", "text": "Missing title"}],
-                    "isError": true
-                })
-            }
-        };
-        let map = match args.get("map").and_then(|v| v.as_str()) {
-            Some(m) => m.to_string(),
-            None => {
-                return serde_json::json!({
-                    "content": [{"type": "text", "text": "Missing map"}],
-                    "isError": true
-                })
-            }
-        };
-        let max_players = args
-            .get("max_players")
-            .and_then(|v| v.as_i64())
-            .unwrap_or(2) as i32;
-        let password = args
-            .get("password")
-            .and_then(|v| v.as_str())
-            .unwrap_or("")
-            .to_string();
-
-        if !self.lobby_state.logged_in {
-            return serde_json::json!({
-                "content": [{"type": "text", "text": "Not logged in"}],
-                "isError": true
-            });
-        }
-
-        let cmd = OpenBattleCommand {
-            header: BattleHeader {
-                battle_id: 0,
-                title: title.clone(),
-                founder: self.lobby_state.my_username.clone().unwrap_or_default(),
-                map: map.clone(),
-                game: self.lobby_state.server_game.clone(),
-                engine: self.lobby_state.server_engine.clone(),
-                max_players,
-                player_count: 0,
-                spectator_count: 0,
-                is_running: false,
-                is_password_protected: !password.is_empty(),
-                mode: None,
-            },
-        };
-
-        if let Some(conn) = &mut self.lobby_conn {
-            if let Err(e) = conn.send_command("OpenBattle", &cmd).await {
-                return serde_json::json!({
-                    "content": [{"type": "text", "text": format!("Failed to send OpenBattle: {}", e)}],
-                    "isError": true
-                });
-            }
-        }
-
-        // The server responds with JoinBattleSuccess (founder auto-joins)
-        match self.await_lobby_response("JoinBattleSuccess", 10).await {
-            Ok(data) => {
-                if let Ok(resp) = serde_json::from_value::<JoinBattleSuccessData>(data) {
-                    self.lobby_state.my_battle = Some(resp.battle_id);
-
-                    // Report sync status — tell the server we have the map/game/engine
-                    self.send_battle_sync().await;
-
-                    serde_json::json!({
-                        "content": [{"type": "text", "text": format!(
-                            "Opened battle '{}' on {} (battle_id: {}). Add bots with lobby_add_bot, then start with lobby_start_battle.",
-                            title, map, resp.battle_id
-                        )}]
-                    })
-                } else {
-                    serde_json::json!({
-                        "content": [{"type": "text", "text": "Opened battle but could not parse response"}]
-                    })
-                }
-            }
-            Err(e) => serde_json::json!({
-                "content": [{"type": "text", "text": format!("Failed to open battle: {}", e)}],
-                "isError": true
-            }),
-        }
-    }
-
-    async fn tool_lobby_add_bot(
-        &mut self,
-        args: &serde_json::Value,
-    ) -> serde_json::Value {
-        let ai_lib = match args.get("ai_lib").and_then(|v| v.as_str()) {
-            Some(a) => a.to_string(),
-            None => {
-                return serde_json::json!({
-                    "content": [{"type": "text", "text": "Missing ai_lib"}],
-                    "isError": true
-                })
-            }
-        };
-        let name = args
-            .get("name")
-            .and_then(|v| v.as_str())
-            .unwrap_or("Bot1")
-            .to_string();
-        let ally_number = args
-            .get("ally_number")
-            .and_then(|v| v.as_i64())
-            .unwrap_or(1) as i32;
-
-        if self.lobby_state.my_battle.is_none() {
-            return serde_json::json!({
-                "content": [{"type": "text", "text": "Not in a battle"}],
-                "isError": true
-            });
-        }
-
-        let cmd = UpdateBotStatusCommand {
-            name: name.clone(),
-            ai_lib: ai_lib.clone(),
-            ally_number,
-            owner: self.lobby_state.my_username.clone().unwrap_or_default(),
-        };
-
-        if let Some(conn) = &mut self.lobby_conn {
-            match conn.send_command("UpdateBotStatus", &cmd).await {
-                Ok(()) => serde_json::json!({
-                    "content": [{"type": "text", "text": format!("Added bot '{}' (AI: {}, ally: {})", name, ai_lib, ally_number)}]
-                }),
-                Err(e) => serde_json::json!({
-                    "content": [{"type": "text", "text": format!("Failed: {}", e)}],
-                    "isError": true
-                }),
-            }
-        } else {
-            serde_json::json!({
-                "content": [{"type": "text", "text": "Not connected"}],
-                "isError": true
-            })
-        }
-    }
-
-    async fn tool_lobby_remove_bot(
-        &mut self,
-        args: &serde_json::Value,
-    ) -> serde_json::Value {
-        let name = match args.get("name").and_then(|v| v.as_str()) {
-            Some(n) => n.to_string(),
-            None => {
-                return serde_json::json!({
-                    "content": [{"type": "text", "text": "Missing bot name"}],
-                    "isError": true
-                })
-            }
-        };
-
-        let cmd = RemoveBotCommand { name: name.clone() };
-
-        if let Some(conn) = &mut self.lobby_conn {
-            match conn.send_command("RemoveBot", &cmd).await {
-                Ok(()) => serde_json::json!({
-                    "content": [{"type": "text", "text": format!("Removed bot '{}'", name)}]
-                }),
-                Err(e) => serde_json::json!({
-                    "content": [{"type": "text", "text": format!("Failed: {}", e)}],
-                    "isError": true
-                }),
-            }
-        } else {
-            serde_json::json!({
-                "content": [{"type": "text", "text": "Not connected"}],
-                "isError": true
-            })
-        }
-    }
-
-    async fn tool_lobby_start_battle(&mut self) -> serde_json::Value {
-        if self.lobby_state.my_battle.is_none() {
-            return serde_json::json!({
-                "content": [{"type": "text", "text": "Not in a battle"}],
-                "isError": true
-            });
-        }
-
-        // ZK custom battles are started by sending !start in battle chat.
-        // The ZKLS autohost processes it and spins up a dedicated game server.
-        let cmd = SayCommand {
-            place: PLACE_BATTLE,
-            target: String::new(),
-            text: "!start".into(),
-            is_emote: false,
-        };
-
-        if let Some(conn) = &mut self.lobby_conn {
-            if let Err(e) = conn.send_command("Say", &cmd).await {
-                return serde_json::json!({
-                    "content": [{"type": "text", "text": format!("Failed to send !start: {}", e)}],
-                    "isError": true
-                });
-            }
-        }
-
-        // Return immediately — the background event loop will receive ConnectSpring
-        // and call handle_connect_spring() to launch the engine. The agent will get
-        // a lobby.connect_spring push event when that happens.
-        tracing::info!("Sent !start to battle chat, waiting for ConnectSpring from background loop");
-        serde_json::json!({
-            "content": [{"type": "text", "text": "Sent !start — waiting for game server to launch. You'll receive a lobby.connect_spring event when the engine connects."}]
-        })
-    }
-
-    // ── Game tool implementations ──
-
-    async fn tool_lobby_start_game(
-        &mut self,
-        args: &serde_json::Value,
-    ) -> serde_json::Value {
-        let map = match args.get("map").and_then(|v| v.as_str()) {
-            Some(m) => m.to_string(),
-            None => {
-                return serde_json::json!({
-                    "content": [{"type": "text", "text": "Missing map name"}],
-                    "isError": true
-                })
-            }
-        };
-        let opponent = args
-            .get("opponent")
-            .and_then(|v| v.as_str())
-            .unwrap_or("CircuitAINovice");
-        let game = args
-            .get("game")
-            .and_then(|v| v.as_str())
-            .unwrap_or("Zero-K $VERSION");
-        let player_mode = args
-            .get("player_mode")
-            .and_then(|v| v.as_bool())
-            .unwrap_or(false);
-        let headless = if player_mode {
-            false // player mode needs LuaUI for bootstrap widget
-        } else {
-            args.get("headless").and_then(|v| v.as_bool()).unwrap_or(true)
-        };
-
-        match self
-            .engines
-            .start_local_game(&map, game, Some(opponent), headless, player_mode, &self.agent_name)
+/// `game-manager self-test`: set up as a real start would, then run the
+/// pipeline against a stub bridge and print a line per stage. With
+/// `keep_running`, a passing run hands back the GameManager with the stub
+/// still connected.
+async fn run_self_test(
+    wdc: &WriteDirConfig,
+    socket_dir: &str,
+    gm_config: &config::GmConfig,
+    with_mcpl: bool,
+    keep_running: bool,
+) -> (bool, Option<(GameManager, self_test::StubBridge)>) {
+    use self_test::Stage;
+    let mut stages = vec![Stage::new(
+        "write dir",
+        wdc.init().map(|()| wdc.write_dir.display().to_string()).map_err(|e| e.to_string()),
+    )];
+    stages.push(Stage::new(
+        "sai bridge",
+        write_dir::ensure_sai_installed(&wdc.write_dir, &wdc.sai_bridge())
             .await
-        {
-            Ok(channel_id) => {
-                // Set up SAI IPC listener
-                let socket_path = self
-                    .engines
-                    .instances
-                    .get(&channel_id)
-                    .map(|i| i.config.socket_path.clone())
-                    .unwrap_or_default();
-
-                if let Err(e) = self.sai.listen_for(&channel_id, &socket_path) {
-                    tracing::error!("Failed to set up SAI listener: {}", e);
-                }
-
-                // Notify channels/changed
-                self.send_channels_changed(
-                    vec![ChannelDescriptor {
-                        id: channel_id.clone(),
-                        channel_type: "game".into(),
-                        label: format!("Local game on {}", map),
-                        direction: ChannelDirection::Bidirectional,
-                        address: None,
-                        metadata: Some(serde_json::json!({
-                            "map": map,
-                            "opponent": opponent,
-                            "headless": headless,
-                            "status": "starting",
-                        })),
-                    }],
-                    vec![],
-                    vec![],
-                )
-                .await;
-
-                serde_json::json!({
-                    "content": [{"type": "text", "text": format!(
-                        "Started local game: AgentBridge vs {} on {} (channel: {}, headless: {})",
-                        opponent, map, channel_id, headless
-                    )}]
-                })
-            }
-            Err(e) => serde_json::json!({
-                "content": [{"type": "text", "text": format!("Failed to start game: {}", e)}],
-                "isError": true
-            }),
-        }
-    }
-
-    /// Handle ConnectSpring lobby event — launch engine in client mode for multiplayer.
-    async fn handle_connect_spring(&mut self, data: &ConnectSpringData) {
-        tracing::info!(
-            "ConnectSpring received: {}:{} map={} mode={}",
-            data.ip, data.port, data.map, data.mode
-        );
-
-        let player_name = self
-            .lobby_state
-            .my_username
-            .clone()
-            .unwrap_or_else(|| self.agent_name.clone());
-
-        // Ensure the lobby username is whitelisted for /aicontrol
-        if let Err(e) = crate::write_dir::ensure_player_whitelisted(
-            &self.write_dir,
-            &player_name,
-        ) {
-            tracing::warn!("Failed to whitelist player '{}': {}", player_name, e);
-        }
-
-        // Warm archive cache for the server's engine version if needed
-        if !data.engine.is_empty() {
-            if let Ok(mp_engine_dir) = engine::find_engine_dir(&self.spring_home, Some(&data.engine)) {
-                let _ = chmod_executable(&mp_engine_dir);
-                if mp_engine_dir != self.engines.engine_dir {
-                    warm_archive_cache(&self.write_dir, &mp_engine_dir).await;
-                }
-            }
-        }
-
-        match self
-            .engines
-            .start_multiplayer_game(data, &player_name, &self.spring_home)
-            .await
-        {
-            Ok(channel_id) => {
-                // Set up SAI IPC listener
-                let socket_path = self
-                    .engines
-                    .instances
-                    .get(&channel_id)
-                    .map(|i| i.config.socket_path.clone())
-                    .unwrap_or_default();
-
-                if let Err(e) = self.sai.listen_for(&channel_id, &socket_path) {
-                    tracing::error!("Failed to set up SAI listener for MP game: {}", e);
-                }
-
-                self.send_channels_changed(
-                    vec![ChannelDescriptor {
-                        id: channel_id.clone(),
-                        channel_type: "game".into(),
-                        label: format!("MP game on {}", data.map),
-                        direction: ChannelDirection::Bidirectional,
-                        address: None,
-                        metadata: Some(serde_json::json!({
-                            "map": data.map,
-                            "mode": data.mode,
-                            "title": data.title,
-                            "status": "connecting",
-                            "multiplayer": true,
-                        })),
-                    }],
-                    vec![],
-                    vec![],
-                )
-                .await;
+            .map(|digest| format!("installed, sha256 {}", digest)),
+    ));
+    let engine_dir = engine::find_engine_dir(&wdc.spring_home, cli_arg("--engine-version").as_deref());
+    stages.push(Stage::new(
+        "engine",
+        engine_dir.as_ref().map(|d| d.display().to_string()).map_err(|e| e.to_string()),
+    ));
+
+    let sockets = socket_dir::SocketDir::create(std::path::Path::new(socket_dir));
+    stages.push(Stage::new(
+        "socket dir",
+        sockets.as_ref().map(|s| s.path().display().to_string()).map_err(|e| e.clone()),
+    ));
+    let Ok(sockets) = sockets else {
+        println!("{}", self_test::report(&stages).0);
+        return (false, None);
+    };
 
-                tracing::info!("Launched multiplayer engine for channel {}", channel_id);
+    let mut gm = GameManager::new(wdc, engine_dir.unwrap_or_default(), sockets);
+    let (link, mut client) = if with_mcpl {
+        match self_test::connect_in_process(gm_config).await {
+            Ok((conn, client)) => {
+                stages.push(Stage::new("mcpl handshake", Ok("in-process client initialized".into())));
+                (mcpl_link::McplLink::spawn(conn, &gm_config.mcpl_delivery), client)
             }
             Err(e) => {
-                tracing::error!("Failed to launch engine for ConnectSpring: {}", e);
+                stages.push(Stage::new("mcpl handshake", Err(e)));
+                let (stages_text, _) = self_test::report(&stages);
+                println!("{}", stages_text);
+                return (false, None);
             }
         }
-    }
-
-    async fn tool_lobby_matchmaker_status(&mut self) -> serde_json::Value {
-        let available: Vec<serde_json::Value> = self
-            .lobby_state
-            .matchmaker_queues
-            .iter()
-            .map(|q| {
-                let count = self
-                    .lobby_state
-                    .matchmaker_queue_counts
-                    .get(&q.name)
-                    .copied()
-                    .unwrap_or(0);
-                serde_json::json!({
-                    "name": q.name,
-                    "description": q.description,
-                    "maxPartySize": q.max_party_size,
-                    "queued": count,
-                })
-            })
-            .collect();
-
-        let joined = &self.lobby_state.matchmaker_joined;
-        let ready_pending = self.lobby_state.matchmaker_ready_pending;
-
-        serde_json::json!({
-            "content": [{"type": "text", "text": format!(
-                "Joined queues: [{}]\nReady-check pending: {}\nAvailable queues:\n{}",
-                joined.join(", "),
-                ready_pending,
-                serde_json::to_string_pretty(&available).unwrap()
-            )}]
-        })
-    }
+    } else {
+        let (loopback, client) = self_test::LoopbackClient::new();
+        (mcpl_link::McplLink::spawn(loopback, &gm_config.mcpl_delivery), client)
+    };
+    gm.mcpl = Some(link);
 
-    /// Convert a lobby event to an MCPL push event and send it.
-    async fn push_lobby_event(
-        &mut self,
-        event: &LobbyEvent,
-    ) -> Result<(), mcpl_core::connection::ConnectionError> {
-        let mcpl = match &mut self.mcpl {
-            Some(c) => c,
-            None => return Ok(()),
-        };
+    let socket = gm.engines.sockets.path().join("self-test.sock").display().to_string();
+    let (pipeline, stub) = gm.self_test_pipeline(&socket, &mut client).await;
+    stages.extend(pipeline);
+    let (text, passed) = self_test::report(&stages);
+    println!("{}", text);
 
-        let (event_id, content_text) = match event {
-            LobbyEvent::Connected { engine, game } => (
-                "lobby.connected".to_string(),
-                format!("Connected to lobby. Engine: {}, Game: {}", engine, game),
-            ),
-            LobbyEvent::Disconnected { reason } => (
-                "lobby.disconnected".to_string(),
-                format!("Disconnected: {}", reason),
-            ),
-            LobbyEvent::LoggedIn { username } => (
-                "lobby.logged_in".to_string(),
-                format!("Logged in as {}", username),
-            ),
-            LobbyEvent::LoginFailed { code, message } => (
-                "lobby.login_failed".to_string(),
-                format!("Login failed (code {}): {}", code, message),
-            ),
-            LobbyEvent::RegisterSuccess => (
-                "lobby.register_success".to_string(),
-                "Account registration successful".to_string(),
-            ),
-            LobbyEvent::RegisterFailed { code, reason } => (
-                "lobby.register_failed".to_string(),
-                format!("Registration failed (code {}): {}", code, reason),
-            ),
-            LobbyEvent::ChatMessage {
-                user,
-                text,
-                target,
-                place,
-                ..
-            } => {
-                // Only forward battle chat and DMs — skip channel chatter
-                match *place {
-                    1 => (
-                        "lobby.chat".to_string(),
-                        format!("[battle] {}: {}", user, text),
-                    ),
-                    4 => (
-                        "lobby.chat".to_string(),
-                        format!("[dm] {}: {}", user, text),
-                    ),
-                    _ => return Ok(()), // skip channel chat, server messages, etc.
-                }
-            }
-            LobbyEvent::BattleJoined { battle_id, player_count, bot_count } => (
-                "lobby.battle_joined".to_string(),
-                format!("Joined battle {} ({} players, {} bots)", battle_id, player_count, bot_count),
-            ),
-            LobbyEvent::ChannelJoined {
-                channel,
-                users,
-                topic,
-            } => (
-                "lobby.channel_joined".to_string(),
-                format!(
-                    "Joined #{} ({} users). Topic: {}",
-                    channel,
-                    users.len(),
-                    topic.as_deref().unwrap_or("(none)")
-                ),
-            ),
-            LobbyEvent::MatchMakerReady {
-                seconds_remaining,
-                quick_play,
-            } => (
-                "lobby.matchmaker_ready".to_string(),
-                format!(
-                    "MATCH FOUND! Accept within {}s (quickplay: {}). Use lobby_matchmaker_accept to respond.",
-                    seconds_remaining, quick_play
-                ),
-            ),
-            LobbyEvent::MatchMakerResult {
-                is_battle_starting,
-                are_you_banned,
-            } => (
-                "lobby.matchmaker_result".to_string(),
-                if *is_battle_starting {
-                    "Match starting! ConnectSpring will follow.".to_string()
-                } else if *are_you_banned {
-                    "Match cancelled. You have been temporarily banned for not accepting.".to_string()
-                } else {
-                    "Match cancelled. Not enough players accepted.".to_string()
-                },
-            ),
-            LobbyEvent::ConnectSpring(_) => (
-                "lobby.connect_spring".to_string(),
-                "Game starting — engine launch initiated".to_string(),
-            ),
-            // Skip high-frequency events that would flood the agent's context
-            LobbyEvent::UserJoined(_)
-            | LobbyEvent::UserLeft { .. }
-            | LobbyEvent::BattleUpdated(_)
-            | LobbyEvent::BattleOpened(_)
-            | LobbyEvent::BattleClosed { .. }
-            | LobbyEvent::ChannelUserJoined { .. }
-            | LobbyEvent::ChannelUserLeft { .. }
-            | LobbyEvent::MatchMakerStatus(_)
-            | LobbyEvent::MatchMakerSetup { .. }
-            | LobbyEvent::MatchMakerReadyUpdate(_) => {
-                return Ok(());
+    gm.mcpl = None;
+    match stub {
+        Some(stub) if passed && keep_running => (true, Some((gm, stub))),
+        stub => {
+            if let Some(stub) = stub {
+                stub.stop();
             }
-        };
-
-        let params = PushEventParams {
-            feature_set: "lobby".into(),
-            event_id: format!("{}_{}", event_id, uuid::Uuid::new_v4()),
-            timestamp: chrono::Utc::now().to_rfc3339(),
-            origin: Some(serde_json::json!({"source": "zk-lobby"})),
-            payload: PushEventPayload {
-                content: vec![ContentBlock::text(content_text)],
-            },
-        };
-
-        mcpl.send_request(
-            method::PUSH_EVENT,
-            Some(serde_json::to_value(&params).unwrap()),
-        )
-        .await?;
-
-        Ok(())
+            let _ = std::fs::remove_file(&socket);
+            (passed, None)
+        }
     }
 }
 
@@ -1672,50 +152,6 @@ fn cli_arg(name: &str) -> Option<String> {
         .and_then(|i| args.get(i + 1).cloned())
 }
 
-/// Ensure engine binaries in a directory are executable.
-fn chmod_executable(engine_dir: &std::path::Path) -> std::io::Result<()> {
-    use std::os::unix::fs::PermissionsExt;
-    for name in &["spring", "spring-headless"] {
-        let bin = engine_dir.join(name);
-        if bin.exists() {
-            let meta = std::fs::metadata(&bin)?;
-            let mut perms = meta.permissions();
-            perms.set_mode(perms.mode() | 0o111);
-            std::fs::set_permissions(&bin, perms)?;
-        }
-    }
-    Ok(())
-}
-
-/// Run the engine briefly to populate the archive cache in the write-dir.
-async fn warm_archive_cache(write_dir: &std::path::Path, engine_dir: &std::path::Path) {
-    let headless = engine::resolve_engine_binary(engine_dir, true);
-    if !headless.exists() {
-        tracing::warn!("Cannot warm cache: {} not found", headless.display());
-        return;
-    }
-
-    tracing::info!("Warming archive cache with {}...", engine_dir.display());
-    let dummy_script = write_dir.join("temp/cache_warm.txt");
-    let _ = tokio::fs::write(&dummy_script, "[GAME]\n{\n}\n").await;
-
-    let result = tokio::process::Command::new(&headless)
-        .arg("--write-dir")
-        .arg(write_dir)
-        .arg(&dummy_script)
-        .stdout(std::process::Stdio::null())
-        .stderr(std::process::Stdio::null())
-        .status()
-        .await;
-
-    let _ = tokio::fs::remove_file(&dummy_script).await;
-
-    match result {
-        Ok(status) => tracing::info!("Cache warm-up done (exit: {})", status),
-        Err(e) => tracing::warn!("Cache warm-up failed: {}", e),
-    }
-}
-
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     // Tracing always goes to stderr — safe for stdio mode
@@ -1728,6 +164,7 @@ async fn main() -> anyhow::Result<()> {
         .init();
 
     let use_stdio = std::env::args().any(|a| a == "--stdio");
+    let socket_dir = std::env::var("SOCKET_DIR").unwrap_or_else(|_| "/tmp".into());
 
     // Write-dir configuration: CLI args > env vars > defaults
     let wdc = WriteDirConfig::from_env(
@@ -1736,6 +173,61 @@ async fn main() -> anyhow::Result<()> {
         cli_arg("--agent-name").as_deref(),
     );
 
+    // Convert a session log offline: convert-log <log.jsonl> [--format json|markdown], then exit
+    if std::env::args().nth(1).as_deref() == Some("convert-log") {
+        let usage = "Usage: game-manager convert-log <session.jsonl> [--format json|markdown]";
+        let input = std::env::args().nth(2).filter(|a| !a.starts_with("--")).ok_or_else(|| anyhow::anyhow!(usage))?;
+        let format = match cli_arg("--format") {
+            None => convert::Format::Json,
+            Some(f) => convert::Format::parse(&f).ok_or_else(|| anyhow::anyhow!("Unknown format '{}'. {}", f, usage))?,
+        };
+        // A crash can tear a multi-byte character too.
+        let log = std::fs::read(&input).map_err(|e| anyhow::anyhow!("Failed to read {}: {}", input, e))?;
+        print!("{}", convert::convert(&String::from_utf8_lossy(&log)).render(format));
+        return Ok(());
+    }
+
+    // Report the GameManager, engine and bridge builds: doctor, then exit
+    if std::env::args().nth(1).as_deref() == Some("doctor") {
+        let engine_dir = engine::find_engine_dir(&wdc.spring_home, cli_arg("--engine-version").as_deref());
+        print!("{}", doctor::report(&wdc, engine_dir.map_err(|e| e.to_string())));
+        return Ok(());
+    }
+
+    // Install an engine release: --install-engine <version>, then exit
+    if let Some(version) = cli_arg("--install-engine") {
+        let mirror = engine_install::mirror_from_env();
+        let dir = engine_install::install(&mirror, &wdc.spring_home, &version, |line| println!("{}", line))
+            .await
+            .map_err(anyhow::Error::msg)?;
+        println!("Engine {} ready in {}", version, dir.display());
+        return Ok(());
+    }
+
+    // Config file problems (bad rule patterns included) stop startup.
+    let config_path = config::GmConfig::path(cli_arg("--config").as_deref(), &wdc.write_dir);
+    let mut gm_config = config::GmConfig::load(&config_path).map_err(anyhow::Error::msg)?;
+    let auto_respond =
+        autorespond::AutoResponder::new(gm_config.auto_respond.clone()).map_err(anyhow::Error::msg)?;
+    if auto_respond.rule_count() > 0 {
+        tracing::info!("Loaded {} auto-respond rules from {}", auto_respond.rule_count(), config_path.display());
+    }
+
+    // Check the installation against a stub bridge: self-test, then exit
+    // with the result, or with --keep-running carry on with the stub.
+    let mut self_tested = None;
+    if std::env::args().nth(1).as_deref() == Some("self-test") {
+        let keep_running = std::env::args().any(|a| a == "--keep-running");
+        let with_mcpl = std::env::args().any(|a| a == "--mcpl");
+        match run_self_test(&wdc, &socket_dir, &gm_config, with_mcpl, keep_running).await {
+            (true, Some(resumed)) => {
+                println!("Stub bridge stays connected as {}", self_test::CHANNEL);
+                self_tested = Some(resumed);
+            }
+            (passed, _) => std::process::exit(if passed { 0 } else { 1 }),
+        }
+    }
+
     // Initialize write directory (creates dirs, symlinks, installs SAI bridge)
     wdc.init()?;
 
@@ -1748,23 +240,29 @@ async fn main() -> anyhow::Result<()> {
     if let Some(warm_ver) = cli_arg("--warm-engine") {
         let warm_dir = engine::find_engine_dir(&wdc.spring_home, Some(&warm_ver))?;
         // Make sure the binary is executable
-        let _ = chmod_executable(&warm_dir);
-        warm_archive_cache(&wdc.write_dir, &warm_dir).await;
+        let _ = engine::chmod_executable(&warm_dir);
+        engine::warm_archive_cache(&wdc.write_dir, &warm_dir).await;
         tracing::info!("Cache warmed for engine {}. Exiting.", warm_ver);
         return Ok(());
     }
 
     // Cache warm on startup (opt-in with --cache-warm)
     if std::env::args().any(|a| a == "--cache-warm") {
-        warm_archive_cache(&wdc.write_dir, &engine_dir).await;
+        engine::warm_archive_cache(&wdc.write_dir, &engine_dir).await;
     }
     // Note: multiplayer games may use a different engine — handle_connect_spring
     // warms the cache for that engine before launching.
 
-    let socket_dir = std::env::var("SOCKET_DIR").unwrap_or_else(|_| "/tmp".into());
+    // The status page is up before a client is, so an operator can see
+    // the GameManager waiting for one.
+    let status_view = status_page::StatusView::new();
+    if gm_config.status_page.enabled {
+        let addr = status_page::serve(&gm_config.status_page, status_view.clone()).await.map_err(anyhow::Error::msg)?;
+        tracing::info!("Status page at http://{}/", addr);
+    }
 
-    let mcpl_conn = if use_stdio {
-        mcpl_server::accept_mcpl_stdio().await?
+    let (mcpl_conn, client_options) = if use_stdio {
+        mcpl_server::accept_mcpl_stdio(&gm_config).await?
     } else {
         let mcpl_port: u16 = std::env::var("MCPL_PORT")
             .ok()
@@ -1774,17 +272,62 @@ async fn main() -> anyhow::Result<()> {
         let listener = TcpListener::bind(format!("127.0.0.1:{}", mcpl_port)).await?;
         tracing::info!("GameManager MCPL server listening on port {}", mcpl_port);
 
-        mcpl_server::accept_mcpl_client(&listener).await?
+        mcpl_server::accept_mcpl_client(&listener, &gm_config).await?
     };
     tracing::info!("MCPL client connected and initialized");
 
-    let mut gm = GameManager::new(&wdc, engine_dir, socket_dir);
-    gm.mcpl = Some(mcpl_conn);
+    let checks = doctor::check(&wdc, Ok(engine_dir.clone()));
+    let (mut gm, stub) = match self_tested {
+        Some((gm, stub)) => (gm, Some(stub)),
+        None => {
+            let sockets = socket_dir::SocketDir::create(std::path::Path::new(&socket_dir)).map_err(anyhow::Error::msg)?;
+            (GameManager::new(&wdc, engine_dir, sockets), None)
+        }
+    };
+    gm.mcpl = Some(mcpl_link::McplLink::spawn(mcpl_conn, &gm_config.mcpl_delivery));
+    gm.configure(&gm_config, auto_respond, client_options);
+    if gm_config.status_page.enabled {
+        gm.status_board = Some(status_page::StatusBoard::new(status_view, gm_config.status_page.recent_events));
+    }
+    if let Some(max) = std::env::var("MAX_CONCURRENT_GAMES").ok().and_then(|v| v.parse().ok()) {
+        gm.engines.max_concurrent_games = max;
+    }
+    if let Some(secs) = std::env::var("DOWNLOAD_TIMEOUT_SECS").ok().and_then(|v| v.parse().ok()) {
+        gm.downloads.timeout = std::time::Duration::from_secs(secs);
+    }
+    if let Some(n) = std::env::var("GAME_SUMMARY_RETAIN").ok().and_then(|v| v.parse().ok()) {
+        gm.summary_retain = n;
+    }
+    gm.restore_session();
+    gm.announce_ready(&gm_config, &checks);
+
+    // The self-test's stub plays on as a game channel of its own.
+    if stub.is_some() {
+        gm.send_channels_changed(
+            vec![ChannelDescriptor {
+                id: self_test::CHANNEL.into(),
+                channel_type: "game".into(),
+                label: "Self-test stub".into(),
+                direction: ChannelDirection::Bidirectional,
+                address: None,
+                metadata: Some(serde_json::json!({"status": "running", "saiConnected": true})),
+            }],
+            vec![],
+            vec![],
+        ).await;
+    }
+
+    let linger = std::time::Duration::from_secs_f64(gm_config.stdio.linger_secs);
+    let mut client_gone = None;
 
     // Engine check interval
     let mut engine_check = tokio::time::interval(tokio::time::Duration::from_millis(100));
 
-    // Main event loop
+    // SIGHUP reloads the config file.
+    let mut hangup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())?;
+
+    // Main event loop: wire the lobby, the MCPL client and the clock to the
+    // GameManager.
     loop {
         let lobby_msg = async {
             if let Some(conn) = &mut gm.lobby_conn {
@@ -1803,106 +346,17 @@ async fn main() -> anyhow::Result<()> {
         };
 
         tokio::select! {
-            result = lobby_msg => {
-                match result {
-                    Ok(msg) => {
-                        if msg.command == "Ping" {
-                            if let Some(conn) = &mut gm.lobby_conn {
-                                let pong = LobbyMessage::new("Ping", serde_json::json!({}));
-                                if let Err(e) = conn.send(&pong).await {
-                                    tracing::error!("Failed to send ping response: {}", e);
-                                }
-                            }
-                            continue;
-                        }
-
-                        tracing::info!("Lobby msg: {} {}", msg.command, msg.data);
-                        let events = gm.lobby_state.handle_message(&msg);
-                        for event in &events {
-                            // Handle ConnectSpring by launching the engine
-                            if let LobbyEvent::ConnectSpring(data) = event {
-                                tracing::info!("Background loop received ConnectSpring — launching engine");
-                                gm.handle_connect_spring(data).await;
-                            }
-                            if let Err(e) = gm.push_lobby_event(event).await {
-                                tracing::error!("Failed to push lobby event: {}", e);
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        tracing::error!("Lobby connection error: {}", e);
-                        gm.lobby_conn = None;
-                        gm.lobby_state.connected = false;
-                        gm.lobby_state.logged_in = false;
-                        let event = LobbyEvent::Disconnected { reason: e.to_string() };
-                        let _ = gm.push_lobby_event(&event).await;
-                    }
-                }
-            }
+            result = lobby_msg => gm.on_lobby_message(result).await,
 
             result = mcpl_msg => {
                 match result {
-                    Ok(msg) => {
-                        match msg {
-                            McplIncoming::Request(req) => {
-                                let result = match req.method.as_str() {
-                                    "tools/list" => {
-                                        mcpl_server::lobby_tools()
-                                    }
-                                    "tools/call" => {
-                                        let params = req.params.unwrap_or_default();
-                                        let tool_name = params.get("name")
-                                            .and_then(|v| v.as_str())
-                                            .unwrap_or("");
-                                        let tool_args = params.get("arguments")
-                                            .cloned()
-                                            .unwrap_or(serde_json::json!({}));
-                                        gm.handle_tool_call(tool_name, &tool_args).await
-                                    }
-                                    "channels/open" => {
-                                        let params = req.params.unwrap_or_default();
-                                        gm.handle_channels_open(&params).await
-                                    }
-                                    "channels/close" => {
-                                        let params = req.params.unwrap_or_default();
-                                        gm.handle_channels_close(&params).await
-                                    }
-                                    "channels/list" => {
-                                        gm.handle_channels_list().await
-                                    }
-                                    "channels/publish" => {
-                                        let params = req.params.unwrap_or_default();
-                                        gm.handle_channels_publish(&params).await
-                                    }
-                                    "state/rollback" => {
-                                        let params = req.params.unwrap_or_default();
-                                        gm.handle_state_rollback(&params).await
-                                    }
-                                    _ => {
-                                        tracing::warn!("Unknown MCPL method: {}", req.method);
-                                        serde_json::json!({
-                                            "error": { "code": -32601, "message": format!("Method not found: {}", req.method) }
-                                        })
-                                    }
-                                };
-
-                                if let Some(mcpl) = &mut gm.mcpl {
-                                    if let Err(e) = mcpl.send_response(req.id, result).await {
-                                        tracing::error!("Failed to send response: {}", e);
-                                    }
-                                }
-                            }
-                            McplIncoming::Notification(notif) => {
-                                match notif.method.as_str() {
-                                    "featureSets/update" => {
-                                        tracing::info!("Feature sets update: {:?}", notif.params);
-                                    }
-                                    _ => {
-                                        tracing::trace!("Unhandled notification: {}", notif.method);
-                                    }
-                                }
-                            }
-                        }
+                    Ok(msg) => gm.on_mcpl_message(msg).await,
+                    // A stdio parent closing our stdin may mean to detach:
+                    // the games play on without a client.
+                    Err(mcpl_link::Disconnect::Closed) if use_stdio => {
+                        tracing::info!("MCPL client closed stdin; games may run on for up to {:?}", linger);
+                        gm.detach_client().await;
+                        client_gone = Some(std::time::Instant::now());
                     }
                     Err(e) => {
                         tracing::error!("MCPL client disconnected: {}", e);
@@ -1911,77 +365,29 @@ async fn main() -> anyhow::Result<()> {
                 }
             }
 
-            _ = engine_check.tick() => {
-                // Check for SAI connections
-                let newly_connected = gm.sai.accept_pending();
-                for channel_id in &newly_connected {
-                    tracing::info!("SAI connected for channel {}", channel_id);
-                    if let Some(inst) = gm.engines.instances.get_mut(channel_id) {
-                        inst.status = engine::GameStatus::Running;
-                    }
-                    gm.send_channels_changed(
-                        vec![],
-                        vec![],
-                        vec![ChannelDescriptor {
-                            id: channel_id.clone(),
-                            channel_type: "game".into(),
-                            label: "Game".into(),
-                            direction: ChannelDirection::Bidirectional,
-                            address: None,
-                            metadata: Some(serde_json::json!({"status": "running", "saiConnected": true})),
-                        }],
-                    ).await;
-                }
-
-                // Check for engine crashes
-                let changed = gm.engines.check_all().await;
-                for (channel_id, status) in &changed {
-                    tracing::warn!("Engine {} status changed: {:?}", channel_id, status);
-                    gm.sai.close_channel(channel_id);
-                    gm.send_channels_changed(
-                        vec![],
-                        vec![channel_id.clone()],
-                        vec![],
-                    ).await;
+            _ = hangup.recv() => {
+                tracing::info!("SIGHUP: reloading {}", config_path.display());
+                let reloaded = gm.reload_config(&gm_config, config::GmConfig::load(&config_path));
+                if reloaded != gm_config {
+                    gm_config = reloaded;
+                    gm.announce_ready(&gm_config, &checks);
                 }
+            }
 
-                // Read events from connected SAIs
-                let channel_ids: Vec<String> = gm.sai.connections.keys().cloned().collect();
-                for channel_id in channel_ids {
-                    // Collect events from this SAI
-                    let mut events = Vec::new();
-                    if let Some(conn) = gm.sai.connections.get_mut(&channel_id) {
-                        // Non-blocking poll: try to read available events
-                        // (next_event is async but the socket is set up for line-buffered reads)
-                        loop {
-                            // Use tokio::time::timeout for a quick check
-                            match tokio::time::timeout(
-                                tokio::time::Duration::from_millis(1),
-                                conn.next_event(),
-                            ).await {
-                                Ok(Some(event)) => events.push(event),
-                                Ok(None) => {
-                                    // EOF — SAI disconnected
-                                    tracing::warn!("SAI disconnected for {}", channel_id);
-                                    break;
-                                }
-                                Err(_) => break, // timeout — no more events
-                            }
-                        }
-                    }
-
-                    // Forward events (skip Update ticks — noise for the LLM)
-                    for event in &events {
-                        if matches!(event, sai_ipc::SaiEvent::Update { .. }) {
-                            continue;
-                        }
-                        gm.forward_sai_event(&channel_id, event).await;
-                    }
+            _ = engine_check.tick() => {
+                gm.tick(std::time::Instant::now()).await;
+                if let Some(reason) = client_gone.and_then(|since| gm.absent_client_exit(since, linger)) {
+                    tracing::info!("Exiting without an MCPL client: {}", reason);
+                    break;
                 }
             }
         }
+        gm.flush_channels_changed(std::time::Instant::now());
     }
 
+    if let Some(mcpl) = &gm.mcpl {
+        tracing::info!("MCPL delivery: {}", mcpl.stats.summary());
+    }
     tracing::info!("GameManager shutting down");
     Ok(())
 }
@@ -0,0 +1,249 @@
+//! Map grid: the map's ground height sampled on a coarse grid, fetched
+//! from the bridge the first time a channel needs it and kept until the
+//! channel closes, so the agent can plan routes and bases from one picture
+//! instead of a query per point.
+//!
+//! Served as the `game://{channel}/map` resource (heights, slope and
+//! metal spots as JSON) and by the `game_map` tool as ASCII art.
+
+use sai_protocol::MetalSpot;
+
+/// Cell size, in elmos, when the agent asks for none: 48 cells across a
+/// 12x12 map.
+pub const DEFAULT_CELL_SIZE: f32 = 128.0;
+
+/// Height bands of the ASCII rendering, lowest first.
+const SHADES: &[u8] = b".:-=+*#%@";
+
+/// Resource URIs look like `game://{channel}/map`.
+const URI_SCHEME: &str = "game://";
+const URI_SUFFIX: &str = "/map";
+
+/// The resource URI of `channel_id`'s map grid.
+pub fn resource_uri(channel_id: &str) -> String {
+    format!("{}{}{}", URI_SCHEME, channel_id, URI_SUFFIX)
+}
+
+/// The channel a map grid resource URI names.
+pub fn parse_resource_uri(uri: &str) -> Option<&str> {
+    uri.strip_prefix(URI_SCHEME)?.strip_suffix(URI_SUFFIX).filter(|c| !c.is_empty())
+}
+
+/// One channel's sampled map. Rows run along z (north to south), columns
+/// along x; cell (col, row) is centred on ((col + 0.5) * cell_size,
+/// (row + 0.5) * cell_size).
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapGrid {
+    /// The cell size asked for; `cell_size` is what the bridge used.
+    pub requested_cell_size: f32,
+    pub build_def: Option<String>,
+    pub cell_size: f32,
+    pub width: usize,
+    pub height: usize,
+    /// Ground height of each cell, in whole elmos, by row.
+    pub elevation: Vec<Vec<i32>>,
+    /// Whether `build_def` fits in each cell, by row; empty without one.
+    pub buildable: Vec<Vec<bool>>,
+}
+
+impl MapGrid {
+    /// An empty grid, to be filled band by band with [`MapGrid::add_band`].
+    pub fn new(requested_cell_size: f32, build_def: Option<String>, cell_size: f32, width: usize, height: usize) -> Self {
+        Self {
+            requested_cell_size,
+            build_def,
+            cell_size,
+            width,
+            height,
+            elevation: Vec::with_capacity(height),
+            buildable: Vec::new(),
+        }
+    }
+
+    /// Whether this grid answers a request for `cell_size` cells (any, if
+    /// none) and `build_def` buildability (any, if none).
+    pub fn answers(&self, cell_size: Option<f32>, build_def: Option<&str>) -> bool {
+        cell_size.is_none_or(|size| size == self.requested_cell_size)
+            && build_def.is_none_or(|def| self.build_def.as_deref() == Some(def))
+    }
+
+    /// Append the rows of one `map_grid` event. Bands arrive in order.
+    pub fn add_band(&mut self, row_offset: usize, elevation: Vec<Vec<i32>>, buildable: Vec<String>) -> Result<(), String> {
+        if row_offset != self.elevation.len() {
+            return Err(format!("map grid rows out of order: got {}, expected {}", row_offset, self.elevation.len()));
+        }
+        if elevation.iter().any(|row| row.len() != self.width) {
+            return Err(format!("map grid row of the wrong width (expected {})", self.width));
+        }
+        self.elevation.extend(elevation);
+        self.buildable.extend(buildable.iter().map(|row| row.chars().map(|c| c == '1').collect()));
+        Ok(())
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.elevation.len() >= self.height
+    }
+
+    /// Lowest and highest cell.
+    pub fn range(&self) -> (i32, i32) {
+        let heights = self.elevation.iter().flatten().copied();
+        (heights.clone().min().unwrap_or(0), heights.max().unwrap_or(0))
+    }
+
+    /// Steepest rise from a cell to a neighbour, as a percentage grade.
+    pub fn slope(&self, col: usize, row: usize) -> u32 {
+        let here = self.elevation[row][col];
+        let neighbours = [
+            (col.wrapping_sub(1), row),
+            (col + 1, row),
+            (col, row.wrapping_sub(1)),
+            (col, row + 1),
+        ];
+        let rise = neighbours
+            .iter()
+            .filter_map(|&(c, r)| self.elevation.get(r)?.get(c))
+            .map(|&h| (h - here).unsigned_abs())
+            .max()
+            .unwrap_or(0);
+        (rise as f32 * 100.0 / self.cell_size).round() as u32
+    }
+
+    /// The cell a map position falls in, if it's on the map.
+    pub fn cell_of(&self, x: f32, z: f32) -> Option<(usize, usize)> {
+        let (col, row) = ((x / self.cell_size).floor(), (z / self.cell_size).floor());
+        (col >= 0.0 && row >= 0.0 && (col as usize) < self.width && (row as usize) < self.height)
+            .then_some((col as usize, row as usize))
+    }
+
+    /// The resource body: heights, slopes, buildability and metal spots.
+    pub fn to_json(&self, spots: &[MetalSpot]) -> serde_json::Value {
+        let (min, max) = self.range();
+        let slope: Vec<Vec<u32>> =
+            (0..self.elevation.len()).map(|row| (0..self.width).map(|col| self.slope(col, row)).collect()).collect();
+        let mut json = serde_json::json!({
+            "cell_size": self.cell_size,
+            "width": self.width,
+            "height": self.height,
+            "min_elevation": min,
+            "max_elevation": max,
+            "elevation": self.elevation,
+            "slope": slope,
+            "metal_spots": spots
+                .iter()
+                .map(|s| serde_json::json!({"x": s.x, "z": s.z, "metal": s.metal}))
+                .collect::<Vec<_>>(),
+        });
+        if let Some(def) = &self.build_def {
+            json["build_def"] = def.clone().into();
+            json["buildable"] = self
+                .buildable
+                .iter()
+                .map(|row| row.iter().map(|&b| if b { '1' } else { '0' }).collect::<String>())
+                .collect::<Vec<_>>()
+                .into();
+        }
+        json
+    }
+
+    /// ASCII art: a character per cell shading its height, water as `~`,
+    /// metal spots as `M` and, with a build def, cells it doesn't fit in
+    /// as `x`. Ends with a legend.
+    pub fn render_ascii(&self, spots: &[MetalSpot]) -> String {
+        let (min, max) = self.range();
+        let band = |h: i32| {
+            let span = (max - min.max(0)).max(1) as f32;
+            let level = ((h - min.max(0)) as f32 / span * SHADES.len() as f32) as usize;
+            SHADES[level.min(SHADES.len() - 1)]
+        };
+        let mut rows: Vec<Vec<u8>> = self
+            .elevation
+            .iter()
+            .enumerate()
+            .map(|(r, row)| {
+                row.iter()
+                    .enumerate()
+                    .map(|(c, &h)| match self.buildable.get(r).and_then(|b| b.get(c)) {
+                        Some(false) => b'x',
+                        _ if h < 0 => b'~',
+                        _ => band(h),
+                    })
+                    .collect()
+            })
+            .collect();
+        for spot in spots {
+            if let Some((col, row)) = self.cell_of(spot.x, spot.z) {
+                rows[row][col] = b'M';
+            }
+        }
+
+        let mut out = format!(
+            "Map grid {}x{} of {}-elmo cells (x across, z down), heights {}..{}\n",
+            self.width, self.height, self.cell_size, min, max
+        );
+        for row in rows {
+            out.push_str(&String::from_utf8(row).unwrap());
+            out.push('\n');
+        }
+        out.push_str(&format!(
+            "Legend: {} low to high, ~ water, M metal spot",
+            String::from_utf8_lossy(SHADES)
+        ));
+        if let Some(def) = &self.build_def {
+            out.push_str(&format!(", x {} can't be built", def));
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid() -> MapGrid {
+        let mut grid = MapGrid::new(64.0, Some("staticmex".into()), 64.0, 4, 3);
+        grid.add_band(0, vec![vec![-10, 0, 0, 0], vec![0, 0, 32, 64]], vec!["0111".into(), "1111".into()]).unwrap();
+        assert!(!grid.is_complete());
+        grid.add_band(2, vec![vec![0, 0, 64, 128]], vec!["1110".into()]).unwrap();
+        assert!(grid.is_complete());
+        grid
+    }
+
+    #[test]
+    fn test_bands_and_slope() {
+        let mut grid = grid();
+        assert_eq!(grid.range(), (-10, 128));
+        // 64 up to the east over a 64-elmo cell: a 100% grade.
+        assert_eq!(grid.slope(3, 2), 100);
+        assert_eq!(grid.slope(1, 0), 16);
+        assert!(grid.answers(Some(64.0), Some("staticmex")));
+        assert!(grid.answers(None, None));
+        assert!(!grid.answers(Some(32.0), None));
+        assert!(!grid.answers(None, Some("factorycloak")));
+        assert!(grid.add_band(0, vec![vec![0; 4]], Vec::new()).unwrap_err().contains("out of order"));
+        assert!(grid.add_band(3, vec![vec![0; 3]], Vec::new()).unwrap_err().contains("wrong width"));
+    }
+
+    #[test]
+    fn test_render_ascii() {
+        let spots = [MetalSpot { x: 100.0, y: 0.0, z: 10.0, metal: 2.0 }, MetalSpot { x: 9999.0, y: 0.0, z: 0.0, metal: 2.0 }];
+        let art = grid().render_ascii(&spots);
+        let lines: Vec<&str> = art.lines().collect();
+        assert_eq!(lines[0], "Map grid 4x3 of 64-elmo cells (x across, z down), heights -10..128");
+        assert_eq!(&lines[1..4], ["xM..", "..-+", "..+x"]);
+        assert!(lines[4].ends_with("M metal spot, x staticmex can't be built"), "{}", lines[4]);
+
+        let json = grid().to_json(&spots);
+        assert_eq!(json["buildable"][2], "1110");
+        assert_eq!(json["slope"][2][3], 100);
+        assert_eq!(json["metal_spots"].as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_resource_uri() {
+        let uri = resource_uri("game:local-1");
+        assert_eq!(uri, "game://game:local-1/map");
+        assert_eq!(parse_resource_uri(&uri), Some("game:local-1"));
+        assert_eq!(parse_resource_uri("game:///map"), None);
+        assert_eq!(parse_resource_uri("file:///map"), None);
+    }
+}
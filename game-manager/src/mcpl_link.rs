@@ -0,0 +1,459 @@
+//! MCPL client link: a sender task owns the client connection, so a slow
+//! or stuck client can't freeze the main loop.
+//!
+//! The task reads the client's messages and hands them to the main loop,
+//! and sends what the main loop queues: responses, notifications, and the
+//! `channels/incoming` and `push/event` requests. Every send has a timeout,
+//! after which it's abandoned and counted. Outbound events go through a
+//! bounded queue and are dropped (and counted) when it's full. Too many
+//! failed deliveries in a row are handled as the client disconnecting.
+//!
+//! A client that closes its end cleanly (EOF) is told apart from one that
+//! fails. After EOF the task stops reading but keeps sending, so responses
+//! to the client's last requests still go out when the link is closed.
+
+use std::fmt;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use mcpl_core::connection::{ConnectionError, IncomingMessage as McplIncoming};
+use mcpl_core::McplConnection;
+use serde::Deserialize;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/// `mcpl_delivery` section of the config file.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DeliveryConfig {
+    /// Seconds a send may take before it's abandoned.
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: f64,
+    /// Outbound events waiting for the client; more are dropped.
+    #[serde(default = "default_queue_size")]
+    pub queue_size: usize,
+    /// Failed, timed-out or dropped deliveries in a row before the client
+    /// is treated as disconnected.
+    #[serde(default = "default_max_failures")]
+    pub max_failures: u64,
+    /// Seconds `channels/changed` updates to one channel are held and
+    /// merged; 0 sends each as it comes.
+    #[serde(default = "default_changes_window_secs")]
+    pub changes_window_secs: f64,
+    /// `channels/incoming` messages kept per channel for `channels/replay`.
+    #[serde(default = "default_replay_size")]
+    pub replay_size: usize,
+}
+
+impl Default for DeliveryConfig {
+    fn default() -> Self {
+        Self {
+            timeout_secs: default_timeout_secs(),
+            queue_size: default_queue_size(),
+            max_failures: default_max_failures(),
+            changes_window_secs: default_changes_window_secs(),
+            replay_size: default_replay_size(),
+        }
+    }
+}
+
+fn default_timeout_secs() -> f64 {
+    5.0
+}
+
+fn default_queue_size() -> usize {
+    256
+}
+
+fn default_max_failures() -> u64 {
+    10
+}
+
+fn default_changes_window_secs() -> f64 {
+    0.25
+}
+
+fn default_replay_size() -> usize {
+    500
+}
+
+/// Something for the sender task to send.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outgoing {
+    Response { id: serde_json::Value, result: serde_json::Value },
+    Notification { method: &'static str, params: Option<serde_json::Value> },
+    Request { method: &'static str, params: Option<serde_json::Value> },
+}
+
+/// Why the client is gone.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Disconnect {
+    /// The client closed its end cleanly (EOF).
+    Closed,
+    /// A read or protocol error, or too many failed deliveries.
+    Failed(String),
+}
+
+impl fmt::Display for Disconnect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Disconnect::Closed => write!(f, "the client closed the connection"),
+            Disconnect::Failed(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// The client end the sender task drives; `McplConnection` in production.
+pub trait Transport: Send + 'static {
+    fn next_message(&mut self) -> impl Future<Output = Result<McplIncoming, Disconnect>> + Send;
+    fn send(&mut self, outgoing: Outgoing) -> impl Future<Output = Result<(), String>> + Send;
+}
+
+impl Transport for McplConnection {
+    async fn next_message(&mut self) -> Result<McplIncoming, Disconnect> {
+        McplConnection::next_message(self).await.map_err(|e| match e {
+            ConnectionError::Closed => Disconnect::Closed,
+            e => Disconnect::Failed(e.to_string()),
+        })
+    }
+
+    async fn send(&mut self, outgoing: Outgoing) -> Result<(), String> {
+        let sent = match outgoing {
+            Outgoing::Response { id, result } => self.send_response(id, result).await,
+            Outgoing::Notification { method, params } => self.send_notification(method, params).await,
+            Outgoing::Request { method, params } => self.send_request(method, params).await.map(|_| ()),
+        };
+        sent.map_err(|e| e.to_string())
+    }
+}
+
+/// Delivery counters, shared by the link and its sender task.
+#[derive(Debug, Default)]
+pub struct DeliveryStats {
+    pub delivered: AtomicU64,
+    pub failed: AtomicU64,
+    pub timed_out: AtomicU64,
+    pub dropped: AtomicU64,
+    consecutive_failures: AtomicU64,
+}
+
+impl DeliveryStats {
+    fn failure(&self, counter: &AtomicU64) -> u64 {
+        counter.fetch_add(1, Ordering::Relaxed);
+        self.consecutive_failures.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "{} delivered, {} failed, {} timed out, {} dropped",
+            self.delivered.load(Ordering::Relaxed),
+            self.failed.load(Ordering::Relaxed),
+            self.timed_out.load(Ordering::Relaxed),
+            self.dropped.load(Ordering::Relaxed)
+        )
+    }
+}
+
+/// The main loop's handle on the client.
+pub struct McplLink {
+    outbox: mpsc::Sender<Outgoing>,
+    /// Responses skip the queue limit: the client is waiting for them.
+    responses: mpsc::UnboundedSender<Outgoing>,
+    incoming: mpsc::UnboundedReceiver<Result<McplIncoming, Disconnect>>,
+    pub stats: Arc<DeliveryStats>,
+    task: JoinHandle<()>,
+    /// How long `close` waits for queued messages to go out.
+    flush_timeout: Duration,
+}
+
+impl McplLink {
+    /// Start the sender task on `transport`.
+    pub fn spawn<T: Transport>(transport: T, config: &DeliveryConfig) -> Self {
+        let (outbox, outbox_rx) = mpsc::channel(config.queue_size.max(1));
+        let (responses, responses_rx) = mpsc::unbounded_channel();
+        let (incoming_tx, incoming) = mpsc::unbounded_channel();
+        let stats = Arc::new(DeliveryStats::default());
+        let sender = Sender {
+            transport,
+            outbox: outbox_rx,
+            responses: responses_rx,
+            incoming: incoming_tx,
+            stats: stats.clone(),
+            timeout: Duration::from_secs_f64(config.timeout_secs),
+            max_failures: config.max_failures,
+        };
+        let task = tokio::spawn(sender.run());
+        let flush_timeout = Duration::from_secs_f64(config.timeout_secs);
+        Self { outbox, responses, incoming, stats, task, flush_timeout }
+    }
+
+    /// The client's next message. An error means it's gone: it closed the
+    /// connection, or it failed (see `Disconnect`).
+    pub async fn next_message(&mut self) -> Result<McplIncoming, Disconnect> {
+        self.incoming
+            .recv()
+            .await
+            .unwrap_or_else(|| Err(Disconnect::Failed("MCPL sender task stopped".into())))
+    }
+
+    /// Stop queuing and give the sender task one delivery timeout to send
+    /// what's already queued.
+    pub async fn close(self) {
+        let Self { outbox, responses, mut task, flush_timeout, .. } = self;
+        drop((outbox, responses));
+        if tokio::time::timeout(flush_timeout, &mut task).await.is_err() {
+            tracing::warn!("MCPL client didn't take the last messages within {:?}", flush_timeout);
+            task.abort();
+        }
+    }
+
+    pub fn respond(&self, id: serde_json::Value, result: serde_json::Value) -> Result<(), String> {
+        self.responses
+            .send(Outgoing::Response { id, result })
+            .map_err(|_| "MCPL sender task stopped".to_string())
+    }
+
+    pub fn notify(&self, method: &'static str, params: Option<serde_json::Value>) -> Result<(), String> {
+        self.queue(Outgoing::Notification { method, params })
+    }
+
+    pub fn request(&self, method: &'static str, params: Option<serde_json::Value>) -> Result<(), String> {
+        self.queue(Outgoing::Request { method, params })
+    }
+
+    /// Queue without waiting; a full queue drops the event.
+    fn queue(&self, outgoing: Outgoing) -> Result<(), String> {
+        match self.outbox.try_send(outgoing) {
+            Ok(()) => Ok(()),
+            Err(mpsc::error::TrySendError::Full(dropped)) => {
+                self.stats.failure(&self.stats.dropped);
+                Err(format!("MCPL client is not keeping up, dropped {}", label(&dropped)))
+            }
+            Err(mpsc::error::TrySendError::Closed(_)) => Err("MCPL sender task stopped".into()),
+        }
+    }
+}
+
+fn label(outgoing: &Outgoing) -> String {
+    match outgoing {
+        Outgoing::Response { id, .. } => format!("response to {}", id),
+        Outgoing::Notification { method, .. } | Outgoing::Request { method, .. } => method.to_string(),
+    }
+}
+
+struct Sender<T> {
+    transport: T,
+    outbox: mpsc::Receiver<Outgoing>,
+    responses: mpsc::UnboundedReceiver<Outgoing>,
+    incoming: mpsc::UnboundedSender<Result<McplIncoming, Disconnect>>,
+    stats: Arc<DeliveryStats>,
+    timeout: Duration,
+    max_failures: u64,
+}
+
+impl<T: Transport> Sender<T> {
+    async fn run(mut self) {
+        let mut reading = true;
+        let reason = loop {
+            let outgoing = tokio::select! {
+                biased;
+                Some(outgoing) = self.responses.recv() => outgoing,
+                Some(outgoing) = self.outbox.recv() => outgoing,
+                message = self.transport.next_message(), if reading => match message {
+                    Ok(message) => {
+                        if self.incoming.send(Ok(message)).is_err() {
+                            return;
+                        }
+                        continue;
+                    }
+                    // Keep sending until the link is closed: the main loop
+                    // may still be answering the last requests.
+                    Err(Disconnect::Closed) => {
+                        reading = false;
+                        let _ = self.incoming.send(Err(Disconnect::Closed));
+                        continue;
+                    }
+                    Err(e) => break e,
+                },
+                // Link closed and everything queued sent.
+                else => return,
+            };
+            let what = label(&outgoing);
+            let failures = match tokio::time::timeout(self.timeout, self.transport.send(outgoing)).await {
+                Ok(Ok(())) => {
+                    self.stats.delivered.fetch_add(1, Ordering::Relaxed);
+                    self.stats.consecutive_failures.store(0, Ordering::Relaxed);
+                    continue;
+                }
+                Ok(Err(e)) => {
+                    tracing::warn!("Failed to send {} to the MCPL client: {}", what, e);
+                    self.stats.failure(&self.stats.failed)
+                }
+                Err(_) => {
+                    tracing::warn!("MCPL client didn't take {} within {:?}, abandoned", what, self.timeout);
+                    self.stats.failure(&self.stats.timed_out)
+                }
+            };
+            if failures >= self.max_failures {
+                break Disconnect::Failed(format!("{} deliveries in a row failed, timed out or were dropped", failures));
+            }
+        };
+        let _ = self.incoming.send(Err(reason));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mcpl_core::connection::{Notification, Request};
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    /// One end of an in-memory pair: messages from the client arrive on
+    /// `from_client`, sends land in `to_client`. Requests are only answered
+    /// while `answering` is set.
+    struct FakeClient {
+        from_client: mpsc::UnboundedReceiver<McplIncoming>,
+        to_client: mpsc::UnboundedSender<Outgoing>,
+        answering: bool,
+    }
+
+    impl Transport for FakeClient {
+        async fn next_message(&mut self) -> Result<McplIncoming, Disconnect> {
+            self.from_client.recv().await.ok_or(Disconnect::Closed)
+        }
+
+        async fn send(&mut self, outgoing: Outgoing) -> Result<(), String> {
+            let stuck = matches!(outgoing, Outgoing::Request { .. }) && !self.answering;
+            let _ = self.to_client.send(outgoing);
+            if stuck {
+                std::future::pending::<()>().await;
+            }
+            Ok(())
+        }
+    }
+
+    /// JSON-RPC lines over an in-memory pipe, like stdio: read EOF is a
+    /// clean close, a line that isn't JSON-RPC a failure.
+    struct PipeClient {
+        lines: tokio::io::Lines<BufReader<tokio::io::ReadHalf<tokio::io::DuplexStream>>>,
+        writer: tokio::io::WriteHalf<tokio::io::DuplexStream>,
+    }
+
+    impl PipeClient {
+        fn new(stream: tokio::io::DuplexStream) -> Self {
+            let (reader, writer) = tokio::io::split(stream);
+            Self { lines: BufReader::new(reader).lines(), writer }
+        }
+    }
+
+    impl Transport for PipeClient {
+        async fn next_message(&mut self) -> Result<McplIncoming, Disconnect> {
+            let line = self.lines.next_line().await.map_err(|e| Disconnect::Failed(e.to_string()))?;
+            let line = line.ok_or(Disconnect::Closed)?;
+            let v: serde_json::Value = serde_json::from_str(&line).map_err(|e| Disconnect::Failed(e.to_string()))?;
+            let method = v["method"].as_str().ok_or(Disconnect::Failed(format!("not a request: {}", line)))?;
+            Ok(McplIncoming::Request(Request { id: v["id"].clone(), method: method.into(), params: None }))
+        }
+
+        async fn send(&mut self, outgoing: Outgoing) -> Result<(), String> {
+            let Outgoing::Response { id, result } = outgoing else { return Ok(()) };
+            let line = serde_json::json!({"jsonrpc": "2.0", "id": id, "result": result}).to_string() + "\n";
+            self.writer.write_all(line.as_bytes()).await.map_err(|e| e.to_string())
+        }
+    }
+
+    fn pair(answering: bool) -> (FakeClient, mpsc::UnboundedSender<McplIncoming>, mpsc::UnboundedReceiver<Outgoing>) {
+        let (client_tx, from_client) = mpsc::unbounded_channel();
+        let (to_client, client_rx) = mpsc::unbounded_channel();
+        (FakeClient { from_client, to_client, answering }, client_tx, client_rx)
+    }
+
+    fn config(queue_size: usize, max_failures: u64) -> DeliveryConfig {
+        DeliveryConfig { timeout_secs: 0.05, queue_size, max_failures, ..Default::default() }
+    }
+
+    fn incoming() -> Outgoing {
+        Outgoing::Request { method: "channels/incoming", params: None }
+    }
+
+    #[tokio::test]
+    async fn test_messages_pass_both_ways() {
+        let (client, client_tx, mut client_rx) = pair(true);
+        let mut link = McplLink::spawn(client, &config(8, 3));
+
+        client_tx
+            .send(McplIncoming::Notification(Notification { method: "featureSets/update".into(), params: None }))
+            .unwrap();
+        match link.next_message().await.unwrap() {
+            McplIncoming::Notification(n) => assert_eq!(n.method, "featureSets/update"),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        link.request("channels/incoming", None).unwrap();
+        link.respond(1.into(), serde_json::json!({"ok": true})).unwrap();
+        let mut sent = vec![client_rx.recv().await.unwrap(), client_rx.recv().await.unwrap()];
+        sent.sort_by_key(|o| matches!(o, Outgoing::Request { .. }));
+        assert_eq!(sent, [Outgoing::Response { id: 1.into(), result: serde_json::json!({"ok": true}) }, incoming()]);
+
+        // The client hanging up reaches the main loop as an error.
+        drop(client_tx);
+        assert_eq!(link.next_message().await.unwrap_err(), Disconnect::Closed);
+        assert_eq!(link.stats.delivered.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn test_eof_still_flushes_responses() {
+        let (server, client) = tokio::io::duplex(4096);
+        let (client_read, mut client_write) = tokio::io::split(client);
+        let mut link = McplLink::spawn(PipeClient::new(server), &config(8, 3));
+
+        // The client sends a request and closes its end, as a parent
+        // process closing our stdin would.
+        client_write.write_all(b"{\"jsonrpc\":\"2.0\",\"id\":7,\"method\":\"tools/list\"}\n").await.unwrap();
+        client_write.shutdown().await.unwrap();
+        match link.next_message().await.unwrap() {
+            McplIncoming::Request(req) => assert_eq!((req.id, req.method.as_str()), (7.into(), "tools/list")),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(link.next_message().await.unwrap_err(), Disconnect::Closed);
+
+        // Answered after the EOF, and still delivered on close.
+        link.respond(7.into(), serde_json::json!({"tools": []})).unwrap();
+        link.close().await;
+        let mut lines = BufReader::new(client_read).lines();
+        let response: serde_json::Value = serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap();
+        assert_eq!((response["id"].as_i64(), &response["result"]), (Some(7), &serde_json::json!({"tools": []})));
+        assert_eq!(lines.next_line().await.unwrap(), None, "the link is closed after the flush");
+
+        // Garbage on the pipe is a failure, not a clean close.
+        let (server, mut client) = tokio::io::duplex(4096);
+        let mut link = McplLink::spawn(PipeClient::new(server), &config(8, 3));
+        client.write_all(b"not json\n").await.unwrap();
+        assert!(matches!(link.next_message().await.unwrap_err(), Disconnect::Failed(_)));
+    }
+
+    #[tokio::test]
+    async fn test_stuck_client_times_out_and_disconnects() {
+        let (client, _client_tx, mut client_rx) = pair(false);
+        let mut link = McplLink::spawn(client, &config(1, 3));
+
+        // Queuing never waits on the client: the second event is dropped
+        // while the first is stuck.
+        let started = std::time::Instant::now();
+        link.request("channels/incoming", None).unwrap();
+        client_rx.recv().await.unwrap();
+        link.request("channels/incoming", None).unwrap();
+        assert_eq!(
+            link.request("channels/incoming", None).unwrap_err(),
+            "MCPL client is not keeping up, dropped channels/incoming"
+        );
+        assert!(started.elapsed() < Duration::from_millis(50));
+
+        // Two timeouts plus the drop make three failures in a row.
+        let reason = link.next_message().await.unwrap_err();
+        assert_eq!(reason.to_string(), "3 deliveries in a row failed, timed out or were dropped");
+        assert_eq!(link.stats.summary(), "0 delivered, 0 failed, 2 timed out, 1 dropped");
+    }
+}
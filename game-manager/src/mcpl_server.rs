@@ -2,6 +2,8 @@ use mcpl_core::capabilities::*;
 use mcpl_core::connection::{ConnectionError, IncomingMessage as McplIncoming, McplConnection};
 use mcpl_core::methods::*;
 
+use crate::config::GmConfig;
+use serde::Deserialize;
 use tokio::net::TcpListener;
 
 /// Tool definitions exposed to the MCPL client.
@@ -31,6 +33,16 @@ pub fn lobby_tools() -> serde_json::Value {
                     "required": ["username", "password"]
                 }
             },
+            {
+                "name": "lobby_login_stored",
+                "description": "Authenticate with the Zero-K lobby using an account stored in the GameManager's config. The password never appears in arguments or results.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "account": { "type": "string", "description": "Stored account name; defaults to the only one, or 'default'" }
+                    }
+                }
+            },
             {
                 "name": "lobby_register",
                 "description": "Register a new account on the Zero-K lobby server",
@@ -86,8 +98,21 @@ pub fn lobby_tools() -> serde_json::Value {
             },
             {
                 "name": "lobby_list_battles",
-                "description": "List open battles in the lobby",
-                "inputSchema": { "type": "object" }
+                "description": "List open battles in the lobby, one line each (verbose: JSON). Filters combine; text filters ignore case.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "running": { "type": "boolean", "description": "Only battles whose game is (true) or isn't (false) running" },
+                        "has_space": { "type": "boolean", "default": false, "description": "Only battles with a free player slot" },
+                        "no_password": { "type": "boolean", "default": false, "description": "Leave out passworded battles" },
+                        "mode": { "type": "string", "description": "Battle mode, e.g. Teams" },
+                        "search": { "type": "string", "description": "Part of the title or the founder's name" },
+                        "map": { "type": "string", "description": "Part of the map name" },
+                        "sort": { "type": "string", "enum": ["id", "players", "title"], "default": "id" },
+                        "limit": { "type": "integer", "default": 50 },
+                        "verbose": { "type": "boolean", "default": false, "description": "Full JSON instead of one line per battle" }
+                    }
+                }
             },
             {
                 "name": "lobby_list_users",
@@ -99,6 +124,17 @@ pub fn lobby_tools() -> serde_json::Value {
                     }
                 }
             },
+            {
+                "name": "lobby_user_info",
+                "description": "Look up one user: level, elo, clan, country, bot/admin flags and the battle they are in. Falls back to case-insensitive and prefix matches",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "name": { "type": "string" }
+                    },
+                    "required": ["name"]
+                }
+            },
             {
                 "name": "lobby_join_battle",
                 "description": "Join a battle room",
@@ -111,6 +147,18 @@ pub fn lobby_tools() -> serde_json::Value {
                     "required": ["battle_id"]
                 }
             },
+            {
+                "name": "lobby_join_battle_by",
+                "description": "Join the one open battle matching a founder name and/or a title regex (both case-insensitive). Lists the candidates if several match",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "founder": { "type": "string" },
+                        "title_pattern": { "type": "string" },
+                        "password": { "type": "string", "default": "" }
+                    }
+                }
+            },
             {
                 "name": "lobby_leave_battle",
                 "description": "Leave the current battle",
@@ -147,6 +195,11 @@ pub fn lobby_tools() -> serde_json::Value {
                     "required": ["ready"]
                 }
             },
+            {
+                "name": "lobby_status",
+                "description": "Get lobby connection and presence: connected, logged in as whom, server info, joined channels, current battle, matchmaker queues, and when the server last sent anything",
+                "inputSchema": { "type": "object" }
+            },
             {
                 "name": "lobby_matchmaker_status",
                 "description": "Get current matchmaker status: available queues, joined queues, queue counts",
@@ -160,13 +213,25 @@ pub fn lobby_tools() -> serde_json::Value {
                     "properties": {
                         "map": { "type": "string", "description": "Map name (e.g., 'Comet Catcher Redux')" },
                         "game": { "type": "string", "default": "Zero-K $VERSION", "description": "Game type / archive name" },
-                        "opponent": { "type": "string", "default": "CircuitAINovice", "description": "Opponent AI shortname" },
+                        "opponent": { "type": "string", "default": "CircuitAINovice", "description": "Opponent AI shortname (see game_list_opponents)" },
                         "headless": { "type": "boolean", "default": true, "description": "Run without UI (true) or with UI (false)" },
-                        "player_mode": { "type": "boolean", "default": false, "description": "Agent as PLAYER slot (widget hands control via /aicontrol)" }
+                        "player_mode": { "type": "boolean", "default": false, "description": "Agent as PLAYER slot (widget hands control via /aicontrol)" },
+                        "channel_id": { "type": "string", "description": "Channel id to use instead of the next game:local-N, e.g. game:practice. Refused while a game on it is running." },
+                        "preserve_state": { "type": "boolean", "default": false, "description": "With channel_id: keep the last game's groups (emptied), verbosity and stream settings" }
                     },
                     "required": ["map"]
                 }
             },
+            {
+                "name": "game_list_opponents",
+                "description": "The opponent AIs lobby_start_game and game channels can use: name, difficulty tier, the games each plays, and whether it's installed (null when that can't be checked). Includes custom AIs from the config file and installed AIs not in the catalog.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "game": { "type": "string", "description": "Only AIs that play this game, e.g. Zero-K $VERSION" }
+                    }
+                }
+            },
             {
                 "name": "lobby_open_battle",
                 "description": "Host a new custom battle room on the lobby server. You must be connected and logged in. After opening, add bots with lobby_add_bot, then start with lobby_start_battle.",
@@ -209,13 +274,406 @@ pub fn lobby_tools() -> serde_json::Value {
                 "name": "lobby_start_battle",
                 "description": "Start the game in the current battle room. All participants will receive connection details.",
                 "inputSchema": { "type": "object" }
+            },
+            {
+                "name": "game_stats",
+                "description": "Event and command counters for a game channel: events and commands by type, bytes in/out, parse failures, last frame, per-minute rates",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "channel_id": { "type": "string", "description": "Game channel id, e.g. game:local-1" },
+                        "reset": { "type": "boolean", "default": false, "description": "Zero the counters after reading them" }
+                    },
+                    "required": ["channel_id"]
+                }
+            },
+            {
+                "name": "game_pause",
+                "description": "Pause a running game",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "channel_id": { "type": "string", "description": "Game channel id, e.g. game:local-1" }
+                    },
+                    "required": ["channel_id"]
+                }
+            },
+            {
+                "name": "game_resume",
+                "description": "Resume a paused game",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "channel_id": { "type": "string", "description": "Game channel id, e.g. game:local-1" }
+                    },
+                    "required": ["channel_id"]
+                }
+            },
+            {
+                "name": "game_set_speed",
+                "description": "Set the game speed multiplier. Must lie within the channel's min_speed/max_speed (default 0.1-10).",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "channel_id": { "type": "string", "description": "Game channel id, e.g. game:local-1" },
+                        "speed": { "type": "number", "description": "Speed multiplier, 1.0 = normal" }
+                    },
+                    "required": ["channel_id", "speed"]
+                }
+            },
+            {
+                "name": "game_run_benchmark",
+                "description": "Start a benchmark run: headless games at maximum speed, played to completion one after another. Returns the run id at once; game_benchmark_status shows progress, and the report (outcome, winner, duration in frames and wall-clock, final economy) comes as a gm.benchmark_finished event and is saved under the write dir's benchmarks/ folder. One run plays at a time.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "map": { "type": "string", "description": "Map name (unless games is given)" },
+                        "game": { "type": "string", "default": "Zero-K $VERSION", "description": "Game archive name" },
+                        "opponent": { "type": "string", "default": "CircuitAINovice", "description": "Opponent AI shortname (see game_list_opponents)" },
+                        "runs": { "type": "integer", "default": 1, "maximum": 50, "description": "How many times to play map vs opponent" },
+                        "games": {
+                            "type": "array",
+                            "description": "Explicit game list instead of map/runs",
+                            "items": {
+                                "type": "object",
+                                "properties": {
+                                    "map": { "type": "string" },
+                                    "game": { "type": "string" },
+                                    "opponent": { "type": "string" }
+                                },
+                                "required": ["map"]
+                            }
+                        },
+                        "timeout_secs": { "type": "integer", "default": 1800, "description": "Wall-clock limit per game" }
+                    }
+                }
+            },
+            {
+                "name": "game_benchmark_status",
+                "description": "A benchmark run's progress (games done, the game being played), or its report once finished.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "run": { "type": "integer", "description": "Run id from game_run_benchmark; the latest run if omitted" }
+                    }
+                }
+            },
+            {
+                "name": "game_end_turn",
+                "description": "Turn mode (channels/open metadata.turn_mode): resume the game after issuing this turn's commands. The game pauses again at the next update.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "channel_id": { "type": "string", "description": "Game channel id, e.g. game:local-1" }
+                    },
+                    "required": ["channel_id"]
+                }
+            },
+            {
+                "name": "game_cancel_queued",
+                "description": "Cancel a game that is still waiting in the launch queue (status Queued) because the concurrent game limit is reached.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "channel_id": { "type": "string", "description": "Game channel id, e.g. game:local-3" }
+                    },
+                    "required": ["channel_id"]
+                }
+            },
+            {
+                "name": "game_say",
+                "description": "Send an in-game chat message. Players' chat arrives on the game channel authored by the player.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "channel_id": { "type": "string", "description": "Game channel id, e.g. game:local-1" },
+                        "text": { "type": "string" },
+                        "destination": { "type": "string", "enum": ["all", "allies", "spectators"], "description": "Who sees the message (default all)" }
+                    },
+                    "required": ["channel_id", "text"]
+                }
+            },
+            {
+                "name": "game_command",
+                "description": "Send a game command, as channels/publish does: the same JSON, with unit_id or a group. With dry_run the SAI bridge only validates it (unit alive, unit def known, position on the map) and reports what would fail; nothing is executed. Unqueued move, fight and patrol orders come back with an estimated time of arrival (straight line at top speed, so usually early).",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "channel_id": { "type": "string", "description": "Game channel id, e.g. game:local-1" },
+                        "command": { "type": "object", "description": "Command JSON, e.g. {\"type\": \"move\", \"unit_id\": 12, \"x\": 1000, \"z\": 800}" },
+                        "dry_run": { "type": "boolean", "default": false, "description": "Validate without executing" },
+                        "notify": { "type": "boolean", "default": false, "description": "Post a message on the game channel when each command is completed, superseded, failed or rejected" },
+                        "remind": { "type": "boolean", "default": false, "description": "Post a message on the game channel when the units should have arrived; cancelled if they die or get a new order first" }
+                    },
+                    "required": ["channel_id", "command"]
+                }
+            },
+            {
+                "name": "game_command_history",
+                "description": "The commands recently sent to a game channel, oldest first: sequence number, game frame, source (publish, tool or automation), the command JSON, and whether it was sent, failed to send, or was rejected by the SAI bridge. Orders to units are also marked completed when the unit finished them, superseded when a later unqueued order replaced them, or failed when the unit was lost first.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "channel_id": { "type": "string", "description": "Game channel id, e.g. game:local-1" },
+                        "limit": { "type": "integer", "default": 20, "description": "Most recent commands to return" }
+                    },
+                    "required": ["channel_id"]
+                }
+            },
+            {
+                "name": "game_unit_history",
+                "description": "Where a unit has been lately: its last few known positions with their game frames, and which way and how fast it is going. Kept for our units and for enemies seen in line of sight; enemies unseen for two game minutes are forgotten.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "channel_id": { "type": "string", "description": "Game channel id, e.g. game:local-1" },
+                        "unit_id": { "type": "integer", "description": "Our unit's or an enemy's id" }
+                    },
+                    "required": ["channel_id", "unit_id"]
+                }
+            },
+            {
+                "name": "game_factories",
+                "description": "What each of our factories is making: the unit in progress with its progress and seconds left, the orders queued behind it with their build times, and the rally point the GameManager last sent it. Queues are read from the game every 10 game seconds.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "channel_id": { "type": "string", "description": "Game channel id, e.g. game:local-1" }
+                    },
+                    "required": ["channel_id"]
+                }
+            },
+            {
+                "name": "gm_audit_tail",
+                "description": "The newest lines of the GameManager's audit log, oldest first: one JSON object per tool call, channel operation, auto-join or auto-respond, with its time, redacted arguments and outcome.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "lines": { "type": "integer", "default": 20, "description": "How many lines to return" }
+                    }
+                }
+            },
+            {
+                "name": "gm_macro_define",
+                "description": "Define (or replace) a named command macro: a list of game_command JSON templates with ${name} placeholders. Each placeholder must be declared in params with its type; a string that is exactly one placeholder takes the typed value, a longer string gets it spliced in as text. Macros are saved in the write dir.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "name": { "type": "string", "description": "Macro name, e.g. rally_raiders" },
+                        "description": { "type": "string" },
+                        "params": {
+                            "type": "object",
+                            "additionalProperties": { "type": "string", "enum": ["unit", "number", "string", "bool"] },
+                            "description": "Parameter types by name, e.g. {\"factory\": \"unit\", \"x\": \"number\"}"
+                        },
+                        "commands": {
+                            "type": "array",
+                            "items": { "type": "object" },
+                            "description": "Command templates, e.g. {\"type\": \"move\", \"unit_id\": \"${factory}\", \"x\": \"${x}\", \"z\": \"${z}\"}"
+                        }
+                    },
+                    "required": ["name", "commands"]
+                }
+            },
+            {
+                "name": "gm_macro_run",
+                "description": "Run a macro on a game channel: fill in its parameters and send its commands in order. Nothing is sent if a parameter is missing, unknown or of the wrong type.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "name": { "type": "string" },
+                        "channel_id": { "type": "string", "description": "Game channel id, e.g. game:local-1" },
+                        "params": { "type": "object", "description": "Parameter values by name" }
+                    },
+                    "required": ["name", "channel_id"]
+                }
+            },
+            {
+                "name": "gm_macro_list",
+                "description": "List the defined macros with their parameters and command templates.",
+                "inputSchema": { "type": "object" }
+            },
+            {
+                "name": "game_group_create",
+                "description": "Create (or replace) a named unit group on a game channel. Commands published with \"group\": \"<name>\" instead of unit_id go to every living member; dead units leave the group automatically.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "channel_id": { "type": "string", "description": "Game channel id, e.g. game:local-1" },
+                        "name": { "type": "string", "description": "Group name, e.g. raiders" },
+                        "unit_ids": { "type": "array", "items": { "type": "integer" } },
+                        "alert_below": { "type": "integer", "description": "Report on the game channel when deaths shrink the group below this many units" }
+                    },
+                    "required": ["channel_id", "name", "unit_ids"]
+                }
+            },
+            {
+                "name": "game_group_add",
+                "description": "Add units to an existing unit group.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "channel_id": { "type": "string", "description": "Game channel id, e.g. game:local-1" },
+                        "name": { "type": "string" },
+                        "unit_ids": { "type": "array", "items": { "type": "integer" } }
+                    },
+                    "required": ["channel_id", "name", "unit_ids"]
+                }
+            },
+            {
+                "name": "game_group_remove",
+                "description": "Remove units from a unit group, or disband the group when unit_ids is omitted.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "channel_id": { "type": "string", "description": "Game channel id, e.g. game:local-1" },
+                        "name": { "type": "string" },
+                        "unit_ids": { "type": "array", "items": { "type": "integer" } }
+                    },
+                    "required": ["channel_id", "name"]
+                }
+            },
+            {
+                "name": "game_group_list",
+                "description": "List a game channel's unit groups with their living members.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "channel_id": { "type": "string", "description": "Game channel id, e.g. game:local-1" }
+                    },
+                    "required": ["channel_id"]
+                }
+            },
+            {
+                "name": "game_expand",
+                "description": "Queue mex builds for a constructor on the nearest unclaimed metal spots, each next spot nearest the previous one. Spots with our mexes, enemy mexes we've seen, or another constructor on its way are skipped. Returns the chosen positions.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "channel_id": { "type": "string", "description": "Game channel id, e.g. game:local-1" },
+                        "builder_id": { "type": "integer", "description": "Constructor or commander unit id" },
+                        "count": { "type": "integer", "default": 1, "description": "Mexes to queue (at most 10)" }
+                    },
+                    "required": ["channel_id", "builder_id"]
+                }
+            },
+            {
+                "name": "game_assist",
+                "description": "Put every idle constructor on one job: assisting a factory (the nearest one, or target), or reclaiming or repairing in an area (around each constructor without one). Constructors that got other orders since going idle are left alone. Returns which units were tasked.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "channel_id": { "type": "string", "description": "Game channel id, e.g. game:local-1" },
+                        "mode": { "type": "string", "enum": ["factory", "reclaim", "repair_area"] },
+                        "target": { "type": "integer", "description": "Factory unit id to assist (mode factory)" },
+                        "area": {
+                            "type": "object",
+                            "description": "Circle to reclaim or repair in (modes reclaim and repair_area)",
+                            "properties": {
+                                "x": { "type": "number" },
+                                "z": { "type": "number" },
+                                "radius": { "type": "number", "default": 400 }
+                            },
+                            "required": ["x", "z"]
+                        }
+                    },
+                    "required": ["channel_id", "mode"]
+                }
+            },
+            {
+                "name": "game_set_location",
+                "description": "Name a point on a game channel's map, from x/z or from a location. Commands (published, game_command and macros) can then give \"location\": {\"named\": \"<name>\"} instead of x/z, as well as {\"at_unit\": id} or {\"offset_from_unit\": id, \"dx\": .., \"dz\": ..}. \"start\" is set from the start position. Locations are listed in the channel's metadata.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "channel_id": { "type": "string", "description": "Game channel id, e.g. game:local-1" },
+                        "name": { "type": "string", "description": "Location name, e.g. base" },
+                        "x": { "type": "number" },
+                        "z": { "type": "number" },
+                        "location": { "type": "object", "description": "Instead of x/z, e.g. {\"at_unit\": 12}" }
+                    },
+                    "required": ["channel_id", "name"]
+                }
+            },
+            {
+                "name": "game_unitdefs",
+                "description": "Browse the game's unit defs: name, human name, description, role (factory, constructor, structure or unit), costs, health, speed and build options. Fetched from the game once per channel and cached.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "channel_id": { "type": "string", "description": "Game channel id, e.g. game:local-1" },
+                        "filter": { "type": "string", "description": "Only defs whose name, human name or description contains this (ignoring case), e.g. anti-air" },
+                        "fields": {
+                            "type": "array",
+                            "items": { "type": "string", "enum": ["id", "name", "human_name", "description", "role", "metal_cost", "energy_cost", "build_time", "health", "speed", "builder", "build_options", "metal_make", "energy_make", "extracts_metal", "build_speed"] },
+                            "description": "Fields to return (default name, human_name, description, role, metal_cost)"
+                        }
+                    },
+                    "required": ["channel_id"]
+                }
+            },
+            {
+                "name": "game_map",
+                "description": "ASCII map of a game: ground height in bands, water, metal spots and, for a build_def, where it can't be built. Sampled from the game once and cached; the same grid is the game://{channel}/map resource.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "channel_id": { "type": "string", "description": "Game channel id, e.g. game:local-1" },
+                        "cell_size": { "type": "number", "description": "Grid cell size in elmos (default 128); coarsened on large maps to at most 256 cells a side" },
+                        "build_def": { "type": "string", "description": "Unit def name whose buildability to mark, e.g. staticmex" }
+                    },
+                    "required": ["channel_id"]
+                }
+            },
+            {
+                "name": "engine_install",
+                "description": "Download, verify and install a Recoil engine release into the Spring home's engine/linux64/<version>. Runs in the background; progress arrives as lobby.engine_install_* push events.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "version": { "type": "string", "description": "Engine version, e.g. 105.1.1-2511-g747f18b" }
+                    },
+                    "required": ["version"]
+                }
+            },
+            {
+                "name": "game_summary",
+                "description": "Post-game analysis of a game that ended: outcome, units built/lost by type, enemies killed, damage dealt/taken per minute and the economy over time. Available for the most recently ended games.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "channel_id": { "type": "string", "description": "Game channel id, e.g. game:local-1" }
+                    },
+                    "required": ["channel_id"]
+                }
+            },
+            {
+                "name": "game_wait_for",
+                "description": "Wait until something happens in a game instead of polling: a unit finishes, a frame is reached, metal or energy crosses a threshold, an event of some type arrives, or the game ends. Answers when the condition fires (with the event that fired it) or when the timeout passes. A condition that already holds is answered at once.",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": {
+                        "channel_id": { "type": "string", "description": "Game channel id, e.g. game:local-1" },
+                        "condition": {
+                            "type": "object",
+                            "description": "One of {type: unit_finished, unit_id}, {type: frame, frame}, {type: economy, resource: metal|energy, field: current|income (default current), above or below}, {type: event, event: <event type, e.g. enemy_enter_los>}, {type: game_ended}",
+                            "properties": {
+                                "type": { "type": "string", "enum": ["unit_finished", "frame", "economy", "event", "game_ended"] }
+                            },
+                            "required": ["type"]
+                        },
+                        "timeout_s": { "type": "number", "default": 60, "maximum": 600, "description": "Seconds to wait at most" }
+                    },
+                    "required": ["channel_id", "condition"]
+                }
             }
         ]
     })
 }
 
-/// MCPL server capabilities for the GameManager.
-pub fn server_capabilities() -> McplCapabilities {
+/// MCPL server capabilities for the GameManager. Optional capabilities
+/// are advertised only when the config turns them on.
+pub fn server_capabilities(config: &GmConfig) -> McplCapabilities {
     McplCapabilities {
         version: "0.4".into(),
         push_events: Some(true),
@@ -242,29 +700,73 @@ pub fn server_capabilities() -> McplCapabilities {
             },
         ]),
         inference_request: None,
-        stream_observer: None,
-        scoped_access: None,
+        stream_observer: config.stream_observer.enabled.then(|| {
+            serde_json::json!({
+                "intervalSecs": config.stream_observer.interval_secs,
+                "method": crate::observer::STREAM_EVENT,
+            })
+        }),
+        scoped_access: (!config.scopes.is_empty()).then(|| {
+            serde_json::json!({
+                "scopes": config.scopes.keys().collect::<Vec<_>>(),
+                "default": config.default_scope,
+            })
+        }),
         model_info: None,
     }
 }
 
+/// What the client asked for in its initialize request.
+#[derive(Debug, Clone, Default)]
+pub struct ClientOptions {
+    /// The client takes stream events and the server offers them.
+    pub stream_observer: bool,
+    /// The scope the session runs under; None is unrestricted.
+    pub scope: Option<crate::scope::Scope>,
+}
+
+impl ClientOptions {
+    /// Read the client's MCPL capabilities from its initialize params.
+    pub fn negotiate(params: Option<&serde_json::Value>, config: &GmConfig) -> Self {
+        let mcpl = params
+            .and_then(|p| p.pointer("/capabilities/experimental/mcpl"))
+            .cloned()
+            .unwrap_or_default();
+        let wants = |key: &str| mcpl.get(key).is_some_and(|v| !v.is_null() && *v != false);
+        let requested_scope = mcpl.pointer("/scopedAccess/scope").and_then(|v| v.as_str());
+        Self {
+            stream_observer: config.stream_observer.enabled && wants("streamObserver"),
+            scope: crate::scope::Scope::negotiate(
+                requested_scope,
+                &config.scopes,
+                config.default_scope.as_deref(),
+            ),
+        }
+    }
+}
+
 /// Perform the MCPL initialize handshake on an established connection.
-async fn mcpl_handshake(conn: &mut McplConnection) -> Result<(), ConnectionError> {
+async fn mcpl_handshake(
+    conn: &mut McplConnection,
+    config: &GmConfig,
+) -> Result<ClientOptions, ConnectionError> {
     // Wait for initialize request
     let msg = conn.next_message().await?;
-    match msg {
+    let options = match msg {
         McplIncoming::Request(req) if req.method == "initialize" => {
             tracing::info!("Received initialize request");
+            let options = ClientOptions::negotiate(req.params.as_ref(), config);
 
             let result = McplInitializeResult {
                 protocol_version: "2024-11-05".into(),
                 capabilities: InitializeCapabilities {
                     experimental: Some(ExperimentalCapabilities {
-                        mcpl: Some(server_capabilities()),
+                        mcpl: Some(server_capabilities(config)),
                     }),
                     other: {
                         let mut m = serde_json::Map::new();
                         m.insert("tools".into(), serde_json::json!({}));
+                        m.insert("resources".into(), serde_json::json!({}));
                         m
                     },
                 },
@@ -276,12 +778,13 @@ async fn mcpl_handshake(conn: &mut McplConnection) -> Result<(), ConnectionError
 
             conn.send_response(req.id, serde_json::to_value(&result).unwrap())
                 .await?;
+            options
         }
         _ => {
             tracing::error!("Expected initialize request, got {:?}", msg);
             return Err(ConnectionError::Closed);
         }
-    }
+    };
 
     // Wait for initialized notification
     let msg = conn.next_message().await?;
@@ -295,29 +798,100 @@ async fn mcpl_handshake(conn: &mut McplConnection) -> Result<(), ConnectionError
         }
     }
 
-    Ok(())
+    Ok(options)
 }
 
 /// Accept and initialize a single MCPL client connection over TCP.
 pub async fn accept_mcpl_client(
     listener: &TcpListener,
-) -> Result<McplConnection, ConnectionError> {
+    config: &GmConfig,
+) -> Result<(McplConnection, ClientOptions), ConnectionError> {
     let (stream, addr) = listener.accept().await.map_err(ConnectionError::Io)?;
     tracing::info!("MCPL client connected from {}", addr);
 
     let mut conn = McplConnection::new(stream);
-    mcpl_handshake(&mut conn).await?;
-    Ok(conn)
+    let options = mcpl_handshake(&mut conn, config).await?;
+    Ok((conn, options))
+}
+
+/// `stdio` section of the config file.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StdioConfig {
+    /// Seconds games may run on after the client closes stdin; the
+    /// GameManager exits sooner once no games are left.
+    #[serde(default = "default_linger_secs")]
+    pub linger_secs: f64,
+}
+
+impl Default for StdioConfig {
+    fn default() -> Self {
+        Self { linger_secs: default_linger_secs() }
+    }
+}
+
+fn default_linger_secs() -> f64 {
+    600.0
 }
 
 /// Create and initialize an MCPL connection over stdin/stdout.
-pub async fn accept_mcpl_stdio() -> Result<McplConnection, ConnectionError> {
+pub async fn accept_mcpl_stdio(
+    config: &GmConfig,
+) -> Result<(McplConnection, ClientOptions), ConnectionError> {
     tracing::info!("Starting MCPL server on stdio");
 
     let stdin = tokio::io::stdin();
     let stdout = tokio::io::stdout();
 
-    let mut conn = McplConnection::from_parts(Box::new(stdin), Box::new(stdout));
-    mcpl_handshake(&mut conn).await?;
-    Ok(conn)
+    accept_mcpl_stream(Box::new(stdin), Box::new(stdout), config).await
+}
+
+/// Create and initialize an MCPL connection over any reader and writer
+/// (the self-test's in-process client).
+pub async fn accept_mcpl_stream(
+    reader: Box<dyn tokio::io::AsyncRead + Unpin + Send>,
+    writer: Box<dyn tokio::io::AsyncWrite + Unpin + Send>,
+    config: &GmConfig,
+) -> Result<(McplConnection, ClientOptions), ConnectionError> {
+    let mut conn = McplConnection::from_parts(reader, writer);
+    let options = mcpl_handshake(&mut conn, config).await?;
+    Ok((conn, options))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stream_observer_negotiation() {
+        let client = |caps: serde_json::Value| {
+            serde_json::json!({"capabilities": {"experimental": {"mcpl": caps}}})
+        };
+        let mut config = GmConfig::default();
+        let wants = client(serde_json::json!({"streamObserver": {}}));
+        assert!(server_capabilities(&config).stream_observer.is_none());
+        assert!(!ClientOptions::negotiate(Some(&wants), &config).stream_observer);
+
+        config.stream_observer.enabled = true;
+        assert!(server_capabilities(&config).stream_observer.is_some());
+        assert!(ClientOptions::negotiate(Some(&wants), &config).stream_observer);
+        let declines = client(serde_json::json!({"streamObserver": false}));
+        assert!(!ClientOptions::negotiate(Some(&declines), &config).stream_observer);
+        assert!(!ClientOptions::negotiate(None, &config).stream_observer);
+    }
+
+    #[test]
+    fn test_scope_negotiation() {
+        let mut config = GmConfig::default();
+        assert!(server_capabilities(&config).scoped_access.is_none());
+        config.scopes.insert("game".into(), serde_json::from_value(serde_json::json!({"tools": ["game_*"]})).unwrap());
+        config.default_scope = Some("game".into());
+        assert!(server_capabilities(&config).scoped_access.is_some());
+
+        let options = ClientOptions::negotiate(None, &config);
+        assert_eq!(options.scope.unwrap().name, "game");
+        let asks = serde_json::json!({"capabilities": {"experimental": {"mcpl": {"scopedAccess": {"scope": "full"}}}}});
+        let options = ClientOptions::negotiate(Some(&asks), &config);
+        assert!(!options.scope.unwrap().allows_tool("game_pause"));
+    }
 }
@@ -0,0 +1,138 @@
+//! A scripted MCPL client for tests. It plugs into the GameManager as an
+//! `McplLink` transport, so a test drives the same path a live client does
+//! (handshake options, request routing, responses, notifications queued
+//! through the link) and reads everything back as JSON-RPC values.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use mcpl_core::connection::{IncomingMessage as McplIncoming, Notification, Request};
+use serde_json::Value;
+use tokio::sync::mpsc;
+
+use crate::config::GmConfig;
+use crate::mcpl_link::{Disconnect, McplLink, Outgoing, Transport};
+use crate::mcpl_server::ClientOptions;
+use crate::service::GameManager;
+
+/// How long to wait for a response or notification before failing.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The GameManager's end: what the client sends arrives here, and what the
+/// GameManager sends goes back as JSON.
+struct ServerEnd {
+    from_client: mpsc::UnboundedReceiver<McplIncoming>,
+    to_client: mpsc::UnboundedSender<Value>,
+}
+
+impl Transport for ServerEnd {
+    async fn next_message(&mut self) -> Result<McplIncoming, Disconnect> {
+        self.from_client.recv().await.ok_or(Disconnect::Closed)
+    }
+
+    async fn send(&mut self, outgoing: Outgoing) -> Result<(), String> {
+        self.to_client.send(crate::self_test::to_message(outgoing)).map_err(|_| "test client gone".to_string())
+    }
+}
+
+pub struct TestClient {
+    to_server: mpsc::UnboundedSender<McplIncoming>,
+    from_server: mpsc::UnboundedReceiver<Value>,
+    /// Messages read past while waiting for something else.
+    pub unread: VecDeque<Value>,
+    next_id: u64,
+}
+
+impl TestClient {
+    /// Connect `gm` to a new client with the usual capabilities.
+    pub fn attach(gm: &mut GameManager, config: &GmConfig) -> Self {
+        Self::attach_with(gm, config, serde_json::json!({"pushEvents": true, "channels": true}))
+    }
+
+    /// Connect `gm` to a new client offering these MCPL capabilities in its
+    /// initialize request, and configure it with what was agreed, as the
+    /// main loop does after the handshake.
+    pub fn attach_with(gm: &mut GameManager, config: &GmConfig, mcpl: Value) -> Self {
+        let initialize = serde_json::json!({
+            "protocolVersion": "2024-11-05",
+            "capabilities": {"experimental": {"mcpl": mcpl}},
+            "clientInfo": {"name": "test", "version": "0"},
+        });
+        let options = ClientOptions::negotiate(Some(&initialize), config);
+        let (to_server, from_client) = mpsc::unbounded_channel();
+        let (to_client, from_server) = mpsc::unbounded_channel();
+        gm.mcpl = Some(McplLink::spawn(ServerEnd { from_client, to_client }, &config.mcpl_delivery));
+        gm.configure(config, Default::default(), options);
+        // Tests read channels/changed as it's sent; holding is tested apart.
+        gm.channel_changes.set_window(std::time::Duration::ZERO);
+        Self { to_server, from_server, unread: VecDeque::new(), next_id: 1 }
+    }
+
+    /// Send a request and let `gm` handle it, as the main loop would.
+    /// Returns the whole JSON-RPC response.
+    pub async fn request(&mut self, gm: &mut GameManager, method: &str, params: Value) -> Value {
+        let id = self.send(gm, method, params).await;
+        self.response(id).await
+    }
+
+    /// Send a request and let `gm` handle it without waiting for the
+    /// response, for calls answered later. Returns the request id.
+    pub async fn send(&mut self, gm: &mut GameManager, method: &str, params: Value) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        let request = Request { id: id.into(), method: method.into(), params: Some(params) };
+        self.to_server.send(McplIncoming::Request(request)).expect("GameManager gone");
+        self.pump(gm, method).await;
+        id
+    }
+
+    /// The response to request `id`.
+    pub async fn response(&mut self, id: u64) -> Value {
+        self.find(|m| m["id"] == id && m.get("method").is_none(), &format!("response to request {}", id)).await
+    }
+
+    /// Shorthand for the `result` of a request.
+    pub async fn call(&mut self, gm: &mut GameManager, method: &str, params: Value) -> Value {
+        let response = self.request(gm, method, params).await;
+        response.get("result").cloned().unwrap_or_else(|| panic!("{} got no result: {}", method, response))
+    }
+
+    /// Send a notification and let `gm` handle it.
+    pub async fn notify(&mut self, gm: &mut GameManager, method: &str, params: Option<Value>) {
+        let notification = Notification { method: method.into(), params };
+        self.to_server.send(McplIncoming::Notification(notification)).expect("GameManager gone");
+        self.pump(gm, method).await;
+    }
+
+    /// The next message from the GameManager with this method, skipping
+    /// (and keeping) anything else.
+    pub async fn next(&mut self, method: &str) -> Value {
+        self.find(|m| m["method"] == method, method).await
+    }
+
+    /// Hand the message just sent to `gm`.
+    async fn pump(&mut self, gm: &mut GameManager, method: &str) {
+        let mcpl = gm.mcpl.as_mut().expect("no client attached");
+        let msg = tokio::time::timeout(TIMEOUT, mcpl.next_message())
+            .await
+            .unwrap_or_else(|_| panic!("{} never reached the GameManager", method))
+            .expect("client disconnected");
+        gm.on_mcpl_message(msg).await;
+    }
+
+    async fn find(&mut self, wanted: impl Fn(&Value) -> bool, what: &str) -> Value {
+        if let Some(at) = self.unread.iter().position(&wanted) {
+            return self.unread.remove(at).unwrap();
+        }
+        loop {
+            let message = tokio::time::timeout(TIMEOUT, self.from_server.recv())
+                .await
+                .unwrap_or_else(|_| panic!("no {}", what))
+                .expect("GameManager gone");
+            if wanted(&message) {
+                return message;
+            }
+            self.unread.push_back(message);
+        }
+    }
+}
@@ -0,0 +1,439 @@
+//! Stream observer: a compact state line per game channel, sent at an
+//! interval as MCPL stream events instead of `channels/incoming`, so the
+//! main channel stays for events worth the agent's attention.
+//!
+//! The line is built from what the GameManager already caches from the
+//! event stream; nothing is asked of the engine.
+
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::army::{ArmyConfig, ArmyTracker, Composition};
+use crate::sai_ipc::{SaiEvent, UnitId};
+use crate::unit_defs::UnitDefCatalog;
+use crate::threats::Threat;
+use sai_protocol::{Economy, TeamEconomy};
+
+/// MCPL notification carrying one state line.
+pub const STREAM_EVENT: &str = "stream/event";
+
+/// `stream_observer` section of the config file.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StreamObserverConfig {
+    /// Advertise the capability. Off unless the config turns it on.
+    #[serde(default)]
+    pub enabled: bool,
+    /// Seconds between state lines unless a channel sets its own.
+    #[serde(default = "default_interval_secs")]
+    pub interval_secs: f64,
+}
+
+impl Default for StreamObserverConfig {
+    fn default() -> Self {
+        Self { enabled: false, interval_secs: default_interval_secs() }
+    }
+}
+
+fn default_interval_secs() -> f64 {
+    10.0
+}
+
+/// Which parts go into a channel's state line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Include {
+    pub frame: bool,
+    pub economy: bool,
+    pub units: bool,
+    pub threats: bool,
+    /// Army composition by role (see `army`).
+    pub army: bool,
+    /// What the factories are making (see `factories`).
+    pub factories: bool,
+}
+
+impl Default for Include {
+    fn default() -> Self {
+        Self { frame: true, economy: true, units: true, threats: true, army: true, factories: true }
+    }
+}
+
+/// A channel's stream settings, from `channels/open` `metadata.stream`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StreamSettings {
+    pub enabled: bool,
+    pub interval: Duration,
+    pub include: Include,
+}
+
+impl StreamSettings {
+    pub fn new(interval_secs: f64) -> Self {
+        Self {
+            enabled: true,
+            interval: Duration::from_secs_f64(interval_secs),
+            include: Include::default(),
+        }
+    }
+
+    /// Parse `metadata.stream`: `false` leaves the channel out of the
+    /// stream, an object sets `interval_secs` and/or `include` (a list of
+    /// frame, economy, units, threats, army, factories).
+    pub fn from_metadata(
+        metadata: Option<&serde_json::Value>,
+        default_interval_secs: f64,
+    ) -> Result<Self, String> {
+        let mut settings = Self::new(default_interval_secs);
+        let config = match metadata.and_then(|m| m.get("stream")) {
+            None => return Ok(settings),
+            Some(serde_json::Value::Bool(enabled)) => {
+                settings.enabled = *enabled;
+                return Ok(settings);
+            }
+            Some(v @ serde_json::Value::Object(_)) => v,
+            Some(_) => return Err("stream must be a boolean or an object".into()),
+        };
+        if let Some(v) = config.get("interval_secs") {
+            let secs = v
+                .as_f64()
+                .filter(|s| *s >= 1.0)
+                .ok_or("stream.interval_secs must be a number of at least 1")?;
+            settings.interval = Duration::from_secs_f64(secs);
+        }
+        if let Some(v) = config.get("include") {
+            let parts = v.as_array().ok_or("stream.include must be a list")?;
+            let mut include =
+                Include { frame: false, economy: false, units: false, threats: false, army: false, factories: false };
+            for part in parts {
+                match part.as_str() {
+                    Some("frame") => include.frame = true,
+                    Some("economy") => include.economy = true,
+                    Some("units") => include.units = true,
+                    Some("threats") => include.threats = true,
+                    Some("army") => include.army = true,
+                    Some("factories") => include.factories = true,
+                    _ => {
+                        return Err(format!(
+                            "Unknown stream part {} (expected frame, economy, units, threats, army or factories)",
+                            part
+                        ))
+                    }
+                }
+            }
+            settings.include = include;
+        }
+        Ok(settings)
+    }
+}
+
+/// What a channel's events say about the game right now.
+#[derive(Debug, Default)]
+pub struct ChannelState {
+    pub frame: i32,
+    pub economy: Option<Economy>,
+    /// Allied teams' economies from the same update; empty in a 1v1.
+    pub allies: Vec<TeamEconomy>,
+    pub units: HashSet<UnitId>,
+    pub enemies_in_los: HashSet<UnitId>,
+    pub army: ArmyTracker,
+}
+
+impl ChannelState {
+    pub fn observe(&mut self, event: &SaiEvent) {
+        self.army.observe(event);
+        match event {
+            SaiEvent::Init { frame, .. } => self.frame = *frame,
+            SaiEvent::Update { frame, economy, allies, .. } => {
+                self.frame = *frame;
+                if economy.is_some() {
+                    self.economy = *economy;
+                    self.allies = allies.clone();
+                }
+            }
+            SaiEvent::Roster { frame, units } => {
+                self.frame = *frame;
+                self.units = units.iter().map(|u| u.unit).collect();
+            }
+            SaiEvent::UnitCreated { unit, .. } | SaiEvent::UnitGiven { unit, .. } => {
+                self.units.insert(*unit);
+            }
+            SaiEvent::UnitDestroyed { unit, .. } | SaiEvent::UnitCaptured { unit, .. } => {
+                self.units.remove(unit);
+            }
+            SaiEvent::EnemyEnterLos { enemy, .. } => {
+                self.enemies_in_los.insert(*enemy);
+            }
+            SaiEvent::EnemyLeaveLos { enemy, .. } | SaiEvent::EnemyDestroyed { enemy, .. } => {
+                self.enemies_in_los.remove(enemy);
+            }
+            _ => {}
+        }
+    }
+
+    /// Our income and our allies' together; None without allies or
+    /// before the first economy.
+    pub fn team_income(&self) -> Option<TeamIncome> {
+        let economy = self.economy.filter(|_| !self.allies.is_empty())?;
+        let allies = self.allies.iter().fold(Income::default(), |sum, ally| Income {
+            metal: sum.metal + ally.metal.income,
+            energy: sum.energy + ally.energy.income,
+        });
+        Some(TeamIncome { ours: Income { metal: economy.metal.income, energy: economy.energy.income }, allies })
+    }
+
+    /// The state line and its structured form. `army` is the army part,
+    /// from [`ChannelObserver::army_report`], and `factories` the factory
+    /// part, from [`crate::factories::digest`].
+    pub fn line(
+        &self,
+        include: Include,
+        threats: &[Threat],
+        army: Option<(String, serde_json::Value)>,
+        factories: Option<(String, serde_json::Value)>,
+    ) -> (String, serde_json::Value) {
+        let mut parts = Vec::new();
+        let mut data = serde_json::json!({});
+        if include.frame {
+            parts.push(format!("frame {} ({}s)", self.frame, self.frame / 30));
+            data["frame"] = self.frame.into();
+        }
+        if let (true, Some(e)) = (include.economy, &self.economy) {
+            parts.push(format!(
+                "metal {:.0}/{:.0} +{:.1} -{:.1}, energy {:.0}/{:.0} +{:.1} -{:.1}",
+                e.metal.current, e.metal.storage, e.metal.income, e.metal.usage,
+                e.energy.current, e.energy.storage, e.energy.income, e.energy.usage
+            ));
+            data["economy"] = serde_json::to_value(e).unwrap();
+            if let Some(team) = self.team_income() {
+                parts.push(format!(
+                    "{} allies +{:.1} metal +{:.1} energy, our share {:.0}%",
+                    self.allies.len(),
+                    team.allies.metal,
+                    team.allies.energy,
+                    team.metal_share() * 100.0
+                ));
+                data["allies"] = serde_json::json!({
+                    "teams": self.allies.len(),
+                    "metalIncome": team.allies.metal,
+                    "energyIncome": team.allies.energy,
+                    "metalShare": team.metal_share(),
+                });
+            }
+        }
+        if include.units {
+            parts.push(format!("{} units, {} enemies in sight", self.units.len(), self.enemies_in_los.len()));
+            data["units"] = self.units.len().into();
+            data["enemiesInSight"] = self.enemies_in_los.len().into();
+        }
+        if include.threats {
+            let ids: Vec<&str> = threats.iter().map(|t| t.id.as_str()).collect();
+            let described: Vec<String> = threats
+                .iter()
+                .map(|t| match &t.motion {
+                    Some(motion) => format!("{} {}", t.id, motion.text()),
+                    None => t.id.clone(),
+                })
+                .collect();
+            parts.push(if ids.is_empty() { "no threats".into() } else { format!("threats: {}", described.join(", ")) });
+            data["threats"] = ids.into();
+        }
+        if let (true, Some((text, army))) = (include.army, army) {
+            parts.push(text);
+            data["army"] = army;
+        }
+        if let (true, Some((text, factories))) = (include.factories, factories) {
+            parts.push(text);
+            data["factories"] = factories;
+        }
+        (parts.join(" | "), data)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize)]
+pub struct Income {
+    pub metal: f32,
+    pub energy: f32,
+}
+
+/// Income across an allied team, from [`ChannelState::team_income`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TeamIncome {
+    pub ours: Income,
+    /// All allies together.
+    pub allies: Income,
+}
+
+impl TeamIncome {
+    /// Our part of the team's metal income, 0 to 1.
+    pub fn metal_share(&self) -> f32 {
+        let total = self.ours.metal + self.allies.metal;
+        if total > 0.0 { self.ours.metal / total } else { 0.0 }
+    }
+
+    pub fn energy_share(&self) -> f32 {
+        let total = self.ours.energy + self.allies.energy;
+        if total > 0.0 { self.ours.energy / total } else { 0.0 }
+    }
+}
+
+/// One channel's settings, state and pacing.
+#[derive(Debug)]
+pub struct ChannelObserver {
+    pub settings: StreamSettings,
+    pub state: ChannelState,
+    last_sent: Option<Instant>,
+    /// The army as of the last line, for its deltas.
+    last_army: Option<Composition>,
+}
+
+impl ChannelObserver {
+    pub fn new(settings: StreamSettings) -> Self {
+        Self { settings, state: ChannelState::default(), last_sent: None, last_army: None }
+    }
+
+    /// True if a line is due at `now`, marking it sent. Nothing is due
+    /// before the first event arrives.
+    pub fn take_due(&mut self, now: Instant) -> bool {
+        if !self.settings.enabled || self.state.frame == 0 && self.state.economy.is_none() {
+            return false;
+        }
+        if self.last_sent.is_some_and(|t| now.duration_since(t) < self.settings.interval) {
+            return false;
+        }
+        self.last_sent = Some(now);
+        true
+    }
+
+    /// The army part of the next line, if the channel includes it, with
+    /// the changes since the previous one.
+    pub fn army_report(
+        &mut self,
+        config: &ArmyConfig,
+        catalog: Option<&UnitDefCatalog>,
+    ) -> Option<(String, serde_json::Value)> {
+        if !self.settings.include.army {
+            return None;
+        }
+        let composition = self.state.army.composition(config, catalog);
+        let report = composition.report(config, self.last_army.as_ref());
+        self.last_army = Some(composition);
+        Some(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sai_protocol::{ResourceState, RosterUnit, WeaponDefId};
+
+    fn roster() -> SaiEvent {
+        let unit = |unit| RosterUnit { unit, unit_name: None, pos: [0.0; 3] };
+        SaiEvent::Roster { frame: 1, units: vec![unit(UnitId(1)), unit(UnitId(2)), unit(UnitId(3))] }
+    }
+
+    #[test]
+    fn test_state_line_from_events() {
+        let mut state = ChannelState::default();
+        state.observe(&roster());
+        state.observe(&SaiEvent::UnitCreated { unit: UnitId(4), unit_name: None, builder: UnitId(1), builder_name: None, pos: None });
+        state.observe(&SaiEvent::UnitDestroyed {
+            unit: UnitId(2), unit_name: None, attacker: UnitId(90), attacker_name: None,
+            attacker_team: None, attacker_relation: None, weapon_def_id: WeaponDefId(1),
+        });
+        state.observe(&SaiEvent::EnemyEnterLos { enemy: UnitId(90), enemy_name: None, team: None, relation: None, pos: None });
+        state.observe(&SaiEvent::EnemyEnterLos { enemy: UnitId(91), enemy_name: None, team: None, relation: None, pos: None });
+        state.observe(&SaiEvent::EnemyLeaveLos { enemy: UnitId(91), enemy_name: None, team: None, relation: None });
+        state.observe(&SaiEvent::Update {
+            frame: 900,
+            awaiting_commands: false,
+            economy: Some(Economy {
+                metal: ResourceState { current: 120.0, income: 6.5, usage: 4.0, storage: 500.0, received: 0.0 },
+                energy: ResourceState { current: 300.0, income: 20.0, usage: 12.0, storage: 1000.0, received: 0.0 },
+            }),
+            allies: Vec::new(),
+            counters: Default::default(),
+            command_backlog: 0,
+            under_construction: Vec::new(),
+            update_interval: None,
+        });
+
+        let (line, data) = state.line(Include::default(), &[], None, None);
+        assert_eq!(
+            line,
+            "frame 900 (30s) | metal 120/500 +6.5 -4.0, energy 300/1000 +20.0 -12.0 | 3 units, 1 enemies in sight | no threats"
+        );
+        assert_eq!(data["units"], 3);
+        assert_eq!(data["economy"]["metal"]["income"], 6.5);
+
+        let only_units =
+            Include { frame: false, economy: false, units: true, threats: false, army: false, factories: false };
+        assert_eq!(state.line(only_units, &[], None, None).0, "3 units, 1 enemies in sight");
+        assert_eq!(state.team_income(), None);
+
+        // A 2v2: one ally making more metal than we do.
+        let ally = |income| ResourceState { income, ..Default::default() };
+        let economy = state.economy;
+        state.observe(&SaiEvent::Update {
+            frame: 930,
+            awaiting_commands: false,
+            economy,
+            allies: vec![TeamEconomy { team: sai_protocol::TeamId(2), metal: ally(13.0), energy: ally(30.0) }],
+            counters: Default::default(),
+            command_backlog: 0,
+            under_construction: Vec::new(),
+            update_interval: None,
+        });
+        let only_economy =
+            Include { frame: false, economy: true, units: false, threats: false, army: false, factories: false };
+        let (line, data) = state.line(only_economy, &[], None, None);
+        assert_eq!(
+            line,
+            "metal 120/500 +6.5 -4.0, energy 300/1000 +20.0 -12.0 | 1 allies +13.0 metal +30.0 energy, our share 33%"
+        );
+        assert_eq!(data["allies"]["metalIncome"], 13.0);
+        assert_eq!(state.team_income().unwrap().energy_share(), 0.4);
+    }
+
+    #[test]
+    fn test_settings_and_pacing() {
+        let meta = serde_json::json!({"stream": {"interval_secs": 30, "include": ["frame", "threats"]}});
+        let settings = StreamSettings::from_metadata(Some(&meta), 10.0).unwrap();
+        assert_eq!(settings.interval, Duration::from_secs(30));
+        assert!(settings.include.threats && !settings.include.economy);
+        assert!(!StreamSettings::from_metadata(Some(&serde_json::json!({"stream": false})), 10.0).unwrap().enabled);
+        let bad = serde_json::json!({"stream": {"include": ["mood"]}});
+        assert_eq!(
+            StreamSettings::from_metadata(Some(&bad), 10.0).unwrap_err(),
+            "Unknown stream part \"mood\" (expected frame, economy, units, threats, army or factories)"
+        );
+
+        let mut observer = ChannelObserver::new(StreamSettings::new(10.0));
+        let now = Instant::now();
+        assert!(!observer.take_due(now), "nothing to say before the first event");
+        observer.state.observe(&roster());
+        assert!(observer.take_due(now));
+        assert!(!observer.take_due(now + Duration::from_secs(5)));
+        assert!(observer.take_due(now + Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn test_army_part_between_lines() {
+        let config = crate::profiles::Profiles::default().select("Zero-K v1.12.1.0").army.clone();
+        let mut observer = ChannelObserver::new(StreamSettings::new(10.0));
+        observer.state.observe(&roster());
+        let (text, army) = observer.army_report(&config, None).unwrap();
+        assert_eq!(text, "army 3: other 3");
+        let (line, data) = observer.state.line(Include::default(), &[], Some((text, army)), None);
+        assert!(line.ends_with("| no threats | army 3: other 3"), "{}", line);
+        assert_eq!(data["army"]["total"], 3);
+
+        observer.state.observe(&SaiEvent::UnitFinished { unit: UnitId(4), unit_name: Some("cloakraid".into()), pos: None });
+        assert_eq!(observer.army_report(&config, None).unwrap().0, "army 4 (+1): raiders 1 (+1), other 3");
+        assert_eq!(observer.army_report(&config, None).unwrap().0, "army 4: raiders 1, other 3");
+
+        let meta = serde_json::json!({"stream": {"include": ["units"]}});
+        observer.settings = StreamSettings::from_metadata(Some(&meta), 10.0).unwrap();
+        assert_eq!(observer.army_report(&config, None), None);
+    }
+}
@@ -0,0 +1,226 @@
+//! Opponent catalog: the AIs a local game can be played against, with a
+//! difficulty tier and the games each plays, so a mistyped `opponent` is
+//! caught with a suggestion instead of an engine that fails to load it.
+//!
+//! The built-in entries cover the usual Zero-K opponents; the config file's
+//! `opponents` adds custom AIs or replaces built-in ones. Where the engine's
+//! `AI/Skirmish` dir can be read, entries are marked installed or not, and
+//! installed AIs the catalog doesn't know are listed as well.
+
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+/// Opponent when a game doesn't name one.
+pub const DEFAULT_OPPONENT: &str = "CircuitAINovice";
+
+/// Name, difficulty, games and description of each built-in opponent.
+const BUILT_IN: &[(&str, &str, &[&str], &str)] = &[
+    ("CircuitAIBeginner", "beginner", &["Zero-K"], "CircuitAI at its weakest, for first games"),
+    ("CircuitAINovice", "novice", &["Zero-K"], "CircuitAI, slow to expand and attack"),
+    ("CircuitAIEasy", "easy", &["Zero-K"], "CircuitAI with a basic economy"),
+    ("CircuitAINormal", "normal", &["Zero-K"], "CircuitAI playing a full game"),
+    ("CircuitAIHard", "hard", &["Zero-K"], "CircuitAI with faster reactions and more micro"),
+    ("CircuitAIBrutal", "brutal", &["Zero-K"], "CircuitAI at full strength"),
+    ("NullAI", "none", &[], "Does nothing; for testing commands"),
+    ("BARb", "normal", &["Beyond All Reason"], "Beyond All Reason's CircuitAI build"),
+];
+
+/// The bridge the agent plays through. Not a catalog entry: as a game's
+/// opponent it means self-play, another agent on the other side.
+pub const AGENT_AI: &str = "AgentBridge";
+
+/// One catalog entry; config file `opponents` entries have the same shape.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Opponent {
+    /// AI shortname, as in the start script.
+    pub name: String,
+    #[serde(default)]
+    pub difficulty: Option<String>,
+    /// Game names the AI plays, as prefixes (`Zero-K` matches
+    /// `Zero-K v1.12.1.0`). Empty means any game.
+    #[serde(default)]
+    pub games: Vec<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Found under an `AI/Skirmish` dir; unknown if the engine's can't be read.
+    #[serde(default, skip_deserializing)]
+    pub installed: Option<bool>,
+}
+
+impl Opponent {
+    pub fn plays(&self, game: &str) -> bool {
+        let game = game.to_lowercase();
+        self.games.is_empty() || self.games.iter().any(|g| game.starts_with(&g.to_lowercase()))
+    }
+}
+
+/// The opponents known for this run.
+#[derive(Debug, Clone)]
+pub struct Catalog {
+    entries: Vec<Opponent>,
+    /// The dirs scanned for installed AIs, for error messages.
+    scanned: Vec<PathBuf>,
+}
+
+impl Catalog {
+    /// The built-in entries with `custom` added; a custom entry replaces a
+    /// built-in one of the same name.
+    pub fn new(custom: &[Opponent]) -> Self {
+        let mut entries: Vec<Opponent> = BUILT_IN
+            .iter()
+            .map(|(name, difficulty, games, description)| Opponent {
+                name: name.to_string(),
+                difficulty: Some(difficulty.to_string()),
+                games: games.iter().map(|g| g.to_string()).collect(),
+                description: Some(description.to_string()),
+                installed: None,
+            })
+            .collect();
+        for opponent in custom {
+            entries.retain(|e| e.name != opponent.name);
+            entries.push(opponent.clone());
+        }
+        Self { entries, scanned: Vec::new() }
+    }
+
+    /// Mark entries installed if an `AI/Skirmish` dir in `dirs` has them,
+    /// and add installed AIs the catalog lacks. The first dir is the
+    /// engine's, which AIs ship with: if it can't be read, installation
+    /// stays unknown. Other dirs that can't be read are skipped.
+    pub fn scan(mut self, dirs: &[PathBuf]) -> Self {
+        if dirs.first().is_none_or(|dir| !dir.is_dir()) {
+            return self;
+        }
+        let mut found = BTreeSet::new();
+        for dir in dirs {
+            let Ok(entries) = std::fs::read_dir(dir) else { continue };
+            self.scanned.push(dir.clone());
+            found.extend(
+                entries
+                    .flatten()
+                    .filter(|e| e.path().is_dir())
+                    .filter_map(|e| e.file_name().into_string().ok())
+                    .filter(|name| name != AGENT_AI),
+            );
+        }
+        for entry in &mut self.entries {
+            entry.installed = Some(found.remove(&entry.name));
+        }
+        self.entries.extend(found.into_iter().map(|name| Opponent {
+            name,
+            difficulty: None,
+            games: Vec::new(),
+            description: None,
+            installed: Some(true),
+        }));
+        self
+    }
+
+    pub fn entries(&self) -> &[Opponent] {
+        &self.entries
+    }
+
+    /// Check that `name` is an opponent that's installed (as far as we can
+    /// tell) and plays `game`.
+    pub fn validate(&self, name: &str, game: &str) -> Result<&Opponent, String> {
+        let Some(opponent) = self.entries.iter().find(|e| e.name == name) else {
+            return Err(match self.suggest(name) {
+                Some(suggestion) => format!("Unknown opponent '{}'; did you mean {}?", name, suggestion),
+                None => format!("Unknown opponent '{}' (game_list_opponents lists the known ones)", name),
+            });
+        };
+        if opponent.installed == Some(false) {
+            let dirs: Vec<String> = self.scanned.iter().map(|d| d.display().to_string()).collect();
+            return Err(format!("Opponent {} is not installed (looked in {})", name, dirs.join(", ")));
+        }
+        if !opponent.plays(game) {
+            return Err(format!("Opponent {} doesn't play {} (plays {})", name, game, opponent.games.join(", ")));
+        }
+        Ok(opponent)
+    }
+
+    /// The known name closest to a mistyped one, if any is close enough.
+    fn suggest(&self, name: &str) -> Option<&str> {
+        let typed = name.to_lowercase();
+        let max_distance = (typed.chars().count() / 3).max(2);
+        self.entries
+            .iter()
+            .map(|e| (edit_distance(&typed, &e.name.to_lowercase()), e.name.as_str()))
+            .filter(|(d, _)| *d <= max_distance)
+            .min_by_key(|(d, _)| *d)
+            .map(|(_, name)| name)
+    }
+}
+
+/// Levenshtein distance over chars.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_with_suggestions() {
+        let catalog = Catalog::new(&[]);
+        assert_eq!(catalog.validate("CircuitAIHard", "Zero-K v1.12.1.0").unwrap().difficulty.as_deref(), Some("hard"));
+        assert_eq!(
+            catalog.validate("CircuitAINovce", "Zero-K $VERSION").unwrap_err(),
+            "Unknown opponent 'CircuitAINovce'; did you mean CircuitAINovice?"
+        );
+        assert_eq!(
+            catalog.validate("nullai", "Zero-K $VERSION").unwrap_err(),
+            "Unknown opponent 'nullai'; did you mean NullAI?"
+        );
+        assert!(catalog.validate("Skynet", "Zero-K $VERSION").unwrap_err().starts_with("Unknown opponent 'Skynet' ("));
+        assert_eq!(
+            catalog.validate("BARb", "Zero-K $VERSION").unwrap_err(),
+            "Opponent BARb doesn't play Zero-K $VERSION (plays Beyond All Reason)"
+        );
+        // NullAI plays anything.
+        assert!(catalog.validate("NullAI", "Beyond All Reason test-123").is_ok());
+    }
+
+    #[test]
+    fn test_custom_entries_and_installed_scan() {
+        let custom: Vec<Opponent> = serde_json::from_value(serde_json::json!([
+            {"name": "MyAI", "difficulty": "hard", "games": ["Zero-K"], "description": "Home-made"},
+            {"name": "NullAI", "difficulty": "none", "description": "Still does nothing"}
+        ]))
+        .unwrap();
+        let dir = std::env::temp_dir().join(format!("gm-opponents-{}", uuid::Uuid::new_v4()));
+        for ai in ["CircuitAINovice", "MyAI", "KAIK", "AgentBridge"] {
+            std::fs::create_dir_all(dir.join(ai).join("stable")).unwrap();
+        }
+
+        let catalog = Catalog::new(&custom).scan(&[dir.clone(), dir.join("missing")]);
+        let names: Vec<&str> = catalog.entries().iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names.last(), Some(&"KAIK"), "installed AIs outside the catalog are listed");
+        assert!(!names.contains(&"AgentBridge"));
+        assert_eq!(names.iter().filter(|n| **n == "NullAI").count(), 1);
+        assert_eq!(catalog.validate("MyAI", "Zero-K $VERSION").unwrap().description.as_deref(), Some("Home-made"));
+        assert!(catalog.validate("KAIK", "Zero-K $VERSION").is_ok());
+        assert!(catalog.validate("CircuitAIHard", "Zero-K $VERSION").unwrap_err().contains("is not installed"));
+
+        // No engine AI dir: installation is unknown and not held against anyone.
+        let unknown = Catalog::new(&custom).scan(&[dir.join("missing"), dir.clone()]);
+        assert!(unknown.entries().iter().all(|e| e.installed.is_none()));
+        assert!(unknown.validate("CircuitAIHard", "Zero-K $VERSION").is_ok());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,151 @@
+//! Command pacing: at most `max_commands` orders go to a bridge per
+//! `interval_ms`. The bridge runs every order it reads in the engine thread
+//! during one frame, so a flood of them stalls the game. Orders over the
+//! limit wait in the connection's queue, in order, and go out as the budget
+//! comes back (the main loop flushes every tick).
+//!
+//! The defaults only bite on floods: ordinary play never comes near them.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::sai_ipc::Tracked;
+
+/// `pacing` section of the config file.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PacingConfig {
+    /// Orders sent per interval before the rest are queued.
+    #[serde(default = "default_max_commands")]
+    pub max_commands: usize,
+    #[serde(default = "default_interval_ms")]
+    pub interval_ms: u64,
+}
+
+fn default_max_commands() -> usize {
+    200
+}
+
+fn default_interval_ms() -> u64 {
+    100
+}
+
+impl Default for PacingConfig {
+    fn default() -> Self {
+        Self { max_commands: default_max_commands(), interval_ms: default_interval_ms() }
+    }
+}
+
+impl PacingConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_commands == 0 || self.interval_ms == 0 {
+            return Err("pacing.max_commands and interval_ms must be at least 1".into());
+        }
+        Ok(())
+    }
+}
+
+/// What became of an order handed to a paced connection.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Paced {
+    Sent,
+    /// Held back; `backlog` orders are queued now, this one included.
+    Queued { backlog: usize },
+}
+
+/// How many orders a connection held back, for game_stats.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PacingCounts {
+    pub delayed: u64,
+    pub backlog: usize,
+}
+
+/// The order budget and queue of one SAI connection.
+#[derive(Debug)]
+pub struct Pacer {
+    config: PacingConfig,
+    window_start: Instant,
+    sent_in_window: usize,
+    queued: VecDeque<Tracked>,
+    delayed: u64,
+}
+
+impl Pacer {
+    pub fn new(config: PacingConfig, now: Instant) -> Self {
+        Self { config, window_start: now, sent_in_window: 0, queued: VecDeque::new(), delayed: 0 }
+    }
+
+    /// Whether an order may go now; if so it's counted against the budget.
+    /// Never while older orders wait.
+    pub fn admit(&mut self, now: Instant) -> bool {
+        self.queued.is_empty() && self.take_budget(now)
+    }
+
+    /// Queue an order `admit` turned away.
+    pub fn hold(&mut self, tracked: Tracked) -> Paced {
+        self.queued.push_back(tracked);
+        self.delayed += 1;
+        Paced::Queued { backlog: self.queued.len() }
+    }
+
+    /// The oldest queued order, once the budget allows it.
+    pub fn release(&mut self, now: Instant) -> Option<Tracked> {
+        if self.queued.is_empty() || !self.take_budget(now) {
+            return None;
+        }
+        self.queued.pop_front()
+    }
+
+    pub fn counts(&self) -> PacingCounts {
+        PacingCounts { delayed: self.delayed, backlog: self.queued.len() }
+    }
+
+    fn take_budget(&mut self, now: Instant) -> bool {
+        if now.duration_since(self.window_start) >= Duration::from_millis(self.config.interval_ms) {
+            self.window_start = now;
+            self.sent_in_window = 0;
+        }
+        if self.sent_in_window >= self.config.max_commands {
+            return false;
+        }
+        self.sent_in_window += 1;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sai_protocol::{GameCommand as SaiCommand, UnitId};
+
+    fn stop(unit: i32) -> Tracked {
+        Tracked { command_ref: unit as u64, command: SaiCommand::Stop { unit_id: UnitId(unit) } }
+    }
+
+    #[test]
+    fn test_orders_over_budget_wait_in_order() {
+        let now = Instant::now();
+        let mut pacer = Pacer::new(PacingConfig { max_commands: 2, interval_ms: 100 }, now);
+        assert!(pacer.admit(now) && pacer.admit(now));
+        assert!(!pacer.admit(now));
+        assert_eq!(pacer.hold(stop(3)), Paced::Queued { backlog: 1 });
+        assert_eq!(pacer.hold(stop(4)), Paced::Queued { backlog: 2 });
+        assert_eq!(pacer.release(now + Duration::from_millis(50)), None);
+
+        // The next window sends the queue first; new orders line up behind.
+        let later = now + Duration::from_millis(100);
+        assert!(!pacer.admit(later));
+        assert_eq!(pacer.hold(stop(5)), Paced::Queued { backlog: 3 });
+        let released: Vec<Tracked> = std::iter::from_fn(|| pacer.release(later)).collect();
+        assert_eq!(released, [stop(3), stop(4)]);
+        assert_eq!(pacer.counts(), PacingCounts { delayed: 3, backlog: 1 });
+        assert_eq!(pacer.release(later + Duration::from_millis(100)), Some(stop(5)));
+        assert!(pacer.admit(later + Duration::from_millis(100)));
+
+        assert!(PacingConfig { max_commands: 0, interval_ms: 100 }.validate().is_err());
+        assert!(PacingConfig::default().validate().is_ok());
+    }
+}
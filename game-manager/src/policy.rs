@@ -0,0 +1,336 @@
+//! Command policy: hard limits on what a game profile lets the agent send,
+//! for cautious deployments such as an agent playing on a shared account.
+//!
+//! A profile's `policy` holds three kinds of rule, each naming commands by
+//! their wire type (`move`, `build`, ...) or, for a game's custom commands,
+//! as `custom:<command id>`:
+//!
+//! - `deny`: commands never sent.
+//! - `per_minute`: the most commands of a type sent in any minute, `*`
+//!   counting every command.
+//! - `limits`: what a command may be given. `unit_defs` lists the defs
+//!   (`*` wildcards) it may command, `not_unit_defs` the ones it may not,
+//!   and `max` caps numeric arguments by name.
+//!
+//! The policy sees the commands that go to the bridge, after groups, macros
+//! and named locations are expanded and convenience commands resolved. The
+//! default policy is empty and lets everything through.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+use crate::sai_ipc::{SaiCommand, UnitId};
+use crate::scope::glob_match;
+
+/// Rate budgets count commands over this long.
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// The `per_minute` key counting every command.
+pub const ANY: &str = "*";
+
+/// A game profile's `policy`.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deny: Vec<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub per_minute: BTreeMap<String, u32>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub limits: BTreeMap<String, Limit>,
+}
+
+/// What one command type may be given.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, serde::Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Limit {
+    /// Only units of these defs may be commanded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unit_defs: Option<Vec<String>>,
+    /// Units of these defs may not be.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub not_unit_defs: Vec<String>,
+    /// The largest value of each numeric argument.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub max: BTreeMap<String, f64>,
+}
+
+/// Which rule stopped a command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rule {
+    Denied,
+    Rate,
+    UnitDef,
+    Max,
+}
+
+impl Rule {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Rule::Denied => "denied",
+            Rule::Rate => "rate",
+            Rule::UnitDef => "unit_def",
+            Rule::Max => "max",
+        }
+    }
+}
+
+/// A command the policy stopped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    pub rule: Rule,
+    /// The command's type, `custom:<id>` for a custom command.
+    pub command: String,
+    pub unit: Option<UnitId>,
+    pub message: String,
+    /// For a rate budget, when a command fits again.
+    pub retry_after: Option<Duration>,
+}
+
+impl Violation {
+    pub fn text(&self) -> String {
+        format!("Blocked by the command policy: {}", self.message)
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut json = serde_json::json!({
+            "reason": "policy",
+            "rule": self.rule.as_str(),
+            "command": self.command,
+            "message": self.text(),
+        });
+        if let Some(unit) = self.unit {
+            json["unit"] = unit.0.into();
+        }
+        if let Some(after) = self.retry_after {
+            json["retryAfter"] = after.as_secs().max(1).into();
+        }
+        json
+    }
+}
+
+/// The names a command goes by in rules: its type, and `custom:<id>`.
+fn names(command: &serde_json::Value) -> Vec<String> {
+    let kind = command["type"].as_str().unwrap_or_default().to_string();
+    match command["command_id"].as_i64() {
+        Some(id) if kind == "custom" => vec![format!("custom:{}", id), kind],
+        _ => vec![kind],
+    }
+}
+
+impl Policy {
+    pub fn is_permissive(&self) -> bool {
+        self == &Policy::default()
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        let names = self.deny.iter().chain(self.per_minute.keys()).chain(self.limits.keys());
+        if names.into_iter().any(|name| name.trim().is_empty()) {
+            return Err("policy: command names must not be empty".into());
+        }
+        if let Some((name, _)) = self.per_minute.iter().find(|(_, n)| **n == 0) {
+            return Err(format!("policy.per_minute.{}: use deny to stop a command altogether", name));
+        }
+        Ok(())
+    }
+
+    /// Check `cmd` against the rules, with `def_of` naming a unit's def
+    /// and `sent` the commands sent so far. A command that passes is
+    /// counted in `sent`.
+    pub fn check(
+        &self,
+        cmd: &SaiCommand,
+        def_of: impl Fn(UnitId) -> Option<String>,
+        sent: &mut Sent,
+        now: Instant,
+    ) -> Result<(), Violation> {
+        if self.is_permissive() {
+            return Ok(());
+        }
+        let command = serde_json::to_value(cmd).unwrap_or_default();
+        let names = names(&command);
+        let unit = command["unit_id"].as_i64().map(|id| UnitId(id as i32));
+        let violation = |rule, message: String| Violation { rule, command: names[0].clone(), unit, message, retry_after: None };
+
+        if let Some(name) = names.iter().find(|n| self.deny.contains(n)) {
+            return Err(violation(Rule::Denied, format!("{} is not allowed", name)));
+        }
+
+        for (name, limit) in names.iter().filter_map(|n| self.limits.get(n).map(|l| (n, l))) {
+            for (argument, max) in &limit.max {
+                if let Some(value) = command[argument].as_f64().filter(|v| v > max) {
+                    return Err(violation(Rule::Max, format!("{} {} is capped at {} (got {})", name, argument, max, value)));
+                }
+            }
+            if limit.unit_defs.is_none() && limit.not_unit_defs.is_empty() {
+                continue;
+            }
+            // Units the GameManager hasn't seen are refused: the rule can't
+            // tell what they are.
+            let Some(unit) = unit else { continue };
+            let Some(def) = def_of(unit) else {
+                return Err(violation(Rule::UnitDef, format!("{} needs a known unit def, and unit #{}'s isn't known", name, unit)));
+            };
+            let listed = |patterns: &[String]| patterns.iter().any(|p| glob_match(p, &def));
+            if limit.unit_defs.as_deref().is_some_and(|allowed| !listed(allowed)) || listed(&limit.not_unit_defs) {
+                return Err(violation(Rule::UnitDef, format!("{} is not allowed for {} (#{})", name, def, unit)));
+            }
+        }
+
+        let budgets: Vec<(&String, u32)> = names
+            .iter()
+            .map(String::as_str)
+            .chain([ANY])
+            .filter_map(|n| self.per_minute.get_key_value(n).map(|(k, v)| (k, *v)))
+            .collect();
+        for (key, budget) in &budgets {
+            let recent = sent.recent(key, now);
+            if recent.len() >= *budget as usize {
+                let retry_after = recent.front().map(|first| RATE_WINDOW.saturating_sub(now.duration_since(*first)));
+                let what = if key.as_str() == ANY { "commands".to_string() } else { format!("{} commands", key) };
+                return Err(Violation {
+                    retry_after,
+                    ..violation(Rule::Rate, format!("at most {} {} a minute", budget, what))
+                });
+            }
+        }
+        for (key, _) in budgets {
+            sent.recent(key, now).push_back(now);
+        }
+        Ok(())
+    }
+}
+
+/// When a channel's commands went out, per rate budget.
+#[derive(Debug, Clone, Default)]
+pub struct Sent {
+    times: HashMap<String, VecDeque<Instant>>,
+}
+
+impl Sent {
+    /// The times within the rate window before `now` for `key`.
+    fn recent(&mut self, key: &str, now: Instant) -> &mut VecDeque<Instant> {
+        let times = self.times.entry(key.to_string()).or_default();
+        while times.front().is_some_and(|t| now.duration_since(*t) >= RATE_WINDOW) {
+            times.pop_front();
+        }
+        times
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(json: serde_json::Value) -> Policy {
+        let policy: Policy = serde_json::from_value(json).unwrap();
+        policy.validate().unwrap();
+        policy
+    }
+
+    fn cmd(json: serde_json::Value) -> SaiCommand {
+        serde_json::from_value(json).unwrap()
+    }
+
+    fn defs(unit: UnitId) -> Option<String> {
+        match unit.0 {
+            1 => Some("dynrecon1".into()),
+            2 => Some("cloakbomb".into()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_permissive_default() {
+        let mut sent = Sent::default();
+        let now = Instant::now();
+        let stop = cmd(serde_json::json!({"type": "stop", "unit_id": 99}));
+        for _ in 0..1000 {
+            Policy::default().check(&stop, defs, &mut sent, now).unwrap();
+        }
+        assert!(Policy::default().is_permissive());
+    }
+
+    #[test]
+    fn test_deny() {
+        let policy = policy(serde_json::json!({"deny": ["pause", "custom:31210"]}));
+        let mut sent = Sent::default();
+        let now = Instant::now();
+        let violation = policy.check(&SaiCommand::Pause, defs, &mut sent, now).unwrap_err();
+        assert_eq!((violation.rule, violation.command.as_str()), (Rule::Denied, "pause"));
+        assert_eq!(violation.text(), "Blocked by the command policy: pause is not allowed");
+
+        // A custom command is denied by its id, and other ids pass.
+        let morph = cmd(serde_json::json!({"type": "custom", "unit_id": 1, "command_id": 31210, "params": []}));
+        assert_eq!(policy.check(&morph, defs, &mut sent, now).unwrap_err().to_json()["command"], "custom:31210");
+        let retreat = cmd(serde_json::json!({"type": "custom", "unit_id": 1, "command_id": 34223, "params": [2.0]}));
+        assert!(policy.check(&retreat, defs, &mut sent, now).is_ok());
+        assert!(policy.check(&SaiCommand::Unpause, defs, &mut sent, now).is_ok());
+    }
+
+    #[test]
+    fn test_rate_budgets() {
+        let policy = policy(serde_json::json!({"per_minute": {"*": 4, "move": 2}}));
+        let mut sent = Sent::default();
+        let start = Instant::now();
+        let move_to = cmd(serde_json::json!({"type": "move", "unit_id": 1, "x": 0.0, "y": 0.0, "z": 0.0}));
+        let stop = cmd(serde_json::json!({"type": "stop", "unit_id": 1}));
+        policy.check(&move_to, defs, &mut sent, start).unwrap();
+        policy.check(&move_to, defs, &mut sent, start + Duration::from_secs(10)).unwrap();
+        let violation = policy.check(&move_to, defs, &mut sent, start + Duration::from_secs(20)).unwrap_err();
+        assert_eq!(violation.rule, Rule::Rate);
+        assert_eq!(violation.message, "at most 2 move commands a minute");
+        assert_eq!(violation.retry_after, Some(Duration::from_secs(40)));
+        assert_eq!(violation.to_json()["retryAfter"], 40);
+
+        // A refused command isn't counted; the overall budget still is.
+        policy.check(&stop, defs, &mut sent, start + Duration::from_secs(20)).unwrap();
+        policy.check(&stop, defs, &mut sent, start + Duration::from_secs(20)).unwrap();
+        assert_eq!(policy.check(&stop, defs, &mut sent, start + Duration::from_secs(30)).unwrap_err().message, "at most 4 commands a minute");
+        // A minute on, the first move is out of the window.
+        policy.check(&move_to, defs, &mut sent, start + Duration::from_secs(60)).unwrap();
+
+        let zero: Policy = serde_json::from_value(serde_json::json!({"per_minute": {"build": 0}})).unwrap();
+        assert!(zero.validate().unwrap_err().contains("use deny"));
+    }
+
+    #[test]
+    fn test_unit_defs() {
+        // Only bombs may be sent at the enemy; the commander never moves.
+        let policy = policy(serde_json::json!({"limits": {
+            "attack": {"unit_defs": ["cloakbomb", "shieldbomb"]},
+            "move": {"not_unit_defs": ["dyn*", "comm*"]},
+        }}));
+        let mut sent = Sent::default();
+        let now = Instant::now();
+        let attack = |unit: i32| cmd(serde_json::json!({"type": "attack", "unit_id": unit, "target_id": 900}));
+        assert!(policy.check(&attack(2), defs, &mut sent, now).is_ok());
+        let violation = policy.check(&attack(1), defs, &mut sent, now).unwrap_err();
+        assert_eq!((violation.rule, violation.unit), (Rule::UnitDef, Some(UnitId(1))));
+        assert_eq!(violation.message, "attack is not allowed for dynrecon1 (#1)");
+        assert!(policy.check(&attack(7), defs, &mut sent, now).unwrap_err().message.contains("unit #7's isn't known"));
+
+        let move_to = |unit: i32| cmd(serde_json::json!({"type": "move", "unit_id": unit, "x": 0.0, "y": 0.0, "z": 0.0}));
+        assert!(policy.check(&move_to(2), defs, &mut sent, now).is_ok());
+        assert_eq!(policy.check(&move_to(1), defs, &mut sent, now).unwrap_err().rule, Rule::UnitDef);
+        // Commands without a unit have nothing to check.
+        assert!(policy.check(&SaiCommand::Pause, defs, &mut sent, now).is_ok());
+    }
+
+    #[test]
+    fn test_argument_caps() {
+        let policy = policy(serde_json::json!({"limits": {"set_speed": {"max": {"speed": 2.0}}}}));
+        let mut sent = Sent::default();
+        let now = Instant::now();
+        assert!(policy.check(&SaiCommand::SetSpeed { speed: 2.0 }, defs, &mut sent, now).is_ok());
+        let violation = policy.check(&SaiCommand::SetSpeed { speed: 5.0 }, defs, &mut sent, now).unwrap_err();
+        assert_eq!(violation.rule, Rule::Max);
+        assert_eq!(violation.message, "set_speed speed is capped at 2 (got 5)");
+        assert_eq!(violation.to_json()["rule"], "max");
+
+        let unknown: Result<Policy, _> = serde_json::from_value(serde_json::json!({"limits": {"move": {"min": {}}}}));
+        assert!(unknown.unwrap_err().to_string().contains("unknown field `min`"));
+    }
+}
@@ -0,0 +1,162 @@
+//! Positions in event text: raw elmo coordinates mean little to an agent,
+//! so events and threat alerts place a point on the map instead.
+//!
+//! - `grid`: a reference on a 10x10 grid over the map, columns A-J west to
+//!   east and rows 1-10 north to south: "in D7".
+//! - `relative` (the default): distance and direction from our start
+//!   position: "1200 elmos north-east of your base".
+//! - `raw`: "near (3428, 2011)", as the bridge reported it.
+//!
+//! Both map-relative styles add a named location close by ("near ridge").
+//! Without the map size from init a grid reference falls back to raw, and
+//! without a start position a relative one falls back to grid. The exact
+//! coordinates stay in each message's metadata.
+
+use std::collections::BTreeMap;
+
+use crate::locations::{Place, START};
+
+/// Grid cells along each side of the map.
+const GRID_CELLS: f32 = 10.0;
+
+/// Closer than this (elmos) to the start is "at your base".
+const AT_BASE: f32 = 400.0;
+
+/// A named location this close (elmos) is mentioned.
+const NEAR_NAMED: f32 = 500.0;
+
+/// Relative distances are rounded to this many elmos.
+const ROUND_TO: f32 = 100.0;
+
+/// How event text places a point. Set per channel with `channels/open`
+/// `metadata.positions`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PositionStyle {
+    Grid,
+    #[default]
+    Relative,
+    Raw,
+}
+
+impl PositionStyle {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "grid" => Some(PositionStyle::Grid),
+            "relative" => Some(PositionStyle::Relative),
+            "raw" => Some(PositionStyle::Raw),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PositionStyle::Grid => "grid",
+            PositionStyle::Relative => "relative",
+            PositionStyle::Raw => "raw",
+        }
+    }
+}
+
+/// What a channel knows to place a point with.
+#[derive(Debug, Clone, Copy)]
+pub struct MapContext<'a> {
+    pub style: PositionStyle,
+    /// Width and height in elmos, from init.
+    pub map_size: Option<[f32; 2]>,
+    /// Named locations, `start` included once known.
+    pub named: &'a BTreeMap<String, Place>,
+}
+
+/// Where (x, z) is, as a phrase: "in D7, near ridge".
+pub fn describe(x: f32, z: f32, context: &MapContext) -> String {
+    let raw = || format!("near ({:.0}, {:.0})", x, z);
+    let grid = || context.map_size.map(|size| format!("in {}", grid_reference(x, z, size)));
+    let place = match context.style {
+        PositionStyle::Raw => return raw(),
+        PositionStyle::Grid => grid(),
+        PositionStyle::Relative => context.named.get(START).map(|base| from_base(x, z, base)).or_else(grid),
+    };
+    let Some(mut place) = place else { return raw() };
+    if let Some(name) = nearest_named(x, z, context.named) {
+        place += &format!(", near {}", name);
+    }
+    place
+}
+
+/// "D7": the cell of a 10x10 grid over a map of `size` elmos.
+pub fn grid_reference(x: f32, z: f32, size: [f32; 2]) -> String {
+    let cell = |v: f32, side: f32| ((v / side * GRID_CELLS).floor() as i32).clamp(0, GRID_CELLS as i32 - 1);
+    let column = (b'A' + cell(x, size[0]) as u8) as char;
+    format!("{}{}", column, cell(z, size[1]) + 1)
+}
+
+/// "1200 elmos north-east of your base", or "at your base".
+fn from_base(x: f32, z: f32, base: &Place) -> String {
+    let (dx, dz) = (x - base.x, z - base.z);
+    let distance = dx.hypot(dz);
+    if distance < AT_BASE {
+        return "at your base".into();
+    }
+    let rounded = (distance / ROUND_TO).round() * ROUND_TO;
+    format!("{:.0} elmos {} of your base", rounded, compass(dx, dz))
+}
+
+/// The eight-point direction of (dx, dz). North is towards z = 0.
+pub fn compass(dx: f32, dz: f32) -> &'static str {
+    const POINTS: [&str; 8] = ["north", "north-east", "east", "south-east", "south", "south-west", "west", "north-west"];
+    let degrees = dx.atan2(-dz).to_degrees().rem_euclid(360.0);
+    POINTS[((degrees + 22.5) / 45.0) as usize % 8]
+}
+
+/// The closest named location within [`NEAR_NAMED`], the start aside.
+fn nearest_named(x: f32, z: f32, named: &BTreeMap<String, Place>) -> Option<&str> {
+    named
+        .iter()
+        .filter(|(name, _)| name.as_str() != START)
+        .map(|(name, p)| (name, (p.x - x).hypot(p.z - z)))
+        .filter(|(_, d)| *d <= NEAR_NAMED)
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(name, _)| name.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe() {
+        let none = BTreeMap::new();
+        let mut context = MapContext { style: PositionStyle::Relative, map_size: None, named: &none };
+        // Nothing known yet: raw it is.
+        assert_eq!(describe(3427.5, 2011.0, &context), "near (3428, 2011)");
+        context.map_size = Some([4096.0, 4096.0]);
+        assert_eq!(describe(3427.5, 2011.0, &context), "in I5");
+
+        let mut named = BTreeMap::new();
+        named.insert(START.to_string(), Place { x: 1500.0, z: 3500.0 });
+        named.insert("ridge".to_string(), Place { x: 3300.0, z: 2200.0 });
+        let context = MapContext { named: &named, ..context };
+        assert_eq!(describe(3427.5, 2011.0, &context), "2400 elmos north-east of your base, near ridge");
+        assert_eq!(describe(1600.0, 3700.0, &context), "at your base");
+        assert_eq!(describe(1500.0, 4500.0, &context), "1000 elmos south of your base");
+
+        let grid = MapContext { style: PositionStyle::Grid, ..context };
+        assert_eq!(describe(3427.5, 2011.0, &grid), "in I5, near ridge");
+        let raw = MapContext { style: PositionStyle::Raw, ..context };
+        assert_eq!(describe(3427.5, 2011.0, &raw), "near (3428, 2011)");
+    }
+
+    #[test]
+    fn test_grid_and_compass() {
+        let size = [4096.0, 2048.0];
+        assert_eq!(grid_reference(0.0, 0.0, size), "A1");
+        assert_eq!(grid_reference(4096.0, 2048.0, size), "J10", "the far edges stay on the grid");
+        assert_eq!(grid_reference(-50.0, 1100.0, size), "A6");
+        let directions: Vec<&str> =
+            [(0.0, -1.0), (1.0, -1.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0), (-1.0, 1.0), (-1.0, 0.0), (-1.0, -1.0)]
+                .iter()
+                .map(|&(dx, dz)| compass(dx, dz))
+                .collect();
+        assert_eq!(directions, ["north", "north-east", "east", "south-east", "south", "south-west", "west", "north-west"]);
+    }
+}
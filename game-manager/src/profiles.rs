@@ -0,0 +1,287 @@
+//! Game profiles: what the GameManager assumes about a game archive, so a
+//! Recoil game other than Zero-K gets its own defaults instead of Zero-K's.
+//!
+//! A profile names the archives it covers by pattern (`Zero-K *`) and
+//! holds the game's default opponent and map, mod options for the start
+//! script, the role table of the army stream part, and the ids of the Lua
+//! custom commands behind the convenience commands (`morph`, `retreat`,
+//! `jump`, `set_priority`), and the command policy the agent plays under. It is picked from the `game` string when a
+//! channel opens. Zero-K and Beyond All Reason are built in, from
+//! `data/profiles`; the config file's `profiles` adds more or replaces
+//! built-in ones by name. A game no profile matches gets the generic
+//! profile, under which the convenience commands fail with an error rather
+//! than send ids the game may use for something else.
+
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+
+use crate::army::ArmyConfig;
+use crate::policy::Policy;
+use crate::scope::glob_match;
+
+/// Name of the profile for games no profile matches.
+pub const GENERIC: &str = "generic";
+
+/// Game when `channels/open` names none.
+pub const DEFAULT_GAME: &str = "Zero-K v1.12.1.0";
+
+/// Map when neither the request nor the profile names one.
+pub const DEFAULT_MAP: &str = "Chicken Defence 1.56";
+
+/// Built-in profiles, in the order they're tried.
+const BUILT_IN: &[&str] = &[
+    include_str!("../data/profiles/zero-k.json"),
+    include_str!("../data/profiles/beyond-all-reason.json"),
+];
+
+/// Commands the GameManager turns into the game's custom commands.
+pub const CONVENIENCES: &[&str] = &["morph", "retreat", "jump", "set_priority"];
+
+/// One game's settings; config file `profiles` entries have the same shape.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GameProfile {
+    pub name: String,
+    /// Game archive names the profile is for, `*` matching anything.
+    /// Case is ignored.
+    #[serde(default)]
+    pub games: Vec<String>,
+    #[serde(default)]
+    pub default_opponent: Option<String>,
+    #[serde(default)]
+    pub default_map: Option<String>,
+    /// Custom command id of each convenience command the game has.
+    #[serde(default)]
+    pub commands: BTreeMap<String, i32>,
+    /// Roles for the army stream part; the config's own `army` overrides
+    /// them for every game.
+    #[serde(default)]
+    pub army: ArmyConfig,
+    /// Written to the start script's `[MODOPTIONS]` for local games.
+    #[serde(default)]
+    pub mod_options: BTreeMap<String, String>,
+    /// Limits on the agent's commands; none by default.
+    #[serde(default)]
+    pub policy: Policy,
+}
+
+impl GameProfile {
+    fn generic() -> Self {
+        Self { name: GENERIC.into(), ..Default::default() }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("profiles: every entry needs a name".into());
+        }
+        if let Some(command) = self.commands.keys().find(|c| !CONVENIENCES.contains(&c.as_str())) {
+            return Err(format!(
+                "profiles.{}: unknown command '{}' (expected {})",
+                self.name,
+                command,
+                CONVENIENCES.join(", ")
+            ));
+        }
+        // Start script syntax: these would end the value or the section.
+        let bad = |s: &String| s.is_empty() || s.contains([';', '{', '}', '=', '\n']);
+        if let Some((key, value)) = self.mod_options.iter().find(|(k, v)| bad(k) || bad(v)) {
+            return Err(format!("profiles.{}: invalid mod option {}={}", self.name, key, value));
+        }
+        self.army.validate().map_err(|e| format!("profiles.{}: {}", self.name, e))?;
+        self.policy.validate().map_err(|e| format!("profiles.{}: {}", self.name, e))
+    }
+
+    pub fn matches(&self, game: &str) -> bool {
+        let game = game.to_lowercase();
+        self.games.iter().any(|pattern| glob_match(&pattern.to_lowercase(), &game))
+    }
+
+    /// Turn a convenience command into the game's custom command:
+    /// `{"type": "retreat", "unit_id": 5, "level": 2}` becomes
+    /// `{"type": "custom", "unit_id": 5, "command_id": 34223, "params": [2.0]}`.
+    /// Other commands are left alone.
+    pub fn resolve(&self, payload: &mut serde_json::Value) -> Result<(), String> {
+        let Some(fields) = payload.as_object_mut() else { return Ok(()) };
+        let Some(kind) = fields.get("type").and_then(|t| t.as_str()).filter(|t| CONVENIENCES.contains(t)) else {
+            return Ok(());
+        };
+        let kind = kind.to_string();
+        let mut command_id = *self
+            .commands
+            .get(&kind)
+            .ok_or_else(|| format!("{} is not supported by this game profile ({})", kind, self.name))?;
+        let mut number = |key: &str| -> Result<Option<f32>, String> {
+            match fields.remove(key) {
+                None => Ok(None),
+                Some(v) => v.as_f64().map(|n| Some(n as f32)).ok_or_else(|| format!("{} must be a number", key)),
+            }
+        };
+        let params = match kind.as_str() {
+            // A unit's morphs take consecutive ids; `option` picks one.
+            "morph" => {
+                command_id += number("option")?.unwrap_or(0.0) as i32;
+                Vec::new()
+            }
+            "retreat" => match number("level")? {
+                Some(level) if (0.0..=3.0).contains(&level) && level.fract() == 0.0 => vec![level],
+                _ => return Err("retreat needs a level from 0 (never) to 3 (at 99% health)".into()),
+            },
+            "jump" => {
+                let (x, y, z) = (number("x")?, number("y")?, number("z")?);
+                match (x, z) {
+                    (Some(x), Some(z)) => vec![x, y.unwrap_or(0.0), z],
+                    _ => return Err("jump needs x and z".into()),
+                }
+            }
+            _ => {
+                let priority = fields.remove("priority");
+                match priority.as_ref().and_then(|p| p.as_str()) {
+                    Some("low") => vec![0.0],
+                    Some("normal") => vec![1.0],
+                    Some("high") => vec![2.0],
+                    _ => return Err("set_priority needs a priority of low, normal or high".into()),
+                }
+            }
+        };
+        fields.insert("type".into(), "custom".into());
+        fields.insert("command_id".into(), command_id.into());
+        fields.insert("params".into(), params.into());
+        Ok(())
+    }
+
+    /// A profile with every convenience command, for checking a command's
+    /// shape before its game is known.
+    pub fn stand_in() -> Self {
+        Self {
+            name: "stand-in".into(),
+            commands: CONVENIENCES.iter().map(|c| (c.to_string(), 0)).collect(),
+            ..Default::default()
+        }
+    }
+}
+
+/// The profiles known for this run.
+#[derive(Debug, Clone)]
+pub struct Profiles {
+    entries: Vec<GameProfile>,
+    generic: GameProfile,
+}
+
+impl Default for Profiles {
+    fn default() -> Self {
+        Self::new(&[])
+    }
+}
+
+impl Profiles {
+    /// The built-in profiles after `custom`; a custom profile replaces a
+    /// built-in one of the same name.
+    pub fn new(custom: &[GameProfile]) -> Self {
+        let mut entries: Vec<GameProfile> = custom.to_vec();
+        entries.extend(
+            BUILT_IN
+                .iter()
+                .map(|raw| serde_json::from_str::<GameProfile>(raw).expect("built-in profile"))
+                .filter(|p| !custom.iter().any(|c| c.name == p.name)),
+        );
+        Self { entries, generic: GameProfile::generic() }
+    }
+
+    /// The first profile matching `game`, else the generic one.
+    pub fn select(&self, game: &str) -> &GameProfile {
+        self.entries.iter().find(|p| p.matches(game)).unwrap_or(&self.generic)
+    }
+
+    pub fn generic(&self) -> &GameProfile {
+        &self.generic
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn load(path: &str) -> GameProfile {
+        let raw = std::fs::read_to_string(std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join(path)).unwrap();
+        let profile: GameProfile = serde_json::from_str(&raw).unwrap();
+        profile.validate().unwrap();
+        profile
+    }
+
+    #[test]
+    fn test_fixtures() {
+        let zk = load("data/profiles/zero-k.json");
+        assert!(zk.matches("Zero-K v1.12.1.0") && zk.matches("zero-k $VERSION") && !zk.matches("Zero-K"));
+        assert_eq!(zk.default_opponent.as_deref(), Some("CircuitAINovice"));
+        assert_eq!(zk.army.role_of("cloakraid", None), "raiders");
+        assert_eq!(zk.army.role_of("staticcon", None), "builders");
+
+        let bar = load("data/profiles/beyond-all-reason.json");
+        assert!(bar.matches("Beyond All Reason test-27451-7f7c9e8"));
+        assert_eq!(bar.default_opponent.as_deref(), Some("BARb"));
+        assert!(bar.commands.is_empty());
+        assert_eq!(bar.army.role_of("armmex", None), "economy");
+        assert_eq!(bar.army.role_of("corcom", None), "builders");
+        assert_eq!(bar.army.role_of("cloakraid", None), "other");
+    }
+
+    #[test]
+    fn test_select() {
+        let profiles = Profiles::default();
+        assert_eq!(profiles.select("Zero-K v1.12.7.0").name, "zero-k");
+        assert_eq!(profiles.select("Beyond All Reason test-27451-7f7c9e8").name, "beyond-all-reason");
+        let generic = profiles.select("Tech Annihilation 0.3");
+        assert_eq!(generic.name, GENERIC);
+        assert!(generic.default_opponent.is_none() && generic.army.roles.is_empty());
+
+        let custom: GameProfile = serde_json::from_value(json!({
+            "name": "zero-k", "games": ["Zero-K *"], "mod_options": {"startmetal": "500"}
+        }))
+        .unwrap();
+        let profiles = Profiles::new(&[custom]);
+        let zk = profiles.select("Zero-K v1.12.7.0");
+        assert_eq!(zk.mod_options["startmetal"], "500");
+        assert!(zk.commands.is_empty(), "a custom profile replaces the built-in one whole");
+
+        let bad: GameProfile = serde_json::from_value(json!({"name": "x", "commands": {"teleport": 1}})).unwrap();
+        assert!(bad.validate().unwrap_err().contains("unknown command 'teleport'"));
+        let bad: GameProfile = serde_json::from_value(json!({"name": "x", "mod_options": {"a": "1; b=2"}})).unwrap();
+        assert!(bad.validate().is_err());
+    }
+
+    #[test]
+    fn test_resolve_conveniences() {
+        let zk = Profiles::default().select("Zero-K v1.12.7.0").clone();
+        let resolved = |command: serde_json::Value| {
+            let mut payload = command;
+            zk.resolve(&mut payload).map(|_| payload)
+        };
+        assert_eq!(
+            resolved(json!({"type": "retreat", "unit_id": 5, "level": 2})),
+            Ok(json!({"type": "custom", "unit_id": 5, "command_id": 34223, "params": [2.0]}))
+        );
+        assert_eq!(
+            resolved(json!({"type": "jump", "group": "raiders", "x": 100, "z": 200.5, "queue": true})),
+            Ok(json!({"type": "custom", "group": "raiders", "command_id": 38521, "params": [100.0, 0.0, 200.5], "queue": true}))
+        );
+        assert_eq!(resolved(json!({"type": "morph", "unit_id": 5, "option": 1}))
+            .unwrap()["command_id"], 31211);
+        assert_eq!(resolved(json!({"type": "set_priority", "unit_id": 5, "priority": "high"})).unwrap()["params"], json!([2.0]));
+        assert!(resolved(json!({"type": "retreat", "unit_id": 5, "level": 7})).is_err());
+        assert!(resolved(json!({"type": "set_priority", "unit_id": 5})).is_err());
+        assert_eq!(resolved(json!({"type": "stop", "unit_id": 5})), Ok(json!({"type": "stop", "unit_id": 5})));
+
+        let mut morph = json!({"type": "morph", "unit_id": 5});
+        assert_eq!(
+            Profiles::default().select("Tech Annihilation 0.3").resolve(&mut morph).unwrap_err(),
+            "morph is not supported by this game profile (generic)"
+        );
+        let mut jump = json!({"type": "jump", "unit_id": 5, "x": 1, "z": 1});
+        assert_eq!(
+            Profiles::default().select("Beyond All Reason test-1").resolve(&mut jump).unwrap_err(),
+            "jump is not supported by this game profile (beyond-all-reason)"
+        );
+    }
+}
@@ -0,0 +1,636 @@
+//! Typed query tools: one `game_query_*` tool per kind of question about a
+//! game, each with an exact argument schema and a typed answer (returned
+//! as text and as `structuredContent`). The table [`QUERY_KINDS`] drives
+//! both `tools/list` and dispatch, so a kind added there gets its tool.
+//!
+//! Kinds answered by the bridge (map, unit defs) go through the same
+//! queries as `game_map` and `game_unitdefs`, with the kind's timeout; the
+//! others are answered from what the channel's events have told us.
+
+use std::time::Duration;
+
+use sai_protocol::{ResourceState, TeamEconomy};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::map_grid::MapGrid;
+use crate::observer::{ChannelState, Income};
+use crate::sai_ipc::UnitDefInfo;
+use crate::unit_defs::{Role, UnitDefCatalog};
+
+/// A parsed query, one variant per kind.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Query {
+    Units(ChannelArgs),
+    Economy(ChannelArgs),
+    AllyEconomy(ChannelArgs),
+    Map(MapArgs),
+    UnitDef(UnitDefArgs),
+    Terrain(TerrainArgs),
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChannelArgs {
+    pub channel_id: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MapArgs {
+    pub channel_id: String,
+    pub cell_size: Option<f32>,
+    pub build_def: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UnitDefArgs {
+    pub channel_id: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TerrainArgs {
+    pub channel_id: String,
+    pub x: f32,
+    pub z: f32,
+    pub build_def: Option<String>,
+}
+
+/// One kind of query and its tool.
+pub struct QueryKind {
+    pub tool: &'static str,
+    pub description: &'static str,
+    /// How long the bridge gets to answer; None for kinds answered from
+    /// the GameManager's own state.
+    pub timeout: Option<Duration>,
+    /// JSON schemas of the arguments and of the answer.
+    pub input_schema: fn() -> serde_json::Value,
+    pub output_schema: fn() -> serde_json::Value,
+    pub parse: fn(&serde_json::Value) -> Result<Query, String>,
+}
+
+/// Every query kind, in `tools/list` order.
+pub const QUERY_KINDS: &[QueryKind] = &[
+    QueryKind {
+        tool: "game_query_units",
+        description: "Ids of our units and of the enemy units in sight, as of the channel's last event.",
+        timeout: None,
+        input_schema: channel_schema,
+        output_schema: units_schema,
+        parse: |args| parse(args, Query::Units),
+    },
+    QueryKind {
+        tool: "game_query_economy",
+        description: "Metal and energy: current, income, usage and storage, from the latest update.",
+        timeout: None,
+        input_schema: channel_schema,
+        output_schema: economy_schema,
+        parse: |args| parse(args, Query::Economy),
+    },
+    QueryKind {
+        tool: "game_query_ally_economy",
+        description: "Each allied team's economy, as far as the engine shares it, with the team's total income and our share of it. No allies in a 1v1.",
+        timeout: None,
+        input_schema: channel_schema,
+        output_schema: ally_economy_schema,
+        parse: |args| parse(args, Query::AllyEconomy),
+    },
+    QueryKind {
+        tool: "game_query_map",
+        description: "The map grid as JSON: ground height and slope per cell, metal spots and, for a build_def, where it fits. Same grid as game_map.",
+        timeout: Some(Duration::from_secs(10)),
+        input_schema: map_schema,
+        output_schema: map_output_schema,
+        parse: |args| parse(args, Query::Map),
+    },
+    QueryKind {
+        tool: "game_query_unitdef",
+        description: "Every field of one unit def, looked up by its exact name, e.g. cloakraid.",
+        timeout: Some(Duration::from_secs(10)),
+        input_schema: unitdef_schema,
+        output_schema: unitdef_output_schema,
+        parse: |args| parse(args, Query::UnitDef),
+    },
+    QueryKind {
+        tool: "game_query_terrain",
+        description: "Ground height, slope and water at a map position, and whether a build_def fits there. From the cached map grid, so accurate to one cell.",
+        timeout: Some(Duration::from_secs(10)),
+        input_schema: terrain_schema,
+        output_schema: terrain_output_schema,
+        parse: |args| parse(args, Query::Terrain),
+    },
+];
+
+/// The kind behind a tool name.
+pub fn kind(tool: &str) -> Option<&'static QueryKind> {
+    QUERY_KINDS.iter().find(|k| k.tool == tool)
+}
+
+/// The `tools/list` entries of every kind.
+pub fn tools() -> Vec<serde_json::Value> {
+    QUERY_KINDS
+        .iter()
+        .map(|k| {
+            serde_json::json!({
+                "name": k.tool,
+                "description": k.description,
+                "inputSchema": (k.input_schema)(),
+                "outputSchema": (k.output_schema)(),
+            })
+        })
+        .collect()
+}
+
+fn parse<T: DeserializeOwned>(args: &serde_json::Value, query: fn(T) -> Query) -> Result<Query, String> {
+    serde_json::from_value(args.clone()).map(query).map_err(|e| format!("Invalid arguments: {}", e))
+}
+
+/// Our units and the enemies in sight.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UnitsAnswer {
+    pub frame: i32,
+    pub units: Vec<i32>,
+    pub enemies_in_sight: Vec<i32>,
+}
+
+impl UnitsAnswer {
+    pub fn of(state: &ChannelState) -> Self {
+        let sorted = |ids: &std::collections::HashSet<crate::sai_ipc::UnitId>| {
+            let mut ids: Vec<i32> = ids.iter().map(|u| u.0).collect();
+            ids.sort_unstable();
+            ids
+        };
+        Self { frame: state.frame, units: sorted(&state.units), enemies_in_sight: sorted(&state.enemies_in_los) }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EconomyAnswer {
+    pub frame: i32,
+    pub metal: ResourceState,
+    pub energy: ResourceState,
+}
+
+impl EconomyAnswer {
+    /// None before the first update with an economy.
+    pub fn of(state: &ChannelState) -> Option<Self> {
+        let economy = state.economy?;
+        Some(Self { frame: state.frame, metal: economy.metal, energy: economy.energy })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AllyEconomyAnswer {
+    pub frame: i32,
+    pub allies: Vec<TeamEconomy>,
+    /// All allies' income together.
+    pub ally_income: Income,
+    /// Our part of the team's income, 0 to 1; 1 without allies.
+    pub metal_share: f32,
+    pub energy_share: f32,
+}
+
+impl AllyEconomyAnswer {
+    /// None before the first update with an economy.
+    pub fn of(state: &ChannelState) -> Option<Self> {
+        state.economy.as_ref()?;
+        let team = state.team_income();
+        Some(Self {
+            frame: state.frame,
+            allies: state.allies.clone(),
+            ally_income: team.map(|t| t.allies).unwrap_or_default(),
+            metal_share: team.map_or(1.0, |t| t.metal_share()),
+            energy_share: team.map_or(1.0, |t| t.energy_share()),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UnitDefAnswer {
+    pub id: i32,
+    pub name: String,
+    pub human_name: String,
+    pub description: Option<String>,
+    pub role: &'static str,
+    pub metal_cost: f32,
+    pub energy_cost: f32,
+    pub build_time: f32,
+    pub health: f32,
+    pub speed: f32,
+    pub builder: bool,
+    pub build_options: Vec<String>,
+}
+
+impl UnitDefAnswer {
+    pub fn of(catalog: &UnitDefCatalog, def: &UnitDefInfo) -> Self {
+        Self {
+            id: def.id.0,
+            name: def.name.clone(),
+            human_name: def.human_name.clone(),
+            description: def.description.clone(),
+            role: Role::of(def).as_str(),
+            metal_cost: def.metal_cost,
+            energy_cost: def.energy_cost,
+            build_time: def.build_time,
+            health: def.health,
+            speed: def.speed,
+            builder: def.builder,
+            build_options: catalog.build_option_names(def),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TerrainAnswer {
+    pub x: f32,
+    pub z: f32,
+    /// Size of the grid cell the answer is for.
+    pub cell_size: f32,
+    pub elevation: i32,
+    /// Steepest rise to a neighbouring cell, as a percentage grade.
+    pub slope: u32,
+    pub water: bool,
+    /// Whether the grid's build def fits in the cell; None without one.
+    pub buildable: Option<bool>,
+}
+
+impl TerrainAnswer {
+    pub fn at(grid: &MapGrid, x: f32, z: f32) -> Result<Self, String> {
+        let (col, row) = grid
+            .cell_of(x, z)
+            .ok_or_else(|| format!("({}, {}) is off the map", x, z))?;
+        let elevation = grid.elevation[row][col];
+        Ok(Self {
+            x,
+            z,
+            cell_size: grid.cell_size,
+            elevation,
+            slope: grid.slope(col, row),
+            water: elevation < 0,
+            buildable: grid.build_def.as_ref().map(|_| grid.buildable.get(row).and_then(|r| r.get(col)) == Some(&true)),
+        })
+    }
+}
+
+fn channel_property() -> serde_json::Value {
+    serde_json::json!({ "type": "string", "description": "Game channel id, e.g. game:local-1" })
+}
+
+fn channel_schema() -> serde_json::Value {
+    serde_json::json!({
+        "type": "object",
+        "properties": { "channel_id": channel_property() },
+        "required": ["channel_id"],
+        "additionalProperties": false
+    })
+}
+
+fn map_schema() -> serde_json::Value {
+    serde_json::json!({
+        "type": "object",
+        "properties": {
+            "channel_id": channel_property(),
+            "cell_size": { "type": "number", "description": "Grid cell size in elmos (default 128)" },
+            "build_def": { "type": "string", "description": "Unit def name whose buildability to include, e.g. staticmex" }
+        },
+        "required": ["channel_id"],
+        "additionalProperties": false
+    })
+}
+
+fn unitdef_schema() -> serde_json::Value {
+    serde_json::json!({
+        "type": "object",
+        "properties": {
+            "channel_id": channel_property(),
+            "name": { "type": "string", "description": "Exact unit def name, e.g. cloakraid" }
+        },
+        "required": ["channel_id", "name"],
+        "additionalProperties": false
+    })
+}
+
+fn terrain_schema() -> serde_json::Value {
+    serde_json::json!({
+        "type": "object",
+        "properties": {
+            "channel_id": channel_property(),
+            "x": { "type": "number", "description": "Map x in elmos" },
+            "z": { "type": "number", "description": "Map z in elmos" },
+            "build_def": { "type": "string", "description": "Unit def name whose buildability to include" }
+        },
+        "required": ["channel_id", "x", "z"],
+        "additionalProperties": false
+    })
+}
+
+fn ids() -> serde_json::Value {
+    serde_json::json!({ "type": "array", "items": { "type": "integer" } })
+}
+
+fn units_schema() -> serde_json::Value {
+    serde_json::json!({
+        "type": "object",
+        "properties": { "frame": { "type": "integer" }, "units": ids(), "enemies_in_sight": ids() },
+        "required": ["frame", "units", "enemies_in_sight"],
+        "additionalProperties": false
+    })
+}
+
+fn resource_schema() -> serde_json::Value {
+    serde_json::json!({
+        "type": "object",
+        "properties": {
+            "current": { "type": "number" },
+            "income": { "type": "number" },
+            "usage": { "type": "number" },
+            "storage": { "type": "number" },
+            "received": { "type": "number", "description": "Shared to us by allies per second, on top of income" }
+        },
+        "required": ["current", "income", "usage", "storage"],
+        "additionalProperties": false
+    })
+}
+
+fn economy_schema() -> serde_json::Value {
+    let resource = resource_schema();
+    serde_json::json!({
+        "type": "object",
+        "properties": { "frame": { "type": "integer" }, "metal": resource, "energy": resource },
+        "required": ["frame", "metal", "energy"],
+        "additionalProperties": false
+    })
+}
+
+fn ally_economy_schema() -> serde_json::Value {
+    let income = serde_json::json!({
+        "type": "object",
+        "properties": { "metal": { "type": "number" }, "energy": { "type": "number" } },
+        "required": ["metal", "energy"],
+        "additionalProperties": false
+    });
+    serde_json::json!({
+        "type": "object",
+        "properties": {
+            "frame": { "type": "integer" },
+            "allies": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": { "team": { "type": "integer" }, "metal": resource_schema(), "energy": resource_schema() },
+                    "required": ["team", "metal", "energy"],
+                    "additionalProperties": false
+                }
+            },
+            "ally_income": income,
+            "metal_share": { "type": "number" },
+            "energy_share": { "type": "number" }
+        },
+        "required": ["frame", "allies", "ally_income", "metal_share", "energy_share"],
+        "additionalProperties": false
+    })
+}
+
+fn map_output_schema() -> serde_json::Value {
+    let rows = |items: &str| serde_json::json!({ "type": "array", "items": { "type": "array", "items": { "type": items } } });
+    serde_json::json!({
+        "type": "object",
+        "properties": {
+            "cell_size": { "type": "number" },
+            "width": { "type": "integer" },
+            "height": { "type": "integer" },
+            "min_elevation": { "type": "integer" },
+            "max_elevation": { "type": "integer" },
+            "elevation": rows("integer"),
+            "slope": rows("integer"),
+            "metal_spots": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": { "x": { "type": "number" }, "z": { "type": "number" }, "metal": { "type": "number" } },
+                    "required": ["x", "z", "metal"]
+                }
+            },
+            "build_def": { "type": "string" },
+            "buildable": { "type": "array", "items": { "type": "string" }, "description": "A row per string, 1 where build_def fits" }
+        },
+        "required": ["cell_size", "width", "height", "min_elevation", "max_elevation", "elevation", "slope", "metal_spots"],
+        "additionalProperties": false
+    })
+}
+
+fn unitdef_output_schema() -> serde_json::Value {
+    serde_json::json!({
+        "type": "object",
+        "properties": {
+            "id": { "type": "integer" },
+            "name": { "type": "string" },
+            "human_name": { "type": "string" },
+            "description": { "type": ["string", "null"] },
+            "role": { "type": "string", "enum": ["factory", "constructor", "structure", "unit"] },
+            "metal_cost": { "type": "number" },
+            "energy_cost": { "type": "number" },
+            "build_time": { "type": "number" },
+            "health": { "type": "number" },
+            "speed": { "type": "number" },
+            "builder": { "type": "boolean" },
+            "build_options": { "type": "array", "items": { "type": "string" } }
+        },
+        "required": [
+            "id", "name", "human_name", "description", "role", "metal_cost", "energy_cost",
+            "build_time", "health", "speed", "builder", "build_options"
+        ],
+        "additionalProperties": false
+    })
+}
+
+fn terrain_output_schema() -> serde_json::Value {
+    serde_json::json!({
+        "type": "object",
+        "properties": {
+            "x": { "type": "number" },
+            "z": { "type": "number" },
+            "cell_size": { "type": "number" },
+            "elevation": { "type": "integer" },
+            "slope": { "type": "integer" },
+            "water": { "type": "boolean" },
+            "buildable": { "type": ["boolean", "null"] }
+        },
+        "required": ["x", "z", "cell_size", "elevation", "slope", "water", "buildable"],
+        "additionalProperties": false
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sai_ipc::{UnitDefId, UnitId};
+    use sai_protocol::{Economy, MetalSpot, TeamId};
+
+    /// Check `value` against the subset of JSON Schema the tools use.
+    fn check(schema: &serde_json::Value, value: &serde_json::Value, at: &str) -> Result<(), String> {
+        let types: Vec<&str> = match &schema["type"] {
+            serde_json::Value::String(t) => vec![t.as_str()],
+            serde_json::Value::Array(ts) => ts.iter().filter_map(|t| t.as_str()).collect(),
+            _ => vec![],
+        };
+        let fits = |t: &str| match t {
+            "object" => value.is_object(),
+            "array" => value.is_array(),
+            "string" => value.is_string(),
+            "integer" => value.is_i64() || value.is_u64(),
+            "number" => value.is_number(),
+            "boolean" => value.is_boolean(),
+            "null" => value.is_null(),
+            _ => false,
+        };
+        if !types.is_empty() && !types.into_iter().any(fits) {
+            return Err(format!("{}: {} is not a {}", at, value, schema["type"]));
+        }
+        if let Some(allowed) = schema["enum"].as_array() {
+            if !allowed.contains(value) {
+                return Err(format!("{}: {} is not one of {:?}", at, value, allowed));
+            }
+        }
+        if let Some(object) = value.as_object() {
+            let properties = schema["properties"].as_object();
+            for name in schema["required"].as_array().into_iter().flatten().filter_map(|n| n.as_str()) {
+                if !object.contains_key(name) {
+                    return Err(format!("{}: missing {}", at, name));
+                }
+            }
+            for (name, field) in object {
+                match properties.and_then(|p| p.get(name)) {
+                    Some(property) => check(property, field, &format!("{}.{}", at, name))?,
+                    None if schema["additionalProperties"] == false => {
+                        return Err(format!("{}: unexpected {}", at, name))
+                    }
+                    None => {}
+                }
+            }
+        }
+        if let (Some(items), Some(array)) = (schema.get("items"), value.as_array()) {
+            for (i, item) in array.iter().enumerate() {
+                check(items, item, &format!("{}[{}]", at, i))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Arguments using every property each kind's schema declares.
+    fn full_arguments(tool: &str) -> serde_json::Value {
+        match tool {
+            "game_query_units" | "game_query_economy" | "game_query_ally_economy" => serde_json::json!({"channel_id": "game:local-1"}),
+            "game_query_map" => serde_json::json!({"channel_id": "game:local-1", "cell_size": 256, "build_def": "staticmex"}),
+            "game_query_unitdef" => serde_json::json!({"channel_id": "game:local-1", "name": "cloakraid"}),
+            "game_query_terrain" => {
+                serde_json::json!({"channel_id": "game:local-1", "x": 100.5, "z": 300, "build_def": "staticmex"})
+            }
+            other => panic!("no example arguments for {}", other),
+        }
+    }
+
+    fn grid() -> MapGrid {
+        let mut grid = MapGrid::new(128.0, Some("staticmex".into()), 128.0, 2, 2);
+        grid.add_band(0, vec![vec![10, 40], vec![-5, 10]], vec!["10".into(), "01".into()]).unwrap();
+        grid
+    }
+
+    #[test]
+    fn test_arguments_match_schemas() {
+        for kind in QUERY_KINDS {
+            let schema = (kind.input_schema)();
+            let args = full_arguments(kind.tool);
+            check(&schema, &args, kind.tool).unwrap();
+            // Every declared property is one the serde struct takes.
+            (kind.parse)(&args).unwrap_or_else(|e| panic!("{}: {}", kind.tool, e));
+            assert_eq!(args.as_object().unwrap().len(), schema["properties"].as_object().unwrap().len(), "{}", kind.tool);
+            // And what the schema requires, the struct does too.
+            let mut missing = args.clone();
+            for name in schema["required"].as_array().unwrap() {
+                missing.as_object_mut().unwrap().remove(name.as_str().unwrap());
+                assert!((kind.parse)(&missing).is_err(), "{} parses without {}", kind.tool, name);
+                missing = args.clone();
+            }
+            assert!((kind.parse)(&serde_json::json!({"channel_id": "game:local-1", "bogus": 1})).is_err());
+        }
+        let terrain = (kind("game_query_terrain").unwrap().parse)(&full_arguments("game_query_terrain")).unwrap();
+        assert!(matches!(terrain, Query::Terrain(TerrainArgs { x: 100.5, .. })));
+    }
+
+    #[test]
+    fn test_answers_match_schemas() {
+        let mut state = ChannelState { frame: 900, ..Default::default() };
+        state.units.extend([UnitId(3), UnitId(1)]);
+        state.enemies_in_los.insert(UnitId(40));
+        state.economy = Some(Economy::default());
+        let catalog = UnitDefCatalog::new(vec![
+            UnitDefInfo {
+                id: UnitDefId(1),
+                name: "factorycloak".into(),
+                human_name: "Cloakbot Factory".into(),
+                description: None,
+                metal_cost: 500.0,
+                energy_cost: 500.0,
+                build_time: 500.0,
+                health: 4000.0,
+                speed: 0.0,
+                builder: true,
+                build_options: vec![UnitDefId(2)],
+                metal_make: 0.0,
+                energy_make: 0.0,
+                extracts_metal: 0.0,
+                build_speed: 0.0,
+            },
+            UnitDefInfo {
+                id: UnitDefId(2),
+                name: "cloakraid".into(),
+                human_name: "Glaive".into(),
+                description: Some("Light Raider Bot".into()),
+                metal_cost: 65.0,
+                energy_cost: 65.0,
+                build_time: 65.0,
+                health: 200.0,
+                speed: 3.6,
+                builder: false,
+                build_options: Vec::new(),
+                metal_make: 0.0,
+                energy_make: 0.0,
+                extracts_metal: 0.0,
+                build_speed: 0.0,
+            },
+        ]);
+        let spots = [MetalSpot { x: 64.0, y: 10.0, z: 64.0, metal: 2.0 }];
+
+        let units = UnitsAnswer::of(&state);
+        assert_eq!((units.units.as_slice(), units.enemies_in_sight.as_slice()), (&[1, 3][..], &[40][..]));
+        let factory = UnitDefAnswer::of(&catalog, catalog.named("factorycloak").unwrap());
+        assert_eq!((factory.role, factory.build_options.as_slice()), ("factory", &["cloakraid".to_string()][..]));
+        let terrain = TerrainAnswer::at(&grid(), 10.0, 200.0).unwrap();
+        assert_eq!((terrain.elevation, terrain.water, terrain.buildable), (-5, true, Some(false)));
+        assert!(TerrainAnswer::at(&grid(), 300.0, 0.0).is_err());
+        let solo = AllyEconomyAnswer::of(&state).unwrap();
+        assert_eq!((solo.allies.len(), solo.metal_share), (0, 1.0));
+        let mut team = ChannelState { frame: 900, economy: state.economy, ..Default::default() };
+        let shared = ResourceState { income: 5.0, received: 1.5, ..Default::default() };
+        team.allies = vec![TeamEconomy { team: TeamId(1), metal: shared, energy: ResourceState::default() }];
+
+        let answers = [
+            ("game_query_units", serde_json::to_value(units).unwrap()),
+            ("game_query_economy", serde_json::to_value(EconomyAnswer::of(&state).unwrap()).unwrap()),
+            ("game_query_ally_economy", serde_json::to_value(solo).unwrap()),
+            ("game_query_ally_economy", serde_json::to_value(AllyEconomyAnswer::of(&team).unwrap()).unwrap()),
+            ("game_query_map", grid().to_json(&spots)),
+            ("game_query_unitdef", serde_json::to_value(factory).unwrap()),
+            ("game_query_unitdef", serde_json::to_value(UnitDefAnswer::of(&catalog, catalog.named("cloakraid").unwrap())).unwrap()),
+            ("game_query_terrain", serde_json::to_value(terrain).unwrap()),
+        ];
+        for (tool, answer) in answers {
+            check(&(kind(tool).unwrap().output_schema)(), &answer, tool).unwrap();
+        }
+        assert_eq!(tools().len(), QUERY_KINDS.len());
+    }
+}
@@ -0,0 +1,146 @@
+//! The `gm.ready` banner: a push event sent once after the MCPL handshake,
+//! so the agent learns what this GameManager can do without trying things.
+//!
+//! It carries the GameManager's version, the engines, the default game and
+//! map, whether lobby accounts are stored, the write dir's state from a
+//! doctor pass, the optional features the config turns on, and the limits
+//! the agent plays under. The JSON keeps to [`SCHEMA`]: fields may be
+//! added, but none change meaning or go away without a new schema number.
+
+use crate::config::GmConfig;
+use crate::doctor::Checks;
+use crate::profiles::{self, Profiles};
+
+/// The banner's schema version.
+pub const SCHEMA: u32 = 1;
+
+/// The push event's id.
+pub const EVENT: &str = "gm.ready";
+
+/// The banner for `config`, with what the doctor found and the number of
+/// games that may run at once.
+pub fn banner(config: &GmConfig, checks: &Checks, max_concurrent_games: usize) -> serde_json::Value {
+    let profiles = Profiles::new(&config.profiles);
+    let default = profiles.select(profiles::DEFAULT_GAME);
+
+    let mut warnings = Vec::new();
+    let engine = match &checks.engine {
+        Ok((version, dir)) => serde_json::json!({"version": version, "dir": dir, "installed": checks.engines}),
+        Err(e) => {
+            warnings.push(format!("No engine: {}", e));
+            serde_json::json!({"version": null, "error": e, "installed": checks.engines})
+        }
+    };
+    if !checks.write_dir_writable {
+        warnings.push(format!("The write dir {} is not writable", checks.write_dir.display()));
+    }
+    if let Some((installed, built)) = checks.stale_bridge() {
+        warnings.push(format!(
+            "The installed bridge ({}) is not the build ({}); the next game installs the build",
+            installed.describe(),
+            built.describe()
+        ));
+    }
+
+    let policies: serde_json::Map<String, serde_json::Value> = config
+        .profiles
+        .iter()
+        .filter(|p| !p.policy.is_permissive())
+        .map(|p| (p.name.clone(), serde_json::to_value(&p.policy).unwrap()))
+        .collect();
+    serde_json::json!({
+        "schema": SCHEMA,
+        "gameManager": {"version": env!("CARGO_PKG_VERSION")},
+        "engine": engine,
+        "defaults": {
+            "game": profiles::DEFAULT_GAME,
+            "profile": default.name,
+            "map": default.default_map.as_deref().unwrap_or(profiles::DEFAULT_MAP),
+            "opponent": default.default_opponent,
+        },
+        "lobby": {
+            "credentialsConfigured": !config.credentials.is_empty(),
+            "storedAccounts": config.credentials.keys().collect::<Vec<_>>(),
+        },
+        "writeDir": {
+            "path": checks.write_dir,
+            "writable": checks.write_dir_writable,
+            "bridge": checks.installed.as_ref().and_then(|r| r.build.as_ref()).map(|b| b.describe()),
+            "bridgeStale": checks.stale_bridge().is_some(),
+        },
+        "features": {
+            "recording": true,
+            "audit": config.audit.enabled,
+            "statusPage": config.status_page.enabled,
+            "streamObserver": config.stream_observer.enabled,
+            "autoJoinFounders": config.auto_join_founders,
+            "autoRespondRules": config.auto_respond.len(),
+            "updateCadence": config.update_cadence.mode,
+            "chaos": config.chaos.is_some(),
+        },
+        "limits": {
+            "maxConcurrentGames": max_concurrent_games,
+            "pacing": {"maxCommands": config.pacing.max_commands, "intervalMs": config.pacing.interval_ms},
+            "commandPolicies": policies,
+        },
+        "warnings": warnings,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn checks() -> Checks {
+        Checks {
+            engine: Ok(("105.1.1-2511-g747f18b".into(), PathBuf::from("/spring/engine/linux64/105.1.1-2511-g747f18b"))),
+            engines: vec!["105.1.1-2511-g747f18b".into()],
+            write_dir: PathBuf::from("/tmp/write"),
+            write_dir_writable: true,
+            installed: None,
+            bridge_lib: PathBuf::from("/build/libSkirmishAI.so"),
+            built: None,
+        }
+    }
+
+    #[test]
+    fn test_default_banner() {
+        let banner = banner(&GmConfig::default(), &checks(), 4);
+        assert_eq!(banner["schema"], SCHEMA);
+        assert_eq!(banner["gameManager"]["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(banner["engine"]["version"], "105.1.1-2511-g747f18b");
+        assert_eq!(banner["defaults"]["profile"], "zero-k");
+        assert_eq!(banner["defaults"]["map"], profiles::DEFAULT_MAP);
+        assert_eq!(banner["lobby"]["credentialsConfigured"], false);
+        assert_eq!(banner["features"]["audit"], true);
+        assert_eq!(banner["features"]["statusPage"], false);
+        assert_eq!(banner["features"]["updateCadence"], "fixed");
+        assert_eq!(banner["limits"]["maxConcurrentGames"], 4);
+        assert_eq!(banner["limits"]["commandPolicies"], serde_json::json!({}));
+        assert_eq!(banner["warnings"], serde_json::json!([]));
+    }
+
+    #[test]
+    fn test_configured_banner() {
+        let config: GmConfig = serde_json::from_value(serde_json::json!({
+            "credentials": {"default": {"username": "agent", "password_env": "ZK_PASSWORD"}},
+            "auto_join_founders": ["TEAMAUTOHOST"],
+            "status_page": {"enabled": true},
+            "profiles": [{"name": "cautious", "games": ["Zero-K *"], "default_map": "Tundra", "policy": {"deny": ["pause"]}}],
+        }))
+        .unwrap();
+        let mut checks = checks();
+        checks.engine = Err("No engine versions found".into());
+        checks.write_dir_writable = false;
+        let banner = banner(&config, &checks, 2);
+        assert_eq!(banner["lobby"]["storedAccounts"], serde_json::json!(["default"]));
+        assert_eq!(banner["features"]["autoJoinFounders"], serde_json::json!(["TEAMAUTOHOST"]));
+        assert_eq!(banner["features"]["statusPage"], true);
+        assert_eq!((banner["defaults"]["profile"].as_str(), banner["defaults"]["map"].as_str()), (Some("cautious"), Some("Tundra")));
+        assert_eq!(banner["limits"]["commandPolicies"]["cautious"]["deny"], serde_json::json!(["pause"]));
+        assert_eq!(banner["engine"]["version"], serde_json::Value::Null);
+        let warnings: Vec<&str> = banner["warnings"].as_array().unwrap().iter().filter_map(|w| w.as_str()).collect();
+        assert_eq!(warnings, ["No engine: No engine versions found", "The write dir /tmp/write is not writable"]);
+    }
+}
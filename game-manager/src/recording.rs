@@ -0,0 +1,106 @@
+//! Session recording: every SAI event a game channel receives, one JSON
+//! line each, under `sessions/` in the write dir. The commands sent to the
+//! channel are logged alongside, as `gm_command` lines that read back as
+//! unknown events. The logs are the input for post-game analysis (see
+//! `analysis`).
+//!
+//! The first line is a `session_header`: the channel and the GameManager,
+//! bridge and engine versions, so a recorded game can be traced to the
+//! builds that played it.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::command_history::{self, Entry};
+use crate::sai_ipc::{BuildInfo, SaiEvent};
+
+/// `type` of a session log's first line.
+pub const HEADER_TYPE: &str = "session_header";
+
+/// Appends one channel's events to its session log.
+pub struct SessionRecorder {
+    pub path: PathBuf,
+    writer: BufWriter<File>,
+}
+
+impl SessionRecorder {
+    /// Start a new log in `dir`, headed by the builds in `bridge` (as
+    /// installed; the engine version filled in). The file name carries the
+    /// start time, so channel ids reused after a restart don't overwrite
+    /// older games.
+    pub fn create(dir: &Path, channel_id: &str, bridge: Option<&BuildInfo>) -> std::io::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        let stamp = chrono::Utc::now().format("%Y%m%dT%H%M%S");
+        let path = dir.join(format!("{}-{}.jsonl", channel_id.replace([':', '/'], "_"), stamp));
+        let mut writer = BufWriter::new(File::create(&path)?);
+        let header = serde_json::json!({
+            "type": HEADER_TYPE,
+            "channel": channel_id,
+            "game_manager_version": env!("CARGO_PKG_VERSION"),
+            "bridge": bridge,
+        });
+        writeln!(writer, "{}", header)?;
+        Ok(Self { path, writer })
+    }
+
+    pub fn record(&mut self, event: &SaiEvent) -> std::io::Result<()> {
+        // Unknown events are written as the bridge sent them.
+        let line = match event {
+            SaiEvent::Unknown { raw } => raw.to_string(),
+            event => serde_json::to_string(event)?,
+        };
+        writeln!(self.writer, "{}", line)
+    }
+
+    pub fn record_command(&mut self, entry: &Entry) -> std::io::Result<()> {
+        let mut line = entry.to_json();
+        line["type"] = command_history::LOG_TYPE.into();
+        writeln!(self.writer, "{}", line)
+    }
+
+    /// Flush and close the log, returning its path.
+    pub fn finish(mut self) -> std::io::Result<PathBuf> {
+        self.writer.flush()?;
+        Ok(self.path)
+    }
+}
+
+/// Read a session log back. Lines that don't parse are skipped.
+pub fn read_session(path: &Path) -> std::io::Result<Vec<SaiEvent>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut events = Vec::new();
+    for line in reader.lines() {
+        if let Ok(event) = SaiEvent::from_line(&line?) {
+            events.push(event);
+        }
+    }
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_and_read_back() {
+        let dir = std::env::temp_dir().join(format!("gm-sessions-{}", uuid::Uuid::new_v4()));
+        let build = BuildInfo { bridge_version: "0.1.0".into(), git_hash: None, engine_version: Some("105.1.1".into()) };
+        let mut recorder = SessionRecorder::create(&dir, "game:local-1", Some(&build)).unwrap();
+        let events = [
+            SaiEvent::Update { frame: 30, awaiting_commands: false, economy: None, allies: Vec::new(), counters: Default::default(), command_backlog: 0, under_construction: Vec::new(), update_interval: None },
+            SaiEvent::from_line(r#"{"type":"future_thing","x":1}"#).unwrap(),
+            SaiEvent::Release { reason: 1, stats: None },
+        ];
+        for event in &events {
+            recorder.record(event).unwrap();
+        }
+        let path = recorder.finish().unwrap();
+        assert!(path.file_name().unwrap().to_str().unwrap().starts_with("game_local-1-"));
+        let read = read_session(&path).unwrap();
+        let SaiEvent::Unknown { raw: header } = &read[0] else { panic!("{:?}", read[0]) };
+        assert_eq!(header["bridge"]["engine_version"], "105.1.1");
+        assert_eq!(header["channel"], "game:local-1");
+        assert_eq!(read[1..], events);
+    }
+}
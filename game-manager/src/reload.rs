@@ -0,0 +1,187 @@
+//! Reloading the config file on SIGHUP, so a long-running GameManager takes
+//! on new rules without dropping its lobby session or its games.
+//!
+//! Most sections take effect straight away or for the next game: chat
+//! rules, auto-join founders, the state stream's interval, command history,
+//! opponents, the engine environment, stored accounts, aggregated events,
+//! update cadence, pacing, the `channels/changed` window, the replay buffer
+//! size, lobby reconnects, army roles and game profiles with their command
+//! policies.
+//!
+//! The rest are fixed once the GameManager starts or the client connects:
+//! scopes, the MCPL delivery queue, stdio, the audit log, chaos, the status
+//! page and whether the state stream is offered. A change to one of them is
+//! refused and the running value kept, until the next restart.
+
+use crate::config::GmConfig;
+use crate::mcpl_link::DeliveryConfig;
+
+/// The push event's id.
+pub const EVENT: &str = "gm.config_reloaded";
+
+/// What reloading the config changes.
+#[derive(Debug)]
+pub struct Reload {
+    /// The config in effect afterwards.
+    pub config: GmConfig,
+    /// Sections the running GameManager took on.
+    pub applied: Vec<&'static str>,
+    /// Sections that changed but wait for a restart.
+    pub rejected: Vec<&'static str>,
+}
+
+impl Reload {
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({"applied": self.applied, "rejected": self.rejected})
+    }
+}
+
+/// Reload `new`, a config that loaded and validated, over `old`: its
+/// reloadable sections replace `old`'s, and the others stay as they were.
+pub fn plan(old: &GmConfig, new: &GmConfig) -> Reload {
+    let mut reload = Reload { config: old.clone(), applied: Vec::new(), rejected: Vec::new() };
+    macro_rules! reloadable {
+        ($($section:ident),*) => {$(
+            if old.$section != new.$section {
+                reload.config.$section = new.$section.clone();
+                reload.applied.push(stringify!($section));
+            }
+        )*};
+    }
+    macro_rules! fixed {
+        ($($section:ident),*) => {$(
+            if old.$section != new.$section {
+                reload.rejected.push(stringify!($section));
+            }
+        )*};
+    }
+
+    reloadable!(auto_respond);
+    // The client learned at the handshake whether the stream is offered.
+    if old.stream_observer.interval_secs != new.stream_observer.interval_secs {
+        reload.config.stream_observer.interval_secs = new.stream_observer.interval_secs;
+        reload.applied.push("stream_observer.interval_secs");
+    }
+    if old.stream_observer.enabled != new.stream_observer.enabled {
+        reload.rejected.push("stream_observer.enabled");
+    }
+    fixed!(scopes, default_scope);
+    reloadable!(auto_join_founders, command_history);
+    // The link's queue and timeouts are set when it starts; the window for
+    // held channel changes and the replay buffers are the GameManager's own.
+    let window = new.mcpl_delivery.changes_window_secs;
+    if old.mcpl_delivery.changes_window_secs != window {
+        reload.config.mcpl_delivery.changes_window_secs = window;
+        reload.applied.push("mcpl_delivery.changes_window_secs");
+    }
+    if old.mcpl_delivery.replay_size != new.mcpl_delivery.replay_size {
+        reload.config.mcpl_delivery.replay_size = new.mcpl_delivery.replay_size;
+        reload.applied.push("mcpl_delivery.replay_size");
+    }
+    let link = DeliveryConfig {
+        changes_window_secs: old.mcpl_delivery.changes_window_secs,
+        replay_size: old.mcpl_delivery.replay_size,
+        ..new.mcpl_delivery.clone()
+    };
+    if old.mcpl_delivery != link {
+        reload.rejected.push("mcpl_delivery");
+    }
+    fixed!(stdio);
+    reloadable!(opponents, engine_env);
+    fixed!(audit);
+    reloadable!(credentials, aggregate_events, update_cadence);
+    fixed!(chaos);
+    reloadable!(pacing);
+    fixed!(status_page);
+    reloadable!(lobby_reconnect, army, profiles);
+    reload
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(json: serde_json::Value) -> GmConfig {
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn test_unchanged() {
+        let old = config(serde_json::json!({"pacing": {"max_commands": 20}, "status_page": {"enabled": true}}));
+        let reload = plan(&old, &old.clone());
+        assert!(reload.applied.is_empty() && reload.rejected.is_empty());
+        assert_eq!(reload.config, old);
+        assert_eq!(reload.to_json(), serde_json::json!({"applied": [], "rejected": []}));
+    }
+
+    #[test]
+    fn test_reloadable_sections() {
+        let sections = [
+            ("auto_respond", serde_json::json!({"auto_respond": [{"pattern": "^gg$", "reply": "gg wp"}]})),
+            ("stream_observer.interval_secs", serde_json::json!({"stream_observer": {"interval_secs": 30.0}})),
+            ("auto_join_founders", serde_json::json!({"auto_join_founders": ["TEAMAUTOHOST"]})),
+            ("command_history", serde_json::json!({"command_history": {"size": 50}})),
+            ("mcpl_delivery.changes_window_secs", serde_json::json!({"mcpl_delivery": {"changes_window_secs": 1.0}})),
+            ("mcpl_delivery.replay_size", serde_json::json!({"mcpl_delivery": {"replay_size": 50}})),
+            ("opponents", serde_json::json!({"opponents": [{"name": "CAI"}]})),
+            ("engine_env", serde_json::json!({"engine_env": {"set": {"OMP_NUM_THREADS": "1"}}})),
+            ("credentials", serde_json::json!({"credentials": {"default": {"username": "agent", "password_env": "ZK_PASSWORD"}}})),
+            ("aggregate_events", serde_json::json!({"aggregate_events": ["weapon_fired"]})),
+            ("update_cadence", serde_json::json!({"update_cadence": {"mode": "adaptive"}})),
+            ("pacing", serde_json::json!({"pacing": {"max_commands": 5}})),
+            ("lobby_reconnect", serde_json::json!({"lobby_reconnect": {"max_attempts": 3}})),
+            ("army", serde_json::json!({"army": {"roles": [{"role": "raiders", "defs": ["cloakraid"]}]}})),
+            ("profiles", serde_json::json!({"profiles": [{"name": "cautious", "games": ["Zero-K *"], "policy": {"deny": ["pause"]}}]})),
+        ];
+        for (section, json) in sections {
+            let new = config(json);
+            let reload = plan(&GmConfig::default(), &new);
+            assert_eq!(reload.applied, [section]);
+            assert!(reload.rejected.is_empty(), "{}: {:?}", section, reload.rejected);
+            assert_eq!(reload.config, new, "{} is taken on", section);
+        }
+    }
+
+    #[test]
+    fn test_fixed_sections() {
+        let sections = [
+            ("stream_observer.enabled", serde_json::json!({"stream_observer": {"enabled": true}})),
+            ("scopes", serde_json::json!({"scopes": {"watcher": {"tools": ["lobby_*"]}}})),
+            ("mcpl_delivery", serde_json::json!({"mcpl_delivery": {"queue_size": 10}})),
+            ("stdio", serde_json::json!({"stdio": {"linger_secs": 60.0}})),
+            ("audit", serde_json::json!({"audit": {"enabled": false}})),
+            ("chaos", serde_json::json!({"chaos": {"latency_ms": 100}})),
+            ("status_page", serde_json::json!({"status_page": {"enabled": true}})),
+        ];
+        for (section, json) in sections {
+            let reload = plan(&GmConfig::default(), &config(json));
+            assert_eq!(reload.rejected, [section]);
+            assert!(reload.applied.is_empty(), "{}: {:?}", section, reload.applied);
+            assert_eq!(reload.config, GmConfig::default(), "{} keeps its running value", section);
+        }
+
+        let scoped = config(serde_json::json!({"scopes": {"watcher": {}}, "default_scope": "watcher"}));
+        assert_eq!(plan(&GmConfig::default(), &scoped).rejected, ["scopes", "default_scope"]);
+    }
+
+    #[test]
+    fn test_mixed_reload() {
+        // A section's reloadable part is taken on and the rest refused.
+        let old = config(serde_json::json!({"pacing": {"max_commands": 20}}));
+        let new = config(serde_json::json!({
+            "pacing": {"max_commands": 10},
+            "stream_observer": {"enabled": true, "interval_secs": 2.0},
+            "mcpl_delivery": {"queue_size": 10, "changes_window_secs": 0.5},
+            "status_page": {"enabled": true},
+        }));
+        let reload = plan(&old, &new);
+        assert_eq!(reload.applied, ["stream_observer.interval_secs", "mcpl_delivery.changes_window_secs", "pacing"]);
+        assert_eq!(reload.rejected, ["stream_observer.enabled", "mcpl_delivery", "status_page"]);
+        assert_eq!(reload.config.pacing.max_commands, 10);
+        assert_eq!(reload.config.stream_observer.interval_secs, 2.0);
+        assert!(!reload.config.stream_observer.enabled);
+        assert_eq!(reload.config.mcpl_delivery.changes_window_secs, 0.5);
+        assert_eq!(reload.config.mcpl_delivery.queue_size, old.mcpl_delivery.queue_size);
+        assert!(!reload.config.status_page.enabled);
+    }
+}
@@ -0,0 +1,152 @@
+//! Replaying missed `channels/incoming` messages.
+//!
+//! Each message sent on a channel gets the next of the channel's sequence
+//! numbers, from 1, as `metadata.seq`. The last [`DeliveryConfig`]
+//! `replay_size` messages are kept, oldest dropped first. A client that
+//! timed out or reconnected compares the `latestSeq` in `channels/list`
+//! with the last message it saw, and asks `channels/replay` for the rest.
+//! Messages dropped from the buffer meanwhile are reported as a range, so
+//! the client knows what it can't get back.
+//!
+//! [`DeliveryConfig`]: crate::mcpl_link::DeliveryConfig
+
+use std::collections::VecDeque;
+
+use mcpl_core::methods::IncomingChannelMessage;
+
+/// One channel's sent messages.
+#[derive(Debug)]
+pub struct ReplayBuffer {
+    size: usize,
+    /// Sequence number of the last message sent; 0 before any.
+    latest: u64,
+    messages: VecDeque<(u64, IncomingChannelMessage)>,
+}
+
+/// Messages since a sequence number.
+#[derive(Debug)]
+pub struct Replay<'a> {
+    pub messages: Vec<&'a IncomingChannelMessage>,
+    /// Asked-for messages no longer buffered: first and last seq.
+    pub evicted: Option<(u64, u64)>,
+    pub latest: u64,
+}
+
+impl ReplayBuffer {
+    pub fn new(size: usize) -> Self {
+        Self { size, latest: 0, messages: VecDeque::new() }
+    }
+
+    /// Keep at most `size` messages from now on.
+    pub fn resize(&mut self, size: usize) {
+        self.size = size;
+        self.trim();
+    }
+
+    fn trim(&mut self) {
+        while self.messages.len() > self.size {
+            self.messages.pop_front();
+        }
+    }
+
+    pub fn latest(&self) -> u64 {
+        self.latest
+    }
+
+    /// Number `message`, setting its `metadata.seq`, and keep a copy.
+    pub fn push(&mut self, message: &mut IncomingChannelMessage) -> u64 {
+        self.latest += 1;
+        let metadata = message.metadata.get_or_insert_with(|| serde_json::json!({}));
+        if let Some(metadata) = metadata.as_object_mut() {
+            metadata.insert("seq".into(), self.latest.into());
+        }
+        self.messages.push_back((self.latest, message.clone()));
+        self.trim();
+        self.latest
+    }
+
+    /// The messages numbered `from` on.
+    pub fn since(&self, from: u64) -> Replay<'_> {
+        let from = from.max(1);
+        let first_kept = self.messages.front().map_or(self.latest + 1, |(seq, _)| *seq);
+        let evicted = (from < first_kept).then_some((from, first_kept - 1));
+        Replay {
+            messages: self.messages.iter().filter(|(seq, _)| *seq >= from).map(|(_, m)| m).collect(),
+            evicted,
+            latest: self.latest,
+        }
+    }
+}
+
+impl Replay<'_> {
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "messages": self.messages,
+            "latestSeq": self.latest,
+            "evicted": self.evicted.map(|(from, to)| serde_json::json!({"fromSeq": from, "toSeq": to})),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mcpl_core::methods::MessageAuthor;
+    use mcpl_core::types::ContentBlock;
+
+    fn message(text: &str) -> IncomingChannelMessage {
+        IncomingChannelMessage {
+            channel_id: "game:local-1".into(),
+            message_id: text.into(),
+            thread_id: None,
+            author: MessageAuthor { id: "gamemanager".into(), name: "GameManager".into() },
+            content: vec![ContentBlock::text(text.to_string())],
+            timestamp: "2026-01-01T00:00:00Z".into(),
+            metadata: None,
+        }
+    }
+
+    fn ids(replay: &Replay) -> Vec<String> {
+        replay.messages.iter().map(|m| m.message_id.clone()).collect()
+    }
+
+    #[test]
+    fn test_sequence_and_replay() {
+        let mut buffer = ReplayBuffer::new(10);
+        assert_eq!(buffer.since(1).latest, 0);
+        assert!(buffer.since(1).evicted.is_none(), "nothing sent is nothing missed");
+        for text in ["a", "b", "c"] {
+            let mut m = message(text);
+            let seq = buffer.push(&mut m);
+            assert_eq!(m.metadata.unwrap()["seq"], seq);
+        }
+        assert_eq!(buffer.latest(), 3);
+        assert_eq!(ids(&buffer.since(2)), ["b", "c"]);
+        assert_eq!(ids(&buffer.since(0)), ["a", "b", "c"]);
+        assert!(buffer.since(4).messages.is_empty());
+
+        // Existing metadata keeps its fields.
+        let mut m = message("d");
+        m.metadata = Some(serde_json::json!({"eventType": "unit_idle"}));
+        buffer.push(&mut m);
+        assert_eq!(m.metadata.unwrap(), serde_json::json!({"eventType": "unit_idle", "seq": 4}));
+    }
+
+    #[test]
+    fn test_eviction() {
+        let mut buffer = ReplayBuffer::new(3);
+        for text in ["a", "b", "c", "d", "e"] {
+            buffer.push(&mut message(text));
+        }
+        let replay = buffer.since(2);
+        assert_eq!(ids(&replay), ["c", "d", "e"]);
+        assert_eq!(replay.evicted, Some((2, 2)));
+        assert_eq!(replay.to_json()["evicted"], serde_json::json!({"fromSeq": 2, "toSeq": 2}));
+        assert!(buffer.since(3).evicted.is_none());
+
+        buffer.resize(1);
+        let replay = buffer.since(1);
+        assert_eq!((ids(&replay), replay.evicted), (vec!["e".to_string()], Some((1, 4))));
+        assert_eq!(replay.to_json()["latestSeq"], 5);
+    }
+}
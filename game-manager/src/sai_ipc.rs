@@ -4,276 +4,144 @@
 //! running the SAI bridge. Routes events to MCPL channels and
 //! commands from MCPL to the appropriate engine.
 
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::time::{Duration, Instant};
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 use tokio::net::UnixStream;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct MetalSpot {
-    pub x: f32,
-    pub y: f32,
-    pub z: f32,
-    pub metal: f32,
+use crate::chaos::{Chaos, ChaosConfig, ChaosCounts};
+use crate::groups::UnitGroups;
+use crate::locations::Places;
+use crate::map_grid::MapGrid;
+use crate::pacing::{Paced, Pacer, PacingConfig, PacingCounts};
+use crate::positions::{self, MapContext};
+use crate::profiles::GameProfile;
+
+pub use sai_protocol::{
+    BuildInfo, ChatDestination, DryRun, GameCommand as SaiCommand, GameEvent as SaiEvent, Paralysis, Relation, TeamId, Tracked, UnitDefId,
+    UnitDefInfo, UnitId, PROTOCOL_VERSION,
+};
+
+/// How long a dry run waits for the bridge's verdicts. The bridge answers
+/// on its next frame, or the next heartbeat while paused for a turn.
+pub const DRY_RUN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The first protocol version whose bridges answer dry runs instead of
+/// executing them.
+const DRY_RUN_PROTOCOL: u32 = 2;
+
+/// How long a unit def query waits for the whole catalog.
+pub const UNIT_DEFS_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The first protocol version whose bridges answer unit def queries.
+const UNIT_DEFS_PROTOCOL: u32 = 3;
+
+/// How long a map grid query waits for the whole grid. Sampling a large
+/// map takes the bridge tens of thousands of callbacks in one frame.
+pub const MAP_GRID_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The first protocol version whose bridges answer map grid queries.
+const MAP_GRID_PROTOCOL: u32 = 4;
+/// The first protocol version whose bridges answer unit queue queries.
+const UNIT_QUEUES_PROTOCOL: u32 = 5;
+
+/// Traffic counters for one game channel. Reset when the channel closes
+/// (or on request via the game_stats tool).
+#[derive(Debug, Clone)]
+pub struct ChannelStats {
+    pub events_by_type: BTreeMap<String, u64>,
+    /// Subset of `events_by_type` this build couldn't parse into a known variant.
+    pub unknown_events: BTreeMap<String, u64>,
+    pub commands_by_type: BTreeMap<String, u64>,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    pub parse_failures: u64,
+    pub last_frame: Option<i32>,
+    pub last_event_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub since: Instant,
 }
 
-/// An event received from a SAI bridge instance.
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(tag = "type")]
-pub enum SaiEvent {
-    #[serde(rename = "init")]
-    Init {
-        frame: i32,
-        saved_game: bool,
-        #[serde(default, skip_serializing_if = "Option::is_none")]
-        metal_spots: Option<Vec<MetalSpot>>,
-        #[serde(default, skip_serializing_if = "Option::is_none")]
-        map_width: Option<i32>,
-        #[serde(default, skip_serializing_if = "Option::is_none")]
-        map_height: Option<i32>,
-    },
-    #[serde(rename = "release")]
-    Release { reason: i32 },
-    #[serde(rename = "update")]
-    Update { frame: i32 },
-    #[serde(rename = "message")]
-    Message { player: i32, text: String },
-    #[serde(rename = "unit_created")]
-    UnitCreated {
-        unit: i32,
-        #[serde(default, skip_serializing_if = "Option::is_none")]
-        unit_name: Option<String>,
-        builder: i32,
-        #[serde(default, skip_serializing_if = "Option::is_none")]
-        builder_name: Option<String>,
-        #[serde(default, skip_serializing_if = "Option::is_none")]
-        pos: Option<[f32; 3]>,
-    },
-    #[serde(rename = "unit_finished")]
-    UnitFinished {
-        unit: i32,
-        #[serde(default, skip_serializing_if = "Option::is_none")]
-        unit_name: Option<String>,
-        #[serde(default, skip_serializing_if = "Option::is_none")]
-        pos: Option<[f32; 3]>,
-    },
-    #[serde(rename = "unit_idle")]
-    UnitIdle {
-        unit: i32,
-        #[serde(default, skip_serializing_if = "Option::is_none")]
-        unit_name: Option<String>,
-    },
-    #[serde(rename = "unit_move_failed")]
-    UnitMoveFailed {
-        unit: i32,
-        #[serde(default, skip_serializing_if = "Option::is_none")]
-        unit_name: Option<String>,
-    },
-    #[serde(rename = "unit_damaged")]
-    UnitDamaged {
-        unit: i32,
-        #[serde(default, skip_serializing_if = "Option::is_none")]
-        unit_name: Option<String>,
-        attacker: i32,
-        #[serde(default, skip_serializing_if = "Option::is_none")]
-        attacker_name: Option<String>,
-        damage: f32,
-        weapon_def_id: i32,
-        paralyzer: bool,
-    },
-    #[serde(rename = "unit_destroyed")]
-    UnitDestroyed {
-        unit: i32,
-        #[serde(default, skip_serializing_if = "Option::is_none")]
-        unit_name: Option<String>,
-        attacker: i32,
-        #[serde(default, skip_serializing_if = "Option::is_none")]
-        attacker_name: Option<String>,
-        weapon_def_id: i32,
-    },
-    #[serde(rename = "unit_given")]
-    UnitGiven {
-        unit: i32,
-        #[serde(default, skip_serializing_if = "Option::is_none")]
-        unit_name: Option<String>,
-        old_team: i32,
-        new_team: i32,
-    },
-    #[serde(rename = "unit_captured")]
-    UnitCaptured {
-        unit: i32,
-        #[serde(default, skip_serializing_if = "Option::is_none")]
-        unit_name: Option<String>,
-        old_team: i32,
-        new_team: i32,
-    },
-    #[serde(rename = "enemy_enter_los")]
-    EnemyEnterLos {
-        enemy: i32,
-        #[serde(default, skip_serializing_if = "Option::is_none")]
-        enemy_name: Option<String>,
-        #[serde(default, skip_serializing_if = "Option::is_none")]
-        pos: Option<[f32; 3]>,
-    },
-    #[serde(rename = "enemy_leave_los")]
-    EnemyLeaveLos {
-        enemy: i32,
-        #[serde(default, skip_serializing_if = "Option::is_none")]
-        enemy_name: Option<String>,
-    },
-    #[serde(rename = "enemy_enter_radar")]
-    EnemyEnterRadar {
-        enemy: i32,
-        #[serde(default, skip_serializing_if = "Option::is_none")]
-        enemy_name: Option<String>,
-    },
-    #[serde(rename = "enemy_leave_radar")]
-    EnemyLeaveRadar {
-        enemy: i32,
-        #[serde(default, skip_serializing_if = "Option::is_none")]
-        enemy_name: Option<String>,
-    },
-    #[serde(rename = "enemy_damaged")]
-    EnemyDamaged {
-        enemy: i32,
-        #[serde(default, skip_serializing_if = "Option::is_none")]
-        enemy_name: Option<String>,
-        attacker: i32,
-        #[serde(default, skip_serializing_if = "Option::is_none")]
-        attacker_name: Option<String>,
-        damage: f32,
-        weapon_def_id: i32,
-        paralyzer: bool,
-    },
-    #[serde(rename = "enemy_destroyed")]
-    EnemyDestroyed {
-        enemy: i32,
-        #[serde(default, skip_serializing_if = "Option::is_none")]
-        enemy_name: Option<String>,
-        attacker: i32,
-        #[serde(default, skip_serializing_if = "Option::is_none")]
-        attacker_name: Option<String>,
-    },
-    #[serde(rename = "enemy_created")]
-    EnemyCreated {
-        enemy: i32,
-        #[serde(default, skip_serializing_if = "Option::is_none")]
-        enemy_name: Option<String>,
-    },
-    #[serde(rename = "enemy_finished")]
-    EnemyFinished {
-        enemy: i32,
-        #[serde(default, skip_serializing_if = "Option::is_none")]
-        enemy_name: Option<String>,
-    },
-    #[serde(rename = "weapon_fired")]
-    WeaponFired {
-        unit: i32,
-        #[serde(default, skip_serializing_if = "Option::is_none")]
-        unit_name: Option<String>,
-        weapon_def_id: i32,
-    },
-    #[serde(rename = "command_finished")]
-    CommandFinished {
-        unit: i32,
-        #[serde(default, skip_serializing_if = "Option::is_none")]
-        unit_name: Option<String>,
-        command_id: i32,
-        command_topic: i32,
-    },
-    #[serde(rename = "lua_message")]
-    LuaMessage { data: String },
-    #[serde(rename = "command_error")]
-    CommandError { error: String, command: String },
+impl Default for ChannelStats {
+    fn default() -> Self {
+        Self {
+            events_by_type: BTreeMap::new(),
+            unknown_events: BTreeMap::new(),
+            commands_by_type: BTreeMap::new(),
+            bytes_in: 0,
+            bytes_out: 0,
+            parse_failures: 0,
+            last_frame: None,
+            last_event_at: None,
+            since: Instant::now(),
+        }
+    }
 }
 
-/// A command to send to a SAI bridge instance.
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(tag = "type")]
-pub enum SaiCommand {
-    #[serde(rename = "move")]
-    Move {
-        unit_id: i32,
-        x: f32,
-        #[serde(default)]
-        y: f32,
-        z: f32,
-        #[serde(default)]
-        queue: bool,
-    },
-    #[serde(rename = "stop")]
-    Stop { unit_id: i32 },
-    #[serde(rename = "attack")]
-    Attack {
-        unit_id: i32,
-        target_id: i32,
-        #[serde(default)]
-        queue: bool,
-    },
-    #[serde(rename = "build")]
-    Build {
-        unit_id: i32,
-        #[serde(default)]
-        build_def_id: i32,
-        #[serde(default)]
-        build_def_name: Option<String>,
-        #[serde(default)]
-        x: f32,
-        #[serde(default)]
-        y: f32,
-        #[serde(default)]
-        z: f32,
-        #[serde(default)]
-        facing: i32,
-        #[serde(default)]
-        queue: bool,
-    },
-    #[serde(rename = "patrol")]
-    Patrol {
-        unit_id: i32,
-        x: f32,
-        #[serde(default)]
-        y: f32,
-        z: f32,
-        #[serde(default)]
-        queue: bool,
-    },
-    #[serde(rename = "fight")]
-    Fight {
-        unit_id: i32,
-        x: f32,
-        #[serde(default)]
-        y: f32,
-        z: f32,
-        #[serde(default)]
-        queue: bool,
-    },
-    #[serde(rename = "guard")]
-    Guard {
-        unit_id: i32,
-        guard_id: i32,
-        #[serde(default)]
-        queue: bool,
-    },
-    #[serde(rename = "repair")]
-    Repair {
-        unit_id: i32,
-        repair_id: i32,
-        #[serde(default)]
-        queue: bool,
-    },
-    #[serde(rename = "set_fire_state")]
-    SetFireState { unit_id: i32, state: i32 },
-    #[serde(rename = "set_move_state")]
-    SetMoveState { unit_id: i32, state: i32 },
-    #[serde(rename = "send_chat")]
-    SendChat { text: String },
-    #[serde(rename = "pause")]
-    Pause,
-    #[serde(rename = "unpause")]
-    Unpause,
-    #[serde(rename = "set_speed")]
-    SetSpeed { speed: f32 },
+impl ChannelStats {
+    fn record_event(&mut self, event: &SaiEvent, bytes: usize) {
+        let kind = event.type_name().to_string();
+        if matches!(event, SaiEvent::Unknown { .. }) {
+            *self.unknown_events.entry(kind.clone()).or_insert(0) += 1;
+        }
+        *self.events_by_type.entry(kind).or_insert(0) += 1;
+        self.bytes_in += bytes as u64;
+        if let SaiEvent::Init { frame, .. } | SaiEvent::Update { frame, .. } = event {
+            self.last_frame = Some(*frame);
+        }
+        self.last_event_at = Some(chrono::Utc::now());
+    }
+
+    fn record_command(&mut self, cmd: &SaiCommand, bytes: usize) {
+        *self
+            .commands_by_type
+            .entry(cmd.type_name().to_string())
+            .or_insert(0) += 1;
+        self.bytes_out += bytes as u64;
+    }
+
+    pub fn events_total(&self) -> u64 {
+        self.events_by_type.values().sum()
+    }
+
+    pub fn commands_total(&self) -> u64 {
+        self.commands_by_type.values().sum()
+    }
+
+    fn per_minute(&self, count: u64) -> f64 {
+        let minutes = self.since.elapsed().as_secs_f64() / 60.0;
+        if minutes <= 0.0 {
+            0.0
+        } else {
+            count as f64 / minutes
+        }
+    }
+
+    /// One-line rate summary for channels/list.
+    pub fn digest(&self) -> String {
+        format!(
+            "{:.1} events/min, {:.1} commands/min",
+            self.per_minute(self.events_total()),
+            self.per_minute(self.commands_total())
+        )
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "events": self.events_total(),
+            "eventsByType": self.events_by_type,
+            "unknownEvents": self.unknown_events,
+            "commands": self.commands_total(),
+            "commandsByType": self.commands_by_type,
+            "bytesIn": self.bytes_in,
+            "bytesOut": self.bytes_out,
+            "parseFailures": self.parse_failures,
+            "lastFrame": self.last_frame,
+            "lastEventAt": self.last_event_at.map(|t| t.to_rfc3339()),
+            "elapsedSecs": self.since.elapsed().as_secs(),
+            "eventsPerMin": self.per_minute(self.events_total()),
+            "commandsPerMin": self.per_minute(self.commands_total()),
+        })
+    }
 }
 
 /// A connected SAI bridge instance.
@@ -281,7 +149,19 @@ pub struct SaiConnection {
     pub channel_id: String,
     writer: tokio::io::WriteHalf<UnixStream>,
     reader: BufReader<tokio::io::ReadHalf<UnixStream>>,
-    read_buf: String,
+    /// The line being read; kept across calls until its newline arrives.
+    read_buf: Vec<u8>,
+    pub stats: ChannelStats,
+    /// Protocol version from the bridge's init event.
+    pub protocol_version: Option<u32>,
+    /// The bridge's build and engine version from its init event.
+    pub build: Option<BuildInfo>,
+    /// Events read while waiting for dry-run verdicts, not yet drained.
+    held: VecDeque<SaiEvent>,
+    /// Fault injection on reads, in chaos mode.
+    chaos: Option<Chaos>,
+    /// Order budget and the orders waiting for it.
+    pacer: Pacer,
 }
 
 impl SaiConnection {
@@ -291,52 +171,155 @@ impl SaiConnection {
             channel_id,
             writer,
             reader: BufReader::new(reader),
-            read_buf: String::new(),
+            read_buf: Vec::new(),
+            stats: ChannelStats::default(),
+            protocol_version: None,
+            build: None,
+            held: VecDeque::new(),
+            chaos: None,
+            pacer: Pacer::new(PacingConfig::default(), Instant::now()),
         }
     }
 
     /// Read the next event from this SAI connection.
     /// Returns None on EOF.
+    ///
+    /// Cancel safe: callers poll with short timeouts, and a line the bridge
+    /// wrote in pieces may be cut short by one. Its start stays in
+    /// `read_buf` and the next call reads on from there. In chaos mode the
+    /// events read go through [`Chaos`] first, which only changes between
+    /// awaits.
     pub async fn next_event(&mut self) -> Option<SaiEvent> {
         loop {
-            self.read_buf.clear();
-            match self.reader.read_line(&mut self.read_buf).await {
+            if let Some(event) = self.chaos.as_mut().and_then(|chaos| chaos.release(Instant::now())) {
+                return Some(event);
+            }
+            let event = match self.chaos.as_ref().and_then(Chaos::next_due) {
+                // Read until the held event is due, then hand that on.
+                Some(due) => match tokio::time::timeout_at(due.into(), self.read_event()).await {
+                    Ok(event) => event?,
+                    Err(_) => continue,
+                },
+                None => self.read_event().await?,
+            };
+            match &mut self.chaos {
+                Some(chaos) => chaos.admit(event, Instant::now()),
+                None => return Some(event),
+            }
+        }
+    }
+
+    /// The next event on the wire.
+    async fn read_event(&mut self) -> Option<SaiEvent> {
+        loop {
+            match self.reader.read_until(b'\n', &mut self.read_buf).await {
                 Ok(0) => return None, // EOF
-                Ok(_) => {
-                    let trimmed = self.read_buf.trim();
-                    if trimmed.is_empty() {
-                        continue;
-                    }
-                    match serde_json::from_str(trimmed) {
-                        Ok(event) => return Some(event),
-                        Err(e) => {
-                            tracing::warn!("Failed to parse SAI event: {} — {:?}", e, trimmed);
-                            continue;
-                        }
-                    }
-                }
+                // A full line, or what's left of one at EOF.
+                Ok(_) => {}
                 Err(e) => {
                     tracing::error!("SAI read error: {}", e);
                     return None;
                 }
             }
+            let line = std::mem::take(&mut self.read_buf);
+            let n = line.len();
+            let text = String::from_utf8_lossy(&line);
+            let trimmed = text.trim();
+            if trimmed.is_empty() {
+                continue;
+            }
+            match SaiEvent::from_line(trimmed) {
+                Ok(event) => {
+                    self.stats.record_event(&event, n);
+                    if let SaiEvent::Init { protocol_version, build, .. } = &event {
+                        self.protocol_version = *protocol_version;
+                        self.build = build.clone();
+                    }
+                    return Some(event);
+                }
+                Err(e) => {
+                    self.stats.parse_failures += 1;
+                    self.stats.bytes_in += n as u64;
+                    tracing::warn!("Failed to parse SAI event: {} — {:?}", e, trimmed);
+                }
+            }
+        }
+    }
+
+    /// Send a command to this SAI connection, or queue it when the pacing
+    /// budget is spent.
+    pub async fn send_command(&mut self, tracked: Tracked) -> Result<Paced, std::io::Error> {
+        if !self.pacer.admit(Instant::now()) {
+            return Ok(self.pacer.hold(tracked));
         }
+        self.write_command(&tracked).await?;
+        Ok(Paced::Sent)
     }
 
-    /// Send a command to this SAI connection.
-    pub async fn send_command(&mut self, cmd: &SaiCommand) -> Result<(), std::io::Error> {
-        let json = serde_json::to_string(cmd).unwrap();
+    /// Send the queued commands the pacing budget allows by now.
+    pub async fn flush_paced(&mut self, now: Instant) -> Result<(), std::io::Error> {
+        while let Some(tracked) = self.pacer.release(now) {
+            self.write_command(&tracked).await?;
+        }
+        Ok(())
+    }
+
+    async fn write_command(&mut self, tracked: &Tracked) -> Result<(), std::io::Error> {
+        let json = tracked.to_line();
         self.writer.write_all(json.as_bytes()).await?;
         self.writer.write_all(b"\n").await?;
         self.writer.flush().await?;
+        self.stats.record_command(&tracked.command, json.len() + 1);
         Ok(())
     }
+
+    /// Send a command for validation only. Not counted as a command.
+    async fn send_dry_run(&mut self, dry_run: &DryRun) -> Result<(), std::io::Error> {
+        self.send_uncounted(&dry_run.to_line()).await
+    }
+
+    /// Send a line that isn't an order (a dry run or a query).
+    async fn send_uncounted(&mut self, line: &str) -> Result<(), std::io::Error> {
+        self.writer.write_all(line.as_bytes()).await?;
+        self.writer.write_all(b"\n").await?;
+        self.writer.flush().await?;
+        self.stats.bytes_out += line.len() as u64 + 1;
+        Ok(())
+    }
+}
+
+/// Sub-channel ids are the game's channel id, this, and the team.
+const COOP_SEPARATOR: &str = "/team";
+
+/// The sub-channel of the AgentBridge playing `team` in `channel_id`'s
+/// game next to the agent's: a co-op ally, or the opponent in self-play.
+/// Its events arrive on a socket of its own.
+pub fn coop_channel_id(channel_id: &str, team: i32) -> String {
+    format!("{}{}{}", channel_id, COOP_SEPARATOR, team)
+}
+
+/// The game channel a (sub-)channel belongs to.
+pub fn game_channel_id(channel_id: &str) -> &str {
+    channel_id.split_once(COOP_SEPARATOR).map_or(channel_id, |(game, _)| game)
+}
+
+/// The team a sub-channel's bridge plays; None for a game channel.
+pub fn sub_channel_team(channel_id: &str) -> Option<i32> {
+    channel_id.split_once(COOP_SEPARATOR).and_then(|(_, team)| team.parse().ok())
 }
 
 /// Manages SAI IPC connections.
 pub struct SaiIpcServer {
     pub listeners: HashMap<String, std::os::unix::net::UnixListener>,
     pub connections: HashMap<String, SaiConnection>,
+    next_request_id: u64,
+    /// Faults to inject into every connection; None in production.
+    pub chaos: Option<ChaosConfig>,
+    /// Chaos counts of channels between connections, carried over to the
+    /// next one.
+    chaos_counts: HashMap<String, ChaosCounts>,
+    /// Order budget of every connection (config `pacing`).
+    pub pacing: PacingConfig,
 }
 
 impl SaiIpcServer {
@@ -344,6 +327,10 @@ impl SaiIpcServer {
         Self {
             listeners: HashMap::new(),
             connections: HashMap::new(),
+            next_request_id: 1,
+            chaos: None,
+            chaos_counts: HashMap::new(),
+            pacing: PacingConfig::default(),
         }
     }
 
@@ -363,10 +350,22 @@ impl SaiIpcServer {
         Ok(())
     }
 
-    /// Stop listening for a channel and close any active connection.
+    /// Stop listening for a channel and close any active connection, its
+    /// co-op sub-channels' too.
     pub fn close_channel(&mut self, channel_id: &str) {
-        self.listeners.remove(channel_id);
-        self.connections.remove(channel_id);
+        for id in std::iter::once(channel_id.to_string()).chain(self.coop_channels(channel_id)) {
+            self.listeners.remove(&id);
+            self.connections.remove(&id);
+            self.chaos_counts.remove(&id);
+        }
+    }
+
+    /// The co-op sub-channels listened for in `channel_id`'s game.
+    pub fn coop_channels(&self, channel_id: &str) -> Vec<String> {
+        let prefix = format!("{}{}", channel_id, COOP_SEPARATOR);
+        let mut ids: Vec<String> = self.listeners.keys().filter(|id| id.starts_with(&prefix)).cloned().collect();
+        ids.sort();
+        ids
     }
 
     /// Accept any pending connections from SAI bridges (non-blocking).
@@ -387,7 +386,12 @@ impl SaiIpcServer {
                         std_stream.set_nonblocking(true).ok();
                         match UnixStream::from_std(std_stream) {
                             Ok(stream) => {
-                                let conn = SaiConnection::new(channel_id.clone(), stream);
+                                let mut conn = SaiConnection::new(channel_id.clone(), stream);
+                                conn.pacer = Pacer::new(self.pacing.clone(), Instant::now());
+                                conn.chaos = self.chaos.clone().map(|config| {
+                                    let counts = self.chaos_counts.remove(&channel_id).unwrap_or_default();
+                                    Chaos::new(&channel_id, config, counts, Instant::now())
+                                });
                                 self.connections.insert(channel_id.clone(), conn);
                                 connected.push(channel_id);
                             }
@@ -409,28 +413,1781 @@ impl SaiIpcServer {
         connected
     }
 
-    /// Send a command to a specific channel's SAI.
+    /// Read the events a channel's SAI has already sent, without waiting
+    /// for more.
+    pub async fn drain_events(&mut self, channel_id: &str) -> Vec<SaiEvent> {
+        let mut events = Vec::new();
+        if let Some(conn) = self.connections.get_mut(channel_id) {
+            events.extend(conn.held.drain(..));
+            loop {
+                // Use tokio::time::timeout for a quick check
+                match tokio::time::timeout(
+                    tokio::time::Duration::from_millis(1),
+                    conn.next_event(),
+                )
+                .await
+                {
+                    Ok(Some(event)) => events.push(event),
+                    Ok(None) => {
+                        // EOF — SAI disconnected
+                        tracing::warn!("SAI disconnected for {}", channel_id);
+                        break;
+                    }
+                    Err(_) => break, // timeout — no more events
+                }
+            }
+        }
+        events
+    }
+
+    /// In chaos mode, cut the connections that have lasted long enough.
+    /// Returns their channel ids; their bridges will reconnect.
+    pub fn inject_disconnects(&mut self, now: Instant) -> Vec<String> {
+        let due: Vec<String> = self
+            .connections
+            .iter()
+            .filter(|(_, conn)| conn.chaos.as_ref().is_some_and(|chaos| chaos.disconnect_due(now)))
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in &due {
+            let Some(mut chaos) = self.connections.remove(id).and_then(|conn| conn.chaos) else { continue };
+            chaos.counts.disconnects += 1;
+            tracing::warn!("Chaos: disconnected the SAI bridge for {} ({} so far)", id, chaos.counts.disconnects);
+            self.chaos_counts.insert(id.clone(), chaos.counts);
+        }
+        due
+    }
+
+    /// What chaos mode did to a channel; None outside chaos mode.
+    pub fn chaos_counts(&self, channel_id: &str) -> Option<ChaosCounts> {
+        self.chaos.as_ref()?;
+        let live = self.connections.get(channel_id).and_then(|conn| conn.chaos.as_ref());
+        Some(live.map(|chaos| chaos.counts).or_else(|| self.chaos_counts.get(channel_id).copied()).unwrap_or_default())
+    }
+
+    /// Send the commands held back by pacing that may go by `now`.
+    pub async fn flush_paced(&mut self, now: Instant) {
+        for (channel_id, conn) in &mut self.connections {
+            if let Err(e) = conn.flush_paced(now).await {
+                tracing::warn!("Failed to send paced commands to {}: {}", channel_id, e);
+            }
+        }
+    }
+
+    /// Commands held back by pacing on a channel's connection.
+    pub fn pacing_counts(&self, channel_id: &str) -> Option<PacingCounts> {
+        self.connections.get(channel_id).map(|conn| conn.pacer.counts())
+    }
+
+    /// Traffic counters for a channel's SAI connection.
+    pub fn stats(&self, channel_id: &str) -> Option<&ChannelStats> {
+        self.connections.get(channel_id).map(|c| &c.stats)
+    }
+
+    /// Zero a channel's counters (for interval measurements).
+    pub fn reset_stats(&mut self, channel_id: &str) -> bool {
+        match self.connections.get_mut(channel_id) {
+            Some(conn) => {
+                conn.stats = ChannelStats::default();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Send a command to a specific channel's SAI, tagged with
+    /// `command_ref` for the bridge to echo.
     pub async fn send_to(
         &mut self,
         channel_id: &str,
         cmd: &SaiCommand,
-    ) -> Result<(), String> {
+        command_ref: u64,
+    ) -> Result<Paced, String> {
         let conn = self
             .connections
             .get_mut(channel_id)
             .ok_or_else(|| format!("No SAI connection for channel {}", channel_id))?;
-        conn.send_command(cmd)
+        conn.send_command(Tracked { command_ref, command: cmd.clone() })
             .await
             .map_err(|e| format!("Failed to send to SAI: {}", e))
     }
+
+    /// Have a channel's bridge validate `cmds` without executing them, and
+    /// wait up to `timeout` for its verdicts: one per command, in order,
+    /// `None` when the command would be dispatched, else the error it would
+    /// hit. Other events read meanwhile are held for the next drain.
+    pub async fn dry_run(
+        &mut self,
+        channel_id: &str,
+        cmds: &[SaiCommand],
+        timeout: Duration,
+    ) -> Result<Vec<Option<String>>, String> {
+        let conn = self
+            .connections
+            .get_mut(channel_id)
+            .ok_or_else(|| format!("No SAI connection for channel {}", channel_id))?;
+        if conn.protocol_version.is_none_or(|v| v < DRY_RUN_PROTOCOL) {
+            return Err(format!(
+                "The SAI bridge for {} doesn't support dry runs (protocol {:?}, needs {})",
+                channel_id, conn.protocol_version, DRY_RUN_PROTOCOL
+            ));
+        }
+        let first_id = self.next_request_id;
+        self.next_request_id += cmds.len() as u64;
+        for (request_id, cmd) in (first_id..).zip(cmds) {
+            conn.send_dry_run(&DryRun { request_id, command: cmd.clone() })
+                .await
+                .map_err(|e| format!("Failed to send to SAI: {}", e))?;
+        }
+
+        let mut verdicts = HashMap::new();
+        let deadline = tokio::time::Instant::now() + timeout;
+        while verdicts.len() < cmds.len() {
+            match tokio::time::timeout_at(deadline, conn.next_event()).await {
+                Ok(Some(SaiEvent::DryRunResult { request_id, error }))
+                    if (first_id..self.next_request_id).contains(&request_id) =>
+                {
+                    verdicts.insert(request_id, error);
+                }
+                Ok(Some(event)) => conn.held.push_back(event),
+                Ok(None) => return Err("The SAI bridge disconnected during the dry run".into()),
+                Err(_) => {
+                    return Err(format!(
+                        "No dry-run verdict from the SAI bridge within {}s (is the game paused?)",
+                        timeout.as_secs()
+                    ))
+                }
+            }
+        }
+        Ok((first_id..self.next_request_id).map(|id| verdicts.remove(&id).flatten()).collect())
+    }
+
+    /// Ask a channel's bridge for every unit def, and wait up to `timeout`
+    /// for all the chunks of its answer. Other events read meanwhile are
+    /// held for the next drain.
+    pub async fn query_unit_defs(&mut self, channel_id: &str, timeout: Duration) -> Result<Vec<UnitDefInfo>, String> {
+        let conn = self
+            .connections
+            .get_mut(channel_id)
+            .ok_or_else(|| format!("No SAI connection for channel {}", channel_id))?;
+        if conn.protocol_version.is_none_or(|v| v < UNIT_DEFS_PROTOCOL) {
+            return Err(format!(
+                "The SAI bridge for {} doesn't support unit def queries (protocol {:?}, needs {})",
+                channel_id, conn.protocol_version, UNIT_DEFS_PROTOCOL
+            ));
+        }
+        let request_id = self.next_request_id;
+        self.next_request_id += 1;
+        let query = serde_json::to_string(&SaiCommand::QueryUnitDefs { request_id }).unwrap();
+        conn.send_uncounted(&query).await.map_err(|e| format!("Failed to send to SAI: {}", e))?;
+
+        let mut defs = Vec::new();
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            match tokio::time::timeout_at(deadline, conn.next_event()).await {
+                Ok(Some(SaiEvent::UnitDefs { request_id: id, total, defs: chunk, .. })) if id == request_id => {
+                    defs.extend(chunk);
+                    if defs.len() >= total {
+                        return Ok(defs);
+                    }
+                }
+                Ok(Some(event)) => conn.held.push_back(event),
+                Ok(None) => return Err("The SAI bridge disconnected during the unit def query".into()),
+                Err(_) => {
+                    return Err(format!(
+                        "No unit defs from the SAI bridge within {}s (is the game paused?)",
+                        timeout.as_secs()
+                    ))
+                }
+            }
+        }
+    }
+
+    /// Sample a channel's map on a grid of `cell_size`-elmo cells (coarsened
+    /// by the bridge on large maps), with `build_def` buildability if given.
+    /// Events that arrive meanwhile are held for the next `next_event`.
+    pub async fn query_map_grid(
+        &mut self,
+        channel_id: &str,
+        cell_size: f32,
+        build_def: Option<&str>,
+        timeout: Duration,
+    ) -> Result<MapGrid, String> {
+        let conn = self
+            .connections
+            .get_mut(channel_id)
+            .ok_or_else(|| format!("No SAI connection for channel {}", channel_id))?;
+        if conn.protocol_version.is_none_or(|v| v < MAP_GRID_PROTOCOL) {
+            return Err(format!(
+                "The SAI bridge for {} doesn't support map grid queries (protocol {:?}, needs {})",
+                channel_id, conn.protocol_version, MAP_GRID_PROTOCOL
+            ));
+        }
+        let request_id = self.next_request_id;
+        self.next_request_id += 1;
+        let query = SaiCommand::QueryMapGrid { request_id, cell_size, build_def: build_def.map(String::from) };
+        conn.send_uncounted(&serde_json::to_string(&query).unwrap())
+            .await
+            .map_err(|e| format!("Failed to send to SAI: {}", e))?;
+
+        let mut grid: Option<MapGrid> = None;
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            match tokio::time::timeout_at(deadline, conn.next_event()).await {
+                Ok(Some(SaiEvent::MapGrid { request_id: id, error: Some(error), .. })) if id == request_id => {
+                    return Err(format!("Map grid query failed: {}", error));
+                }
+                Ok(Some(SaiEvent::MapGrid {
+                    request_id: id,
+                    cell_size: cell,
+                    width,
+                    height,
+                    row_offset,
+                    elevation,
+                    buildable,
+                    ..
+                })) if id == request_id => {
+                    let grid = grid.get_or_insert_with(|| {
+                        MapGrid::new(cell_size, build_def.map(String::from), cell, width, height)
+                    });
+                    grid.add_band(row_offset, elevation, buildable)?;
+                    if grid.is_complete() {
+                        return Ok(grid.clone());
+                    }
+                }
+                Ok(Some(event)) => conn.held.push_back(event),
+                Ok(None) => return Err("The SAI bridge disconnected during the map grid query".into()),
+                Err(_) => {
+                    return Err(format!(
+                        "No map grid from the SAI bridge within {}s (is the game paused?)",
+                        timeout.as_secs()
+                    ))
+                }
+            }
+        }
+    }
+
+    /// Ask a channel's bridge for `units`' command queues. The answer comes
+    /// as a `unit_queues` event with the returned request id.
+    pub async fn request_unit_queues(&mut self, channel_id: &str, units: Vec<UnitId>) -> Result<u64, String> {
+        let conn = self
+            .connections
+            .get_mut(channel_id)
+            .ok_or_else(|| format!("No SAI connection for channel {}", channel_id))?;
+        if conn.protocol_version.is_none_or(|v| v < UNIT_QUEUES_PROTOCOL) {
+            return Err(format!(
+                "The SAI bridge for {} doesn't support unit queue queries (protocol {:?}, needs {})",
+                channel_id, conn.protocol_version, UNIT_QUEUES_PROTOCOL
+            ));
+        }
+        let request_id = self.next_request_id;
+        self.next_request_id += 1;
+        let query = SaiCommand::QueryUnitQueues { request_id, units };
+        conn.send_uncounted(&serde_json::to_string(&query).unwrap())
+            .await
+            .map_err(|e| format!("Failed to send to SAI: {}", e))?;
+        Ok(request_id)
+    }
 }
 
-/// Convert a channels/publish content text into a SaiCommand.
-pub fn parse_publish_command(text: &str) -> Result<SaiCommand, String> {
-    serde_json::from_str(text).map_err(|e| format!("Invalid command JSON: {}", e))
+/// A command's type and the unit it addresses, e.g. "move (unit 12)".
+pub fn command_label(cmd: &SaiCommand) -> String {
+    let unit = serde_json::to_value(cmd).ok().and_then(|v| v.get("unit_id").and_then(|u| u.as_i64()));
+    match unit {
+        Some(unit) => format!("{} (unit {})", cmd.type_name(), unit),
+        None => cmd.type_name().to_string(),
+    }
 }
 
-/// Convert a SaiEvent into MCPL channels/incoming content.
-pub fn event_to_content(event: &SaiEvent) -> String {
-    serde_json::to_string(event).unwrap_or_else(|_| "{}".to_string())
+/// Convert a channels/publish content text into the SaiCommands it stands
+/// for: one, or one per member when it's addressed to a unit group. A
+/// `location` is resolved with the channel's `places` first, then a
+/// convenience command with the game's `profile`.
+pub fn parse_publish_command(
+    text: &str,
+    groups: Option<&UnitGroups>,
+    places: Option<&Places>,
+    profile: &GameProfile,
+) -> Result<Vec<SaiCommand>, String> {
+    let mut payload = serde_json::from_str(text).map_err(|e| format!("Invalid command JSON: {}", e))?;
+    match places {
+        Some(places) => places.resolve(&mut payload)?,
+        None => Places::default().resolve(&mut payload)?,
+    }
+    profile.resolve(&mut payload)?;
+    match groups {
+        Some(groups) => groups.expand(payload),
+        None => UnitGroups::default().expand(payload),
+    }
+}
+
+/// How events are rendered in channels/incoming text blocks.
+/// Selected per channel via `metadata.verbosity` on channels/open.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Verbosity {
+    /// One short line, no positions or secondary details.
+    Terse,
+    /// One sentence with names, ids and positions.
+    #[default]
+    Normal,
+    /// The event JSON as received from the bridge.
+    Raw,
+}
+
+impl Verbosity {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "terse" => Some(Verbosity::Terse),
+            "normal" => Some(Verbosity::Normal),
+            "raw" => Some(Verbosity::Raw),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Verbosity::Terse => "terse",
+            Verbosity::Normal => "normal",
+            Verbosity::Raw => "raw",
+        }
+    }
+}
+
+/// Default bounds for `game_set_speed`, overridable per channel with
+/// `metadata.min_speed` / `metadata.max_speed` on channels/open.
+pub const DEFAULT_MIN_SPEED: f32 = 0.1;
+pub const DEFAULT_MAX_SPEED: f32 = 10.0;
+
+/// Last-known pause/speed state of a game channel, as set through the
+/// game_pause / game_resume / game_set_speed tools and turn-mode updates,
+/// and as the bridge sees the engine change under players' hands.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GameControl {
+    pub paused: bool,
+    pub speed: f32,
+    pub min_speed: f32,
+    pub max_speed: f32,
+    /// Lockstep play, from `metadata.turn_mode` on channels/open. Sent to
+    /// the bridge when it reports init.
+    pub turn_mode: bool,
+    /// The bridge paused for a turn and waits for game_end_turn.
+    pub awaiting_turn: bool,
+}
+
+impl Default for GameControl {
+    fn default() -> Self {
+        Self {
+            paused: false,
+            speed: 1.0,
+            min_speed: DEFAULT_MIN_SPEED,
+            max_speed: DEFAULT_MAX_SPEED,
+            turn_mode: false,
+            awaiting_turn: false,
+        }
+    }
+}
+
+impl GameControl {
+    /// Read the speed range and turn mode from channels/open metadata.
+    pub fn from_metadata(metadata: Option<&serde_json::Value>) -> Result<Self, String> {
+        let bound = |key: &str, default: f32| -> Result<f32, String> {
+            match metadata.and_then(|m| m.get(key)) {
+                None => Ok(default),
+                Some(v) => v
+                    .as_f64()
+                    .map(|f| f as f32)
+                    .filter(|f| *f > 0.0)
+                    .ok_or_else(|| format!("{} must be a positive number", key)),
+            }
+        };
+        let turn_mode = match metadata.and_then(|m| m.get("turn_mode")) {
+            None => false,
+            Some(v) => v.as_bool().ok_or("turn_mode must be a boolean")?,
+        };
+        let control = Self {
+            min_speed: bound("min_speed", DEFAULT_MIN_SPEED)?,
+            max_speed: bound("max_speed", DEFAULT_MAX_SPEED)?,
+            turn_mode,
+            ..Self::default()
+        };
+        if control.min_speed > control.max_speed {
+            return Err(format!(
+                "min_speed {} is above max_speed {}",
+                control.min_speed, control.max_speed
+            ));
+        }
+        Ok(control)
+    }
+
+    /// Reject speeds outside the channel's configured range.
+    pub fn check_speed(&self, speed: f32) -> Result<(), String> {
+        if speed.is_nan() || speed < self.min_speed || speed > self.max_speed {
+            return Err(format!(
+                "Speed {} is outside the allowed range {}-{}",
+                speed, self.min_speed, self.max_speed
+            ));
+        }
+        Ok(())
+    }
+
+    /// Follow a pause or speed change the bridge saw in the engine. Returns
+    /// whether it told us anything new: our own turn pause was already
+    /// announced by its update, and an end_turn already counted as resumed.
+    pub fn observe(&mut self, event: &SaiEvent) -> bool {
+        let before = *self;
+        match event {
+            SaiEvent::GamePaused { .. } => self.paused = true,
+            SaiEvent::GameResumed => self.paused = false,
+            SaiEvent::SpeedChanged { speed } => self.speed = *speed,
+            _ => {}
+        }
+        *self != before
+    }
+
+    pub fn to_json(self) -> serde_json::Value {
+        serde_json::json!({
+            "paused": self.paused,
+            "speed": self.speed,
+            "minSpeed": self.min_speed,
+            "maxSpeed": self.max_speed,
+            "turnMode": self.turn_mode,
+            "awaitingTurn": self.awaiting_turn,
+        })
+    }
+}
+
+/// "cloakraid (#812)", or "unit #812" when the name wasn't resolved.
+/// A chat sender's name, or "Player N" when the bridge couldn't resolve it.
+pub fn player_label(player: i32, name: &Option<String>) -> String {
+    match name {
+        Some(n) => n.clone(),
+        None => format!("Player {}", player),
+    }
+}
+
+fn unit_label(name: &Option<String>, id: UnitId) -> String {
+    match name {
+        Some(n) => format!("{} (#{})", n, id),
+        None => format!("unit #{}", id),
+    }
+}
+
+/// "allied Ripper (#12)": a unit introduced by whose it is, `assumed`
+/// when the bridge didn't say (older bridges, units out of sight).
+fn whose_label(relation: &Option<Relation>, assumed: Relation, name: &Option<String>, id: UnitId) -> String {
+    format!("{} {}", relation.unwrap_or(assumed).adjective(), unit_label(name, id))
+}
+
+/// "allied team 2", or "team 2" when the bridge didn't say whose it is.
+fn team_label(relation: &Option<Relation>, team: TeamId) -> String {
+    match relation {
+        Some(relation) => format!("{} team {}", relation.adjective(), team),
+        None => format!("team {}", team),
+    }
+}
+
+/// `whose_label` at the start of a sentence.
+fn whose_label_capitalized(relation: &Option<Relation>, assumed: Relation, name: &Option<String>, id: UnitId) -> String {
+    let label = whose_label(relation, assumed, name, id);
+    let mut chars = label.chars();
+    chars.next().map(|c| c.to_ascii_uppercase().to_string() + chars.as_str()).unwrap_or_default()
+}
+
+/// " near (3428, 2011)", or placed on the map when the channel knows it.
+fn near(pos: &Option<[f32; 3]>, context: Option<&MapContext>) -> String {
+    match (pos, context) {
+        (Some(p), Some(context)) => format!(" {}", positions::describe(p[0], p[2], context)),
+        (Some(p), None) => format!(" near ({:.0}, {:.0})", p[0], p[2]),
+        (None, _) => String::new(),
+    }
+}
+
+/// A damage event's line. EMP hits with the unit's paralysis read as a stun
+/// in progress, not as damage: "Your Glaive (#12) is being stunned by
+/// enemy Bolas (#300): 42 paralyzer damage, 60% to disable".
+fn damage_text(unit: &str, damage: f32, paralyzer: bool, paralysis: &Option<Paralysis>, attacker: Option<String>) -> String {
+    let by = |word: &str| attacker.as_ref().map(|a| format!(" {} {}", word, a)).unwrap_or_default();
+    match paralysis {
+        Some(p) if paralyzer && p.stunned() => {
+            format!("{} is stunned{}: {:.0} paralyzer damage, disabled", unit, by("by"), damage)
+        }
+        Some(p) if paralyzer => format!(
+            "{} is being stunned{}: {:.0} paralyzer damage, {:.0}% to disable",
+            unit,
+            by("by"),
+            damage,
+            p.level() * 100.0
+        ),
+        _ => {
+            let kind = if paralyzer { "paralyzer damage" } else { "damage" };
+            format!("{} took {:.0} {}{}", unit, damage, kind, by("from"))
+        }
+    }
+}
+
+/// "unit #12: 14 weapon_fired, 2 command_finished; unit #7: ...", busiest
+/// unit first.
+fn counters_label(counters: &sai_protocol::UnitCounters) -> String {
+    let mut units: Vec<_> = counters.iter().map(|(unit, counts)| (counts.values().sum::<u32>(), unit, counts)).collect();
+    units.sort_by_key(|u| std::cmp::Reverse(u.0));
+    units
+        .iter()
+        .map(|(_, unit, counts)| {
+            let counts: Vec<String> = counts.iter().map(|(event, n)| format!("{} {}", n, event)).collect();
+            format!("unit #{}: {}", unit, counts.join(", "))
+        })
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// Units named individually in a roster summary; the rest are counted.
+const ROSTER_LISTED: usize = 10;
+
+/// Render an event as a short English sentence for the agent.
+/// Positions are raw coordinates.
+pub fn summarize_event(event: &SaiEvent) -> String {
+    summarize(event, false, None)
+}
+
+fn summarize(event: &SaiEvent, terse: bool, context: Option<&MapContext>) -> String {
+    let near = |pos: &Option<[f32; 3]>| if terse { String::new() } else { near(pos, context) };
+    match event {
+        SaiEvent::Init { metal_spots, map_width, map_height, .. } => {
+            let mut s = "Game initialized".to_string();
+            if let (Some(w), Some(h)) = (map_width, map_height) {
+                s += &format!(": map {}x{}", w, h);
+            }
+            if let Some(spots) = metal_spots {
+                s += &format!(", {} metal spots", spots.len());
+            }
+            s
+        }
+        SaiEvent::Release { reason, stats: None } => format!("AI released (reason {})", reason),
+        SaiEvent::Release { reason, stats: Some(stats) } => format!(
+            "AI released (reason {}) after {} frames: {} events sent, {} dropped, {} panics",
+            reason, stats.frames, stats.events_sent, stats.events_dropped, stats.panics
+        ),
+        SaiEvent::Update { frame, awaiting_commands, counters, command_backlog, under_construction, .. } => {
+            let mut s = if *awaiting_commands {
+                format!("Turn at frame {}: game paused, call game_end_turn when done", frame)
+            } else {
+                format!("Frame {}", frame)
+            };
+            if !counters.is_empty() {
+                s += &if terse {
+                    format!(" ({} units busy)", counters.len())
+                } else {
+                    format!(". Since the last update: {}", counters_label(counters))
+                };
+            }
+            if *command_backlog > 0 {
+                s += &format!(". {} commands still queued in the bridge", command_backlog);
+            }
+            if !under_construction.is_empty() {
+                s += &if terse {
+                    format!(". {} under construction", under_construction.len())
+                } else {
+                    let sites: Vec<String> = under_construction
+                        .iter()
+                        .map(|c| format!("{} {}%", unit_label(&c.unit_name, c.unit), c.progress))
+                        .collect();
+                    format!(". Under construction: {}", sites.join(", "))
+                };
+            }
+            s
+        }
+        SaiEvent::Message { player, player_name, text, .. } => {
+            format!("{} says: {}", player_label(*player, player_name), text)
+        }
+        SaiEvent::UnitCreated { unit, unit_name, builder, builder_name, pos } => {
+            let mut s = format!("Your {} started construction{}", unit_label(unit_name, *unit), near(pos));
+            if builder.0 > 0 && !terse {
+                s += &format!(", built by {}", unit_label(builder_name, *builder));
+            }
+            s
+        }
+        SaiEvent::ConstructionStalled { unit, unit_name, progress, builders } => {
+            let mut s = format!("Construction of your {} stalled at {}%", unit_label(unit_name, *unit), progress);
+            if !terse {
+                s += &format!(
+                    " with {} builder{} on it (low resources, or builders that can't reach it?)",
+                    builders,
+                    if *builders == 1 { "" } else { "s" }
+                );
+            }
+            s
+        }
+        SaiEvent::UnitFinished { unit, unit_name, pos } => {
+            format!("Your {} is finished{}", unit_label(unit_name, *unit), near(pos))
+        }
+        SaiEvent::UnitIdle { unit, unit_name } => {
+            format!("Your {} is idle", unit_label(unit_name, *unit))
+        }
+        SaiEvent::UnitMoveFailed { unit, unit_name } => {
+            format!("Your {} could not reach its destination", unit_label(unit_name, *unit))
+        }
+        SaiEvent::UnitDamaged {
+            unit, unit_name, attacker, attacker_name, attacker_relation, damage, paralyzer, paralysis, ..
+        } => {
+            let attacker = (attacker.0 > 0 && !terse)
+                .then(|| whose_label(attacker_relation, Relation::Enemy, attacker_name, *attacker));
+            let unit = format!("Your {}", unit_label(unit_name, *unit));
+            damage_text(&unit, *damage, *paralyzer, paralysis, attacker)
+        }
+        SaiEvent::UnitDestroyed { unit, unit_name, attacker, attacker_name, attacker_relation, .. } => {
+            let mut s = format!("Your {} was destroyed", unit_label(unit_name, *unit));
+            if attacker.0 > 0 {
+                s += &format!(" by {}", whose_label(attacker_relation, Relation::Enemy, attacker_name, *attacker));
+            }
+            s
+        }
+        SaiEvent::UnitGiven { unit, unit_name, old_team, old_relation, pos, .. } => {
+            let from = team_label(old_relation, *old_team);
+            match old_relation {
+                Some(Relation::Enemy) => format!("You captured {} from {}{}", unit_label(unit_name, *unit), from, near(pos)),
+                _ => format!("You received {} from {}{}", unit_label(unit_name, *unit), from, near(pos)),
+            }
+        }
+        SaiEvent::UnitCaptured { unit, unit_name, new_team, new_relation, .. } => {
+            let to = team_label(new_relation, *new_team);
+            match new_relation {
+                Some(Relation::Enemy) => format!("Your {} was captured by {}", unit_label(unit_name, *unit), to),
+                _ => format!("Your {} was given to {}", unit_label(unit_name, *unit), to),
+            }
+        }
+        SaiEvent::EnemyEnterLos { enemy, enemy_name, relation, pos, .. } => {
+            format!("{} spotted{}", whose_label_capitalized(relation, Relation::Enemy, enemy_name, *enemy), near(pos))
+        }
+        SaiEvent::EnemyLeaveLos { enemy, enemy_name, relation, .. } => {
+            format!("{} left line of sight", whose_label_capitalized(relation, Relation::Enemy, enemy_name, *enemy))
+        }
+        SaiEvent::EnemyEnterRadar { enemy, enemy_name, relation, .. } => {
+            format!("Radar contact: {}", whose_label(relation, Relation::Enemy, enemy_name, *enemy))
+        }
+        SaiEvent::EnemyLeaveRadar { enemy, enemy_name, relation, .. } => {
+            format!("Lost radar contact with {}", whose_label(relation, Relation::Enemy, enemy_name, *enemy))
+        }
+        SaiEvent::EnemyDamaged {
+            enemy, enemy_name, relation, attacker, attacker_name, attacker_relation, damage, paralyzer, paralysis, ..
+        } => {
+            let attacker = (attacker.0 > 0 && !terse)
+                .then(|| whose_label(attacker_relation, Relation::Mine, attacker_name, *attacker));
+            let enemy = whose_label_capitalized(relation, Relation::Enemy, enemy_name, *enemy);
+            damage_text(&enemy, *damage, *paralyzer, paralysis, attacker)
+        }
+        SaiEvent::EnemyDestroyed { enemy, enemy_name, relation, attacker, attacker_name, attacker_relation, .. } => {
+            let enemy = whose_label_capitalized(relation, Relation::Enemy, enemy_name, *enemy);
+            let mut s = format!("{} was destroyed", enemy);
+            if attacker.0 > 0 {
+                s += &format!(" by {}", whose_label(attacker_relation, Relation::Mine, attacker_name, *attacker));
+            }
+            s
+        }
+        SaiEvent::EnemyCreated { enemy, enemy_name, relation, .. } => {
+            format!("{} started construction", whose_label_capitalized(relation, Relation::Enemy, enemy_name, *enemy))
+        }
+        SaiEvent::EnemyFinished { enemy, enemy_name, relation, .. } => {
+            format!("{} is finished", whose_label_capitalized(relation, Relation::Enemy, enemy_name, *enemy))
+        }
+        SaiEvent::WeaponFired { unit, unit_name, weapon_def_id } => {
+            format!("Your {} fired weapon {}", unit_label(unit_name, *unit), weapon_def_id)
+        }
+        SaiEvent::CommandFinished { unit, unit_name, command_id, command_topic, .. } => {
+            if terse {
+                format!("Your {} finished a command", unit_label(unit_name, *unit))
+            } else {
+                format!(
+                    "Your {} finished command {} (topic {})",
+                    unit_label(unit_name, *unit), command_id, command_topic
+                )
+            }
+        }
+        SaiEvent::Roster { units, .. } => {
+            if units.is_empty() {
+                return "Game started: you control no units yet".into();
+            }
+            let listed: Vec<String> = units
+                .iter()
+                .take(ROSTER_LISTED)
+                .map(|u| format!("{}{}", unit_label(&u.unit_name, u.unit), near(&Some(u.pos))))
+                .collect();
+            let mut s = format!(
+                "Game started: you control {} unit{}: {}",
+                units.len(),
+                if units.len() == 1 { "" } else { "s" },
+                listed.join(", ")
+            );
+            if units.len() > ROSTER_LISTED {
+                s += &format!(", and {} more", units.len() - ROSTER_LISTED);
+            }
+            s
+        }
+        SaiEvent::LuaMessage { data, .. } => format!("Lua message: {}", data),
+        SaiEvent::CommandError { error, command, .. } => {
+            if terse {
+                format!("Command failed: {}", error)
+            } else {
+                format!("Command failed: {} ({})", error, command)
+            }
+        }
+        SaiEvent::MalformedEvent { topic, reason } => {
+            format!("The bridge dropped a malformed engine event (topic {}): {}", topic, reason)
+        }
+        SaiEvent::DryRunResult { request_id, error: None } => {
+            format!("Dry run {}: the command would be accepted", request_id)
+        }
+        SaiEvent::DryRunResult { request_id, error: Some(error) } => {
+            format!("Dry run {}: the command would fail: {}", request_id, error)
+        }
+        SaiEvent::UnitDefs { offset, total, defs, .. } => {
+            format!("Unit defs {}-{} of {}", offset + 1, offset + defs.len(), total)
+        }
+        SaiEvent::MapGrid { error: Some(error), .. } => format!("Map grid query failed: {}", error),
+        SaiEvent::MapGrid { width, height, row_offset, elevation, .. } => format!(
+            "Map grid rows {}-{} of {} ({} cells across)",
+            row_offset + 1,
+            row_offset + elevation.len(),
+            height,
+            width
+        ),
+        SaiEvent::UnitQueues { queues, .. } => format!("Command queues of {} units", queues.len()),
+        SaiEvent::GamePaused { by_us: true } => "Game paused for the turn".to_string(),
+        SaiEvent::GamePaused { by_us: false } => "Game paused by a player".to_string(),
+        SaiEvent::GameResumed => "Game resumed".to_string(),
+        SaiEvent::SpeedChanged { speed } => format!("Game speed changed to {}", speed),
+        SaiEvent::Unknown { .. } => format!(
+            "Unrecognized event type '{}' (newer SAI bridge?)",
+            event.unknown_type().unwrap_or("?")
+        ),
+    }
+}
+
+/// Convert a SaiEvent into MCPL channels/incoming text content, placing
+/// positions with the channel's `context`.
+pub fn event_to_content(event: &SaiEvent, verbosity: Verbosity, context: Option<&MapContext>) -> String {
+    match (verbosity, event) {
+        (Verbosity::Raw, SaiEvent::Unknown { raw }) => raw.to_string(),
+        (Verbosity::Raw, _) => serde_json::to_string(event).unwrap_or_else(|_| "{}".to_string()),
+        (Verbosity::Terse, _) => summarize(event, true, context),
+        (Verbosity::Normal, _) => summarize(event, false, context),
+    }
+}
+
+/// Metadata for channels/incoming: the structured event, so clients can
+/// act on exact ids and coordinates regardless of the text rendering.
+/// Unknown events carry the payload exactly as the bridge sent it.
+pub fn event_metadata(event: &SaiEvent) -> Option<serde_json::Value> {
+    let payload = match event {
+        SaiEvent::Unknown { raw } => raw.clone(),
+        _ => serde_json::to_value(event).ok()?,
+    };
+    Some(serde_json::json!({ "event": payload }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sai_protocol::{TeamId, WeaponDefId};
+
+    #[test]
+    fn test_game_control_speed_range() {
+        let control = GameControl::from_metadata(None).unwrap();
+        assert_eq!(control, GameControl::default());
+        assert!(control.check_speed(1.0).is_ok());
+        assert!(control.check_speed(DEFAULT_MAX_SPEED).is_ok());
+        assert!(control.check_speed(0.0).is_err());
+        assert!(control.check_speed(f32::NAN).is_err());
+
+        let meta = serde_json::json!({"min_speed": 0.5, "max_speed": 3});
+        let control = GameControl::from_metadata(Some(&meta)).unwrap();
+        assert_eq!((control.min_speed, control.max_speed), (0.5, 3.0));
+        assert_eq!(
+            control.check_speed(5.0).unwrap_err(),
+            "Speed 5 is outside the allowed range 0.5-3"
+        );
+
+        let inverted = serde_json::json!({"min_speed": 4, "max_speed": 2});
+        assert!(GameControl::from_metadata(Some(&inverted)).is_err());
+        let bogus = serde_json::json!({"max_speed": "fast"});
+        assert!(GameControl::from_metadata(Some(&bogus)).is_err());
+
+        let turns = serde_json::json!({"turn_mode": true});
+        assert!(GameControl::from_metadata(Some(&turns)).unwrap().turn_mode);
+        let bogus = serde_json::json!({"turn_mode": "yes"});
+        assert!(GameControl::from_metadata(Some(&bogus)).is_err());
+    }
+
+    #[test]
+    fn test_game_control_follows_the_engine() {
+        let mut control = GameControl { paused: true, awaiting_turn: true, ..GameControl::default() };
+        assert!(!control.observe(&SaiEvent::GamePaused { by_us: true }));
+        control.paused = false;
+        assert!(control.observe(&SaiEvent::GamePaused { by_us: false }));
+        assert!(control.observe(&SaiEvent::GameResumed) && !control.paused);
+        assert!(!control.observe(&SaiEvent::GameResumed));
+        assert!(control.observe(&SaiEvent::SpeedChanged { speed: 3.0 }));
+        assert_eq!(control.speed, 3.0);
+        assert_eq!(summarize(&SaiEvent::GamePaused { by_us: false }, false, None), "Game paused by a player");
+    }
+
+    #[test]
+    fn test_summarize_unit_destroyed() {
+        let event = SaiEvent::UnitDestroyed {
+            unit: UnitId(812),
+            unit_name: Some("cloakassault".into()),
+            attacker: UnitId(77),
+            attacker_name: Some("cloakraid".into()),
+            attacker_team: None,
+            attacker_relation: None,
+            weapon_def_id: WeaponDefId(3),
+        };
+        assert_eq!(
+            summarize_event(&event),
+            "Your cloakassault (#812) was destroyed by enemy cloakraid (#77)"
+        );
+    }
+
+    #[test]
+    fn test_summarize_relations() {
+        let killed = SaiEvent::EnemyDestroyed {
+            enemy: UnitId(900),
+            enemy_name: Some("cloakraid".into()),
+            team: Some(TeamId(2)),
+            relation: Some(Relation::Enemy),
+            attacker: UnitId(40),
+            attacker_name: Some("cloakriot".into()),
+            attacker_team: Some(TeamId(1)),
+            attacker_relation: Some(Relation::Ally),
+        };
+        assert_eq!(summarize_event(&killed), "Enemy cloakraid (#900) was destroyed by allied cloakriot (#40)");
+        let critter = SaiEvent::EnemyEnterLos {
+            enemy: UnitId(950),
+            enemy_name: Some("chicken_dodo".into()),
+            team: Some(TeamId(3)),
+            relation: Some(Relation::Gaia),
+            pos: None,
+        };
+        assert_eq!(summarize_event(&critter), "Neutral chicken_dodo (#950) spotted");
+        let hit = SaiEvent::UnitDamaged {
+            unit: UnitId(12),
+            unit_name: Some("cloakraid".into()),
+            attacker: UnitId(41),
+            attacker_name: Some("cloakriot".into()),
+            attacker_team: Some(TeamId(1)),
+            attacker_relation: Some(Relation::Ally),
+            damage: 20.0,
+            weapon_def_id: WeaponDefId(1),
+            paralyzer: false,
+            paralysis: None,
+            pos: None,
+        };
+        assert_eq!(summarize_event(&hit), "Your cloakraid (#12) took 20 damage from allied cloakriot (#41)");
+    }
+
+    #[test]
+    fn test_summarize_counters() {
+        let counters: sai_protocol::UnitCounters = [
+            ("7".to_string(), [("command_finished".to_string(), 1)].into()),
+            ("12".to_string(), [("command_finished".to_string(), 2), ("weapon_fired".to_string(), 14)].into()),
+        ]
+        .into();
+        let update = SaiEvent::Update { frame: 90, awaiting_commands: false, economy: None, allies: Vec::new(), counters, command_backlog: 0, under_construction: Vec::new(), update_interval: None };
+        assert_eq!(
+            summarize_event(&update),
+            "Frame 90. Since the last update: unit #12: 2 command_finished, 14 weapon_fired; unit #7: 1 command_finished"
+        );
+        assert_eq!(summarize(&update, true, None), "Frame 90 (2 units busy)");
+    }
+
+    #[test]
+    fn test_summarize_construction() {
+        let fusion = |progress, builders| sai_protocol::Construction {
+            unit: UnitId(30),
+            unit_name: Some("energyfusion".into()),
+            progress,
+            builders,
+        };
+        let update = SaiEvent::Update {
+            frame: 900,
+            awaiting_commands: true,
+            economy: None,
+            allies: Vec::new(),
+            counters: Default::default(),
+            command_backlog: 0,
+            under_construction: vec![fusion(42.5, 2), sai_protocol::Construction { unit: UnitId(31), unit_name: None, ..fusion(5.0, 0) }],
+            update_interval: None,
+        };
+        assert_eq!(
+            summarize_event(&update),
+            "Turn at frame 900: game paused, call game_end_turn when done. Under construction: energyfusion (#30) 42.5%, unit #31 5%"
+        );
+        assert!(summarize(&update, true, None).ends_with(". 2 under construction"));
+
+        let stalled = SaiEvent::ConstructionStalled { unit: UnitId(30), unit_name: Some("energyfusion".into()), progress: 42.5, builders: 1 };
+        assert_eq!(
+            summarize_event(&stalled),
+            "Construction of your energyfusion (#30) stalled at 42.5% with 1 builder on it (low resources, or builders that can't reach it?)"
+        );
+        assert_eq!(summarize(&stalled, true, None), "Construction of your energyfusion (#30) stalled at 42.5%");
+    }
+
+    #[test]
+    fn test_summarize_unresolved_names() {
+        let event = SaiEvent::UnitDestroyed {
+            unit: UnitId(5),
+            unit_name: None,
+            attacker: UnitId(-1),
+            attacker_name: None,
+            attacker_team: None,
+            attacker_relation: None,
+            weapon_def_id: WeaponDefId(-1),
+        };
+        assert_eq!(summarize_event(&event), "Your unit #5 was destroyed");
+    }
+
+    #[test]
+    fn test_summarize_transfers() {
+        let given = |relation| SaiEvent::UnitGiven {
+            unit: UnitId(41), unit_name: Some("cloakcon".into()), old_team: TeamId(2), new_team: TeamId(0),
+            old_relation: relation, pos: Some([600.0, 20.0, 800.0]),
+        };
+        assert_eq!(summarize_event(&given(Some(Relation::Ally))), "You received cloakcon (#41) from allied team 2 near (600, 800)");
+        assert_eq!(summarize_event(&given(Some(Relation::Enemy))), "You captured cloakcon (#41) from enemy team 2 near (600, 800)");
+        assert_eq!(summarize(&given(None), true, None), "You received cloakcon (#41) from team 2");
+        let captured = |relation| SaiEvent::UnitCaptured {
+            unit: UnitId(41), unit_name: None, old_team: TeamId(0), new_team: TeamId(1), new_relation: relation,
+        };
+        assert_eq!(summarize_event(&captured(Some(Relation::Enemy))), "Your unit #41 was captured by enemy team 1");
+        assert_eq!(summarize_event(&captured(Some(Relation::Ally))), "Your unit #41 was given to allied team 1");
+    }
+
+    #[test]
+    fn test_summarize_positions() {
+        let event = SaiEvent::EnemyEnterLos {
+            enemy: UnitId(901),
+            enemy_name: Some("vehraid".into()),
+            team: None,
+            relation: None,
+            pos: Some([3427.5, 88.2, 2011.0]),
+        };
+        assert_eq!(summarize_event(&event), "Enemy vehraid (#901) spotted near (3428, 2011)");
+        assert_eq!(
+            event_to_content(&event, Verbosity::Terse, None),
+            "Enemy vehraid (#901) spotted"
+        );
+
+        let mut places = Places::default();
+        places.set(crate::locations::START, crate::locations::Place { x: 1500.0, z: 3500.0 }).unwrap();
+        assert_eq!(
+            event_to_content(&event, Verbosity::Normal, Some(&places.context())),
+            "Enemy vehraid (#901) spotted 2400 elmos north-east of your base"
+        );
+        assert_eq!(event_to_content(&event, Verbosity::Terse, Some(&places.context())), "Enemy vehraid (#901) spotted");
+    }
+
+    #[test]
+    fn test_summarize_unit_created_and_damaged() {
+        let created = SaiEvent::UnitCreated {
+            unit: UnitId(20),
+            unit_name: Some("staticmex".into()),
+            builder: UnitId(1),
+            builder_name: Some("dyntrainer_strike".into()),
+            pos: Some([1000.0, 50.0, 1500.0]),
+        };
+        assert_eq!(
+            summarize_event(&created),
+            "Your staticmex (#20) started construction near (1000, 1500), built by dyntrainer_strike (#1)"
+        );
+
+        let damaged = SaiEvent::UnitDamaged {
+            unit: UnitId(20),
+            unit_name: Some("staticmex".into()),
+            attacker: UnitId(300),
+            attacker_name: Some("spiderscout".into()),
+            attacker_team: None,
+            attacker_relation: None,
+            damage: 42.4,
+            weapon_def_id: WeaponDefId(9),
+            paralyzer: true,
+            paralysis: None,
+            pos: None,
+        };
+        assert_eq!(
+            summarize_event(&damaged),
+            "Your staticmex (#20) took 42 paralyzer damage from enemy spiderscout (#300)"
+        );
+        assert_eq!(
+            event_to_content(&damaged, Verbosity::Terse, None),
+            "Your staticmex (#20) took 42 paralyzer damage"
+        );
+
+        // With the unit's paralysis, EMP reads as a stun in progress.
+        let stunning = match damaged.clone() {
+            SaiEvent::UnitDamaged { unit, unit_name, attacker, attacker_name, damage, weapon_def_id, .. } => {
+                SaiEvent::UnitDamaged {
+                    unit, unit_name, attacker, attacker_name, attacker_team: None, attacker_relation: None,
+                    damage, weapon_def_id, paralyzer: true,
+                    paralysis: Some(Paralysis { paralyze_damage: 1200.0, health: 2000.0, max_health: 2000.0 }),
+                    pos: None,
+                }
+            }
+            _ => unreachable!(),
+        };
+        assert_eq!(
+            summarize_event(&stunning),
+            "Your staticmex (#20) is being stunned by enemy spiderscout (#300): 42 paralyzer damage, 60% to disable"
+        );
+        let stunned = SaiEvent::EnemyDamaged {
+            enemy: UnitId(300),
+            enemy_name: Some("spiderscout".into()),
+            team: None,
+            relation: None,
+            attacker: UnitId(20),
+            attacker_name: Some("turretemp".into()),
+            attacker_team: None,
+            attacker_relation: None,
+            damage: 300.0,
+            weapon_def_id: WeaponDefId(9),
+            paralyzer: true,
+            paralysis: Some(Paralysis { paralyze_damage: 950.0, health: 700.0, max_health: 700.0 }),
+        };
+        assert_eq!(
+            event_to_content(&stunned, Verbosity::Terse, None),
+            "Enemy spiderscout (#300) is stunned: 300 paralyzer damage, disabled"
+        );
+    }
+
+    #[test]
+    fn test_summarize_init_and_errors() {
+        let init = SaiEvent::Init {
+            frame: 0,
+            saved_game: false,
+            protocol_version: Some(PROTOCOL_VERSION),
+            metal_spots: Some(vec![]),
+            map_width: Some(1024),
+            map_height: Some(512),
+            start_pos: None,
+            teams: Vec::new(),
+            build: None,
+        };
+        assert_eq!(summarize_event(&init), "Game initialized: map 1024x512, 0 metal spots");
+
+        let err = SaiEvent::CommandError {
+            error: "unit 4 does not exist".into(),
+            command: "Stop { unit_id: 4 }".into(),
+            command_ref: None,
+        };
+        assert_eq!(
+            summarize_event(&err),
+            "Command failed: unit 4 does not exist (Stop { unit_id: 4 })"
+        );
+
+        let stats = sai_protocol::BridgeStats { frames: 9000, events_sent: 412, events_dropped: 3, panics: 0 };
+        assert_eq!(
+            summarize_event(&SaiEvent::Release { reason: 2, stats: Some(stats) }),
+            "AI released (reason 2) after 9000 frames: 412 events sent, 3 dropped, 0 panics"
+        );
+    }
+
+    #[test]
+    fn test_raw_verbosity_and_metadata() {
+        let event = SaiEvent::UnitIdle { unit: UnitId(7), unit_name: None };
+        assert_eq!(event_to_content(&event, Verbosity::Raw, None), r#"{"type":"unit_idle","unit":7}"#);
+        assert_eq!(
+            event_metadata(&event),
+            Some(serde_json::json!({ "event": { "type": "unit_idle", "unit": 7 } }))
+        );
+
+        let unknown = SaiEvent::from_line(r#"{"type":"future_thing","x":1}"#).unwrap();
+        assert_eq!(
+            summarize_event(&unknown),
+            "Unrecognized event type 'future_thing' (newer SAI bridge?)"
+        );
+        assert_eq!(
+            event_metadata(&unknown),
+            Some(serde_json::json!({ "event": { "type": "future_thing", "x": 1 } }))
+        );
+    }
+
+    #[test]
+    fn test_channel_stats_counters() {
+        let mut stats = ChannelStats::default();
+        stats.record_event(&SaiEvent::Update { frame: 300, awaiting_commands: false, economy: None, allies: Vec::new(), counters: Default::default(), command_backlog: 0, under_construction: Vec::new(), update_interval: None }, 30);
+        stats.record_event(&SaiEvent::UnitIdle { unit: UnitId(1), unit_name: None }, 30);
+        stats.record_event(&SaiEvent::UnitIdle { unit: UnitId(2), unit_name: None }, 30);
+        stats.record_event(&SaiEvent::from_line(r#"{"type":"future_thing"}"#).unwrap(), 25);
+        stats.record_command(&SaiCommand::Stop { unit_id: UnitId(1) }, 30);
+
+        assert_eq!(stats.events_total(), 4);
+        assert_eq!(stats.events_by_type["unit_idle"], 2);
+        assert_eq!(stats.unknown_events["future_thing"], 1);
+        assert_eq!(stats.commands_by_type["stop"], 1);
+        assert_eq!(stats.bytes_in, 115);
+        assert_eq!(stats.bytes_out, 30);
+        assert_eq!(stats.last_frame, Some(300));
+        assert!(stats.last_event_at.is_some());
+    }
+
+    // ── Loopback: GameManager server ⇄ real bridge client ──
+
+    /// One representative value per event variant, with every optional
+    /// field populated so nothing is silently skipped on the wire.
+    fn sample_events() -> Vec<SaiEvent> {
+        let name = |s: &str| Some(s.to_string());
+        vec![
+            SaiEvent::Init {
+                frame: 0,
+                saved_game: false,
+                protocol_version: Some(PROTOCOL_VERSION),
+                metal_spots: Some(vec![sai_protocol::MetalSpot {
+                    x: 312.0,
+                    y: 40.5,
+                    z: 1880.0,
+                    metal: 2.1,
+                }]),
+                map_width: Some(1024),
+                map_height: Some(768),
+                start_pos: None,
+                teams: Vec::new(),
+                build: None,
+            },
+            SaiEvent::Release {
+                reason: 1,
+                stats: Some(sai_protocol::BridgeStats { frames: 9000, events_sent: 412, events_dropped: 3, panics: 0 }),
+            },
+            SaiEvent::Update {
+                frame: 900,
+                awaiting_commands: true,
+                economy: Some(sai_protocol::Economy {
+                    metal: sai_protocol::ResourceState { current: 210.0, income: 6.2, usage: 5.0, storage: 500.0, received: 0.0 },
+                    energy: sai_protocol::ResourceState::default(),
+                }),
+                allies: Vec::new(),
+                counters: [("12".to_string(), [("weapon_fired".to_string(), 14)].into())].into(),
+                command_backlog: 40,
+                under_construction: vec![sai_protocol::Construction {
+                    unit: UnitId(30),
+                    unit_name: name("energyfusion"),
+                    progress: 42.5,
+                    builders: 2,
+                }],
+                update_interval: Some(15),
+            },
+            SaiEvent::ConstructionStalled { unit: UnitId(30), unit_name: name("energyfusion"), progress: 42.5, builders: 2 },
+            SaiEvent::Message {
+                player: 2,
+                player_name: name("Godde"),
+                text: "gl hf".into(),
+                text_lossy: false,
+            },
+            SaiEvent::UnitCreated {
+                unit: UnitId(12),
+                unit_name: name("cloakraid"),
+                builder: UnitId(3),
+                builder_name: name("factorycloak"),
+                pos: Some([100.0, 12.5, 220.25]),
+            },
+            SaiEvent::UnitFinished {
+                unit: UnitId(12),
+                unit_name: name("cloakraid"),
+                pos: Some([101.0, 12.0, 221.0]),
+            },
+            SaiEvent::UnitIdle {
+                unit: UnitId(12),
+                unit_name: name("cloakraid"),
+            },
+            SaiEvent::UnitMoveFailed {
+                unit: UnitId(12),
+                unit_name: name("cloakraid"),
+            },
+            SaiEvent::UnitDamaged {
+                unit: UnitId(12),
+                unit_name: name("cloakraid"),
+                attacker: UnitId(900),
+                attacker_name: name("shieldraid"),
+                attacker_team: None,
+                attacker_relation: None,
+                damage: 37.5,
+                weapon_def_id: WeaponDefId(14),
+                paralyzer: false,
+                paralysis: None,
+                pos: Some([640.0, 12.0, 880.0]),
+            },
+            SaiEvent::UnitDestroyed {
+                unit: UnitId(12),
+                unit_name: name("cloakraid"),
+                attacker: UnitId(900),
+                attacker_name: name("shieldraid"),
+                attacker_team: None,
+                attacker_relation: None,
+                weapon_def_id: WeaponDefId(14),
+            },
+            SaiEvent::UnitGiven {
+                unit: UnitId(40),
+                unit_name: name("staticmex"),
+                old_team: TeamId(1),
+                new_team: TeamId(0),
+                old_relation: Some(Relation::Ally),
+                pos: Some([600.0, 20.0, 800.0]),
+            },
+            SaiEvent::UnitCaptured {
+                unit: UnitId(41),
+                unit_name: name("vehcapture"),
+                old_team: TeamId(0),
+                new_team: TeamId(1),
+                new_relation: None,
+            },
+            SaiEvent::EnemyEnterLos {
+                enemy: UnitId(900),
+                enemy_name: name("shieldraid"),
+                team: None,
+                relation: None,
+                pos: Some([640.0, 30.0, 512.0]),
+            },
+            SaiEvent::EnemyLeaveLos {
+                enemy: UnitId(900),
+                enemy_name: name("shieldraid"),
+                team: None,
+                relation: None,
+            },
+            SaiEvent::EnemyEnterRadar {
+                enemy: UnitId(901),
+                enemy_name: None,
+                team: None,
+                relation: None,
+            },
+            SaiEvent::EnemyLeaveRadar {
+                enemy: UnitId(901),
+                enemy_name: None,
+                team: None,
+                relation: None,
+            },
+            SaiEvent::EnemyDamaged {
+                enemy: UnitId(900),
+                enemy_name: name("shieldraid"),
+                team: None,
+                relation: None,
+                attacker: UnitId(12),
+                attacker_name: name("cloakraid"),
+                attacker_team: None,
+                attacker_relation: None,
+                damage: 0.5,
+                weapon_def_id: WeaponDefId(7),
+                paralyzer: true,
+                paralysis: None,
+            },
+            SaiEvent::EnemyDestroyed {
+                enemy: UnitId(900),
+                enemy_name: name("shieldraid"),
+                team: None,
+                relation: None,
+                attacker: UnitId(12),
+                attacker_name: name("cloakraid"),
+                attacker_team: None,
+                attacker_relation: None,
+            },
+            SaiEvent::EnemyCreated {
+                enemy: UnitId(902),
+                enemy_name: name("shieldcon"),
+                team: None,
+                relation: None,
+            },
+            SaiEvent::EnemyFinished {
+                enemy: UnitId(902),
+                enemy_name: name("shieldcon"),
+                team: None,
+                relation: None,
+            },
+            SaiEvent::WeaponFired {
+                unit: UnitId(12),
+                unit_name: name("cloakraid"),
+                weapon_def_id: WeaponDefId(7),
+            },
+            SaiEvent::CommandFinished {
+                unit: UnitId(12),
+                unit_name: name("cloakraid"),
+                command_id: 10,
+                command_topic: 10,
+                command_ref: Some(3),
+            },
+            SaiEvent::LuaMessage {
+                data: "{\"widget\":\"ping\"}".into(),
+                data_lossy: true,
+            },
+            SaiEvent::Roster {
+                frame: 0,
+                units: vec![sai_protocol::RosterUnit {
+                    unit: UnitId(5),
+                    unit_name: name("dyntrainer_strike_base"),
+                    pos: [300.0, 20.0, 900.0],
+                }],
+            },
+            SaiEvent::CommandError {
+                error: "Unit not found".into(),
+                command: "Stop { unit_id: 5 }".into(),
+                command_ref: Some(2),
+            },
+            SaiEvent::MalformedEvent { topic: 11, reason: "team 1108213760 is out of range".into() },
+            SaiEvent::DryRunResult {
+                request_id: 3,
+                error: Some("unit 5 does not exist".into()),
+            },
+            SaiEvent::UnitDefs {
+                request_id: 4,
+                offset: 0,
+                total: 1,
+                defs: vec![UnitDefInfo {
+                    id: UnitDefId(7),
+                    name: "cloakaa".into(),
+                    human_name: "Gremlin".into(),
+                    description: Some("Cloaked Anti-Air Bot".into()),
+                    metal_cost: 150.0,
+                    energy_cost: 150.0,
+                    build_time: 150.0,
+                    health: 550.0,
+                    speed: 2.6,
+                    builder: false,
+                    build_options: Vec::new(),
+                    metal_make: 0.0,
+                    energy_make: 0.0,
+                    extracts_metal: 0.0,
+                    build_speed: 0.0,
+                }],
+            },
+            SaiEvent::MapGrid {
+                request_id: 5,
+                cell_size: 128.0,
+                width: 2,
+                height: 1,
+                row_offset: 0,
+                elevation: vec![vec![40, 52]],
+                buildable: vec!["10".into()],
+                error: None,
+            },
+            SaiEvent::UnitQueues {
+                request_id: 6,
+                queues: vec![sai_protocol::UnitQueue {
+                    unit: UnitId(7),
+                    commands: vec![sai_protocol::QueuedCommand { id: -12, params: Vec::new() }],
+                }],
+            },
+            SaiEvent::GamePaused { by_us: false },
+            SaiEvent::GameResumed,
+            SaiEvent::SpeedChanged { speed: 2.0 },
+        ]
+    }
+
+    fn sample_commands() -> Vec<SaiCommand> {
+        vec![
+            SaiCommand::Move {
+                unit_id: UnitId(12),
+                x: 100.0,
+                y: 0.0,
+                z: 200.5,
+                queue: false,
+            },
+            SaiCommand::Stop { unit_id: UnitId(12) },
+            SaiCommand::Wait { unit_id: UnitId(12), queue: false },
+            SaiCommand::TimeWait { unit_id: UnitId(12), seconds: 10, queue: true },
+            SaiCommand::Attack {
+                unit_id: UnitId(12),
+                target_id: UnitId(900),
+                queue: true,
+            },
+            SaiCommand::AttackGround {
+                unit_id: UnitId(12),
+                x: 900.0,
+                y: 30.0,
+                z: 400.0,
+                queue: false,
+            },
+            SaiCommand::AttackArea {
+                unit_id: UnitId(12),
+                x: 900.0,
+                y: 0.0,
+                z: 400.0,
+                radius: 150.0,
+                queue: true,
+            },
+            SaiCommand::ManualFire {
+                unit_id: UnitId(1),
+                target_id: Some(UnitId(900)),
+                x: None,
+                y: 0.0,
+                z: None,
+                queue: false,
+            },
+            SaiCommand::Build {
+                unit_id: UnitId(3),
+                build_def_id: UnitDefId(55),
+                build_def_name: Some("staticmex".into()),
+                x: 312.0,
+                y: 40.5,
+                z: 1880.0,
+                facing: 2,
+                queue: true,
+            },
+            SaiCommand::Patrol {
+                unit_id: UnitId(12),
+                x: 10.0,
+                y: 0.0,
+                z: 20.0,
+                queue: false,
+            },
+            SaiCommand::Fight {
+                unit_id: UnitId(12),
+                x: 640.0,
+                y: 0.0,
+                z: 512.0,
+                queue: false,
+            },
+            SaiCommand::Guard {
+                unit_id: UnitId(12),
+                guard_id: UnitId(3),
+                queue: false,
+            },
+            SaiCommand::Repair {
+                unit_id: UnitId(3),
+                repair_id: UnitId(12),
+                queue: true,
+            },
+            SaiCommand::Reclaim {
+                unit_id: UnitId(3),
+                target: sai_protocol::ReclaimTarget::Unit(UnitId(40)),
+                queue: false,
+            },
+            SaiCommand::Reclaim {
+                unit_id: UnitId(3),
+                target: sai_protocol::ReclaimTarget::Feature(sai_protocol::FeatureId(7)),
+                queue: true,
+            },
+            SaiCommand::ReclaimArea {
+                unit_id: UnitId(3),
+                x: 1000.0,
+                y: 0.0,
+                z: 1500.0,
+                radius: 300.0,
+                queue: true,
+            },
+            SaiCommand::LoadUnits {
+                unit_id: UnitId(30),
+                target_ids: vec![UnitId(12), UnitId(13)],
+                queue: false,
+            },
+            SaiCommand::LoadOntoTransport {
+                unit_id: UnitId(12),
+                transport_id: UnitId(30),
+            },
+            SaiCommand::UnloadUnits {
+                unit_id: UnitId(30),
+                x: 900.0,
+                y: 0.0,
+                z: 400.0,
+                radius: 100.0,
+                queue: true,
+            },
+            SaiCommand::SelfDestruct { unit_id: UnitId(12) },
+            SaiCommand::SetFireState {
+                unit_id: UnitId(12),
+                state: 2,
+            },
+            SaiCommand::SetMoveState {
+                unit_id: UnitId(12),
+                state: 0,
+            },
+            SaiCommand::Custom {
+                unit_id: UnitId(12),
+                command_id: 34223,
+                params: vec![2.0],
+                queue: false,
+            },
+            SaiCommand::SendChat {
+                text: "hello from the agent".into(),
+                destination: ChatDestination::Allies,
+            },
+            SaiCommand::DrawPoint {
+                x: 1200.0,
+                z: 800.0,
+                label: "rally here".into(),
+            },
+            SaiCommand::Pause,
+            SaiCommand::Unpause,
+            SaiCommand::SetSpeed { speed: 2.5 },
+            SaiCommand::SetTurnMode { enabled: true },
+            SaiCommand::SetUpdateInterval { frames: Some(15), mode: Some(sai_protocol::UpdateMode::Fixed) },
+            SaiCommand::EndTurn,
+            SaiCommand::QueryUnitDefs { request_id: 4 },
+            SaiCommand::QueryMapGrid { request_id: 5, cell_size: 128.0, build_def: Some("staticmex".into()) },
+            SaiCommand::QueryUnitQueues { request_id: 6, units: vec![UnitId(7)] },
+        ]
+    }
+
+    /// No wildcard arms: adding a variant to sai-protocol fails to compile
+    /// here, naming the variant, until it gets a sample above.
+    fn sampled_event(event: &SaiEvent) -> bool {
+        match event {
+            SaiEvent::Init { .. }
+            | SaiEvent::Release { .. }
+            | SaiEvent::Update { .. }
+            | SaiEvent::Message { .. }
+            | SaiEvent::UnitCreated { .. }
+            | SaiEvent::ConstructionStalled { .. }
+            | SaiEvent::UnitFinished { .. }
+            | SaiEvent::UnitIdle { .. }
+            | SaiEvent::UnitMoveFailed { .. }
+            | SaiEvent::UnitDamaged { .. }
+            | SaiEvent::UnitDestroyed { .. }
+            | SaiEvent::UnitGiven { .. }
+            | SaiEvent::UnitCaptured { .. }
+            | SaiEvent::EnemyEnterLos { .. }
+            | SaiEvent::EnemyLeaveLos { .. }
+            | SaiEvent::EnemyEnterRadar { .. }
+            | SaiEvent::EnemyLeaveRadar { .. }
+            | SaiEvent::EnemyDamaged { .. }
+            | SaiEvent::EnemyDestroyed { .. }
+            | SaiEvent::EnemyCreated { .. }
+            | SaiEvent::EnemyFinished { .. }
+            | SaiEvent::WeaponFired { .. }
+            | SaiEvent::CommandFinished { .. }
+            | SaiEvent::LuaMessage { .. }
+            | SaiEvent::CommandError { .. }
+            | SaiEvent::MalformedEvent { .. }
+            | SaiEvent::Roster { .. }
+            | SaiEvent::DryRunResult { .. }
+            | SaiEvent::UnitDefs { .. }
+            | SaiEvent::MapGrid { .. }
+            | SaiEvent::UnitQueues { .. }
+            | SaiEvent::GamePaused { .. }
+            | SaiEvent::GameResumed
+            | SaiEvent::SpeedChanged { .. } => true,
+            // Receive-side fallback, never sent.
+            SaiEvent::Unknown { .. } => false,
+        }
+    }
+
+    fn sampled_command(cmd: &SaiCommand) -> bool {
+        match cmd {
+            SaiCommand::Move { .. }
+            | SaiCommand::Stop { .. }
+            | SaiCommand::Wait { .. }
+            | SaiCommand::TimeWait { .. }
+            | SaiCommand::Attack { .. }
+            | SaiCommand::AttackGround { .. }
+            | SaiCommand::AttackArea { .. }
+            | SaiCommand::ManualFire { .. }
+            | SaiCommand::Build { .. }
+            | SaiCommand::Patrol { .. }
+            | SaiCommand::Fight { .. }
+            | SaiCommand::Guard { .. }
+            | SaiCommand::Repair { .. }
+            | SaiCommand::Reclaim { .. }
+            | SaiCommand::ReclaimArea { .. }
+            | SaiCommand::LoadUnits { .. }
+            | SaiCommand::LoadOntoTransport { .. }
+            | SaiCommand::UnloadUnits { .. }
+            | SaiCommand::SelfDestruct { .. }
+            | SaiCommand::SetFireState { .. }
+            | SaiCommand::SetMoveState { .. }
+            | SaiCommand::Custom { .. }
+            | SaiCommand::SendChat { .. }
+            | SaiCommand::DrawPoint { .. }
+            | SaiCommand::Pause
+            | SaiCommand::Unpause
+            | SaiCommand::SetSpeed { .. }
+            | SaiCommand::SetTurnMode { .. }
+            | SaiCommand::SetUpdateInterval { .. }
+            | SaiCommand::EndTurn
+            | SaiCommand::QueryUnitDefs { .. }
+            | SaiCommand::QueryMapGrid { .. }
+            | SaiCommand::QueryUnitQueues { .. } => true,
+            SaiCommand::Unknown { .. } => false,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_event_written_in_pieces() {
+        let (ours, mut theirs) = UnixStream::pair().unwrap();
+        let mut conn = SaiConnection::new("game-1".into(), ours);
+        let line = serde_json::to_string(&SaiEvent::UnitIdle { unit: UnitId(12), unit_name: Some("cloakcon".into()) }).unwrap() + "\n";
+        let writer = tokio::spawn(async move {
+            for byte in line.bytes() {
+                theirs.write_all(&[byte]).await.unwrap();
+                tokio::time::sleep(Duration::from_millis(2)).await;
+            }
+            theirs
+        });
+
+        // Drain the way the main loop does, with reads cut short mid-line.
+        let mut events = Vec::new();
+        while !writer.is_finished() {
+            if let Ok(Some(event)) = tokio::time::timeout(Duration::from_millis(1), conn.next_event()).await {
+                events.push(event);
+            }
+        }
+        drop(writer.await.unwrap());
+        while let Some(event) = conn.next_event().await {
+            events.push(event);
+        }
+        assert_eq!(events, [SaiEvent::UnitIdle { unit: UnitId(12), unit_name: Some("cloakcon".into()) }]);
+        assert_eq!(conn.stats.parse_failures, 0);
+    }
+
+    #[tokio::test]
+    async fn test_coop_bridges_arrive_apart() {
+        let dir = std::env::temp_dir().join(format!("sai-coop-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let socket = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        let coop = coop_channel_id("game-1", 2);
+        let mut server = SaiIpcServer::new();
+        server.listen_for("game-1", &socket("sai_1.sock")).unwrap();
+        server.listen_for(&coop, &socket("sai_1_team2.sock")).unwrap();
+        server.listen_for("game-10", &socket("sai_10.sock")).unwrap();
+        assert_eq!(server.coop_channels("game-1"), ["game-1/team2"]);
+        assert_eq!(game_channel_id("game-1/team2"), "game-1");
+        assert_eq!(game_channel_id("game-1"), "game-1");
+        assert_eq!((sub_channel_team("game-1/team2"), sub_channel_team("game-1")), (Some(2), None));
+
+        let mut ours = sai_protocol::IpcClient::connect(&socket("sai_1.sock")).unwrap();
+        let mut ally = sai_protocol::IpcClient::connect(&socket("sai_1_team2.sock")).unwrap();
+        let mut connected = server.accept_pending();
+        connected.sort();
+        assert_eq!(connected, ["game-1".to_string(), coop.clone()]);
+        ours.send_event(&SaiEvent::UnitIdle { unit: UnitId(1), unit_name: None }).unwrap();
+        ally.send_event(&SaiEvent::UnitIdle { unit: UnitId(2), unit_name: None }).unwrap();
+        assert_eq!(server.drain_events("game-1").await, [SaiEvent::UnitIdle { unit: UnitId(1), unit_name: None }]);
+        assert_eq!(server.drain_events(&coop).await, [SaiEvent::UnitIdle { unit: UnitId(2), unit_name: None }]);
+
+        server.close_channel("game-1");
+        assert_eq!(server.listeners.keys().collect::<Vec<_>>(), ["game-10"]);
+        assert!(server.connections.is_empty());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_chaos_mode() {
+        let socket = std::env::temp_dir().join(format!("sai-chaos-{}.sock", uuid::Uuid::new_v4()));
+        let socket = socket.to_str().unwrap();
+        let mut server = SaiIpcServer::new();
+        assert_eq!(server.chaos_counts("game-1"), None);
+        server.chaos = Some(ChaosConfig {
+            latency_ms: 50,
+            drop_percent: 50.0,
+            disconnect_every_secs: Some(60.0),
+            seed: Some(3),
+        });
+        server.listen_for("game-1", socket).unwrap();
+        let mut client = sai_protocol::IpcClient::connect(socket).unwrap();
+        server.accept_pending();
+        for frame in 0..20 {
+            let update = SaiEvent::Update { frame, awaiting_commands: false, economy: None, allies: Vec::new(), counters: Default::default(), command_backlog: 0, under_construction: Vec::new(), update_interval: None };
+            client.send_event(&update).unwrap();
+        }
+
+        // Read off the wire, the events are held back for the latency...
+        let mut frames: Vec<i32> = Vec::new();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut early = None;
+        while server.chaos_counts("game-1").unwrap().dropped + (frames.len() as u64) < 20 {
+            assert!(Instant::now() < deadline, "events went missing");
+            let events = server.drain_events("game-1").await;
+            early.get_or_insert(events.len());
+            frames.extend(events.iter().map(|e| match e {
+                SaiEvent::Update { frame, .. } => *frame,
+                other => panic!("unexpected {:?}", other),
+            }));
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert_eq!(early, Some(0));
+        // ...and what isn't dropped comes through in order.
+        let counts = server.chaos_counts("game-1").unwrap();
+        assert!(counts.dropped > 0 && !frames.is_empty(), "{:?}", counts);
+        assert!(frames.windows(2).all(|w| w[0] < w[1]), "{:?}", frames);
+        assert_eq!(counts.delayed, frames.len() as u64);
+
+        // The connection is cut once it's due; the counts carry over to
+        // the bridge's next connection.
+        assert!(server.inject_disconnects(Instant::now()).is_empty());
+        assert_eq!(server.inject_disconnects(Instant::now() + Duration::from_secs(60)), ["game-1"]);
+        assert!(server.connections.is_empty());
+        let _client = sai_protocol::IpcClient::connect(socket).unwrap();
+        assert_eq!(server.accept_pending(), ["game-1"]);
+        assert_eq!(server.chaos_counts("game-1"), Some(ChaosCounts { disconnects: 1, ..counts }));
+        let _ = std::fs::remove_file(socket);
+    }
+
+    #[tokio::test]
+    async fn test_ipc_loopback_all_variants() {
+        let socket =
+            std::env::temp_dir().join(format!("sai-loopback-{}.sock", uuid::Uuid::new_v4()));
+        let socket = socket.to_str().unwrap();
+        let mut server = SaiIpcServer::new();
+        server.listen_for("game-1", socket).unwrap();
+        let mut client = sai_protocol::IpcClient::connect(socket).unwrap();
+        assert_eq!(server.accept_pending(), vec!["game-1".to_string()]);
+
+        let events = sample_events();
+        let mut event_types = std::collections::HashSet::new();
+        for event in &events {
+            assert!(sampled_event(event));
+            assert!(
+                event_types.insert(event.type_name()),
+                "duplicate sample for '{}'",
+                event.type_name()
+            );
+            client.send_event(event).unwrap();
+            let conn = server.connections.get_mut("game-1").unwrap();
+            let received = conn.next_event().await.expect("connection closed");
+            assert!(
+                received.unknown_type().is_none(),
+                "event '{}' is not recognized by the GameManager",
+                event.type_name()
+            );
+            assert_eq!(
+                &received,
+                event,
+                "event '{}' changed in transit",
+                event.type_name()
+            );
+        }
+        let stats = server.stats("game-1").unwrap();
+        assert_eq!(stats.events_total(), events.len() as u64);
+        assert_eq!(stats.parse_failures, 0);
+
+        let commands = sample_commands();
+        let mut command_types = std::collections::HashSet::new();
+        for cmd in &commands {
+            assert!(sampled_command(cmd));
+            assert!(
+                command_types.insert(cmd.type_name()),
+                "duplicate sample for '{}'",
+                cmd.type_name()
+            );
+            server.send_to("game-1", cmd, 1).await.unwrap();
+            let received = client.poll_commands();
+            assert!(
+                client.take_errors().is_empty(),
+                "command '{}' failed to parse",
+                cmd.type_name()
+            );
+            assert_eq!(
+                received.len(),
+                1,
+                "command '{}' was not delivered",
+                cmd.type_name()
+            );
+            assert!(
+                !matches!(received[0], SaiCommand::Unknown { .. }),
+                "command '{}' is not recognized by the bridge",
+                cmd.type_name()
+            );
+            assert_eq!(
+                &received[0],
+                cmd,
+                "command '{}' changed in transit",
+                cmd.type_name()
+            );
+        }
+
+        let _ = std::fs::remove_file(socket);
+    }
 }
@@ -0,0 +1,130 @@
+//! Scoped access: named sets of allowed tools and channel operations from
+//! the config file. The client picks a scope in its initialize request (or
+//! gets the config's default), and everything outside it is refused.
+
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+
+/// Error code for calls outside the negotiated scope.
+pub const FORBIDDEN_BY_SCOPE: i64 = -32003;
+
+/// Channel operations a scope can allow.
+pub const CHANNEL_OPS: [&str; 4] = ["open", "close", "list", "publish"];
+
+/// One scope as written in the config file. Tool patterns may use `*`.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScopeConfig {
+    #[serde(default)]
+    pub tools: Vec<String>,
+    #[serde(default)]
+    pub channels: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scope {
+    pub name: String,
+    tools: Vec<String>,
+    channels: Vec<String>,
+}
+
+impl Scope {
+    pub fn new(name: &str, config: &ScopeConfig) -> Self {
+        Self { name: name.to_string(), tools: config.tools.clone(), channels: config.channels.clone() }
+    }
+
+    /// The scope a session runs under: the one the client asked for, else
+    /// the default. None means unrestricted. A scope the config doesn't
+    /// define allows nothing rather than falling back to something wider.
+    pub fn negotiate(
+        requested: Option<&str>,
+        scopes: &BTreeMap<String, ScopeConfig>,
+        default: Option<&str>,
+    ) -> Option<Self> {
+        let name = requested.or(default)?;
+        match scopes.get(name) {
+            Some(config) => Some(Self::new(name, config)),
+            None => {
+                tracing::warn!("Client asked for unknown scope '{}'; nothing is allowed", name);
+                Some(Self::new(name, &ScopeConfig::default()))
+            }
+        }
+    }
+
+    pub fn allows_tool(&self, tool: &str) -> bool {
+        self.tools.iter().any(|p| glob_match(p, tool))
+    }
+
+    pub fn allows_channel_op(&self, op: &str) -> bool {
+        self.channels.iter().any(|c| c == op || c == "*")
+    }
+
+    /// The error returned for a refused call.
+    pub fn forbidden(&self, what: &str) -> serde_json::Value {
+        serde_json::json!({
+            "error": {
+                "code": FORBIDDEN_BY_SCOPE,
+                "message": format!("{} is forbidden by scope '{}'", what, self.name),
+            }
+        })
+    }
+}
+
+/// Match `name` against a pattern where `*` stands for any run of characters.
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let Some((head, rest)) = pattern.split_once('*') else {
+        return pattern == name;
+    };
+    let Some(mut remaining) = name.strip_prefix(head) else { return false };
+    let mut parts: Vec<&str> = rest.split('*').collect();
+    let tail = parts.pop().unwrap_or_default();
+    for part in parts {
+        match remaining.find(part) {
+            Some(i) => remaining = &remaining[i + part.len()..],
+            None => return false,
+        }
+    }
+    remaining.len() >= tail.len() && remaining.ends_with(tail)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("game_*", "game_pause"));
+        assert!(!glob_match("game_*", "lobby_say"));
+        assert!(glob_match("*", "lobby_say"));
+        assert!(glob_match("lobby_*_battle", "lobby_join_battle"));
+        assert!(!glob_match("lobby_*_battle", "lobby_join_battles"));
+        assert!(glob_match("*status", "lobby_matchmaker_status"));
+        assert!(glob_match("game_stats", "game_stats"));
+        assert!(!glob_match("game_stats", "game_stats_reset"));
+    }
+
+    #[test]
+    fn test_negotiate_scope() {
+        let scopes: BTreeMap<String, ScopeConfig> = serde_json::from_value(serde_json::json!({
+            "game": {"tools": ["game_*"], "channels": ["list", "publish"]},
+            "full": {"tools": ["*"], "channels": ["*"]},
+        }))
+        .unwrap();
+
+        assert_eq!(Scope::negotiate(None, &scopes, None), None);
+        let game = Scope::negotiate(None, &scopes, Some("game")).unwrap();
+        assert!(game.allows_tool("game_say") && !game.allows_tool("lobby_say"));
+        assert!(game.allows_channel_op("publish") && !game.allows_channel_op("open"));
+
+        let full = Scope::negotiate(Some("full"), &scopes, Some("game")).unwrap();
+        assert!(full.allows_tool("lobby_say") && full.allows_channel_op("open"));
+
+        let unknown = Scope::negotiate(Some("admin"), &scopes, Some("full")).unwrap();
+        assert!(!unknown.allows_tool("game_pause") && !unknown.allows_channel_op("list"));
+        assert_eq!(
+            unknown.forbidden("Tool game_pause")["error"]["message"],
+            "Tool game_pause is forbidden by scope 'admin'"
+        );
+    }
+}
@@ -0,0 +1,337 @@
+//! `game-manager self-test`: checks an installation end to end without
+//! launching a game.
+//!
+//! A stub bridge speaks the SAI IPC protocol over a temp socket. It sends
+//! `init`, a roster and a few unit events, echoes every unit command back as
+//! `command_finished`, and passes every dry run. The GameManager forwards
+//! what it receives to a client in the same process: a loopback, or with
+//! `--mcpl` a real MCPL connection after the initialize handshake. With
+//! `--keep-running` the stub stays connected as `game:self-test` once the
+//! checks pass, so an agent can be exercised without a game.
+//!
+//! The GameManager drives the stages; this module holds the stub, the
+//! in-process clients and the report.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::mpsc;
+
+use crate::config::GmConfig;
+use crate::mcpl_link::{Disconnect, Outgoing, Transport};
+use crate::sai_ipc::{SaiCommand, SaiEvent, UnitId};
+use mcpl_core::connection::IncomingMessage as McplIncoming;
+use mcpl_core::methods::{method, ChannelsIncomingParams};
+use mcpl_core::McplConnection;
+use sai_protocol::{IpcClient, MetalSpot, RosterUnit};
+
+/// The stub bridge's game channel.
+pub const CHANNEL: &str = "game:self-test";
+
+/// The stub's one unit, a constructor.
+pub const STUB_UNIT: UnitId = UnitId(1);
+
+/// How long each stage may wait for the stub or the client.
+pub const STAGE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Frames between the stub's `update` events (one game second).
+const UPDATE_FRAMES: i32 = 30;
+
+/// One checked stage: what passed, or why it failed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stage {
+    pub name: &'static str,
+    pub outcome: Result<String, String>,
+}
+
+impl Stage {
+    pub fn new(name: &'static str, outcome: Result<String, String>) -> Self {
+        Self { name, outcome }
+    }
+
+    pub fn passed(&self) -> bool {
+        self.outcome.is_ok()
+    }
+
+    /// `PASS  sai connect: ...` or `FAIL  engine: ...`.
+    pub fn line(&self) -> String {
+        match &self.outcome {
+            Ok(detail) => format!("PASS  {}: {}", self.name, detail),
+            Err(error) => format!("FAIL  {}: {}", self.name, error),
+        }
+    }
+}
+
+/// The report for a run, and whether every stage passed.
+pub fn report(stages: &[Stage]) -> (String, bool) {
+    let passed = stages.iter().all(Stage::passed);
+    let mut lines: Vec<String> = stages.iter().map(Stage::line).collect();
+    let failed = stages.iter().filter(|s| !s.passed()).count();
+    lines.push(if passed {
+        format!("Self-test passed ({} stages)", stages.len())
+    } else {
+        format!("Self-test failed ({} of {} stages)", failed, stages.len())
+    });
+    (lines.join("\n"), passed)
+}
+
+/// A stub SAI bridge on its own thread.
+pub struct StubBridge {
+    stop: Arc<AtomicBool>,
+    thread: std::thread::JoinHandle<()>,
+}
+
+impl StubBridge {
+    /// Connect to the GameManager's socket for the channel and start
+    /// playing a tiny game.
+    pub fn spawn(socket: &str) -> Result<Self, String> {
+        let client = IpcClient::connect(socket).map_err(|e| format!("Failed to connect to {}: {}", socket, e))?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = std::thread::spawn({
+            let stop = stop.clone();
+            move || run_stub(client, &stop)
+        });
+        Ok(Self { stop, thread })
+    }
+
+    pub fn stop(self) {
+        self.stop.store(true, Ordering::Relaxed);
+        let _ = self.thread.join();
+    }
+}
+
+/// The opening events of the stub game.
+pub fn opening_events() -> Vec<SaiEvent> {
+    vec![
+        SaiEvent::Init {
+            frame: 0,
+            saved_game: false,
+            protocol_version: Some(crate::sai_ipc::PROTOCOL_VERSION),
+            metal_spots: Some(vec![MetalSpot { x: 600.0, y: 10.0, z: 600.0, metal: 2.0 }]),
+            map_width: Some(128),
+            map_height: Some(128),
+            start_pos: None,
+            teams: Vec::new(),
+            build: None,
+        },
+        SaiEvent::Roster {
+            frame: 0,
+            units: vec![RosterUnit { unit: STUB_UNIT, unit_name: Some("cloakcon".into()), pos: [500.0, 10.0, 500.0] }],
+        },
+        SaiEvent::UnitFinished { unit: UnitId(2), unit_name: Some("factorycloak".into()), pos: Some([400.0, 10.0, 400.0]) },
+        SaiEvent::Update { frame: UPDATE_FRAMES, awaiting_commands: false, economy: None, allies: Vec::new(), counters: Default::default(), command_backlog: 0, under_construction: Vec::new(), update_interval: None },
+    ]
+}
+
+/// The stub's answer to a command: units report it finished.
+fn echo((command, command_ref): &(SaiCommand, Option<u64>)) -> Option<SaiEvent> {
+    let unit = UnitId(serde_json::to_value(command).ok()?.get("unit_id")?.as_i64()? as i32);
+    Some(SaiEvent::CommandFinished { unit, unit_name: None, command_id: 0, command_topic: 0, command_ref: *command_ref })
+}
+
+fn run_stub(mut client: IpcClient, stop: &AtomicBool) {
+    for event in opening_events() {
+        if client.send_event(&event).is_err() {
+            return;
+        }
+    }
+    let mut frame = UPDATE_FRAMES;
+    let mut last_update = std::time::Instant::now();
+    while !stop.load(Ordering::Relaxed) && client.is_connected() {
+        let mut replies: Vec<SaiEvent> = client.poll_tracked_commands().iter().filter_map(echo).collect();
+        replies.extend(
+            client
+                .take_dry_runs()
+                .into_iter()
+                .map(|dry_run| SaiEvent::DryRunResult { request_id: dry_run.request_id, error: None }),
+        );
+        if last_update.elapsed() >= Duration::from_secs(1) {
+            frame += UPDATE_FRAMES;
+            last_update = std::time::Instant::now();
+            replies.push(SaiEvent::Update { frame, awaiting_commands: false, economy: None, allies: Vec::new(), counters: Default::default(), command_backlog: 0, under_construction: Vec::new(), update_interval: None });
+        }
+        for event in &replies {
+            if client.send_event(event).is_err() {
+                return;
+            }
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/// The client end of an outgoing MCPL message, as JSON-RPC.
+pub fn to_message(outgoing: Outgoing) -> serde_json::Value {
+    match outgoing {
+        Outgoing::Response { id, result } => serde_json::json!({"jsonrpc": "2.0", "id": id, "result": result}),
+        Outgoing::Notification { method, params } | Outgoing::Request { method, params } => {
+            serde_json::json!({"jsonrpc": "2.0", "method": method, "params": params})
+        }
+    }
+}
+
+/// An in-process client that takes everything the GameManager sends and
+/// never sends anything itself.
+pub struct LoopbackClient {
+    received: mpsc::UnboundedSender<serde_json::Value>,
+}
+
+impl LoopbackClient {
+    pub fn new() -> (Self, mpsc::UnboundedReceiver<serde_json::Value>) {
+        let (received, rx) = mpsc::unbounded_channel();
+        (Self { received }, rx)
+    }
+}
+
+impl Transport for LoopbackClient {
+    async fn next_message(&mut self) -> Result<McplIncoming, Disconnect> {
+        std::future::pending().await
+    }
+
+    async fn send(&mut self, outgoing: Outgoing) -> Result<(), String> {
+        self.received.send(to_message(outgoing)).map_err(|_| "self-test client gone".to_string())
+    }
+}
+
+/// Open an MCPL connection to a client in this process and run the
+/// initialize handshake over it. The client answers every request the
+/// GameManager sends and passes each message it receives on.
+pub async fn connect_in_process(
+    config: &GmConfig,
+) -> Result<(McplConnection, mpsc::UnboundedReceiver<serde_json::Value>), String> {
+    let (server, client) = tokio::io::duplex(64 * 1024);
+    let (received, rx) = mpsc::unbounded_channel();
+    tokio::spawn(run_client(client, received));
+    let (reader, writer) = tokio::io::split(server);
+    let accept = crate::mcpl_server::accept_mcpl_stream(Box::new(reader), Box::new(writer), config);
+    let (conn, _options) = tokio::time::timeout(STAGE_TIMEOUT, accept)
+        .await
+        .map_err(|_| "No answer to the initialize handshake".to_string())?
+        .map_err(|e| format!("Handshake failed: {}", e))?;
+    Ok((conn, rx))
+}
+
+async fn run_client(stream: tokio::io::DuplexStream, received: mpsc::UnboundedSender<serde_json::Value>) {
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut lines = BufReader::new(reader).lines();
+    let initialize = serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "initialize",
+        "params": {
+            "protocolVersion": "2024-11-05",
+            "capabilities": {"experimental": {"mcpl": {"pushEvents": true, "channels": true}}},
+            "clientInfo": {"name": "self-test", "version": env!("CARGO_PKG_VERSION")},
+        }
+    });
+    let initialized = serde_json::json!({"jsonrpc": "2.0", "method": "notifications/initialized"});
+    for message in [initialize, initialized] {
+        if write_line(&mut writer, &message).await.is_err() {
+            return;
+        }
+        if message.get("id").is_some() && !matches!(lines.next_line().await, Ok(Some(_))) {
+            return;
+        }
+    }
+    while let Ok(Some(line)) = lines.next_line().await {
+        let Ok(message) = serde_json::from_str::<serde_json::Value>(&line) else { continue };
+        if let (Some(id), Some(_)) = (message.get("id"), message.get("method")) {
+            let response = serde_json::json!({"jsonrpc": "2.0", "id": id, "result": {}});
+            if write_line(&mut writer, &response).await.is_err() {
+                return;
+            }
+        }
+        if received.send(message).is_err() {
+            return;
+        }
+    }
+}
+
+async fn write_line<W: tokio::io::AsyncWrite + Unpin>(writer: &mut W, message: &serde_json::Value) -> std::io::Result<()> {
+    writer.write_all(format!("{}\n", message).as_bytes()).await?;
+    writer.flush().await
+}
+
+/// The SAI event type of a `channels/incoming` message for the stub's
+/// channel, if that's what `message` is.
+pub fn forwarded_event(message: &serde_json::Value) -> Option<String> {
+    if message["method"] != method::CHANNELS_INCOMING {
+        return None;
+    }
+    let params: ChannelsIncomingParams = serde_json::from_value(message["params"].clone()).ok()?;
+    let forwarded = params.messages.into_iter().find(|m| m.channel_id == CHANNEL)?;
+    forwarded.metadata?["event"]["type"].as_str().map(String::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, Write};
+
+    #[test]
+    fn test_stub_plays_and_echoes() {
+        let socket = std::env::temp_dir().join(format!("gm-stub-{}.sock", uuid::Uuid::new_v4()));
+        let listener = std::os::unix::net::UnixListener::bind(&socket).unwrap();
+        let stub = StubBridge::spawn(socket.to_str().unwrap()).unwrap();
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut reader = std::io::BufReader::new(stream.try_clone().unwrap());
+        let mut next_event = || {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            SaiEvent::from_line(line.trim()).unwrap()
+        };
+        for expected in opening_events() {
+            assert_eq!(next_event(), expected);
+        }
+
+        writeln!(stream, r#"{{"type":"stop","unit_id":1,"command_ref":4}}"#).unwrap();
+        writeln!(stream, r#"{{"type":"stop","unit_id":1,"dry_run":true,"request_id":7}}"#).unwrap();
+        let mut replies = Vec::new();
+        while replies.len() < 2 {
+            match next_event() {
+                SaiEvent::Update { .. } => {}
+                event => replies.push(event),
+            }
+        }
+        assert!(replies.contains(&SaiEvent::CommandFinished {
+            unit: UnitId(1),
+            unit_name: None,
+            command_id: 0,
+            command_topic: 0,
+            command_ref: Some(4),
+        }));
+        assert!(replies.contains(&SaiEvent::DryRunResult { request_id: 7, error: None }));
+        stub.stop();
+        let _ = std::fs::remove_file(&socket);
+    }
+
+    #[test]
+    fn test_report() {
+        let stages = [
+            Stage::new("sai connect", Ok("stub connected".into())),
+            Stage::new("engine", Err("No engine found".into())),
+        ];
+        let (text, passed) = report(&stages);
+        assert!(!passed);
+        assert_eq!(
+            text,
+            "PASS  sai connect: stub connected\nFAIL  engine: No engine found\nSelf-test failed (1 of 2 stages)"
+        );
+        assert!(report(&stages[..1]).1);
+        let message = |event: &SaiEvent| {
+            let forwarded = mcpl_core::methods::IncomingChannelMessage {
+                channel_id: CHANNEL.into(),
+                message_id: "1".into(),
+                thread_id: None,
+                author: mcpl_core::methods::MessageAuthor { id: "engine".into(), name: "Game Engine".into() },
+                content: vec![],
+                timestamp: String::new(),
+                metadata: crate::sai_ipc::event_metadata(event),
+            };
+            let params = ChannelsIncomingParams { messages: vec![forwarded] };
+            to_message(Outgoing::Request { method: method::CHANNELS_INCOMING, params: serde_json::to_value(params).ok() })
+        };
+        assert_eq!(forwarded_event(&message(&opening_events()[1])).as_deref(), Some("roster"));
+    }
+}
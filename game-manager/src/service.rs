@@ -0,0 +1,8729 @@
+//! The GameManager service: every channel, tool, lobby and SAI event
+//! handler, driven by `main`'s select loop through [`GameManager::on_mcpl_message`],
+//! [`GameManager::handle_lobby_message`], [`GameManager::handle_sai_event`]
+//! and [`GameManager::tick`]. Tests drive it the same way, with a loopback
+//! MCPL client and the clock passed in.
+
+use crate::engine::EngineManager;
+use crate::{
+    analysis, army, assist, audit, autorespond, benchmark, channel_changes, channel_ids, closing, command_history, config, content, credentials,
+    doctor, economy_alerts, engine, engine_install, eta, expansion, factories, game_start, groups, idle_builders, income, lobby, lobby_reconnect, locations, login_guard,
+    macros, map_grid, mcpl_link, mcpl_server, observer, opponents, policy, profiles, queries, ready, recording, reload, replay, sai_ipc, scope, self_test, socket_dir,
+    status_page, threats, unit_history,
+    positions, unit_defs, waiters, write_dir,
+};
+use crate::lobby::chat::ChatChannel;
+use crate::lobby::*;
+use crate::pacing::Paced;
+use crate::sai_ipc::{GameControl, SaiCommand, SaiIpcServer, UnitId, Verbosity};
+use crate::write_dir::WriteDirConfig;
+use mcpl_core::connection::IncomingMessage as McplIncoming;
+use mcpl_core::methods::*;
+use mcpl_core::types::*;
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::path::PathBuf;
+
+pub struct GameManager {
+    pub mcpl: Option<mcpl_link::McplLink>,
+    pub lobby_conn: Option<LobbyConnection>,
+    lobby_state: LobbyState,
+    /// The lobby server and login to reconnect with, and the calls queued
+    /// meanwhile; None until `lobby_connect`.
+    lobby_session: Option<lobby_reconnect::LobbySession>,
+    /// Accounts cooling down after a failed login.
+    login_guard: login_guard::LoginGuard,
+    /// Config `lobby_reconnect`, for new sessions.
+    lobby_reconnect: lobby_reconnect::ReconnectConfig,
+    /// Config `army`: the roles the stream groups units into, overriding
+    /// the game profiles'.
+    army: Option<army::ArmyConfig>,
+    /// Game profiles, built-in and from the config.
+    profiles: profiles::Profiles,
+    pub engines: EngineManager,
+    pub sai: SaiIpcServer,
+    write_dir: PathBuf,
+    spring_home: PathBuf,
+    agent_name: String,
+    /// Event rendering per game channel (set on channels/open).
+    verbosity: HashMap<String, Verbosity>,
+    /// Pause/speed state and allowed speed range per game channel.
+    game_control: HashMap<String, GameControl>,
+    /// Session logs of the channels with a connected SAI.
+    recorders: HashMap<String, recording::SessionRecorder>,
+    /// Post-game summaries of the most recently ended games, oldest first.
+    summaries: VecDeque<(String, serde_json::Value)>,
+    /// How many summaries `game_summary` can return.
+    pub summary_retain: usize,
+    /// Chat rules from the config file, answered without the agent.
+    auto_respond: autorespond::AutoResponder,
+    /// Recent economy snapshots and alert thresholds per game channel.
+    economy_alerts: HashMap<String, economy_alerts::EconomyWatch>,
+    /// Own economy structures and what they earn, per game channel.
+    income: HashMap<String, income::IncomeRegistry>,
+    /// Recent commands sent to each game channel.
+    command_history: HashMap<String, command_history::CommandHistory>,
+    /// Entries kept per channel (config `command_history.size`).
+    command_history_size: usize,
+    /// Idle constructors and factories per game channel.
+    idle_builders: HashMap<String, idle_builders::IdleWatch>,
+    /// Arrival reminders asked for per game channel.
+    eta_reminders: HashMap<String, eta::Reminders>,
+    /// Init and roster per game channel, until the game_started summary.
+    game_starts: HashMap<String, game_start::StartWatch>,
+    /// Recent sightings and damage per game channel, clustered into threats.
+    threats: HashMap<String, threats::ThreatTracker>,
+    /// Recent positions of our units and seen enemies per game channel.
+    unit_history: HashMap<String, unit_history::UnitHistory>,
+    /// Our factories and their build queues per game channel.
+    factories: HashMap<String, factories::FactoryTracker>,
+    /// The client negotiated stream observation.
+    stream_observer: bool,
+    /// Default seconds between state lines (config `stream_observer`).
+    stream_interval_secs: f64,
+    /// Cached game state and stream settings per game channel.
+    observers: HashMap<String, observer::ChannelObserver>,
+    /// Named unit groups per game channel.
+    groups: HashMap<String, groups::UnitGroups>,
+    /// Command macros, kept in the write dir.
+    macros: macros::MacroBook,
+    /// Metal spot claims per game channel, for game_expand.
+    expansions: HashMap<String, expansion::MexPlanner>,
+    /// Named locations and unit positions per game channel, for commands'
+    /// `location`.
+    places: HashMap<String, locations::Places>,
+    /// Unit def catalog per game channel, fetched on first use.
+    unit_defs: HashMap<String, unit_defs::UnitDefCatalog>,
+    /// Sampled map per game channel, fetched on first use.
+    map_grids: HashMap<String, map_grid::MapGrid>,
+    /// Tools and channel operations the client may use; None allows all.
+    scope: Option<scope::Scope>,
+    /// Lobby rooms and DM conversations announced as channels.
+    lobby_chats: BTreeSet<ChatChannel>,
+    /// Players whose new battles we join (config `auto_join_founders`).
+    auto_join_founders: Vec<String>,
+    /// The match behind each game channel launched by the matchmaker.
+    matchmaker_games: HashMap<String, MatchContext>,
+    /// The lobby battle behind each game channel it started.
+    battle_games: HashMap<String, i64>,
+    /// pr-downloader runs for missing battle content.
+    pub downloads: content::Downloads,
+    /// Downloads the current battle (or a held-back launch) waits for.
+    content_wait: Option<ContentWait>,
+    /// Engine installs started with the engine_install tool.
+    engine_installs: engine_install::EngineInstalls,
+    /// Benchmark runs, oldest first: at most one playing, then the last
+    /// finished ones.
+    benchmarks: VecDeque<benchmark::BenchmarkRun>,
+    next_benchmark_id: u64,
+    /// Custom AIs for the opponent catalog (config `opponents`).
+    custom_opponents: Vec<opponents::Opponent>,
+    /// Lobby accounts for lobby_login_stored (config `credentials`).
+    credentials: BTreeMap<String, credentials::StoredAccount>,
+    /// Record of the actions taken through us; None when config `audit` is off.
+    audit: Option<audit::AuditLog>,
+    /// Game channels closing gracefully, until their engine exits.
+    closing: HashMap<String, closing::Closing>,
+    /// What closed channels with a chosen id left for a rematch.
+    kept_state: HashMap<String, channel_ids::KeptState>,
+    /// What the status page shows; None when config `status_page` is off.
+    pub status_board: Option<status_page::StatusBoard>,
+    /// game_wait_for calls not answered yet.
+    waiters: waiters::Waiters,
+    /// The id of the MCPL request being handled. A tool that answers later
+    /// takes it; the request then gets no response now.
+    current_request: Option<serde_json::Value>,
+    /// channels/changed updates held to be merged (config
+    /// `mcpl_delivery.changes_window_secs`); sent as they come until then.
+    pub channel_changes: channel_changes::ChannelChanges,
+    /// Messages sent per channel, for `channels/replay`.
+    replay: HashMap<String, replay::ReplayBuffer>,
+    /// Messages kept per channel (config `mcpl_delivery.replay_size`).
+    replay_size: usize,
+    /// When each channel's commands went out, for the command policy's
+    /// rate budgets.
+    policy_sent: HashMap<String, policy::Sent>,
+}
+
+/// Content the current battle is missing, and the launch that waits for it.
+struct ContentWait {
+    archives: Vec<content::Archive>,
+    launch: Option<ConnectSpringData>,
+}
+
+/// Ended games whose summaries are kept unless GAME_SUMMARY_RETAIN says otherwise.
+const DEFAULT_SUMMARY_RETAIN: usize = 10;
+
+impl GameManager {
+    pub fn new(write_dir_config: &WriteDirConfig, engine_dir: PathBuf, sockets: socket_dir::SocketDir) -> Self {
+        Self {
+            mcpl: None,
+            lobby_conn: None,
+            lobby_state: LobbyState::new(),
+            lobby_session: None,
+            login_guard: login_guard::LoginGuard::default(),
+            lobby_reconnect: lobby_reconnect::ReconnectConfig::default(),
+            army: None,
+            profiles: profiles::Profiles::default(),
+            engines: EngineManager::new(
+                engine_dir,
+                write_dir_config.write_dir.clone(),
+                sockets,
+                write_dir_config.sai_bridge(),
+            ),
+            sai: SaiIpcServer::new(),
+            write_dir: write_dir_config.write_dir.clone(),
+            spring_home: write_dir_config.spring_home.clone(),
+            agent_name: write_dir_config.agent_name.clone(),
+            verbosity: HashMap::new(),
+            game_control: HashMap::new(),
+            recorders: HashMap::new(),
+            summaries: VecDeque::new(),
+            summary_retain: DEFAULT_SUMMARY_RETAIN,
+            auto_respond: autorespond::AutoResponder::default(),
+            economy_alerts: HashMap::new(),
+            income: HashMap::new(),
+            command_history: HashMap::new(),
+            command_history_size: command_history::DEFAULT_SIZE,
+            idle_builders: HashMap::new(),
+            eta_reminders: HashMap::new(),
+            game_starts: HashMap::new(),
+            threats: HashMap::new(),
+            unit_history: HashMap::new(),
+            factories: HashMap::new(),
+            groups: HashMap::new(),
+            macros: macros::MacroBook::load(&write_dir_config.write_dir),
+            expansions: HashMap::new(),
+            places: HashMap::new(),
+            unit_defs: HashMap::new(),
+            map_grids: HashMap::new(),
+            stream_observer: false,
+            stream_interval_secs: observer::StreamObserverConfig::default().interval_secs,
+            observers: HashMap::new(),
+            scope: None,
+            lobby_chats: BTreeSet::new(),
+            auto_join_founders: Vec::new(),
+            matchmaker_games: HashMap::new(),
+            battle_games: HashMap::new(),
+            downloads: content::Downloads::new(
+                std::env::var("PR_DOWNLOADER").unwrap_or_else(|_| "pr-downloader".into()).into(),
+                write_dir_config.spring_home.clone(),
+            ),
+            content_wait: None,
+            engine_installs: engine_install::EngineInstalls::new(engine_install::mirror_from_env()),
+            benchmarks: VecDeque::new(),
+            next_benchmark_id: 1,
+            custom_opponents: Vec::new(),
+            credentials: BTreeMap::new(),
+            audit: None,
+            closing: HashMap::new(),
+            kept_state: HashMap::new(),
+            status_board: None,
+            waiters: waiters::Waiters::default(),
+            current_request: None,
+            channel_changes: channel_changes::ChannelChanges::new(std::time::Duration::ZERO),
+            replay: HashMap::new(),
+            replay_size: mcpl_link::DeliveryConfig::default().replay_size,
+            policy_sent: HashMap::new(),
+        }
+    }
+
+    fn audit(&mut self, kind: audit::Kind, name: &str, details: &serde_json::Value, outcome: &Result<(), String>) {
+        if let Some(log) = &mut self.audit {
+            log.record(kind, name, details, outcome);
+        }
+    }
+
+    /// With the client gone since `since`, why the GameManager should exit
+    /// now, if it should: no games are left, or `linger` is up.
+    pub fn absent_client_exit(&self, since: std::time::Instant, linger: std::time::Duration) -> Option<String> {
+        if !self.engines.has_live_games() {
+            Some("no games left".into())
+        } else if since.elapsed() >= linger {
+            Some(format!("games still running after {:?}", linger))
+        } else {
+            None
+        }
+    }
+
+    // ── Entry points for the driver loop ──
+
+    /// Take on the config file's settings and what the client negotiated.
+    pub fn configure(
+        &mut self,
+        config: &config::GmConfig,
+        auto_respond: autorespond::AutoResponder,
+        client: mcpl_server::ClientOptions,
+    ) {
+        self.auto_respond = auto_respond;
+        self.apply_config(config);
+        self.stream_observer = client.stream_observer;
+        if let Some(chaos) = &config.chaos {
+            tracing::warn!("Chaos mode: injecting faults into SAI connections ({:?})", chaos);
+            self.sai.chaos = Some(chaos.clone());
+        }
+        if config.audit.enabled {
+            let dir = self.write_dir.join("audit");
+            match audit::AuditLog::open(&dir, config.audit.clone()) {
+                Ok(log) => self.audit = Some(log),
+                Err(e) => tracing::warn!("Audit log disabled, can't open {}: {}", dir.display(), e),
+            }
+        }
+        if let Some(scope) = &client.scope {
+            tracing::info!("Client runs in scope '{}'", scope.name);
+        }
+        self.scope = client.scope;
+        if self.stream_observer {
+            tracing::info!("Client observes the state stream");
+        }
+    }
+
+    /// Take on the config's reloadable sections (see `reload`). Games
+    /// already running keep the engine settings they started with.
+    fn apply_config(&mut self, config: &config::GmConfig) {
+        self.auto_join_founders = config.auto_join_founders.clone();
+        self.stream_interval_secs = config.stream_observer.interval_secs;
+        self.command_history_size = config.command_history.size;
+        self.custom_opponents = config.opponents.clone();
+        self.credentials = config.credentials.clone();
+        self.engines.engine_env = config.engine_env.clone();
+        self.engines.aggregate_events = match &config.aggregate_events {
+            Some(events) => events.clone(),
+            None => engine::DEFAULT_AGGREGATE_EVENTS.iter().map(|e| e.to_string()).collect(),
+        };
+        self.engines.update_cadence = config.update_cadence.clone();
+        self.sai.pacing = config.pacing.clone();
+        self.channel_changes.set_window(std::time::Duration::from_secs_f64(config.mcpl_delivery.changes_window_secs));
+        self.replay_size = config.mcpl_delivery.replay_size;
+        for buffer in self.replay.values_mut() {
+            buffer.resize(self.replay_size);
+        }
+        self.lobby_reconnect = config.lobby_reconnect.clone();
+        self.army = config.army.clone();
+        self.profiles = profiles::Profiles::new(&config.profiles);
+    }
+
+    /// Take on a reloaded config file, `loaded`, as far as a running
+    /// GameManager can, and tell the client what changed. A file that
+    /// doesn't load or validate changes nothing. Returns the config now in
+    /// effect.
+    pub fn reload_config(
+        &mut self,
+        current: &config::GmConfig,
+        loaded: Result<config::GmConfig, String>,
+    ) -> config::GmConfig {
+        let reload = loaded.and_then(|new| {
+            let reload = reload::plan(current, &new);
+            if reload.applied.contains(&"auto_respond") {
+                self.auto_respond.replace_rules(new.auto_respond.clone())?;
+            }
+            Ok(reload)
+        });
+        let reload = match reload {
+            Ok(reload) => reload,
+            Err(e) => {
+                tracing::warn!("Config not reloaded, keeping the running config: {}", e);
+                self.push_gm_event(reload::EVENT, serde_json::json!({"applied": [], "rejected": [], "error": e}));
+                return current.clone();
+            }
+        };
+        for section in &reload.rejected {
+            tracing::warn!("Config reload: {} can't change while running; restart to take it on", section);
+        }
+        if reload.applied.is_empty() {
+            tracing::info!("Config reloaded, nothing to apply");
+        } else {
+            tracing::info!("Config reloaded: {}", reload.applied.join(", "));
+        }
+        self.apply_config(&reload.config);
+        self.push_gm_event(reload::EVENT, reload.to_json());
+        reload.config
+    }
+
+    /// Listen for the games a previous run left queued; they launch as
+    /// slots allow.
+    pub fn restore_session(&mut self) {
+        for channel_id in self.engines.restore_session() {
+            self.listen_for_game(&channel_id);
+            tracing::info!("Restored queued game {}", channel_id);
+        }
+    }
+
+    /// A message from the lobby server, or why the connection ended.
+    pub async fn on_lobby_message(&mut self, result: Result<LobbyMessage, LobbyError>) {
+        match result {
+            Ok(msg) => self.handle_lobby_message(&msg).await,
+            Err(e) => self.handle_lobby_closed(&e).await,
+        }
+    }
+
+    /// A message from the MCPL client: requests are answered, notifications
+    /// logged.
+    pub async fn on_mcpl_message(&mut self, msg: McplIncoming) {
+        match msg {
+            McplIncoming::Request(req) => {
+                let params = req.params.unwrap_or_default();
+                self.current_request = Some(req.id);
+                let result = self.on_mcpl_request(&req.method, &params).await;
+                // Taken by a tool that answers later.
+                let Some(id) = self.current_request.take() else { return };
+                self.respond(id, result);
+            }
+            McplIncoming::Notification(notif) => match notif.method.as_str() {
+                "featureSets/update" => {
+                    tracing::info!("Feature sets update: {:?}", notif.params);
+                }
+                _ => {
+                    tracing::trace!("Unhandled notification: {}", notif.method);
+                }
+            },
+        }
+    }
+
+    fn respond(&self, id: serde_json::Value, result: serde_json::Value) {
+        if let Some(mcpl) = &self.mcpl {
+            if let Err(e) = mcpl.respond(id, result) {
+                tracing::error!("Failed to send response: {}", e);
+            }
+        }
+    }
+
+    /// Route an MCPL request to its handler and audit it. Returns the
+    /// response; null when a tool answers later, audited then.
+    pub async fn on_mcpl_request(&mut self, method: &str, params: &serde_json::Value) -> serde_json::Value {
+        let result = match method {
+            "tools/list" => self.tools_list(),
+            "tools/call" => {
+                let tool_name = params.get("name").and_then(|v| v.as_str()).unwrap_or("");
+                let tool_args = params.get("arguments").cloned().unwrap_or(serde_json::json!({}));
+                self.handle_tool_call(tool_name, &tool_args).await
+            }
+            "channels/open" => self.handle_channels_open(params).await,
+            "channels/close" => self.handle_channels_close(params).await,
+            "channels/list" => self.handle_channels_list().await,
+            "channels/publish" => self.handle_channels_publish(params).await,
+            "channels/replay" => self.handle_channels_replay(params),
+            "state/rollback" => self.handle_state_rollback(params).await,
+            "resources/list" => self.handle_resources_list(),
+            "resources/read" => self.handle_resources_read(params).await,
+            _ => {
+                tracing::warn!("Unknown MCPL method: {}", method);
+                serde_json::json!({
+                    "error": { "code": -32601, "message": format!("Method not found: {}", method) }
+                })
+            }
+        };
+        if !result.is_null() {
+            self.audit_request(method, params, &result);
+        }
+        result
+    }
+
+    /// A stdio client closed stdin: stop delivering to it, and let the
+    /// games play on.
+    pub async fn detach_client(&mut self) {
+        let waiting = self.waiters.take_all();
+        if !waiting.is_empty() {
+            tracing::info!("Dropped {} game_wait_for calls of the detached client", waiting.len());
+        }
+        if let Some(link) = self.mcpl.take() {
+            tracing::info!("MCPL delivery: {}", link.stats.summary());
+            link.close().await;
+        }
+    }
+
+    /// The periodic work, every 100 ms: SAI connections and their events,
+    /// engine checks, queued launches, downloads and stream lines.
+    pub async fn tick(&mut self, now: std::time::Instant) {
+        self.inject_sai_disconnects(now).await;
+        self.accept_sai_connections().await;
+        self.sai.flush_paced(now).await;
+        self.check_engines().await;
+        self.poll_game_starts(now).await;
+        self.launch_queued_games().await;
+        self.poll_benchmarks(now).await;
+        self.poll_downloads().await;
+        self.poll_engine_installs().await;
+        self.poll_lobby_reconnect(now).await;
+
+        // Read events from connected SAIs
+        let channel_ids: Vec<String> = self.sai.connections.keys().cloned().collect();
+        for channel_id in channel_ids {
+            // Non-blocking poll: collect the events this SAI has sent
+            let events = self.sai.drain_events(&channel_id).await;
+            for event in &events {
+                self.handle_sai_event(&channel_id, event).await;
+            }
+        }
+        self.expire_waiters(now);
+        self.flush_channels_changed(now);
+        self.emit_stream_lines().await;
+        self.publish_status(now);
+        if let Some(log) = &mut self.audit {
+            log.flush();
+        }
+    }
+
+    /// Refresh the status page's copy of our state, once a second.
+    fn publish_status(&mut self, now: std::time::Instant) {
+        if !self.status_board.as_mut().is_some_and(|board| board.take_due(now)) {
+            return;
+        }
+        let document = self.status_document();
+        let board = self.status_board.as_mut().unwrap();
+        board.retain(|id| self.engines.instances.contains_key(id));
+        board.view.publish(document);
+    }
+
+    /// The status page document: lobby state, and per game channel its
+    /// status, game state, engine process and recent events.
+    pub fn status_document(&self) -> serde_json::Value {
+        let mut ids: Vec<&String> = self.engines.instances.keys().collect();
+        ids.sort();
+        let channels: Vec<serde_json::Value> = ids
+            .into_iter()
+            .map(|id| {
+                let inst = &self.engines.instances[id];
+                let state = self.observers.get(id).map(|o| &o.state);
+                let crashed = match &inst.status {
+                    engine::GameStatus::Crashed(reason) => Some(reason.as_str()),
+                    _ => None,
+                };
+                serde_json::json!({
+                    "id": id,
+                    "map": inst.config.map,
+                    "game": inst.config.game,
+                    "status": format!("{:?}", inst.status),
+                    "saiConnected": self.sai.connections.contains_key(id),
+                    "frame": state.map(|s| s.frame),
+                    "economy": state.and_then(|s| s.economy),
+                    "units": state.map(|s| s.units.len()),
+                    "enemiesInSight": state.map(|s| s.enemies_in_los.len()),
+                    "threats": self.threats.get(id).map_or(0, |t| t.active().len()),
+                    "engine": {
+                        "pid": inst.process.as_ref().and_then(|p| p.id()),
+                        "running": inst.process.is_some(),
+                        "startedAt": inst.started_at.map(|t| chrono::DateTime::<chrono::Utc>::from(t).to_rfc3339()),
+                        "crashed": crashed,
+                    },
+                    "recentEvents": self.status_board.as_ref().map(|b| b.recent(id)).unwrap_or_default(),
+                })
+            })
+            .collect();
+        serde_json::json!({
+            "updatedAt": chrono::Utc::now().to_rfc3339(),
+            "mcplClient": self.mcpl.is_some(),
+            "lobby": self.lobby_state.status(),
+            "channels": channels,
+        })
+    }
+
+    /// Take the bridges that connected since the last tick and mark their
+    /// games running.
+    async fn accept_sai_connections(&mut self) {
+        let newly_connected = self.sai.accept_pending();
+        for channel_id in &newly_connected {
+            tracing::info!("SAI connected for channel {}", channel_id);
+            if let Some(inst) = self.engines.instances.get_mut(channel_id) {
+                inst.status = engine::GameStatus::Running;
+            }
+            self.send_channels_changed(
+                vec![],
+                vec![],
+                vec![ChannelDescriptor {
+                    id: channel_id.clone(),
+                    channel_type: "game".into(),
+                    label: "Game".into(),
+                    direction: ChannelDirection::Bidirectional,
+                    address: None,
+                    metadata: Some(serde_json::json!({"status": "running", "saiConnected": true})),
+                }],
+            )
+            .await;
+        }
+    }
+
+    /// Audit a tool call or channel operation with the response it got.
+    fn audit_request(&mut self, method: &str, params: &serde_json::Value, result: &serde_json::Value) {
+        let error_text = |v: &serde_json::Value| v.as_str().map(String::from).unwrap_or_else(|| v.to_string());
+        match method {
+            "tools/call" => {
+                let outcome = if result.get("isError").and_then(|v| v.as_bool()).unwrap_or(false) {
+                    Err(error_text(&result["content"][0]["text"]))
+                } else {
+                    Ok(())
+                };
+                let name = params.get("name").and_then(|v| v.as_str()).unwrap_or_default();
+                let args = params.get("arguments").cloned().unwrap_or_default();
+                self.audit(audit::Kind::Tool, name, &args, &outcome);
+            }
+            "channels/open" | "channels/close" | "channels/publish" => {
+                let outcome = match result.get("error") {
+                    Some(error) => Err(error.get("message").map(error_text).unwrap_or_else(|| error_text(error))),
+                    None => Ok(()),
+                };
+                self.audit(audit::Kind::Channel, method, params, &outcome);
+            }
+            _ => {}
+        }
+    }
+
+    /// The profile of the game on `channel_id`; generic for channels
+    /// without one.
+    fn game_profile(&self, channel_id: &str) -> &profiles::GameProfile {
+        match self.engines.instances.get(channel_id) {
+            Some(instance) => self.profiles.select(&instance.config.game),
+            None => self.profiles.generic(),
+        }
+    }
+
+    /// The opponent catalog, checked against the engine's and write dir's AIs.
+    fn opponent_catalog(&self) -> opponents::Catalog {
+        opponents::Catalog::new(&self.custom_opponents).scan(&[
+            self.engines.engine_dir.join("AI/Skirmish"),
+            self.write_dir.join("AI/Skirmish"),
+        ])
+    }
+
+    /// Check a local game's opponent: a catalog AI, or AgentBridge for
+    /// self-play, which needs both sides in AI mode.
+    fn check_opponent(&self, opponent: &str, game: &str, player_mode: bool) -> Result<(), String> {
+        if opponent != opponents::AGENT_AI {
+            return self.opponent_catalog().validate(opponent, game).map(|_| ());
+        }
+        if player_mode {
+            return Err("Self-play (opponent AgentBridge) needs AI mode (not player_mode)".into());
+        }
+        Ok(())
+    }
+
+    /// Handle an MCPL tool call from the AF client.
+    async fn handle_tool_call(
+        &mut self,
+        name: &str,
+        args: &serde_json::Value,
+    ) -> serde_json::Value {
+        if let Some(scope) = self.scope.as_ref().filter(|s| !s.allows_tool(name)) {
+            return scope.forbidden(&format!("Tool {}", name));
+        }
+        if let Some(session) = self.lobby_session.as_mut().filter(|s| s.must_queue(name, self.lobby_conn.is_some())) {
+            return match session.queue(name, args, std::time::Instant::now()) {
+                Ok(id) => serde_json::json!({
+                    "content": [{"type": "text", "text": format!(
+                        "Not connected to the lobby; queued as #{}, will send on reconnect (dropped after {}s)",
+                        id,
+                        session.max_age_secs()
+                    )}]
+                }),
+                Err(e) => serde_json::json!({
+                    "content": [{"type": "text", "text": format!("Not connected to the lobby, and {}", e)}],
+                    "isError": true
+                }),
+            };
+        }
+        match name {
+            "lobby_connect" => self.tool_lobby_connect(args).await,
+            "lobby_login" => self.tool_lobby_login(args).await,
+            "lobby_login_stored" => self.tool_lobby_login_stored(args).await,
+            "lobby_register" => self.tool_lobby_register(args).await,
+            "lobby_disconnect" => self.tool_lobby_disconnect().await,
+            "lobby_say" => self.tool_lobby_say(args).await,
+            "lobby_join_channel" => self.tool_lobby_join_channel(args).await,
+            "lobby_leave_channel" => self.tool_lobby_leave_channel(args).await,
+            "lobby_list_battles" => self.tool_lobby_list_battles(args).await,
+            "lobby_list_users" => self.tool_lobby_list_users(args).await,
+            "lobby_user_info" => self.tool_lobby_user_info(args),
+            "lobby_join_battle" => self.tool_lobby_join_battle(args).await,
+            "lobby_join_battle_by" => self.tool_lobby_join_battle_by(args).await,
+            "lobby_leave_battle" => self.tool_lobby_leave_battle().await,
+            "lobby_matchmaker_join" => self.tool_lobby_matchmaker_join(args).await,
+            "lobby_matchmaker_leave" => self.tool_lobby_matchmaker_leave().await,
+            "lobby_matchmaker_accept" => self.tool_lobby_matchmaker_accept(args).await,
+            "lobby_matchmaker_status" => self.tool_lobby_matchmaker_status().await,
+            "lobby_status" => self.tool_lobby_status(),
+            "lobby_start_game" => self.tool_lobby_start_game(args).await,
+            "game_list_opponents" => self.tool_game_list_opponents(args),
+            "lobby_open_battle" => self.tool_lobby_open_battle(args).await,
+            "lobby_add_bot" => self.tool_lobby_add_bot(args).await,
+            "lobby_remove_bot" => self.tool_lobby_remove_bot(args).await,
+            "lobby_start_battle" => self.tool_lobby_start_battle().await,
+            "game_stats" => self.tool_game_stats(args),
+            "game_pause" => self.tool_game_pause(args).await,
+            "game_resume" => self.tool_game_resume(args).await,
+            "game_set_speed" => self.tool_game_set_speed(args).await,
+            "game_end_turn" => self.tool_game_end_turn(args).await,
+            "game_run_benchmark" => self.tool_game_run_benchmark(args),
+            "game_benchmark_status" => self.tool_game_benchmark_status(args),
+            "game_cancel_queued" => self.tool_game_cancel_queued(args).await,
+            "game_summary" => self.tool_game_summary(args),
+            "game_wait_for" => self.tool_game_wait_for(args),
+            "game_say" => self.tool_game_say(args).await,
+            "game_group_create" => self.tool_game_group(name, args),
+            "game_group_add" => self.tool_game_group(name, args),
+            "game_group_remove" => self.tool_game_group(name, args),
+            "game_group_list" => self.tool_game_group(name, args),
+            "game_expand" => self.tool_game_expand(args).await,
+            "game_assist" => self.tool_game_assist(args).await,
+            "game_set_location" => self.tool_game_set_location(args),
+            "game_unitdefs" => self.tool_game_unitdefs(args).await,
+            "game_map" => self.tool_game_map(args).await,
+            name if queries::kind(name).is_some() => self.tool_game_query(name, args).await,
+            "game_command" => self.tool_game_command(args).await,
+            "game_command_history" => self.tool_game_command_history(args),
+            "game_unit_history" => self.tool_game_unit_history(args),
+            "game_factories" => self.tool_game_factories(args).await,
+            "gm_audit_tail" => self.tool_gm_audit_tail(args),
+            "gm_macro_define" => self.tool_gm_macro_define(args),
+            "gm_macro_run" => self.tool_gm_macro_run(args).await,
+            "gm_macro_list" => self.tool_gm_macro_list(),
+            "engine_install" => self.tool_engine_install(args),
+            _ => serde_json::json!({
+                "content": [{"type": "text", "text": format!("Unknown tool: {}", name)}],
+                "isError": true
+            }),
+        }
+    }
+
+    // ── MCPL channel methods ──
+
+    /// The tool list, without the tools the scope forbids.
+    fn tools_list(&self) -> serde_json::Value {
+        let mut tools = mcpl_server::lobby_tools();
+        if let Some(list) = tools["tools"].as_array_mut() {
+            list.extend(queries::tools());
+        }
+        if let (Some(scope), Some(list)) = (&self.scope, tools["tools"].as_array_mut()) {
+            list.retain(|t| t["name"].as_str().is_some_and(|name| scope.allows_tool(name)));
+        }
+        tools
+    }
+
+    /// The scope error for a channel operation the client may not use.
+    fn forbidden_channel_op(&self, op: &str) -> Option<serde_json::Value> {
+        let scope = self.scope.as_ref().filter(|s| !s.allows_channel_op(op))?;
+        Some(scope.forbidden(&format!("channels/{}", op)))
+    }
+
+    async fn handle_channels_open(
+        &mut self,
+        params: &serde_json::Value,
+    ) -> serde_json::Value {
+        if let Some(forbidden) = self.forbidden_channel_op("open") {
+            return forbidden;
+        }
+        let game = params
+            .get("address")
+            .and_then(|a| a.get("game"))
+            .and_then(|v| v.as_str())
+            .unwrap_or(profiles::DEFAULT_GAME);
+        let profile = self.profiles.select(game).clone();
+        let map = params
+            .get("address")
+            .and_then(|a| a.get("map"))
+            .and_then(|v| v.as_str())
+            .or(profile.default_map.as_deref())
+            .unwrap_or(profiles::DEFAULT_MAP);
+        let opponent = params
+            .get("address")
+            .and_then(|a| a.get("opponent"))
+            .and_then(|v| v.as_str())
+            .or(profile.default_opponent.as_deref());
+        let player_mode = params
+            .get("address")
+            .and_then(|a| a.get("player_mode"))
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+        if let Err(e) = self.check_opponent(opponent.unwrap_or(opponents::DEFAULT_OPPONENT), game, player_mode) {
+            return serde_json::json!({
+                "error": { "code": -32602, "message": e }
+            });
+        }
+        let headless = if player_mode {
+            false
+        } else {
+            params
+                .get("address")
+                .and_then(|a| a.get("headless"))
+                .and_then(|v| v.as_bool())
+                .unwrap_or(true)
+        };
+        let coop_agents = params
+            .get("address")
+            .and_then(|a| a.get("coop_agents"))
+            .and_then(|v| v.as_u64())
+            .unwrap_or(0) as usize;
+        if coop_agents > engine::MAX_COOP_AGENTS || (coop_agents > 0 && player_mode) {
+            return serde_json::json!({
+                "error": {
+                    "code": -32602,
+                    "message": format!(
+                        "coop_agents must be at most {}, and needs AI mode (not player_mode)",
+                        engine::MAX_COOP_AGENTS
+                    )
+                }
+            });
+        }
+        let verbosity = match params
+            .get("metadata")
+            .and_then(|m| m.get("verbosity"))
+            .and_then(|v| v.as_str())
+        {
+            Some(v) => match Verbosity::parse(v) {
+                Some(v) => Some(v),
+                None => {
+                    return serde_json::json!({
+                        "error": {
+                            "code": -32602,
+                            "message": format!("Unknown verbosity '{}' (expected terse, normal or raw)", v)
+                        }
+                    })
+                }
+            },
+            None => None,
+        };
+        let positions = match params.get("metadata").and_then(|m| m.get("positions")).and_then(|v| v.as_str()) {
+            Some(v) => match positions::PositionStyle::parse(v) {
+                Some(style) => style,
+                None => {
+                    return serde_json::json!({
+                        "error": {
+                            "code": -32602,
+                            "message": format!("Unknown positions '{}' (expected grid, relative or raw)", v)
+                        }
+                    })
+                }
+            },
+            None => positions::PositionStyle::default(),
+        };
+        let game_control = match GameControl::from_metadata(params.get("metadata")) {
+            Ok(c) => c,
+            Err(e) => {
+                return serde_json::json!({
+                    "error": { "code": -32602, "message": e }
+                })
+            }
+        };
+        let economy_thresholds = match economy_alerts::Thresholds::from_metadata(params.get("metadata")) {
+            Ok(t) => t,
+            Err(e) => {
+                return serde_json::json!({
+                    "error": { "code": -32602, "message": e }
+                })
+            }
+        };
+        let idle_settings = match idle_builders::IdleSettings::from_metadata(params.get("metadata")) {
+            Ok(s) => s,
+            Err(e) => {
+                return serde_json::json!({
+                    "error": { "code": -32602, "message": e }
+                })
+            }
+        };
+        let stream = match observer::StreamSettings::from_metadata(
+            params.get("metadata"),
+            self.stream_interval_secs,
+        ) {
+            Ok(s) => s,
+            Err(e) => {
+                return serde_json::json!({
+                    "error": { "code": -32602, "message": e }
+                })
+            }
+        };
+        let auto_respond = params
+            .get("metadata")
+            .and_then(|m| m.get("auto_respond"))
+            .and_then(|v| v.as_bool())
+            .unwrap_or(true);
+        let preserve_state = params
+            .get("metadata")
+            .and_then(|m| m.get("preserve_state"))
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+        let requested_id = params
+            .get("address")
+            .and_then(|a| a.get("channel_id"))
+            .and_then(|v| v.as_str());
+        let kept = match requested_id {
+            Some(id) => match self.claim_channel_id(id, preserve_state).await {
+                Ok(kept) => kept,
+                Err(e) => {
+                    return serde_json::json!({
+                        "error": { "code": -32602, "message": e }
+                    })
+                }
+            },
+            None => None,
+        };
+        // Settings given now win over the ones the last game left.
+        let preserved = kept.is_some();
+        let channel_ids::KeptState { verbosity: kept_verbosity, stream: kept_stream, groups } = kept.unwrap_or_default();
+        let verbosity = verbosity.or(kept_verbosity).unwrap_or_default();
+        let stream = match kept_stream {
+            Some(kept) if params.get("metadata").and_then(|m| m.get("stream")).is_none() => kept,
+            _ => stream,
+        };
+
+        match self
+            .engines
+            .start_local_game(
+                map,
+                game,
+                opponent,
+                headless,
+                player_mode,
+                &self.agent_name,
+                false,
+                coop_agents,
+                requested_id,
+                &profile.mod_options,
+            )
+            .await
+        {
+            Ok(channel_id) => {
+                self.listen_for_game(&channel_id);
+                self.verbosity.insert(channel_id.clone(), verbosity);
+                self.game_control.insert(channel_id.clone(), game_control);
+                self.auto_respond.set_enabled(&channel_id, auto_respond);
+                self.economy_alerts
+                    .insert(channel_id.clone(), economy_alerts::EconomyWatch::new(economy_thresholds));
+                self.idle_builders
+                    .insert(channel_id.clone(), idle_builders::IdleWatch::new(idle_settings));
+                self.observers.insert(channel_id.clone(), observer::ChannelObserver::new(stream));
+                self.groups.insert(channel_id.clone(), groups);
+                self.places.insert(channel_id.clone(), locations::Places::new(positions));
+
+                // Over the concurrency limit the game waits for a free slot.
+                let mut metadata = serde_json::json!({
+                    "map": map,
+                    "game": game,
+                    "status": "starting",
+                    "verbosity": verbosity.as_str(),
+                    "positions": positions.as_str(),
+                });
+                if preserved {
+                    metadata["preservedState"] = true.into();
+                }
+                if let Some(position) = self.engines.queue_position(&channel_id) {
+                    metadata["status"] = "Queued".into();
+                    metadata["queuePosition"] = position.into();
+                }
+                let mut coop = self.coop_descriptors(&channel_id);
+                if !coop.is_empty() {
+                    metadata["coopChannels"] = coop.iter().map(|c| c.id.clone()).collect::<Vec<_>>().into();
+                }
+                if let Some(opponent) = self.opponent_descriptor(&channel_id) {
+                    metadata["opponentChannel"] = opponent.id.clone().into();
+                    coop.push(opponent);
+                }
+
+                // Send channels/changed notification
+                let mut added = vec![ChannelDescriptor {
+                    id: channel_id.clone(),
+                    channel_type: "game".into(),
+                    label: format!("Game on {}", map),
+                    direction: ChannelDirection::Bidirectional,
+                    address: None,
+                    metadata: Some(metadata.clone()),
+                }];
+                added.extend(coop);
+                self.send_channels_changed(added, vec![], vec![]).await;
+
+                serde_json::json!({
+                    "channel": {
+                        "id": channel_id,
+                        "type": "game",
+                        "label": format!("Game on {}", map),
+                        "direction": "bidirectional",
+                        "metadata": metadata
+                    }
+                })
+            }
+            Err(e) => serde_json::json!({
+                "error": { "code": -32000, "message": e }
+            }),
+        }
+    }
+
+    async fn handle_channels_close(
+        &mut self,
+        params: &serde_json::Value,
+    ) -> serde_json::Value {
+        if let Some(forbidden) = self.forbidden_channel_op("close") {
+            return forbidden;
+        }
+        let channel_id = match params.get("channelId").and_then(|v| v.as_str()) {
+            Some(id) => id.to_string(),
+            None => {
+                return serde_json::json!({
+                    "closed": false,
+                    "error": "Missing channelId"
+                })
+            }
+        };
+        if let Some(chat) = ChatChannel::parse(&channel_id) {
+            return self.close_lobby_chat(chat).await;
+        }
+
+        let force = params.get("force").and_then(|v| v.as_bool()).unwrap_or(false);
+        if !force {
+            if self.closing.contains_key(&channel_id) {
+                return serde_json::json!({ "closed": true, "pending": true });
+            }
+            // A running engine gets to quit on its own; one that isn't
+            // running (queued, exited) has nothing left to send.
+            if self.engines.request_quit(&channel_id).is_ok() {
+                tracing::info!("Closing {}: waiting up to {:?} for the engine", channel_id, closing::GRACE_PERIOD);
+                self.closing.insert(channel_id, closing::Closing::new(std::time::Instant::now(), closing::GRACE_PERIOD));
+                return serde_json::json!({ "closed": true, "pending": true });
+            }
+        }
+
+        self.closing.remove(&channel_id);
+        let mut removed = vec![channel_id.clone()];
+        removed.extend(self.sai.coop_channels(&channel_id));
+        if let Err(e) = self.tear_down_game(&channel_id, &engine::GameStatus::Stopped).await {
+            return serde_json::json!({
+                "closed": false,
+                "error": e
+            });
+        }
+
+        // Notify channels/changed
+        self.send_channels_changed(vec![], removed, vec![])
+            .await;
+
+        serde_json::json!({ "closed": true })
+    }
+
+    /// The sub-channels of a game's co-op AgentBridges.
+    fn coop_descriptors(&self, channel_id: &str) -> Vec<ChannelDescriptor> {
+        let Some(instance) = self.engines.instances.get(channel_id) else { return Vec::new() };
+        instance
+            .config
+            .coop_teams
+            .iter()
+            .map(|&team| {
+                let id = sai_ipc::coop_channel_id(channel_id, team);
+                let connected = self.sai.connections.contains_key(&id);
+                ChannelDescriptor {
+                    label: format!("Game on {} (team {})", instance.config.map, team),
+                    channel_type: "game".into(),
+                    direction: ChannelDirection::Bidirectional,
+                    address: None,
+                    metadata: Some(serde_json::json!({
+                        "parent": channel_id,
+                        "team": team,
+                        "allyTeam": instance.config.ally_team_of(team),
+                        "side": "ally",
+                        "saiConnected": connected,
+                    })),
+                    id,
+                }
+            })
+            .collect()
+    }
+
+    /// The sub-channel of a self-play game's opponent AgentBridge.
+    fn opponent_descriptor(&self, channel_id: &str) -> Option<ChannelDescriptor> {
+        let config = &self.engines.instances.get(channel_id)?.config;
+        if !config.self_play() {
+            return None;
+        }
+        let team = config.opponent_team;
+        let id = sai_ipc::coop_channel_id(channel_id, team);
+        let connected = self.sai.connections.contains_key(&id);
+        Some(ChannelDescriptor {
+            label: format!("Game on {} (opponent, team {})", config.map, team),
+            channel_type: "game".into(),
+            direction: ChannelDirection::Bidirectional,
+            address: None,
+            metadata: Some(serde_json::json!({
+                "parent": channel_id,
+                "team": team,
+                "allyTeam": config.ally_team_of(team),
+                "side": "opponent",
+                "saiConnected": connected,
+            })),
+            id,
+        })
+    }
+
+    /// Listen for a game's bridges: the agent's on the game channel, and
+    /// each co-op one's on its sub-channel.
+    fn listen_for_game(&mut self, channel_id: &str) {
+        let Some(instance) = self.engines.instances.get(channel_id) else { return };
+        for (id, socket_path) in instance.config.sai_sockets(channel_id) {
+            if let Err(e) = self.sai.listen_for(&id, &socket_path) {
+                tracing::error!("Failed to set up SAI listener for {}: {}", id, e);
+            }
+        }
+    }
+
+    /// Drop everything kept for a game channel and stop its engine.
+    async fn tear_down_game(&mut self, channel_id: &str, status: &engine::GameStatus) -> Result<(), String> {
+        for coop in self.sai.coop_channels(channel_id) {
+            self.forget_channel(&coop);
+            self.finish_session(&coop, status);
+        }
+        self.forget_channel(channel_id);
+        self.matchmaker_games.remove(channel_id);
+        self.battle_games.remove(channel_id);
+        self.finish_session(channel_id, status);
+        self.engines.stop_game(channel_id).await
+    }
+
+    /// Make way for a new game on a chosen channel id: refuse one whose
+    /// game is still running or closing, and drop an ended game that still
+    /// holds it. Returns what the last game of that id left, if
+    /// `preserve_state`.
+    async fn claim_channel_id(
+        &mut self,
+        channel_id: &str,
+        preserve_state: bool,
+    ) -> Result<Option<channel_ids::KeptState>, String> {
+        if self.closing.contains_key(channel_id) {
+            return Err(format!("{} is still closing; try again once it's gone", channel_id));
+        }
+        self.engines.check_channel_id(channel_id)?;
+        // Its removal was announced when the engine exited.
+        if let Some(status) = self.engines.instances.get(channel_id).map(|inst| inst.status.clone()) {
+            self.tear_down_game(channel_id, &status).await?;
+        }
+        let kept = self.kept_state.remove(channel_id);
+        Ok(kept.filter(|_| preserve_state))
+    }
+
+    /// Drop the per-channel state of a game channel or co-op sub-channel.
+    /// A chosen id keeps its settings and groups for the next game.
+    fn forget_channel(&mut self, channel_id: &str) {
+        self.end_waiters(channel_id, "the channel closed", false);
+        self.sai.close_channel(channel_id);
+        let verbosity = self.verbosity.remove(channel_id);
+        if channel_ids::is_chosen(channel_id) {
+            let stream = self.observers.get(channel_id).map(|o| o.settings);
+            let groups = self.groups.remove(channel_id).unwrap_or_default();
+            self.kept_state
+                .insert(channel_id.to_string(), channel_ids::KeptState::new(verbosity, stream, groups));
+        }
+        self.game_control.remove(channel_id);
+        self.auto_respond.close_channel(channel_id);
+        self.economy_alerts.remove(channel_id);
+        self.idle_builders.remove(channel_id);
+        self.eta_reminders.remove(channel_id);
+        self.game_starts.remove(channel_id);
+        self.command_history.remove(channel_id);
+        self.threats.remove(channel_id);
+        self.unit_history.remove(channel_id);
+        self.factories.remove(channel_id);
+        self.replay.remove(channel_id);
+        self.observers.remove(channel_id);
+        self.expansions.remove(channel_id);
+        self.places.remove(channel_id);
+        self.income.remove(channel_id);
+        self.unit_defs.remove(channel_id);
+        self.map_grids.remove(channel_id);
+        self.policy_sent.remove(channel_id);
+    }
+
+    /// Announce engines that exited on their own (game over, crash) and
+    /// finish the graceful closes that are due.
+    async fn check_engines(&mut self) {
+        let mut changed = self.engines.check_all().await;
+        // Closing channels are finished off by poll_closing.
+        changed.retain(|(id, _)| !self.closing.contains_key(id));
+        for (channel_id, status) in &changed {
+            tracing::warn!("Engine {} status changed: {:?}", channel_id, status);
+            let mut removed = vec![channel_id.clone()];
+            removed.extend(self.sai.coop_channels(channel_id));
+            for id in &removed {
+                // The bridge's last events (release) may still be unread.
+                for event in self.sai.drain_events(id).await {
+                    self.handle_sai_event(id, &event).await;
+                }
+                self.end_waiters(id, &format!("the engine exited ({:?})", status), true);
+                self.finish_session(id, status);
+            }
+            self.sai.close_channel(channel_id);
+            self.send_channels_changed(vec![], removed, vec![]).await;
+        }
+        self.poll_closing(std::time::Instant::now()).await;
+    }
+
+    /// Finish the graceful closes whose engine exited or whose grace period
+    /// ran out: forward the bridge's last events, kill what's left, and
+    /// announce the removal with the game's final metadata.
+    async fn poll_closing(&mut self, now: std::time::Instant) {
+        let due: Vec<String> = self
+            .closing
+            .iter()
+            .filter(|(id, closing)| {
+                closing.expired(now) || self.engines.instances.get(*id).is_none_or(|inst| inst.process.is_none())
+            })
+            .map(|(id, _)| id.clone())
+            .collect();
+        for channel_id in due {
+            let mut removed = vec![channel_id.clone()];
+            removed.extend(self.sai.coop_channels(&channel_id));
+            for id in &removed {
+                for event in self.sai.drain_events(id).await {
+                    self.handle_sai_event(id, &event).await;
+                }
+            }
+            let Some(closing) = self.closing.remove(&channel_id) else { continue };
+            let instance = self.engines.instances.get(&channel_id);
+            let status = instance.map(|inst| inst.status.clone()).unwrap_or(engine::GameStatus::Stopped);
+            let started_at = instance.and_then(|inst| inst.started_at);
+            if instance.is_some_and(|inst| inst.process.is_some()) {
+                tracing::warn!("Engine for {} didn't quit within {:?}; killing it", channel_id, closing::GRACE_PERIOD);
+            }
+            let demo = started_at.and_then(|since| closing::find_demo(&self.write_dir, since));
+            let duration = started_at.and_then(|since| since.elapsed().ok());
+            let last_frame = self.sai.stats(&channel_id).and_then(|stats| stats.last_frame);
+            let metadata = closing.final_metadata(&status, demo.as_deref(), last_frame, duration);
+            let status = match status {
+                engine::GameStatus::Ended => engine::GameStatus::Ended,
+                _ => engine::GameStatus::Stopped,
+            };
+            if let Err(e) = self.tear_down_game(&channel_id, &status).await {
+                tracing::warn!("Closing {}: {}", channel_id, e);
+            }
+            tracing::info!("Closed {}: {}", channel_id, metadata);
+            let descriptor = ChannelDescriptor {
+                id: channel_id.clone(),
+                channel_type: "game".into(),
+                label: "Game".into(),
+                direction: ChannelDirection::Bidirectional,
+                address: None,
+                metadata: Some(metadata),
+            };
+            self.send_channels_changed(vec![], removed, vec![descriptor]).await;
+        }
+    }
+
+    async fn handle_channels_list(&self) -> serde_json::Value {
+        if let Some(forbidden) = self.forbidden_channel_op("list") {
+            return forbidden;
+        }
+        let channels: Vec<serde_json::Value> = self
+            .engines
+            .instances
+            .iter()
+            .flat_map(|(id, inst)| {
+                let connected = self.sai.connections.contains_key(id);
+                let mut channel = serde_json::json!({
+                    "id": id,
+                    "type": "game",
+                    "label": format!("Game on {}", inst.config.map),
+                    "direction": "bidirectional",
+                    "metadata": {
+                        "map": inst.config.map,
+                        "game": inst.config.game,
+                        "profile": self.profiles.select(&inst.config.game).name,
+                        "status": format!("{:?}", inst.status),
+                        "saiConnected": connected,
+                        "verbosity": self.verbosity.get(id).copied().unwrap_or_default().as_str(),
+                        "positions": self.places.get(id).map(|p| p.style()).unwrap_or_default().as_str(),
+                        "stats": self.sai.stats(id).map(|s| s.digest()),
+                        "gameControl": self.game_control.get(id).copied().unwrap_or_default().to_json(),
+                    }
+                });
+                let policy = &self.profiles.select(&inst.config.game).policy;
+                if !policy.is_permissive() {
+                    channel["metadata"]["policy"] = serde_json::to_value(policy).unwrap();
+                }
+                if let Some(position) = self.engines.queue_position(id) {
+                    channel["metadata"]["queuePosition"] = position.into();
+                }
+                if let Some(build) = self.sai.connections.get(id).and_then(|conn| conn.build.as_ref()) {
+                    channel["metadata"]["bridgeBuild"] = serde_json::to_value(build).unwrap();
+                }
+                if let Some(places) = self.places.get(id).filter(|p| !p.named().is_empty()) {
+                    channel["metadata"]["locations"] = serde_json::to_value(places.named()).unwrap();
+                }
+                if let Some(tracker) = self.threats.get(id) {
+                    channel["metadata"]["threats"] = serde_json::to_value(tracker.active()).unwrap();
+                }
+                if let Some(context) = self.matchmaker_games.get(id) {
+                    channel["metadata"]["matchmaker"] = context.to_json();
+                }
+                if let Some(battle_id) = self.battle_games.get(id) {
+                    channel["metadata"]["battleChannel"] = ChatChannel::Battle(*battle_id).id().into();
+                }
+                let mut coop = self.coop_descriptors(id);
+                if !coop.is_empty() {
+                    channel["metadata"]["coopChannels"] = coop.iter().map(|c| c.id.clone()).collect::<Vec<_>>().into();
+                }
+                if let Some(opponent) = self.opponent_descriptor(id) {
+                    channel["metadata"]["opponentChannel"] = opponent.id.clone().into();
+                    coop.push(opponent);
+                }
+                std::iter::once(channel).chain(coop.into_iter().map(|c| serde_json::to_value(c).unwrap()))
+            })
+            .chain(self.lobby_chats.iter().map(|chat| {
+                serde_json::to_value(chat.descriptor(self.lobby_chat_metadata(chat))).unwrap()
+            }))
+            .collect();
+
+        let mut channels = channels;
+        for channel in &mut channels {
+            let latest = channel["id"].as_str().and_then(|id| self.replay.get(id)).map(|b| b.latest());
+            if let Some(latest) = latest {
+                channel["metadata"]["latestSeq"] = latest.into();
+            }
+        }
+        serde_json::json!({ "channels": channels })
+    }
+
+    /// The channel's messages from `fromSeq` on, as far as they are kept.
+    fn handle_channels_replay(&self, params: &serde_json::Value) -> serde_json::Value {
+        if let Some(forbidden) = self.forbidden_channel_op("list") {
+            return forbidden;
+        }
+        let Some(channel_id) = params.get("channelId").and_then(|v| v.as_str()) else {
+            return serde_json::json!({"messages": [], "error": "Missing channelId"});
+        };
+        let from = params.get("fromSeq").and_then(|v| v.as_u64()).unwrap_or(1);
+        match self.replay.get(channel_id) {
+            Some(buffer) => buffer.since(from).to_json(),
+            None => serde_json::json!({"messages": [], "latestSeq": 0, "evicted": null}),
+        }
+    }
+
+    async fn handle_channels_publish(
+        &mut self,
+        params: &serde_json::Value,
+    ) -> serde_json::Value {
+        if let Some(forbidden) = self.forbidden_channel_op("publish") {
+            return forbidden;
+        }
+        let channel_id = match params.get("channelId").and_then(|v| v.as_str()) {
+            Some(id) => id,
+            None => {
+                return serde_json::json!({
+                    "delivered": false,
+                    "error": "Missing channelId"
+                })
+            }
+        };
+
+        let content = params
+            .get("content")
+            .and_then(|c| c.as_array())
+            .and_then(|arr| arr.first())
+            .and_then(|block| block.get("text"))
+            .map(|v| {
+                if let Some(s) = v.as_str() {
+                    s.to_string()
+                } else {
+                    // Agent sent JSON object instead of string — stringify it
+                    v.to_string()
+                }
+            })
+            .unwrap_or_default();
+        if let Some(chat) = ChatChannel::parse(channel_id) {
+            return self.publish_lobby_chat(chat, &content).await;
+        }
+
+        let cmds = match sai_ipc::parse_publish_command(&content, self.groups.get(channel_id), self.places.get(channel_id), self.game_profile(channel_id)) {
+            Ok(c) => c,
+            Err(e) => {
+                return serde_json::json!({
+                    "delivered": false,
+                    "error": e
+                })
+            }
+        };
+
+        let dry_run = params
+            .get("metadata")
+            .and_then(|m| m.get("dry_run"))
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+        if dry_run {
+            return match self.dry_run_commands(channel_id, &cmds).await {
+                Ok(results) => {
+                    let mut response = serde_json::json!({
+                        "delivered": false,
+                        "dryRun": {
+                            "valid": results.iter().all(|r| r.1.is_none()),
+                            "results": results.iter().map(|(label, error)| {
+                                serde_json::json!({"command": label, "ok": error.is_none(), "error": error})
+                            }).collect::<Vec<_>>(),
+                        }
+                    });
+                    if let Some((label, Some(error))) = results.iter().find(|r| r.1.is_some()) {
+                        response["error"] = format!("{}: {}", label, error).into();
+                    }
+                    response
+                }
+                Err(e) => serde_json::json!({
+                    "delivered": false,
+                    "error": e
+                }),
+            };
+        }
+
+        // A batch goes out whole or not at all as far as the policy goes.
+        if let Err(violation) = self.police(channel_id, &cmds, command_history::Source::Publish, false) {
+            return serde_json::json!({
+                "delivered": false,
+                "error": violation.text(),
+                "policy": violation.to_json(),
+            });
+        }
+        let delayed = match self.send_commands(channel_id, &cmds, command_history::Source::Publish).await {
+            Ok(delayed) => delayed,
+            Err(e) => {
+                return serde_json::json!({
+                    "delivered": false,
+                    "error": e
+                })
+            }
+        };
+        let mut response = serde_json::json!({
+            "delivered": true,
+            "messageId": uuid::Uuid::new_v4().to_string()
+        });
+        if delayed > 0 {
+            response["pacing"] = self.pacing_note(channel_id, delayed);
+        }
+        let remind = params
+            .get("metadata")
+            .and_then(|m| m.get("remind"))
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+        if let Some((_, eta)) = self.arrival(channel_id, &cmds, remind) {
+            response["eta"] = eta;
+        }
+        response
+    }
+
+    /// Send commands to a channel's SAI in order, stopping at the first
+    /// that can't be sent. Returns how many pacing held back.
+    async fn send_commands(
+        &mut self,
+        channel_id: &str,
+        cmds: &[SaiCommand],
+        source: command_history::Source,
+    ) -> Result<usize, String> {
+        let mut delayed = 0;
+        for cmd in cmds {
+            if let Paced::Queued { .. } = self.send_command(channel_id, cmd, source).await? {
+                delayed += 1;
+            }
+            if let Some(watch) = self.idle_builders.get_mut(channel_id) {
+                watch.ordered(cmd);
+            }
+            if let Some(tracker) = self.factories.get_mut(channel_id) {
+                tracker.ordered(cmd);
+            }
+            if let Some(reminders) = self.eta_reminders.get_mut(channel_id) {
+                reminders.ordered(cmd);
+            }
+        }
+        Ok(delayed)
+    }
+
+    /// Arrival estimates for the move, fight and patrol orders among
+    /// `cmds`, from the units' last known positions and their defs' speed;
+    /// with `remind`, a reminder per destination when the slowest should
+    /// be there. Call after sending, so the orders don't cancel their own
+    /// reminders.
+    fn arrival(&mut self, channel_id: &str, cmds: &[SaiCommand], remind: bool) -> Option<(String, serde_json::Value)> {
+        let history = self.unit_history.get(channel_id)?;
+        let catalog = self.unit_defs.get(channel_id)?;
+        let locate = |unit| {
+            let track = history.track(unit)?;
+            let pos = track.samples.back()?.pos;
+            Some((pos, catalog.named(track.unit_name.as_deref()?)?.speed))
+        };
+        let estimates: Vec<(eta::UnitEta, [f32; 2], &str)> = cmds
+            .iter()
+            .filter_map(|cmd| eta::estimate(cmd, &locate).map(|(eta, target)| (eta, target, cmd.type_name())))
+            .collect();
+        let etas: Vec<eta::UnitEta> = estimates.iter().map(|e| e.0).collect();
+        let summary = eta::summary(&etas)?;
+        if remind {
+            let reminders = self.eta_reminders.entry(channel_id.to_string()).or_default();
+            let mut targets: Vec<([f32; 2], &str)> = Vec::new();
+            for (_, target, label) in &estimates {
+                if !targets.iter().any(|(t, _)| t == target) {
+                    targets.push((*target, *label));
+                }
+            }
+            for (target, label) in targets {
+                let going: Vec<eta::UnitEta> =
+                    estimates.iter().filter(|e| e.1 == target).map(|e| e.0).collect();
+                reminders.schedule(&going, target, label.to_string());
+            }
+        }
+        Some(summary)
+    }
+
+    /// What to tell the agent when pacing held back `delayed` of its
+    /// commands.
+    fn pacing_note(&self, channel_id: &str, delayed: usize) -> serde_json::Value {
+        let backlog = self.sai.pacing_counts(channel_id).map_or(0, |counts| counts.backlog);
+        serde_json::json!({
+            "delayed": delayed,
+            "backlog": backlog,
+            "note": format!(
+                "{} commands were queued to pace the bridge ({} waiting); they go out in order over the next moments",
+                delayed, backlog
+            ),
+        })
+    }
+
+    /// Send one command to a channel's SAI, recording it in the channel's
+    /// command history and session log whether or not it got through. Its
+    /// history sequence number goes along as its `command_ref`.
+    /// Commands over the pacing budget are queued, and count as sent. The
+    /// agent's commands go through the command policy first.
+    async fn send_command(
+        &mut self,
+        channel_id: &str,
+        cmd: &SaiCommand,
+        source: command_history::Source,
+    ) -> Result<Paced, String> {
+        let size = self.command_history_size;
+        let command_ref = self
+            .command_history
+            .entry(channel_id.to_string())
+            .or_insert_with(|| command_history::CommandHistory::new(size))
+            .next_seq();
+        let sent = match self.police(channel_id, std::slice::from_ref(cmd), source, true) {
+            Ok(()) => self.sai.send_to(channel_id, cmd, command_ref).await,
+            Err(violation) => Err(violation.text()),
+        };
+        let frame = self.sai.stats(channel_id).and_then(|s| s.last_frame);
+        let entry = self
+            .command_history
+            .get_mut(channel_id)
+            .expect("created above")
+            .record(cmd, source, frame, &sent.clone().map(|_| ()));
+        if let Some(recorder) = self.recorders.get_mut(channel_id) {
+            if let Err(e) = recorder.record_command(entry) {
+                tracing::warn!("Failed to record command for {}: {}", channel_id, e);
+            }
+        }
+        sent
+    }
+
+    /// Check commands against the channel's command policy, counting them
+    /// towards its rate budgets if `count` is set and they all pass. A
+    /// violation is audited. The GameManager's own commands aren't the
+    /// agent's, and pass unchecked.
+    fn police(
+        &mut self,
+        channel_id: &str,
+        cmds: &[SaiCommand],
+        source: command_history::Source,
+        count: bool,
+    ) -> Result<(), policy::Violation> {
+        if source == command_history::Source::Automation {
+            return Ok(());
+        }
+        let policy = &self.game_profile(channel_id).policy;
+        let army = self.observers.get(channel_id).map(|o| &o.state.army);
+        let def_of = |unit| army.and_then(|a| a.def_of(unit)).map(String::from);
+        let mut sent = self.policy_sent.get(channel_id).cloned().unwrap_or_default();
+        let now = std::time::Instant::now();
+        let checked = cmds.iter().try_for_each(|cmd| policy.check(cmd, def_of, &mut sent, now).map_err(|v| (cmd, v)));
+        match checked {
+            Ok(()) => {
+                if count {
+                    self.policy_sent.insert(channel_id.to_string(), sent);
+                }
+                Ok(())
+            }
+            Err((cmd, violation)) => {
+                tracing::info!("Command policy stopped {} on {}: {}", violation.command, channel_id, violation.message);
+                let details = serde_json::json!({
+                    "channel_id": channel_id,
+                    "source": source.as_str(),
+                    "rule": violation.rule.as_str(),
+                    "command": cmd,
+                });
+                self.audit(audit::Kind::Policy, &violation.command, &details, &Err(violation.text()));
+                Err(violation)
+            }
+        }
+    }
+
+    /// Have the channel's bridge validate commands without executing them.
+    /// Returns each command's label with the error it would hit, if any.
+    async fn dry_run_commands(
+        &mut self,
+        channel_id: &str,
+        cmds: &[SaiCommand],
+    ) -> Result<Vec<(String, Option<String>)>, String> {
+        let verdicts = self.sai.dry_run(channel_id, cmds, sai_ipc::DRY_RUN_TIMEOUT).await?;
+        Ok(cmds.iter().map(sai_ipc::command_label).zip(verdicts).collect())
+    }
+
+    async fn handle_state_rollback(
+        &mut self,
+        params: &serde_json::Value,
+    ) -> serde_json::Value {
+        let _feature_set = params
+            .get("featureSet")
+            .and_then(|v| v.as_str())
+            .unwrap_or("game");
+        let checkpoint = params
+            .get("checkpoint")
+            .and_then(|v| v.as_str())
+            .unwrap_or("");
+
+        // For now, rollback is a placeholder — full implementation
+        // requires engine savestate support
+        serde_json::json!({
+            "success": false,
+            "checkpoint": checkpoint,
+            "reason": "Rollback not yet implemented — requires engine savestate support"
+        })
+    }
+
+    // ── Self-test ──
+
+    /// The self-test stages that need a GameManager: connect the stub
+    /// bridge, forward its events to the client, and run commands through
+    /// it. `client` receives whatever the GameManager sends to its MCPL
+    /// client. The stub is returned once it connected.
+    pub async fn self_test_pipeline(
+        &mut self,
+        socket: &str,
+        client: &mut tokio::sync::mpsc::UnboundedReceiver<serde_json::Value>,
+    ) -> (Vec<self_test::Stage>, Option<self_test::StubBridge>) {
+        use self_test::{Stage, CHANNEL, STAGE_TIMEOUT, STUB_UNIT};
+        let mut stages = Vec::new();
+
+        let connected = match self.sai.listen_for(CHANNEL, socket) {
+            Ok(()) => self_test::StubBridge::spawn(socket),
+            Err(e) => Err(e),
+        };
+        let stub = match connected {
+            Ok(stub) => stub,
+            Err(e) => {
+                stages.push(Stage::new("sai connect", Err(e)));
+                return (stages, None);
+            }
+        };
+        let deadline = tokio::time::Instant::now() + STAGE_TIMEOUT;
+        while !self.sai.connections.contains_key(CHANNEL) && tokio::time::Instant::now() < deadline {
+            self.sai.accept_pending();
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        if !self.sai.connections.contains_key(CHANNEL) {
+            stages.push(Stage::new("sai connect", Err("The stub bridge never connected".into())));
+            return (stages, Some(stub));
+        }
+        stages.push(Stage::new("sai connect", Ok(format!("stub bridge connected on {}", socket))));
+
+        // Everything the stub opens with except update ticks reaches the client.
+        let expected = ["init", "roster", "unit_finished"];
+        let forwarded = self.self_test_wait(client, &expected).await;
+        stages.push(Stage::new(
+            "event forwarding",
+            if forwarded.len() == expected.len() {
+                Ok(format!("{} forwarded as channels/incoming", expected.join(", ")))
+            } else {
+                Err(format!("Only {:?} of {:?} reached the client", forwarded, expected))
+            },
+        ));
+
+        let cmd = SaiCommand::Stop { unit_id: STUB_UNIT };
+        let outcome = match self.send_command(CHANNEL, &cmd, command_history::Source::Tool).await {
+            Ok(_) if self.self_test_wait(client, &["command_finished"]).await.is_empty() => {
+                Err("The stub's command_finished never reached the client".into())
+            }
+            Ok(_) => Ok(format!("{} sent, command_finished came back", sai_ipc::command_label(&cmd))),
+            Err(e) => Err(e),
+        };
+        stages.push(Stage::new("commands", outcome));
+
+        let outcome = match self.dry_run_commands(CHANNEL, &[cmd]).await {
+            Ok(results) => match results.into_iter().find_map(|(label, error)| Some(format!("{}: {}", label, error?))) {
+                Some(failed) => Err(failed),
+                None => Ok("the bridge validated a command without running it".into()),
+            },
+            Err(e) => Err(e),
+        };
+        stages.push(Stage::new("dry run", outcome));
+        (stages, Some(stub))
+    }
+
+    /// Pump the stub's events until the client received each of `types`
+    /// for the self-test channel, or the stage times out. Returns the types
+    /// that arrived.
+    async fn self_test_wait(
+        &mut self,
+        client: &mut tokio::sync::mpsc::UnboundedReceiver<serde_json::Value>,
+        types: &[&str],
+    ) -> Vec<String> {
+        let deadline = tokio::time::Instant::now() + self_test::STAGE_TIMEOUT;
+        let mut seen = Vec::new();
+        while seen.len() < types.len() && tokio::time::Instant::now() < deadline {
+            for event in self.sai.drain_events(self_test::CHANNEL).await {
+                self.handle_sai_event(self_test::CHANNEL, &event).await;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            while let Ok(message) = client.try_recv() {
+                let Some(event) = self_test::forwarded_event(&message) else { continue };
+                if types.contains(&event.as_str()) && !seen.contains(&event) {
+                    seen.push(event);
+                }
+            }
+        }
+        seen
+    }
+
+    // ── Notification helpers ──
+
+    /// Queue channels/changed updates. They go out once their channel's
+    /// window has passed, merged with the channel's later changes.
+    pub async fn send_channels_changed(
+        &mut self,
+        added: Vec<ChannelDescriptor>,
+        removed: Vec<String>,
+        updated: Vec<ChannelDescriptor>,
+    ) {
+        let now = std::time::Instant::now();
+        self.channel_changes.push(now, channel_changes::Changes { added, removed, updated });
+        self.flush_channels_changed(now);
+    }
+
+    /// Send the channels/changed updates due by `now`, as one notification.
+    pub fn flush_channels_changed(&mut self, now: std::time::Instant) {
+        let changes = self.channel_changes.due(now);
+        if changes.is_empty() {
+            return;
+        }
+        let channel_changes::Changes { added, removed, updated } = changes;
+        if let Some(mcpl) = &self.mcpl {
+            let params = ChannelsChangedParams {
+                added: if added.is_empty() {
+                    None
+                } else {
+                    Some(added)
+                },
+                removed: if removed.is_empty() {
+                    None
+                } else {
+                    Some(removed)
+                },
+                updated: if updated.is_empty() {
+                    None
+                } else {
+                    Some(updated)
+                },
+            };
+            if let Err(e) = mcpl.notify(method::CHANNELS_CHANGED, Some(serde_json::to_value(&params).unwrap())) {
+                tracing::warn!("Failed to queue channels/changed: {}", e);
+            }
+        }
+    }
+
+    /// Handle one event from a channel's SAI bridge.
+    async fn handle_sai_event(&mut self, channel_id: &str, event: &sai_ipc::SaiEvent) {
+        self.record_sai_event(channel_id, event);
+        // The answer to the factory tracker's own query.
+        if let sai_ipc::SaiEvent::UnitQueues { queues, .. } = event {
+            if let Some(tracker) = self.factories.get_mut(channel_id) {
+                tracker.answered(queues);
+            }
+            return;
+        }
+        // A unit handed to us may be of a def we never built: look it up,
+        // so the trackers below know whether it builds or earns.
+        if let sai_ipc::SaiEvent::UnitGiven { unit_name: Some(name), .. } = event {
+            if !self.unit_defs.contains_key(channel_id) {
+                if let Err(e) = self.unit_def_catalog(channel_id, sai_ipc::UNIT_DEFS_TIMEOUT).await {
+                    tracing::debug!("No unit defs for received {} on {}: {}", name, channel_id, e);
+                }
+            }
+        }
+        if let Some(board) = &mut self.status_board {
+            if !matches!(event, sai_ipc::SaiEvent::Update { .. }) {
+                board.observe(channel_id, sai_ipc::summarize_event(event));
+            }
+        }
+        if let Some(closing) = self.closing.get_mut(channel_id) {
+            closing.observe(event);
+        }
+        if let Some(run) = self.benchmarks.back_mut() {
+            run.observe(channel_id, event);
+        }
+        let income_loss = self
+            .income
+            .entry(channel_id.to_string())
+            .or_default()
+            .observe(event, self.unit_defs.get(channel_id));
+        if let sai_ipc::SaiEvent::Update { frame, economy: Some(economy), .. } = event {
+            self.check_economy(channel_id, *frame, *economy).await;
+        }
+        self.check_threats(channel_id, event).await;
+        self.check_groups(channel_id, event).await;
+        self.check_idle_builders(channel_id, event).await;
+        self.check_factories(channel_id, event).await;
+        self.check_command_history(channel_id, event).await;
+        self.check_eta_reminders(channel_id, event).await;
+        self.expansions.entry(channel_id.to_string()).or_default().observe(event);
+        self.places.entry(channel_id.to_string()).or_default().observe(event);
+        let interval = self.stream_interval_secs;
+        self.observers
+            .entry(channel_id.to_string())
+            .or_insert_with(|| observer::ChannelObserver::new(observer::StreamSettings::new(interval)))
+            .state
+            .observe(event);
+        self.check_waiters(channel_id, event);
+        match event {
+            // Update ticks are noise for the LLM — except the turn-mode
+            // pause, which is the agent's cue to act.
+            sai_ipc::SaiEvent::Update { awaiting_commands: false, .. } => return,
+            sai_ipc::SaiEvent::Update { awaiting_commands: true, .. } => {
+                let control = self.game_control.entry(channel_id.to_string()).or_default();
+                control.paused = true;
+                control.awaiting_turn = true;
+            }
+            // A player paused or changed speed: the agent needs to know why
+            // the frames stopped. Repeats of what we did ourselves don't.
+            sai_ipc::SaiEvent::GamePaused { .. }
+            | sai_ipc::SaiEvent::GameResumed
+            | sai_ipc::SaiEvent::SpeedChanged { .. }
+                if !self.game_control.entry(channel_id.to_string()).or_default().observe(event) =>
+            {
+                return
+            }
+            sai_ipc::SaiEvent::Init { protocol_version, .. }
+                if *protocol_version != Some(sai_ipc::PROTOCOL_VERSION) =>
+            {
+                tracing::warn!(
+                    "SAI bridge for {} speaks protocol {:?}, expected {}",
+                    channel_id, protocol_version, sai_ipc::PROTOCOL_VERSION
+                );
+            }
+            sai_ipc::SaiEvent::Init { build: Some(build), .. } => {
+                tracing::info!("SAI bridge for {} is {}", channel_id, build.describe());
+            }
+            // Follows init: the agent learns what it owns as the game starts
+            // instead of piecing it together from later unit events.
+            sai_ipc::SaiEvent::Roster { units, .. } => {
+                tracing::info!("SAI for {} reports {} starting units", channel_id, units.len());
+            }
+            _ => {}
+        }
+        if let sai_ipc::SaiEvent::Init { .. } = event {
+            self.apply_turn_mode(channel_id).await;
+        }
+        self.forward_sai_event(channel_id, event, income_loss).await;
+        let start = self.game_starts.entry(channel_id.to_string()).or_default().observe(event, std::time::Instant::now());
+        if let Some(start) = start {
+            self.announce_game_start(channel_id, start).await;
+        }
+        if let sai_ipc::SaiEvent::Message { player, player_name, text, .. } = event {
+            self.auto_respond_to(channel_id, &sai_ipc::player_label(*player, player_name), text)
+                .await;
+        }
+    }
+
+    /// Chaos mode: cut the SAI connections that are due and tell the client
+    /// they're down until their bridges reconnect.
+    async fn inject_sai_disconnects(&mut self, now: std::time::Instant) {
+        let cut = self.sai.inject_disconnects(now);
+        if cut.is_empty() {
+            return;
+        }
+        let updated = cut
+            .into_iter()
+            .map(|id| ChannelDescriptor {
+                id,
+                channel_type: "game".into(),
+                label: "Game".into(),
+                direction: ChannelDirection::Bidirectional,
+                address: None,
+                metadata: Some(serde_json::json!({"status": "running", "saiConnected": false})),
+            })
+            .collect();
+        self.send_channels_changed(vec![], vec![], updated).await;
+    }
+
+    /// Send the game_started summaries whose roster never came.
+    async fn poll_game_starts(&mut self, now: std::time::Instant) {
+        let due: Vec<(String, game_start::GameStart)> = self
+            .game_starts
+            .iter_mut()
+            .filter_map(|(id, watch)| Some((id.clone(), watch.poll(now)?)))
+            .collect();
+        for (channel_id, start) in due {
+            self.announce_game_start(&channel_id, start).await;
+        }
+    }
+
+    /// Push the one-off game_started summary, with the map the game was
+    /// launched on.
+    async fn announce_game_start(&mut self, channel_id: &str, mut start: game_start::GameStart) {
+        start.map = self
+            .engines
+            .instances
+            .get(sai_ipc::game_channel_id(channel_id))
+            .map(|inst| inst.config.map.clone());
+        let notice = self.notice_message(channel_id, start.text(), serde_json::json!({ "gameStarted": start }));
+        self.push_incoming(notice).await;
+    }
+
+    /// Feed an economy snapshot to the channel's watch and push any alerts.
+    /// Channels from the lobby get the default thresholds.
+    async fn check_economy(&mut self, channel_id: &str, frame: i32, economy: sai_protocol::Economy) {
+        let watch = match self.economy_alerts.entry(channel_id.to_string()) {
+            std::collections::hash_map::Entry::Occupied(entry) => entry.into_mut(),
+            std::collections::hash_map::Entry::Vacant(entry)
+                if self.engines.instances.contains_key(sai_ipc::game_channel_id(channel_id)) =>
+            {
+                entry.insert(economy_alerts::EconomyWatch::default())
+            }
+            // Not one of our games (or one already torn down): no watch.
+            std::collections::hash_map::Entry::Vacant(_) => return,
+        };
+        let alerts = watch.observe(frame, economy);
+        for mut alert in alerts {
+            if let Some(income) = self.income.get(channel_id) {
+                income.annotate(&mut alert, self.unit_defs.get(channel_id));
+            }
+            let notice = self.notice_message(
+                channel_id,
+                alert.text,
+                serde_json::json!({
+                    "economyAlert": {
+                        "kind": alert.kind.as_str(),
+                        "frame": alert.frame,
+                        "details": alert.details,
+                    }
+                }),
+            );
+            self.push_incoming(notice).await;
+        }
+    }
+
+    /// Feed an event to the channel's position history and threat tracker,
+    /// and push any alerts with where the threats are going.
+    async fn check_threats(&mut self, channel_id: &str, event: &sai_ipc::SaiEvent) {
+        let history = self.unit_history.entry(channel_id.to_string()).or_default();
+        history.observe(event);
+        let tracker = self.threats.entry(channel_id.to_string()).or_default();
+        let mut alerts = tracker.observe(event);
+        if let sai_ipc::SaiEvent::Update { .. } = event {
+            tracker.track_motion(history);
+        }
+        for alert in &mut alerts {
+            if alert.kind != threats::ThreatAlertKind::Cleared {
+                alert.threat.motion = history.group_motion(&alert.threat.enemies);
+            }
+        }
+        for alert in alerts {
+            let text = alert.text(self.places.get(channel_id).map(|p| p.context()).as_ref());
+            let notice = self.notice_message(
+                channel_id,
+                text,
+                serde_json::json!({
+                    "threatAlert": {
+                        "kind": alert.kind.as_str(),
+                        "threat": alert.threat,
+                    }
+                }),
+            );
+            self.push_incoming(notice).await;
+        }
+    }
+
+    /// Drop dead units from the channel's groups and report groups that
+    /// fell below their alert size.
+    async fn check_groups(&mut self, channel_id: &str, event: &sai_ipc::SaiEvent) {
+        let Some(groups) = self.groups.get_mut(channel_id) else { return };
+        for alert in groups.observe(event) {
+            let notice = self.notice_message(
+                channel_id,
+                alert.text(),
+                serde_json::json!({
+                    "groupAlert": {
+                        "group": alert.group,
+                        "size": alert.size,
+                        "alertBelow": alert.alert_below,
+                        "lostUnit": alert.lost,
+                    }
+                }),
+            );
+            self.push_incoming(notice).await;
+        }
+    }
+
+    /// Track the channel's builders and report those idle past the grace
+    /// period. Channels from the lobby get the default settings.
+    async fn check_idle_builders(&mut self, channel_id: &str, event: &sai_ipc::SaiEvent) {
+        let alerts = self.idle_builders.entry(channel_id.to_string()).or_default().observe(event);
+        for alert in alerts {
+            let notice = self.notice_message(
+                channel_id,
+                alert.text(),
+                serde_json::json!({
+                    "idleBuilder": {
+                        "unit": alert.unit,
+                        "unitName": alert.unit_name,
+                        "kind": alert.kind.as_str(),
+                        "pos": alert.pos,
+                        "idleFrames": alert.idle_frames,
+                    }
+                }),
+            );
+            self.push_incoming(notice).await;
+        }
+    }
+
+    /// Tell the agent when units it asked to hear about should have
+    /// arrived.
+    async fn check_eta_reminders(&mut self, channel_id: &str, event: &sai_ipc::SaiEvent) {
+        let Some(reminders) = self.eta_reminders.get_mut(channel_id) else { return };
+        for reminder in reminders.observe(event) {
+            let notice = self.notice_message(
+                channel_id,
+                reminder.text(),
+                serde_json::json!({
+                    "etaReminder": {
+                        "units": reminder.units,
+                        "target": reminder.target,
+                        "command": reminder.label,
+                    }
+                }),
+            );
+            self.push_incoming(notice).await;
+        }
+    }
+
+    /// Settle commands in the channel's history, and tell the agent about
+    /// those it asked to hear about.
+    async fn check_command_history(&mut self, channel_id: &str, event: &sai_ipc::SaiEvent) {
+        let Some(history) = self.command_history.get_mut(channel_id) else { return };
+        for entry in history.observe(event) {
+            let notice = self.notice_message(
+                channel_id,
+                format!("Command {}", entry.line()),
+                serde_json::json!({ "commandSettled": entry.to_json() }),
+            );
+            self.push_incoming(notice).await;
+        }
+    }
+
+    /// Track the channel's factories, and ask the bridge for their queues
+    /// when it's time.
+    async fn check_factories(&mut self, channel_id: &str, event: &sai_ipc::SaiEvent) {
+        let tracker = self.factories.entry(channel_id.to_string()).or_default();
+        tracker.observe(event, self.unit_defs.get(channel_id));
+        if !matches!(event, sai_ipc::SaiEvent::Update { .. }) {
+            return;
+        }
+        let Some(units) = tracker.due() else { return };
+        if let Err(e) = self.sai.request_unit_queues(channel_id, units).await {
+            tracing::debug!("No factory queues for {}: {}", channel_id, e);
+        }
+    }
+
+    /// State lines due at `now`, as stream event params.
+    fn stream_lines(&mut self, now: std::time::Instant) -> Vec<serde_json::Value> {
+        let mut lines = Vec::new();
+        for (channel_id, observer) in &mut self.observers {
+            if !observer.take_due(now) {
+                continue;
+            }
+            let threats = self.threats.get(channel_id).map(|t| t.active()).unwrap_or_default();
+            let army_config = match (&self.army, self.engines.instances.get(channel_id)) {
+                (Some(army), _) => army,
+                (None, Some(instance)) => &self.profiles.select(&instance.config.game).army,
+                (None, None) => &self.profiles.generic().army,
+            };
+            let army = observer.army_report(army_config, self.unit_defs.get(channel_id));
+            let catalog = self.unit_defs.get(channel_id);
+            let factories = self.factories.get(channel_id).map(|f| f.summaries(catalog)).unwrap_or_default();
+            let (text, state) =
+                observer.state.line(observer.settings.include, threats, army, factories::digest(&factories));
+            lines.push(serde_json::json!({
+                "channelId": channel_id,
+                "timestamp": chrono::Utc::now().to_rfc3339(),
+                "content": [ContentBlock::text(text)],
+                "state": state,
+            }));
+        }
+        lines
+    }
+
+    /// Send the state lines that are due, if the client observes the stream.
+    async fn emit_stream_lines(&mut self) {
+        if !self.stream_observer {
+            return;
+        }
+        let lines = self.stream_lines(std::time::Instant::now());
+        let Some(mcpl) = &self.mcpl else { return };
+        for params in lines {
+            let _ = mcpl.notify(observer::STREAM_EVENT, Some(params));
+        }
+    }
+
+    /// Run a chat message through the auto-respond rules, send whatever
+    /// fires and tell the agent what was done on its behalf.
+    async fn auto_respond_to(&mut self, channel_id: &str, author: &str, text: &str) {
+        let now = std::time::Instant::now();
+        let Some(response) = self.auto_respond.respond(channel_id, text, now) else { return };
+        let mut sent = Ok(());
+        for cmd in &response.commands {
+            sent = self.send_command(channel_id, cmd, command_history::Source::Automation).await.map(|_| ());
+            if sent.is_err() {
+                break;
+            }
+        }
+        let details = serde_json::json!({
+            "channel_id": channel_id,
+            "rule": response.rule,
+            "trigger": text,
+            "commands": response.commands,
+        });
+        self.audit(audit::Kind::AutoRespond, "auto_respond", &details, &sent);
+        if let Err(e) = sent {
+            tracing::warn!("Auto-respond for {} failed: {}", channel_id, e);
+            return;
+        }
+        let notice = self.notice_message(
+            channel_id,
+            format!("Auto-responded to {}: {}", author, response.describe()),
+            serde_json::json!({
+                "autoRespond": {
+                    "rule": response.rule,
+                    "pattern": response.pattern,
+                    "trigger": text,
+                    "commands": response.commands,
+                }
+            }),
+        );
+        self.push_incoming(notice).await;
+    }
+
+    /// The bridge build installed for a channel's game, with the version of
+    /// the engine it runs in.
+    fn installed_build(&self, channel_id: &str) -> Option<sai_ipc::BuildInfo> {
+        let mut build = write_dir::installed_bridge(&self.write_dir)?.build?;
+        build.engine_version = self
+            .engines
+            .instances
+            .get(sai_ipc::game_channel_id(channel_id))
+            .and_then(|inst| engine::engine_version_of(&inst.config.engine_dir));
+        Some(build)
+    }
+
+    /// Append an event to the channel's session log, starting the log on
+    /// the first event.
+    fn record_sai_event(&mut self, channel_id: &str, event: &sai_ipc::SaiEvent) {
+        if !self.recorders.contains_key(channel_id) {
+            let bridge = match event {
+                sai_ipc::SaiEvent::Init { build: Some(build), .. } => Some(build.clone()),
+                _ => self.installed_build(channel_id),
+            };
+            match recording::SessionRecorder::create(&self.write_dir.join("sessions"), channel_id, bridge.as_ref()) {
+                Ok(recorder) => {
+                    self.recorders.insert(channel_id.to_string(), recorder);
+                }
+                Err(e) => {
+                    tracing::warn!("Failed to start session log for {}: {}", channel_id, e);
+                    return;
+                }
+            }
+        }
+        if let Err(e) = self.recorders.get_mut(channel_id).unwrap().record(event) {
+            tracing::warn!("Failed to record event for {}: {}", channel_id, e);
+        }
+    }
+
+    /// Close a channel's session log. A game that ended on its own is
+    /// analyzed, and the summary is written next to the log.
+    fn finish_session(&mut self, channel_id: &str, status: &engine::GameStatus) {
+        let Some(recorder) = self.recorders.remove(channel_id) else { return };
+        let path = match recorder.finish() {
+            Ok(path) => path,
+            Err(e) => {
+                tracing::warn!("Failed to close session log for {}: {}", channel_id, e);
+                return;
+            }
+        };
+        if *status != engine::GameStatus::Ended {
+            return;
+        }
+        let events = match recording::read_session(&path) {
+            Ok(events) => events,
+            Err(e) => {
+                tracing::warn!("Failed to read session log {}: {}", path.display(), e);
+                return;
+            }
+        };
+        let analysis = analysis::analyze(&events);
+        let mut summary = serde_json::to_value(&analysis).unwrap();
+        summary["channel"] = channel_id.into();
+        // Which side this bridge played; in self-play both sides get a
+        // summary, and each names the winning ally team.
+        if let Some(config) = self.engines.instances.get(sai_ipc::game_channel_id(channel_id)).map(|i| &i.config) {
+            let team = sai_ipc::sub_channel_team(channel_id).unwrap_or(config.agent_team);
+            let ally_team = config.ally_team_of(team);
+            summary["team"] = team.into();
+            summary["allyTeam"] = ally_team.into();
+            if config.self_play() {
+                summary["winningAllyTeam"] = match analysis.outcome {
+                    "win" => ally_team.into(),
+                    "loss" => (1 - ally_team).into(),
+                    _ => serde_json::Value::Null,
+                };
+            }
+        }
+        summary["sessionLog"] = path.display().to_string().into();
+        let summary_path = path.with_extension("summary.json");
+        if let Err(e) = std::fs::write(&summary_path, serde_json::to_string_pretty(&summary).unwrap()) {
+            tracing::warn!("Failed to write {}: {}", summary_path.display(), e);
+        }
+        tracing::info!("Game {} summary written to {}", channel_id, summary_path.display());
+        self.summaries.push_back((channel_id.to_string(), summary));
+        while self.summaries.len() > self.summary_retain {
+            self.summaries.pop_front();
+        }
+    }
+
+    /// Tell a freshly initialized bridge to play in turns, if the channel
+    /// was opened with `metadata.turn_mode`.
+    async fn apply_turn_mode(&mut self, channel_id: &str) {
+        let enabled = self.game_control.get(channel_id).is_some_and(|c| c.turn_mode);
+        if !enabled {
+            return;
+        }
+        let cmd = SaiCommand::SetTurnMode { enabled };
+        if let Err(e) = self.send_command(channel_id, &cmd, command_history::Source::Automation).await {
+            tracing::warn!("Failed to enable turn mode for {}: {}", channel_id, e);
+        }
+    }
+
+    /// Build the channels/incoming message for a SAI event, rendered with
+    /// the channel's verbosity.
+    fn sai_incoming_message(
+        &self,
+        channel_id: &str,
+        event: &sai_ipc::SaiEvent,
+    ) -> mcpl_core::methods::IncomingChannelMessage {
+        let verbosity = self.verbosity.get(channel_id).copied().unwrap_or_default();
+        let context = self.places.get(channel_id).map(|p| p.context());
+        // In-game chat comes from the player who said it, not the engine.
+        let author = match event {
+            sai_ipc::SaiEvent::Message { player, player_name, .. } => MessageAuthor {
+                id: format!("player:{}", player),
+                name: sai_ipc::player_label(*player, player_name),
+            },
+            _ => MessageAuthor {
+                id: "engine".into(),
+                name: "Game Engine".into(),
+            },
+        };
+        mcpl_core::methods::IncomingChannelMessage {
+            channel_id: channel_id.to_string(),
+            message_id: uuid::Uuid::new_v4().to_string(),
+            thread_id: None,
+            author,
+            content: vec![ContentBlock::text(sai_ipc::event_to_content(event, verbosity, context.as_ref()))],
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            metadata: sai_ipc::event_metadata(event),
+        }
+    }
+
+    /// A channels/incoming message from the GameManager itself, for things
+    /// it did or noticed on a game channel without being asked.
+    fn notice_message(
+        &self,
+        channel_id: &str,
+        text: String,
+        metadata: serde_json::Value,
+    ) -> mcpl_core::methods::IncomingChannelMessage {
+        mcpl_core::methods::IncomingChannelMessage {
+            channel_id: channel_id.to_string(),
+            message_id: uuid::Uuid::new_v4().to_string(),
+            thread_id: None,
+            author: MessageAuthor {
+                id: "gamemanager".into(),
+                name: "GameManager".into(),
+            },
+            content: vec![ContentBlock::text(text)],
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            metadata: Some(metadata),
+        }
+    }
+
+    /// Forward a SAI event as channels/incoming to the MCPL client.
+    async fn forward_sai_event(
+        &mut self,
+        channel_id: &str,
+        event: &sai_ipc::SaiEvent,
+        income_loss: Option<income::IncomeLoss>,
+    ) {
+        let mut message = self.sai_incoming_message(channel_id, event);
+        // A lost economy structure says what it was earning.
+        if let Some(loss) = income_loss {
+            message.content.push(ContentBlock::text(loss.text()));
+            let metadata = message.metadata.get_or_insert_with(|| serde_json::json!({}));
+            metadata["incomeLoss"] = serde_json::to_value(&loss).unwrap();
+        }
+        self.push_incoming(message).await;
+    }
+
+    /// Send a message on its channel, numbered and kept for replay.
+    async fn push_incoming(&mut self, mut message: mcpl_core::methods::IncomingChannelMessage) {
+        let size = self.replay_size;
+        self.replay
+            .entry(message.channel_id.clone())
+            .or_insert_with(|| replay::ReplayBuffer::new(size))
+            .push(&mut message);
+        let mcpl = match &self.mcpl {
+            Some(c) => c,
+            None => return,
+        };
+
+        let params = ChannelsIncomingParams {
+            messages: vec![message],
+        };
+
+        if let Err(e) = mcpl.request(method::CHANNELS_INCOMING, Some(serde_json::to_value(&params).unwrap())) {
+            tracing::warn!("Failed to queue channels/incoming: {}", e);
+        }
+    }
+
+    // ── Game tool implementations ──
+
+    fn tool_game_stats(&mut self, args: &serde_json::Value) -> serde_json::Value {
+        let channel_id = match args.get("channel_id").and_then(|v| v.as_str()) {
+            Some(id) => id,
+            None => {
+                return serde_json::json!({
+                    "content": [{"type": "text", "text": "Missing channel_id"}],
+                    "isError": true
+                })
+            }
+        };
+        let mut stats = match self.sai.stats(channel_id) {
+            Some(stats) => stats.to_json(),
+            None => {
+                return serde_json::json!({
+                    "content": [{"type": "text", "text": format!("No SAI connection for channel {}", channel_id)}],
+                    "isError": true
+                })
+            }
+        };
+        if let Some(counts) = self.sai.pacing_counts(channel_id) {
+            stats["pacing"] = serde_json::json!(counts);
+        }
+        if let Some(counts) = self.sai.chaos_counts(channel_id) {
+            stats["chaos"] = serde_json::json!(counts);
+        }
+        if args.get("reset").and_then(|v| v.as_bool()).unwrap_or(false) {
+            self.sai.reset_stats(channel_id);
+        }
+        serde_json::json!({
+            "content": [{"type": "text", "text": serde_json::to_string_pretty(&stats).unwrap()}]
+        })
+    }
+
+    async fn tool_game_pause(&mut self, args: &serde_json::Value) -> serde_json::Value {
+        self.send_game_control(args, SaiCommand::Pause).await
+    }
+
+    async fn tool_game_resume(&mut self, args: &serde_json::Value) -> serde_json::Value {
+        self.send_game_control(args, SaiCommand::Unpause).await
+    }
+
+    async fn tool_game_set_speed(&mut self, args: &serde_json::Value) -> serde_json::Value {
+        let speed = match args.get("speed").and_then(|v| v.as_f64()) {
+            Some(s) => s as f32,
+            None => {
+                return serde_json::json!({
+                    "content": [{"type": "text", "text": "Missing speed"}],
+                    "isError": true
+                })
+            }
+        };
+        self.send_game_control(args, SaiCommand::SetSpeed { speed }).await
+    }
+
+    async fn tool_game_say(&mut self, args: &serde_json::Value) -> serde_json::Value {
+        let (Some(channel_id), Some(text)) = (
+            args.get("channel_id").and_then(|v| v.as_str()),
+            args.get("text").and_then(|v| v.as_str()),
+        ) else {
+            return serde_json::json!({
+                "content": [{"type": "text", "text": "Missing channel_id or text"}],
+                "isError": true
+            });
+        };
+        let destination = match args.get("destination").and_then(|v| v.as_str()) {
+            None => sai_ipc::ChatDestination::All,
+            Some(d) => match sai_ipc::ChatDestination::parse(d) {
+                Some(d) => d,
+                None => {
+                    return serde_json::json!({
+                        "content": [{"type": "text", "text": format!(
+                            "Unknown destination '{}' (expected all, allies or spectators)", d
+                        )}],
+                        "isError": true
+                    })
+                }
+            },
+        };
+        let cmd = SaiCommand::SendChat { text: text.to_string(), destination };
+        match self.send_command(channel_id, &cmd, command_history::Source::Tool).await {
+            Ok(_) => serde_json::json!({
+                "content": [{"type": "text", "text": format!("Said to {}: {}", destination.as_str(), text)}]
+            }),
+            Err(e) => serde_json::json!({
+                "content": [{"type": "text", "text": e}],
+                "isError": true
+            }),
+        }
+    }
+
+    /// Send a command as channels/publish would, or only validate it.
+    async fn tool_game_command(&mut self, args: &serde_json::Value) -> serde_json::Value {
+        let error = |text: String| {
+            serde_json::json!({
+                "content": [{"type": "text", "text": text}],
+                "isError": true
+            })
+        };
+        let (Some(channel_id), Some(command)) =
+            (args.get("channel_id").and_then(|v| v.as_str()), args.get("command"))
+        else {
+            return error("Missing channel_id or command".into());
+        };
+        let text = match command.as_str() {
+            Some(s) => s.to_string(),
+            None => command.to_string(),
+        };
+        let cmds = match sai_ipc::parse_publish_command(&text, self.groups.get(channel_id), self.places.get(channel_id), self.game_profile(channel_id)) {
+            Ok(c) => c,
+            Err(e) => return error(e),
+        };
+        let labels: Vec<String> = cmds.iter().map(sai_ipc::command_label).collect();
+
+        if !args.get("dry_run").and_then(|v| v.as_bool()).unwrap_or(false) {
+            if let Err(violation) = self.police(channel_id, &cmds, command_history::Source::Tool, false) {
+                return serde_json::json!({
+                    "content": [{"type": "text", "text": violation.text()}],
+                    "isError": true,
+                    "structuredContent": violation.to_json(),
+                });
+            }
+            let size = self.command_history_size;
+            let first = self
+                .command_history
+                .entry(channel_id.to_string())
+                .or_insert_with(|| command_history::CommandHistory::new(size))
+                .next_seq();
+            let sent = self.send_commands(channel_id, &cmds, command_history::Source::Tool).await;
+            if args.get("notify").and_then(|v| v.as_bool()).unwrap_or(false) {
+                if let Some(history) = self.command_history.get_mut(channel_id) {
+                    history.notify_from(first);
+                }
+            }
+            let delayed = match sent {
+                Ok(delayed) => delayed,
+                Err(e) => return error(e),
+            };
+            let mut text = format!("Sent {}", labels.join(", "));
+            if delayed > 0 {
+                let pacing = self.pacing_note(channel_id, delayed);
+                text = format!("{}\n{}", text, pacing["note"].as_str().unwrap_or_default());
+            }
+            let remind = args.get("remind").and_then(|v| v.as_bool()).unwrap_or(false);
+            let Some((eta_text, eta)) = self.arrival(channel_id, &cmds, remind) else {
+                return serde_json::json!({
+                    "content": [{"type": "text", "text": text}]
+                });
+            };
+            return serde_json::json!({
+                "content": [{"type": "text", "text": format!("{}\n{}", text, eta_text)}],
+                "structuredContent": {"eta": eta},
+            });
+        }
+        let results = match self.dry_run_commands(channel_id, &cmds).await {
+            Ok(results) => results,
+            Err(e) => return error(e),
+        };
+        let failed: Vec<String> = results
+            .iter()
+            .filter_map(|(label, e)| e.as_ref().map(|e| format!("{}: {}", label, e)))
+            .collect();
+        let text = if failed.is_empty() {
+            format!("Dry run: would send {}", labels.join(", "))
+        } else {
+            format!("Dry run: {} of {} would fail\n{}", failed.len(), results.len(), failed.join("\n"))
+        };
+        serde_json::json!({
+            "content": [{"type": "text", "text": text}]
+        })
+    }
+
+    /// The opponent catalog, optionally only the AIs that play `game`.
+    fn tool_game_list_opponents(&self, args: &serde_json::Value) -> serde_json::Value {
+        let game = args.get("game").and_then(|v| v.as_str());
+        let catalog = self.opponent_catalog();
+        let list: Vec<&opponents::Opponent> =
+            catalog.entries().iter().filter(|o| game.is_none_or(|g| o.plays(g))).collect();
+        serde_json::json!({
+            "content": [{"type": "text", "text": serde_json::to_string_pretty(&list).unwrap()}]
+        })
+    }
+
+    /// The newest audit log lines, oldest first.
+    fn tool_gm_audit_tail(&mut self, args: &serde_json::Value) -> serde_json::Value {
+        let lines = args.get("lines").and_then(|v| v.as_u64()).unwrap_or(20) as usize;
+        let tail = match &mut self.audit {
+            Some(log) => log.tail(lines).map_err(|e| format!("Failed to read the audit log: {}", e)),
+            None => Err("The audit log is off (config audit.enabled)".into()),
+        };
+        match tail {
+            Ok(tail) if tail.is_empty() => serde_json::json!({
+                "content": [{"type": "text", "text": "The audit log is empty"}]
+            }),
+            Ok(tail) => serde_json::json!({
+                "content": [{"type": "text", "text": tail.join("\n")}]
+            }),
+            Err(e) => serde_json::json!({
+                "content": [{"type": "text", "text": e}],
+                "isError": true
+            }),
+        }
+    }
+
+    /// The channel's recent commands, oldest first.
+    fn tool_game_command_history(&self, args: &serde_json::Value) -> serde_json::Value {
+        let Some(channel_id) = args.get("channel_id").and_then(|v| v.as_str()) else {
+            return serde_json::json!({
+                "content": [{"type": "text", "text": "Missing channel_id"}],
+                "isError": true
+            });
+        };
+        let limit = args.get("limit").and_then(|v| v.as_u64()).unwrap_or(20) as usize;
+        let lines: Vec<String> = self
+            .command_history
+            .get(channel_id)
+            .map(|h| h.recent(limit).map(|e| e.line()).collect())
+            .unwrap_or_default();
+        let text = if lines.is_empty() {
+            format!("No commands sent to {}", channel_id)
+        } else {
+            lines.join("\n")
+        };
+        serde_json::json!({
+            "content": [{"type": "text", "text": text}]
+        })
+    }
+
+    /// Where a unit has been lately, and which way it's going.
+    fn tool_game_unit_history(&self, args: &serde_json::Value) -> serde_json::Value {
+        let error = |text: String| {
+            serde_json::json!({
+                "content": [{"type": "text", "text": text}],
+                "isError": true
+            })
+        };
+        let Some(channel_id) = args.get("channel_id").and_then(|v| v.as_str()) else {
+            return error("Missing channel_id".into());
+        };
+        let Some(unit) = args.get("unit_id").and_then(|v| v.as_i64()).map(|id| UnitId(id as i32)) else {
+            return error("Missing unit_id".into());
+        };
+        let history = self.unit_history.get(channel_id);
+        let Some(track) = history.and_then(|h| h.track(unit)) else {
+            return error(format!("No positions known for unit #{} on {}", unit, channel_id));
+        };
+        let motion = history.and_then(|h| h.motion(unit));
+        let places = self.places.get(channel_id);
+        let context = places.map(|p| p.context());
+        let mut lines = vec![format!(
+            "{} #{} ({}), last {} positions:",
+            track.unit_name.as_deref().unwrap_or("unit"),
+            unit,
+            if track.side == unit_history::Side::Own { "ours" } else { "enemy" },
+            track.samples.len()
+        )];
+        for sample in &track.samples {
+            let at = match &context {
+                Some(context) => positions::describe(sample.pos[0], sample.pos[1], context),
+                None => format!("near ({:.0}, {:.0})", sample.pos[0], sample.pos[1]),
+            };
+            lines.push(format!("frame {}: {}", sample.frame, at));
+        }
+        lines.push(motion.map_or("not seen moving lately".into(), |m| m.text()));
+        serde_json::json!({
+            "content": [{"type": "text", "text": lines.join("\n")}],
+            "structuredContent": {
+                "unit": unit.0,
+                "track": track,
+                "motion": motion,
+                "frame": history.map(|h| h.frame()),
+            }
+        })
+    }
+
+    /// What each of our factories is making and has queued. Build times
+    /// need the unit def catalog, fetched here if it wasn't yet.
+    async fn tool_game_factories(&mut self, args: &serde_json::Value) -> serde_json::Value {
+        let Some(channel_id) = args.get("channel_id").and_then(|v| v.as_str()) else {
+            return serde_json::json!({
+                "content": [{"type": "text", "text": "Missing channel_id"}],
+                "isError": true
+            });
+        };
+        let known = self.factories.get(channel_id).is_some_and(|f| !f.is_empty());
+        if known && !self.unit_defs.contains_key(channel_id) {
+            if let Err(e) = self.unit_def_catalog(channel_id, sai_ipc::UNIT_DEFS_TIMEOUT).await {
+                tracing::debug!("No unit defs for factory times on {}: {}", channel_id, e);
+            }
+        }
+        let summaries = self
+            .factories
+            .get(channel_id)
+            .map(|f| f.summaries(self.unit_defs.get(channel_id)))
+            .unwrap_or_default();
+        let text = if summaries.is_empty() {
+            format!("No factories known on {}", channel_id)
+        } else {
+            summaries.iter().map(factories::Summary::text).collect::<Vec<_>>().join("\n")
+        };
+        serde_json::json!({
+            "content": [{"type": "text", "text": text}],
+            "structuredContent": {"factories": summaries}
+        })
+    }
+
+    /// Define or replace a command macro.
+    fn tool_gm_macro_define(&mut self, args: &serde_json::Value) -> serde_json::Value {
+        let error = |text: String| {
+            serde_json::json!({
+                "content": [{"type": "text", "text": text}],
+                "isError": true
+            })
+        };
+        let Some(name) = args.get("name").and_then(|v| v.as_str()) else {
+            return error("Missing name".into());
+        };
+        let mut definition = args.clone();
+        if let Some(o) = definition.as_object_mut() {
+            o.remove("name");
+        }
+        let definition: macros::Macro = match serde_json::from_value(definition) {
+            Ok(definition) => definition,
+            Err(e) => return error(format!("Invalid macro: {}", e)),
+        };
+        let commands = definition.commands.len();
+        match self.macros.define(name, definition) {
+            Ok(replaced) => serde_json::json!({
+                "content": [{"type": "text", "text": format!(
+                    "{} macro {} ({} commands)",
+                    if replaced { "Replaced" } else { "Defined" }, name, commands
+                )}]
+            }),
+            Err(e) => error(e),
+        }
+    }
+
+    /// Fill in a macro's parameters and send its commands. Nothing is sent
+    /// unless every command expands.
+    async fn tool_gm_macro_run(&mut self, args: &serde_json::Value) -> serde_json::Value {
+        let error = |text: String| {
+            serde_json::json!({
+                "content": [{"type": "text", "text": text}],
+                "isError": true
+            })
+        };
+        let (Some(name), Some(channel_id)) = (
+            args.get("name").and_then(|v| v.as_str()),
+            args.get("channel_id").and_then(|v| v.as_str()),
+        ) else {
+            return error("Missing name or channel_id".into());
+        };
+        let Some(definition) = self.macros.get(name) else {
+            return error(format!("Unknown macro {}", name));
+        };
+        let params = match args.get("params") {
+            None => serde_json::Map::new(),
+            Some(serde_json::Value::Object(params)) => params.clone(),
+            Some(_) => return error("params must be an object".into()),
+        };
+        let cmds = match definition.expand(&params, self.groups.get(channel_id), self.places.get(channel_id), self.game_profile(channel_id)) {
+            Ok(cmds) => cmds,
+            Err(e) => return error(format!("Macro {}: {}", name, e)),
+        };
+        let labels: Vec<String> = cmds.iter().map(sai_ipc::command_label).collect();
+        match self.send_commands(channel_id, &cmds, command_history::Source::Tool).await {
+            Ok(delayed) => {
+                let mut text = format!("Macro {} sent {}", name, labels.join(", "));
+                if delayed > 0 {
+                    let pacing = self.pacing_note(channel_id, delayed);
+                    text = format!("{}\n{}", text, pacing["note"].as_str().unwrap_or_default());
+                }
+                serde_json::json!({
+                    "content": [{"type": "text", "text": text}]
+                })
+            }
+            Err(e) => error(e),
+        }
+    }
+
+    /// Every macro with its parameters and commands.
+    fn tool_gm_macro_list(&self) -> serde_json::Value {
+        let list: serde_json::Map<String, serde_json::Value> =
+            self.macros.iter().map(|(name, m)| (name.clone(), serde_json::to_value(m).unwrap())).collect();
+        let text = if list.is_empty() {
+            "No macros defined".to_string()
+        } else {
+            serde_json::to_string_pretty(&list).unwrap()
+        };
+        serde_json::json!({
+            "content": [{"type": "text", "text": text}]
+        })
+    }
+
+    /// game_group_create / _add / _remove / _list on a channel's groups.
+    fn tool_game_group(&mut self, tool: &str, args: &serde_json::Value) -> serde_json::Value {
+        let error = |text: String| {
+            serde_json::json!({
+                "content": [{"type": "text", "text": text}],
+                "isError": true
+            })
+        };
+        let Some(channel_id) = args.get("channel_id").and_then(|v| v.as_str()) else {
+            return error("Missing channel_id".into());
+        };
+        if !self.engines.instances.contains_key(channel_id) && !self.sai.connections.contains_key(channel_id) {
+            return error(format!("Unknown game channel {}", channel_id));
+        }
+        let groups = self.groups.entry(channel_id.to_string()).or_default();
+        if tool == "game_group_list" {
+            return serde_json::json!({
+                "content": [{"type": "text", "text": serde_json::to_string_pretty(&groups.list()).unwrap()}]
+            });
+        }
+        let Some(name) = args.get("name").and_then(|v| v.as_str()) else {
+            return error("Missing name".into());
+        };
+        let units: Option<Vec<UnitId>> = match args.get("unit_ids") {
+            None => None,
+            Some(v) => match serde_json::from_value(v.clone()) {
+                Ok(units) => Some(units),
+                Err(_) => return error("unit_ids must be a list of unit ids".into()),
+            },
+        };
+        let result = match tool {
+            "game_group_create" => {
+                let alert_below = args.get("alert_below").and_then(|v| v.as_u64()).map(|n| n as usize);
+                groups.create(name, units.as_deref().unwrap_or_default(), alert_below).map(Some)
+            }
+            "game_group_add" => match &units {
+                Some(units) => groups.add(name, units).map(Some),
+                None => Err("Missing unit_ids".into()),
+            },
+            _ => groups.remove(name, units.as_deref()),
+        };
+        let text = match result {
+            Ok(Some(group)) => {
+                let ids: Vec<String> = group.members.iter().map(|u| u.to_string()).collect();
+                format!("Group {}: {} units [{}]", name, ids.len(), ids.join(", "))
+            }
+            Ok(None) => format!("Disbanded group {}", name),
+            Err(e) => return error(e),
+        };
+        serde_json::json!({
+            "content": [{"type": "text", "text": text}]
+        })
+    }
+
+    /// Name a point on a game channel's map, given as x/z or as a location.
+    fn tool_game_set_location(&mut self, args: &serde_json::Value) -> serde_json::Value {
+        let error = |text: String| {
+            serde_json::json!({
+                "content": [{"type": "text", "text": text}],
+                "isError": true
+            })
+        };
+        let (Some(channel_id), Some(name)) = (
+            args.get("channel_id").and_then(|v| v.as_str()),
+            args.get("name").and_then(|v| v.as_str()),
+        ) else {
+            return error("Missing channel_id or name".into());
+        };
+        if !self.engines.instances.contains_key(channel_id) && !self.sai.connections.contains_key(channel_id) {
+            return error(format!("Unknown game channel {}", channel_id));
+        }
+        let places = self.places.entry(channel_id.to_string()).or_default();
+        let coordinate = |key: &str| args.get(key).and_then(|v| v.as_f64()).map(|v| v as f32);
+        let place = match (args.get("location"), coordinate("x"), coordinate("z")) {
+            (Some(spec), None, None) => places.locate(spec),
+            (None, Some(x), Some(z)) => Ok(locations::Place { x, z }),
+            _ => Err("Give either location or x and z".into()),
+        };
+        if let Err(e) = place.and_then(|place| places.set(name, place)) {
+            return error(e);
+        }
+        let known: Vec<String> =
+            places.named().iter().map(|(name, p)| format!("{} ({:.0}, {:.0})", name, p.x, p.z)).collect();
+        serde_json::json!({
+            "content": [{"type": "text", "text": format!("Locations: {}", known.join(", "))}]
+        })
+    }
+
+    /// Send a constructor to build mexes on the nearest unclaimed metal spots.
+    async fn tool_game_expand(&mut self, args: &serde_json::Value) -> serde_json::Value {
+        let error = |text: String| {
+            serde_json::json!({
+                "content": [{"type": "text", "text": text}],
+                "isError": true
+            })
+        };
+        let (Some(channel_id), Some(builder)) = (
+            args.get("channel_id").and_then(|v| v.as_str()),
+            args.get("builder_id").and_then(|v| v.as_i64()).and_then(|v| i32::try_from(v).ok()).map(UnitId),
+        ) else {
+            return error("Missing channel_id or builder_id".into());
+        };
+        if !self.engines.instances.contains_key(channel_id) && !self.sai.connections.contains_key(channel_id) {
+            return error(format!("Unknown game channel {}", channel_id));
+        }
+        let count = args.get("count").and_then(|v| v.as_u64()).unwrap_or(1).clamp(1, 10) as usize;
+        let spots = match self.expansions.entry(channel_id.to_string()).or_default().plan(builder, count) {
+            Ok(spots) => spots,
+            Err(e) => return error(e),
+        };
+        for cmd in expansion::build_commands(builder, &spots) {
+            if let Err(e) = self.send_command(channel_id, &cmd, command_history::Source::Tool).await {
+                if let Some(planner) = self.expansions.get_mut(channel_id) {
+                    planner.cancel(builder);
+                }
+                return error(e);
+            }
+            if let Some(watch) = self.idle_builders.get_mut(channel_id) {
+                watch.ordered(&cmd);
+            }
+        }
+        let positions: Vec<String> = spots.iter().map(|s| format!("({:.0}, {:.0})", s.x, s.z)).collect();
+        let mut text = format!("Queued {} mex builds for unit {}: {}", spots.len(), builder, positions.join(", "));
+        if spots.len() < count {
+            text += &format!(" (only {} unclaimed spots left)", spots.len());
+        }
+        serde_json::json!({
+            "content": [{"type": "text", "text": text}]
+        })
+    }
+
+    /// Put every idle constructor on one job: assisting a factory,
+    /// reclaiming or repairing an area. Units that got orders since the
+    /// plan was made are left alone.
+    async fn tool_game_assist(&mut self, args: &serde_json::Value) -> serde_json::Value {
+        let error = |text: String| {
+            serde_json::json!({
+                "content": [{"type": "text", "text": text}],
+                "isError": true
+            })
+        };
+        let (Some(channel_id), Some(mode)) =
+            (args.get("channel_id").and_then(|v| v.as_str()), args.get("mode").and_then(|v| v.as_str()))
+        else {
+            return error("Missing channel_id or mode".into());
+        };
+        let mode = match assist::AssistMode::parse(mode) {
+            Ok(mode) => mode,
+            Err(e) => return error(e),
+        };
+        let target = args.get("target").and_then(|v| v.as_i64()).and_then(|v| i32::try_from(v).ok()).map(UnitId);
+        let area = match args.get("area") {
+            None => None,
+            Some(area) => {
+                let number = |key: &str| area.get(key).and_then(|v| v.as_f64()).map(|v| v as f32);
+                match (number("x"), number("z"), number("radius").unwrap_or(assist::DEFAULT_RADIUS)) {
+                    (Some(x), Some(z), radius) if radius > 0.0 => Some(assist::Area { x, z, radius }),
+                    _ => return error("area needs x and z, and a positive radius if given".into()),
+                }
+            }
+        };
+        let builders = self.idle_builders.get(channel_id).map(|watch| watch.builders()).unwrap_or_default();
+        let grid = self.map_grids.get(channel_id);
+        let ground = |x: f32, z: f32| {
+            grid.and_then(|g| g.cell_of(x, z).map(|(col, row)| g.elevation[row][col].max(0) as f32)).unwrap_or(0.0)
+        };
+        let tasks = match assist::plan(mode, &builders, target, area, ground) {
+            Ok(tasks) => tasks,
+            Err(e) => return error(e),
+        };
+        let mut tasked = Vec::new();
+        let mut skipped = Vec::new();
+        let mut delayed = 0;
+        for task in tasks {
+            // Ordered elsewhere since: best not to override it.
+            if !self.idle_builders.get(channel_id).is_some_and(|watch| watch.is_idle(task.unit)) {
+                skipped.push(task.unit);
+                continue;
+            }
+            match self.send_commands(channel_id, std::slice::from_ref(&task.command), command_history::Source::Tool).await {
+                Ok(held) => delayed += held,
+                Err(e) => return error(format!("{} (after tasking {} units)", e, tasked.len())),
+            }
+            tasked.push(task);
+        }
+        let mut text = if tasked.is_empty() {
+            "No idle constructors to task".to_string()
+        } else {
+            let lines: Vec<&str> = tasked.iter().map(|t| t.text.as_str()).collect();
+            format!("Tasked {} idle constructors: {}", tasked.len(), lines.join("; "))
+        };
+        if !skipped.is_empty() {
+            let ids: Vec<String> = skipped.iter().map(|u| u.to_string()).collect();
+            text += &format!(" (skipped {}: got other orders first)", ids.join(", "));
+        }
+        if delayed > 0 {
+            let pacing = self.pacing_note(channel_id, delayed);
+            text = format!("{}\n{}", text, pacing["note"].as_str().unwrap_or_default());
+        }
+        serde_json::json!({
+            "content": [{"type": "text", "text": text}],
+            "structuredContent": {
+                "tasked": tasked.iter().map(|t| t.unit.0).collect::<Vec<_>>(),
+                "skipped": skipped.iter().map(|u| u.0).collect::<Vec<_>>(),
+            }
+        })
+    }
+
+    /// The channel's unit def catalog, fetched from its bridge on first use.
+    /// Fetching it also teaches the channel's idle watch which defs build.
+    async fn unit_def_catalog(
+        &mut self,
+        channel_id: &str,
+        timeout: std::time::Duration,
+    ) -> Result<&unit_defs::UnitDefCatalog, String> {
+        if !self.unit_defs.contains_key(channel_id) {
+            let defs = self.sai.query_unit_defs(channel_id, timeout).await?;
+            let catalog = unit_defs::UnitDefCatalog::new(defs);
+            if let Some(watch) = self.idle_builders.get_mut(channel_id) {
+                watch.learn_kinds(catalog.builder_kinds());
+            }
+            self.unit_defs.insert(channel_id.to_string(), catalog);
+        }
+        Ok(&self.unit_defs[channel_id])
+    }
+
+    async fn tool_game_unitdefs(&mut self, args: &serde_json::Value) -> serde_json::Value {
+        let error = |text: String| {
+            serde_json::json!({
+                "content": [{"type": "text", "text": text}],
+                "isError": true
+            })
+        };
+        let Some(channel_id) = args.get("channel_id").and_then(|v| v.as_str()) else {
+            return error("Missing channel_id".into());
+        };
+        let fields = match unit_defs::parse_fields(args.get("fields")) {
+            Ok(fields) => fields,
+            Err(e) => return error(e),
+        };
+        let filter = args.get("filter").and_then(|v| v.as_str()).filter(|f| !f.is_empty());
+        let catalog = match self.unit_def_catalog(channel_id, sai_ipc::UNIT_DEFS_TIMEOUT).await {
+            Ok(catalog) => catalog,
+            Err(e) => return error(e),
+        };
+        let defs: Vec<serde_json::Value> =
+            catalog.search(filter).into_iter().map(|def| catalog.project(def, &fields)).collect();
+        let text = match filter {
+            Some(filter) if defs.is_empty() => {
+                format!("No unit defs match '{}' ({} defs in the catalog)", filter, catalog.len())
+            }
+            _ => serde_json::to_string_pretty(&defs).unwrap(),
+        };
+        serde_json::json!({
+            "content": [{"type": "text", "text": text}]
+        })
+    }
+
+    /// A channel's map grid and metal spots. The cached grid is reused when
+    /// it answers `cell_size` and `build_def`, else the bridge is asked for
+    /// a new one (at [`map_grid::DEFAULT_CELL_SIZE`] without a cell size),
+    /// waiting up to `timeout`.
+    async fn map_grid(
+        &mut self,
+        channel_id: &str,
+        cell_size: Option<f32>,
+        build_def: Option<&str>,
+        timeout: std::time::Duration,
+    ) -> Result<(&map_grid::MapGrid, &[sai_protocol::MetalSpot]), String> {
+        if !self.map_grids.get(channel_id).is_some_and(|grid| grid.answers(cell_size, build_def)) {
+            let cell_size = cell_size.unwrap_or(map_grid::DEFAULT_CELL_SIZE);
+            let grid = self.sai.query_map_grid(channel_id, cell_size, build_def, timeout).await?;
+            self.map_grids.insert(channel_id.to_string(), grid);
+        }
+        let spots = self.expansions.get(channel_id).map(|planner| planner.spots()).unwrap_or_default();
+        Ok((&self.map_grids[channel_id], spots))
+    }
+
+    async fn tool_game_map(&mut self, args: &serde_json::Value) -> serde_json::Value {
+        let error = |text: String| {
+            serde_json::json!({
+                "content": [{"type": "text", "text": text}],
+                "isError": true
+            })
+        };
+        let Some(channel_id) = args.get("channel_id").and_then(|v| v.as_str()) else {
+            return error("Missing channel_id".into());
+        };
+        let cell_size = args.get("cell_size").and_then(|v| v.as_f64()).map(|v| v as f32);
+        if cell_size.is_some_and(|size| size <= 0.0) {
+            return error("cell_size must be positive".into());
+        }
+        let build_def = args.get("build_def").and_then(|v| v.as_str()).filter(|d| !d.is_empty());
+        match self.map_grid(channel_id, cell_size, build_def, sai_ipc::MAP_GRID_TIMEOUT).await {
+            Ok((grid, spots)) => serde_json::json!({
+                "content": [{"type": "text", "text": grid.render_ascii(spots)}]
+            }),
+            Err(e) => error(e),
+        }
+    }
+
+    /// A `game_query_*` tool: the typed answer as JSON text and as
+    /// structured content.
+    async fn tool_game_query(&mut self, name: &str, args: &serde_json::Value) -> serde_json::Value {
+        let kind = queries::kind(name).unwrap();
+        let answer = match (kind.parse)(args) {
+            Ok(query) => self.answer_query(query, kind.timeout.unwrap_or_default()).await,
+            Err(e) => Err(e),
+        };
+        match answer {
+            Ok(answer) => serde_json::json!({
+                "content": [{"type": "text", "text": serde_json::to_string_pretty(&answer).unwrap()}],
+                "structuredContent": answer
+            }),
+            Err(e) => serde_json::json!({
+                "content": [{"type": "text", "text": e}],
+                "isError": true
+            }),
+        }
+    }
+
+    async fn answer_query(&mut self, query: queries::Query, timeout: std::time::Duration) -> Result<serde_json::Value, String> {
+        use queries::Query;
+        let state = |channel_id: &str| {
+            self.observers.get(channel_id).map(|o| &o.state).ok_or_else(|| format!("Unknown game channel {}", channel_id))
+        };
+        let answer = match query {
+            Query::Units(args) => serde_json::to_value(queries::UnitsAnswer::of(state(&args.channel_id)?)),
+            Query::Economy(args) => {
+                let economy = queries::EconomyAnswer::of(state(&args.channel_id)?)
+                    .ok_or_else(|| format!("No economy update from {} yet", args.channel_id))?;
+                serde_json::to_value(economy)
+            }
+            Query::AllyEconomy(args) => {
+                let allies = queries::AllyEconomyAnswer::of(state(&args.channel_id)?)
+                    .ok_or_else(|| format!("No economy update from {} yet", args.channel_id))?;
+                serde_json::to_value(allies)
+            }
+            Query::Map(args) => {
+                let (grid, spots) = self.map_grid(&args.channel_id, args.cell_size, args.build_def.as_deref(), timeout).await?;
+                Ok(grid.to_json(spots))
+            }
+            Query::UnitDef(args) => {
+                let catalog = self.unit_def_catalog(&args.channel_id, timeout).await?;
+                let def = catalog
+                    .named(&args.name)
+                    .ok_or_else(|| format!("No unit def named '{}' (game_unitdefs searches by part of a name)", args.name))?;
+                serde_json::to_value(queries::UnitDefAnswer::of(catalog, def))
+            }
+            Query::Terrain(args) => {
+                let (grid, _) = self.map_grid(&args.channel_id, None, args.build_def.as_deref(), timeout).await?;
+                serde_json::to_value(queries::TerrainAnswer::at(grid, args.x, args.z)?)
+            }
+        };
+        Ok(answer.unwrap())
+    }
+
+    /// MCP resources/list: the map grid of every game with a connected
+    /// bridge, if the scope allows `game_map`.
+    fn handle_resources_list(&self) -> serde_json::Value {
+        if self.scope.as_ref().is_some_and(|s| !s.allows_tool("game_map")) {
+            return serde_json::json!({ "resources": [] });
+        }
+        let mut channels: Vec<&String> =
+            self.engines.instances.keys().filter(|id| self.sai.connections.contains_key(*id)).collect();
+        channels.sort();
+        let resources: Vec<serde_json::Value> = channels
+            .into_iter()
+            .map(|id| {
+                serde_json::json!({
+                    "uri": map_grid::resource_uri(id),
+                    "name": format!("Map of {}", id),
+                    "description": "Ground height and slope on a coarse grid, with metal spots",
+                    "mimeType": "application/json"
+                })
+            })
+            .collect();
+        serde_json::json!({ "resources": resources })
+    }
+
+    /// MCP resources/read for a `game://{channel}/map` URI.
+    async fn handle_resources_read(&mut self, params: &serde_json::Value) -> serde_json::Value {
+        let invalid = |message: String| serde_json::json!({ "error": { "code": -32602, "message": message } });
+        let uri = params.get("uri").and_then(|v| v.as_str()).unwrap_or_default();
+        let Some(channel_id) = map_grid::parse_resource_uri(uri) else {
+            return invalid(format!("Unknown resource: {}", uri));
+        };
+        if let Some(scope) = self.scope.as_ref().filter(|s| !s.allows_tool("game_map")) {
+            return scope.forbidden(&format!("Resource {}", uri));
+        }
+        match self.map_grid(channel_id, None, None, sai_ipc::MAP_GRID_TIMEOUT).await {
+            Ok((grid, spots)) => serde_json::json!({
+                "contents": [{
+                    "uri": uri,
+                    "mimeType": "application/json",
+                    "text": grid.to_json(spots).to_string()
+                }]
+            }),
+            Err(e) => invalid(e),
+        }
+    }
+
+    async fn tool_game_end_turn(&mut self, args: &serde_json::Value) -> serde_json::Value {
+        self.send_game_control(args, SaiCommand::EndTurn).await
+    }
+
+    /// Send a pause/unpause/set_speed/end_turn command to a channel's SAI and record
+    /// the resulting state. Out-of-range speeds are refused before sending.
+    async fn send_game_control(
+        &mut self,
+        args: &serde_json::Value,
+        cmd: SaiCommand,
+    ) -> serde_json::Value {
+        let channel_id = match args.get("channel_id").and_then(|v| v.as_str()) {
+            Some(id) => id,
+            None => {
+                return serde_json::json!({
+                    "content": [{"type": "text", "text": "Missing channel_id"}],
+                    "isError": true
+                })
+            }
+        };
+        let mut control = self.game_control.get(channel_id).copied().unwrap_or_default();
+        if let SaiCommand::SetSpeed { speed } = cmd {
+            if let Err(e) = control.check_speed(speed) {
+                return serde_json::json!({
+                    "content": [{"type": "text", "text": e}],
+                    "isError": true
+                });
+            }
+        }
+        if let Err(e) = self.send_command(channel_id, &cmd, command_history::Source::Tool).await {
+            return serde_json::json!({
+                "content": [{"type": "text", "text": e}],
+                "isError": true
+            });
+        }
+
+        match cmd {
+            SaiCommand::Pause => control.paused = true,
+            SaiCommand::Unpause => control.paused = false,
+            SaiCommand::SetSpeed { speed } => control.speed = speed,
+            SaiCommand::EndTurn => {
+                control.paused = false;
+                control.awaiting_turn = false;
+            }
+            _ => {}
+        }
+        self.game_control.insert(channel_id.to_string(), control);
+        let text = format!(
+            "Game {} at speed {}",
+            if control.paused { "paused" } else { "running" },
+            control.speed
+        );
+        serde_json::json!({
+            "content": [{"type": "text", "text": text}]
+        })
+    }
+
+    fn tool_game_summary(&self, args: &serde_json::Value) -> serde_json::Value {
+        let channel_id = match args.get("channel_id").and_then(|v| v.as_str()) {
+            Some(id) => id,
+            None => {
+                return serde_json::json!({
+                    "content": [{"type": "text", "text": "Missing channel_id"}],
+                    "isError": true
+                })
+            }
+        };
+        // Channel ids can repeat across restarts; the latest game wins.
+        match self.summaries.iter().rev().find(|(id, _)| id == channel_id) {
+            Some((_, summary)) => serde_json::json!({
+                "content": [{"type": "text", "text": serde_json::to_string_pretty(summary).unwrap()}]
+            }),
+            None => serde_json::json!({
+                "content": [{"type": "text", "text": format!(
+                    "No summary for {} (summaries are kept for the last {} games that ended)",
+                    channel_id, self.summary_retain
+                )}],
+                "isError": true
+            }),
+        }
+    }
+
+    /// Answer when a condition fires in a game, or at the timeout. Unless
+    /// it holds already, the call's request is kept for check_waiters,
+    /// expire_waiters or end_waiters to answer, and gets no response now.
+    fn tool_game_wait_for(&mut self, args: &serde_json::Value) -> serde_json::Value {
+        let error = |text: String| {
+            serde_json::json!({
+                "content": [{"type": "text", "text": text}],
+                "isError": true
+            })
+        };
+        let Some(channel_id) = args.get("channel_id").and_then(|v| v.as_str()) else {
+            return error("Missing channel_id".into());
+        };
+        let condition = match args.get("condition").map(waiters::Condition::parse) {
+            None => return error("Missing condition".into()),
+            Some(Err(e)) => return error(e),
+            Some(Ok(condition)) => condition,
+        };
+        let timeout = match waiters::timeout(args) {
+            Ok(timeout) => timeout,
+            Err(e) => return error(e),
+        };
+        let live = match self.engines.instances.get(channel_id) {
+            Some(inst) => matches!(
+                inst.status,
+                engine::GameStatus::Queued | engine::GameStatus::Starting | engine::GameStatus::Running
+            ),
+            None => self.sai.connections.contains_key(channel_id),
+        };
+        if !live || self.closing.contains_key(channel_id) {
+            return error(format!("No game running on {}", channel_id));
+        }
+        if let Some(observer) = self.observers.get(channel_id).filter(|o| condition.holds(&o.state)) {
+            let resolution = waiters::Resolution::Fired(None);
+            return waiters::answer(&condition, &resolution, observer.state.frame, std::time::Duration::ZERO);
+        }
+        let Some(request_id) = self.current_request.clone() else {
+            return error("game_wait_for answers later, so it needs an MCPL request".into());
+        };
+        let waiter = waiters::Waiter::new(request_id, args, channel_id, condition, timeout, std::time::Instant::now());
+        match self.waiters.add(waiter) {
+            Ok(()) => {
+                self.current_request = None;
+                serde_json::Value::Null
+            }
+            Err(e) => error(e),
+        }
+    }
+
+    /// Answer the game_wait_for calls an event of `channel_id` resolves.
+    fn check_waiters(&mut self, channel_id: &str, event: &sai_ipc::SaiEvent) {
+        if self.waiters.is_empty() {
+            return;
+        }
+        let Some(observer) = self.observers.get(channel_id) else { return };
+        let frame = observer.state.frame;
+        let now = std::time::Instant::now();
+        for (waiter, resolution) in self.waiters.check(channel_id, event, &observer.state) {
+            self.answer_waiter(waiter, &resolution, frame, now);
+        }
+    }
+
+    /// Answer the game_wait_for calls whose timeout passed.
+    fn expire_waiters(&mut self, now: std::time::Instant) {
+        for waiter in self.waiters.expired(now) {
+            let frame = self.observers.get(&waiter.channel_id).map_or(0, |o| o.state.frame);
+            self.answer_waiter(waiter, &waiters::Resolution::TimedOut, frame, now);
+        }
+    }
+
+    /// Answer a channel's game_wait_for calls as it goes away. When the
+    /// game is over, waiting for its end was worth it.
+    fn end_waiters(&mut self, channel_id: &str, why: &str, game_over: bool) {
+        let frame = self.observers.get(channel_id).map_or(0, |o| o.state.frame);
+        let now = std::time::Instant::now();
+        for waiter in self.waiters.remove_channel(channel_id) {
+            let resolution = match waiter.condition {
+                waiters::Condition::GameEnded if game_over => waiters::Resolution::Fired(None),
+                _ => waiters::Resolution::Gone(why.to_string()),
+            };
+            self.answer_waiter(waiter, &resolution, frame, now);
+        }
+    }
+
+    fn answer_waiter(
+        &mut self,
+        waiter: waiters::Waiter,
+        resolution: &waiters::Resolution,
+        frame: i32,
+        now: std::time::Instant,
+    ) {
+        let outcome = match resolution {
+            waiters::Resolution::Gone(why) => Err(why.clone()),
+            _ => Ok(()),
+        };
+        self.audit(audit::Kind::Tool, "game_wait_for", &waiter.args, &outcome);
+        self.respond(waiter.request_id.clone(), waiter.answer(resolution, frame, now));
+    }
+
+    async fn tool_game_cancel_queued(&mut self, args: &serde_json::Value) -> serde_json::Value {
+        let channel_id = match args.get("channel_id").and_then(|v| v.as_str()) {
+            Some(id) => id.to_string(),
+            None => {
+                return serde_json::json!({
+                    "content": [{"type": "text", "text": "Missing channel_id"}],
+                    "isError": true
+                })
+            }
+        };
+        if let Err(e) = self.engines.cancel_queued(&channel_id) {
+            return serde_json::json!({
+                "content": [{"type": "text", "text": e}],
+                "isError": true
+            });
+        }
+        // Waiters hear why before forget_channel ends them as closed.
+        self.end_waiters(&channel_id, "the queued game was cancelled", false);
+        self.forget_channel(&channel_id);
+        self.send_channels_changed(vec![], vec![channel_id.clone()], vec![])
+            .await;
+        serde_json::json!({
+            "content": [{"type": "text", "text": format!("Cancelled queued game {}", channel_id)}]
+        })
+    }
+
+    /// Launch queued games that fit under the concurrency limit now.
+    async fn launch_queued_games(&mut self) {
+        for (channel_id, result) in self.engines.launch_queued().await {
+            match result {
+                Ok(()) => {
+                    let map = self.engines.instances.get(&channel_id).map(|inst| inst.config.map.clone()).unwrap_or_default();
+                    tracing::info!("Launched queued game {} on {}", channel_id, map);
+                    self.send_channels_changed(
+                        vec![],
+                        vec![],
+                        vec![ChannelDescriptor {
+                            id: channel_id.clone(),
+                            channel_type: "game".into(),
+                            label: format!("Game on {}", map),
+                            direction: ChannelDirection::Bidirectional,
+                            address: None,
+                            metadata: Some(serde_json::json!({"status": "starting"})),
+                        }],
+                    )
+                    .await;
+                }
+                Err(e) => {
+                    tracing::error!("Queued game {} failed to launch: {}", channel_id, e);
+                    self.sai.close_channel(&channel_id);
+                    self.send_channels_changed(vec![], vec![channel_id], vec![])
+                        .await;
+                }
+            }
+        }
+    }
+
+    /// Start a benchmark run; `poll_benchmarks` plays its games.
+    fn tool_game_run_benchmark(&mut self, args: &serde_json::Value) -> serde_json::Value {
+        if let Some(run) = self.benchmarks.back().filter(|run| !run.is_done()) {
+            return serde_json::json!({
+                "content": [{"type": "text", "text": format!(
+                    "Benchmark run {} is still playing; see game_benchmark_status", run.id
+                )}],
+                "isError": true
+            });
+        }
+        let specs = match benchmark::BenchmarkSpec::from_args(args) {
+            Ok(specs) => specs,
+            Err(e) => {
+                return serde_json::json!({
+                    "content": [{"type": "text", "text": e}],
+                    "isError": true
+                })
+            }
+        };
+        let timeout = args
+            .get("timeout_secs")
+            .and_then(|v| v.as_u64())
+            .map(std::time::Duration::from_secs)
+            .unwrap_or(benchmark::DEFAULT_TIMEOUT);
+
+        let id = self.next_benchmark_id;
+        self.next_benchmark_id += 1;
+        let games = specs.len();
+        self.benchmarks.push_back(benchmark::BenchmarkRun::new(id, specs, timeout));
+        let finished = self.benchmarks.iter().filter(|run| run.is_done()).count();
+        if finished > benchmark::KEEP_FINISHED {
+            self.benchmarks.pop_front();
+        }
+        serde_json::json!({
+            "content": [{"type": "text", "text": format!(
+                "Started benchmark run {}: {} game(s), one after another. game_benchmark_status shows its progress; \
+                 the report comes as a {} event once every game is done.",
+                id, games, benchmark::EVENT
+            )}],
+            "structuredContent": {"run": id, "games": games}
+        })
+    }
+
+    /// A benchmark run's progress, or its report once finished; the latest
+    /// run unless `run` says which.
+    fn tool_game_benchmark_status(&self, args: &serde_json::Value) -> serde_json::Value {
+        let run = match args.get("run").and_then(|v| v.as_u64()) {
+            Some(id) => self.benchmarks.iter().find(|run| run.id == id),
+            None => self.benchmarks.back(),
+        };
+        let Some(run) = run else {
+            return serde_json::json!({
+                "content": [{"type": "text", "text": "No such benchmark run"}],
+                "isError": true
+            });
+        };
+        let status = run.status(std::time::Instant::now());
+        serde_json::json!({
+            "content": [{"type": "text", "text": serde_json::to_string_pretty(&status).unwrap()}],
+            "structuredContent": status
+        })
+    }
+
+    /// Advance the playing benchmark run: settle its game once the engine
+    /// exits or the timeout passes, launch the next, and report the run
+    /// when every game is done.
+    async fn poll_benchmarks(&mut self, now: std::time::Instant) {
+        let Some(run) = self.benchmarks.back().filter(|run| !run.is_done()) else { return };
+        if let Some(game) = &run.current {
+            let channel_id = game.channel_id.clone();
+            // Exits were announced and their last events read by check_engines.
+            let status = match self.engines.instances.get(&channel_id) {
+                None => Some(engine::GameStatus::Stopped),
+                Some(inst) if inst.process.is_none() && inst.status != engine::GameStatus::Queued => {
+                    Some(inst.status.clone())
+                }
+                Some(_) if run.timed_out(now).is_some() => None,
+                Some(_) => return,
+            };
+            if status.is_none() {
+                for event in self.sai.drain_events(&channel_id).await {
+                    self.handle_sai_event(&channel_id, &event).await;
+                }
+                self.send_channels_changed(vec![], vec![channel_id.clone()], vec![]).await;
+            }
+            let final_status = status.clone().unwrap_or(engine::GameStatus::Stopped);
+            if self.engines.instances.contains_key(&channel_id) {
+                if let Err(e) = self.tear_down_game(&channel_id, &final_status).await {
+                    tracing::warn!("Benchmark game {}: {}", channel_id, e);
+                }
+            }
+            let run = self.benchmarks.back_mut().expect("checked above");
+            run.finish_game(status.as_ref(), now);
+        }
+
+        let run = self.benchmarks.back_mut().expect("checked above");
+        let run_id = run.id;
+        if let Some(spec) = run.next_spec() {
+            let launched = self
+                .engines
+                .start_local_game(
+                    &spec.map,
+                    &spec.game,
+                    Some(&spec.opponent),
+                    true,
+                    false,
+                    &self.agent_name,
+                    true,
+                    0,
+                    None,
+                    &self.profiles.select(&spec.game).mod_options,
+                )
+                .await;
+            match launched {
+                Ok(channel_id) => {
+                    tracing::info!("Benchmark game {} on {} vs {}", channel_id, spec.map, spec.opponent);
+                    self.listen_for_game(&channel_id);
+                    let descriptor = ChannelDescriptor {
+                        id: channel_id.clone(),
+                        channel_type: "game".into(),
+                        label: format!("Benchmark game on {}", spec.map),
+                        direction: ChannelDirection::Bidirectional,
+                        address: None,
+                        metadata: Some(serde_json::json!({
+                            "map": spec.map,
+                            "game": spec.game,
+                            "opponent": spec.opponent,
+                            "status": "starting",
+                            "benchmarkRun": run_id,
+                        })),
+                    };
+                    let run = self.benchmarks.back_mut().expect("checked above");
+                    run.started(spec, channel_id, now);
+                    self.send_channels_changed(vec![descriptor], vec![], vec![]).await;
+                }
+                Err(e) => {
+                    tracing::warn!("Benchmark game on {} failed to launch: {}", spec.map, e);
+                    let run = self.benchmarks.back_mut().expect("checked above");
+                    run.launch_failed(spec, e);
+                }
+            }
+        }
+
+        let run = self.benchmarks.back_mut().expect("checked above");
+        if !run.is_done() {
+            return;
+        }
+        let report = benchmark::report(&run.results);
+        let dir = self.write_dir.join("benchmarks");
+        let secs = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let path = dir.join(format!("benchmark-{}.json", secs));
+        let saved = match tokio::fs::create_dir_all(&dir).await {
+            Ok(()) => tokio::fs::write(&path, serde_json::to_string_pretty(&report).unwrap()).await,
+            Err(e) => Err(e),
+        };
+        let run = self.benchmarks.back_mut().expect("checked above");
+        run.saved = Some(match saved {
+            Ok(()) => Ok(path.display().to_string()),
+            Err(e) => {
+                tracing::warn!("Failed to save benchmark report {}: {}", path.display(), e);
+                Err(e.to_string())
+            }
+        });
+        let status = run.status(now);
+        tracing::info!("Benchmark run {} finished: {}", run_id, status["report"]["summary"]);
+        self.push_gm_event(benchmark::EVENT, status);
+    }
+
+    // ── Lobby tool implementations (unchanged) ──
+
+    async fn tool_lobby_connect(&mut self, args: &serde_json::Value) -> serde_json::Value {
+        let host = args
+            .get("host")
+            .and_then(|v| v.as_str())
+            .unwrap_or("zero-k.info");
+        let port = args.get("port").and_then(|v| v.as_u64()).unwrap_or(8200) as u16;
+
+        match LobbyConnection::connect(host, port).await {
+            Ok(conn) => {
+                self.lobby_conn = Some(conn);
+                // A new connection starts logged out.
+                self.lobby_state.logged_in = false;
+                // Calls queued for this server wait on; another starts over.
+                match &mut self.lobby_session {
+                    Some(session) if session.host == host && session.port == port => session.connected(),
+                    _ => self.lobby_session = Some(lobby_reconnect::LobbySession::new(host, port, self.lobby_reconnect.clone())),
+                }
+                serde_json::json!({
+                    "content": [{"type": "text", "text": format!("Connected to {}:{}", host, port)}]
+                })
+            }
+            Err(e) => serde_json::json!({
+                "content": [{"type": "text", "text": format!("Connection failed: {}", e)}],
+                "isError": true
+            }),
+        }
+    }
+
+    /// Read lobby messages until we see `response_command`, or timeout.
+    /// Handles pings and updates lobby state for other messages while waiting.
+    /// Takes the connection out of self to satisfy the borrow checker.
+    async fn await_lobby_response(
+        &mut self,
+        response_command: &str,
+        timeout_secs: u64,
+    ) -> Result<serde_json::Value, String> {
+        let mut conn = match self.lobby_conn.take() {
+            Some(c) => c,
+            None => return Err("Not connected".into()),
+        };
+
+        let deadline = tokio::time::Instant::now()
+            + tokio::time::Duration::from_secs(timeout_secs);
+
+        let result = loop {
+            let remaining = deadline.saturating_duration_since(tokio::time::Instant::now());
+            if remaining.is_zero() {
+                break Err(format!("Timed out waiting for {}", response_command));
+            }
+
+            match tokio::time::timeout(remaining, conn.recv()).await {
+                Ok(Ok(msg)) => {
+                    if msg.command == response_command {
+                        // The response itself carries state too (channel
+                        // members, battle roster), so apply it like any other.
+                        self.lobby_state.handle_message(&msg);
+                        break Ok(msg.data);
+                    }
+                    // Handle keepalive
+                    if msg.command == "Ping" {
+                        let pong = LobbyMessage::new("Ping", serde_json::json!({}));
+                        let _ = conn.send(&pong).await;
+                        continue;
+                    }
+                    // Process other messages for state updates
+                    self.lobby_state.handle_message(&msg);
+                }
+                Ok(Err(e)) => {
+                    self.lobby_conn = Some(conn);
+                    return Err(format!("Connection error: {}", e));
+                }
+                Err(_) => {
+                    break Err(format!("Timed out waiting for {}", response_command));
+                }
+            }
+        };
+
+        self.lobby_conn = Some(conn);
+        result
+    }
+
+    async fn tool_lobby_login(&mut self, args: &serde_json::Value) -> serde_json::Value {
+        let username = match args.get("username").and_then(|v| v.as_str()) {
+            Some(u) => u.to_string(),
+            None => {
+                return serde_json::json!({
+                    "content": [{"type": "text", "text": "Missing username"}],
+                    "isError": true
+                })
+            }
+        };
+        let password = match args.get("password").and_then(|v| v.as_str()) {
+            Some(p) => p,
+            None => {
+                return serde_json::json!({
+                    "content": [{"type": "text", "text": "Missing password"}],
+                    "isError": true
+                })
+            }
+        };
+        self.lobby_login(username, hash_password(password)).await
+    }
+
+    /// Log in with an account from the config file's `credentials`. The
+    /// password is read here and goes nowhere but the login hash.
+    async fn tool_lobby_login_stored(&mut self, args: &serde_json::Value) -> serde_json::Value {
+        let error = |text: String| {
+            serde_json::json!({
+                "content": [{"type": "text", "text": text}],
+                "isError": true
+            })
+        };
+        let name = args.get("account").and_then(|v| v.as_str());
+        let (name, account) = match credentials::select(&self.credentials, name) {
+            Ok(selected) => selected,
+            Err(e) => return error(e),
+        };
+        let password = match account.password(name) {
+            Ok(password) => password,
+            Err(e) => return error(e),
+        };
+        let username = account.username.clone();
+        self.lobby_login(username, hash_password(&password)).await
+    }
+
+    /// Log in, keeping the login for reconnects if it works. A failure
+    /// cools the account down; see [`login_guard`].
+    async fn lobby_login(&mut self, username: String, password_hash: String) -> serde_json::Value {
+        if self.lobby_conn.is_none() {
+            return serde_json::json!({
+                "content": [{"type": "text", "text": "Not connected to lobby. Call lobby_connect first."}],
+                "isError": true
+            });
+        }
+        let failed = |failure: login_guard::LoginFailure| {
+            serde_json::json!({
+                "content": [{"type": "text", "text": failure.text()}],
+                "structuredContent": failure.to_json(),
+                "isError": true
+            })
+        };
+        if self.lobby_state.logged_in {
+            let current = self.lobby_state.my_username.clone().unwrap_or_default();
+            return failed(login_guard::LoginFailure::already_logged_in(&current));
+        }
+        let now = std::time::Instant::now();
+        if let Some(left) = self.login_guard.cooling_down(&username, now) {
+            return failed(login_guard::LoginFailure::cooling_down(&username, left));
+        }
+
+        let cmd = LoginCommand {
+            name: username.clone(),
+            password_hash: password_hash.clone(),
+            user_id: 0,
+            install_id: 0,
+            lobby_version: 0,
+            steam_auth_token: String::new(),
+            dlc: String::new(),
+        };
+
+        if let Some(conn) = &mut self.lobby_conn {
+            if let Err(e) = conn.send_command("Login", &cmd).await {
+                return serde_json::json!({
+                    "content": [{"type": "text", "text": format!("Failed to send login: {}", e)}],
+                    "isError": true
+                });
+            }
+        }
+
+        // Wait for the correlated response
+        match self.await_lobby_response("LoginResponse", 10).await {
+            Ok(data) => {
+                if let Ok(resp) = serde_json::from_value::<LoginResponseData>(data) {
+                    if resp.result_code == LOGIN_OK {
+                        self.lobby_state.logged_in = true;
+                        self.lobby_state.my_username = Some(resp.name.clone());
+                        self.login_guard.succeeded(&username);
+                        if let Some(session) = &mut self.lobby_session {
+                            session.login = Some((username, password_hash));
+                        }
+                        serde_json::json!({
+                            "content": [{"type": "text", "text": format!("Logged in as '{}'", resp.name)}]
+                        })
+                    } else {
+                        let mut failure = login_guard::LoginFailure::from_response(&resp);
+                        failure.retry_after = Some(self.login_guard.failed(&username, now).as_secs());
+                        tracing::warn!("Login for '{}' failed: {}", username, failure.text());
+                        failed(failure)
+                    }
+                } else {
+                    serde_json::json!({
+                        "content": [{"type": "text", "text": "Login response unparseable"}],
+                        "isError": true
+                    })
+                }
+            }
+            Err(e) => serde_json::json!({
+                "content": [{"type": "text", "text": e}],
+                "isError": true
+            }),
+        }
+    }
+
+    async fn tool_lobby_register(&mut self, args: &serde_json::Value) -> serde_json::Value {
+        let username = match args.get("username").and_then(|v| v.as_str()) {
+            Some(u) => u.to_string(),
+            None => {
+                return serde_json::json!({
+                    "content": [{"type": "text", "text": "Missing username"}],
+                    "isError": true
+                })
+            }
+        };
+        let password = match args.get("password").and_then(|v| v.as_str()) {
+            Some(p) => p,
+            None => {
+                return serde_json::json!({
+                    "content": [{"type": "text", "text": "Missing password"}],
+                    "isError": true
+                })
+            }
+        };
+        let email = match args.get("email").and_then(|v| v.as_str()) {
+            Some(e) => e,
+            None => {
+                return serde_json::json!({
+                    "content": [{"type": "text", "text": "Missing email"}],
+                    "isError": true
+                })
+            }
+        };
+
+        if self.lobby_conn.is_none() {
+            return serde_json::json!({
+                "content": [{"type": "text", "text": "Not connected to lobby. Call lobby_connect first."}],
+                "isError": true
+            });
+        }
+
+        let cmd = RegisterCommand {
+            name: username.clone(),
+            password_hash: hash_password(password),
+            email: email.to_string(),
+            user_id: 0,
+            install_id: String::new(),
+            steam_auth_token: String::new(),
+            dlc: String::new(),
+        };
+
+        if let Some(conn) = &mut self.lobby_conn {
+            if let Err(e) = conn.send_command("Register", &cmd).await {
+                return serde_json::json!({
+                    "content": [{"type": "text", "text": format!("Failed to send register: {}", e)}],
+                    "isError": true
+                });
+            }
+        }
+
+        // Wait for the correlated response
+        match self.await_lobby_response("RegisterResponse", 10).await {
+            Ok(data) => {
+                if let Ok(resp) = serde_json::from_value::<RegisterResponseData>(data) {
+                    if resp.result_code == REGISTER_OK {
+                        serde_json::json!({
+                            "content": [{"type": "text", "text": format!("Account '{}' registered successfully", username)}]
+                        })
+                    } else {
+                        let reason = resp.ban_reason.unwrap_or_default();
+                        serde_json::json!({
+                            "content": [{"type": "text", "text": format!("Registration failed (code {}): {}", resp.result_code, reason)}],
+                            "isError": true
+                        })
+                    }
+                } else {
+                    serde_json::json!({
+                        "content": [{"type": "text", "text": "Register response unparseable"}],
+                        "isError": true
+                    })
+                }
+            }
+            Err(e) => serde_json::json!({
+                "content": [{"type": "text", "text": e}],
+                "isError": true
+            }),
+        }
+    }
+
+    async fn tool_lobby_disconnect(&mut self) -> serde_json::Value {
+        self.lobby_conn = None;
+        self.lobby_session = None;
+        self.lobby_state = LobbyState::new();
+        self.forget_lobby_chats(|_| true).await;
+        serde_json::json!({
+            "content": [{"type": "text", "text": "Disconnected from lobby"}]
+        })
+    }
+
+    async fn tool_lobby_say(&mut self, args: &serde_json::Value) -> serde_json::Value {
+        let target = match args.get("target").and_then(|v| v.as_str()) {
+            Some(t) => t,
+            None => {
+                return serde_json::json!({
+                    "content": [{"type": "text", "text": "Missing target"}],
+                    "isError": true
+                })
+            }
+        };
+        let text = match args.get("text").and_then(|v| v.as_str()) {
+            Some(t) => t,
+            None => {
+                return serde_json::json!({
+                    "content": [{"type": "text", "text": "Missing text"}],
+                    "isError": true
+                })
+            }
+        };
+        let place = args
+            .get("place")
+            .and_then(|v| v.as_i64())
+            .unwrap_or(0) as i32;
+
+        let conn = match &mut self.lobby_conn {
+            Some(c) => c,
+            None => {
+                return serde_json::json!({
+                    "content": [{"type": "text", "text": "Not connected"}],
+                    "isError": true
+                })
+            }
+        };
+
+        let cmd = SayCommand {
+            place,
+            target: target.to_string(),
+            text: text.to_string(),
+            is_emote: false,
+        };
+
+        match conn.send_command("Say", &cmd).await {
+            Ok(()) => serde_json::json!({
+                "content": [{"type": "text", "text": format!("Sent to {}: {}", target, text)}]
+            }),
+            Err(e) => serde_json::json!({
+                "content": [{"type": "text", "text": format!("Send failed: {}", e)}],
+                "isError": true
+            }),
+        }
+    }
+
+    async fn tool_lobby_join_channel(
+        &mut self,
+        args: &serde_json::Value,
+    ) -> serde_json::Value {
+        let channel = match args.get("channel").and_then(|v| v.as_str()) {
+            Some(c) => c.to_string(),
+            None => {
+                return serde_json::json!({
+                    "content": [{"type": "text", "text": "Missing channel"}],
+                    "isError": true
+                })
+            }
+        };
+
+        if self.lobby_conn.is_none() {
+            return serde_json::json!({
+                "content": [{"type": "text", "text": "Not connected"}],
+                "isError": true
+            });
+        }
+
+        let cmd = JoinChannelCommand {
+            channel_name: channel.clone(),
+            password: String::new(),
+        };
+
+        if let Some(conn) = &mut self.lobby_conn {
+            if let Err(e) = conn.send_command("JoinChannel", &cmd).await {
+                return serde_json::json!({
+                    "content": [{"type": "text", "text": format!("Failed: {}", e)}],
+                    "isError": true
+                });
+            }
+        }
+
+        match self.await_lobby_response("JoinChannelResponse", 10).await {
+            Ok(data) => {
+                if let Ok(resp) = serde_json::from_value::<JoinChannelResponseData>(data) {
+                    if resp.success {
+                        let user_count = resp.channel.as_ref()
+                            .map(|c| c.users.len())
+                            .unwrap_or(0);
+                        let topic = resp.channel.as_ref()
+                            .and_then(|c| c.topic.as_ref())
+                            .map(|t| t.text.clone())
+                            .unwrap_or_default();
+                        self.open_lobby_chat(ChatChannel::Room(channel.clone())).await;
+                        serde_json::json!({
+                            "content": [{"type": "text", "text": format!("Joined #{} ({} users). Topic: {}", channel, user_count, if topic.is_empty() { "(none)".into() } else { topic })}]
+                        })
+                    } else {
+                        serde_json::json!({
+                            "content": [{"type": "text", "text": format!("Failed to join #{}: rejected by server", channel)}],
+                            "isError": true
+                        })
+                    }
+                } else {
+                    serde_json::json!({
+                        "content": [{"type": "text", "text": "Join response unparseable"}],
+                        "isError": true
+                    })
+                }
+            }
+            Err(e) => serde_json::json!({
+                "content": [{"type": "text", "text": e}],
+                "isError": true
+            }),
+        }
+    }
+
+    async fn tool_lobby_leave_channel(
+        &mut self,
+        args: &serde_json::Value,
+    ) -> serde_json::Value {
+        let channel = match args.get("channel").and_then(|v| v.as_str()) {
+            Some(c) => c,
+            None => {
+                return serde_json::json!({
+                    "content": [{"type": "text", "text": "Missing channel"}],
+                    "isError": true
+                })
+            }
+        };
+
+        let conn = match &mut self.lobby_conn {
+            Some(c) => c,
+            None => {
+                return serde_json::json!({
+                    "content": [{"type": "text", "text": "Not connected"}],
+                    "isError": true
+                })
+            }
+        };
+
+        let cmd = LeaveChannelCommand {
+            channel_name: channel.to_string(),
+        };
+
+        match conn.send_command("LeaveChannel", &cmd).await {
+            Ok(()) => {
+                self.lobby_state.channels.remove(channel);
+                let chat = ChatChannel::Room(channel.to_string());
+                self.forget_lobby_chats(|c| *c == chat).await;
+                serde_json::json!({
+                    "content": [{"type": "text", "text": format!("Left #{}", channel)}]
+                })
+            }
+            Err(e) => serde_json::json!({
+                "content": [{"type": "text", "text": format!("Failed: {}", e)}],
+                "isError": true
+            }),
+        }
+    }
+
+    async fn tool_lobby_list_battles(&mut self, args: &serde_json::Value) -> serde_json::Value {
+        let text = |key: &str| args.get(key).and_then(|v| v.as_str()).map(String::from);
+        let flag = |key: &str| args.get(key).and_then(|v| v.as_bool()).unwrap_or(false);
+        let sort = match args.get("sort").and_then(|v| v.as_str()) {
+            None => lobby::BattleSort::default(),
+            Some(s) => match lobby::BattleSort::parse(s) {
+                Some(sort) => sort,
+                None => {
+                    return serde_json::json!({
+                        "content": [{"type": "text", "text": format!("Unknown sort '{}' (expected id, players or title)", s)}],
+                        "isError": true
+                    })
+                }
+            },
+        };
+        let filter = lobby::BattleFilter {
+            running: args.get("running").and_then(|v| v.as_bool()),
+            has_space: flag("has_space"),
+            no_password: flag("no_password"),
+            mode: text("mode"),
+            text: text("search"),
+            map: text("map"),
+        };
+        let limit = args.get("limit").and_then(|v| v.as_u64()).unwrap_or(50) as usize;
+        let matches = self.lobby_state.list_battles(&filter, sort);
+        let total = matches.len();
+        let shown = &matches[..total.min(limit)];
+
+        let text = if flag("verbose") {
+            let battles: Vec<serde_json::Value> = shown
+                .iter()
+                .map(|b| {
+                    serde_json::json!({
+                        "id": b.battle_id,
+                        "title": b.title,
+                        "founder": b.founder,
+                        "map": b.map,
+                        "players": b.player_count,
+                        "maxPlayers": b.max_players,
+                        "spectators": b.spectator_count,
+                        "running": b.is_running,
+                        "passwordProtected": b.is_password_protected,
+                        "mode": b.mode,
+                    })
+                })
+                .collect();
+            serde_json::to_string_pretty(&serde_json::json!({ "total": total, "battles": battles })).unwrap()
+        } else if total == 0 {
+            format!("No battles match ({} open)", self.lobby_state.battles.len())
+        } else {
+            let mut text = format!("{} of {} open battles match", total, self.lobby_state.battles.len());
+            if shown.len() < total {
+                text.push_str(&format!(", showing the first {}", shown.len()));
+            }
+            for battle in shown {
+                text.push('\n');
+                text.push_str(&battle.summary_line());
+            }
+            text
+        };
+        serde_json::json!({
+            "content": [{"type": "text", "text": text}]
+        })
+    }
+
+    async fn tool_lobby_list_users(
+        &mut self,
+        args: &serde_json::Value,
+    ) -> serde_json::Value {
+        let limit = args
+            .get("limit")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(50) as usize;
+
+        let users: Vec<serde_json::Value> = self
+            .lobby_state
+            .users
+            .values()
+            .take(limit)
+            .map(|u| {
+                serde_json::json!({
+                    "name": u.name,
+                    "level": u.level,
+                    "elo": u.elo,
+                    "clan": u.clan,
+                    "country": u.country,
+                    "isBot": u.is_bot,
+                    "isAdmin": u.is_admin,
+                    "battleId": u.battle_id,
+                })
+            })
+            .collect();
+
+        serde_json::json!({
+            "content": [{"type": "text", "text": format!("{} users (showing {})\n{}", self.lobby_state.users.len(), users.len(), serde_json::to_string_pretty(&users).unwrap())}]
+        })
+    }
+
+    fn tool_lobby_user_info(&self, args: &serde_json::Value) -> serde_json::Value {
+        let Some(name) = args.get("name").and_then(|v| v.as_str()) else {
+            return serde_json::json!({
+                "content": [{"type": "text", "text": "Missing 'name' parameter"}],
+                "isError": true
+            });
+        };
+        let text = match self.lobby_state.find_users(name).as_slice() {
+            // The user list comes from the flood at login and may be
+            // incomplete, so not knowing someone isn't an error.
+            [] => format!(
+                "User '{}' is not known locally ({} users seen); they may be offline or missing from the login user list",
+                name,
+                self.lobby_state.users.len()
+            ),
+            [u] => {
+                let battle = u.battle_id.map(|id| {
+                    serde_json::json!({
+                        "id": id,
+                        "title": self.lobby_state.battles.get(&id).map(|b| b.title.as_str()),
+                    })
+                });
+                let info = serde_json::json!({
+                    "name": u.name,
+                    "displayName": u.display_name,
+                    "accountId": u.account_id,
+                    "level": u.level,
+                    "elo": u.elo,
+                    "clan": u.clan,
+                    "country": u.country,
+                    "isBot": u.is_bot,
+                    "isAdmin": u.is_admin,
+                    "battle": battle,
+                });
+                let pretty = serde_json::to_string_pretty(&info).unwrap();
+                if u.name == name {
+                    pretty
+                } else {
+                    format!("No user named '{}'; closest match is {}\n{}", name, u.name, pretty)
+                }
+            }
+            candidates => format!(
+                "No user named '{}'; candidates: {}",
+                name,
+                candidates.iter().map(|u| u.name.as_str()).collect::<Vec<_>>().join(", ")
+            ),
+        };
+        serde_json::json!({
+            "content": [{"type": "text", "text": text}]
+        })
+    }
+
+    async fn tool_lobby_join_battle(
+        &mut self,
+        args: &serde_json::Value,
+    ) -> serde_json::Value {
+        let battle_id = match args.get("battle_id").and_then(|v| v.as_i64()) {
+            Some(id) => id,
+            None => {
+                return serde_json::json!({
+                    "content": [{"type": "text", "text": "Missing battle_id"}],
+                    "isError": true
+                })
+            }
+        };
+        let password = args
+            .get("password")
+            .and_then(|v| v.as_str())
+            .unwrap_or("");
+
+        if self.lobby_conn.is_none() {
+            return serde_json::json!({
+                "content": [{"type": "text", "text": "Not connected"}],
+                "isError": true
+            });
+        }
+
+        let cmd = JoinBattleCommand {
+            battle_id,
+            password: password.to_string(),
+        };
+
+        if let Some(conn) = &mut self.lobby_conn {
+            if let Err(e) = conn.send_command("JoinBattle", &cmd).await {
+                return serde_json::json!({
+                    "content": [{"type": "text", "text": format!("Failed: {}", e)}],
+                    "isError": true
+                });
+            }
+        }
+
+        // ZKLS has no explicit failure message for JoinBattle — only JoinBattleSuccess on success.
+        // Timeout means rejection (wrong password, battle full, etc.)
+        match self.await_lobby_response("JoinBattleSuccess", 10).await {
+            Ok(data) => {
+                if let Ok(resp) = serde_json::from_value::<JoinBattleSuccessData>(data) {
+                    self.lobby_state.my_battle = Some(resp.battle_id);
+                    self.open_battle_chat(resp.battle_id).await;
+                    let player_count = resp.players.len();
+                    let bot_count = resp.bots.len();
+
+                    // Report sync status
+                    let note = self.sync_joined_battle(resp.battle_id).await;
+
+                    serde_json::json!({
+                        "content": [{"type": "text", "text": format!("Joined battle {} ({} players, {} bots){}", resp.battle_id, player_count, bot_count, note)}]
+                    })
+                } else {
+                    self.lobby_state.my_battle = Some(battle_id);
+                    self.open_battle_chat(battle_id).await;
+                    let note = self.sync_joined_battle(battle_id).await;
+                    serde_json::json!({
+                        "content": [{"type": "text", "text": format!("Joined battle {}{}", battle_id, note)}]
+                    })
+                }
+            }
+            Err(e) => serde_json::json!({
+                "content": [{"type": "text", "text": format!("Failed to join battle {}: {}", battle_id, e)}],
+                "isError": true
+            }),
+        }
+    }
+
+    async fn tool_lobby_join_battle_by(&mut self, args: &serde_json::Value) -> serde_json::Value {
+        let founder = args.get("founder").and_then(|v| v.as_str());
+        let pattern = args.get("title_pattern").and_then(|v| v.as_str());
+        if founder.is_none() && pattern.is_none() {
+            return serde_json::json!({
+                "content": [{"type": "text", "text": "Give a founder, a title_pattern or both"}],
+                "isError": true
+            });
+        }
+        let title = match pattern.map(|p| regex::RegexBuilder::new(p).case_insensitive(true).build()).transpose() {
+            Ok(title) => title,
+            Err(e) => {
+                return serde_json::json!({
+                    "content": [{"type": "text", "text": format!("Invalid title_pattern: {}", e)}],
+                    "isError": true
+                })
+            }
+        };
+        let battle_id = match self.lobby_state.find_battles(founder, title.as_ref()).as_slice() {
+            [battle] => battle.battle_id,
+            [] => {
+                return serde_json::json!({
+                    "content": [{"type": "text", "text": format!("No open battle matches ({} known)", self.lobby_state.battles.len())}],
+                    "isError": true
+                })
+            }
+            candidates => {
+                let listed: Vec<String> = candidates
+                    .iter()
+                    .map(|b| format!("{} '{}' by {}", b.battle_id, b.title, b.founder))
+                    .collect();
+                return serde_json::json!({
+                    "content": [{"type": "text", "text": format!("{} battles match: {}", candidates.len(), listed.join("; "))}],
+                    "isError": true
+                });
+            }
+        };
+        let password = args.get("password").cloned().unwrap_or_else(|| "".into());
+        self.tool_lobby_join_battle(&serde_json::json!({ "battle_id": battle_id, "password": password }))
+            .await
+    }
+
+    /// Join a newly opened battle if its founder is in `auto_join_founders`
+    /// and we aren't in a battle already, telling the agent either way.
+    async fn auto_join_battle(&mut self, event: &LobbyEvent) {
+        let LobbyEvent::BattleOpened(battle) = event else { return };
+        if self.lobby_state.my_battle.is_some()
+            || !self.auto_join_founders.iter().any(|f| f.eq_ignore_ascii_case(&battle.founder))
+        {
+            return;
+        }
+        let text = if battle.is_password_protected {
+            format!(
+                "{} opened battle {} '{}', but it has a password; not auto-joining",
+                battle.founder, battle.battle_id, battle.title
+            )
+        } else {
+            let result = self.tool_lobby_join_battle(&serde_json::json!({ "battle_id": battle.battle_id })).await;
+            let outcome = match result["isError"].as_bool() {
+                Some(true) => Err(result["content"][0]["text"].as_str().unwrap_or_default().to_string()),
+                _ => Ok(()),
+            };
+            let details = serde_json::json!({ "battle_id": battle.battle_id, "founder": battle.founder, "title": battle.title });
+            self.audit(audit::Kind::AutoJoin, "lobby_join_battle", &details, &outcome);
+            format!(
+                "Auto-join of {}'s battle '{}': {}",
+                battle.founder,
+                battle.title,
+                result["content"][0]["text"].as_str().unwrap_or_default()
+            )
+        };
+        tracing::info!("{}", text);
+        if let Err(e) = self.send_lobby_push("lobby.auto_join", text, false).await {
+            tracing::error!("Failed to push auto-join event: {}", e);
+        }
+    }
+
+    async fn tool_lobby_leave_battle(&mut self) -> serde_json::Value {
+        let conn = match &mut self.lobby_conn {
+            Some(c) => c,
+            None => {
+                return serde_json::json!({
+                    "content": [{"type": "text", "text": "Not connected"}],
+                    "isError": true
+                })
+            }
+        };
+
+        let cmd = LeaveBattleCommand { battle_id: None };
+
+        match conn.send_command("LeaveBattle", &cmd).await {
+            Ok(()) => {
+                self.lobby_state.my_battle = None;
+                self.lobby_state.battle_roster = Default::default();
+                self.forget_lobby_chats(|c| matches!(c, ChatChannel::Battle(_))).await;
+                serde_json::json!({
+                    "content": [{"type": "text", "text": "Left battle"}]
+                })
+            }
+            Err(e) => serde_json::json!({
+                "content": [{"type": "text", "text": format!("Failed: {}", e)}],
+                "isError": true
+            }),
+        }
+    }
+
+    // ── Matchmaker tool implementations ──
+
+    async fn tool_lobby_matchmaker_join(
+        &mut self,
+        args: &serde_json::Value,
+    ) -> serde_json::Value {
+        let queues: Vec<String> = match args.get("queues").and_then(|v| v.as_array()) {
+            Some(arr) => arr
+                .iter()
+                .filter_map(|v| v.as_str().map(String::from))
+                .collect(),
+            None => {
+                return serde_json::json!({
+                    "content": [{"type": "text", "text": "Missing queues array"}],
+                    "isError": true
+                })
+            }
+        };
+
+        if queues.is_empty() {
+            return serde_json::json!({
+                "content": [{"type": "text", "text": "Queues array is empty. Use lobby_matchmaker_leave to leave all queues."}],
+                "isError": true
+            });
+        }
+
+        if self.lobby_conn.is_none() {
+            return serde_json::json!({
+                "content": [{"type": "text", "text": "Not connected"}],
+                "isError": true
+            });
+        }
+
+        let cmd = MatchMakerQueueRequestCommand {
+            queues: queues.clone(),
+        };
+
+        if let Some(conn) = &mut self.lobby_conn {
+            if let Err(e) = conn.send_command("MatchMakerQueueRequest", &cmd).await {
+                return serde_json::json!({
+                    "content": [{"type": "text", "text": format!("Failed: {}", e)}],
+                    "isError": true
+                });
+            }
+        }
+
+        // Wait for MatchMakerStatus response confirming our queue state
+        match self.await_lobby_response("MatchMakerStatus", 10).await {
+            Ok(data) => {
+                if let Ok(status) = serde_json::from_value::<MatchMakerStatusData>(data) {
+                    self.lobby_state.matchmaker_joined = status.joined_queues.clone();
+                    self.lobby_state.matchmaker_queue_counts = status.queue_counts.clone();
+                    let joined = status.joined_queues.join(", ");
+                    let counts: Vec<String> = status
+                        .queue_counts
+                        .iter()
+                        .filter(|(name, _)| status.joined_queues.contains(name))
+                        .map(|(name, count)| format!("{}: {} queued", name, count))
+                        .collect();
+                    if status.joined_queues.is_empty() {
+                        serde_json::json!({
+                            "content": [{"type": "text", "text": format!("Failed to join queues (may be banned for {}s)", status.banned_seconds.unwrap_or(0))}],
+                            "isError": true
+                        })
+                    } else {
+                        serde_json::json!({
+                            "content": [{"type": "text", "text": format!("Joined matchmaker queues: [{}]. {}", joined, counts.join(", "))}]
+                        })
+                    }
+                } else {
+                    serde_json::json!({
+                        "content": [{"type": "text", "text": "MatchMakerStatus unparseable"}],
+                        "isError": true
+                    })
+                }
+            }
+            Err(e) => serde_json::json!({
+                "content": [{"type": "text", "text": e}],
+                "isError": true
+            }),
+        }
+    }
+
+    async fn tool_lobby_matchmaker_leave(&mut self) -> serde_json::Value {
+        if self.lobby_conn.is_none() {
+            return serde_json::json!({
+                "content": [{"type": "text", "text": "Not connected"}],
+                "isError": true
+            });
+        }
+
+        let cmd = MatchMakerQueueRequestCommand {
+            queues: vec![],
+        };
+
+        if let Some(conn) = &mut self.lobby_conn {
+            if let Err(e) = conn.send_command("MatchMakerQueueRequest", &cmd).await {
+                return serde_json::json!({
+                    "content": [{"type": "text", "text": format!("Failed: {}", e)}],
+                    "isError": true
+                });
+            }
+        }
+
+        match self.await_lobby_response("MatchMakerStatus", 10).await {
+            Ok(data) => {
+                if let Ok(status) = serde_json::from_value::<MatchMakerStatusData>(data) {
+                    self.lobby_state.matchmaker_joined = status.joined_queues.clone();
+                    self.lobby_state.matchmaker_queue_counts = status.queue_counts.clone();
+                    serde_json::json!({
+                        "content": [{"type": "text", "text": "Left all matchmaker queues"}]
+                    })
+                } else {
+                    self.lobby_state.matchmaker_joined.clear();
+                    serde_json::json!({
+                        "content": [{"type": "text", "text": "Left matchmaker queues"}]
+                    })
+                }
+            }
+            Err(e) => {
+                // Even on timeout, we likely left — the server might not send status
+                // if we weren't in any queue
+                self.lobby_state.matchmaker_joined.clear();
+                tracing::debug!("MatchMakerStatus await after leave: {}", e);
+                serde_json::json!({
+                    "content": [{"type": "text", "text": "Left matchmaker queues"}]
+                })
+            }
+        }
+    }
+
+    async fn tool_lobby_matchmaker_accept(
+        &mut self,
+        args: &serde_json::Value,
+    ) -> serde_json::Value {
+        let ready = args
+            .get("ready")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(true);
+
+        let conn = match &mut self.lobby_conn {
+            Some(c) => c,
+            None => {
+                return serde_json::json!({
+                    "content": [{"type": "text", "text": "Not connected"}],
+                    "isError": true
+                })
+            }
+        };
+
+        if !self.lobby_state.matchmaker_ready_pending {
+            return serde_json::json!({
+                "content": [{"type": "text", "text": "No ready-check pending"}],
+                "isError": true
+            });
+        }
+        let now = chrono::Utc::now();
+        if let Some(deadline) = self.lobby_state.matchmaker_ready_deadline.filter(|d| *d < now) {
+            self.lobby_state.matchmaker_ready_pending = false;
+            return serde_json::json!({
+                "content": [{"type": "text", "text": format!("Ready-check expired {}s ago", (now - deadline).num_seconds())}],
+                "isError": true
+            });
+        }
+
+        let cmd = AreYouReadyResponseCommand { ready };
+
+        match conn.send_command("AreYouReadyResponse", &cmd).await {
+            Ok(()) => {
+                self.lobby_state.matchmaker_ready_pending = false;
+                let action = if ready { "Accepted" } else { "Declined" };
+                serde_json::json!({
+                    "content": [{"type": "text", "text": format!("{} matchmaker ready-check", action)}]
+                })
+            }
+            Err(e) => serde_json::json!({
+                "content": [{"type": "text", "text": format!("Failed: {}", e)}],
+                "isError": true
+            }),
+        }
+    }
+
+    /// Tell the lobby server whether we have the map/game/engine files.
+    async fn send_battle_sync(&mut self, synced: bool) {
+        let username = self.lobby_state.my_username.clone().unwrap_or_default();
+        let cmd = UpdateUserBattleStatusCommand {
+            name: username,
+            is_spectator: Some(false),
+            sync: Some(if synced { "Synced" } else { "Unsynced" }.into()),
+            ally_number: Some(0),
+        };
+        if let Some(conn) = &mut self.lobby_conn {
+            if let Err(e) = conn.send_command("UpdateUserBattleStatus", &cmd).await {
+                tracing::warn!("Failed to send sync status: {}", e);
+            }
+        }
+    }
+
+    // ── Battle hosting tool implementations ──
+
+    async fn tool_lobby_open_battle(
+        &mut self,
+        args: &serde_json::Value,
+    ) -> serde_json::Value {
+        let title = match args.get("title").and_then(|v| v.as_str()) {
+            Some(t) => t.to_string(),
+            None => {
+                return serde_json::json!({
+                    "content": [{"type": "text", "text": "Missing title"}],
+                    "isError": true
+                })
+            }
+        };
+        let map = match args.get("map").and_then(|v| v.as_str()) {
+            Some(m) => m.to_string(),
+            None => {
+                return serde_json::json!({
+                    "content": [{"type": "text", "text": "Missing map"}],
+                    "isError": true
+                })
+            }
+        };
+        let max_players = args
+            .get("max_players")
+            .and_then(|v| v.as_i64())
+            .unwrap_or(2) as i32;
+        let password = args
+            .get("password")
+            .and_then(|v| v.as_str())
+            .unwrap_or("")
+            .to_string();
+
+        if !self.lobby_state.logged_in {
+            return serde_json::json!({
+                "content": [{"type": "text", "text": "Not logged in"}],
+                "isError": true
+            });
+        }
+
+        let cmd = OpenBattleCommand {
+            header: BattleHeader {
+                battle_id: 0,
+                title: title.clone(),
+                founder: self.lobby_state.my_username.clone().unwrap_or_default(),
+                map: map.clone(),
+                game: self.lobby_state.server_game.clone(),
+                engine: self.lobby_state.server_engine.clone(),
+                max_players,
+                player_count: 0,
+                spectator_count: 0,
+                is_running: false,
+                is_password_protected: !password.is_empty(),
+                mode: None,
+            },
+        };
+
+        if let Some(conn) = &mut self.lobby_conn {
+            if let Err(e) = conn.send_command("OpenBattle", &cmd).await {
+                return serde_json::json!({
+                    "content": [{"type": "text", "text": format!("Failed to send OpenBattle: {}", e)}],
+                    "isError": true
+                });
+            }
+        }
+
+        // The server responds with JoinBattleSuccess (founder auto-joins)
+        match self.await_lobby_response("JoinBattleSuccess", 10).await {
+            Ok(data) => {
+                if let Ok(resp) = serde_json::from_value::<JoinBattleSuccessData>(data) {
+                    self.lobby_state.my_battle = Some(resp.battle_id);
+                    self.open_battle_chat(resp.battle_id).await;
+
+                    // Report sync status — whether we have the map/engine. The
+                    // server's game is a rapid tag (zk:stable), which the
+                    // archive cache can't be checked against.
+                    let engine = self.lobby_state.server_engine.clone();
+                    let note = self.sync_battle_content(&map, "", &engine).await;
+
+                    serde_json::json!({
+                        "content": [{"type": "text", "text": format!(
+                            "Opened battle '{}' on {} (battle_id: {}). Add bots with lobby_add_bot, then start with lobby_start_battle.{}",
+                            title, map, resp.battle_id, note
+                        )}]
+                    })
+                } else {
+                    serde_json::json!({
+                        "content": [{"type": "text", "text": "Opened battle but could not parse response"}]
+                    })
+                }
+            }
+            Err(e) => serde_json::json!({
+                "content": [{"type": "text", "text": format!("Failed to open battle: {}", e)}],
+                "isError": true
+            }),
+        }
+    }
+
+    async fn tool_lobby_add_bot(
+        &mut self,
+        args: &serde_json::Value,
+    ) -> serde_json::Value {
+        let ai_lib = match args.get("ai_lib").and_then(|v| v.as_str()) {
+            Some(a) => a.to_string(),
+            None => {
+                return serde_json::json!({
+                    "content": [{"type": "text", "text": "Missing ai_lib"}],
+                    "isError": true
+                })
+            }
+        };
+        let name = args
+            .get("name")
+            .and_then(|v| v.as_str())
+            .unwrap_or("Bot1")
+            .to_string();
+        let ally_number = args
+            .get("ally_number")
+            .and_then(|v| v.as_i64())
+            .unwrap_or(1) as i32;
+
+        if self.lobby_state.my_battle.is_none() {
+            return serde_json::json!({
+                "content": [{"type": "text", "text": "Not in a battle"}],
+                "isError": true
+            });
+        }
+
+        let cmd = UpdateBotStatusCommand {
+            name: name.clone(),
+            ai_lib: ai_lib.clone(),
+            ally_number,
+            owner: self.lobby_state.my_username.clone().unwrap_or_default(),
+        };
+
+        if let Some(conn) = &mut self.lobby_conn {
+            match conn.send_command("UpdateBotStatus", &cmd).await {
+                Ok(()) => serde_json::json!({
+                    "content": [{"type": "text", "text": format!("Added bot '{}' (AI: {}, ally: {})", name, ai_lib, ally_number)}]
+                }),
+                Err(e) => serde_json::json!({
+                    "content": [{"type": "text", "text": format!("Failed: {}", e)}],
+                    "isError": true
+                }),
+            }
+        } else {
+            serde_json::json!({
+                "content": [{"type": "text", "text": "Not connected"}],
+                "isError": true
+            })
+        }
+    }
+
+    async fn tool_lobby_remove_bot(
+        &mut self,
+        args: &serde_json::Value,
+    ) -> serde_json::Value {
+        let name = match args.get("name").and_then(|v| v.as_str()) {
+            Some(n) => n.to_string(),
+            None => {
+                return serde_json::json!({
+                    "content": [{"type": "text", "text": "Missing bot name"}],
+                    "isError": true
+                })
+            }
+        };
+
+        let cmd = RemoveBotCommand { name: name.clone() };
+
+        if let Some(conn) = &mut self.lobby_conn {
+            match conn.send_command("RemoveBot", &cmd).await {
+                Ok(()) => serde_json::json!({
+                    "content": [{"type": "text", "text": format!("Removed bot '{}'", name)}]
+                }),
+                Err(e) => serde_json::json!({
+                    "content": [{"type": "text", "text": format!("Failed: {}", e)}],
+                    "isError": true
+                }),
+            }
+        } else {
+            serde_json::json!({
+                "content": [{"type": "text", "text": "Not connected"}],
+                "isError": true
+            })
+        }
+    }
+
+    async fn tool_lobby_start_battle(&mut self) -> serde_json::Value {
+        if self.lobby_state.my_battle.is_none() {
+            return serde_json::json!({
+                "content": [{"type": "text", "text": "Not in a battle"}],
+                "isError": true
+            });
+        }
+
+        // ZK custom battles are started by sending !start in battle chat.
+        // The ZKLS autohost processes it and spins up a dedicated game server.
+        let cmd = SayCommand {
+            place: PLACE_BATTLE,
+            target: String::new(),
+            text: "!start".into(),
+            is_emote: false,
+        };
+
+        if let Some(conn) = &mut self.lobby_conn {
+            if let Err(e) = conn.send_command("Say", &cmd).await {
+                return serde_json::json!({
+                    "content": [{"type": "text", "text": format!("Failed to send !start: {}", e)}],
+                    "isError": true
+                });
+            }
+        }
+
+        // Return immediately — the background event loop will receive ConnectSpring
+        // and call handle_connect_spring() to launch the engine. The agent will get
+        // a lobby.connect_spring push event when that happens.
+        tracing::info!("Sent !start to battle chat, waiting for ConnectSpring from background loop");
+        serde_json::json!({
+            "content": [{"type": "text", "text": "Sent !start — waiting for game server to launch. You'll receive a lobby.connect_spring event when the engine connects."}]
+        })
+    }
+
+    async fn tool_lobby_start_game(
+        &mut self,
+        args: &serde_json::Value,
+    ) -> serde_json::Value {
+        let map = match args.get("map").and_then(|v| v.as_str()) {
+            Some(m) => m.to_string(),
+            None => {
+                return serde_json::json!({
+                    "content": [{"type": "text", "text": "Missing map name"}],
+                    "isError": true
+                })
+            }
+        };
+        let game = args
+            .get("game")
+            .and_then(|v| v.as_str())
+            .unwrap_or("Zero-K $VERSION");
+        let profile = self.profiles.select(game).clone();
+        let opponent = args
+            .get("opponent")
+            .and_then(|v| v.as_str())
+            .or(profile.default_opponent.as_deref())
+            .unwrap_or(opponents::DEFAULT_OPPONENT);
+        let player_mode = args
+            .get("player_mode")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+        if let Err(e) = self.check_opponent(opponent, game, player_mode) {
+            return serde_json::json!({
+                "content": [{"type": "text", "text": e}],
+                "isError": true
+            });
+        }
+        let headless = if player_mode {
+            false // player mode needs LuaUI for bootstrap widget
+        } else {
+            args.get("headless").and_then(|v| v.as_bool()).unwrap_or(true)
+        };
+        let requested_id = args.get("channel_id").and_then(|v| v.as_str());
+        let preserve_state = args.get("preserve_state").and_then(|v| v.as_bool()).unwrap_or(false);
+        let kept = match requested_id {
+            Some(id) => match self.claim_channel_id(id, preserve_state).await {
+                Ok(kept) => kept,
+                Err(e) => {
+                    return serde_json::json!({
+                        "content": [{"type": "text", "text": format!("Failed to start game: {}", e)}],
+                        "isError": true
+                    })
+                }
+            },
+            None => None,
+        };
+
+        match self
+            .engines
+            .start_local_game(
+                &map,
+                game,
+                Some(opponent),
+                headless,
+                player_mode,
+                &self.agent_name,
+                false,
+                0,
+                requested_id,
+                &profile.mod_options,
+            )
+            .await
+        {
+            Ok(channel_id) => {
+                self.listen_for_game(&channel_id);
+                let opponent_channel = self.opponent_descriptor(&channel_id);
+                if let Some(kept) = kept {
+                    if let Some(verbosity) = kept.verbosity {
+                        self.verbosity.insert(channel_id.clone(), verbosity);
+                    }
+                    if let Some(stream) = kept.stream {
+                        self.observers.insert(channel_id.clone(), observer::ChannelObserver::new(stream));
+                    }
+                    self.groups.insert(channel_id.clone(), kept.groups);
+                }
+
+                // Notify channels/changed
+                let mut metadata = serde_json::json!({
+                    "map": map,
+                    "opponent": opponent,
+                    "headless": headless,
+                    "status": "starting",
+                });
+                let mut text = format!(
+                    "Started local game: AgentBridge vs {} on {} (channel: {}, headless: {})",
+                    opponent, map, channel_id, headless
+                );
+                if let Some(descriptor) = &opponent_channel {
+                    metadata["opponentChannel"] = descriptor.id.clone().into();
+                    text += &format!(". The opponent plays on {}", descriptor.id);
+                }
+                let added = std::iter::once(ChannelDescriptor {
+                    id: channel_id.clone(),
+                    channel_type: "game".into(),
+                    label: format!("Local game on {}", map),
+                    direction: ChannelDirection::Bidirectional,
+                    address: None,
+                    metadata: Some(metadata),
+                })
+                .chain(opponent_channel)
+                .collect();
+                self.send_channels_changed(added, vec![], vec![]).await;
+
+                serde_json::json!({
+                    "content": [{"type": "text", "text": text}]
+                })
+            }
+            Err(e) => serde_json::json!({
+                "content": [{"type": "text", "text": format!("Failed to start game: {}", e)}],
+                "isError": true
+            }),
+        }
+    }
+
+    /// Handle ConnectSpring lobby event — launch engine in client mode for multiplayer.
+    async fn handle_connect_spring(&mut self, data: &ConnectSpringData) {
+        tracing::info!(
+            "ConnectSpring received: {}:{} map={} mode={}",
+            data.ip, data.port, data.map, data.mode
+        );
+        // Launching without the content would only fail in the engine.
+        let check = self.ensure_content(&data.map, &data.game, &data.engine, Some(data.clone())).await;
+        if !check.is_complete() {
+            return;
+        }
+        self.launch_connect_spring(data).await;
+    }
+
+    async fn launch_connect_spring(&mut self, data: &ConnectSpringData) {
+        let player_name = self
+            .lobby_state
+            .my_username
+            .clone()
+            .unwrap_or_else(|| self.agent_name.clone());
+        // Set if this game comes out of a matchmaker ready-check.
+        let matchmaker = self.lobby_state.matchmaker_match.take();
+
+        // Warm archive cache for the server's engine version if needed
+        if !data.engine.is_empty() {
+            if let Ok(mp_engine_dir) = engine::find_engine_dir(&self.spring_home, Some(&data.engine)) {
+                let _ = engine::chmod_executable(&mp_engine_dir);
+                if mp_engine_dir != self.engines.engine_dir {
+                    engine::warm_archive_cache(&self.write_dir, &mp_engine_dir).await;
+                }
+            }
+        }
+
+        match self
+            .engines
+            .start_multiplayer_game(data, &player_name, &self.spring_home)
+            .await
+        {
+            Ok(channel_id) => {
+                self.listen_for_game(&channel_id);
+
+                let mut metadata = serde_json::json!({
+                    "map": data.map,
+                    "mode": data.mode,
+                    "title": data.title,
+                    "status": "connecting",
+                    "multiplayer": true,
+                });
+                if let Some(context) = matchmaker {
+                    metadata["matchmaker"] = context.to_json();
+                    self.matchmaker_games.insert(channel_id.clone(), context);
+                }
+                // A battle room we're in hands over to the game.
+                let battle = self
+                    .lobby_state
+                    .my_battle
+                    .map(ChatChannel::Battle)
+                    .filter(|chat| self.lobby_chats.contains(chat));
+                if let Some(chat @ ChatChannel::Battle(battle_id)) = &battle {
+                    metadata["battleChannel"] = chat.id().into();
+                    self.battle_games.insert(channel_id.clone(), *battle_id);
+                }
+                self.send_channels_changed(
+                    vec![ChannelDescriptor {
+                        id: channel_id.clone(),
+                        channel_type: "game".into(),
+                        label: format!("MP game on {}", data.map),
+                        direction: ChannelDirection::Bidirectional,
+                        address: None,
+                        metadata: Some(metadata),
+                    }],
+                    vec![],
+                    vec![],
+                )
+                .await;
+                if let Some(chat) = battle {
+                    let descriptor = chat.descriptor(self.lobby_chat_metadata(&chat));
+                    self.send_channels_changed(vec![], vec![], vec![descriptor]).await;
+                    let notice = self.notice_message(
+                        &chat.id(),
+                        format!("The game is on {}", channel_id),
+                        serde_json::json!({ "battleChange": { "change": "game_launched" }, "gameChannel": channel_id }),
+                    );
+                    self.push_incoming(notice).await;
+                }
+
+                tracing::info!("Launched multiplayer engine for channel {}", channel_id);
+            }
+            Err(e) => {
+                tracing::error!("Failed to launch engine for ConnectSpring: {}", e);
+            }
+        }
+    }
+
+    fn tool_lobby_status(&self) -> serde_json::Value {
+        let mut status = self.lobby_state.status();
+        let last_received = self.lobby_conn.as_ref().and_then(|c| c.last_received());
+        status["lastReceived"] = last_received.map(|t| t.to_rfc3339()).into();
+        status["secondsSinceLastReceived"] =
+            last_received.map(|t| (chrono::Utc::now() - t).num_seconds()).into();
+        serde_json::json!({
+            "content": [{"type": "text", "text": serde_json::to_string_pretty(&status).unwrap()}]
+        })
+    }
+
+    async fn tool_lobby_matchmaker_status(&mut self) -> serde_json::Value {
+        let available: Vec<serde_json::Value> = self
+            .lobby_state
+            .matchmaker_queues
+            .iter()
+            .map(|q| {
+                let count = self
+                    .lobby_state
+                    .matchmaker_queue_counts
+                    .get(&q.name)
+                    .copied()
+                    .unwrap_or(0);
+                serde_json::json!({
+                    "name": q.name,
+                    "description": q.description,
+                    "maxPartySize": q.max_party_size,
+                    "queued": count,
+                })
+            })
+            .collect();
+
+        let joined = &self.lobby_state.matchmaker_joined;
+        let ready_pending = self.lobby_state.matchmaker_ready_pending;
+
+        serde_json::json!({
+            "content": [{"type": "text", "text": format!(
+                "Joined queues: [{}]\nReady-check pending: {}\nAvailable queues:\n{}",
+                joined.join(", "),
+                ready_pending,
+                serde_json::to_string_pretty(&available).unwrap()
+            )}]
+        })
+    }
+
+    // ── Battle content ──
+
+    /// Check the content of a battle we joined and report our sync status.
+    /// Battles the lobby never described count as synced.
+    async fn sync_joined_battle(&mut self, battle_id: i64) -> String {
+        let Some(battle) = self.lobby_state.battles.get(&battle_id).cloned() else {
+            self.send_battle_sync(true).await;
+            return String::new();
+        };
+        self.sync_battle_content(&battle.map, &battle.game, &battle.engine).await
+    }
+
+    /// Report our sync status for a battle's content, fetching whatever is
+    /// missing. Returns a note for the tool result when something is.
+    async fn sync_battle_content(&mut self, map: &str, game: &str, engine: &str) -> String {
+        let check = self.ensure_content(map, game, engine, None).await;
+        self.send_battle_sync(check.is_complete()).await;
+        if check.is_complete() {
+            return String::new();
+        }
+        let mut missing: Vec<String> = check.missing.iter().map(|a| format!("{} (downloading)", a)).collect();
+        missing.extend(check.missing_engine.map(|e| format!("engine {} (not installed)", e)));
+        format!(". Unsynced, missing {}", missing.join(", "))
+    }
+
+    /// Check a battle's content and start downloading what's missing. An
+    /// uninstalled engine can't be downloaded and is reported instead.
+    /// `launch` is held back until the downloads finish.
+    async fn ensure_content(
+        &mut self,
+        map: &str,
+        game: &str,
+        engine: &str,
+        launch: Option<ConnectSpringData>,
+    ) -> content::ContentCheck {
+        let check = content::check(&self.write_dir, &self.spring_home, map, game, engine);
+        if check.is_complete() {
+            self.content_wait = None;
+            return check;
+        }
+        for archive in &check.missing {
+            self.downloads.start(archive.clone());
+        }
+        let text = if let Some(engine) = &check.missing_engine {
+            self.content_wait = None;
+            format!(
+                "Engine {} required but not installed (looked in {}). Install it with engine_install, then rejoin the battle{}",
+                engine,
+                self.spring_home.join("engine/linux64").display(),
+                if launch.is_some() { "; the game was not launched" } else { "" }
+            )
+        } else {
+            let missing: Vec<String> = check.missing.iter().map(|a| a.to_string()).collect();
+            self.content_wait = Some(ContentWait { archives: check.missing.clone(), launch });
+            format!("Missing {}; downloading with pr-downloader", missing.join(" and "))
+        };
+        let event_id = if check.missing_engine.is_some() { "lobby.engine_missing" } else { "lobby.download_started" };
+        if let Err(e) = self.send_lobby_push(event_id, text, false).await {
+            tracing::error!("Failed to push content event: {}", e);
+        }
+        check
+    }
+
+    /// Report download progress; once the awaited content is all there,
+    /// sync up and launch whatever was held back.
+    async fn poll_downloads(&mut self) {
+        for update in self.downloads.poll() {
+            let (event_id, text) = match &update {
+                content::DownloadUpdate::Progress { archive, percent } => {
+                    ("lobby.download_progress", format!("Downloading {}: {}%", archive, percent))
+                }
+                content::DownloadUpdate::Done { archive } => ("lobby.download_done", format!("Downloaded {}", archive)),
+                content::DownloadUpdate::Failed { archive, reason } => {
+                    ("lobby.download_failed", format!("Download of {} failed: {}", archive, reason))
+                }
+            };
+            if let Err(e) = self.send_lobby_push(event_id, text, false).await {
+                tracing::error!("Failed to push download event: {}", e);
+            }
+
+            let Some(wait) = &mut self.content_wait else { continue };
+            match update {
+                content::DownloadUpdate::Done { archive } => {
+                    wait.archives.retain(|a| *a != archive);
+                    if !wait.archives.is_empty() {
+                        continue;
+                    }
+                    let launch = self.content_wait.take().and_then(|w| w.launch);
+                    if self.lobby_state.my_battle.is_some() {
+                        self.send_battle_sync(true).await;
+                    }
+                    if let Some(data) = launch {
+                        self.launch_connect_spring(&data).await;
+                    }
+                }
+                content::DownloadUpdate::Failed { archive, reason } if wait.archives.contains(&archive) => {
+                    let launch = self.content_wait.take().and_then(|w| w.launch);
+                    if self.lobby_state.my_battle.is_some() {
+                        self.send_battle_sync(false).await;
+                    }
+                    let text = format!(
+                        "Staying unsynced without {} ({}){}",
+                        archive,
+                        reason,
+                        if launch.is_some() { "; the game was not launched" } else { "" }
+                    );
+                    if let Err(e) = self.send_lobby_push("lobby.content_unavailable", text, false).await {
+                        tracing::error!("Failed to push content event: {}", e);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // ── Engine installs ──
+
+    fn tool_engine_install(&mut self, args: &serde_json::Value) -> serde_json::Value {
+        let Some(version) = args.get("version").and_then(|v| v.as_str()) else {
+            return serde_json::json!({
+                "content": [{"type": "text", "text": "Missing version"}],
+                "isError": true
+            });
+        };
+        let text = if self.engine_installs.start(self.spring_home.clone(), version.to_string()) {
+            format!(
+                "Installing engine {} from {}; progress follows as lobby.engine_install_* push events",
+                version,
+                self.engine_installs.mirror.replace("{version}", version)
+            )
+        } else {
+            format!("Engine {} is already being installed", version)
+        };
+        serde_json::json!({
+            "content": [{"type": "text", "text": text}]
+        })
+    }
+
+    /// Report engine install progress as push events.
+    async fn poll_engine_installs(&mut self) {
+        for update in self.engine_installs.poll() {
+            let (event_id, text) = match update {
+                engine_install::InstallUpdate::Progress { version, text } => {
+                    ("lobby.engine_install_progress", format!("Engine {}: {}", version, text))
+                }
+                engine_install::InstallUpdate::Done { version, dir } => {
+                    ("lobby.engine_install_done", format!("Engine {} installed in {}", version, dir.display()))
+                }
+                engine_install::InstallUpdate::Failed { version, reason } => {
+                    ("lobby.engine_install_failed", format!("Installing engine {} failed: {}", version, reason))
+                }
+            };
+            if let Err(e) = self.send_lobby_push(event_id, text, false).await {
+                tracing::error!("Failed to push engine install event: {}", e);
+            }
+        }
+    }
+
+    // ── Lobby chat channels ──
+
+    fn lobby_chat_metadata(&self, chat: &ChatChannel) -> Option<serde_json::Value> {
+        match chat {
+            ChatChannel::Room(room) => {
+                let info = self.lobby_state.channels.get(room)?;
+                Some(serde_json::json!({ "users": info.users.len(), "topic": info.topic }))
+            }
+            ChatChannel::Direct(_) => None,
+            ChatChannel::Battle(battle_id) => {
+                let mut metadata = serde_json::json!({ "roster": self.lobby_state.battle_roster.to_json() });
+                if let Some(battle) = self.lobby_state.battles.get(battle_id) {
+                    metadata["title"] = battle.title.clone().into();
+                    metadata["map"] = battle.map.clone().into();
+                    metadata["founder"] = battle.founder.clone().into();
+                    metadata["running"] = battle.is_running.into();
+                }
+                if let Some(game) = self.battle_games.iter().find(|(_, id)| *id == battle_id).map(|(game, _)| game) {
+                    metadata["gameChannel"] = game.clone().into();
+                }
+                Some(metadata)
+            }
+        }
+    }
+
+    /// Announce a lobby room or DM conversation as a channel, once.
+    async fn open_lobby_chat(&mut self, chat: ChatChannel) {
+        if self.lobby_chats.contains(&chat) {
+            return;
+        }
+        let descriptor = chat.descriptor(self.lobby_chat_metadata(&chat));
+        self.lobby_chats.insert(chat);
+        self.send_channels_changed(vec![descriptor], vec![], vec![]).await;
+    }
+
+    /// Announce the battle we joined as a channel, replacing any other.
+    async fn open_battle_chat(&mut self, battle_id: i64) {
+        self.forget_lobby_chats(|c| matches!(c, ChatChannel::Battle(id) if *id != battle_id)).await;
+        self.open_lobby_chat(ChatChannel::Battle(battle_id)).await;
+    }
+
+    /// Tell the battle channel what changed in the room. Kicks and closed
+    /// battles end the channel.
+    async fn route_battle_room(&mut self, event: &LobbyEvent) -> bool {
+        let (chat, text, change, ended) = match event {
+            LobbyEvent::BattleRoom { battle_id, change } => {
+                let ended = *change == BattleRoomChange::Removed;
+                (ChatChannel::Battle(*battle_id), change.text(), change.to_json(), ended)
+            }
+            LobbyEvent::BattleClosed { battle_id } => (
+                ChatChannel::Battle(*battle_id),
+                format!("Battle {} closed", battle_id),
+                serde_json::json!({ "change": "closed" }),
+                true,
+            ),
+            _ => return false,
+        };
+        if !self.lobby_chats.contains(&chat) {
+            // Other battles closing isn't news.
+            return matches!(event, LobbyEvent::BattleRoom { .. });
+        }
+        let metadata = serde_json::json!({
+            "battleChange": change,
+            "roster": self.lobby_state.battle_roster.to_json(),
+        });
+        let notice = self.notice_message(&chat.id(), text, metadata);
+        self.push_incoming(notice).await;
+        if ended {
+            self.forget_lobby_chats(|c| *c == chat).await;
+        }
+        true
+    }
+
+    /// Drop the chat channels matching `which` and announce their removal.
+    async fn forget_lobby_chats(&mut self, which: impl Fn(&ChatChannel) -> bool) {
+        let removed: Vec<String> = self.lobby_chats.iter().filter(|c| which(c)).map(|c| c.id()).collect();
+        if removed.is_empty() {
+            return;
+        }
+        self.lobby_chats.retain(|c| !which(c));
+        self.send_channels_changed(vec![], removed, vec![]).await;
+    }
+
+    /// Send lobby chat that belongs to a room, DM or our battle to its
+    /// channel. False for chat without one (server messages, rooms we
+    /// aren't in), which goes out as a push event instead.
+    async fn route_lobby_chat(&mut self, event: &LobbyEvent) -> bool {
+        let LobbyEvent::ChatMessage { user, text, target, place, is_emote, time } = event else {
+            return false;
+        };
+        let me = self.lobby_state.my_username.clone().unwrap_or_default();
+        let Some(chat) = ChatChannel::of_said(*place, user, target, &me, self.lobby_state.my_battle) else {
+            return false;
+        };
+        if let ChatChannel::Room(room) = &chat {
+            if !self.lobby_state.channels.contains_key(room) {
+                return false;
+            }
+        }
+        // The server echoes our own messages; the agent knows what it said.
+        if *user == me {
+            return true;
+        }
+        self.open_lobby_chat(chat.clone()).await;
+        let message = self.lobby_chat_message(&chat, user, text, *is_emote, time);
+        self.push_incoming(message).await;
+        true
+    }
+
+    fn lobby_chat_message(
+        &self,
+        chat: &ChatChannel,
+        user: &str,
+        text: &str,
+        is_emote: bool,
+        time: &str,
+    ) -> mcpl_core::methods::IncomingChannelMessage {
+        let text = if is_emote { format!("* {} {}", user, text) } else { text.to_string() };
+        mcpl_core::methods::IncomingChannelMessage {
+            channel_id: chat.id(),
+            message_id: uuid::Uuid::new_v4().to_string(),
+            thread_id: None,
+            author: MessageAuthor {
+                id: format!("user:{}", user),
+                name: user.to_string(),
+            },
+            content: vec![ContentBlock::text(text)],
+            timestamp: if time.is_empty() { chrono::Utc::now().to_rfc3339() } else { time.to_string() },
+            metadata: None,
+        }
+    }
+
+    /// channels/publish on a lobby chat channel: a Say to the room or user.
+    async fn publish_lobby_chat(&mut self, chat: ChatChannel, text: &str) -> serde_json::Value {
+        if let ChatChannel::Room(room) = &chat {
+            if !self.lobby_state.channels.contains_key(room) {
+                return serde_json::json!({
+                    "delivered": false,
+                    "error": format!("Not in #{}; join it with lobby_join_channel", room)
+                });
+            }
+        }
+        if let ChatChannel::Battle(battle_id) = &chat {
+            if self.lobby_state.my_battle != Some(*battle_id) {
+                return serde_json::json!({
+                    "delivered": false,
+                    "error": format!("Not in battle {}; join it with lobby_join_battle", battle_id)
+                });
+            }
+        }
+        let Some(conn) = &mut self.lobby_conn else {
+            return serde_json::json!({
+                "delivered": false,
+                "error": "Not connected to lobby"
+            });
+        };
+        if let Err(e) = conn.send_command("Say", &chat.say(text)).await {
+            return serde_json::json!({
+                "delivered": false,
+                "error": format!("Send failed: {}", e)
+            });
+        }
+        // Writing to someone starts the conversation.
+        self.open_lobby_chat(chat).await;
+        serde_json::json!({
+            "delivered": true,
+            "messageId": uuid::Uuid::new_v4().to_string()
+        })
+    }
+
+    /// channels/close on a lobby chat channel: leave the room, or stop
+    /// showing the DM conversation until the next message.
+    async fn close_lobby_chat(&mut self, chat: ChatChannel) -> serde_json::Value {
+        if !self.lobby_chats.contains(&chat) {
+            return serde_json::json!({
+                "closed": false,
+                "error": format!("Channel {} is not open", chat.id())
+            });
+        }
+        let left = match &chat {
+            ChatChannel::Room(room) => Some(self.tool_lobby_leave_channel(&serde_json::json!({ "channel": room })).await),
+            ChatChannel::Battle(_) => Some(self.tool_lobby_leave_battle().await),
+            ChatChannel::Direct(_) => None,
+        };
+        if let Some(result) = left {
+            if result.get("isError").is_some() {
+                return serde_json::json!({
+                    "closed": false,
+                    "error": result["content"][0]["text"]
+                });
+            }
+        }
+        self.forget_lobby_chats(|c| *c == chat).await;
+        serde_json::json!({ "closed": true })
+    }
+
+    /// Handle a lobby message that arrived outside any tool call.
+    async fn handle_lobby_message(&mut self, msg: &LobbyMessage) {
+        if msg.command == "Ping" {
+            if let Some(conn) = &mut self.lobby_conn {
+                let pong = LobbyMessage::new("Ping", serde_json::json!({}));
+                if let Err(e) = conn.send(&pong).await {
+                    tracing::error!("Failed to send ping response: {}", e);
+                }
+            }
+            return;
+        }
+
+        tracing::info!("Lobby msg: {} {}", msg.command, msg.data);
+        let events = self.lobby_state.handle_message(msg);
+        for event in &events {
+            // Handle ConnectSpring by launching the engine
+            if let LobbyEvent::ConnectSpring(data) = event {
+                tracing::info!("Background loop received ConnectSpring — launching engine");
+                self.handle_connect_spring(data).await;
+            }
+            self.auto_join_battle(event).await;
+            if self.route_lobby_chat(event).await || self.route_battle_room(event).await {
+                continue;
+            }
+            if let Err(e) = self.push_lobby_event(event).await {
+                tracing::error!("Failed to push lobby event: {}", e);
+            }
+        }
+    }
+
+    /// Drop a lobby connection that failed or was closed by the server,
+    /// and start reconnecting. Tools report "not connected" (or queue,
+    /// see `lobby_reconnect`) until the connection is back.
+    async fn handle_lobby_closed(&mut self, e: &LobbyError) {
+        tracing::error!("Lobby connection error: {}", e);
+        self.lobby_conn = None;
+        self.lobby_state.connected = false;
+        self.lobby_state.logged_in = false;
+        self.forget_lobby_chats(|_| true).await;
+        let event = LobbyEvent::Disconnected { reason: e.to_string() };
+        let _ = self.push_lobby_event(&event).await;
+        if let Some(secs) = self.lobby_session.as_mut().and_then(|s| s.lost(std::time::Instant::now())) {
+            let text = format!(
+                "Reconnecting to the lobby in {}s. Until then {} calls are queued; other lobby tools fail.",
+                secs,
+                lobby_reconnect::QUEUEABLE_TOOLS.join(", ")
+            );
+            let _ = self.send_lobby_push("lobby.reconnecting", text, false).await;
+        }
+    }
+
+    /// Drop queued lobby calls that waited too long, retry a lost lobby
+    /// connection when due, and run the queued calls once logged in again.
+    async fn poll_lobby_reconnect(&mut self, now: std::time::Instant) {
+        let Some(session) = &mut self.lobby_session else { return };
+        let max_age = session.max_age_secs();
+        for call in session.expire(now) {
+            let text = format!("Queued {} #{} dropped: no lobby connection for {}s", call.tool, call.id, max_age);
+            let _ = self.send_lobby_push("lobby.queue_expired", text, false).await;
+        }
+        if self.lobby_conn.is_none() && self.lobby_session.as_ref().is_some_and(|s| s.attempt_due(now)) {
+            self.retry_lobby_connection(now).await;
+        }
+
+        let Some(session) = &mut self.lobby_session else { return };
+        if self.lobby_conn.is_none() || (session.login.is_some() && !self.lobby_state.logged_in) {
+            return;
+        }
+        for call in session.take_queue() {
+            let result = self.handle_tool_call(&call.tool, &call.args).await;
+            let outcome = if result.get("isError") == Some(&serde_json::json!(true)) { "failed" } else { "sent" };
+            let text = format!(
+                "Queued {} #{} {}: {}",
+                call.tool,
+                call.id,
+                outcome,
+                result["content"][0]["text"].as_str().unwrap_or_default()
+            );
+            let _ = self.send_lobby_push("lobby.queue_sent", text, false).await;
+        }
+    }
+
+    /// One reconnect attempt, logging in again with the last login.
+    async fn retry_lobby_connection(&mut self, now: std::time::Instant) {
+        let Some(session) = &mut self.lobby_session else { return };
+        let (host, port, login) = (session.host.clone(), session.port, session.login.clone());
+        let attempt = tokio::time::timeout(std::time::Duration::from_secs(10), LobbyConnection::connect(&host, port));
+        let error = match attempt.await {
+            Ok(Ok(conn)) => {
+                self.lobby_conn = Some(conn);
+                session.connected();
+                let text = match login {
+                    Some((username, password_hash)) => {
+                        let result = self.lobby_login(username, password_hash).await;
+                        format!("Reconnected to the lobby. {}", result["content"][0]["text"].as_str().unwrap_or_default())
+                    }
+                    None => "Reconnected to the lobby".to_string(),
+                };
+                let _ = self.send_lobby_push("lobby.reconnected", text, false).await;
+                return;
+            }
+            Ok(Err(e)) => e.to_string(),
+            Err(_) => "timed out".to_string(),
+        };
+        tracing::warn!("Lobby reconnect to {}:{} failed: {}", host, port, error);
+        if !session.attempt_failed(now) {
+            let dropped = session.take_queue().len();
+            let text = format!(
+                "Gave up reconnecting to the lobby ({}); dropped {} queued calls. Call lobby_connect to try again.",
+                error, dropped
+            );
+            let _ = self.send_lobby_push("lobby.reconnect_failed", text, false).await;
+        }
+    }
+
+    /// Convert a lobby event to an MCPL push event and send it.
+    async fn push_lobby_event(
+        &mut self,
+        event: &LobbyEvent,
+    ) -> Result<(), String> {
+        let (event_id, content_text) = match event {
+            LobbyEvent::Connected { engine, game } => (
+                "lobby.connected".to_string(),
+                format!("Connected to lobby. Engine: {}, Game: {}", engine, game),
+            ),
+            LobbyEvent::Disconnected { reason } => (
+                "lobby.disconnected".to_string(),
+                format!("Disconnected: {}", reason),
+            ),
+            LobbyEvent::LoggedIn { username } => (
+                "lobby.logged_in".to_string(),
+                format!("Logged in as {}", username),
+            ),
+            LobbyEvent::LoginFailed(resp) => (
+                "lobby.login_failed".to_string(),
+                login_guard::LoginFailure::from_response(resp).text(),
+            ),
+            LobbyEvent::RegisterSuccess => (
+                "lobby.register_success".to_string(),
+                "Account registration successful".to_string(),
+            ),
+            LobbyEvent::RegisterFailed { code, reason } => (
+                "lobby.register_failed".to_string(),
+                format!("Registration failed (code {}): {}", code, reason),
+            ),
+            LobbyEvent::ChatMessage {
+                user,
+                text,
+                target,
+                place,
+                ..
+            } => {
+                // Rooms and DMs have channels (route_lobby_chat); of the
+                // rest only battle chat is worth an event.
+                match *place {
+                    PLACE_BATTLE => (
+                        "lobby.chat".to_string(),
+                        format!("[battle] {}: {}", user, text),
+                    ),
+                    _ => return Ok(()), // skip unjoined rooms, server messages, etc.
+                }
+            }
+            LobbyEvent::BattleJoined { battle_id, player_count, bot_count } => (
+                "lobby.battle_joined".to_string(),
+                format!("Joined battle {} ({} players, {} bots)", battle_id, player_count, bot_count),
+            ),
+            LobbyEvent::ChannelJoined {
+                channel,
+                users,
+                topic,
+            } => (
+                "lobby.channel_joined".to_string(),
+                format!(
+                    "Joined #{} ({} users). Topic: {}",
+                    channel,
+                    users.len(),
+                    topic.as_deref().unwrap_or("(none)")
+                ),
+            ),
+            LobbyEvent::MatchMakerReady {
+                seconds_remaining,
+                quick_play,
+            } => (
+                "lobby.matchmaker_ready".to_string(),
+                format!(
+                    "MATCH FOUND! Accept within {}s (until {}, quickplay: {}). Use lobby_matchmaker_accept to respond.",
+                    seconds_remaining,
+                    self.lobby_state
+                        .matchmaker_ready_deadline
+                        .map(|t| t.format("%H:%M:%S UTC").to_string())
+                        .unwrap_or_default(),
+                    quick_play
+                ),
+            ),
+            LobbyEvent::MatchMakerReadyUpdate(update) => (
+                "lobby.matchmaker_ready_update".to_string(),
+                format!(
+                    "Ready-check: {}/{} players ready, {}{}",
+                    update.your_battle_ready.map_or("?".into(), |n| n.to_string()),
+                    update.your_battle_size.map_or("?".into(), |n| n.to_string()),
+                    if update.ready_accepted { "we accepted" } else { "waiting for our answer" },
+                    if update.likely_to_play { "" } else { "; the match is unlikely to start" }
+                ),
+            ),
+            LobbyEvent::MatchMakerStatus(status) if status.banned_seconds.is_some_and(|s| s > 0) => (
+                "lobby.matchmaker_banned".to_string(),
+                format!(
+                    "Matchmaker ban: can't queue for another {}s",
+                    status.banned_seconds.unwrap_or_default()
+                ),
+            ),
+            LobbyEvent::MatchMakerResult {
+                is_battle_starting,
+                are_you_banned,
+            } => (
+                "lobby.matchmaker_result".to_string(),
+                if *is_battle_starting {
+                    "Match starting! ConnectSpring will follow.".to_string()
+                } else if *are_you_banned {
+                    "Match cancelled. You have been temporarily banned for not accepting.".to_string()
+                } else {
+                    "Match cancelled. Not enough players accepted.".to_string()
+                },
+            ),
+            LobbyEvent::ConnectSpring(_) => (
+                "lobby.connect_spring".to_string(),
+                "Game starting — engine launch initiated".to_string(),
+            ),
+            // Skip high-frequency events that would flood the agent's context
+            LobbyEvent::UserJoined(_)
+            | LobbyEvent::UserLeft { .. }
+            | LobbyEvent::BattleUpdated(_)
+            | LobbyEvent::BattleOpened(_)
+            | LobbyEvent::BattleClosed { .. }
+            | LobbyEvent::BattleRoom { .. }
+            | LobbyEvent::ChannelUserJoined { .. }
+            | LobbyEvent::ChannelUserLeft { .. }
+            | LobbyEvent::MatchMakerStatus(_)
+            | LobbyEvent::MatchMakerSetup { .. } => {
+                return Ok(());
+            }
+        };
+
+        // A ready-check left unanswered gets us banned from queueing.
+        let urgent = matches!(event, LobbyEvent::MatchMakerReady { .. });
+        self.send_lobby_push(&event_id, content_text, urgent).await
+    }
+
+    /// Tell the client what this GameManager can do: the `gm.ready`
+    /// banner, sent after the handshake.
+    pub fn announce_ready(&self, config: &config::GmConfig, checks: &doctor::Checks) {
+        let banner = ready::banner(config, checks, self.engines.max_concurrent_games);
+        self.push_gm_event(ready::EVENT, banner);
+    }
+
+    /// A push event about the GameManager itself, its JSON as the text.
+    fn push_gm_event(&self, event_id: &str, json: serde_json::Value) {
+        let Some(mcpl) = &self.mcpl else { return };
+        let params = PushEventParams {
+            feature_set: "game".into(),
+            event_id: event_id.into(),
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            origin: Some(serde_json::json!({"source": "zk-gm", "schema": ready::SCHEMA})),
+            payload: PushEventPayload { content: vec![ContentBlock::text(json.to_string())] },
+        };
+        if let Err(e) = mcpl.request(method::PUSH_EVENT, Some(serde_json::to_value(&params).unwrap())) {
+            tracing::warn!("Failed to queue {}: {}", event_id, e);
+        }
+    }
+
+    async fn send_lobby_push(
+        &mut self,
+        event_id: &str,
+        content_text: String,
+        urgent: bool,
+    ) -> Result<(), String> {
+        let mcpl = match &self.mcpl {
+            Some(c) => c,
+            None => return Ok(()),
+        };
+        let params = PushEventParams {
+            feature_set: "lobby".into(),
+            event_id: format!("{}_{}", event_id, uuid::Uuid::new_v4()),
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            origin: Some(if urgent {
+                serde_json::json!({"source": "zk-lobby", "priority": "high"})
+            } else {
+                serde_json::json!({"source": "zk-lobby"})
+            }),
+            payload: PushEventPayload {
+                content: vec![ContentBlock::text(content_text)],
+            },
+        };
+
+        mcpl.request(method::PUSH_EVENT, Some(serde_json::to_value(&params).unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lobby::fake_server::{FakeLobbyServer, BANNED_USER, OPEN_BATTLE_ID, RESTRICTED_CHANNEL, UNKNOWN_USER};
+    use crate::write_dir;
+    use crate::mcpl_test_client::TestClient;
+    use sai_protocol::{UnitDefId, WeaponDefId};
+
+    fn test_gm() -> GameManager {
+        let dir = std::env::temp_dir().join(format!("gm-test-{}", uuid::Uuid::new_v4()));
+        let config = WriteDirConfig {
+            write_dir: dir.join("write"),
+            spring_home: dir.join("home"),
+            sai_bridge_lib: dir.join("libSkirmishAI.so"),
+            sai_bridge_data: dir.join("AgentBridge"),
+            widget_source: dir.join("widgets"),
+            agent_name: "agent".into(),
+        };
+        GameManager::new(&config, dir.join("engine"), socket_dir::SocketDir::create(&std::env::temp_dir()).unwrap())
+    }
+
+    /// A bridge socket path in the temp dir, removed when the test is done.
+    struct SocketGuard(String);
+
+    impl SocketGuard {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("gm-{}-{}.sock", name, uuid::Uuid::new_v4()));
+            Self(path.to_str().unwrap().to_string())
+        }
+    }
+
+    impl std::ops::Deref for SocketGuard {
+        type Target = String;
+
+        fn deref(&self) -> &String {
+            &self.0
+        }
+    }
+
+    impl Drop for SocketGuard {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    /// Connect a test bridge to `gm` as game:local-1's SAI.
+    fn connect_bridge(gm: &mut GameManager, name: &str) -> (sai_protocol::IpcClient, SocketGuard) {
+        let socket = SocketGuard::new(name);
+        gm.sai.listen_for("game:local-1", &socket).unwrap();
+        let bridge = sai_protocol::IpcClient::connect(&socket).unwrap();
+        gm.sai.accept_pending();
+        (bridge, socket)
+    }
+
+    /// A test GameManager with a bridge connected on game:local-1.
+    fn connected_gm(name: &str) -> (GameManager, sai_protocol::IpcClient, SocketGuard) {
+        let mut gm = test_gm();
+        let (bridge, socket) = connect_bridge(&mut gm, name);
+        (gm, bridge, socket)
+    }
+
+    fn text(result: &serde_json::Value) -> &str {
+        result["content"][0]["text"].as_str().unwrap_or_default()
+    }
+
+    fn is_error(result: &serde_json::Value) -> bool {
+        result.get("isError").and_then(|v| v.as_bool()).unwrap_or(false)
+    }
+
+    async fn connect(gm: &mut GameManager, server: &FakeLobbyServer) {
+        let result = gm
+            .handle_tool_call(
+                "lobby_connect",
+                &serde_json::json!({"host": "127.0.0.1", "port": server.port}),
+            )
+            .await;
+        assert!(!is_error(&result), "{}", result);
+    }
+
+    async fn login(gm: &mut GameManager, password: &str) -> serde_json::Value {
+        gm.handle_tool_call(
+            "lobby_login",
+            &serde_json::json!({"username": "agent", "password": password}),
+        )
+        .await
+    }
+
+    /// Make a battle's map, game and engine (empty ones skipped) look
+    /// installed, the way `content::check` finds them.
+    fn install_content(gm: &GameManager, map: &str, game: &str, engine: &str) {
+        let maps = gm.spring_home.join("maps");
+        std::fs::create_dir_all(&maps).unwrap();
+        if !map.is_empty() {
+            std::fs::write(maps.join(format!("{}.sd7", map)), "").unwrap();
+        }
+        let cache = gm.write_dir.join("cache");
+        std::fs::create_dir_all(&cache).unwrap();
+        std::fs::write(cache.join("ArchiveCache20.lua"), format!("\t\t\tname = \"{}\",\n", game)).unwrap();
+        if !engine.is_empty() {
+            std::fs::create_dir_all(gm.spring_home.join("engine/linux64").join(engine)).unwrap();
+        }
+    }
+
+    fn install_open_battle_content(gm: &GameManager) {
+        install_content(gm, "Comet Catcher Redux v3.1", "Zero-K v1.12.7.0", "105.1.1-2511-g747f18b");
+    }
+
+    #[tokio::test]
+    async fn test_lobby_login_channel_and_say() {
+        let server = FakeLobbyServer::start("hunter2").await;
+        let mut gm = test_gm();
+        connect(&mut gm, &server).await;
+
+        let result = login(&mut gm, "hunter2").await;
+        assert_eq!(text(&result), "Logged in as 'agent'");
+        assert!(gm.lobby_state.connected, "Welcome should be processed while waiting");
+        assert_eq!(gm.lobby_state.server_game, "zk:stable");
+        let sent = server.wait_for("Login").await;
+        assert_eq!(sent.data["PasswordHash"], hash_password("hunter2"));
+
+        let result = gm
+            .handle_tool_call("lobby_join_channel", &serde_json::json!({"channel": "zk"}))
+            .await;
+        assert_eq!(
+            text(&result),
+            "Joined #zk (3 users). Topic: Welcome to Zero-K! Ask questions here."
+        );
+        // Messages queued behind LoginResponse were applied on the way.
+        assert_eq!(gm.lobby_state.channels["zk"].users.len(), 3);
+        assert!(gm.lobby_state.users.contains_key("Godde"));
+        assert_eq!(gm.lobby_state.battles.len(), 2);
+
+        let result = gm
+            .handle_tool_call(
+                "lobby_say",
+                &serde_json::json!({"target": "zk", "text": "gl hf"}),
+            )
+            .await;
+        assert_eq!(text(&result), "Sent to zk: gl hf");
+        let say = server.wait_for("Say").await;
+        assert_eq!(say.data["Target"], "zk");
+        assert_eq!(say.data["Text"], "gl hf");
+        // The echo comes back as a chat event.
+        let echo = gm.lobby_conn.as_mut().unwrap().recv().await.unwrap();
+        let events = gm.lobby_state.handle_message(&echo);
+        assert!(matches!(
+            &events[..],
+            [LobbyEvent::ChatMessage { user, text, .. }] if user == "agent" && text == "gl hf"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_lobby_login_rejected() {
+        let server = FakeLobbyServer::start("hunter2").await;
+        let mut gm = test_gm();
+        connect(&mut gm, &server).await;
+
+        let result = login(&mut gm, "wrong").await;
+        assert!(is_error(&result));
+        assert_eq!(
+            text(&result),
+            "Login failed (code 2): Invalid password. Check the password; retrying the same one won't help \
+             (next attempt allowed in 15s)"
+        );
+        assert_eq!(result["structuredContent"]["reason"], "invalid_password");
+        assert_eq!(result["structuredContent"]["retryable"], false);
+        assert!(!gm.lobby_state.logged_in);
+
+        // Trying again straight away stays here, even with the right password.
+        let result = login(&mut gm, "hunter2").await;
+        assert_eq!(result["structuredContent"]["reason"], "cooling_down");
+        assert!(result["structuredContent"]["retryAfter"].as_u64().unwrap() <= 15);
+        assert_eq!(server.received().iter().filter(|m| m.command == "Login").count(), 1);
+
+        // Once it's over the login goes through, and clears the account.
+        let now = std::time::Instant::now();
+        assert!(gm.login_guard.cooling_down("agent", now + login_guard::BASE_COOLDOWN).is_none());
+        gm.login_guard.succeeded("agent");
+        assert_eq!(text(&login(&mut gm, "hunter2").await), "Logged in as 'agent'");
+        let result = login(&mut gm, "hunter2").await;
+        assert_eq!(result["structuredContent"]["reason"], "already_logged_in");
+        assert_eq!(server.received().iter().filter(|m| m.command == "Login").count(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_lobby_login_banned_and_unknown() {
+        let server = FakeLobbyServer::start("hunter2").await;
+        let mut gm = test_gm();
+        connect(&mut gm, &server).await;
+        let login_as = |name: &str| serde_json::json!({"username": name, "password": "hunter2"});
+
+        let result = gm.handle_tool_call("lobby_login", &login_as(BANNED_USER)).await;
+        let failure = &result["structuredContent"];
+        assert_eq!((failure["code"].as_i64(), failure["reason"].as_str()), (Some(4), Some("banned")));
+        assert_eq!(failure["banReason"], "Repeated teamkilling — appeal on the forum (until 2026-11-01)");
+        assert_eq!(failure["retryable"], false);
+        assert!(text(&result).contains("Ban reason: Repeated teamkilling — appeal on the forum (until 2026-11-01). Do not retry"));
+
+        // Each account cools down on its own.
+        let result = gm.handle_tool_call("lobby_login", &login_as(UNKNOWN_USER)).await;
+        assert_eq!(result["structuredContent"]["reason"], "invalid_name");
+        assert!(text(&result).contains("lobby_register"), "{}", text(&result));
+        assert_eq!(server.received().iter().filter(|m| m.command == "Login").count(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_lobby_login_stored() {
+        let server = FakeLobbyServer::start("hunter2").await;
+        let mut gm = test_gm();
+        connect(&mut gm, &server).await;
+        let stored = |account: &str| serde_json::json!({"account": account});
+        let result = gm.handle_tool_call("lobby_login_stored", &serde_json::json!({})).await;
+        assert_eq!(text(&result), "No stored credentials (config credentials); use lobby_login");
+
+        let password_file = gm.write_dir.join("main.pw");
+        std::fs::create_dir_all(&gm.write_dir).unwrap();
+        std::fs::write(&password_file, "hunter2\n").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&password_file, std::fs::Permissions::from_mode(0o600)).unwrap();
+        }
+        gm.credentials = serde_json::from_value(serde_json::json!({
+            "main": {"username": "agent", "password_file": password_file},
+            "env": {"username": "agent", "password_env": "GM_TEST_LOBBY_PASSWORD_UNSET"}
+        }))
+        .unwrap();
+
+        let result = gm.handle_tool_call("lobby_login_stored", &stored("env")).await;
+        assert_eq!(text(&result), "Environment variable GM_TEST_LOBBY_PASSWORD_UNSET for account 'env' is not set");
+        let result = gm.handle_tool_call("lobby_login_stored", &serde_json::json!({})).await;
+        assert_eq!(text(&result), "No stored account 'default' (stored: env, main)");
+        assert!(!gm.lobby_state.logged_in);
+
+        let result = gm.handle_tool_call("lobby_login_stored", &stored("main")).await;
+        assert_eq!(text(&result), "Logged in as 'agent'");
+        assert!(!result.to_string().contains("hunter2"));
+        let sent = server.wait_for("Login").await;
+        assert_eq!(sent.data["PasswordHash"], hash_password("hunter2"));
+        assert!(!sent.to_log().contains(&hash_password("hunter2")), "the hash isn't logged");
+    }
+
+    #[tokio::test]
+    async fn test_lobby_join_channel_rejected() {
+        let server = FakeLobbyServer::start("hunter2").await;
+        let mut gm = test_gm();
+        connect(&mut gm, &server).await;
+        login(&mut gm, "hunter2").await;
+
+        let result = gm
+            .handle_tool_call(
+                "lobby_join_channel",
+                &serde_json::json!({"channel": RESTRICTED_CHANNEL}),
+            )
+            .await;
+        assert!(is_error(&result));
+        assert_eq!(text(&result), "Failed to join #zkadmin: rejected by server");
+        assert!(!gm.lobby_state.channels.contains_key(RESTRICTED_CHANNEL));
+    }
+
+    #[tokio::test]
+    async fn test_lobby_join_battle_reports_sync() {
+        let server = FakeLobbyServer::start("hunter2").await;
+        let mut gm = test_gm();
+        install_open_battle_content(&gm);
+        connect(&mut gm, &server).await;
+        login(&mut gm, "hunter2").await;
+
+        let result = gm
+            .handle_tool_call(
+                "lobby_join_battle",
+                &serde_json::json!({"battle_id": OPEN_BATTLE_ID}),
+            )
+            .await;
+        assert_eq!(text(&result), "Joined battle 38219 (2 players, 1 bots)");
+        assert_eq!(gm.lobby_state.my_battle, Some(OPEN_BATTLE_ID));
+
+        let status = server.wait_for("UpdateUserBattleStatus").await;
+        assert_eq!(status.data["Name"], "agent");
+        assert_eq!(status.data["Sync"], "Synced");
+    }
+
+    /// Point a test GameManager at a fake pr-downloader running `body`.
+    fn fake_downloader(gm: &mut GameManager, body: &str) {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::create_dir_all(&gm.spring_home).unwrap();
+        let bin = gm.spring_home.join("pr-downloader");
+        std::fs::write(&bin, format!("#!/bin/sh\n{}\n", body)).unwrap();
+        std::fs::set_permissions(&bin, std::fs::Permissions::from_mode(0o755)).unwrap();
+        gm.downloads.binary = bin;
+    }
+
+    /// Poll downloads until nothing is awaited any more.
+    async fn finish_downloads(gm: &mut GameManager) {
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        while gm.content_wait.is_some() {
+            assert!(std::time::Instant::now() < deadline, "downloads never finished");
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            gm.poll_downloads().await;
+        }
+    }
+
+    async fn sync_statuses(server: &FakeLobbyServer, count: usize) -> Vec<String> {
+        for _ in 0..250 {
+            let statuses: Vec<String> = server
+                .received()
+                .into_iter()
+                .filter(|m| m.command == "UpdateUserBattleStatus")
+                .map(|m| m.data["Sync"].as_str().unwrap_or_default().to_string())
+                .collect();
+            if statuses.len() >= count {
+                return statuses;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        panic!("fake lobby never received {} battle statuses", count);
+    }
+
+    #[tokio::test]
+    async fn test_lobby_join_battle_downloads_content() {
+        let server = FakeLobbyServer::start("hunter2").await;
+        let mut gm = test_gm();
+        install_content(&gm, "Comet Catcher Redux v3.1", "", "105.1.1-2511-g747f18b");
+        fake_downloader(&mut gm, "echo '[Progress]  50% [=====     ] 5/10'\nexit 0");
+        connect(&mut gm, &server).await;
+        login(&mut gm, "hunter2").await;
+
+        let result = gm
+            .handle_tool_call("lobby_join_battle", &serde_json::json!({"battle_id": OPEN_BATTLE_ID}))
+            .await;
+        assert_eq!(
+            text(&result),
+            "Joined battle 38219 (2 players, 1 bots). Unsynced, missing game Zero-K v1.12.7.0 (downloading)"
+        );
+        finish_downloads(&mut gm).await;
+        assert_eq!(sync_statuses(&server, 2).await, ["Unsynced", "Synced"]);
+
+        // pr-downloader can't fetch engines, so nothing waits and we stay unsynced.
+        gm.handle_tool_call("lobby_leave_battle", &serde_json::json!({})).await;
+        std::fs::remove_dir_all(gm.spring_home.join("engine")).unwrap();
+        fake_downloader(&mut gm, "exit 1");
+        let result = gm
+            .handle_tool_call("lobby_join_battle", &serde_json::json!({"battle_id": OPEN_BATTLE_ID}))
+            .await;
+        assert_eq!(
+            text(&result),
+            "Joined battle 38219 (2 players, 1 bots). Unsynced, missing game Zero-K v1.12.7.0 (downloading), \
+             engine 105.1.1-2511-g747f18b (not installed)"
+        );
+        assert!(gm.content_wait.is_none());
+        assert_eq!(sync_statuses(&server, 3).await[2], "Unsynced");
+    }
+
+    #[tokio::test]
+    async fn test_lobby_status() {
+        let mut gm = test_gm();
+        let result = gm.handle_tool_call("lobby_status", &serde_json::json!({})).await;
+        let status: serde_json::Value = serde_json::from_str(text(&result)).unwrap();
+        assert_eq!(status["connected"], false);
+        assert!(status["lastReceived"].is_null() && status["battle"].is_null());
+
+        let server = FakeLobbyServer::start("hunter2").await;
+        connect(&mut gm, &server).await;
+        login(&mut gm, "hunter2").await;
+        gm.handle_tool_call("lobby_join_channel", &serde_json::json!({"channel": "zk"})).await;
+        gm.handle_tool_call("lobby_join_battle", &serde_json::json!({"battle_id": OPEN_BATTLE_ID})).await;
+
+        let result = gm.handle_tool_call("lobby_status", &serde_json::json!({})).await;
+        let status: serde_json::Value = serde_json::from_str(text(&result)).unwrap();
+        assert_eq!(status["loggedIn"], true);
+        assert_eq!(status["username"], "agent");
+        assert_eq!(status["server"]["game"], "zk:stable");
+        assert_eq!(status["channels"], serde_json::json!([{"name": "zk", "users": 3}]));
+        assert_eq!(status["battle"]["id"], OPEN_BATTLE_ID);
+        assert!(status["battle"]["players"].is_number());
+        assert_eq!(status["matchmaker"]["joinedQueues"], serde_json::json!([]));
+        assert!(status["secondsSinceLastReceived"].as_i64().unwrap() <= 1);
+    }
+
+    #[tokio::test]
+    async fn test_lobby_user_info() {
+        let server = FakeLobbyServer::start("hunter2").await;
+        let mut gm = test_gm();
+        connect(&mut gm, &server).await;
+        login(&mut gm, "hunter2").await;
+        // The user flood after login is applied while waiting for the join.
+        gm.handle_tool_call("lobby_join_channel", &serde_json::json!({"channel": "zk"})).await;
+
+        let result = gm.handle_tool_call("lobby_user_info", &serde_json::json!({"name": "Godde"})).await;
+        let info: serde_json::Value = serde_json::from_str(text(&result)).unwrap();
+        assert_eq!(info["level"], 127);
+        assert_eq!(info["country"], "DE");
+        assert_eq!(info["battle"], serde_json::json!({"id": OPEN_BATTLE_ID, "title": "Teams All Welcome"}));
+
+        let result = gm.handle_tool_call("lobby_user_info", &serde_json::json!({"name": "nightwatch"})).await;
+        assert!(text(&result).starts_with("No user named 'nightwatch'; closest match is Nightwatch\n"));
+        assert!(text(&result).contains("\"isBot\": true"));
+
+        gm.lobby_state.users.insert(
+            "Nightfall".into(),
+            lobby::state::UserInfo { name: "Nightfall".into(), ..gm.lobby_state.users["Godde"].clone() },
+        );
+        let result = gm.handle_tool_call("lobby_user_info", &serde_json::json!({"name": "night"})).await;
+        assert_eq!(text(&result), "No user named 'night'; candidates: Nightfall, Nightwatch");
+
+        let result = gm.handle_tool_call("lobby_user_info", &serde_json::json!({"name": "Sprung"})).await;
+        assert!(result.get("isError").is_none());
+        assert!(text(&result).starts_with("User 'Sprung' is not known locally (4 users seen)"));
+    }
+
+    #[tokio::test]
+    async fn test_lobby_chat_channels() {
+        let server = FakeLobbyServer::start("hunter2").await;
+        let mut gm = test_gm();
+        connect(&mut gm, &server).await;
+        login(&mut gm, "hunter2").await;
+        gm.handle_tool_call("lobby_join_channel", &serde_json::json!({"channel": "zk"})).await;
+        let room = ChatChannel::Room("zk".into());
+        assert!(gm.lobby_chats.contains(&room));
+        let listed = gm.handle_channels_list().await;
+        let chat = &listed["channels"][0];
+        assert_eq!((chat["id"].as_str(), chat["type"].as_str()), (Some("lobby:#zk"), Some("lobby")));
+        assert_eq!(chat["metadata"]["users"], 3);
+
+        let said = |user: &str, target: &str, place: i32| LobbyEvent::ChatMessage {
+            user: user.into(),
+            text: "o/".into(),
+            target: target.into(),
+            place,
+            is_emote: false,
+            time: "2026-10-16T12:00:00Z".into(),
+        };
+        assert!(gm.route_lobby_chat(&said("Godde", "zk", PLACE_CHANNEL)).await);
+        assert!(!gm.route_lobby_chat(&said("Godde", "newbies", PLACE_CHANNEL)).await);
+        assert!(!gm.route_lobby_chat(&said("Godde", "", PLACE_BATTLE)).await);
+        // A DM opens a conversation channel with the sender.
+        assert!(gm.route_lobby_chat(&said("Godde", "agent", PLACE_USER)).await);
+        let dm = ChatChannel::Direct("Godde".into());
+        assert!(gm.lobby_chats.contains(&dm));
+        let msg = gm.lobby_chat_message(&dm, "Godde", "o/", false, "2026-10-16T12:00:00Z");
+        assert_eq!((msg.channel_id.as_str(), msg.author.name.as_str()), ("lobby:@Godde", "Godde"));
+
+        let publish = |channel: &str| {
+            serde_json::json!({"channelId": channel, "content": [{"type": "text", "text": "hello"}]})
+        };
+        let result = gm.handle_channels_publish(&publish("lobby:@Godde")).await;
+        assert_eq!(result["delivered"], true);
+        let say = server.wait_for("Say").await;
+        assert_eq!((say.data["Target"].as_str(), say.data["Place"].as_i64()), (Some("Godde"), Some(4)));
+        let result = gm.handle_channels_publish(&publish("lobby:#newbies")).await;
+        assert_eq!(result["error"], "Not in #newbies; join it with lobby_join_channel");
+
+        let result = gm.handle_channels_close(&serde_json::json!({"channelId": "lobby:#zk"})).await;
+        assert_eq!(result["closed"], true);
+        assert_eq!(server.wait_for("LeaveChannel").await.data["ChannelName"], "zk");
+        assert!(!gm.lobby_chats.contains(&room) && !gm.lobby_state.channels.contains_key("zk"));
+
+        gm.handle_tool_call("lobby_disconnect", &serde_json::json!({})).await;
+        assert!(gm.lobby_chats.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_battle_room_channel() {
+        let server = FakeLobbyServer::start("hunter2").await;
+        let mut gm = test_gm();
+        let (loopback, mut client) = self_test::LoopbackClient::new();
+        gm.mcpl = Some(mcpl_link::McplLink::spawn(loopback, &Default::default()));
+        fake_engine(&gm, "sleep 30");
+        let engine_dir = gm.engines.engine_dir.clone();
+        std::fs::copy(engine_dir.join("spring-headless"), engine_dir.join("spring")).unwrap();
+        install_content(&gm, "Comet Catcher Redux v3.1", "Zero-K v1.12.7.0", "");
+        connect(&mut gm, &server).await;
+        login(&mut gm, "hunter2").await;
+        gm.handle_tool_call("lobby_join_channel", &serde_json::json!({"channel": "zk"})).await;
+        let me = gm.lobby_state.my_username.clone().unwrap();
+
+        // Joining opens the battle's channel, roster included.
+        gm.handle_tool_call("lobby_join_battle", &serde_json::json!({"battle_id": OPEN_BATTLE_ID})).await;
+        let changed = loop {
+            let changed = next_mcpl(&mut client, "channels/changed").await;
+            if changed["params"]["added"][0]["id"] == "lobby:battle:38219" {
+                break changed;
+            }
+        };
+        let metadata = &changed["params"]["added"][0]["metadata"];
+        assert_eq!(metadata["map"], "Comet Catcher Redux v3.1");
+        assert_eq!(metadata["roster"]["players"].as_array().unwrap().len(), 2);
+        assert_eq!(metadata["roster"]["bots"][0]["name"], "Bot1");
+
+        // Battle chat arrives on it, and publishing says it in the battle.
+        server
+            .send(LobbyMessage::new("Say", serde_json::json!({"Place": 1, "Target": "", "User": "Godde", "Text": "gl hf"})))
+            .await;
+        pump_until(&mut gm, "Say").await;
+        let incoming = next_mcpl(&mut client, "channels/incoming").await;
+        let message = &incoming["params"]["messages"][0];
+        assert_eq!((message["author"]["name"].as_str(), message["content"][0]["text"].as_str()), (Some("Godde"), Some("gl hf")));
+        let publish = serde_json::json!({"channelId": "lobby:battle:38219", "content": [{"type": "text", "text": "hi"}]});
+        assert_eq!(gm.handle_channels_publish(&publish).await["delivered"], true);
+        let say = server.wait_for("Say").await;
+        assert_eq!((say.data["Place"].as_i64(), say.data["Target"].as_str()), (Some(1), Some("")));
+
+        // Roster changes come as notices with the new roster.
+        server.send(LobbyMessage::new("JoinedBattle", serde_json::json!({"BattleID": OPEN_BATTLE_ID, "User": "Sprung"}))).await;
+        pump_until(&mut gm, "JoinedBattle").await;
+        let incoming = next_mcpl(&mut client, "channels/incoming").await;
+        let message = &incoming["params"]["messages"][0];
+        assert_eq!(message["content"][0]["text"], "Sprung joined the battle");
+        assert_eq!(message["metadata"]["battleChange"], serde_json::json!({"change": "player_joined", "name": "Sprung"}));
+        assert_eq!(message["metadata"]["roster"]["players"].as_array().unwrap().len(), 3);
+
+        // The battle starts and hands over to the game channel.
+        let header = |running: bool| {
+            serde_json::json!({"Header": {
+                "BattleID": OPEN_BATTLE_ID, "Founder": "TeamAutohost", "Title": "Teams All Welcome",
+                "Map": "Comet Catcher Redux v3.1", "Game": "Zero-K v1.12.7.0", "IsRunning": running,
+            }})
+        };
+        server.send(LobbyMessage::new("BattleUpdate", header(false))).await;
+        server.send(LobbyMessage::new("BattleUpdate", header(true))).await;
+        pump_until(&mut gm, "BattleUpdate").await;
+        pump_until(&mut gm, "BattleUpdate").await;
+        let incoming = next_mcpl(&mut client, "channels/incoming").await;
+        assert_eq!(incoming["params"]["messages"][0]["content"][0]["text"], "The battle is starting");
+        // The fake engine stands in for the battle's own engine version.
+        let connect_spring = serde_json::json!({
+            "Ip": "127.0.0.1", "Port": 8452, "ScriptPassword": "a1b2c3d4", "Game": "Zero-K v1.12.7.0",
+            "Map": "Comet Catcher Redux v3.1", "Title": "Teams All Welcome", "Mode": 5, "IsSpectator": false,
+        });
+        server.send(LobbyMessage::new("ConnectSpring", connect_spring)).await;
+        pump_until(&mut gm, "ConnectSpring").await;
+        let changed = next_mcpl(&mut client, "channels/changed").await;
+        let game = &changed["params"]["added"][0];
+        let game_id = game["id"].as_str().unwrap().to_string();
+        assert!(game_id.starts_with("game:mp-"));
+        assert_eq!(game["metadata"]["battleChannel"], "lobby:battle:38219");
+        let changed = next_mcpl(&mut client, "channels/changed").await;
+        assert_eq!(changed["params"]["updated"][0]["metadata"]["gameChannel"], game_id.as_str());
+        let incoming = next_mcpl(&mut client, "channels/incoming").await;
+        assert_eq!(incoming["params"]["messages"][0]["metadata"]["gameChannel"], game_id.as_str());
+
+        // Being kicked ends the battle channel.
+        server.send(LobbyMessage::new("LeftBattle", serde_json::json!({"BattleID": OPEN_BATTLE_ID, "User": me}))).await;
+        pump_until(&mut gm, "LeftBattle").await;
+        let incoming = next_mcpl(&mut client, "channels/incoming").await;
+        assert_eq!(incoming["params"]["messages"][0]["content"][0]["text"], "You were removed from the battle");
+        let changed = next_mcpl(&mut client, "channels/changed").await;
+        assert_eq!(changed["params"]["removed"][0], "lobby:battle:38219");
+        assert!(gm.lobby_state.my_battle.is_none());
+        let result = gm.handle_channels_publish(&publish).await;
+        assert_eq!(result["error"], "Not in battle 38219; join it with lobby_join_battle");
+        gm.engines.stop_game(&game_id).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_lobby_join_battle_by() {
+        let server = FakeLobbyServer::start("hunter2").await;
+        let mut gm = test_gm();
+        install_open_battle_content(&gm);
+        connect(&mut gm, &server).await;
+        login(&mut gm, "hunter2").await;
+        gm.handle_tool_call("lobby_join_channel", &serde_json::json!({"channel": "zk"})).await;
+
+        let result = gm.handle_tool_call("lobby_join_battle_by", &serde_json::json!({"title_pattern": "."})).await;
+        assert!(is_error(&result));
+        assert_eq!(
+            text(&result),
+            "2 battles match: 38219 'Teams All Welcome' by TeamAutohost; 38240 'Private' by agent-host"
+        );
+        let result = gm.handle_tool_call("lobby_join_battle_by", &serde_json::json!({"founder": "nobody"})).await;
+        assert_eq!(text(&result), "No open battle matches (2 known)");
+        let result = gm.handle_tool_call("lobby_join_battle_by", &serde_json::json!({"title_pattern": "("})).await;
+        assert!(text(&result).starts_with("Invalid title_pattern"));
+
+        let result = gm
+            .handle_tool_call(
+                "lobby_join_battle_by",
+                &serde_json::json!({"founder": "teamautohost", "title_pattern": "^teams"}),
+            )
+            .await;
+        assert_eq!(text(&result), "Joined battle 38219 (2 players, 1 bots)");
+        assert_eq!(server.wait_for("JoinBattle").await.data["BattleID"], OPEN_BATTLE_ID);
+    }
+
+    #[tokio::test]
+    async fn test_lobby_list_battles_compact() {
+        let server = FakeLobbyServer::start("hunter2").await;
+        let mut gm = test_gm();
+        connect(&mut gm, &server).await;
+        login(&mut gm, "hunter2").await;
+        gm.handle_tool_call("lobby_join_channel", &serde_json::json!({"channel": "zk"})).await;
+
+        let result = gm.handle_tool_call("lobby_list_battles", &serde_json::json!({"sort": "title", "limit": 1})).await;
+        let listing = text(&result);
+        let lines: Vec<&str> = listing.lines().collect();
+        assert_eq!(lines[0], "2 of 2 open battles match, showing the first 1");
+        assert!(lines[1].starts_with("#38240 \"Private\" by agent-host: "), "{}", lines[1]);
+        assert_eq!(lines.len(), 2);
+
+        let result = gm.handle_tool_call("lobby_list_battles", &serde_json::json!({"search": "autohost", "verbose": true})).await;
+        let listed: serde_json::Value = serde_json::from_str(text(&result)).unwrap();
+        assert_eq!((listed["total"].as_u64(), listed["battles"][0]["id"].as_i64()), (Some(1), Some(38219)));
+        let result = gm.handle_tool_call("lobby_list_battles", &serde_json::json!({"sort": "size"})).await;
+        assert!(is_error(&result));
+    }
+
+    #[tokio::test]
+    async fn test_auto_join_battle_by_founder() {
+        let server = FakeLobbyServer::start("hunter2").await;
+        let mut gm = test_gm();
+        install_open_battle_content(&gm);
+        connect(&mut gm, &server).await;
+        login(&mut gm, "hunter2").await;
+        gm.handle_tool_call("lobby_join_channel", &serde_json::json!({"channel": "zk"})).await;
+        gm.auto_join_founders = vec!["TEAMAUTOHOST".into()];
+
+        let private = gm.lobby_state.battles[&38240].clone();
+        gm.auto_join_battle(&LobbyEvent::BattleOpened(private)).await;
+        assert_eq!(gm.lobby_state.my_battle, None);
+
+        let open = gm.lobby_state.battles[&OPEN_BATTLE_ID].clone();
+        gm.auto_join_battle(&LobbyEvent::BattleOpened(open)).await;
+        assert_eq!(gm.lobby_state.my_battle, Some(OPEN_BATTLE_ID));
+    }
+
+    #[tokio::test]
+    async fn test_audit_log() {
+        let server = FakeLobbyServer::start("hunter2").await;
+        let mut gm = test_gm();
+        let result = gm.handle_tool_call("gm_audit_tail", &serde_json::json!({})).await;
+        assert_eq!(text(&result), "The audit log is off (config audit.enabled)");
+        gm.audit = Some(audit::AuditLog::open(&gm.write_dir.join("audit"), Default::default()).unwrap());
+
+        install_open_battle_content(&gm);
+        connect(&mut gm, &server).await;
+
+        // Requests from the client are audited as they're routed.
+        let login = serde_json::json!({"username": "agent", "password": "hunter2"});
+        gm.on_mcpl_request("tools/call", &serde_json::json!({"name": "lobby_login", "arguments": login})).await;
+        gm.handle_tool_call("lobby_join_channel", &serde_json::json!({"channel": "zk"})).await;
+        let publish = serde_json::json!({"channelId": "game:local-9", "content": [{"type": "text", "text": "{\"type\":\"stop\",\"unit_id\":1}"}]});
+        gm.on_mcpl_request("channels/publish", &publish).await;
+        gm.auto_join_founders = vec!["TEAMAUTOHOST".into()];
+        let open = gm.lobby_state.battles[&OPEN_BATTLE_ID].clone();
+        gm.auto_join_battle(&LobbyEvent::BattleOpened(open)).await;
+
+        let result = gm.handle_tool_call("gm_audit_tail", &serde_json::json!({"lines": 3})).await;
+        let lines: Vec<serde_json::Value> = text(&result).lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!((lines[0]["kind"].as_str(), lines[0]["name"].as_str()), (Some("tool"), Some("lobby_login")));
+        assert_eq!(lines[0]["details"], serde_json::json!({"username": "agent", "password": audit::REDACTED}));
+        assert_eq!(lines[0]["outcome"], "ok");
+        assert_eq!(lines[1]["name"], "channels/publish");
+        assert_eq!(lines[1]["details"]["channelId"], "game:local-9");
+        assert_eq!(lines[1]["outcome"], "error");
+        assert_eq!(lines[2]["kind"], "auto_join");
+        assert_eq!(lines[2]["details"]["battle_id"], OPEN_BATTLE_ID);
+        let raw = std::fs::read_to_string(gm.write_dir.join("audit/audit.jsonl")).unwrap();
+        assert!(!raw.contains("hunter2"));
+    }
+
+    #[tokio::test]
+    async fn test_mcpl_routing() {
+        let mut gm = test_gm();
+        let (loopback, mut client) = self_test::LoopbackClient::new();
+        gm.mcpl = Some(mcpl_link::McplLink::spawn(loopback, &Default::default()));
+
+        let tools = gm.on_mcpl_request("tools/list", &serde_json::json!({})).await;
+        assert!(tools["tools"].as_array().unwrap().iter().any(|t| t["name"] == "game_stats"));
+        let call = serde_json::json!({"name": "game_stats", "arguments": {"channel_id": "game:local-1"}});
+        let result = gm.on_mcpl_request("tools/call", &call).await;
+        assert_eq!(text(&result), "No SAI connection for channel game:local-1");
+        let result = gm.on_mcpl_request("channels/close", &serde_json::json!({})).await;
+        assert_eq!(result, serde_json::json!({"closed": false, "error": "Missing channelId"}));
+        let result = gm.on_mcpl_request("channels/subscribe", &serde_json::json!({})).await;
+        assert_eq!(result["error"], serde_json::json!({"code": -32601, "message": "Method not found: channels/subscribe"}));
+
+        // A request as it comes off the wire is answered under its id;
+        // notifications get no answer.
+        let notification = mcpl_core::connection::Notification { method: "featureSets/update".into(), params: None };
+        gm.on_mcpl_message(McplIncoming::Notification(notification)).await;
+        let request = mcpl_core::connection::Request { id: serde_json::json!(7), method: "channels/list".into(), params: None };
+        gm.on_mcpl_message(McplIncoming::Request(request)).await;
+        let response = tokio::time::timeout(std::time::Duration::from_secs(5), client.recv()).await.unwrap().unwrap();
+        assert_eq!(response["id"], 7);
+        assert_eq!(response["result"]["channels"], serde_json::json!([]));
+    }
+
+    #[tokio::test]
+    async fn test_tick_forwards_sai_events() {
+        let socket = SocketGuard::new("tick");
+        let mut gm = test_gm();
+        let (loopback, mut client) = self_test::LoopbackClient::new();
+        gm.mcpl = Some(mcpl_link::McplLink::spawn(loopback, &Default::default()));
+        gm.sai.listen_for("game:local-1", &socket).unwrap();
+
+        let mut bridge = sai_protocol::IpcClient::connect(&socket).unwrap();
+        bridge.send_event(&sai_ipc::SaiEvent::UnitIdle { unit: UnitId(12), unit_name: Some("cloakcon".into()) }).unwrap();
+        gm.tick(std::time::Instant::now()).await;
+
+        // The connection is announced, then the event forwarded.
+        let changed = next_mcpl(&mut client, "channels/changed").await;
+        assert_eq!(changed["params"]["updated"][0]["id"], "game:local-1");
+        assert_eq!(changed["params"]["updated"][0]["metadata"]["saiConnected"], true);
+        let incoming = next_mcpl(&mut client, "channels/incoming").await;
+        assert_eq!(incoming["params"]["messages"][0]["metadata"]["event"]["type"], "unit_idle");
+        assert_eq!(gm.sai.stats("game:local-1").unwrap().events_total(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_command_pacing() {
+        let socket = SocketGuard::new("pacing");
+        let mut gm = test_gm();
+        gm.sai.pacing = crate::pacing::PacingConfig { max_commands: 2, interval_ms: 100 };
+        gm.sai.listen_for("game:local-1", &socket).unwrap();
+        let mut bridge = sai_protocol::IpcClient::connect(&socket).unwrap();
+        let start = std::time::Instant::now();
+        gm.tick(start).await;
+
+        let publish = serde_json::json!({
+            "channelId": "game:local-1",
+            "content": [{"type": "text", "text": r#"{"type": "stop", "unit_id": 12}"#}]
+        });
+        for _ in 0..2 {
+            let result = gm.handle_channels_publish(&publish).await;
+            assert_eq!((result["delivered"].as_bool(), result.get("pacing")), (Some(true), None));
+        }
+        let result = gm.handle_channels_publish(&publish).await;
+        assert_eq!(result["delivered"], true);
+        assert_eq!((result["pacing"]["delayed"].as_u64(), result["pacing"]["backlog"].as_u64()), (Some(1), Some(1)));
+        let received = |bridge: &mut sai_protocol::IpcClient, n: usize| {
+            let mut commands = Vec::new();
+            let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+            while commands.len() < n && std::time::Instant::now() < deadline {
+                commands.extend(bridge.poll_commands());
+                std::thread::sleep(std::time::Duration::from_millis(5));
+            }
+            commands.len()
+        };
+        assert_eq!(received(&mut bridge, 2), 2);
+
+        // The held command goes out once the next interval starts.
+        gm.tick(start + std::time::Duration::from_millis(50)).await;
+        let stats = gm.handle_tool_call("game_stats", &serde_json::json!({"channel_id": "game:local-1"})).await;
+        assert_eq!(serde_json::from_str::<serde_json::Value>(text(&stats)).unwrap()["pacing"]["backlog"], 1);
+        gm.tick(std::time::Instant::now() + std::time::Duration::from_millis(100)).await;
+        assert_eq!(received(&mut bridge, 1), 1);
+        assert_eq!(gm.sai.pacing_counts("game:local-1"), Some(crate::pacing::PacingCounts { delayed: 1, backlog: 0 }));
+    }
+
+    #[tokio::test]
+    async fn test_lobby_ping_answered_while_waiting() {
+        let server = FakeLobbyServer::start("hunter2").await;
+        let mut gm = test_gm();
+        connect(&mut gm, &server).await;
+
+        // Queued ahead of the LoginResponse, so the client meets it mid-wait.
+        server.ping().await;
+        let result = login(&mut gm, "hunter2").await;
+        assert!(!is_error(&result));
+
+        server.wait_for("Ping").await;
+        let commands: Vec<String> = server.received().into_iter().map(|m| m.command).collect();
+        assert_eq!(commands, vec!["Login", "Ping"]);
+    }
+
+    #[tokio::test]
+    async fn test_lobby_connect_spring_parses() {
+        let server = FakeLobbyServer::start("hunter2").await;
+        let mut gm = test_gm();
+        connect(&mut gm, &server).await;
+        login(&mut gm, "hunter2").await;
+        gm.handle_tool_call("lobby_join_channel", &serde_json::json!({"channel": "zk"}))
+            .await;
+
+        server.connect_spring().await;
+        let msg = gm.lobby_conn.as_mut().unwrap().recv().await.unwrap();
+        assert_eq!(msg.command, "ConnectSpring");
+        match &gm.lobby_state.handle_message(&msg)[..] {
+            [LobbyEvent::ConnectSpring(data)] => {
+                assert_eq!(data.ip, "127.0.0.1");
+                assert_eq!(data.port, 8452);
+                assert_eq!(data.script_password, "a1b2c3d4");
+                assert_eq!(data.mode, 5);
+            }
+            other => panic!("unexpected events: {:?}", other),
+        }
+    }
+
+    /// Run lobby messages through the main loop's handler until one with
+    /// `command` has been handled.
+    async fn pump_until(gm: &mut GameManager, command: &str) {
+        loop {
+            let recv = gm.lobby_conn.as_mut().unwrap().recv();
+            let msg = tokio::time::timeout(std::time::Duration::from_secs(5), recv)
+                .await
+                .unwrap_or_else(|_| panic!("no {} from the lobby", command))
+                .unwrap();
+            gm.handle_lobby_message(&msg).await;
+            if msg.command == command {
+                return;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_matchmaker_flow() {
+        let server = FakeLobbyServer::start("hunter2").await;
+        let mut gm = test_gm();
+        // Multiplayer games run the windowed engine binary.
+        fake_engine(&gm, "sleep 30");
+        let engine_dir = gm.engines.engine_dir.clone();
+        std::fs::copy(engine_dir.join("spring-headless"), engine_dir.join("spring")).unwrap();
+        install_content(&gm, "Obsidian_1.5", "Zero-K v1.12.7.0", "");
+        connect(&mut gm, &server).await;
+        login(&mut gm, "hunter2").await;
+        gm.handle_tool_call("lobby_join_channel", &serde_json::json!({"channel": "zk"})).await;
+        let queue_1v1 = serde_json::json!({"queues": ["1v1"]});
+
+        let result = gm.handle_tool_call("lobby_matchmaker_join", &queue_1v1).await;
+        assert_eq!(text(&result), "Joined matchmaker queues: [1v1]. 1v1: 3 queued");
+        server.match_found().await;
+        pump_until(&mut gm, "AreYouReadyUpdate").await;
+        assert!(gm.lobby_state.matchmaker_ready_pending);
+        let deadline = gm.lobby_state.matchmaker_ready_deadline.unwrap();
+        assert!((8..=10).contains(&(deadline - chrono::Utc::now()).num_seconds()));
+        let expected = MatchContext {
+            queues: vec!["1v1".into()],
+            battle_size: Some(2),
+            battle_ready: Some(1),
+            accepted: false,
+        };
+        assert_eq!(gm.lobby_state.matchmaker_match.as_ref(), Some(&expected));
+
+        // Accepting starts the game, which carries the match along.
+        let result = gm.handle_tool_call("lobby_matchmaker_accept", &serde_json::json!({"ready": true})).await;
+        assert_eq!(text(&result), "Accepted matchmaker ready-check");
+        assert_eq!(server.wait_for("AreYouReadyResponse").await.data["Ready"], true);
+        pump_until(&mut gm, "ConnectSpring").await;
+        assert!(gm.lobby_state.matchmaker_match.is_none() && gm.lobby_state.matchmaker_joined.is_empty());
+        let listed = gm.handle_channels_list().await;
+        let game = listed["channels"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|c| c["id"].as_str().unwrap().starts_with("game:mp-"))
+            .expect("matchmaker game launched");
+        assert_eq!(
+            game["metadata"]["matchmaker"],
+            serde_json::json!({"queues": ["1v1"], "battleSize": 2, "expectedOpponents": 1})
+        );
+
+        // Declining the next one earns a ban from queueing.
+        gm.handle_tool_call("lobby_matchmaker_join", &queue_1v1).await;
+        server.match_found().await;
+        pump_until(&mut gm, "AreYouReadyUpdate").await;
+        let result = gm.handle_tool_call("lobby_matchmaker_accept", &serde_json::json!({"ready": false})).await;
+        assert_eq!(text(&result), "Declined matchmaker ready-check");
+        pump_until(&mut gm, "MatchMakerStatus").await;
+        assert_eq!(gm.lobby_state.matchmaker_banned_seconds, Some(60));
+        assert!(gm.lobby_state.matchmaker_match.is_none());
+        let result = gm.handle_tool_call("lobby_matchmaker_join", &queue_1v1).await;
+        assert!(is_error(&result));
+        assert_eq!(text(&result), "Failed to join queues (may be banned for 60s)");
+
+        // Answering after the deadline fails without bothering the server.
+        gm.lobby_state.matchmaker_ready_pending = true;
+        gm.lobby_state.matchmaker_ready_deadline = Some(chrono::Utc::now() - chrono::Duration::seconds(3));
+        let result = gm.handle_tool_call("lobby_matchmaker_accept", &serde_json::json!({"ready": true})).await;
+        assert_eq!(text(&result), "Ready-check expired 3s ago");
+        assert!(!gm.lobby_state.matchmaker_ready_pending);
+    }
+
+    #[tokio::test]
+    async fn test_lobby_disconnect_and_reconnect() {
+        let server = FakeLobbyServer::start("hunter2").await;
+        let mut gm = test_gm();
+        connect(&mut gm, &server).await;
+        login(&mut gm, "hunter2").await;
+
+        server.disconnect().await;
+        // Drain whatever the server sent before hanging up.
+        let err = loop {
+            match gm.lobby_conn.as_mut().unwrap().recv().await {
+                Ok(msg) => {
+                    gm.lobby_state.handle_message(&msg);
+                }
+                Err(e) => break e,
+            }
+        };
+        assert!(matches!(err, LobbyError::Closed));
+        gm.handle_lobby_closed(&err).await;
+        assert!(gm.lobby_conn.is_none());
+        assert!(!gm.lobby_state.logged_in);
+
+        let result = login(&mut gm, "hunter2").await;
+        assert!(is_error(&result));
+        assert_eq!(
+            text(&result),
+            "Not connected to lobby. Call lobby_connect first."
+        );
+
+        connect(&mut gm, &server).await;
+        let result = login(&mut gm, "hunter2").await;
+        assert_eq!(text(&result), "Logged in as 'agent'");
+        assert_eq!(server.connections(), 2);
+
+        let result = gm.handle_tool_call("lobby_disconnect", &serde_json::json!({})).await;
+        assert_eq!(text(&result), "Disconnected from lobby");
+        assert!(gm.lobby_conn.is_none());
+        assert!(gm.lobby_state.my_username.is_none());
+    }
+
+    /// Let the server hang up, and handle the close as the main loop does.
+    async fn lose_lobby(gm: &mut GameManager, server: &FakeLobbyServer) {
+        server.disconnect().await;
+        loop {
+            match gm.lobby_conn.as_mut().unwrap().recv().await {
+                Ok(msg) => {
+                    gm.lobby_state.handle_message(&msg);
+                }
+                Err(e) => break gm.handle_lobby_closed(&e).await,
+            }
+        }
+    }
+
+    /// The text of the next lobby push event starting with `prefix`.
+    async fn next_lobby_push(client: &mut tokio::sync::mpsc::UnboundedReceiver<serde_json::Value>, prefix: &str) -> String {
+        loop {
+            let event = next_mcpl(client, "push/event").await;
+            let text = event["params"]["payload"]["content"][0]["text"].as_str().unwrap_or_default();
+            if text.starts_with(prefix) {
+                return text.to_string();
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lobby_calls_queue_while_reconnecting() {
+        let server = FakeLobbyServer::start("hunter2").await;
+        let mut gm = test_gm();
+        let (loopback, mut client) = self_test::LoopbackClient::new();
+        gm.mcpl = Some(mcpl_link::McplLink::spawn(loopback, &Default::default()));
+        connect(&mut gm, &server).await;
+        login(&mut gm, "hunter2").await;
+        lose_lobby(&mut gm, &server).await;
+        let now = std::time::Instant::now();
+
+        // Chat and channel joins wait; logins and battle joins fail as before.
+        let result = gm.handle_tool_call("lobby_say", &serde_json::json!({"target": "zk", "text": "brb"})).await;
+        assert_eq!(text(&result), "Not connected to the lobby; queued as #1, will send on reconnect (dropped after 60s)");
+        let result = gm.handle_tool_call("lobby_join_channel", &serde_json::json!({"channel": "zk"})).await;
+        assert!(text(&result).contains("queued as #2"));
+        let join = serde_json::json!({"battle_id": OPEN_BATTLE_ID, "password": "secret"});
+        assert!(is_error(&gm.handle_tool_call("lobby_join_battle", &join).await));
+        assert!(is_error(&login(&mut gm, "hunter2").await));
+
+        gm.tick(now).await;
+        assert_eq!(server.connections(), 1);
+        gm.tick(now + std::time::Duration::from_secs(5)).await;
+        assert_eq!(server.connections(), 2);
+        let notice = next_lobby_push(&mut client, "Reconnecting").await;
+        assert_eq!(
+            notice,
+            "Reconnecting to the lobby in 5s. Until then lobby_say, lobby_join_channel, lobby_matchmaker_join, \
+             lobby_matchmaker_leave calls are queued; other lobby tools fail."
+        );
+        assert_eq!(next_lobby_push(&mut client, "Reconnected").await, "Reconnected to the lobby. Logged in as 'agent'");
+
+        // After the new login, the queued calls go out in order.
+        server.wait_for("JoinChannel").await;
+        let commands: Vec<String> = server.received().into_iter().map(|m| m.command).collect();
+        let relogin = commands.iter().rposition(|c| c == "Login").unwrap();
+        assert_eq!(commands[relogin + 1..], ["Say", "JoinChannel"]);
+        assert_eq!(next_lobby_push(&mut client, "Queued").await, "Queued lobby_say #1 sent: Sent to zk: brb");
+        assert!(next_lobby_push(&mut client, "Queued").await.starts_with("Queued lobby_join_channel #2 sent: Joined #zk"));
+        assert!(gm.lobby_chats.contains(&ChatChannel::Room("zk".into())));
+
+        // A call that outlives its max age is dropped with a notice.
+        lose_lobby(&mut gm, &server).await;
+        let now = std::time::Instant::now();
+        gm.handle_tool_call("lobby_matchmaker_join", &serde_json::json!({"queues": ["1v1"]})).await;
+        gm.tick(now + std::time::Duration::from_secs(61)).await;
+        assert_eq!(
+            next_lobby_push(&mut client, "Queued").await,
+            "Queued lobby_matchmaker_join #3 dropped: no lobby connection for 60s"
+        );
+        assert!(gm.lobby_state.logged_in);
+        assert_eq!(server.received().iter().filter(|m| m.command == "MatchMakerQueueRequest").count(), 0);
+    }
+
+    fn roster(count: i32) -> sai_ipc::SaiEvent {
+        sai_ipc::SaiEvent::Roster {
+            frame: 0,
+            units: (1..=count)
+                .map(|i| sai_protocol::RosterUnit {
+                    unit: UnitId(i),
+                    unit_name: Some(if i == 1 { "dyntrainer_strike_base".into() } else { "cloakraid".into() }),
+                    pos: [100.0 * i as f32, 10.0, 200.0],
+                })
+                .collect(),
+        }
+    }
+
+    fn incoming_text(gm: &GameManager, channel_id: &str, event: &sai_ipc::SaiEvent) -> String {
+        let msg = gm.sai_incoming_message(channel_id, event);
+        assert_eq!(msg.channel_id, channel_id);
+        let content = serde_json::to_value(&msg.content).unwrap();
+        content[0]["text"].as_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_roster_announces_starting_units() {
+        let mut gm = test_gm();
+        assert_eq!(
+            incoming_text(&gm, "game:local-1", &roster(2)),
+            "Game started: you control 2 units: dyntrainer_strike_base (#1) near (100, 200), \
+             cloakraid (#2) near (200, 200)"
+        );
+        assert_eq!(
+            incoming_text(&gm, "game:local-1", &roster(0)),
+            "Game started: you control no units yet"
+        );
+
+        gm.verbosity.insert("game:local-1".into(), Verbosity::Terse);
+        let text = incoming_text(&gm, "game:local-1", &roster(12));
+        assert!(
+            text.starts_with("Game started: you control 12 units: dyntrainer_strike_base (#1), cloakraid (#2),"),
+            "{}",
+            text
+        );
+        assert!(text.ends_with("cloakraid (#10), and 2 more"), "{}", text);
+    }
+
+    #[test]
+    fn test_roster_metadata_carries_units() {
+        let gm = test_gm();
+        let msg = gm.sai_incoming_message("game:local-1", &roster(1));
+        let event = &msg.metadata.unwrap()["event"];
+        assert_eq!(event["type"], "roster");
+        assert_eq!(event["units"][0]["unit"], 1);
+        assert_eq!(event["units"][0]["pos"], serde_json::json!([100.0, 10.0, 200.0]));
+    }
+
+    #[tokio::test]
+    async fn test_roster_from_bridge_after_init() {
+        // The bridge sends init then roster on connect; both reach the channel.
+        let (mut gm, mut bridge, _socket) = connected_gm("roster");
+
+        bridge
+            .send_event(&sai_ipc::SaiEvent::Init {
+                frame: 0,
+                saved_game: false,
+                protocol_version: Some(sai_ipc::PROTOCOL_VERSION),
+                metal_spots: None,
+                map_width: Some(512),
+                map_height: Some(512),
+                start_pos: None,
+                teams: Vec::new(),
+                build: None,
+            })
+            .unwrap();
+        bridge.send_event(&roster(1)).unwrap();
+
+        let conn = gm.sai.connections.get_mut("game:local-1").unwrap();
+        let init = conn.next_event().await.unwrap();
+        let roster_event = conn.next_event().await.unwrap();
+        assert_eq!(init.type_name(), "init");
+        gm.handle_sai_event("game:local-1", &init).await;
+        gm.handle_sai_event("game:local-1", &roster_event).await;
+        assert_eq!(
+            incoming_text(&gm, "game:local-1", &roster_event),
+            "Game started: you control 1 unit: dyntrainer_strike_base (#1) at your base"
+        );
+        // Raw coordinates stay in the metadata.
+        let message = gm.sai_incoming_message("game:local-1", &roster_event);
+        assert_eq!(message.metadata.unwrap()["event"]["units"][0]["pos"], serde_json::json!([100.0, 10.0, 200.0]));
+    }
+
+    #[tokio::test]
+    async fn test_game_control_tools() {
+        let (mut gm, mut bridge, _socket) = connected_gm("control");
+        let args = |extra: serde_json::Value| {
+            let mut a = serde_json::json!({"channel_id": "game:local-1"});
+            a.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+            a
+        };
+
+        let result = gm.handle_tool_call("game_pause", &args(serde_json::json!({}))).await;
+        assert!(!is_error(&result), "{}", text(&result));
+        assert_eq!(text(&result), "Game paused at speed 1");
+        assert_eq!(bridge.poll_commands(), vec![SaiCommand::Pause]);
+        assert!(gm.game_control["game:local-1"].paused);
+
+        let result = gm
+            .handle_tool_call("game_set_speed", &args(serde_json::json!({"speed": 2.5})))
+            .await;
+        assert_eq!(text(&result), "Game paused at speed 2.5");
+        assert_eq!(bridge.poll_commands(), vec![SaiCommand::SetSpeed { speed: 2.5 }]);
+
+        let result = gm.handle_tool_call("game_resume", &args(serde_json::json!({}))).await;
+        assert_eq!(text(&result), "Game running at speed 2.5");
+        assert_eq!(bridge.poll_commands(), vec![SaiCommand::Unpause]);
+    }
+
+    #[tokio::test]
+    async fn test_game_set_speed_out_of_range() {
+        let (mut gm, mut bridge, _socket) = connected_gm("speed");
+        let meta = serde_json::json!({"min_speed": 0.5, "max_speed": 3.0});
+        gm.game_control.insert(
+            "game:local-1".into(),
+            GameControl::from_metadata(Some(&meta)).unwrap(),
+        );
+
+        for speed in [0.25, 4.0] {
+            let result = gm
+                .handle_tool_call(
+                    "game_set_speed",
+                    &serde_json::json!({"channel_id": "game:local-1", "speed": speed}),
+                )
+                .await;
+            assert!(is_error(&result));
+            assert!(text(&result).contains("outside the allowed range 0.5-3"));
+        }
+        let result = gm
+            .handle_tool_call("game_set_speed", &serde_json::json!({"channel_id": "game:local-1"}))
+            .await;
+        assert_eq!(text(&result), "Missing speed");
+
+        // Nothing reached the bridge and the recorded speed is unchanged.
+        assert!(bridge.poll_commands().is_empty());
+        assert_eq!(gm.game_control["game:local-1"].speed, 1.0);
+    }
+
+    #[tokio::test]
+    async fn test_game_pause_without_connection() {
+        let mut gm = test_gm();
+        let result = gm
+            .handle_tool_call("game_pause", &serde_json::json!({"channel_id": "game:local-9"}))
+            .await;
+        assert!(is_error(&result));
+        assert_eq!(text(&result), "No SAI connection for channel game:local-9");
+        assert!(!gm.game_control.contains_key("game:local-9"));
+    }
+
+    #[tokio::test]
+    async fn test_game_chat_both_ways() {
+        let (mut gm, mut bridge, _socket) = connected_gm("chat");
+
+        let say = |destination: Option<&str>| {
+            let mut args = serde_json::json!({"channel_id": "game:local-1", "text": "gg"});
+            if let Some(d) = destination {
+                args["destination"] = d.into();
+            }
+            args
+        };
+        let result = gm.handle_tool_call("game_say", &say(Some("allies"))).await;
+        assert_eq!(text(&result), "Said to allies: gg");
+        let result = gm.handle_tool_call("game_say", &say(None)).await;
+        assert_eq!(text(&result), "Said to all: gg");
+        assert_eq!(
+            bridge.poll_commands(),
+            vec![
+                SaiCommand::SendChat { text: "gg".into(), destination: sai_ipc::ChatDestination::Allies },
+                SaiCommand::SendChat { text: "gg".into(), destination: sai_ipc::ChatDestination::All },
+            ]
+        );
+        let result = gm.handle_tool_call("game_say", &say(Some("team"))).await;
+        assert!(is_error(&result));
+        assert_eq!(text(&result), "Unknown destination 'team' (expected all, allies or spectators)");
+
+        // Incoming chat is authored by the player, named when the bridge knows the name.
+        let chat = |player_name: Option<&str>| sai_ipc::SaiEvent::Message {
+            player: 2,
+            player_name: player_name.map(String::from),
+            text: "gl hf".into(),
+            text_lossy: false,
+        };
+        let msg = gm.sai_incoming_message("game:local-1", &chat(Some("Godde")));
+        assert_eq!((msg.author.id.as_str(), msg.author.name.as_str()), ("player:2", "Godde"));
+        assert_eq!(incoming_text(&gm, "game:local-1", &chat(Some("Godde"))), "Godde says: gl hf");
+        let msg = gm.sai_incoming_message("game:local-1", &chat(None));
+        assert_eq!(msg.author.name, "Player 2");
+    }
+
+    #[tokio::test]
+    async fn test_auto_respond_in_game() {
+        let mut gm = test_gm();
+        gm.auto_respond = autorespond::AutoResponder::new(
+            serde_json::from_value(serde_json::json!([
+                {"pattern": "(?i)^start\\?", "reply": "ready, $0"},
+            ]))
+            .unwrap(),
+        )
+        .unwrap();
+        let (mut bridge, _socket) = connect_bridge(&mut gm, "autorespond");
+
+        let chat = |text: &str| sai_ipc::SaiEvent::Message {
+            player: 2,
+            player_name: Some("Godde".into()),
+            text: text.into(),
+            text_lossy: false,
+        };
+        gm.handle_sai_event("game:local-1", &chat("Start?")).await;
+        gm.handle_sai_event("game:local-1", &chat("gl hf")).await;
+        assert_eq!(
+            bridge.poll_commands(),
+            vec![SaiCommand::SendChat {
+                text: "ready, Start?".into(),
+                destination: sai_ipc::ChatDestination::All,
+            }]
+        );
+
+        // Turned off for the channel, the rule stays quiet.
+        gm.auto_respond.close_channel("game:local-1");
+        gm.auto_respond.set_enabled("game:local-1", false);
+        gm.handle_sai_event("game:local-1", &chat("start?")).await;
+        assert!(bridge.poll_commands().is_empty());
+
+        let notice = gm.notice_message("game:local-1", "Auto-responded".into(), serde_json::json!({}));
+        assert_eq!(notice.author.id, "gamemanager");
+    }
+
+    #[tokio::test]
+    async fn test_group_addressed_commands() {
+        let (mut gm, mut bridge, _socket) = connected_gm("groups");
+
+        let create = serde_json::json!({
+            "channel_id": "game:local-1", "name": "raiders", "unit_ids": [12, 11, 13], "alert_below": 3,
+        });
+        let result = gm.handle_tool_call("game_group_create", &create).await;
+        assert_eq!(text(&result), "Group raiders: 3 units [11, 12, 13]");
+        let add = serde_json::json!({"channel_id": "game:local-1", "name": "tanks", "unit_ids": [20]});
+        let result = gm.handle_tool_call("game_group_add", &add).await;
+        assert!(is_error(&result));
+        assert_eq!(text(&result), "Unknown group tanks");
+        let result = gm.handle_tool_call("game_group_list", &serde_json::json!({"channel_id": "game:local-9"})).await;
+        assert!(is_error(&result));
+        assert_eq!(text(&result), "Unknown game channel game:local-9");
+        assert!(!gm.groups.contains_key("game:local-9"));
+
+        // A dead raider drops out, taking the group below its alert size.
+        let destroyed = sai_ipc::SaiEvent::UnitDestroyed {
+            unit: UnitId(12), unit_name: None, attacker: UnitId(90), attacker_name: None,
+            attacker_team: None, attacker_relation: None, weapon_def_id: WeaponDefId(1),
+        };
+        gm.handle_sai_event("game:local-1", &destroyed).await;
+        let publish = serde_json::json!({
+            "channelId": "game:local-1",
+            "content": [{"type": "text", "text": "{\"type\": \"stop\", \"group\": \"raiders\"}"}],
+        });
+        assert_eq!(gm.handle_channels_publish(&publish).await["delivered"], true);
+        assert_eq!(
+            bridge.poll_commands(),
+            vec![SaiCommand::Stop { unit_id: UnitId(11) }, SaiCommand::Stop { unit_id: UnitId(13) }]
+        );
+
+        let list = gm.handle_tool_call("game_group_list", &serde_json::json!({"channel_id": "game:local-1"})).await;
+        let list: serde_json::Value = serde_json::from_str(text(&list)).unwrap();
+        assert_eq!(list[0]["units"], serde_json::json!([11, 13]));
+        let remove = serde_json::json!({"channel_id": "game:local-1", "name": "raiders"});
+        assert_eq!(text(&gm.handle_tool_call("game_group_remove", &remove).await), "Disbanded group raiders");
+        let result = gm.handle_channels_publish(&publish).await;
+        assert_eq!(result["error"], "Unknown group raiders");
+    }
+
+    #[tokio::test]
+    async fn test_game_assist_tasks_idle_constructors() {
+        let (mut gm, mut bridge, _socket) = connected_gm("assist");
+
+        let unit = |unit, name: &str, x| sai_protocol::RosterUnit { unit: UnitId(unit), unit_name: Some(name.into()), pos: [x, 10.0, 0.0] };
+        let roster = sai_ipc::SaiEvent::Roster {
+            frame: 30,
+            units: vec![unit(5, "cloakcon", 100.0), unit(6, "cloakcon", 200.0), unit(7, "cloakcon", 300.0), unit(20, "factorycloak", 0.0)],
+        };
+        gm.handle_sai_event("game:local-1", &roster).await;
+        for unit in [5, 6] {
+            gm.handle_sai_event("game:local-1", &sai_ipc::SaiEvent::UnitIdle { unit: UnitId(unit), unit_name: None }).await;
+        }
+        // 6 gets orders another way before the agent asks.
+        let publish = serde_json::json!({
+            "channelId": "game:local-1",
+            "content": [{"type": "text", "text": "{\"type\": \"stop\", \"unit_id\": 6}"}],
+        });
+        assert_eq!(gm.handle_channels_publish(&publish).await["delivered"], true);
+        bridge.poll_commands();
+
+        let assist = serde_json::json!({"channel_id": "game:local-1", "mode": "factory"});
+        let result = gm.handle_tool_call("game_assist", &assist).await;
+        assert_eq!(text(&result), "Tasked 1 idle constructors: 5 assists factory 20");
+        assert_eq!(result["structuredContent"]["tasked"], serde_json::json!([5]));
+        assert_eq!(bridge.poll_commands(), vec![SaiCommand::Guard { unit_id: UnitId(5), guard_id: UnitId(20), queue: false }]);
+
+        // 5 is busy now: nobody left to task.
+        let result = gm.handle_tool_call("game_assist", &assist).await;
+        assert_eq!(text(&result), "No idle constructors to task");
+        let bad = serde_json::json!({"channel_id": "game:local-1", "mode": "reclaim", "area": {"x": 100}});
+        assert_eq!(text(&gm.handle_tool_call("game_assist", &bad).await), "area needs x and z, and a positive radius if given");
+    }
+
+    #[tokio::test]
+    async fn test_game_command_eta_and_reminder() {
+        let (mut gm, mut bridge, _socket) = connected_gm("eta");
+        let channel = "game:local-1";
+
+        let raider = sai_ipc::UnitDefInfo {
+            id: UnitDefId(1),
+            name: "cloakraid".into(),
+            human_name: "Glaive".into(),
+            description: None,
+            metal_cost: 65.0,
+            energy_cost: 65.0,
+            build_time: 65.0,
+            health: 230.0,
+            speed: 100.0,
+            builder: false,
+            build_options: Vec::new(),
+            metal_make: 0.0,
+            energy_make: 0.0,
+            extracts_metal: 0.0,
+            build_speed: 0.0,
+        };
+        gm.unit_defs.insert(channel.into(), unit_defs::UnitDefCatalog::new(vec![raider]));
+        let unit = |unit, x| sai_protocol::RosterUnit { unit: UnitId(unit), unit_name: Some("cloakraid".into()), pos: [x, 10.0, 0.0] };
+        gm.handle_sai_event(channel, &sai_ipc::SaiEvent::Roster { frame: 30, units: vec![unit(5, 0.0), unit(6, 1000.0)] }).await;
+        let update = |frame| sai_ipc::SaiEvent::Update { frame, awaiting_commands: false, economy: None, allies: Vec::new(), counters: Default::default(), command_backlog: 0, under_construction: Vec::new(), update_interval: None };
+        gm.handle_sai_event(channel, &update(30)).await;
+
+        let command = |unit: i32, remind| {
+            serde_json::json!({
+                "channel_id": channel,
+                "command": {"type": "move", "unit_id": unit, "x": 3000, "z": 0},
+                "remind": remind,
+            })
+        };
+        let result = gm.handle_tool_call("game_command", &command(5, true)).await;
+        assert_eq!(
+            text(&result),
+            "Sent move (unit 5)\nEstimated arrival of unit 5 in ~30s (straight line at top speed; likely later)"
+        );
+        assert_eq!(result["structuredContent"]["eta"]["secs"], 30.0);
+        gm.handle_tool_call("game_command", &command(6, true)).await;
+        assert_eq!(bridge.poll_commands().len(), 2);
+
+        // 6 dies on the way: its reminder goes with it.
+        gm.handle_sai_event(channel, &sai_ipc::SaiEvent::UnitDestroyed {
+            unit: UnitId(6), unit_name: None, attacker: UnitId(90), attacker_name: None,
+            attacker_team: None, attacker_relation: None, weapon_def_id: sai_protocol::WeaponDefId(1),
+        }).await;
+        gm.handle_sai_event(channel, &update(30 + 30 * 30)).await;
+        let replay = gm.on_mcpl_request("channels/replay", &serde_json::json!({"channelId": channel})).await;
+        let reminders: Vec<&serde_json::Value> =
+            replay["messages"].as_array().unwrap().iter().filter(|m| m["metadata"].get("etaReminder").is_some()).collect();
+        assert_eq!(reminders.len(), 1);
+        assert_eq!(reminders[0]["content"][0]["text"], "Unit 5 should be arriving at (3000, 0) about now (move; estimated)");
+
+        // Without remind, nothing is scheduled.
+        gm.handle_tool_call("game_command", &command(5, false)).await;
+        gm.handle_sai_event(channel, &update(30 + 90 * 30)).await;
+        let replay = gm.on_mcpl_request("channels/replay", &serde_json::json!({"channelId": channel})).await;
+        let reminders =
+            replay["messages"].as_array().unwrap().iter().filter(|m| m["metadata"].get("etaReminder").is_some()).count();
+        assert_eq!(reminders, 1);
+    }
+
+    #[tokio::test]
+    async fn test_unit_transfers_update_trackers() {
+        let mut gm = test_gm();
+        let channel = "game:local-1";
+        gm.handle_sai_event(channel, &roster(2)).await;
+        gm.groups.entry(channel.into()).or_default().create("raiders", &[UnitId(2), UnitId(41)], Some(2)).unwrap();
+        let given = |relation| sai_ipc::SaiEvent::UnitGiven {
+            unit: UnitId(41), unit_name: Some("cloakcon".into()), old_team: sai_ipc::TeamId(1), new_team: sai_ipc::TeamId(0),
+            old_relation: Some(relation), pos: Some([700.0, 10.0, 300.0]),
+        };
+        let captured = sai_ipc::SaiEvent::UnitCaptured {
+            unit: UnitId(41), unit_name: Some("cloakcon".into()), old_team: sai_ipc::TeamId(0), new_team: sai_ipc::TeamId(1),
+            new_relation: Some(sai_ipc::Relation::Enemy),
+        };
+        let units = |gm: &GameManager| gm.observers[channel].state.units.iter().map(|u| u.0).collect::<std::collections::BTreeSet<_>>();
+        let at_unit = serde_json::json!({"at_unit": 41});
+
+        // Handed over by an ally: ours, with a position to send things to.
+        gm.handle_sai_event(channel, &given(sai_ipc::Relation::Ally)).await;
+        assert_eq!(units(&gm), [1, 2, 41].into());
+        assert_eq!(gm.places[channel].locate(&at_unit), Ok(locations::Place { x: 700.0, z: 300.0 }));
+        assert_eq!(
+            incoming_text(&gm, channel, &given(sai_ipc::Relation::Ally)),
+            "You received cloakcon (#41) from allied team 1 600 elmos east of your base"
+        );
+
+        // Captured by the enemy, then taken back: the same id comes and goes.
+        gm.handle_sai_event(channel, &captured).await;
+        assert_eq!(units(&gm), [1, 2].into());
+        assert!(gm.places[channel].locate(&at_unit).is_err());
+        assert_eq!(gm.groups[channel].get("raiders").unwrap().members, [UnitId(2)].into());
+        assert_eq!(incoming_text(&gm, channel, &captured), "Your cloakcon (#41) was captured by enemy team 1");
+        gm.handle_sai_event(channel, &given(sai_ipc::Relation::Enemy)).await;
+        assert_eq!(units(&gm), [1, 2, 41].into());
+        assert!(incoming_text(&gm, channel, &given(sai_ipc::Relation::Enemy)).starts_with("You captured cloakcon (#41) from enemy team 1"));
+    }
+
+    #[tokio::test]
+    async fn test_game_expand_queues_mexes() {
+        let (mut gm, mut bridge, _socket) = connected_gm("expand");
+        let expand = serde_json::json!({"channel_id": "game:local-1", "builder_id": 5, "count": 3});
+        let result = gm.handle_tool_call("game_expand", &expand).await;
+        assert_eq!(text(&result), "No metal spots known yet (they arrive with the init event)");
+
+        let spot = |x| sai_protocol::MetalSpot { x, y: 0.0, z: 100.0, metal: 2.0 };
+        let init = sai_ipc::SaiEvent::Init {
+            frame: 0,
+            saved_game: false,
+            protocol_version: Some(sai_ipc::PROTOCOL_VERSION),
+            metal_spots: Some(vec![spot(500.0), spot(1500.0)]),
+            map_width: Some(512),
+            map_height: Some(512),
+            start_pos: None,
+            teams: Vec::new(),
+            build: None,
+        };
+        gm.handle_sai_event("game:local-1", &init).await;
+        let commander = sai_protocol::RosterUnit { unit: UnitId(5), unit_name: Some("dyntrainer".into()), pos: [0.0, 0.0, 100.0] };
+        gm.handle_sai_event("game:local-1", &sai_ipc::SaiEvent::Roster { frame: 1, units: vec![commander] }).await;
+
+        let result = gm.handle_tool_call("game_expand", &expand).await;
+        assert_eq!(
+            text(&result),
+            "Queued 2 mex builds for unit 5: (500, 100), (1500, 100) (only 2 unclaimed spots left)"
+        );
+        let xs: Vec<f32> = bridge
+            .poll_commands()
+            .into_iter()
+            .map(|c| match c {
+                SaiCommand::Build { build_def_name: Some(name), x, queue: true, .. } if name == "staticmex" => x,
+                other => panic!("unexpected {:?}", other),
+            })
+            .collect();
+        assert_eq!(xs, [500.0, 1500.0]);
+        let result = gm.handle_tool_call("game_expand", &expand).await;
+        assert_eq!(text(&result), "All 2 metal spots are claimed");
+        let elsewhere = serde_json::json!({"channel_id": "game:local-9", "builder_id": 5});
+        let result = gm.handle_tool_call("game_expand", &elsewhere).await;
+        assert_eq!(text(&result), "Unknown game channel game:local-9");
+        assert!(!gm.expansions.contains_key("game:local-9"));
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_commands() {
+        let (mut gm, mut bridge, _socket) = connected_gm("dry-run");
+        let init = |version| sai_ipc::SaiEvent::Init {
+            frame: 0,
+            saved_game: false,
+            protocol_version: Some(version),
+            metal_spots: None,
+            map_width: None,
+            map_height: None,
+            start_pos: None,
+            teams: Vec::new(),
+            build: None,
+        };
+        let stop = |unit: i32| serde_json::json!({"type": "stop", "unit_id": unit});
+        let dry_publish = serde_json::json!({
+            "channelId": "game:local-1",
+            "content": [{"type": "text", "text": stop(5).to_string()}],
+            "metadata": {"dry_run": true}
+        });
+
+        // An older bridge would execute the command instead.
+        bridge.send_event(&init(1)).unwrap();
+        gm.sai.drain_events("game:local-1").await;
+        let result = gm.handle_channels_publish(&dry_publish).await;
+        assert_eq!(
+            result["error"],
+            "The SAI bridge for game:local-1 doesn't support dry runs (protocol Some(1), needs 2)"
+        );
+
+        bridge.send_event(&init(sai_ipc::PROTOCOL_VERSION)).unwrap();
+        gm.sai.drain_events("game:local-1").await;
+        // The bridge answers dry runs (unit 99 is gone) and reports an idle
+        // unit before the first verdict.
+        let bridge = std::thread::spawn(move || {
+            let mut executed = Vec::new();
+            let mut answered = 0;
+            for _ in 0..500 {
+                executed.extend(bridge.poll_commands());
+                for dry_run in bridge.take_dry_runs() {
+                    if answered == 0 {
+                        bridge.send_event(&sai_ipc::SaiEvent::UnitIdle { unit: UnitId(7), unit_name: None }).unwrap();
+                    }
+                    answered += 1;
+                    let error = match dry_run.command {
+                        SaiCommand::Stop { unit_id: UnitId(99) } => Some("unit 99 does not exist".to_string()),
+                        _ => None,
+                    };
+                    bridge.send_event(&sai_ipc::SaiEvent::DryRunResult { request_id: dry_run.request_id, error }).unwrap();
+                }
+                if !executed.is_empty() {
+                    break;
+                }
+                std::thread::sleep(std::time::Duration::from_millis(10));
+            }
+            executed
+        });
+
+        let result = gm.handle_channels_publish(&dry_publish).await;
+        assert_eq!(result["delivered"], false);
+        assert_eq!(
+            result["dryRun"],
+            serde_json::json!({"valid": true, "results": [{"command": "stop (unit 5)", "ok": true, "error": null}]})
+        );
+        let dry = serde_json::json!({"channel_id": "game:local-1", "command": stop(99), "dry_run": true});
+        let result = gm.handle_tool_call("game_command", &dry).await;
+        assert_eq!(text(&result), "Dry run: 1 of 1 would fail\nstop (unit 99): unit 99 does not exist");
+        assert!(!is_error(&result));
+
+        let send = serde_json::json!({"channel_id": "game:local-1", "command": stop(5)});
+        let result = gm.handle_tool_call("game_command", &send).await;
+        assert_eq!(text(&result), "Sent stop (unit 5)");
+        assert_eq!(bridge.join().unwrap(), [SaiCommand::Stop { unit_id: UnitId(5) }], "only the real command ran");
+        // Events read while waiting for verdicts are still delivered.
+        assert_eq!(
+            gm.sai.drain_events("game:local-1").await,
+            [sai_ipc::SaiEvent::UnitIdle { unit: UnitId(7), unit_name: None }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unit_defs_fetched_once_and_searched() {
+        let (mut gm, mut bridge, _socket) = connected_gm("unitdefs");
+        let init = |version| sai_ipc::SaiEvent::Init {
+            frame: 0,
+            saved_game: false,
+            protocol_version: Some(version),
+            metal_spots: None,
+            map_width: None,
+            map_height: None,
+            start_pos: None,
+            teams: Vec::new(),
+            build: None,
+        };
+        let query = serde_json::json!({"channel_id": "game:local-1", "filter": "anti-air"});
+
+        bridge.send_event(&init(2)).unwrap();
+        gm.sai.drain_events("game:local-1").await;
+        let result = gm.handle_tool_call("game_unitdefs", &query).await;
+        assert_eq!(
+            text(&result),
+            "The SAI bridge for game:local-1 doesn't support unit def queries (protocol Some(2), needs 3)"
+        );
+
+        bridge.send_event(&init(sai_ipc::PROTOCOL_VERSION)).unwrap();
+        gm.sai.drain_events("game:local-1").await;
+        // The bridge answers the one query in two chunks, with an event in between.
+        let bridge = std::thread::spawn(move || {
+            let def = |id: i32, name: &str, human_name: &str, description: &str| sai_ipc::UnitDefInfo {
+                id: UnitDefId(id),
+                name: name.into(),
+                human_name: human_name.into(),
+                description: Some(description.into()),
+                metal_cost: 150.0,
+                energy_cost: 150.0,
+                build_time: 150.0,
+                health: 500.0,
+                speed: 0.0,
+                builder: false,
+                build_options: Vec::new(),
+                metal_make: 0.0,
+                energy_make: 0.0,
+                extracts_metal: 0.0,
+                build_speed: 0.0,
+            };
+            let factory = sai_ipc::UnitDefInfo {
+                builder: true,
+                build_options: vec![UnitDefId(2), UnitDefId(3)],
+                ..def(1, "factorycloak", "Cloakbot Factory", "Produces Cloaked Robots")
+            };
+            let aa = sai_ipc::UnitDefInfo { speed: 2.6, ..def(2, "cloakaa", "Gremlin", "Cloaked Anti-Air Bot") };
+            let turret = def(3, "turretaalaser", "Hacksaw", "Burst Anti-Air Turret");
+            let mut queries = Vec::new();
+            for _ in 0..500 {
+                for cmd in bridge.poll_commands() {
+                    let SaiCommand::QueryUnitDefs { request_id } = cmd else { continue };
+                    queries.push(request_id);
+                    let chunk = |offset, defs| sai_ipc::SaiEvent::UnitDefs { request_id, offset, total: 3, defs };
+                    bridge.send_event(&chunk(0, vec![factory.clone(), aa.clone()])).unwrap();
+                    bridge.send_event(&sai_ipc::SaiEvent::UnitIdle { unit: UnitId(7), unit_name: None }).unwrap();
+                    bridge.send_event(&chunk(2, vec![turret.clone()])).unwrap();
+                }
+                if !queries.is_empty() {
+                    break;
+                }
+                std::thread::sleep(std::time::Duration::from_millis(10));
+            }
+            queries
+        });
+
+        let result = gm.handle_tool_call("game_unitdefs", &query).await;
+        let defs: serde_json::Value = serde_json::from_str(text(&result)).unwrap();
+        assert_eq!(
+            defs,
+            serde_json::json!([
+                {"name": "cloakaa", "human_name": "Gremlin", "description": "Cloaked Anti-Air Bot", "role": "unit", "metal_cost": 150.0},
+                {"name": "turretaalaser", "human_name": "Hacksaw", "description": "Burst Anti-Air Turret", "role": "structure", "metal_cost": 150.0}
+            ])
+        );
+        assert_eq!(bridge.join().unwrap().len(), 1);
+        assert_eq!(
+            gm.sai.drain_events("game:local-1").await,
+            [sai_ipc::SaiEvent::UnitIdle { unit: UnitId(7), unit_name: None }]
+        );
+
+        // Later calls are answered from the cache; the bridge is gone by now.
+        let factory = serde_json::json!({"channel_id": "game:local-1", "filter": "cloakbot", "fields": ["role", "build_options"]});
+        let result = gm.handle_tool_call("game_unitdefs", &factory).await;
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(text(&result)).unwrap(),
+            serde_json::json!([{"role": "factory", "build_options": ["cloakaa", "turretaalaser"]}])
+        );
+        let none = serde_json::json!({"channel_id": "game:local-1", "filter": "nuke"});
+        assert_eq!(text(&gm.handle_tool_call("game_unitdefs", &none).await), "No unit defs match 'nuke' (3 defs in the catalog)");
+        let bad = serde_json::json!({"channel_id": "game:local-1", "fields": ["dps"]});
+        assert!(is_error(&gm.handle_tool_call("game_unitdefs", &bad).await));
+    }
+
+    #[tokio::test]
+    async fn test_map_grid_served_as_tool_and_resource() {
+        let (mut gm, mut bridge, _socket) = connected_gm("mapgrid");
+        bridge
+            .send_event(&sai_ipc::SaiEvent::Init {
+                frame: 0,
+                saved_game: false,
+                protocol_version: Some(sai_ipc::PROTOCOL_VERSION),
+                metal_spots: Some(vec![sai_protocol::MetalSpot { x: 300.0, y: 0.0, z: 20.0, metal: 2.0 }]),
+                map_width: None,
+                map_height: None,
+                start_pos: None,
+                teams: Vec::new(),
+                build: None,
+            })
+            .unwrap();
+        for event in gm.sai.drain_events("game:local-1").await {
+            gm.handle_sai_event("game:local-1", &event).await;
+        }
+
+        // The bridge answers the one query in two bands, with an event in between.
+        let bridge = std::thread::spawn(move || {
+            let mut queries = Vec::new();
+            for _ in 0..500 {
+                for cmd in bridge.poll_commands() {
+                    let SaiCommand::QueryMapGrid { request_id, cell_size, build_def } = cmd else { continue };
+                    queries.push((cell_size, build_def));
+                    let band = |row_offset, elevation| sai_ipc::SaiEvent::MapGrid {
+                        request_id,
+                        cell_size: 256.0,
+                        width: 3,
+                        height: 2,
+                        row_offset,
+                        elevation,
+                        buildable: Vec::new(),
+                        error: None,
+                    };
+                    bridge.send_event(&band(0, vec![vec![0, 100, 200]])).unwrap();
+                    bridge.send_event(&sai_ipc::SaiEvent::UnitIdle { unit: UnitId(7), unit_name: None }).unwrap();
+                    bridge.send_event(&band(1, vec![vec![-20, 0, 0]])).unwrap();
+                }
+                if !queries.is_empty() {
+                    break;
+                }
+                std::thread::sleep(std::time::Duration::from_millis(10));
+            }
+            queries
+        });
+
+        let query = serde_json::json!({"channel_id": "game:local-1"});
+        let result = gm.handle_tool_call("game_map", &query).await;
+        assert_eq!(
+            text(&result).lines().take(3).collect::<Vec<_>>(),
+            ["Map grid 3x2 of 256-elmo cells (x across, z down), heights -20..200", ".M@", "~.."]
+        );
+        assert_eq!(bridge.join().unwrap(), [(map_grid::DEFAULT_CELL_SIZE, None)]);
+        assert_eq!(
+            gm.sai.drain_events("game:local-1").await,
+            [sai_ipc::SaiEvent::UnitIdle { unit: UnitId(7), unit_name: None }]
+        );
+
+        // The resource is served from the cache; the bridge is gone by now.
+        let read = gm.handle_resources_read(&serde_json::json!({"uri": "game://game:local-1/map"})).await;
+        let contents = &read["contents"][0];
+        assert_eq!(contents["uri"], "game://game:local-1/map");
+        let grid: serde_json::Value = serde_json::from_str(contents["text"].as_str().unwrap()).unwrap();
+        assert_eq!(grid["elevation"], serde_json::json!([[0, 100, 200], [-20, 0, 0]]));
+        assert_eq!(grid["slope"][0], serde_json::json!([39, 39, 78]));
+        assert_eq!(grid["metal_spots"][0]["x"], 300.0);
+        let unknown = gm.handle_resources_read(&serde_json::json!({"uri": "game://game:local-1/units"})).await;
+        assert_eq!(unknown["error"]["message"], "Unknown resource: game://game:local-1/units");
+        let fine = serde_json::json!({"channel_id": "game:local-1", "cell_size": 64});
+        assert!(is_error(&gm.handle_tool_call("game_map", &fine).await), "another cell size is queried anew");
+    }
+
+    #[tokio::test]
+    async fn test_command_history() {
+        let mut gm = test_gm();
+        let history = serde_json::json!({"channel_id": "game:local-1"});
+        let result = gm.handle_tool_call("game_command_history", &history).await;
+        assert_eq!(text(&result), "No commands sent to game:local-1");
+
+        // No bridge yet: the failed send is still on record.
+        let send = |unit: i32| serde_json::json!({"channel_id": "game:local-1", "command": {"type": "stop", "unit_id": unit}});
+        assert!(is_error(&gm.handle_tool_call("game_command", &send(4)).await));
+
+        let (mut bridge, _socket) = connect_bridge(&mut gm, "history");
+        let publish = serde_json::json!({
+            "channelId": "game:local-1",
+            "content": [{"type": "text", "text": r#"{"type":"stop","unit_id":5}"#}]
+        });
+        assert_eq!(gm.handle_channels_publish(&publish).await["delivered"], true);
+        gm.handle_tool_call("game_command", &send(6)).await;
+        let sent = bridge.poll_tracked_commands();
+        assert_eq!(sent.iter().map(|(_, command_ref)| *command_ref).collect::<Vec<_>>(), [Some(2), Some(3)]);
+        gm.handle_sai_event(
+            "game:local-1",
+            &sai_ipc::SaiEvent::CommandError {
+                error: "unit 6 does not exist".into(),
+                command: format!("{:?}", SaiCommand::Stop { unit_id: UnitId(6) }),
+                command_ref: Some(3),
+            },
+        )
+        .await;
+
+        let result = gm.handle_tool_call("game_command_history", &history).await;
+        let lines: Vec<&str> = text(&result).lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("#1 tool: {\"type\":\"stop\",\"unit_id\":4} — send_failed ("));
+        assert_eq!(lines[1], "#2 publish: {\"type\":\"stop\",\"unit_id\":5} — sent");
+        assert_eq!(lines[2], "#3 tool: {\"type\":\"stop\",\"unit_id\":6} — rejected (unit 6 does not exist)");
+        let last = serde_json::json!({"channel_id": "game:local-1", "limit": 1});
+        assert!(text(&gm.handle_tool_call("game_command_history", &last).await).starts_with("#3 "));
+    }
+
+    #[tokio::test]
+    async fn test_named_locations() {
+        let (mut gm, _bridge, _socket) = connected_gm("locations");
+        let roster = sai_ipc::SaiEvent::Roster {
+            frame: 30,
+            units: vec![sai_protocol::RosterUnit { unit: UnitId(5), unit_name: None, pos: [1000.0, 0.0, 2000.0] }],
+        };
+        gm.handle_sai_event("game:local-1", &roster).await;
+        let command = |location: serde_json::Value| {
+            serde_json::json!({"channel_id": "game:local-1", "command": {"type": "move", "unit_id": 5, "location": location}})
+        };
+        let result = gm.handle_tool_call("game_command", &command(serde_json::json!({"named": "base"}))).await;
+        assert_eq!(text(&result), "No location named base (known: start)");
+
+        let set = serde_json::json!({"channel_id": "game:local-1", "name": "base", "location": {"offset_from_unit": 5, "dx": -200}});
+        assert_eq!(
+            text(&gm.handle_tool_call("game_set_location", &set).await),
+            "Locations: base (800, 2000), start (1000, 2000)"
+        );
+        let bad = serde_json::json!({"channel_id": "game:local-1", "name": "hill", "x": 5});
+        assert_eq!(text(&gm.handle_tool_call("game_set_location", &bad).await), "Give either location or x and z");
+        let elsewhere = serde_json::json!({"channel_id": "game:local-9", "name": "hill", "x": 5, "z": 5});
+        let result = gm.handle_tool_call("game_set_location", &elsewhere).await;
+        assert!(is_error(&result));
+        assert_eq!(text(&result), "Unknown game channel game:local-9");
+        assert!(!gm.places.contains_key("game:local-9"));
+
+        // Resolved before sending: the history holds the absolute move.
+        gm.handle_tool_call("game_command", &command(serde_json::json!({"named": "base"}))).await;
+        let history = gm.handle_tool_call("game_command_history", &serde_json::json!({"channel_id": "game:local-1"})).await;
+        assert!(text(&history).starts_with("#1 tool: {\"type\":\"move\",\"unit_id\":5,\"x\":800.0,"), "{}", text(&history));
+        let result = gm.handle_tool_call("game_command", &command(serde_json::json!({"at_unit": 99}))).await;
+        assert_eq!(text(&result), "No known position for unit 99");
+    }
+
+    #[tokio::test]
+    async fn test_macros() {
+        let mut gm = test_gm();
+        assert_eq!(text(&gm.handle_tool_call("gm_macro_list", &serde_json::json!({})).await), "No macros defined");
+        let define = serde_json::json!({
+            "name": "regroup",
+            "params": {"unit": "unit", "x": "number", "z": "number"},
+            "commands": [
+                {"type": "stop", "unit_id": "${unit}"},
+                {"type": "move", "unit_id": "${unit}", "x": "${x}", "z": "${z}", "queue": true}
+            ]
+        });
+        assert_eq!(text(&gm.handle_tool_call("gm_macro_define", &define).await), "Defined macro regroup (2 commands)");
+        let mut bad = define.clone();
+        bad["commands"][1]["x"] = "${unit}x".into();
+        let result = gm.handle_tool_call("gm_macro_define", &bad).await;
+        assert!(is_error(&result) && text(&result).starts_with("Command 2 is not a valid command"), "{}", text(&result));
+
+        let run = |params: serde_json::Value| serde_json::json!({"name": "regroup", "channel_id": "game:local-1", "params": params});
+        let result = gm.handle_tool_call("gm_macro_run", &run(serde_json::json!({"unit": 5, "x": 1}))).await;
+        assert_eq!(text(&result), "Macro regroup: Missing parameter z");
+        let result = gm.handle_tool_call("gm_macro_run", &run(serde_json::json!({"unit": 5, "x": "far", "z": 1}))).await;
+        assert_eq!(text(&result), "Macro regroup: Parameter x must be a number, got \"far\"");
+
+        let (_bridge, _socket) = connect_bridge(&mut gm, "macros");
+        let result = gm.handle_tool_call("gm_macro_run", &run(serde_json::json!({"unit": 5, "x": 100, "z": 200.5}))).await;
+        assert!(!is_error(&result) && text(&result).starts_with("Macro regroup sent "), "{}", text(&result));
+        let history = gm.handle_tool_call("game_command_history", &serde_json::json!({"channel_id": "game:local-1"})).await;
+        let lines: Vec<&str> = text(&history).lines().collect();
+        assert_eq!(lines[0], "#1 tool: {\"type\":\"stop\",\"unit_id\":5} — sent");
+        assert!(lines[1].contains("\"x\":100.0") && lines[1].contains("\"z\":200.5"), "{}", lines[1]);
+
+        // Saved in the write dir for the next run.
+        let listed = text(&gm.handle_tool_call("gm_macro_list", &serde_json::json!({})).await).to_string();
+        assert!(listed.contains("\"regroup\"") && listed.contains("${unit}"));
+        assert!(macros::MacroBook::load(&gm.write_dir).get("regroup").is_some());
+    }
+
+    #[tokio::test]
+    async fn test_self_test_pipeline() {
+        let socket = SocketGuard::new("self-test");
+        let mut gm = test_gm();
+        let (loopback, mut client) = self_test::LoopbackClient::new();
+        gm.mcpl = Some(mcpl_link::McplLink::spawn(loopback, &Default::default()));
+
+        let (stages, stub) = gm.self_test_pipeline(&socket, &mut client).await;
+        let names: Vec<&str> = stages.iter().map(|s| s.name).collect();
+        assert_eq!(names, ["sai connect", "event forwarding", "commands", "dry run"]);
+        assert!(stages.iter().all(|s| s.passed()), "{}", self_test::report(&stages).0);
+        assert_eq!(stages[2].line(), "PASS  commands: stop (unit 1) sent, command_finished came back");
+
+        // Nothing listening: the first stage fails and the rest are skipped.
+        stub.unwrap().stop();
+        let mut gm = test_gm();
+        let (stages, stub) = gm.self_test_pipeline("/nonexistent/gm.sock", &mut client).await;
+        assert!(stub.is_none() && stages.len() == 1 && !stages[0].passed());
+    }
+
+    #[tokio::test]
+    async fn test_game_started_after_init_and_roster() {
+        let mut gm = test_gm();
+        let (loopback, mut client) = self_test::LoopbackClient::new();
+        gm.mcpl = Some(mcpl_link::McplLink::spawn(loopback, &Default::default()));
+        let init = sai_ipc::SaiEvent::Init {
+            frame: 0,
+            saved_game: false,
+            protocol_version: Some(sai_ipc::PROTOCOL_VERSION),
+            metal_spots: Some(vec![sai_protocol::MetalSpot { x: 1000.0, y: 0.0, z: 1000.0, metal: 2.0 }]),
+            map_width: Some(512),
+            map_height: Some(512),
+            start_pos: Some([1000.0, 0.0, 900.0]),
+            teams: vec![sai_protocol::TeamSlot {
+                team: sai_protocol::TeamId(0),
+                ally_team: 0,
+                relation: sai_ipc::Relation::Mine,
+            }],
+            build: None,
+        };
+        let roster = sai_ipc::SaiEvent::Roster {
+            frame: 0,
+            units: vec![sai_protocol::RosterUnit {
+                unit: UnitId(5),
+                unit_name: Some("dyntrainer_strike".into()),
+                pos: [1000.0, 10.0, 900.0],
+            }],
+        };
+        gm.handle_sai_event("game:local-1", &init).await;
+        gm.handle_sai_event("game:local-1", &roster).await;
+        // Reconnecting bridges replay the opening; the summary isn't repeated.
+        gm.handle_sai_event("game:local-1", &init).await;
+        gm.handle_sai_event("game:local-1", &roster).await;
+        gm.poll_game_starts(std::time::Instant::now() + game_start::ROSTER_WAIT).await;
+
+        let mut starts = Vec::new();
+        while let Ok(Some(msg)) = tokio::time::timeout(std::time::Duration::from_millis(200), client.recv()).await {
+            if msg["method"] == "channels/incoming" && !msg["params"]["messages"][0]["metadata"]["gameStarted"].is_null() {
+                starts.push(msg["params"]["messages"][0].clone());
+            }
+        }
+        assert_eq!(starts.len(), 1, "{:?}", starts);
+        let started = &starts[0]["metadata"]["gameStarted"];
+        assert_eq!(started["commander"], serde_json::json!({"unit": 5, "defName": "dyntrainer_strike"}));
+        assert_eq!(started["mapSize"], serde_json::json!([4096, 4096]));
+        assert_eq!(started["nearestMetal"][0]["distance"], 100.0);
+        assert!(
+            starts[0]["content"][0]["text"].as_str().unwrap().contains("You are team 0, starting at (1000, 900)"),
+            "{}",
+            starts[0]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_turn_mode_cycle() {
+        let mut gm = test_gm();
+        let meta = serde_json::json!({"turn_mode": true});
+        gm.game_control.insert(
+            "game:local-1".into(),
+            GameControl::from_metadata(Some(&meta)).unwrap(),
+        );
+        let (mut bridge, _socket) = connect_bridge(&mut gm, "turns");
+
+        // Turn mode is switched on once the bridge reports init.
+        let init = sai_ipc::SaiEvent::Init {
+            frame: 0,
+            saved_game: false,
+            protocol_version: Some(sai_ipc::PROTOCOL_VERSION),
+            metal_spots: None,
+            map_width: Some(512),
+            map_height: Some(512),
+            start_pos: None,
+            teams: Vec::new(),
+            build: None,
+        };
+        gm.handle_sai_event("game:local-1", &init).await;
+        assert_eq!(
+            bridge.poll_commands(),
+            vec![SaiCommand::SetTurnMode { enabled: true }]
+        );
+
+        // The turn pause reaches the agent; plain ticks don't.
+        let tick = sai_ipc::SaiEvent::Update { frame: 15, awaiting_commands: false, economy: None, allies: Vec::new(), counters: Default::default(), command_backlog: 0, under_construction: Vec::new(), update_interval: None };
+        gm.handle_sai_event("game:local-1", &tick).await;
+        assert!(!gm.game_control["game:local-1"].paused);
+        let turn = sai_ipc::SaiEvent::Update { frame: 30, awaiting_commands: true, economy: None, allies: Vec::new(), counters: Default::default(), command_backlog: 0, under_construction: Vec::new(), update_interval: None };
+        gm.handle_sai_event("game:local-1", &turn).await;
+        let control = gm.game_control["game:local-1"];
+        assert!(control.paused && control.awaiting_turn);
+        assert_eq!(
+            incoming_text(&gm, "game:local-1", &turn),
+            "Turn at frame 30: game paused, call game_end_turn when done"
+        );
+
+        // The bridge seeing its own pause changes nothing; a player
+        // speeding the game up is tracked.
+        gm.handle_sai_event("game:local-1", &sai_ipc::SaiEvent::GamePaused { by_us: true }).await;
+        assert_eq!(gm.game_control["game:local-1"], control);
+        gm.handle_sai_event("game:local-1", &sai_ipc::SaiEvent::SpeedChanged { speed: 2.0 }).await;
+        assert_eq!(gm.game_control["game:local-1"].speed, 2.0);
+
+        let result = gm
+            .handle_tool_call("game_end_turn", &serde_json::json!({"channel_id": "game:local-1"}))
+            .await;
+        assert_eq!(text(&result), "Game running at speed 2");
+        assert_eq!(bridge.poll_commands(), vec![SaiCommand::EndTurn]);
+        let control = gm.game_control["game:local-1"];
+        assert!(!control.paused && !control.awaiting_turn && control.turn_mode);
+    }
+
+    /// Point a test GameManager at a fake `spring-headless` running `body`.
+    fn fake_engine(gm: &GameManager, body: &str) {
+        use std::os::unix::fs::PermissionsExt;
+        let engine_dir = gm.engines.engine_dir.clone();
+        std::fs::create_dir_all(&engine_dir).unwrap();
+        let bridge_dir = gm.write_dir.join(write_dir::SAI_DATA_DIR);
+        std::fs::create_dir_all(&bridge_dir).unwrap();
+        std::fs::write(bridge_dir.join(write_dir::SAI_LIB), "").unwrap();
+        std::fs::write(bridge_dir.join("AIInfo.lua"), "").unwrap();
+        std::fs::create_dir_all(gm.write_dir.join("LuaUI/Config")).unwrap();
+        std::fs::create_dir_all(gm.write_dir.join("temp")).unwrap();
+        let bin = engine_dir.join("spring-headless");
+        std::fs::write(&bin, format!("#!/bin/sh\n{}\n", body)).unwrap();
+        std::fs::set_permissions(&bin, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    /// Tick until the latest benchmark run is done; its final status.
+    async fn finish_benchmark(gm: &mut GameManager) -> serde_json::Value {
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+        while gm.benchmarks.back().is_some_and(|run| !run.is_done()) {
+            assert!(std::time::Instant::now() < deadline, "benchmark run didn't finish");
+            gm.tick(std::time::Instant::now()).await;
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        let status = gm.handle_tool_call("game_benchmark_status", &serde_json::json!({})).await;
+        assert!(!is_error(&status), "{}", text(&status));
+        status["structuredContent"].clone()
+    }
+
+    #[tokio::test]
+    async fn test_benchmark_runs_games_in_sequence() {
+        let mut gm = test_gm();
+        fake_engine(&gm, "sleep 0.3");
+        let result = gm
+            .handle_tool_call("game_run_benchmark", &serde_json::json!({"map": "Tundra", "runs": 2}))
+            .await;
+        assert!(!is_error(&result), "{}", text(&result));
+        assert_eq!(result["structuredContent"], serde_json::json!({"run": 1, "games": 2}));
+        // The tool returns at once; the games are played from the tick.
+        assert!(gm.engines.instances.is_empty());
+        let busy = gm.handle_tool_call("game_run_benchmark", &serde_json::json!({"map": "Tundra"})).await;
+        assert_eq!(text(&busy), "Benchmark run 1 is still playing; see game_benchmark_status");
+
+        gm.tick(std::time::Instant::now()).await;
+        assert!(gm.engines.instances.contains_key("game:local-1"));
+        let status = gm.handle_tool_call("game_benchmark_status", &serde_json::json!({"run": 1})).await;
+        let status = &status["structuredContent"];
+        assert_eq!((status["state"].as_str(), status["done"].as_u64()), (Some("running"), Some(0)));
+        assert_eq!(status["current"]["channel"], "game:local-1");
+
+        // The benchmark flag reached the bridge config and the startscript.
+        let connection: serde_json::Value = serde_json::from_str(
+            &std::fs::read_to_string(gm.write_dir.join("AI/Skirmish/AgentBridge/0.1/connection.json")).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(connection["benchmark"], true);
+        assert_eq!(connection["aggregate_events"], serde_json::json!(["weapon_fired", "command_finished"]));
+        assert_eq!(connection["update_cadence"]["mode"], "fixed");
+
+        let status = finish_benchmark(&mut gm).await;
+        let report = &status["report"];
+        assert_eq!(report["summary"]["games"], 2);
+        assert_eq!(report["summary"]["unknown"], 2);
+        assert_eq!(report["results"][0]["channel"], "game:local-1");
+        assert_eq!(report["results"][1]["channel"], "game:local-2");
+        let script =
+            std::fs::read_to_string(gm.write_dir.join("temp/gm_script_game_local-2.txt")).unwrap();
+        assert!(script.contains("MinSpeed=100;"));
+
+        // Finished games are cleaned up and the report is on disk.
+        assert!(gm.engines.instances.is_empty());
+        assert!(gm.sai.listeners.is_empty());
+        let saved: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(status["savedTo"].as_str().unwrap()).unwrap()).unwrap();
+        assert_eq!(&saved, report);
+    }
+
+    #[tokio::test]
+    async fn test_benchmark_timeout_and_crash() {
+        let mut gm = test_gm();
+        fake_engine(&gm, "sleep 30");
+        gm.handle_tool_call("game_run_benchmark", &serde_json::json!({"map": "Tundra", "timeout_secs": 0}))
+            .await;
+        let status = finish_benchmark(&mut gm).await;
+        assert_eq!(status["report"]["results"][0]["outcome"], "timeout");
+        assert!(gm.engines.instances.is_empty());
+
+        fake_engine(&gm, "exit 3");
+        let result = gm.handle_tool_call("game_run_benchmark", &serde_json::json!({"map": "Tundra"})).await;
+        assert_eq!(result["structuredContent"]["run"], 2);
+        let status = finish_benchmark(&mut gm).await;
+        assert_eq!(status["run"], 2);
+        assert_eq!(status["report"]["results"][0]["outcome"], "crashed");
+        assert_eq!(status["report"]["results"][0]["error"], "Exit code: Some(3)");
+        // Earlier runs stay listed.
+        let first = gm.handle_tool_call("game_benchmark_status", &serde_json::json!({"run": 1})).await;
+        assert_eq!(first["structuredContent"]["state"], "finished");
+
+        let result = gm.handle_tool_call("game_run_benchmark", &serde_json::json!({})).await;
+        assert!(is_error(&result));
+        let result = gm.handle_tool_call("game_run_benchmark", &serde_json::json!({"map": "Tundra", "runs": 51})).await;
+        assert!(is_error(&result));
+        let result = gm.handle_tool_call("game_benchmark_status", &serde_json::json!({"run": 9})).await;
+        assert!(is_error(&result));
+    }
+
+    #[tokio::test]
+    async fn test_game_profiles() {
+        let mut gm = test_gm();
+        fake_engine(&gm, "sleep 30");
+        gm.handle_channels_open(&serde_json::json!({"address": {"map": "Tundra"}})).await;
+        let bar = serde_json::json!({"address": {"map": "Tundra", "game": "Beyond All Reason test-27451-7f7c9e8"}});
+        gm.handle_channels_open(&bar).await;
+        assert_eq!(gm.engines.instances["game:local-2"].config.opponent_ai.as_deref(), Some("BARb"));
+        let list = gm.handle_channels_list().await;
+        let profiles: Vec<&str> =
+            list["channels"].as_array().unwrap().iter().filter_map(|c| c["metadata"]["profile"].as_str()).collect();
+        assert!(profiles.contains(&"zero-k") && profiles.contains(&"beyond-all-reason"));
+
+        let retreat = |channel: &str| {
+            serde_json::json!({"channel_id": channel, "command": {"type": "retreat", "unit_id": 5, "level": 2}})
+        };
+        gm.handle_tool_call("game_command", &retreat("game:local-1")).await;
+        let history = gm.handle_tool_call("game_command_history", &serde_json::json!({"channel_id": "game:local-1"})).await;
+        assert!(text(&history).contains("\"type\":\"custom\",\"unit_id\":5,\"command_id\":34223"), "{}", text(&history));
+        let result = gm.handle_tool_call("game_command", &retreat("game:local-2")).await;
+        assert_eq!(text(&result), "retreat is not supported by this game profile (beyond-all-reason)");
+        for channel in ["game:local-1", "game:local-2"] {
+            gm.handle_channels_close(&serde_json::json!({"channelId": channel, "force": true})).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_command_policy() {
+        let mut gm = test_gm();
+        let profile = serde_json::json!({
+            "name": "cautious",
+            "games": ["Zero-K *"],
+            "commands": {"retreat": 34223},
+            "policy": {
+                "deny": ["pause"],
+                "per_minute": {"move": 2},
+                "limits": {"attack": {"not_unit_defs": ["dyn*"]}},
+            },
+        });
+        gm.profiles = profiles::Profiles::new(&[serde_json::from_value(profile).unwrap()]);
+        gm.audit = Some(audit::AuditLog::open(&gm.write_dir.join("audit"), Default::default()).unwrap());
+        fake_engine(&gm, "sleep 30");
+        gm.handle_channels_open(&serde_json::json!({"address": {"map": "Tundra"}})).await;
+        let channel = "game:local-1";
+        gm.handle_sai_event(channel, &roster(3)).await;
+        gm.groups.entry(channel.into()).or_default().create("raiders", &[UnitId(2), UnitId(3)], None).unwrap();
+        let list = gm.handle_channels_list().await;
+        assert_eq!(list["channels"][0]["metadata"]["policy"]["deny"], serde_json::json!(["pause"]));
+
+        let command = |command: serde_json::Value| serde_json::json!({"channel_id": channel, "command": command});
+        let result = gm.handle_tool_call("game_command", &command(serde_json::json!({"type": "pause"}))).await;
+        assert!(is_error(&result));
+        assert_eq!(text(&result), "Blocked by the command policy: pause is not allowed");
+        assert_eq!(result["structuredContent"]["rule"], "denied");
+
+        // The commander may not attack; a raider may. The raider's attack is
+        // let through to the bridge, which isn't connected.
+        let attack = |unit: i32| command(serde_json::json!({"type": "attack", "unit_id": unit, "target_id": 900}));
+        let result = gm.handle_tool_call("game_command", &attack(1)).await;
+        assert_eq!(result["structuredContent"]["rule"], "unit_def");
+        assert_eq!(result["structuredContent"]["unit"], 1);
+        assert!(text(&result).ends_with("attack is not allowed for dyntrainer_strike_base (#1)"));
+        let result = gm.handle_tool_call("game_command", &attack(2)).await;
+        assert_eq!(text(&result), "No SAI connection for channel game:local-1");
+
+        // A group's commands are checked after expansion, and a batch over
+        // the rate budget goes out not at all.
+        let publish = |command: serde_json::Value| {
+            serde_json::json!({"channelId": channel, "content": [{"type": "text", "text": command.to_string()}]})
+        };
+        gm.handle_channels_publish(&publish(serde_json::json!({"type": "move", "unit_id": 2, "x": 0.0, "z": 0.0}))).await;
+        let result = gm.handle_channels_publish(&publish(serde_json::json!({"type": "move", "group": "raiders", "x": 0.0, "z": 0.0}))).await;
+        assert_eq!(result["delivered"], false);
+        assert_eq!(result["policy"]["rule"], "rate");
+        assert_eq!(result["error"], "Blocked by the command policy: at most 2 move commands a minute");
+        let history = gm.handle_tool_call("game_command_history", &serde_json::json!({"channel_id": channel})).await;
+        assert_eq!(text(&history).matches("\"move\"").count(), 1, "{}", text(&history));
+
+        let tail = gm.handle_tool_call("gm_audit_tail", &serde_json::json!({"lines": 10})).await;
+        let lines: Vec<serde_json::Value> = text(&tail).lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        let stopped: Vec<(&str, &str)> = lines
+            .iter()
+            .filter(|l| l["kind"] == "policy")
+            .map(|l| (l["name"].as_str().unwrap(), l["details"]["rule"].as_str().unwrap()))
+            .collect();
+        assert_eq!(stopped, [("pause", "denied"), ("attack", "unit_def"), ("move", "rate")]);
+        gm.handle_channels_close(&serde_json::json!({"channelId": channel, "force": true})).await;
+    }
+
+    #[tokio::test]
+    async fn test_games_over_the_limit_queue() {
+        let mut gm = test_gm();
+        gm.engines.max_concurrent_games = 1;
+        fake_engine(&gm, "sleep 30");
+        let open = |map: &str| serde_json::json!({"address": {"map": map}});
+
+        let first = gm.handle_channels_open(&open("Tundra")).await;
+        assert_eq!(first["channel"]["metadata"]["status"], "starting");
+        let second = gm.handle_channels_open(&open("Fields")).await;
+        assert_eq!(second["channel"]["id"], "game:local-2");
+        assert_eq!(second["channel"]["metadata"]["status"], "Queued");
+        assert_eq!(second["channel"]["metadata"]["queuePosition"], 1);
+        gm.handle_channels_open(&open("Comet")).await;
+
+        let list = gm.handle_channels_list().await;
+        let third = list["channels"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|c| c["id"] == "game:local-3")
+            .unwrap();
+        assert_eq!(third["metadata"]["status"], "Queued");
+        assert_eq!(third["metadata"]["queuePosition"], 2);
+
+        let result = gm
+            .handle_tool_call("game_cancel_queued", &serde_json::json!({"channel_id": "game:local-2"}))
+            .await;
+        assert_eq!(text(&result), "Cancelled queued game game:local-2");
+        assert!(!gm.engines.instances.contains_key("game:local-2"));
+        let result = gm
+            .handle_tool_call("game_cancel_queued", &serde_json::json!({"channel_id": "game:local-1"}))
+            .await;
+        assert!(is_error(&result));
+
+        // Nothing launches until the running game is gone.
+        gm.launch_queued_games().await;
+        assert_eq!(gm.engines.instances["game:local-3"].status, engine::GameStatus::Queued);
+        gm.handle_channels_close(&serde_json::json!({"channelId": "game:local-1", "force": true})).await;
+        gm.launch_queued_games().await;
+        assert_eq!(gm.engines.instances["game:local-3"].status, engine::GameStatus::Starting);
+        assert_eq!(gm.engines.queue_position("game:local-3"), None);
+        gm.handle_channels_close(&serde_json::json!({"channelId": "game:local-3", "force": true})).await;
+    }
+
+    #[tokio::test]
+    async fn test_query_tools() {
+        let mut gm = test_gm();
+        fake_engine(&gm, "sleep 30");
+        gm.handle_channels_open(&serde_json::json!({"address": {"map": "Tundra"}})).await;
+        let listed = gm.tools_list();
+        let terrain = listed["tools"].as_array().unwrap().iter().find(|t| t["name"] == "game_query_terrain").unwrap();
+        assert_eq!(terrain["inputSchema"]["required"], serde_json::json!(["channel_id", "x", "z"]));
+
+        let channel = serde_json::json!({"channel_id": "game:local-1"});
+        let result = gm.handle_tool_call("game_query_economy", &channel).await;
+        assert_eq!(text(&result), "No economy update from game:local-1 yet");
+        let economy = sai_protocol::Economy {
+            metal: sai_protocol::ResourceState { current: 120.0, income: 4.5, usage: 2.0, storage: 500.0, received: 0.0 },
+            ..Default::default()
+        };
+        let update = sai_ipc::SaiEvent::Update { frame: 300, awaiting_commands: false, economy: Some(economy), allies: Vec::new(), counters: Default::default(), command_backlog: 0, under_construction: Vec::new(), update_interval: None };
+        gm.handle_sai_event("game:local-1", &update).await;
+        let result = gm.handle_tool_call("game_query_economy", &channel).await;
+        assert_eq!(result["structuredContent"]["frame"], 300);
+        assert_eq!(result["structuredContent"]["metal"]["income"], 4.5);
+        assert_eq!(serde_json::from_str::<serde_json::Value>(text(&result)).unwrap(), result["structuredContent"]);
+        let result = gm.handle_tool_call("game_query_ally_economy", &channel).await;
+        assert_eq!(result["structuredContent"]["allies"], serde_json::json!([]));
+        assert_eq!(result["structuredContent"]["metal_share"], 1.0);
+
+        let result = gm.handle_tool_call("game_query_units", &serde_json::json!({"channel_id": "game:local-1", "unit": 3})).await;
+        assert!(is_error(&result) && text(&result).contains("unknown field `unit`"), "{}", text(&result));
+        let result = gm.handle_tool_call("game_query_map", &channel).await;
+        assert_eq!(text(&result), "No SAI connection for channel game:local-1");
+        gm.handle_channels_close(&serde_json::json!({"channelId": "game:local-1", "force": true})).await;
+    }
+
+    #[tokio::test]
+    async fn test_status_page_document() {
+        let mut gm = test_gm();
+        fake_engine(&gm, "sleep 30");
+        let view = status_page::StatusView::new();
+        gm.status_board = Some(status_page::StatusBoard::new(view.clone(), 5));
+        gm.handle_channels_open(&serde_json::json!({"address": {"map": "Tundra"}})).await;
+        gm.handle_sai_event("game:local-1", &sai_ipc::SaiEvent::UnitIdle { unit: UnitId(7), unit_name: None }).await;
+
+        let now = std::time::Instant::now();
+        gm.tick(now).await;
+        let document = view.current();
+        assert_eq!(document["lobby"]["connected"], false);
+        let channel = &document["channels"][0];
+        assert_eq!((channel["id"].as_str(), channel["map"].as_str()), (Some("game:local-1"), Some("Tundra")));
+        assert_eq!(channel["saiConnected"], false);
+        assert!(channel["engine"]["pid"].is_u64() && channel["engine"]["running"] == true);
+        assert_eq!(channel["recentEvents"].as_array().unwrap().len(), 1);
+
+        // Once gone, a channel leaves the page with the next publish.
+        gm.handle_channels_close(&serde_json::json!({"channelId": "game:local-1", "force": true})).await;
+        gm.tick(now + status_page::PUBLISH_INTERVAL).await;
+        assert_eq!(view.current()["channels"], serde_json::json!([]));
+    }
+
+    #[tokio::test]
+    async fn test_opponents_checked_against_catalog() {
+        let mut gm = test_gm();
+        gm.custom_opponents = vec![opponents::Opponent {
+            name: "MyAI".into(),
+            difficulty: Some("hard".into()),
+            games: vec!["Zero-K".into()],
+            description: None,
+            installed: None,
+        }];
+        for ai in ["CircuitAINovice", "CircuitAIHard", "MyAI"] {
+            std::fs::create_dir_all(gm.engines.engine_dir.join("AI/Skirmish").join(ai)).unwrap();
+        }
+
+        let result = gm
+            .handle_tool_call("game_list_opponents", &serde_json::json!({"game": "Zero-K v1.12.1.0"}))
+            .await;
+        let list: Vec<serde_json::Value> = serde_json::from_str(text(&result)).unwrap();
+        assert!(list.iter().all(|o| o["name"] != "BARb"));
+        let mine = list.iter().find(|o| o["name"] == "MyAI").unwrap();
+        assert_eq!((mine["difficulty"].as_str(), mine["installed"].as_bool()), (Some("hard"), Some(true)));
+        let easy = list.iter().find(|o| o["name"] == "CircuitAIEasy").unwrap();
+        assert_eq!(easy["installed"], false);
+
+        let result = gm
+            .handle_tool_call("lobby_start_game", &serde_json::json!({"map": "Tundra", "opponent": "CircuitAIHrad"}))
+            .await;
+        assert!(is_error(&result));
+        assert_eq!(text(&result), "Unknown opponent 'CircuitAIHrad'; did you mean CircuitAIHard?");
+        let result = gm
+            .handle_channels_open(&serde_json::json!({"address": {"map": "Tundra", "opponent": "CircuitAIEasy"}}))
+            .await;
+        assert_eq!(result["error"]["code"], -32602);
+        assert!(result["error"]["message"].as_str().unwrap().starts_with("Opponent CircuitAIEasy is not installed"));
+        assert!(gm.engines.instances.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_games_outlive_an_absent_client() {
+        let mut gm = test_gm();
+        fake_engine(&gm, "sleep 30");
+        let since = std::time::Instant::now();
+        let linger = std::time::Duration::from_secs(600);
+        assert_eq!(gm.absent_client_exit(since, linger).as_deref(), Some("no games left"));
+
+        gm.handle_channels_open(&serde_json::json!({"address": {"map": "Tundra"}})).await;
+        assert_eq!(gm.absent_client_exit(since, linger), None);
+        assert_eq!(
+            gm.absent_client_exit(since, std::time::Duration::ZERO).as_deref(),
+            Some("games still running after 0ns")
+        );
+        gm.handle_channels_close(&serde_json::json!({"channelId": "game:local-1", "force": true})).await;
+        assert_eq!(gm.absent_client_exit(since, linger).as_deref(), Some("no games left"));
+    }
+
+    /// The next message to the MCPL client with this method.
+    async fn next_mcpl(client: &mut tokio::sync::mpsc::UnboundedReceiver<serde_json::Value>, method: &str) -> serde_json::Value {
+        loop {
+            let msg = tokio::time::timeout(std::time::Duration::from_secs(5), client.recv())
+                .await
+                .unwrap_or_else(|_| panic!("no {}", method))
+                .unwrap();
+            if msg["method"] == method {
+                return msg;
+            }
+        }
+    }
+
+    /// Wait for a fake engine to signal (by creating `ready` in the write
+    /// dir) that its signal handling is set up.
+    async fn wait_ready(gm: &GameManager) {
+        let ready = gm.write_dir.join("ready");
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        while !ready.exists() {
+            assert!(std::time::Instant::now() < deadline, "fake engine never started");
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        std::fs::remove_file(&ready).unwrap();
+    }
+
+    /// Run the engine checks until the engine of a closing channel exits
+    /// and the close is finished.
+    async fn finish_close(gm: &mut GameManager, channel_id: &str) {
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        while gm.closing.contains_key(channel_id) {
+            assert!(std::time::Instant::now() < deadline, "{} never finished closing", channel_id);
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            gm.check_engines().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_close_waits_for_final_events() {
+        let mut gm = test_gm();
+        let (loopback, mut client) = self_test::LoopbackClient::new();
+        gm.mcpl = Some(mcpl_link::McplLink::spawn(loopback, &Default::default()));
+        // Asked to quit, the engine finishes its demo and exits.
+        fake_engine(
+            &gm,
+            r#"trap 'mkdir -p "$2/demos"; : > "$2/demos/game.sdfz"; exit 0' TERM; : > "$2/ready"; while :; do sleep 0.05; done"#,
+        );
+        gm.handle_channels_open(&serde_json::json!({"address": {"map": "Tundra"}})).await;
+        wait_ready(&gm).await;
+        let socket = gm.engines.instances["game:local-1"].config.socket_path.clone();
+        let mut bridge = sai_protocol::IpcClient::connect(&socket).unwrap();
+        gm.sai.accept_pending();
+
+        let close = serde_json::json!({"channelId": "game:local-1"});
+        assert_eq!(gm.handle_channels_close(&close).await, serde_json::json!({"closed": true, "pending": true}));
+        assert_eq!(gm.handle_channels_close(&close).await, serde_json::json!({"closed": true, "pending": true}));
+        // The channel stays up until the engine is gone.
+        gm.poll_closing(std::time::Instant::now()).await;
+        assert!(gm.sai.connections.contains_key("game:local-1") && gm.closing.contains_key("game:local-1"));
+
+        let update = sai_ipc::SaiEvent::Update { frame: 900, awaiting_commands: false, economy: None, allies: Vec::new(), counters: Default::default(), command_backlog: 0, under_construction: Vec::new(), update_interval: None };
+        let stats = sai_protocol::BridgeStats { frames: 900, events_sent: 2, events_dropped: 0, panics: 0 };
+        for event in [update, sai_ipc::SaiEvent::Release { reason: 1, stats: Some(stats) }] {
+            bridge.send_event(&event).unwrap();
+        }
+        finish_close(&mut gm, "game:local-1").await;
+
+        let release = next_mcpl(&mut client, "channels/incoming").await;
+        assert_eq!(release["params"]["messages"][0]["metadata"]["event"]["type"], "release");
+        let changed = next_mcpl(&mut client, "channels/changed").await;
+        assert_eq!(changed["params"]["removed"], serde_json::json!(["game:local-1"]));
+        let metadata = &changed["params"]["updated"][0]["metadata"];
+        assert_eq!(metadata["status"], "ended");
+        assert_eq!(metadata["outcome"], "win");
+        assert_eq!(metadata["lastFrame"], 900);
+        assert_eq!(metadata["bridge"]["eventsSent"], 2);
+        assert!(metadata["demoPath"].as_str().unwrap().ends_with("demos/game.sdfz"), "{}", metadata);
+        assert!(gm.engines.instances.is_empty() && gm.sai.connections.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_close_grace_period_and_force() {
+        let mut gm = test_gm();
+        // This engine ignores the request to quit.
+        fake_engine(&gm, r#"trap '' TERM; : > "$2/ready"; while :; do sleep 0.05; done"#);
+        gm.handle_channels_open(&serde_json::json!({"address": {"map": "Tundra"}})).await;
+        wait_ready(&gm).await;
+        gm.handle_channels_open(&serde_json::json!({"address": {"map": "Tundra"}})).await;
+        wait_ready(&gm).await;
+
+        let close = |id: &str| serde_json::json!({"channelId": id});
+        assert_eq!(gm.handle_channels_close(&close("game:local-1")).await["pending"], true);
+        // Crash detection leaves a closing channel alone...
+        gm.check_engines().await;
+        assert!(gm.closing.contains_key("game:local-1"));
+        // ...until the grace period is over and the engine is killed.
+        gm.poll_closing(std::time::Instant::now() + closing::GRACE_PERIOD).await;
+        assert!(gm.closing.is_empty());
+        assert!(!gm.engines.instances.contains_key("game:local-1"));
+
+        // force: torn down on the spot, even while closing.
+        assert_eq!(gm.handle_channels_close(&close("game:local-2")).await["pending"], true);
+        let force = serde_json::json!({"channelId": "game:local-2", "force": true});
+        assert_eq!(gm.handle_channels_close(&force).await, serde_json::json!({"closed": true}));
+        assert!(gm.closing.is_empty() && gm.engines.instances.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_coop_agents_get_sub_channels() {
+        let mut gm = test_gm();
+        fake_engine(&gm, "sleep 30");
+        let open = |coop: u64| serde_json::json!({"address": {"map": "Tundra", "coop_agents": coop}});
+        let result = gm.handle_channels_open(&open(4)).await;
+        assert_eq!(result["error"]["code"], -32602);
+
+        let result = gm.handle_channels_open(&open(1)).await;
+        assert_eq!(result["channel"]["metadata"]["coopChannels"], serde_json::json!(["game:local-1/team2"]));
+        let connection: serde_json::Value = serde_json::from_str(
+            &std::fs::read_to_string(gm.write_dir.join("AI/Skirmish/AgentBridge/0.1/connection.json")).unwrap(),
+        )
+        .unwrap();
+        let coop_socket = connection["socket_paths"]["team:2"].as_str().unwrap();
+        assert_eq!(connection["socket_paths"]["team:0"], connection["socket_path"]);
+
+        let mut ally = sai_protocol::IpcClient::connect(coop_socket).unwrap();
+        assert_eq!(gm.sai.accept_pending(), ["game:local-1/team2".to_string()]);
+        ally.send_event(&sai_ipc::SaiEvent::UnitIdle { unit: UnitId(2), unit_name: None }).unwrap();
+        for event in gm.sai.drain_events("game:local-1/team2").await {
+            gm.handle_sai_event("game:local-1/team2", &event).await;
+        }
+        let list = gm.handle_channels_list().await;
+        let coop = &list["channels"][1];
+        assert_eq!((coop["id"].as_str(), coop["metadata"]["team"].as_i64()), (Some("game:local-1/team2"), Some(2)));
+        assert_eq!(coop["metadata"]["saiConnected"], true);
+
+        let force = serde_json::json!({"channelId": "game:local-1", "force": true});
+        assert_eq!(gm.handle_channels_close(&force).await, serde_json::json!({"closed": true}));
+        assert!(gm.sai.listeners.is_empty() && gm.sai.connections.is_empty());
+        assert!(!gm.observers.contains_key("game:local-1/team2"));
+    }
+
+    #[tokio::test]
+    async fn test_self_play_gets_opponent_channel() {
+        let mut gm = test_gm();
+        fake_engine(&gm, "sleep 30");
+        let open = |address: serde_json::Value| serde_json::json!({"address": address});
+        let result = gm.handle_channels_open(&open(serde_json::json!({"map": "Tundra", "opponent": "AgentBridge", "player_mode": true}))).await;
+        assert_eq!(result["error"]["message"], "Self-play (opponent AgentBridge) needs AI mode (not player_mode)");
+
+        let result = gm.handle_channels_open(&open(serde_json::json!({"map": "Tundra", "opponent": "AgentBridge"}))).await;
+        assert_eq!(result["channel"]["metadata"]["opponentChannel"], "game:local-1/team1");
+        assert!(result["channel"]["metadata"].get("coopChannels").is_none());
+        let connection: serde_json::Value = serde_json::from_str(
+            &std::fs::read_to_string(gm.write_dir.join("AI/Skirmish/AgentBridge/0.1/connection.json")).unwrap(),
+        )
+        .unwrap();
+        let mut opponent = sai_protocol::IpcClient::connect(connection["socket_paths"]["team:1"].as_str().unwrap()).unwrap();
+        assert_eq!(gm.sai.accept_pending(), ["game:local-1/team1".to_string()]);
+
+        let list = gm.handle_channels_list().await;
+        let side = &list["channels"][1]["metadata"];
+        assert_eq!((side["side"].as_str(), side["team"].as_i64(), side["allyTeam"].as_i64()), (Some("opponent"), Some(1), Some(1)));
+        assert_eq!(side["saiConnected"], true);
+
+        // The opponent's side loses: its summary names the other ally team.
+        opponent.send_event(&sai_ipc::SaiEvent::Release { reason: 2, stats: None }).unwrap();
+        for event in gm.sai.drain_events("game:local-1/team1").await {
+            gm.handle_sai_event("game:local-1/team1", &event).await;
+        }
+        gm.finish_session("game:local-1/team1", &engine::GameStatus::Ended);
+        let (channel, summary) = gm.summaries.back().unwrap();
+        assert_eq!(channel, "game:local-1/team1");
+        assert_eq!((summary["outcome"].as_str(), summary["team"].as_i64()), (Some("loss"), Some(1)));
+        assert_eq!(summary["winningAllyTeam"], 0);
+        let force = serde_json::json!({"channelId": "game:local-1", "force": true});
+        assert_eq!(gm.handle_channels_close(&force).await, serde_json::json!({"closed": true}));
+        assert!(gm.sai.listeners.is_empty() && gm.sai.connections.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_rematch_on_chosen_channel_id() {
+        let mut gm = test_gm();
+        fake_engine(&gm, "sleep 30");
+        let open = |id: &str, metadata: serde_json::Value| {
+            serde_json::json!({"address": {"map": "Tundra", "channel_id": id}, "metadata": metadata})
+        };
+        let result = gm.handle_channels_open(&open("game:local-9", serde_json::json!({}))).await;
+        assert_eq!(result["error"]["message"], "channel_id 'game:local-9': names starting with 'local-' are reserved");
+        let result = gm.handle_channels_open(&open("practice", serde_json::json!({}))).await;
+        assert_eq!(result["error"]["code"], -32602);
+
+        let result = gm.handle_channels_open(&open("game:practice", serde_json::json!({"verbosity": "terse"}))).await;
+        assert_eq!(result["channel"]["id"], "game:practice");
+        gm.groups.entry("game:practice".into()).or_default().create("raiders", &[UnitId(4), UnitId(5)], Some(2)).unwrap();
+        let result = gm.handle_channels_open(&open("game:practice", serde_json::json!({}))).await;
+        assert_eq!(
+            result["error"]["message"],
+            "game:practice is still running; close it or choose another channel_id"
+        );
+
+        // An ended game gives way without a close; its groups come along
+        // empty, its verbosity as it was.
+        gm.engines.instances.get_mut("game:practice").unwrap().stop().await;
+        let result = gm.handle_channels_open(&open("game:practice", serde_json::json!({"preserve_state": true}))).await;
+        assert_eq!(result["channel"]["metadata"]["preservedState"], true);
+        assert_eq!(gm.verbosity["game:practice"], Verbosity::Terse);
+        let raiders = gm.groups["game:practice"].get("raiders").unwrap();
+        assert!(raiders.members.is_empty() && raiders.alert_below == Some(2));
+
+        // Closed, then reopened without preserve_state: a clean slate.
+        let force = serde_json::json!({"channelId": "game:practice", "force": true});
+        assert_eq!(gm.handle_channels_close(&force).await, serde_json::json!({"closed": true}));
+        let result = gm.handle_channels_open(&open("game:practice", serde_json::json!({}))).await;
+        assert_eq!(result["channel"]["metadata"].get("preservedState"), None);
+        assert_eq!(gm.verbosity["game:practice"], Verbosity::Normal);
+        assert!(gm.groups["game:practice"].get("raiders").is_none());
+        assert!(gm.kept_state.is_empty());
+        gm.handle_channels_close(&force).await;
+    }
+
+    #[tokio::test]
+    async fn test_economy_alert_thresholds_per_channel() {
+        let mut gm = test_gm();
+        fake_engine(&gm, "sleep 30");
+        let open = |alerts: serde_json::Value| {
+            serde_json::json!({"address": {"map": "Tundra"}, "metadata": {"economy_alerts": alerts}})
+        };
+
+        let result = gm.handle_channels_open(&open(serde_json::json!({"metal_excess_fraction": 1.5}))).await;
+        assert_eq!(result["error"]["code"], -32602);
+        assert_eq!(result["error"]["message"], "economy_alerts.metal_excess_fraction must be between 0 and 1");
+        assert!(gm.engines.instances.is_empty());
+
+        gm.handle_channels_open(&open(serde_json::json!({"cooldown_minutes": 5}))).await;
+        gm.handle_channels_open(&open(serde_json::json!(false))).await;
+        assert_eq!(gm.economy_alerts["game:local-1"].thresholds.cooldown_minutes, 5.0);
+        assert!(!gm.economy_alerts["game:local-2"].thresholds.enabled);
+
+        // Lobby games never pass through channels/open and get the defaults.
+        let update = sai_ipc::SaiEvent::Update {
+            frame: 30,
+            awaiting_commands: false,
+            economy: Some(sai_protocol::Economy::default()),
+            allies: Vec::new(),
+            counters: Default::default(),
+            command_backlog: 0,
+            under_construction: Vec::new(),
+            update_interval: None,
+        };
+        let config = gm.engines.instances["game:local-1"].config.clone();
+        gm.engines.instances.insert("game:mp-1".into(), engine::EngineInstance::new("game:mp-1".into(), config));
+        gm.handle_sai_event("game:mp-1", &update).await;
+        assert!(gm.economy_alerts["game:mp-1"].thresholds.enabled);
+        // Channels that aren't one of our games get no watch.
+        gm.handle_sai_event("game:gone-1", &update).await;
+        assert!(!gm.economy_alerts.contains_key("game:gone-1"));
+        gm.engines.instances.remove("game:mp-1");
+
+        for id in ["game:local-1", "game:local-2"] {
+            gm.handle_channels_close(&serde_json::json!({"channelId": id, "force": true})).await;
+            assert!(!gm.economy_alerts.contains_key(id));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_idle_builder_settings_per_channel() {
+        let mut gm = test_gm();
+        fake_engine(&gm, "sleep 30");
+        let open = |idle: serde_json::Value| {
+            serde_json::json!({"address": {"map": "Tundra"}, "metadata": {"idle_builders": idle}})
+        };
+
+        let result = gm.handle_channels_open(&open(serde_json::json!("often"))).await;
+        assert_eq!(result["error"]["message"], "idle_builders must be a boolean or an object");
+        assert!(gm.engines.instances.is_empty());
+
+        gm.handle_channels_open(&open(serde_json::json!({"grace_secs": 20}))).await;
+        gm.handle_channels_open(&open(serde_json::json!(false))).await;
+        assert_eq!(gm.idle_builders["game:local-1"].settings.grace_secs, 20.0);
+        assert!(!gm.idle_builders["game:local-2"].settings.enabled);
+
+        let idle = sai_ipc::SaiEvent::UnitIdle { unit: UnitId(5), unit_name: Some("cloakcon".into()) };
+        gm.handle_sai_event("game:mp-1", &idle).await;
+        assert!(gm.idle_builders["game:mp-1"].settings.enabled);
+
+        for id in ["game:local-1", "game:local-2"] {
+            gm.handle_channels_close(&serde_json::json!({"channelId": id, "force": true})).await;
+            assert!(!gm.idle_builders.contains_key(id));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_channels_replay() {
+        let mut gm = test_gm();
+        fake_engine(&gm, "sleep 30");
+        gm.handle_channels_open(&serde_json::json!({"address": {"map": "Tundra"}})).await;
+        gm.replay_size = 3;
+        // Without a client the messages are still numbered and kept.
+        for i in 1..=5 {
+            let notice = gm.notice_message("game:local-1", format!("notice {}", i), serde_json::json!({}));
+            gm.push_incoming(notice).await;
+        }
+        let list = gm.on_mcpl_request("channels/list", &serde_json::json!({})).await;
+        assert_eq!(list["channels"][0]["metadata"]["latestSeq"], 5);
+
+        let replay = gm.on_mcpl_request("channels/replay", &serde_json::json!({"channelId": "game:local-1", "fromSeq": 2})).await;
+        let texts: Vec<&str> = replay["messages"].as_array().unwrap().iter().map(|m| m["content"][0]["text"].as_str().unwrap()).collect();
+        assert_eq!(texts, ["notice 3", "notice 4", "notice 5"]);
+        assert_eq!(replay["messages"][0]["metadata"]["seq"], 3);
+        assert_eq!(replay["evicted"], serde_json::json!({"fromSeq": 2, "toSeq": 2}));
+        assert_eq!(replay["latestSeq"], 5);
+
+        let later = gm.on_mcpl_request("channels/replay", &serde_json::json!({"channelId": "game:local-1", "fromSeq": 6})).await;
+        assert_eq!((later["messages"].as_array().unwrap().len(), &later["evicted"]), (0, &serde_json::Value::Null));
+        let missing = gm.on_mcpl_request("channels/replay", &serde_json::json!({})).await;
+        assert_eq!(missing["error"], "Missing channelId");
+    }
+
+    #[tokio::test]
+    async fn test_unit_history() {
+        let mut gm = test_gm();
+        fake_engine(&gm, "sleep 30");
+        gm.handle_channels_open(&serde_json::json!({"address": {"map": "Tundra"}})).await;
+        let update = |frame| sai_ipc::SaiEvent::Update { frame, awaiting_commands: false, economy: None, allies: Vec::new(), counters: Default::default(), command_backlog: 0, under_construction: Vec::new(), update_interval: None };
+        let seen = |enemy: i32, x: f32| sai_ipc::SaiEvent::EnemyEnterLos {
+            enemy: UnitId(enemy),
+            enemy_name: Some("cloakraid".into()),
+            team: None,
+            relation: None,
+            pos: Some([x, 0.0, 1000.0]),
+        };
+        for (frame, x) in [(30, 3000.0), (60, 2910.0), (90, 2820.0)] {
+            gm.handle_sai_event("game:local-1", &update(frame)).await;
+            gm.handle_sai_event("game:local-1", &seen(501, x)).await;
+            gm.handle_sai_event("game:local-1", &seen(502, x + 50.0)).await;
+        }
+        gm.handle_sai_event("game:local-1", &update(120)).await;
+
+        let args = serde_json::json!({"channel_id": "game:local-1", "unit_id": 501});
+        let result = gm.handle_tool_call("game_unit_history", &args).await;
+        assert!(!is_error(&result));
+        assert_eq!(
+            text(&result),
+            "cloakraid #501 (enemy), last 3 positions:\nframe 30: near (3000, 1000)\nframe 60: near (2910, 1000)\nframe 90: near (2820, 1000)\nmoving west at ~90 elmos/s"
+        );
+        assert_eq!(result["structuredContent"]["track"]["samples"][2]["pos"], serde_json::json!([2820.0, 1000.0]));
+        assert_eq!(result["structuredContent"]["motion"]["heading"], "west");
+
+        // The raiders' threat says where they're headed.
+        let list = gm.handle_channels_list().await;
+        assert_eq!(list["channels"][0]["metadata"]["threats"][0]["motion"]["heading"], "west");
+
+        let unknown = serde_json::json!({"channel_id": "game:local-1", "unit_id": 77});
+        assert_eq!(text(&gm.handle_tool_call("game_unit_history", &unknown).await), "No positions known for unit #77 on game:local-1");
+    }
+
+    #[tokio::test]
+    async fn test_game_factories() {
+        let mut gm = test_gm();
+        fake_engine(&gm, "sleep 30");
+        gm.handle_channels_open(&serde_json::json!({"address": {"map": "Tundra"}})).await;
+        let args = serde_json::json!({"channel_id": "game:local-1"});
+        assert_eq!(text(&gm.handle_tool_call("game_factories", &args).await), "No factories known on game:local-1");
+
+        let events = [
+            sai_ipc::SaiEvent::UnitFinished { unit: UnitId(10), unit_name: Some("factorycloak".into()), pos: Some([1000.0, 0.0, 1000.0]) },
+            sai_ipc::SaiEvent::UnitCreated { unit: UnitId(20), unit_name: Some("cloakraid".into()), builder: UnitId(10), builder_name: None, pos: None },
+            sai_ipc::SaiEvent::Update {
+                frame: factories::QUERY_FRAMES,
+                awaiting_commands: false,
+                economy: None,
+                allies: Vec::new(),
+                counters: Default::default(),
+                command_backlog: 0,
+                under_construction: vec![sai_protocol::Construction { unit: UnitId(20), unit_name: None, progress: 40.0, builders: 1 }],
+                update_interval: None,
+            },
+            // The bridge's answer to the queue query.
+            sai_ipc::SaiEvent::UnitQueues {
+                request_id: 1,
+                queues: vec![sai_protocol::UnitQueue {
+                    unit: UnitId(10),
+                    commands: [2, 2, 3].iter().map(|d| sai_protocol::QueuedCommand { id: -d, params: Vec::new() }).collect(),
+                }],
+            },
+        ];
+        for event in &events {
+            gm.handle_sai_event("game:local-1", event).await;
+        }
+        let result = gm.handle_tool_call("game_factories", &args).await;
+        assert!(!is_error(&result));
+        // Without the catalog, defs go by name or id and times are unknown.
+        assert_eq!(text(&result), "factorycloak: building cloakraid (40%), 3 more queued");
+        assert_eq!(result["structuredContent"]["factories"][0]["progress"], 40.0);
+        assert_eq!(result["structuredContent"]["factories"][0]["queued"][2]["unitName"], "def #3");
+        assert!(is_error(&gm.handle_tool_call("game_factories", &serde_json::json!({})).await));
+    }
+
+    #[tokio::test]
+    async fn test_threats_listed_with_channel() {
+        let mut gm = test_gm();
+        fake_engine(&gm, "sleep 30");
+        gm.handle_channels_open(&serde_json::json!({"address": {"map": "Tundra"}})).await;
+        let hit = |unit: i32, attacker: i32| sai_ipc::SaiEvent::UnitDamaged {
+            unit: UnitId(unit),
+            unit_name: Some("cloakriot".into()),
+            attacker: UnitId(attacker),
+            attacker_name: Some("vehraid".into()),
+            attacker_team: None,
+            attacker_relation: None,
+            damage: 60.0,
+            weapon_def_id: WeaponDefId(4),
+            paralyzer: false,
+            paralysis: None,
+            pos: Some([2000.0, 40.0, 1500.0]),
+        };
+        for event in [hit(10, 501), hit(11, 502)] {
+            gm.handle_sai_event("game:local-1", &event).await;
+        }
+        let update = |frame| sai_ipc::SaiEvent::Update { frame, awaiting_commands: false, economy: None, allies: Vec::new(), counters: Default::default(), command_backlog: 0, under_construction: Vec::new(), update_interval: None };
+        gm.handle_sai_event("game:local-1", &update(30)).await;
+
+        let list = gm.handle_channels_list().await;
+        let threats = &list["channels"][0]["metadata"]["threats"];
+        assert_eq!(threats[0]["id"], "threat-1");
+        assert_eq!(threats[0]["composition"]["vehraid"], 2);
+        assert_eq!(threats[0]["underFire"][1]["unit"], 11);
+
+        gm.handle_sai_event("game:local-1", &update(30 + threats::THREAT_WINDOW_FRAMES + 30)).await;
+        let list = gm.handle_channels_list().await;
+        assert_eq!(list["channels"][0]["metadata"]["threats"], serde_json::json!([]));
+        gm.handle_channels_close(&serde_json::json!({"channelId": "game:local-1", "force": true})).await;
+    }
+
+    #[tokio::test]
+    async fn test_stream_lines_per_channel() {
+        let mut gm = test_gm();
+        fake_engine(&gm, "sleep 30");
+        let open = |stream: serde_json::Value| {
+            serde_json::json!({"address": {"map": "Tundra"}, "metadata": {"stream": stream}})
+        };
+        let result = gm.handle_channels_open(&open(serde_json::json!({"interval_secs": 0}))).await;
+        assert_eq!(result["error"]["message"], "stream.interval_secs must be a number of at least 1");
+
+        gm.handle_channels_open(&open(serde_json::json!({"include": ["units", "threats"]}))).await;
+        gm.handle_channels_open(&open(serde_json::json!(false))).await;
+        let unit = |unit| sai_protocol::RosterUnit { unit, unit_name: None, pos: [0.0; 3] };
+        let roster = sai_ipc::SaiEvent::Roster { frame: 5, units: vec![unit(UnitId(1)), unit(UnitId(2))] };
+        for id in ["game:local-1", "game:local-2"] {
+            gm.handle_sai_event(id, &roster).await;
+        }
+
+        let now = std::time::Instant::now();
+        let lines = gm.stream_lines(now);
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0]["channelId"], "game:local-1");
+        assert_eq!(lines[0]["content"][0]["text"], "2 units, 0 enemies in sight | no threats");
+        assert_eq!(lines[0]["state"]["units"], 2);
+        assert!(gm.stream_lines(now).is_empty(), "next line waits for the interval");
+
+        for id in ["game:local-1", "game:local-2"] {
+            gm.handle_channels_close(&serde_json::json!({"channelId": id, "force": true})).await;
+        }
+        assert!(gm.observers.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_scope_enforced() {
+        let mut gm = test_gm();
+        let config: config::GmConfig = serde_json::from_value(serde_json::json!({
+            "scopes": {"game-only": {"tools": ["game_*"], "channels": ["list"]}},
+        }))
+        .unwrap();
+        gm.scope = scope::Scope::negotiate(Some("game-only"), &config.scopes, None);
+
+        let result = gm
+            .handle_tool_call("lobby_say", &serde_json::json!({"target": "zk", "message": "hi"}))
+            .await;
+        assert_eq!(result["error"]["code"], scope::FORBIDDEN_BY_SCOPE);
+        assert_eq!(result["error"]["message"], "Tool lobby_say is forbidden by scope 'game-only'");
+        // Allowed tools run as usual.
+        let result = gm.handle_tool_call("game_pause", &serde_json::json!({"channel_id": "game:local-9"})).await;
+        assert_eq!(text(&result), "No SAI connection for channel game:local-9");
+
+        let publish = serde_json::json!({
+            "channelId": "game:local-1",
+            "content": [{"type": "text", "text": "{\"type\": \"pause\"}"}],
+        });
+        let result = gm.handle_channels_publish(&publish).await;
+        assert_eq!(result["error"]["code"], scope::FORBIDDEN_BY_SCOPE);
+        assert_eq!(result["error"]["message"], "channels/publish is forbidden by scope 'game-only'");
+        assert!(gm.handle_channels_list().await.get("channels").is_some());
+
+        let tools = gm.tools_list();
+        let names: Vec<&str> = tools["tools"].as_array().unwrap().iter().filter_map(|t| t["name"].as_str()).collect();
+        assert!(names.contains(&"game_pause"));
+        assert!(names.iter().all(|n| n.starts_with("game_")));
+
+        gm.scope = None;
+        assert!(gm.tools_list()["tools"].as_array().unwrap().len() > names.len());
+    }
+
+    #[test]
+    fn test_game_summary_after_game_ends() {
+        let mut gm = test_gm();
+        gm.summary_retain = 1;
+        let events: Vec<sai_ipc::SaiEvent> =
+            include_str!("../tests/fixtures/sessions/short_game.jsonl")
+                .lines()
+                .map(|l| sai_ipc::SaiEvent::from_line(l).unwrap())
+                .collect();
+        for event in &events {
+            gm.record_sai_event("game:local-1", event);
+        }
+        gm.finish_session("game:local-1", &engine::GameStatus::Ended);
+
+        let result = gm.tool_game_summary(&serde_json::json!({"channel_id": "game:local-1"}));
+        assert!(!is_error(&result), "{}", text(&result));
+        let summary: serde_json::Value = serde_json::from_str(text(&result)).unwrap();
+        assert_eq!(summary["outcome"], "loss");
+        assert_eq!(summary["unitsLost"]["cloakraid"], 1);
+        // Written next to the session log, which holds every event after
+        // its header.
+        let log = std::path::Path::new(summary["sessionLog"].as_str().unwrap());
+        assert_eq!(recording::read_session(log).unwrap()[1..], events);
+        let saved: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(log.with_extension("summary.json")).unwrap())
+                .unwrap();
+        assert_eq!(saved, summary);
+
+        // A game the GameManager stopped gets no summary.
+        gm.record_sai_event("game:local-2", &events[0]);
+        gm.finish_session("game:local-2", &engine::GameStatus::Stopped);
+        let result = gm.tool_game_summary(&serde_json::json!({"channel_id": "game:local-2"}));
+        assert!(is_error(&result));
+
+        // Only the most recent summaries are kept.
+        gm.record_sai_event("game:local-3", &events[0]);
+        gm.finish_session("game:local-3", &engine::GameStatus::Ended);
+        let result = gm.tool_game_summary(&serde_json::json!({"channel_id": "game:local-1"}));
+        assert_eq!(
+            text(&result),
+            "No summary for game:local-1 (summaries are kept for the last 1 games that ended)"
+        );
+        assert!(!is_error(&gm.tool_game_summary(&serde_json::json!({"channel_id": "game:local-3"}))));
+    }
+
+    // ── Over a real MCPL connection ──
+
+    #[tokio::test]
+    async fn test_mcpl_tool_routing() {
+        let mut gm = test_gm();
+        let mut client = TestClient::attach(&mut gm, &config::GmConfig::default());
+
+        let tools = client.call(&mut gm, "tools/list", serde_json::json!({})).await;
+        let names: Vec<&str> = tools["tools"].as_array().unwrap().iter().filter_map(|t| t["name"].as_str()).collect();
+        assert!(names.contains(&"lobby_status") && names.contains(&"game_command"), "{:?}", names);
+
+        let call = serde_json::json!({"name": "lobby_status", "arguments": {}});
+        let result = client.call(&mut gm, "tools/call", call).await;
+        assert!(!is_error(&result));
+        let status: serde_json::Value = serde_json::from_str(text(&result)).unwrap();
+        assert_eq!(status["connected"], false);
+        let call = serde_json::json!({"name": "game_group_create", "arguments": {"channel_id": "game:local-1"}});
+        assert_eq!(text(&client.call(&mut gm, "tools/call", call).await), "Unknown game channel game:local-1");
+        let result = client.call(&mut gm, "tools/call", serde_json::json!({"name": "no_such_tool"})).await;
+        assert!(is_error(&result) && text(&result) == "Unknown tool: no_such_tool");
+
+        // Notifications are taken without an answer.
+        client.notify(&mut gm, "featureSets/update", None).await;
+        let result = client.call(&mut gm, "channels/list", serde_json::json!({})).await;
+        assert_eq!(result["channels"], serde_json::json!([]));
+        assert_eq!(client.unread.len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_mcpl_scope_from_handshake() {
+        let mut gm = test_gm();
+        let mut config = config::GmConfig::default();
+        config.scopes.insert("game".into(), serde_json::from_value(serde_json::json!({"tools": ["game_*"]})).unwrap());
+        let mcpl = serde_json::json!({"channels": true, "scopedAccess": {"scope": "game"}});
+        let mut client = TestClient::attach_with(&mut gm, &config, mcpl);
+
+        let tools = client.call(&mut gm, "tools/list", serde_json::json!({})).await;
+        assert!(tools["tools"].as_array().unwrap().iter().all(|t| t["name"].as_str().unwrap().starts_with("game_")));
+        let call = serde_json::json!({"name": "lobby_status", "arguments": {}});
+        let result = client.call(&mut gm, "tools/call", call).await;
+        assert_eq!(result["error"]["message"], "Tool lobby_status is forbidden by scope 'game'");
+    }
+
+    #[tokio::test]
+    async fn test_ready_banner() {
+        let mut gm = test_gm();
+        let config = config::GmConfig::default();
+        let mut client = TestClient::attach(&mut gm, &config);
+        let wdc = WriteDirConfig::from_env(Some(gm.write_dir.to_str().unwrap()), None, None);
+        gm.announce_ready(&config, &doctor::check(&wdc, Err("no engines".into())));
+
+        let push = client.next("push/event").await;
+        assert_eq!(push["params"]["event_id"], ready::EVENT);
+        assert_eq!(push["params"]["origin"]["schema"], ready::SCHEMA);
+        let banner: serde_json::Value =
+            serde_json::from_str(push["params"]["payload"]["content"][0]["text"].as_str().unwrap()).unwrap();
+        assert_eq!(banner["engine"]["error"], "no engines");
+        assert_eq!(banner["limits"]["maxConcurrentGames"], gm.engines.max_concurrent_games);
+    }
+
+    async fn reloaded(client: &mut TestClient) -> serde_json::Value {
+        let push = client.next("push/event").await;
+        assert_eq!(push["params"]["event_id"], reload::EVENT);
+        serde_json::from_str(push["params"]["payload"]["content"][0]["text"].as_str().unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_config_reload() {
+        let mut gm = test_gm();
+        let config = config::GmConfig::default();
+        let mut client = TestClient::attach(&mut gm, &config);
+        let new: config::GmConfig = serde_json::from_value(serde_json::json!({
+            "auto_respond": [{"pattern": "^gg$", "reply": "gg wp"}],
+            "pacing": {"max_commands": 5},
+            "status_page": {"enabled": true},
+        }))
+        .unwrap();
+        let config = gm.reload_config(&config, Ok(new));
+        assert_eq!(
+            reloaded(&mut client).await,
+            serde_json::json!({"applied": ["auto_respond", "pacing"], "rejected": ["status_page"]})
+        );
+        assert_eq!((gm.auto_respond.rule_count(), gm.sai.pacing.max_commands), (1, 5));
+        assert!(!config.status_page.enabled);
+
+        // A rule that doesn't compile fails the whole reload.
+        let bad: config::GmConfig = serde_json::from_value(serde_json::json!({
+            "auto_respond": [{"pattern": "(", "reply": "x"}],
+            "pacing": {"max_commands": 50},
+        }))
+        .unwrap();
+        let kept = gm.reload_config(&config, Ok(bad));
+        assert!(reloaded(&mut client).await["error"].as_str().unwrap().starts_with("auto_respond rule 0:"));
+        assert_eq!(kept, config);
+        assert_eq!((gm.auto_respond.rule_count(), gm.sai.pacing.max_commands), (1, 5));
+
+        gm.reload_config(&config, Err("Invalid config gm_config.json: expected value".into()));
+        assert_eq!(reloaded(&mut client).await["applied"], serde_json::json!([]));
+        assert_eq!(gm.sai.pacing.max_commands, 5);
+    }
+
+    #[tokio::test]
+    async fn test_channels_changed_held() {
+        let mut gm = test_gm();
+        let mut client = TestClient::attach(&mut gm, &config::GmConfig::default());
+        gm.channel_changes.set_window(std::time::Duration::from_millis(250));
+        let game = |id: &str, status: &str| ChannelDescriptor {
+            id: id.into(),
+            channel_type: "game".into(),
+            label: id.into(),
+            direction: ChannelDirection::Bidirectional,
+            address: None,
+            metadata: Some(serde_json::json!({"status": status})),
+        };
+
+        // A launch that crashes at once, next to one that starts.
+        gm.send_channels_changed(vec![game("game:local-1", "starting")], vec![], vec![]).await;
+        gm.send_channels_changed(vec![game("game:local-2", "starting")], vec![], vec![]).await;
+        gm.send_channels_changed(vec![], vec![], vec![game("game:local-1", "crashed")]).await;
+        gm.send_channels_changed(vec![], vec![], vec![game("game:local-2", "running")]).await;
+        gm.send_channels_changed(vec![], vec!["game:local-1".into()], vec![]).await;
+        gm.flush_channels_changed(std::time::Instant::now());
+
+        // Nothing went out before: the first notification is the merged one.
+        gm.tick(std::time::Instant::now() + std::time::Duration::from_secs(1)).await;
+        let changed = client.next("channels/changed").await;
+        assert_eq!(changed["params"]["added"].as_array().unwrap().len(), 1);
+        assert_eq!(changed["params"]["added"][0]["id"], "game:local-2");
+        assert_eq!(changed["params"]["added"][0]["metadata"]["status"], "running");
+        assert!(changed["params"]["removed"].is_null() && changed["params"]["updated"].is_null());
+    }
+
+    #[tokio::test]
+    async fn test_mcpl_channels_open_notifies() {
+        let mut gm = test_gm();
+        fake_engine(&gm, "sleep 30");
+        let mut client = TestClient::attach(&mut gm, &config::GmConfig::default());
+
+        let open = serde_json::json!({"address": {"map": "Tundra"}, "metadata": {"verbosity": "terse", "positions": "grid"}});
+        let result = client.call(&mut gm, "channels/open", open).await;
+        assert_eq!(result["channel"]["id"], "game:local-1");
+        assert_eq!(result["channel"]["direction"], "bidirectional");
+
+        let changed = client.next("channels/changed").await;
+        let added = changed["params"]["added"].as_array().unwrap();
+        assert_eq!(added.len(), 1);
+        assert_eq!((added[0]["id"].as_str(), added[0]["type"].as_str()), (Some("game:local-1"), Some("game")));
+        assert_eq!(added[0]["label"], "Game on Tundra");
+        assert_eq!(added[0]["metadata"]["map"], "Tundra");
+        assert_eq!(added[0]["metadata"]["status"], "starting");
+        assert_eq!(added[0]["metadata"]["verbosity"], "terse");
+        assert_eq!(added[0]["metadata"]["positions"], "grid");
+
+        let list = client.call(&mut gm, "channels/list", serde_json::json!({})).await;
+        assert_eq!(list["channels"][0]["id"], "game:local-1");
+        assert_eq!(list["channels"][0]["metadata"]["positions"], "grid");
+        let open = serde_json::json!({"address": {"map": "Tundra"}, "metadata": {"positions": "gps"}});
+        let result = client.call(&mut gm, "channels/open", open).await;
+        assert_eq!(result["error"]["message"], "Unknown positions 'gps' (expected grid, relative or raw)");
+
+        let open = serde_json::json!({"address": {"map": "Tundra"}, "metadata": {"verbosity": "loud"}});
+        let result = client.call(&mut gm, "channels/open", open).await;
+        assert_eq!(result["error"]["code"], -32602);
+        gm.engines.stop_game("game:local-1").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_mcpl_publish_errors() {
+        let mut gm = test_gm();
+        let mut client = TestClient::attach(&mut gm, &config::GmConfig::default());
+        let publish = |channel: Option<&str>, text: &str| {
+            let mut params = serde_json::json!({"content": [{"type": "text", "text": text}]});
+            if let Some(channel) = channel {
+                params["channelId"] = channel.into();
+            }
+            params
+        };
+        let stop = r#"{"type": "stop", "unit_id": 1}"#;
+
+        let result = client.call(&mut gm, "channels/publish", publish(None, stop)).await;
+        assert_eq!(result, serde_json::json!({"delivered": false, "error": "Missing channelId"}));
+        let result = client.call(&mut gm, "channels/publish", publish(Some("game:local-1"), "{not json")).await;
+        assert_eq!(result["delivered"], false);
+        assert!(!result["error"].as_str().unwrap().is_empty());
+        let result = client.call(&mut gm, "channels/publish", publish(Some("game:local-1"), stop)).await;
+        assert_eq!(result["delivered"], false);
+        assert!(result["error"].as_str().unwrap().contains("game:local-1"), "{}", result);
+    }
+
+    #[tokio::test]
+    async fn test_mcpl_game_wait_for() {
+        let mut gm = test_gm();
+        let mut client = TestClient::attach(&mut gm, &config::GmConfig::default());
+        let wait = |condition: serde_json::Value, timeout_s: f64| {
+            serde_json::json!({"name": "game_wait_for", "arguments": {
+                "channel_id": "game:local-1", "condition": condition, "timeout_s": timeout_s,
+            }})
+        };
+        let unit = serde_json::json!({"type": "unit_finished", "unit_id": 12});
+        let result = client.call(&mut gm, "tools/call", wait(unit.clone(), 30.0)).await;
+        assert!(is_error(&result) && text(&result) == "No game running on game:local-1", "{}", result);
+
+        let (_bridge, _socket) = connect_bridge(&mut gm, "wait");
+        let update = sai_ipc::SaiEvent::Update {
+            frame: 300,
+            awaiting_commands: false,
+            economy: None,
+            allies: Vec::new(),
+            counters: Default::default(),
+            command_backlog: 0,
+            under_construction: Vec::new(),
+            update_interval: None,
+        };
+        gm.handle_sai_event("game:local-1", &update).await;
+
+        // A condition that already holds is answered at once.
+        let result = client.call(&mut gm, "tools/call", wait(serde_json::json!({"type": "frame", "frame": 200}), 30.0)).await;
+        assert_eq!(result["structuredContent"]["fired"], true);
+        assert!(text(&result).starts_with("Done waiting for frame 200 at frame 300"), "{}", result);
+
+        // The rest wait, without holding up other calls.
+        let finished = client.send(&mut gm, "tools/call", wait(unit, 30.0)).await;
+        let seen = client.send(&mut gm, "tools/call", wait(serde_json::json!({"type": "event", "event": "enemy_enter_los"}), 1.0)).await;
+        let ended = client.send(&mut gm, "tools/call", wait(serde_json::json!({"type": "game_ended"}), 30.0)).await;
+        client.call(&mut gm, "channels/list", serde_json::json!({})).await;
+        assert_eq!(client.unread.len(), 0);
+        assert_eq!(gm.waiters.count("game:local-1"), 3);
+
+        let event = sai_ipc::SaiEvent::UnitFinished { unit: UnitId(12), unit_name: Some("cloakraid".into()), pos: None };
+        gm.handle_sai_event("game:local-1", &event).await;
+        let result = client.response(finished).await["result"].clone();
+        assert_eq!(result["structuredContent"]["reason"], "fired");
+        assert_eq!(result["structuredContent"]["event"]["type"], "unit_finished");
+
+        gm.expire_waiters(std::time::Instant::now() + std::time::Duration::from_secs(2));
+        let result = client.response(seen).await["result"].clone();
+        assert_eq!(result["structuredContent"]["reason"], "timeout");
+        assert!(text(&result).starts_with("Timed out after "), "{}", result);
+
+        // The game going away answers what's left.
+        gm.forget_channel("game:local-1");
+        let result = client.response(ended).await["result"].clone();
+        assert!(is_error(&result));
+        assert_eq!(text(&result), "Stopped waiting for the game to end: the channel closed");
+        assert!(gm.waiters.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_mcpl_state_rollback() {
+        let mut gm = test_gm();
+        let mut client = TestClient::attach(&mut gm, &config::GmConfig::default());
+        let params = serde_json::json!({"featureSet": "game", "checkpoint": "frame-900"});
+        let result = client.call(&mut gm, "state/rollback", params).await;
+        assert_eq!(result["success"], false);
+        assert_eq!(result["checkpoint"], "frame-900");
+        assert!(result["reason"].as_str().unwrap().contains("savestate"));
+        let result = client.call(&mut gm, "state/rollback", serde_json::json!({})).await;
+        assert_eq!(result["checkpoint"], "");
+    }
+}
@@ -0,0 +1,154 @@
+//! Where SAI sockets live: a directory of this run's own under the
+//! configured one (`SOCKET_DIR`, `/tmp` by default), named
+//! `gm-{pid}-{random}` and readable by our user only, so GameManagers
+//! sharing a dir and restarts never bind each other's sockets.
+//!
+//! Sockets are named by channel (`local-1.sock` for `game:local-1`). The
+//! directory goes when the run ends; dirs a killed run left behind are
+//! swept at the next start, once their pid is gone.
+
+use std::path::{Path, PathBuf};
+
+use crate::write_dir::pid_alive;
+
+/// Longest Unix socket path: `sun_path` is 108 bytes with the NUL.
+pub const MAX_SOCKET_PATH: usize = 107;
+
+/// Prefix of the per-run directories.
+const RUN_PREFIX: &str = "gm-";
+
+/// Longest socket name: a channel name at full length with a co-op suffix.
+fn longest_name() -> String {
+    format!("{}_team99.sock", "x".repeat(crate::channel_ids::MAX_NAME_LEN))
+}
+
+/// This run's socket directory; removed when dropped.
+#[derive(Debug)]
+pub struct SocketDir {
+    path: PathBuf,
+}
+
+impl SocketDir {
+    /// Sweep `base` of dead runs' dirs and create this run's, mode 0700.
+    pub fn create(base: &Path) -> Result<Self, String> {
+        for swept in sweep_orphans(base) {
+            tracing::info!("Removed socket dir {} left by a stopped GameManager", swept.display());
+        }
+        let suffix = uuid::Uuid::new_v4().simple().to_string();
+        let path = base.join(format!("{}{}-{}", RUN_PREFIX, std::process::id(), &suffix[..8]));
+        let longest = path.join(longest_name());
+        if longest.as_os_str().len() > MAX_SOCKET_PATH {
+            return Err(format!(
+                "Socket dir {} is too deep: socket paths in it could reach {} bytes, over the {} Unix sockets allow. \
+                 Set SOCKET_DIR to a shorter path",
+                base.display(),
+                longest.as_os_str().len(),
+                MAX_SOCKET_PATH
+            ));
+        }
+        use std::os::unix::fs::DirBuilderExt;
+        std::fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(&path)
+            .map_err(|e| format!("Failed to create socket dir {}: {}", path.display(), e))?;
+        Ok(Self { path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The socket for `channel_id`'s bridge.
+    pub fn socket_for(&self, channel_id: &str) -> Result<String, String> {
+        let name: String = channel_id
+            .strip_prefix(crate::channel_ids::PREFIX)
+            .unwrap_or(channel_id)
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+            .collect();
+        let path = self.path.join(format!("{}.sock", name)).display().to_string();
+        if path.len() > MAX_SOCKET_PATH {
+            return Err(format!("Socket path {} is longer than the {} bytes Unix sockets allow", path, MAX_SOCKET_PATH));
+        }
+        Ok(path)
+    }
+}
+
+impl Drop for SocketDir {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_dir_all(&self.path) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                tracing::warn!("Failed to remove socket dir {}: {}", self.path.display(), e);
+            }
+        }
+    }
+}
+
+/// Remove the run dirs in `base` whose GameManager is no longer running.
+/// Returns what was removed.
+pub fn sweep_orphans(base: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(base) else { return Vec::new() };
+    let mut swept = Vec::new();
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let Some(pid) = name
+            .to_str()
+            .and_then(|n| n.strip_prefix(RUN_PREFIX))
+            .and_then(|rest| rest.split_once('-'))
+            .and_then(|(pid, _)| pid.parse::<u32>().ok())
+        else {
+            continue;
+        };
+        if pid_alive(pid) || !entry.file_type().is_ok_and(|t| t.is_dir()) {
+            continue;
+        }
+        if std::fs::remove_dir_all(entry.path()).is_ok() {
+            swept.push(entry.path());
+        }
+    }
+    swept
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn base() -> PathBuf {
+        let base = std::env::temp_dir().join(format!("gm-sockets-{}", &uuid::Uuid::new_v4().simple().to_string()[..8]));
+        std::fs::create_dir_all(&base).unwrap();
+        base
+    }
+
+    #[test]
+    fn test_run_dir() {
+        let base = base();
+        // A dead run's dir is swept, a live one's and other files stay.
+        std::fs::create_dir_all(base.join("gm-999999999-deadbeef")).unwrap();
+        let live = base.join(format!("gm-{}-0000live", std::process::id()));
+        std::fs::create_dir_all(&live).unwrap();
+        std::fs::write(base.join("gm-1-notadir"), "").unwrap();
+
+        let dir = SocketDir::create(&base).unwrap();
+        assert!(!base.join("gm-999999999-deadbeef").exists());
+        assert!(live.exists() && base.join("gm-1-notadir").exists());
+        let mode = std::fs::metadata(dir.path()).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+        assert_eq!(dir.socket_for("game:local-1").unwrap(), dir.path().join("local-1.sock").display().to_string());
+        assert!(dir.socket_for("game:mp-2").unwrap().ends_with("/mp-2.sock"));
+
+        let path = dir.path().to_path_buf();
+        drop(dir);
+        assert!(!path.exists(), "the run's dir goes with it");
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn test_too_deep() {
+        let deep = std::env::temp_dir().join("d".repeat(60));
+        let err = SocketDir::create(&deep).unwrap_err();
+        assert!(err.contains("is too deep") && err.contains("SOCKET_DIR"), "{}", err);
+        assert!(!deep.exists());
+    }
+}
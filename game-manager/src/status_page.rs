@@ -0,0 +1,372 @@
+//! Read-only status page for operators: a JSON document and an HTML page
+//! over plain HTTP, so a running GameManager can be checked without an
+//! MCPL client.
+//!
+//! The main loop builds the document from its own state about once a
+//! second and swaps it into a shared [`StatusView`]; the listener only ever
+//! reads that copy, never the live structures.
+
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// How often the main loop refreshes the document.
+pub const PUBLISH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Longest request head read; anything longer is refused.
+const MAX_REQUEST_BYTES: usize = 8 * 1024;
+
+/// How long a connection may take to send its request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// `status_page` section of the config file.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StatusPageConfig {
+    /// Off unless the config turns it on.
+    #[serde(default)]
+    pub enabled: bool,
+    /// Address to listen on. Anything but loopback needs a `token`.
+    #[serde(default = "default_bind")]
+    pub bind: String,
+    /// Required as `Authorization: Bearer <token>` or `?token=<token>`.
+    #[serde(default)]
+    pub token: Option<String>,
+    /// Event lines kept per channel for the page.
+    #[serde(default = "default_recent_events")]
+    pub recent_events: usize,
+}
+
+fn default_bind() -> String {
+    "127.0.0.1:9810".into()
+}
+
+fn default_recent_events() -> usize {
+    20
+}
+
+impl Default for StatusPageConfig {
+    fn default() -> Self {
+        Self { enabled: false, bind: default_bind(), token: None, recent_events: default_recent_events() }
+    }
+}
+
+impl StatusPageConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        let addr: SocketAddr = self
+            .bind
+            .parse()
+            .map_err(|_| format!("status_page.bind '{}' is not an address like 127.0.0.1:9810", self.bind))?;
+        if self.enabled && !addr.ip().is_loopback() && self.token.as_deref().is_none_or(str::is_empty) {
+            return Err(format!("status_page.bind {} is not loopback; set status_page.token", addr));
+        }
+        Ok(())
+    }
+}
+
+/// The latest published document, shared with the listener.
+#[derive(Debug, Clone)]
+pub struct StatusView(Arc<RwLock<serde_json::Value>>);
+
+impl StatusView {
+    pub fn new() -> Self {
+        Self(Arc::new(RwLock::new(serde_json::json!({"status": "waiting for an MCPL client"}))))
+    }
+
+    pub fn publish(&self, document: serde_json::Value) {
+        *self.0.write().unwrap() = document;
+    }
+
+    pub fn current(&self) -> serde_json::Value {
+        self.0.read().unwrap().clone()
+    }
+}
+
+/// The main loop's side: recent event lines per channel, and when the
+/// view was last refreshed.
+#[derive(Debug)]
+pub struct StatusBoard {
+    pub view: StatusView,
+    recent: HashMap<String, VecDeque<String>>,
+    size: usize,
+    last_published: Option<Instant>,
+}
+
+impl StatusBoard {
+    pub fn new(view: StatusView, size: usize) -> Self {
+        Self { view, recent: HashMap::new(), size, last_published: None }
+    }
+
+    pub fn observe(&mut self, channel_id: &str, line: String) {
+        let lines = self.recent.entry(channel_id.to_string()).or_default();
+        if lines.len() >= self.size {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+    }
+
+    /// A channel's recent lines, oldest first.
+    pub fn recent(&self, channel_id: &str) -> Vec<String> {
+        self.recent.get(channel_id).map(|l| l.iter().cloned().collect()).unwrap_or_default()
+    }
+
+    /// Drop the lines of channels no longer listed.
+    pub fn retain(&mut self, keep: impl Fn(&str) -> bool) {
+        self.recent.retain(|id, _| keep(id));
+    }
+
+    /// True if the document is due at `now`, marking it published.
+    pub fn take_due(&mut self, now: Instant) -> bool {
+        if self.last_published.is_some_and(|t| now.duration_since(t) < PUBLISH_INTERVAL) {
+            return false;
+        }
+        self.last_published = Some(now);
+        true
+    }
+}
+
+/// Start listening. Returns the bound address; connections are served on
+/// their own tasks until the process exits.
+pub async fn serve(config: &StatusPageConfig, view: StatusView) -> Result<SocketAddr, String> {
+    let listener = TcpListener::bind(&config.bind)
+        .await
+        .map_err(|e| format!("Status page can't listen on {}: {}", config.bind, e))?;
+    let addr = listener.local_addr().map_err(|e| e.to_string())?;
+    let token = config.token.clone().filter(|t| !t.is_empty());
+    tokio::spawn(async move {
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    tracing::warn!("Status page accept failed: {}", e);
+                    continue;
+                }
+            };
+            let (view, token) = (view.clone(), token.clone());
+            tokio::spawn(async move {
+                if let Err(e) = answer(stream, &view, token.as_deref()).await {
+                    tracing::debug!("Status page request failed: {}", e);
+                }
+            });
+        }
+    });
+    Ok(addr)
+}
+
+async fn answer(mut stream: TcpStream, view: &StatusView, token: Option<&str>) -> std::io::Result<()> {
+    let head = match tokio::time::timeout(REQUEST_TIMEOUT, read_head(&mut stream)).await {
+        Ok(Ok(Some(head))) => head,
+        Ok(Err(e)) => return Err(e),
+        _ => return respond(&mut stream, 400, "text/plain", "Bad request\n").await,
+    };
+    let mut lines = head.lines();
+    let mut request = lines.next().unwrap_or_default().split(' ');
+    let (method, target) = (request.next().unwrap_or_default(), request.next().unwrap_or_default());
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+
+    if let Some(token) = token {
+        let bearer = lines
+            .filter_map(|l| l.split_once(':'))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("authorization"))
+            .and_then(|(_, value)| value.trim().strip_prefix("Bearer "))
+            .map(str::trim);
+        let in_query = query.split('&').find_map(|pair| pair.strip_prefix("token="));
+        if bearer != Some(token) && in_query != Some(token) {
+            return respond(&mut stream, 401, "text/plain", "Missing or wrong token\n").await;
+        }
+    }
+    if method != "GET" {
+        return respond(&mut stream, 405, "text/plain", "Only GET is served\n").await;
+    }
+    match path {
+        "/" | "/index.html" => respond(&mut stream, 200, "text/html; charset=utf-8", &render_html(&view.current())).await,
+        "/status.json" => {
+            let body = serde_json::to_string_pretty(&view.current()).unwrap_or_default();
+            respond(&mut stream, 200, "application/json", &body).await
+        }
+        _ => respond(&mut stream, 404, "text/plain", "Not found; try / or /status.json\n").await,
+    }
+}
+
+/// The request line and headers, or None if they don't fit or the peer
+/// hung up first.
+async fn read_head(stream: &mut TcpStream) -> std::io::Result<Option<String>> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 1024];
+    while !buf.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 || buf.len() + n > MAX_REQUEST_BYTES {
+            return Ok(None);
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+    Ok(Some(String::from_utf8_lossy(&buf).into_owned()))
+}
+
+async fn respond(stream: &mut TcpStream, code: u16, content_type: &str, body: &str) -> std::io::Result<()> {
+    let reason = match code {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        _ => "Method Not Allowed",
+    };
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n",
+        code,
+        reason,
+        content_type,
+        body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// A JSON value as page text: strings bare, absent values as a dash.
+fn show(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::Null => "–".into(),
+        serde_json::Value::String(s) => escape(s),
+        other => escape(&other.to_string()),
+    }
+}
+
+/// The document as a plain HTML page that refreshes itself.
+fn render_html(doc: &serde_json::Value) -> String {
+    let mut html = String::from(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><meta http-equiv=\"refresh\" content=\"5\">\
+         <title>GameManager status</title></head><body>\n<h1>GameManager status</h1>\n",
+    );
+    if let Some(status) = doc["status"].as_str() {
+        html += &format!("<p>{}</p>\n", escape(status));
+    }
+    if !doc["updatedAt"].is_null() {
+        html += &format!("<p>Updated {}. MCPL client attached: {}.</p>\n", show(&doc["updatedAt"]), show(&doc["mcplClient"]));
+    }
+
+    let lobby = &doc["lobby"];
+    if lobby.is_object() {
+        html += "<h2>Lobby</h2>\n<ul>\n";
+        html += &format!("<li>Connected: {}, logged in: {}</li>\n", show(&lobby["connected"]), show(&lobby["loggedIn"]));
+        if !lobby["username"].is_null() {
+            html += &format!("<li>As {}</li>\n", show(&lobby["username"]));
+        }
+        if !lobby["battle"].is_null() {
+            html += &format!("<li>In battle {} on {}</li>\n", show(&lobby["battle"]["id"]), show(&lobby["battle"]["map"]));
+        }
+        html += "</ul>\n";
+    }
+
+    let channels = doc["channels"].as_array().map(Vec::as_slice).unwrap_or_default();
+    if doc["channels"].is_array() {
+        html += &format!("<h2>Game channels ({})</h2>\n", channels.len());
+    }
+    for channel in channels {
+        html += &format!("<h3>{}</h3>\n<table>\n", show(&channel["id"]));
+        let engine = &channel["engine"];
+        let economy = &channel["economy"];
+        let rows = [
+            ("Map", show(&channel["map"])),
+            ("Status", show(&channel["status"])),
+            ("Bridge connected", show(&channel["saiConnected"])),
+            ("Frame", show(&channel["frame"])),
+            ("Metal", show(&economy["metal"]["current"])),
+            ("Energy", show(&economy["energy"]["current"])),
+            ("Units / enemies in sight", format!("{} / {}", show(&channel["units"]), show(&channel["enemiesInSight"]))),
+            ("Threats", show(&channel["threats"])),
+            ("Engine pid", show(&engine["pid"])),
+            ("Engine running", show(&engine["running"])),
+        ];
+        for (label, value) in rows {
+            html += &format!("<tr><th align=\"left\">{}</th><td>{}</td></tr>\n", label, value);
+        }
+        html += "</table>\n";
+        let recent = channel["recentEvents"].as_array().map(Vec::as_slice).unwrap_or_default();
+        if !recent.is_empty() {
+            html += "<ol>\n";
+            for line in recent {
+                html += &format!("<li>{}</li>\n", show(line));
+            }
+            html += "</ol>\n";
+        }
+    }
+    html += "<p><a href=\"status.json\">status.json</a></p>\n</body></html>\n";
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn get(addr: SocketAddr, request: &str) -> (u16, String) {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        let code = response[9..12].parse().unwrap();
+        let body = response.split_once("\r\n\r\n").unwrap().1.to_string();
+        (code, body)
+    }
+
+    #[tokio::test]
+    async fn test_serves_the_published_document() {
+        let config = StatusPageConfig { enabled: true, bind: "127.0.0.1:0".into(), token: Some("s3cret".into()), ..Default::default() };
+        let view = StatusView::new();
+        let addr = serve(&config, view.clone()).await.unwrap();
+
+        let (code, _) = get(addr, "GET /status.json HTTP/1.1\r\nHost: x\r\n\r\n").await;
+        assert_eq!(code, 401);
+        let (code, body) = get(addr, "GET /status.json HTTP/1.1\r\nAuthorization: Bearer s3cret\r\n\r\n").await;
+        assert_eq!(code, 200);
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&body).unwrap()["status"], "waiting for an MCPL client");
+
+        view.publish(serde_json::json!({
+            "updatedAt": "2026-01-01T00:00:00Z",
+            "channels": [{"id": "game:local-1", "map": "Tundra", "frame": 900, "recentEvents": ["Your <b> is idle"]}],
+        }));
+        let (code, body) = get(addr, "GET /?token=s3cret HTTP/1.1\r\n\r\n").await;
+        assert_eq!(code, 200);
+        assert!(body.contains("<h3>game:local-1</h3>") && body.contains("<td>900</td>"), "{}", body);
+        assert!(body.contains("Your &lt;b&gt; is idle"));
+
+        let (code, _) = get(addr, "GET /nope?token=s3cret HTTP/1.1\r\n\r\n").await;
+        assert_eq!(code, 404);
+        let (code, _) = get(addr, "POST /status.json?token=s3cret HTTP/1.1\r\n\r\n").await;
+        assert_eq!(code, 405);
+    }
+
+    #[test]
+    fn test_board_keeps_recent_lines() {
+        let mut board = StatusBoard::new(StatusView::new(), 2);
+        for line in ["a", "b", "c"] {
+            board.observe("game:local-1", line.into());
+        }
+        assert_eq!(board.recent("game:local-1"), ["b", "c"]);
+        board.retain(|id| id != "game:local-1");
+        assert!(board.recent("game:local-1").is_empty());
+
+        let now = Instant::now();
+        assert!(board.take_due(now) && !board.take_due(now + Duration::from_millis(500)));
+        assert!(board.take_due(now + PUBLISH_INTERVAL));
+    }
+
+    #[test]
+    fn test_wide_bind_needs_token() {
+        let wide = StatusPageConfig { enabled: true, bind: "0.0.0.0:9810".into(), ..Default::default() };
+        assert!(wide.validate().unwrap_err().contains("set status_page.token"));
+        assert!(StatusPageConfig { token: Some("t".into()), ..wide.clone() }.validate().is_ok());
+        assert!(StatusPageConfig { enabled: false, ..wide }.validate().is_ok());
+        assert!(StatusPageConfig { bind: "localhost".into(), ..Default::default() }.validate().is_err());
+        assert!(StatusPageConfig::default().validate().is_ok());
+    }
+}
@@ -0,0 +1,607 @@
+//! Threat alerts: enemy sightings and damage to our units, clustered by
+//! place and time into one alert per engagement instead of a stream of
+//! single events.
+//!
+//! A pure function of a sliding window over the channel's events. The
+//! window is re-clustered on each `update`; a cluster that matches one
+//! already reported updates it, and a reported threat whose events have
+//! all aged out is cleared.
+//!
+//! EMP is kept apart from real damage: an engagement that mostly stuns
+//! our units is a `stun` threat, where the danger is units disabled and
+//! captured rather than destroyed.
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::locations::START;
+use crate::positions::{self, MapContext};
+use crate::sai_ipc::{Relation, SaiEvent, UnitId};
+use crate::unit_history::{Motion, UnitHistory};
+
+/// How long an event counts toward a threat: 20 game seconds.
+pub const THREAT_WINDOW_FRAMES: i32 = 30 * 20;
+
+/// Events within this distance (elmos) of a cluster's centroid join it.
+pub const CLUSTER_RADIUS: f32 = 800.0;
+
+/// Enemies needed for a threat when none of our units is hit yet.
+const MIN_ENEMIES_WITHOUT_DAMAGE: usize = 2;
+
+/// A threat heading within this angle of our base is "toward your base".
+const TOWARD_BASE_DEGREES: f32 = 30.0;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DamagedUnit {
+    pub unit: UnitId,
+    pub unit_name: Option<String>,
+    pub damage: f32,
+    /// EMP damage, not counted in `damage`.
+    #[serde(skip_serializing_if = "is_zero")]
+    pub paralyze_damage: f32,
+}
+
+fn is_zero(n: &f32) -> bool {
+    *n == 0.0
+}
+
+/// What an engagement mostly does to our units.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ThreatCategory {
+    #[default]
+    Attack,
+    /// More EMP than real damage: units are being stunned, and stunned
+    /// units can be captured or picked off at leisure.
+    Stun,
+}
+
+/// One engagement as last reported.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Threat {
+    pub id: String,
+    /// Centroid of the cluster, x and z.
+    pub pos: [f32; 2],
+    /// Distinct enemies seen or shooting in the cluster.
+    pub enemy_count: usize,
+    /// Enemy def names and counts. Enemies never seen by name are "unknown".
+    pub composition: BTreeMap<String, usize>,
+    /// The enemies' ids.
+    pub enemies: Vec<UnitId>,
+    /// Where the enemies are going, from their recent positions.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub motion: Option<Motion>,
+    pub category: ThreatCategory,
+    /// Our units hit in the window, with the damage each took.
+    pub under_fire: Vec<DamagedUnit>,
+    pub first_frame: i32,
+    pub last_frame: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThreatAlertKind {
+    Detected,
+    Updated,
+    Cleared,
+}
+
+impl ThreatAlertKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ThreatAlertKind::Detected => "threat_detected",
+            ThreatAlertKind::Updated => "threat_updated",
+            ThreatAlertKind::Cleared => "threat_cleared",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThreatAlert {
+    pub kind: ThreatAlertKind,
+    pub threat: Threat,
+}
+
+impl ThreatAlert {
+    /// One line for the agent, placed on the map with `context` when the
+    /// channel has one.
+    pub fn text(&self, context: Option<&MapContext>) -> String {
+        let t = &self.threat;
+        let at = match context {
+            Some(context) => positions::describe(t.pos[0], t.pos[1], context),
+            None => format!("near ({:.0}, {:.0})", t.pos[0], t.pos[1]),
+        };
+        if self.kind == ThreatAlertKind::Cleared {
+            return format!("Threat {} {} cleared", t.id, at);
+        }
+        let enemies = if t.enemy_count == 0 {
+            "no enemies in sight".to_string()
+        } else {
+            let composition: Vec<String> =
+                t.composition.iter().map(|(name, n)| format!("{} {}", n, name)).collect();
+            format!("~{} enemies ({})", t.enemy_count, composition.join(", "))
+        };
+        let enemies = match &t.motion {
+            Some(motion) => {
+                let base = context.and_then(|c| c.named.get(START));
+                let toward = base.filter(|base| heads_for(t.pos, motion, [base.x, base.z]));
+                format!("{} {}{}", enemies, motion.text(), if toward.is_some() { ", toward your base" } else { "" })
+            }
+            None => enemies,
+        };
+        let heading = match (self.kind, t.category) {
+            (ThreatAlertKind::Detected, ThreatCategory::Attack) => "Threat detected",
+            (ThreatAlertKind::Detected, ThreatCategory::Stun) => "Stun threat detected",
+            (_, ThreatCategory::Attack) => "Threat update",
+            (_, ThreatCategory::Stun) => "Stun threat update",
+        };
+        let mut s = format!(
+            "{} {} {}: {}",
+            heading,
+            t.id,
+            at,
+            enemies
+        );
+        if !t.under_fire.is_empty() {
+            let units: Vec<String> = t
+                .under_fire
+                .iter()
+                .map(|u| format!("{} (#{})", u.unit_name.as_deref().unwrap_or("unit"), u.unit))
+                .collect();
+            let what = match t.category {
+                ThreatCategory::Attack => "under fire",
+                ThreatCategory::Stun => "being stunned (watch for capture)",
+            };
+            s += &format!("; {}: {}", what, units.join(", "));
+        }
+        s
+    }
+}
+
+/// Whether `motion` from `pos` points at `target`.
+fn heads_for(pos: [f32; 2], motion: &Motion, target: [f32; 2]) -> bool {
+    let (dx, dz) = (target[0] - pos[0], target[1] - pos[1]);
+    let distance = dx.hypot(dz);
+    distance > 0.0
+        && (motion.direction[0] * dx + motion.direction[1] * dz) / distance >= TOWARD_BASE_DEGREES.to_radians().cos()
+}
+
+#[derive(Debug, Clone)]
+enum Sighting {
+    Enemy { enemy: UnitId, name: Option<String> },
+    Damage {
+        unit: UnitId,
+        name: Option<String>,
+        attacker: UnitId,
+        attacker_name: Option<String>,
+        damage: f32,
+        paralyzer: bool,
+    },
+}
+
+#[derive(Debug, Clone)]
+struct Point {
+    frame: i32,
+    pos: [f32; 2],
+    sighting: Sighting,
+}
+
+/// One channel's event window and the threats reported from it.
+#[derive(Debug, Default)]
+pub struct ThreatTracker {
+    frame: i32,
+    window: Vec<Point>,
+    active: Vec<Threat>,
+    next_id: u32,
+}
+
+impl ThreatTracker {
+    /// Threats reported and not yet cleared.
+    pub fn active(&self) -> &[Threat] {
+        &self.active
+    }
+
+    /// Set where each active threat's enemies are going, from their
+    /// positions in `history`.
+    pub fn track_motion(&mut self, history: &UnitHistory) {
+        for threat in &mut self.active {
+            threat.motion = history.group_motion(&threat.enemies);
+        }
+    }
+
+    /// Take in one event. Alerts come back on `update` events, where the
+    /// window is re-clustered.
+    pub fn observe(&mut self, event: &SaiEvent) -> Vec<ThreatAlert> {
+        let xz = |pos: &[f32; 3]| [pos[0], pos[2]];
+        match event {
+            SaiEvent::Init { frame, .. } => self.frame = *frame,
+            SaiEvent::Update { frame, .. } => {
+                self.frame = *frame;
+                return self.evaluate();
+            }
+            // Critters and other gaia units threaten no one.
+            SaiEvent::EnemyEnterLos { relation: Some(Relation::Gaia), .. } => {}
+            SaiEvent::EnemyEnterLos { enemy, enemy_name, pos: Some(pos), .. } => {
+                self.window.push(Point {
+                    frame: self.frame,
+                    pos: xz(pos),
+                    sighting: Sighting::Enemy { enemy: *enemy, name: enemy_name.clone() },
+                });
+            }
+            SaiEvent::UnitDamaged {
+                unit, unit_name, attacker, attacker_name, damage, paralyzer, pos: Some(pos), ..
+            } => {
+                self.window.push(Point {
+                    frame: self.frame,
+                    pos: xz(pos),
+                    sighting: Sighting::Damage {
+                        unit: *unit,
+                        name: unit_name.clone(),
+                        attacker: *attacker,
+                        attacker_name: attacker_name.clone(),
+                        damage: *damage,
+                        paralyzer: *paralyzer,
+                    },
+                });
+            }
+            // Dead enemies are no longer part of any threat; the damage
+            // they did still is.
+            SaiEvent::EnemyDestroyed { enemy, .. } => {
+                self.window.retain(|p| {
+                    !matches!(p.sighting, Sighting::Enemy { enemy: e, .. } if e == *enemy)
+                });
+                for point in &mut self.window {
+                    if let Sighting::Damage { attacker, .. } = &mut point.sighting {
+                        if *attacker == *enemy {
+                            *attacker = UnitId(-1);
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+        Vec::new()
+    }
+
+    fn evaluate(&mut self) -> Vec<ThreatAlert> {
+        let now = self.frame;
+        self.window.retain(|p| now - p.frame <= THREAT_WINDOW_FRAMES);
+
+        let mut previous = std::mem::take(&mut self.active);
+        let mut alerts = Vec::new();
+        for mut threat in cluster(&self.window) {
+            let closest = previous
+                .iter()
+                .enumerate()
+                .map(|(i, t)| (i, distance(t.pos, threat.pos)))
+                .filter(|(_, d)| *d <= CLUSTER_RADIUS)
+                .min_by(|a, b| a.1.total_cmp(&b.1))
+                .map(|(i, _)| i);
+            match closest.map(|i| previous.remove(i)) {
+                Some(old) => {
+                    threat.id = old.id.clone();
+                    threat.first_frame = threat.first_frame.min(old.first_frame);
+                    // Drift of the centroid alone isn't news.
+                    if threat.enemy_count != old.enemy_count
+                        || threat.composition != old.composition
+                        || threat.category != old.category
+                        || unit_ids(&threat) != unit_ids(&old)
+                    {
+                        alerts.push(ThreatAlert { kind: ThreatAlertKind::Updated, threat: threat.clone() });
+                    }
+                }
+                None => {
+                    self.next_id += 1;
+                    threat.id = format!("threat-{}", self.next_id);
+                    alerts.push(ThreatAlert { kind: ThreatAlertKind::Detected, threat: threat.clone() });
+                }
+            }
+            self.active.push(threat);
+        }
+        alerts.extend(
+            previous.into_iter().map(|threat| ThreatAlert { kind: ThreatAlertKind::Cleared, threat }),
+        );
+        alerts
+    }
+}
+
+fn distance(a: [f32; 2], b: [f32; 2]) -> f32 {
+    ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2)).sqrt()
+}
+
+fn unit_ids(threat: &Threat) -> Vec<UnitId> {
+    threat.under_fire.iter().map(|u| u.unit).collect()
+}
+
+/// Group points greedily: each joins the first cluster whose centroid is
+/// within `CLUSTER_RADIUS`, oldest points first. Clusters too small to be a
+/// threat are dropped. Ids are left for the caller to assign.
+fn cluster(window: &[Point]) -> Vec<Threat> {
+    let mut groups: Vec<(Vec<&Point>, [f32; 2])> = Vec::new();
+    for point in window {
+        match groups.iter_mut().find(|(_, c)| distance(*c, point.pos) <= CLUSTER_RADIUS) {
+            Some((members, centroid)) => {
+                members.push(point);
+                let n = members.len() as f32;
+                centroid[0] += (point.pos[0] - centroid[0]) / n;
+                centroid[1] += (point.pos[1] - centroid[1]) / n;
+            }
+            None => groups.push((vec![point], point.pos)),
+        }
+    }
+
+    let mut threats = Vec::new();
+    for (members, centroid) in groups {
+        // Enemy id -> best known name; sightings name enemies better than
+        // damage events do.
+        let mut enemies: BTreeMap<UnitId, Option<String>> = BTreeMap::new();
+        let mut under_fire: Vec<DamagedUnit> = Vec::new();
+        for point in &members {
+            match &point.sighting {
+                Sighting::Enemy { enemy, name } => {
+                    let known = enemies.entry(*enemy).or_default();
+                    if name.is_some() {
+                        *known = name.clone();
+                    }
+                }
+                Sighting::Damage { unit, name, attacker, attacker_name, damage, paralyzer } => {
+                    if attacker.0 >= 0 {
+                        let known = enemies.entry(*attacker).or_default();
+                        if known.is_none() {
+                            *known = attacker_name.clone();
+                        }
+                    }
+                    let index = match under_fire.iter().position(|u| u.unit == *unit) {
+                        Some(i) => i,
+                        None => {
+                            under_fire.push(DamagedUnit {
+                                unit: *unit,
+                                unit_name: name.clone(),
+                                damage: 0.0,
+                                paralyze_damage: 0.0,
+                            });
+                            under_fire.len() - 1
+                        }
+                    };
+                    if *paralyzer {
+                        under_fire[index].paralyze_damage += damage;
+                    } else {
+                        under_fire[index].damage += damage;
+                    }
+                }
+            }
+        }
+        if under_fire.is_empty() && enemies.len() < MIN_ENEMIES_WITHOUT_DAMAGE {
+            continue;
+        }
+        let emp: f32 = under_fire.iter().map(|u| u.paralyze_damage).sum();
+        let real: f32 = under_fire.iter().map(|u| u.damage).sum();
+        let category = if emp > real { ThreatCategory::Stun } else { ThreatCategory::Attack };
+        let mut composition = BTreeMap::new();
+        for name in enemies.values() {
+            *composition.entry(name.clone().unwrap_or_else(|| "unknown".into())).or_insert(0) += 1;
+        }
+        threats.push(Threat {
+            id: String::new(),
+            pos: centroid,
+            enemy_count: enemies.len(),
+            composition,
+            enemies: enemies.keys().copied().collect(),
+            motion: None,
+            category,
+            under_fire,
+            first_frame: members.iter().map(|p| p.frame).min().unwrap_or_default(),
+            last_frame: members.iter().map(|p| p.frame).max().unwrap_or_default(),
+        });
+    }
+    threats
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sai_protocol::{TeamId, WeaponDefId};
+
+    fn update(frame: i32) -> SaiEvent {
+        SaiEvent::Update { frame, awaiting_commands: false, economy: None, allies: Vec::new(), counters: Default::default(), command_backlog: 0, under_construction: Vec::new(), update_interval: None }
+    }
+
+    fn seen(enemy: i32, name: &str, x: f32, z: f32) -> SaiEvent {
+        SaiEvent::EnemyEnterLos {
+            enemy: UnitId(enemy),
+            enemy_name: Some(name.into()),
+            team: None,
+            relation: None,
+            pos: Some([x, 0.0, z]),
+        }
+    }
+
+    fn hit(unit: i32, attacker: i32, damage: f32, x: f32, z: f32) -> SaiEvent {
+        emp_hit(unit, attacker, damage, x, z, false)
+    }
+
+    fn emp_hit(unit: i32, attacker: i32, damage: f32, x: f32, z: f32, paralyzer: bool) -> SaiEvent {
+        SaiEvent::UnitDamaged {
+            unit: UnitId(unit),
+            unit_name: Some("cloakraid".into()),
+            attacker: UnitId(attacker),
+            attacker_name: None,
+            attacker_team: None,
+            attacker_relation: None,
+            damage,
+            weapon_def_id: WeaponDefId(1),
+            paralyzer,
+            paralysis: None,
+            pos: Some([x, 0.0, z]),
+        }
+    }
+
+    fn run(tracker: &mut ThreatTracker, events: &[SaiEvent]) -> Vec<ThreatAlert> {
+        events.iter().flat_map(|e| tracker.observe(e)).collect()
+    }
+
+    fn kinds(alerts: &[ThreatAlert]) -> Vec<(ThreatAlertKind, &str)> {
+        alerts.iter().map(|a| (a.kind, a.threat.id.as_str())).collect()
+    }
+
+    #[test]
+    fn test_raid_detected_updated_and_cleared() {
+        let mut tracker = ThreatTracker::default();
+        let alerts = run(&mut tracker, &[
+            update(30),
+            seen(501, "vehraid", 1000.0, 1000.0),
+            seen(502, "vehraid", 1100.0, 1000.0),
+            hit(10, 501, 40.0, 1050.0, 1100.0),
+            update(60),
+        ]);
+        assert_eq!(kinds(&alerts), [(ThreatAlertKind::Detected, "threat-1")]);
+        let threat = &alerts[0].threat;
+        assert_eq!(threat.enemy_count, 2);
+        assert_eq!(threat.composition["vehraid"], 2);
+        assert_eq!(threat.under_fire, vec![DamagedUnit { unit: UnitId(10), unit_name: Some("cloakraid".into()), damage: 40.0, paralyze_damage: 0.0 }]);
+        assert_eq!(
+            alerts[0].text(None),
+            "Threat detected threat-1 near (1050, 1033): ~2 enemies (2 vehraid); under fire: cloakraid (#10)"
+        );
+
+        // More damage to the same unit from the same raiders: nothing new.
+        assert!(run(&mut tracker, &[hit(10, 502, 30.0, 1050.0, 1100.0), update(90)]).is_empty());
+
+        // A third enemy, unseen but shooting, and a second victim: an update.
+        let alerts = run(&mut tracker, &[hit(11, 503, 25.0, 1200.0, 1000.0), update(120)]);
+        assert_eq!(kinds(&alerts), [(ThreatAlertKind::Updated, "threat-1")]);
+        assert_eq!(alerts[0].threat.composition["unknown"], 1);
+        assert_eq!(alerts[0].threat.enemy_count, 3);
+        assert_eq!(tracker.active().len(), 1);
+
+        // Quiet for the whole window: cleared.
+        let alerts = run(&mut tracker, &[update(120 + THREAT_WINDOW_FRAMES + 30)]);
+        assert_eq!(kinds(&alerts), [(ThreatAlertKind::Cleared, "threat-1")]);
+        assert!(alerts[0].text(None).starts_with("Threat threat-1 near ("));
+        assert!(tracker.active().is_empty());
+    }
+
+    #[test]
+    fn test_separate_fronts_and_lone_scouts() {
+        let mut tracker = ThreatTracker::default();
+        let alerts = run(&mut tracker, &[
+            update(30),
+            // A lone scout far away is not a threat by itself.
+            seen(600, "spiderscout", 5000.0, 5000.0),
+            hit(10, 501, 40.0, 1000.0, 1000.0),
+            hit(20, 701, 80.0, 3000.0, 200.0),
+            seen(701, "tankassault", 3050.0, 250.0),
+            update(60),
+        ]);
+        assert_eq!(
+            kinds(&alerts),
+            [(ThreatAlertKind::Detected, "threat-1"), (ThreatAlertKind::Detected, "threat-2")]
+        );
+        assert_eq!(alerts[1].threat.composition["tankassault"], 1);
+
+        // The tank dies. Its damage to unit 20 is still recent, so the
+        // second front stays, with no enemy left in it.
+        let alerts = run(&mut tracker, &[
+            SaiEvent::EnemyDestroyed {
+                enemy: UnitId(701), enemy_name: None, team: None, relation: None,
+                attacker: UnitId(20), attacker_name: None, attacker_team: None, attacker_relation: None,
+            },
+            update(90),
+        ]);
+        assert_eq!(kinds(&alerts), [(ThreatAlertKind::Updated, "threat-2")]);
+        assert_eq!(alerts[0].threat.enemy_count, 0);
+        assert_eq!(
+            alerts[0].text(None),
+            "Threat update threat-2 near (3000, 200): no enemies in sight; under fire: cloakraid (#20)"
+        );
+    }
+
+    #[test]
+    fn test_emp_raid_is_a_stun_threat() {
+        let mut tracker = ThreatTracker::default();
+        let alerts = run(&mut tracker, &[
+            update(30),
+            seen(501, "cloakarty", 1000.0, 1000.0),
+            emp_hit(10, 501, 300.0, 1050.0, 1000.0, true),
+            emp_hit(11, 501, 250.0, 1100.0, 1000.0, true),
+            hit(11, 501, 20.0, 1100.0, 1000.0),
+            update(60),
+        ]);
+        assert_eq!(kinds(&alerts), [(ThreatAlertKind::Detected, "threat-1")]);
+        let threat = &alerts[0].threat;
+        assert_eq!(threat.category, ThreatCategory::Stun);
+        assert_eq!((threat.under_fire[1].damage, threat.under_fire[1].paralyze_damage), (20.0, 250.0));
+        assert!(
+            alerts[0].text(None).starts_with("Stun threat detected threat-1")
+                && alerts[0].text(None).ends_with("being stunned (watch for capture): cloakraid (#10), cloakraid (#11)"),
+            "{}",
+            alerts[0].text(None)
+        );
+        assert_eq!(serde_json::to_value(threat).unwrap()["category"], "stun");
+
+        // Real damage takes over: the same threat, now an attack.
+        let alerts = run(&mut tracker, &[hit(10, 501, 900.0, 1050.0, 1000.0), update(90)]);
+        assert_eq!(kinds(&alerts), [(ThreatAlertKind::Updated, "threat-1")]);
+        assert_eq!(alerts[0].threat.category, ThreatCategory::Attack);
+    }
+
+    #[test]
+    fn test_events_without_positions_ignored() {
+        let mut tracker = ThreatTracker::default();
+        let alerts = run(&mut tracker, &[
+            SaiEvent::EnemyEnterLos { enemy: UnitId(1), enemy_name: None, team: None, relation: None, pos: None },
+            SaiEvent::EnemyEnterLos { enemy: UnitId(2), enemy_name: None, team: None, relation: None, pos: None },
+            update(30),
+        ]);
+        assert!(alerts.is_empty());
+    }
+
+    #[test]
+    fn test_gaia_units_ignored() {
+        let mut tracker = ThreatTracker::default();
+        let critter = |enemy| SaiEvent::EnemyEnterLos {
+            enemy,
+            enemy_name: Some("chicken_dodo".into()),
+            team: Some(TeamId(3)),
+            relation: Some(Relation::Gaia),
+            pos: Some([1000.0, 0.0, 1000.0]),
+        };
+        assert!(run(&mut tracker, &[critter(UnitId(1)), critter(UnitId(2)), critter(UnitId(3)), update(30)]).is_empty());
+    }
+
+    #[test]
+    fn test_threat_motion() {
+        let mut tracker = ThreatTracker::default();
+        let mut history = UnitHistory::default();
+        // Two raiders run west, at our base, 90 elmos a second.
+        for step in 0..3 {
+            let x = 3000.0 - 90.0 * step as f32;
+            for event in [update(30 * (step + 1)), seen(501, "cloakraid", x, 1000.0), seen(502, "cloakraid", x, 1100.0)] {
+                history.observe(&event);
+                tracker.observe(&event);
+            }
+        }
+        history.observe(&update(120));
+        tracker.observe(&update(120));
+        tracker.track_motion(&history);
+        let threat = tracker.active()[0].clone();
+        assert_eq!(threat.enemies, [UnitId(501), UnitId(502)]);
+        let motion = threat.motion.unwrap();
+        assert_eq!((motion.heading, motion.units), ("west", 2));
+
+        let mut named = BTreeMap::new();
+        named.insert(START.to_string(), crate::locations::Place { x: 500.0, z: 1000.0 });
+        let context = MapContext { style: positions::PositionStyle::Raw, map_size: None, named: &named };
+        let alert = ThreatAlert { kind: ThreatAlertKind::Updated, threat };
+        assert_eq!(
+            alert.text(Some(&context)),
+            "Threat update threat-1 near (2910, 1050): ~2 enemies (2 cloakraid) moving west at ~90 elmos/s, toward your base"
+        );
+        let mut elsewhere = BTreeMap::new();
+        elsewhere.insert(START.to_string(), crate::locations::Place { x: 2900.0, z: 4000.0 });
+        let context = MapContext { named: &elsewhere, ..context };
+        assert!(alert.text(Some(&context)).ends_with("(2 cloakraid) moving west at ~90 elmos/s"));
+    }
+}
@@ -0,0 +1,255 @@
+//! Unit def catalog: every unit def of a game, fetched from the bridge the
+//! first time a channel needs it and kept until the channel closes, so the
+//! agent can browse what it could build ("what anti-air does the Cloakbot
+//! factory make?") without a query per def.
+//!
+//! Besides the `game_unitdefs` tool, the catalog classifies defs by role
+//! from their builder flag, speed and build options, which is more reliable
+//! than the def-name conventions the alert features fall back on.
+
+use std::collections::HashMap;
+
+use crate::idle_builders::BuilderKind;
+use crate::sai_ipc::{UnitDefId, UnitDefInfo};
+
+/// Fields `game_unitdefs` can project.
+pub const FIELDS: &[&str] = &[
+    "id",
+    "name",
+    "human_name",
+    "description",
+    "role",
+    "metal_cost",
+    "energy_cost",
+    "build_time",
+    "health",
+    "speed",
+    "builder",
+    "build_options",
+    "metal_make",
+    "energy_make",
+    "extracts_metal",
+    "build_speed",
+];
+
+/// Fields shown when `game_unitdefs` names none.
+pub const DEFAULT_FIELDS: &[&str] = &["name", "human_name", "description", "role", "metal_cost"];
+
+/// What a def is for, from the engine's flags rather than its name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    /// Builds units without moving.
+    Factory,
+    /// Builds while mobile (constructors, commanders), or assists without
+    /// build options of its own (caretakers).
+    Constructor,
+    Structure,
+    Unit,
+}
+
+impl Role {
+    pub fn of(def: &UnitDefInfo) -> Self {
+        match (def.builder, def.speed > 0.0) {
+            (true, false) if !def.build_options.is_empty() => Role::Factory,
+            (true, _) => Role::Constructor,
+            (false, false) => Role::Structure,
+            (false, true) => Role::Unit,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Role::Factory => "factory",
+            Role::Constructor => "constructor",
+            Role::Structure => "structure",
+            Role::Unit => "unit",
+        }
+    }
+
+    pub fn builder_kind(self) -> Option<BuilderKind> {
+        match self {
+            Role::Factory => Some(BuilderKind::Factory),
+            Role::Constructor => Some(BuilderKind::Constructor),
+            Role::Structure | Role::Unit => None,
+        }
+    }
+}
+
+/// One game's unit defs.
+#[derive(Debug, Clone)]
+pub struct UnitDefCatalog {
+    defs: Vec<UnitDefInfo>,
+    by_id: HashMap<UnitDefId, usize>,
+}
+
+impl UnitDefCatalog {
+    pub fn new(defs: Vec<UnitDefInfo>) -> Self {
+        let by_id = defs.iter().enumerate().map(|(i, d)| (d.id, i)).collect();
+        Self { defs, by_id }
+    }
+
+    pub fn len(&self) -> usize {
+        self.defs.len()
+    }
+
+    /// The builder kind of every def, by name; `None` for non-builders.
+    pub fn builder_kinds(&self) -> HashMap<String, Option<BuilderKind>> {
+        self.defs.iter().map(|d| (d.name.clone(), Role::of(d).builder_kind())).collect()
+    }
+
+    /// Defs whose name, human name or description contains `filter`
+    /// (ignoring case); all of them without one.
+    pub fn search(&self, filter: Option<&str>) -> Vec<&UnitDefInfo> {
+        let Some(filter) = filter.map(str::to_lowercase) else {
+            return self.defs.iter().collect();
+        };
+        self.defs
+            .iter()
+            .filter(|d| {
+                [Some(&d.name), Some(&d.human_name), d.description.as_ref()]
+                    .into_iter()
+                    .flatten()
+                    .any(|text| text.to_lowercase().contains(&filter))
+            })
+            .collect()
+    }
+
+    /// The def called `name` exactly.
+    pub fn named(&self, name: &str) -> Option<&UnitDefInfo> {
+        self.defs.iter().find(|d| d.name == name)
+    }
+
+    pub fn by_id(&self, id: UnitDefId) -> Option<&UnitDefInfo> {
+        self.by_id.get(&id).map(|&i| &self.defs[i])
+    }
+
+    /// What `def` can build, as def names.
+    pub fn build_option_names(&self, def: &UnitDefInfo) -> Vec<String> {
+        def.build_options
+            .iter()
+            .map(|id| match self.by_id.get(id) {
+                Some(&i) => self.defs[i].name.clone(),
+                None => id.to_string(),
+            })
+            .collect()
+    }
+
+    /// `def` with only `fields`, build options as def names.
+    pub fn project(&self, def: &UnitDefInfo, fields: &[&str]) -> serde_json::Value {
+        let mut out = serde_json::Map::new();
+        for &field in fields {
+            let value = match field {
+                "id" => def.id.0.into(),
+                "name" => def.name.clone().into(),
+                "human_name" => def.human_name.clone().into(),
+                "description" => def.description.clone().into(),
+                "role" => Role::of(def).as_str().into(),
+                "metal_cost" => def.metal_cost.into(),
+                "energy_cost" => def.energy_cost.into(),
+                "build_time" => def.build_time.into(),
+                "health" => def.health.into(),
+                "speed" => def.speed.into(),
+                "builder" => def.builder.into(),
+                "build_options" => self.build_option_names(def).into(),
+                "metal_make" => def.metal_make.into(),
+                "energy_make" => def.energy_make.into(),
+                "extracts_metal" => def.extracts_metal.into(),
+                "build_speed" => def.build_speed.into(),
+                _ => continue,
+            };
+            out.insert(field.to_string(), value);
+        }
+        out.into()
+    }
+}
+
+/// Parse `game_unitdefs` `fields`: absent means [`DEFAULT_FIELDS`].
+pub fn parse_fields(fields: Option<&serde_json::Value>) -> Result<Vec<&'static str>, String> {
+    let Some(fields) = fields else {
+        return Ok(DEFAULT_FIELDS.to_vec());
+    };
+    let names = fields.as_array().ok_or("fields must be an array of field names")?;
+    names
+        .iter()
+        .map(|name| {
+            let name = name.as_str().unwrap_or_default();
+            FIELDS
+                .iter()
+                .find(|f| **f == name)
+                .copied()
+                .ok_or_else(|| format!("Unknown field '{}' (fields: {})", name, FIELDS.join(", ")))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn def(id: i32, name: &str, human_name: &str, description: &str) -> UnitDefInfo {
+        UnitDefInfo {
+            id: UnitDefId(id),
+            name: name.into(),
+            human_name: human_name.into(),
+            description: Some(description.into()),
+            metal_cost: 100.0,
+            energy_cost: 100.0,
+            build_time: 100.0,
+            health: 500.0,
+            speed: 2.0,
+            builder: false,
+            build_options: Vec::new(),
+            metal_make: 0.0,
+            energy_make: 0.0,
+            extracts_metal: 0.0,
+            build_speed: 0.0,
+        }
+    }
+
+    fn catalog() -> UnitDefCatalog {
+        let factory = UnitDefInfo {
+            speed: 0.0,
+            builder: true,
+            build_options: vec![UnitDefId(2), UnitDefId(3), UnitDefId(99)],
+            ..def(1, "factorycloak", "Cloakbot Factory", "Produces Cloaked Robots")
+        };
+        let con = UnitDefInfo { builder: true, ..def(2, "cloakcon", "Conjurer", "Cloaked Construction Bot") };
+        let caretaker = UnitDefInfo { speed: 0.0, builder: true, ..def(5, "staticcon", "Caretaker", "Construction Assistant") };
+        UnitDefCatalog::new(vec![
+            factory,
+            con,
+            def(3, "cloakaa", "Gremlin", "Cloaked Anti-Air Bot"),
+            UnitDefInfo { speed: 0.0, ..def(4, "turretaalaser", "Hacksaw", "Burst Anti-Air Turret") },
+            caretaker,
+        ])
+    }
+
+    #[test]
+    fn test_roles() {
+        let catalog = catalog();
+        let roles: Vec<&str> = catalog.search(None).into_iter().map(|d| Role::of(d).as_str()).collect();
+        assert_eq!(roles, ["factory", "constructor", "unit", "structure", "constructor"]);
+        let kinds = catalog.builder_kinds();
+        assert_eq!(kinds["factorycloak"], Some(BuilderKind::Factory));
+        assert_eq!(kinds["cloakaa"], None);
+    }
+
+    #[test]
+    fn test_search_and_project() {
+        let catalog = catalog();
+        let names = |filter| catalog.search(filter).iter().map(|d| d.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names(Some("anti-air")), ["cloakaa", "turretaalaser"]);
+        assert_eq!(names(Some("GREMLIN")), ["cloakaa"]);
+        assert_eq!(names(Some("factory")), ["factorycloak"]);
+        assert_eq!(names(None).len(), 5);
+
+        let fields = parse_fields(Some(&serde_json::json!(["name", "role", "build_options"]))).unwrap();
+        assert_eq!(
+            catalog.project(catalog.search(Some("factorycloak"))[0], &fields),
+            serde_json::json!({"name": "factorycloak", "role": "factory", "build_options": ["cloakcon", "cloakaa", "99"]})
+        );
+        assert_eq!(parse_fields(None).unwrap(), DEFAULT_FIELDS);
+        assert!(parse_fields(Some(&serde_json::json!(["dps"]))).unwrap_err().starts_with("Unknown field 'dps'"));
+        assert!(parse_fields(Some(&serde_json::json!("name"))).is_err());
+    }
+}
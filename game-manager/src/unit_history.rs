@@ -0,0 +1,329 @@
+//! Where units have been: the last few positions of our units and of the
+//! enemies we've seen, so the GameManager can tell which way they're going.
+//!
+//! Positions come from the events that carry one: the roster, our units
+//! created, finished, given or damaged, and enemies entering line of sight.
+//! Each unit keeps its last [`SAMPLES`] positions with their frames. Dead
+//! and captured units are dropped at once. Enemies not seen for
+//! [`FORGET_FRAMES`] are dropped too, and past [`MAX_ENEMIES`] the longest
+//! unseen go first, so a long game's history stays small.
+
+use std::collections::{HashMap, VecDeque};
+
+use serde::Serialize;
+
+use crate::positions;
+use crate::sai_ipc::{Relation, SaiEvent, UnitId};
+
+/// Positions kept per unit.
+pub const SAMPLES: usize = 8;
+
+/// Enemies unseen this long are forgotten: two game minutes.
+pub const FORGET_FRAMES: i32 = 30 * 120;
+
+/// The most enemies tracked at once.
+pub const MAX_ENEMIES: usize = 300;
+
+/// Motion is worked out from positions this recent: 20 game seconds.
+const MOTION_FRAMES: i32 = 30 * 20;
+
+/// Slower than this (elmos a second) is standing still.
+const MIN_SPEED: f32 = 5.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Side {
+    Own,
+    Enemy,
+}
+
+/// A position, x and z, and the frame it was reported at.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Sample {
+    pub frame: i32,
+    pub pos: [f32; 2],
+}
+
+/// One unit's recent positions, oldest first.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Track {
+    pub side: Side,
+    pub unit_name: Option<String>,
+    pub samples: VecDeque<Sample>,
+}
+
+impl Track {
+    fn last_frame(&self) -> i32 {
+        self.samples.back().map_or(i32::MIN, |s| s.frame)
+    }
+
+    /// Velocity in elmos a second over the recent samples, if there are
+    /// two of them apart in time and the newest is recent at `frame`.
+    fn velocity(&self, frame: i32) -> Option<[f32; 2]> {
+        let last = self.samples.back()?;
+        if frame - last.frame > MOTION_FRAMES {
+            return None;
+        }
+        let first = self.samples.iter().find(|s| last.frame - s.frame <= MOTION_FRAMES)?;
+        let secs = (last.frame - first.frame) as f32 / 30.0;
+        if secs <= 0.0 {
+            return None;
+        }
+        Some([(last.pos[0] - first.pos[0]) / secs, (last.pos[1] - first.pos[1]) / secs])
+    }
+}
+
+/// Which way a unit, or a group of them, is going.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Motion {
+    /// Eight-point compass direction.
+    pub heading: &'static str,
+    /// Elmos a second.
+    pub speed: f32,
+    /// Unit vector of the heading, x and z.
+    pub direction: [f32; 2],
+    /// Units it was worked out from.
+    pub units: usize,
+}
+
+impl Motion {
+    fn from_velocity(velocity: [f32; 2], units: usize) -> Option<Self> {
+        let speed = velocity[0].hypot(velocity[1]);
+        if speed < MIN_SPEED {
+            return None;
+        }
+        Some(Motion {
+            heading: positions::compass(velocity[0], velocity[1]),
+            speed,
+            direction: [velocity[0] / speed, velocity[1] / speed],
+            units,
+        })
+    }
+
+    /// "moving west at ~90 elmos/s"
+    pub fn text(&self) -> String {
+        format!("moving {} at ~{:.0} elmos/s", self.heading, (self.speed / 10.0).round() * 10.0)
+    }
+}
+
+/// One channel's unit positions.
+#[derive(Debug, Default)]
+pub struct UnitHistory {
+    frame: i32,
+    tracks: HashMap<UnitId, Track>,
+}
+
+impl UnitHistory {
+    pub fn observe(&mut self, event: &SaiEvent) {
+        let xz = |pos: &[f32; 3]| [pos[0], pos[2]];
+        match event {
+            SaiEvent::Init { frame, .. } => self.frame = *frame,
+            SaiEvent::Update { frame, .. } => {
+                self.frame = *frame;
+                self.evict();
+            }
+            SaiEvent::Roster { frame, units } => {
+                self.frame = *frame;
+                for unit in units {
+                    self.sample(unit.unit, Side::Own, &unit.unit_name, xz(&unit.pos));
+                }
+            }
+            SaiEvent::UnitCreated { unit, unit_name, pos: Some(pos), .. }
+            | SaiEvent::UnitFinished { unit, unit_name, pos: Some(pos) }
+            | SaiEvent::UnitGiven { unit, unit_name, pos: Some(pos), .. }
+            | SaiEvent::UnitDamaged { unit, unit_name, pos: Some(pos), .. } => {
+                self.sample(*unit, Side::Own, unit_name, xz(pos));
+            }
+            SaiEvent::EnemyEnterLos { relation: Some(Relation::Gaia), .. } => {}
+            SaiEvent::EnemyEnterLos { enemy, enemy_name, pos: Some(pos), .. } => {
+                self.sample(*enemy, Side::Enemy, enemy_name, xz(pos));
+            }
+            SaiEvent::UnitDestroyed { unit, .. } | SaiEvent::UnitCaptured { unit, .. } => {
+                self.tracks.remove(unit);
+            }
+            SaiEvent::EnemyDestroyed { enemy, .. } => {
+                self.tracks.remove(enemy);
+            }
+            _ => {}
+        }
+    }
+
+    fn sample(&mut self, unit: UnitId, side: Side, name: &Option<String>, pos: [f32; 2]) {
+        let track = self.tracks.entry(unit).or_insert_with(|| Track { side, unit_name: None, samples: VecDeque::new() });
+        // A unit given to us was an enemy's, and an id may be reused.
+        if track.side != side {
+            *track = Track { side, unit_name: None, samples: VecDeque::new() };
+        }
+        if name.is_some() {
+            track.unit_name = name.clone();
+        }
+        let sample = Sample { frame: self.frame, pos };
+        match track.samples.back_mut() {
+            Some(last) if last.frame == sample.frame => *last = sample,
+            _ => track.samples.push_back(sample),
+        }
+        if track.samples.len() > SAMPLES {
+            track.samples.pop_front();
+        }
+    }
+
+    /// Forget enemies unseen for too long, then the longest unseen past
+    /// the cap.
+    fn evict(&mut self) {
+        let frame = self.frame;
+        self.tracks.retain(|_, t| t.side == Side::Own || frame - t.last_frame() <= FORGET_FRAMES);
+        let mut enemies: Vec<(i32, UnitId)> =
+            self.tracks.iter().filter(|(_, t)| t.side == Side::Enemy).map(|(id, t)| (t.last_frame(), *id)).collect();
+        if enemies.len() > MAX_ENEMIES {
+            enemies.sort();
+            for (_, unit) in &enemies[..enemies.len() - MAX_ENEMIES] {
+                self.tracks.remove(unit);
+            }
+        }
+    }
+
+    pub fn frame(&self) -> i32 {
+        self.frame
+    }
+
+    pub fn track(&self, unit: UnitId) -> Option<&Track> {
+        self.tracks.get(&unit)
+    }
+
+    /// How `unit` is moving, if it was seen moving lately.
+    pub fn motion(&self, unit: UnitId) -> Option<Motion> {
+        Motion::from_velocity(self.tracks.get(&unit)?.velocity(self.frame)?, 1)
+    }
+
+    /// How `units` are moving together: their mean velocity, over those
+    /// with recent positions. None when they are still or unknown.
+    pub fn group_motion(&self, units: &[UnitId]) -> Option<Motion> {
+        let velocities: Vec<[f32; 2]> =
+            units.iter().filter_map(|u| self.tracks.get(u)?.velocity(self.frame)).collect();
+        if velocities.is_empty() {
+            return None;
+        }
+        let n = velocities.len() as f32;
+        let mean = [velocities.iter().map(|v| v[0]).sum::<f32>() / n, velocities.iter().map(|v| v[1]).sum::<f32>() / n];
+        Motion::from_velocity(mean, velocities.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sai_protocol::{RosterUnit, TeamId, WeaponDefId};
+
+    fn update(frame: i32) -> SaiEvent {
+        SaiEvent::Update { frame, awaiting_commands: false, economy: None, allies: Vec::new(), counters: Default::default(), command_backlog: 0, under_construction: Vec::new(), update_interval: None }
+    }
+
+    fn seen(enemy: i32, x: f32, z: f32) -> SaiEvent {
+        SaiEvent::EnemyEnterLos { enemy: UnitId(enemy), enemy_name: Some("cloakraid".into()), team: None, relation: None, pos: Some([x, 0.0, z]) }
+    }
+
+    fn at(history: &mut UnitHistory, frame: i32, event: SaiEvent) {
+        history.observe(&update(frame));
+        history.observe(&event);
+    }
+
+    #[test]
+    fn test_enemy_motion() {
+        let mut history = UnitHistory::default();
+        // Two raiders run west at 90 elmos a second.
+        for (i, frame) in [30, 60, 90].into_iter().enumerate() {
+            at(&mut history, frame, seen(501, 3000.0 - 90.0 * i as f32, 1000.0));
+            history.observe(&seen(502, 3000.0 - 90.0 * i as f32, 1200.0));
+        }
+        let track = history.track(UnitId(501)).unwrap();
+        assert_eq!((track.side, track.unit_name.as_deref(), track.samples.len()), (Side::Enemy, Some("cloakraid"), 3));
+        let motion = history.motion(UnitId(501)).unwrap();
+        assert_eq!((motion.heading, motion.speed.round()), ("west", 90.0));
+        assert_eq!(motion.text(), "moving west at ~90 elmos/s");
+
+        let group = history.group_motion(&[UnitId(501), UnitId(502), UnitId(999)]).unwrap();
+        assert_eq!((group.heading, group.units), ("west", 2));
+
+        // One sighting says nothing about motion, nor does standing still.
+        at(&mut history, 120, seen(503, 100.0, 100.0));
+        assert!(history.motion(UnitId(503)).is_none());
+        at(&mut history, 150, seen(503, 101.0, 100.0));
+        assert!(history.motion(UnitId(503)).is_none());
+        // Opposite motions cancel out.
+        at(&mut history, 180, seen(504, 0.0, 0.0));
+        history.observe(&seen(505, 0.0, 90.0));
+        at(&mut history, 210, seen(504, 0.0, 90.0));
+        history.observe(&seen(505, 0.0, 0.0));
+        assert_eq!(history.motion(UnitId(504)).unwrap().heading, "south");
+        assert!(history.group_motion(&[UnitId(504), UnitId(505)]).is_none());
+    }
+
+    #[test]
+    fn test_own_units() {
+        let mut history = UnitHistory::default();
+        history.observe(&SaiEvent::Roster {
+            frame: 0,
+            units: vec![RosterUnit { unit: UnitId(1), unit_name: Some("dyntrainer_strike_base".into()), pos: [500.0, 0.0, 500.0] }],
+        });
+        let hit = SaiEvent::UnitDamaged {
+            unit: UnitId(1),
+            unit_name: None,
+            attacker: UnitId(501),
+            attacker_name: None,
+            attacker_team: Some(TeamId(1)),
+            attacker_relation: None,
+            damage: 10.0,
+            weapon_def_id: WeaponDefId(1),
+            paralyzer: false,
+            paralysis: None,
+            pos: Some([500.0, 0.0, 800.0]),
+        };
+        at(&mut history, 300, hit);
+        let track = history.track(UnitId(1)).unwrap();
+        assert_eq!(track.side, Side::Own);
+        assert_eq!(track.unit_name.as_deref(), Some("dyntrainer_strike_base"), "a nameless event keeps the name");
+        assert_eq!(track.samples.iter().map(|s| s.frame).collect::<Vec<_>>(), [0, 300]);
+        assert_eq!(history.motion(UnitId(1)).unwrap().heading, "south");
+
+        // Own units are never forgotten for being still, only when lost.
+        history.observe(&update(300 + FORGET_FRAMES * 2));
+        assert!(history.track(UnitId(1)).is_some());
+        assert!(history.motion(UnitId(1)).is_none(), "positions this old say nothing about motion");
+        history.observe(&SaiEvent::UnitCaptured { unit: UnitId(1), unit_name: None, old_team: TeamId(0), new_team: TeamId(1), new_relation: None });
+        assert!(history.track(UnitId(1)).is_none());
+    }
+
+    #[test]
+    fn test_bounds() {
+        let mut history = UnitHistory::default();
+        // Only the last few positions are kept.
+        for frame in 0..SAMPLES as i32 + 5 {
+            at(&mut history, frame * 30, seen(501, frame as f32 * 50.0, 0.0));
+        }
+        let samples = &history.track(UnitId(501)).unwrap().samples;
+        assert_eq!(samples.len(), SAMPLES);
+        assert_eq!(samples[0].frame, 5 * 30);
+
+        // A dead enemy goes at once; one long unseen when the next update says so.
+        at(&mut history, 600, seen(502, 0.0, 0.0));
+        history.observe(&SaiEvent::EnemyDestroyed {
+            enemy: UnitId(501), enemy_name: None, team: None, relation: None, attacker: UnitId(1), attacker_name: None, attacker_team: None, attacker_relation: None,
+        });
+        assert!(history.track(UnitId(501)).is_none());
+        history.observe(&update(600 + FORGET_FRAMES));
+        assert!(history.track(UnitId(502)).is_some());
+        history.observe(&update(601 + FORGET_FRAMES));
+        assert!(history.track(UnitId(502)).is_none());
+
+        // Past the cap, the longest unseen enemies make room.
+        let start = 10_000;
+        for i in 0..MAX_ENEMIES as i32 + 10 {
+            at(&mut history, start + i, seen(1000 + i, 0.0, 0.0));
+        }
+        history.observe(&update(start + MAX_ENEMIES as i32 + 10));
+        assert_eq!(history.tracks.len(), MAX_ENEMIES);
+        assert!(history.track(UnitId(1009)).is_none() && history.track(UnitId(1010)).is_some());
+    }
+}
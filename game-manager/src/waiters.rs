@@ -0,0 +1,445 @@
+//! `game_wait_for`: tool calls answered once something happens in a game,
+//! so the agent doesn't spend turns polling.
+//!
+//! A waiter keeps the MCPL request id of the call, its channel, condition
+//! and deadline. Every SAI event of the channel is checked against it, after
+//! the stream observer's state has taken the event in. The call is answered
+//! when the condition fires, the timeout passes, the condition can no longer
+//! fire (the awaited unit died), or the game goes away — whichever comes
+//! first. A condition that already holds is answered at once.
+
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+use crate::observer::ChannelState;
+use crate::sai_ipc::{self, SaiEvent, UnitId};
+
+pub const DEFAULT_TIMEOUT_SECS: f64 = 60.0;
+pub const MAX_TIMEOUT_SECS: f64 = 600.0;
+/// Calls waiting at once on one channel.
+pub const MAX_WAITERS_PER_CHANNEL: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Resource {
+    Metal,
+    Energy,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Field {
+    #[default]
+    Current,
+    Income,
+}
+
+/// What a waiter waits for.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Condition {
+    /// One of our units finished building.
+    UnitFinished { unit_id: UnitId },
+    /// The game reached a frame.
+    Frame { frame: i32 },
+    /// A resource's stock or income crossed a threshold.
+    Economy {
+        resource: Resource,
+        #[serde(default)]
+        field: Field,
+        #[serde(default)]
+        above: Option<f32>,
+        #[serde(default)]
+        below: Option<f32>,
+    },
+    /// Any event of a type, such as `enemy_enter_los`.
+    Event { event: String },
+    /// The bridge released: the game is over for this AI.
+    GameEnded,
+}
+
+/// A waiter's check against an event.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Check {
+    Pending,
+    Fired,
+    /// The condition can't fire any more.
+    Impossible(String),
+}
+
+impl Condition {
+    pub fn parse(value: &serde_json::Value) -> Result<Self, String> {
+        let condition: Self = serde_json::from_value(value.clone()).map_err(|e| format!("Invalid condition: {}", e))?;
+        match &condition {
+            Condition::Economy { above, below, .. } if above.is_some() == below.is_some() => {
+                Err("Invalid condition: an economy condition needs exactly one of above and below".into())
+            }
+            Condition::Event { event } if event.is_empty() => Err("Invalid condition: event is empty".into()),
+            _ => Ok(condition),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Condition::UnitFinished { unit_id } => format!("unit #{} to finish", unit_id),
+            Condition::Frame { frame } => format!("frame {}", frame),
+            Condition::Economy { resource, field, above, below } => {
+                let (side, threshold) = match (above, below) {
+                    (Some(t), _) => ("above", t),
+                    (_, Some(t)) => ("below", t),
+                    _ => ("at", &0.0),
+                };
+                format!("{:?} {:?} {} {}", resource, field, side, threshold).to_lowercase()
+            }
+            Condition::Event { event } => format!("a {} event", event),
+            Condition::GameEnded => "the game to end".into(),
+        }
+    }
+
+    /// Whether it already holds, before any event.
+    pub fn holds(&self, state: &ChannelState) -> bool {
+        match self {
+            Condition::Frame { frame } => state.frame >= *frame,
+            Condition::Economy { .. } => self.economy_holds(state),
+            _ => false,
+        }
+    }
+
+    /// Check an event, with `state` already updated by it.
+    pub fn check(&self, event: &SaiEvent, state: &ChannelState) -> Check {
+        let fired = match self {
+            Condition::UnitFinished { unit_id } => match event {
+                SaiEvent::UnitFinished { unit, .. } => unit == unit_id,
+                SaiEvent::UnitDestroyed { unit, .. } if unit == unit_id => {
+                    return Check::Impossible(format!("unit #{} was destroyed", unit_id))
+                }
+                SaiEvent::UnitCaptured { unit, .. } if unit == unit_id => {
+                    return Check::Impossible(format!("unit #{} changed hands", unit_id))
+                }
+                _ => false,
+            },
+            Condition::Frame { frame } => state.frame >= *frame,
+            Condition::Economy { .. } => {
+                matches!(event, SaiEvent::Update { economy: Some(_), .. }) && self.economy_holds(state)
+            }
+            Condition::Event { event: wanted } => event.type_name() == wanted,
+            Condition::GameEnded => matches!(event, SaiEvent::Release { .. }),
+        };
+        if fired {
+            Check::Fired
+        } else {
+            Check::Pending
+        }
+    }
+
+    fn economy_holds(&self, state: &ChannelState) -> bool {
+        let (Condition::Economy { resource, field, above, below }, Some(economy)) = (self, &state.economy) else {
+            return false;
+        };
+        let stock = match resource {
+            Resource::Metal => &economy.metal,
+            Resource::Energy => &economy.energy,
+        };
+        let value = match field {
+            Field::Current => stock.current,
+            Field::Income => stock.income,
+        };
+        above.is_some_and(|t| value >= t) || below.is_some_and(|t| value <= t)
+    }
+}
+
+/// How a wait ended.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Resolution {
+    /// With the event that fired it; None when it held already.
+    Fired(Option<SaiEvent>),
+    TimedOut,
+    /// The condition can't fire: the unit died, the game ended, the channel
+    /// closed.
+    Gone(String),
+}
+
+/// A `game_wait_for` call waiting for its answer.
+#[derive(Debug, Clone)]
+pub struct Waiter {
+    /// The MCPL request to answer.
+    pub request_id: serde_json::Value,
+    /// The call's arguments, for the audit log.
+    pub args: serde_json::Value,
+    pub channel_id: String,
+    pub condition: Condition,
+    pub since: Instant,
+    pub deadline: Instant,
+}
+
+impl Waiter {
+    pub fn new(
+        request_id: serde_json::Value,
+        args: &serde_json::Value,
+        channel_id: &str,
+        condition: Condition,
+        timeout: Duration,
+        now: Instant,
+    ) -> Self {
+        Self {
+            request_id,
+            args: args.clone(),
+            channel_id: channel_id.to_string(),
+            condition,
+            since: now,
+            deadline: now + timeout,
+        }
+    }
+
+    /// The tool result for a resolution, at `frame`.
+    pub fn answer(&self, resolution: &Resolution, frame: i32, now: Instant) -> serde_json::Value {
+        answer(&self.condition, resolution, frame, now.duration_since(self.since))
+    }
+}
+
+/// The tool result of a wait for `condition`.
+pub fn answer(condition: &Condition, resolution: &Resolution, frame: i32, waited: Duration) -> serde_json::Value {
+    let waited_secs = (waited.as_secs_f64() * 10.0).round() / 10.0;
+    let (text, fired, reason, event) = match resolution {
+        Resolution::Fired(event) => {
+            let mut text = format!("Done waiting for {} at frame {} after {}s", condition.describe(), frame, waited_secs);
+            if let Some(event) = event {
+                text += &format!(": {}", sai_ipc::summarize_event(event));
+            }
+            (text, true, "fired", event.as_ref())
+        }
+        Resolution::TimedOut => (
+            format!("Timed out after {}s waiting for {} (now at frame {})", waited_secs, condition.describe(), frame),
+            false,
+            "timeout",
+            None,
+        ),
+        Resolution::Gone(why) => (format!("Stopped waiting for {}: {}", condition.describe(), why), false, "gone", None),
+    };
+    let mut result = serde_json::json!({
+        "content": [{"type": "text", "text": text}],
+        "structuredContent": {
+            "fired": fired,
+            "reason": reason,
+            "condition": condition.describe(),
+            "frame": frame,
+            "waitedSecs": waited_secs,
+            "event": event.and_then(|e| serde_json::to_value(e).ok()),
+        }
+    });
+    if let Resolution::Gone(_) = resolution {
+        result["isError"] = true.into();
+    }
+    result
+}
+
+/// The timeout of a call, from `timeout_s`.
+pub fn timeout(args: &serde_json::Value) -> Result<Duration, String> {
+    let secs = match args.get("timeout_s") {
+        None | Some(serde_json::Value::Null) => DEFAULT_TIMEOUT_SECS,
+        Some(v) => v.as_f64().ok_or("timeout_s must be a number")?,
+    };
+    if !(secs > 0.0 && secs <= MAX_TIMEOUT_SECS) {
+        return Err(format!("timeout_s must be above 0 and at most {}", MAX_TIMEOUT_SECS));
+    }
+    Ok(Duration::from_secs_f64(secs))
+}
+
+/// Every call waiting, on every channel.
+#[derive(Debug, Default)]
+pub struct Waiters {
+    waiters: Vec<Waiter>,
+}
+
+impl Waiters {
+    pub fn add(&mut self, waiter: Waiter) -> Result<(), String> {
+        if self.count(&waiter.channel_id) >= MAX_WAITERS_PER_CHANNEL {
+            return Err(format!(
+                "{} already has {} calls waiting; let some finish first",
+                waiter.channel_id, MAX_WAITERS_PER_CHANNEL
+            ));
+        }
+        self.waiters.push(waiter);
+        Ok(())
+    }
+
+    pub fn count(&self, channel_id: &str) -> usize {
+        self.waiters.iter().filter(|w| w.channel_id == channel_id).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.waiters.is_empty()
+    }
+
+    /// The waiters an event of `channel_id` resolves, taken out.
+    pub fn check(&mut self, channel_id: &str, event: &SaiEvent, state: &ChannelState) -> Vec<(Waiter, Resolution)> {
+        let mut resolved = Vec::new();
+        self.waiters.retain(|w| {
+            if w.channel_id != channel_id {
+                return true;
+            }
+            let resolution = match w.condition.check(event, state) {
+                Check::Pending => return true,
+                Check::Fired => Resolution::Fired(Some(event.clone())),
+                Check::Impossible(why) => Resolution::Gone(why),
+            };
+            resolved.push((w.clone(), resolution));
+            false
+        });
+        resolved
+    }
+
+    /// The waiters whose deadline passed, taken out.
+    pub fn expired(&mut self, now: Instant) -> Vec<Waiter> {
+        self.take(|w| w.deadline <= now)
+    }
+
+    /// A channel's waiters, taken out.
+    pub fn remove_channel(&mut self, channel_id: &str) -> Vec<Waiter> {
+        self.take(|w| w.channel_id == channel_id)
+    }
+
+    /// Every waiter, taken out.
+    pub fn take_all(&mut self) -> Vec<Waiter> {
+        std::mem::take(&mut self.waiters)
+    }
+
+    fn take(&mut self, pick: impl Fn(&Waiter) -> bool) -> Vec<Waiter> {
+        let (taken, kept) = std::mem::take(&mut self.waiters).into_iter().partition(pick);
+        self.waiters = kept;
+        taken
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sai_protocol::{Economy, ResourceState};
+
+    fn event(json: serde_json::Value) -> SaiEvent {
+        serde_json::from_value(json).unwrap()
+    }
+
+    fn update(frame: i32, metal: f32) -> SaiEvent {
+        let metal = ResourceState { current: metal, income: 3.0, usage: 1.0, storage: 500.0, received: 0.0 };
+        SaiEvent::Update {
+            frame,
+            awaiting_commands: false,
+            economy: Some(Economy { metal, energy: ResourceState::default() }),
+            allies: Vec::new(),
+            counters: Default::default(),
+            command_backlog: 0,
+            under_construction: Vec::new(),
+            update_interval: None,
+        }
+    }
+
+    fn checks(condition: &Condition, events: &[SaiEvent]) -> Vec<Check> {
+        let mut state = ChannelState::default();
+        events
+            .iter()
+            .map(|e| {
+                state.observe(e);
+                condition.check(e, &state)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_conditions() {
+        let parse = |v| Condition::parse(&v);
+        assert_eq!(
+            parse(serde_json::json!({"type": "unit_finished", "unit_id": 42})),
+            Ok(Condition::UnitFinished { unit_id: UnitId(42) })
+        );
+        let economy = parse(serde_json::json!({"type": "economy", "resource": "metal", "above": 500})).unwrap();
+        assert_eq!(economy.describe(), "metal current above 500");
+        assert!(parse(serde_json::json!({"type": "economy", "resource": "metal"})).unwrap_err().contains("exactly one"));
+        assert!(parse(serde_json::json!({"type": "frame"})).unwrap_err().contains("missing field `frame`"));
+        assert!(parse(serde_json::json!({"type": "frame", "frame": 60, "when": 1})).unwrap_err().contains("unknown field"));
+        assert!(parse(serde_json::json!({"type": "sunrise"})).unwrap_err().contains("unknown variant"));
+    }
+
+    #[test]
+    fn test_conditions_fire() {
+        use Check::*;
+        let finished = event(serde_json::json!({"type": "unit_finished", "unit": 42}));
+        let other = event(serde_json::json!({"type": "unit_finished", "unit": 7}));
+        let died = event(serde_json::json!({"type": "unit_destroyed", "unit": 42, "attacker": 9, "weapon_def_id": 3}));
+        let unit = Condition::UnitFinished { unit_id: UnitId(42) };
+        assert_eq!(checks(&unit, &[other.clone(), finished]), [Pending, Fired]);
+        assert_eq!(checks(&unit, &[other, died]), [Pending, Impossible("unit #42 was destroyed".into())]);
+
+        let frame = Condition::Frame { frame: 60 };
+        assert_eq!(checks(&frame, &[update(30, 0.0), update(60, 0.0)]), [Pending, Fired]);
+
+        let metal = Condition::parse(&serde_json::json!({"type": "economy", "resource": "metal", "below": 50})).unwrap();
+        assert_eq!(checks(&metal, &[update(30, 200.0), update(60, 40.0)]), [Pending, Fired]);
+        let income = serde_json::json!({"type": "economy", "resource": "metal", "field": "income", "above": 5});
+        assert_eq!(checks(&Condition::parse(&income).unwrap(), &[update(30, 0.0)]), [Pending]);
+
+        let seen = Condition::Event { event: "enemy_enter_los".into() };
+        let enemy = event(serde_json::json!({"type": "enemy_enter_los", "enemy": 300}));
+        assert_eq!(checks(&seen, &[update(30, 0.0), enemy]), [Pending, Fired]);
+        let release = event(serde_json::json!({"type": "release", "reason": 2}));
+        assert_eq!(checks(&Condition::GameEnded, &[update(30, 0.0), release]), [Pending, Fired]);
+
+        // Already true before any event.
+        let mut state = ChannelState::default();
+        state.observe(&update(90, 600.0));
+        assert!(frame.holds(&state) && !metal.holds(&state) && !unit.holds(&state));
+    }
+
+    #[test]
+    fn test_waiters_resolve_and_expire() {
+        let now = Instant::now();
+        let mut waiters = Waiters::default();
+        let waiter = |id: u64, channel: &str, condition: Condition, secs: u64| {
+            Waiter::new(id.into(), &serde_json::json!({}), channel, condition, Duration::from_secs(secs), now)
+        };
+        waiters.add(waiter(1, "game:local-1", Condition::Frame { frame: 60 }, 30)).unwrap();
+        waiters.add(waiter(2, "game:local-1", Condition::GameEnded, 5)).unwrap();
+        waiters.add(waiter(3, "game:local-2", Condition::Frame { frame: 60 }, 30)).unwrap();
+
+        let mut state = ChannelState::default();
+        let tick = update(60, 0.0);
+        state.observe(&tick);
+        let resolved = waiters.check("game:local-1", &tick, &state);
+        assert_eq!(resolved.len(), 1);
+        assert_eq!((resolved[0].0.request_id.clone(), &resolved[0].1), (1.into(), &Resolution::Fired(Some(tick))));
+
+        assert!(waiters.expired(now + Duration::from_secs(4)).is_empty());
+        let expired = waiters.expired(now + Duration::from_secs(5));
+        assert_eq!(expired.iter().map(|w| w.request_id.clone()).collect::<Vec<_>>(), [serde_json::json!(2)]);
+        assert_eq!(waiters.remove_channel("game:local-2").len(), 1);
+        assert!(waiters.is_empty());
+
+        for id in 0..MAX_WAITERS_PER_CHANNEL as u64 {
+            waiters.add(waiter(id, "game:local-1", Condition::GameEnded, 5)).unwrap();
+        }
+        assert!(waiters.add(waiter(99, "game:local-1", Condition::GameEnded, 5)).unwrap_err().contains("16 calls waiting"));
+        assert_eq!(waiters.take_all().len(), MAX_WAITERS_PER_CHANNEL);
+    }
+
+    #[test]
+    fn test_answers() {
+        let condition = Condition::UnitFinished { unit_id: UnitId(42) };
+        let finished = event(serde_json::json!({"type": "unit_finished", "unit": 42, "unit_name": "factorycloak"}));
+        let result = answer(&condition, &Resolution::Fired(Some(finished)), 900, Duration::from_millis(12_340));
+        assert_eq!(
+            result["content"][0]["text"],
+            "Done waiting for unit #42 to finish at frame 900 after 12.3s: Your factorycloak (#42) is finished"
+        );
+        assert_eq!(result["structuredContent"]["event"]["unit"], 42);
+        assert!(result.get("isError").is_none());
+
+        let result = answer(&condition, &Resolution::TimedOut, 930, Duration::from_secs(60));
+        assert_eq!(result["content"][0]["text"], "Timed out after 60s waiting for unit #42 to finish (now at frame 930)");
+        assert_eq!((result["structuredContent"]["fired"].clone(), result.get("isError")), (false.into(), None));
+
+        let result = answer(&condition, &Resolution::Gone("the channel closed".into()), 930, Duration::ZERO);
+        assert_eq!(result["isError"], true);
+        assert_eq!(result["structuredContent"]["reason"], "gone");
+    }
+}
@@ -7,6 +7,8 @@
 
 use std::path::{Path, PathBuf};
 
+use crate::sai_ipc::BuildInfo;
+
 /// Directories to symlink from spring_home into the agent write-dir.
 /// Note: `cache` is intentionally excluded — ArchiveCache20.lua stores absolute
 /// paths, so sharing it across different write-dirs causes a full rescan anyway,
@@ -42,7 +44,7 @@ pub fn init_write_dir(
 
     // 2. Create subdirs
     let subdirs = [
-        "AI/Skirmish/AgentBridge/0.1",
+        SAI_DATA_DIR,
         "AI/Interfaces",
         "LuaUI/Widgets",
         "LuaUI/Config",
@@ -106,29 +108,7 @@ pub fn init_write_dir(
     }
 
     // 4. Install SAI bridge
-    let ai_dir = base.join("AI/Skirmish/AgentBridge/0.1");
-    let lib_dest = ai_dir.join("libSkirmishAI.so");
-    if sai_bridge_lib.exists() {
-        if should_update(&lib_dest, sai_bridge_lib)? {
-            std::fs::copy(sai_bridge_lib, &lib_dest)?;
-            tracing::info!("  Installed libSkirmishAI.so");
-        }
-    } else {
-        tracing::warn!(
-            "  SAI bridge lib not found at {}, skipping",
-            sai_bridge_lib.display()
-        );
-    }
-
-    // Copy AIInfo.lua and AIOptions.lua
-    for name in &["AIInfo.lua", "AIOptions.lua"] {
-        let src = sai_bridge_data.join(name);
-        let dest = ai_dir.join(name);
-        if src.exists() && should_update(&dest, &src)? {
-            std::fs::copy(&src, &dest)?;
-            tracing::info!("  Installed {}", name);
-        }
-    }
+    install_sai_bridge(base, sai_bridge_lib, sai_bridge_data)?;
 
     // 5. Install startup widget
     let widget_dest = base.join("LuaUI/Widgets/agent_bootstrap.lua");
@@ -167,6 +147,158 @@ pub fn init_write_dir(
     Ok(())
 }
 
+/// Where the SAI bridge is installed, and where it looks for connection.json.
+pub const SAI_DATA_DIR: &str = "AI/Skirmish/AgentBridge/0.1";
+
+/// The SAI bridge library's file name.
+pub const SAI_LIB: &str = "libSkirmishAI.so";
+
+/// Record of the installed bridge's build, next to the library.
+pub const SAI_BUILD_FILE: &str = "bridge-build.json";
+
+/// What the bridge library compiles in ahead of its `version+commit`.
+const BUILD_MARKER: &[u8] = b"sai-bridge-build:";
+
+/// The installed bridge, as recorded at install time.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct InstalledBridge {
+    /// The library's build marker; None for bridges built without one.
+    pub build: Option<BuildInfo>,
+    /// The library it was copied from.
+    pub source: PathBuf,
+    pub installed_at: String,
+}
+
+/// The version and commit compiled into a bridge library, if it has them.
+pub fn embedded_build(lib: &Path) -> Option<BuildInfo> {
+    let bytes = std::fs::read(lib).ok()?;
+    let start = bytes.windows(BUILD_MARKER.len()).position(|w| w == BUILD_MARKER)? + BUILD_MARKER.len();
+    let len = bytes[start..].iter().take(128).position(|b| *b == 0)?;
+    let text = std::str::from_utf8(&bytes[start..start + len]).ok()?;
+    let (version, hash) = text.split_once('+')?;
+    Some(BuildInfo {
+        bridge_version: version.to_string(),
+        git_hash: Some(hash.to_string()).filter(|h| !h.is_empty()),
+        engine_version: None,
+    })
+}
+
+/// The installed bridge's build record, if there is one.
+pub fn installed_bridge(write_dir: &Path) -> Option<InstalledBridge> {
+    let text = std::fs::read_to_string(write_dir.join(SAI_DATA_DIR).join(SAI_BUILD_FILE)).ok()?;
+    serde_json::from_str(&text).ok()
+}
+
+/// Where the SAI bridge is installed from: the built library and the
+/// directory with its AIInfo.lua and AIOptions.lua.
+#[derive(Debug, Clone)]
+pub struct SaiBridgeSource {
+    pub lib: PathBuf,
+    pub data: PathBuf,
+}
+
+/// Copy the SAI bridge library and its metadata into the write dir where
+/// missing or out of date. A missing source is skipped with a warning.
+fn install_sai_bridge(base: &Path, sai_bridge_lib: &Path, sai_bridge_data: &Path) -> anyhow::Result<()> {
+    let ai_dir = base.join(SAI_DATA_DIR);
+    std::fs::create_dir_all(&ai_dir)?;
+    let lib_dest = ai_dir.join(SAI_LIB);
+    let record = ai_dir.join(SAI_BUILD_FILE);
+    if sai_bridge_lib.exists() {
+        if should_update(&lib_dest, sai_bridge_lib)? || !record.exists() {
+            std::fs::copy(sai_bridge_lib, &lib_dest)?;
+            let installed = InstalledBridge {
+                build: embedded_build(&lib_dest),
+                source: sai_bridge_lib.to_path_buf(),
+                installed_at: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+            };
+            std::fs::write(&record, serde_json::to_string_pretty(&installed)?)?;
+            match &installed.build {
+                Some(build) => tracing::info!("  Installed {} {}", SAI_LIB, build.describe()),
+                None => tracing::info!("  Installed {} (no build marker)", SAI_LIB),
+            }
+        }
+    } else {
+        tracing::warn!(
+            "  SAI bridge lib not found at {}, skipping",
+            sai_bridge_lib.display()
+        );
+    }
+
+    // Copy AIInfo.lua and AIOptions.lua
+    for name in &["AIInfo.lua", "AIOptions.lua"] {
+        let src = sai_bridge_data.join(name);
+        let dest = ai_dir.join(name);
+        if src.exists() && should_update(&dest, &src)? {
+            std::fs::copy(&src, &dest)?;
+            tracing::info!("  Installed {}", name);
+        }
+    }
+    Ok(())
+}
+
+/// Launch preflight: install the SAI bridge if it's missing or stale and
+/// check the installed library against the build. Without it the engine
+/// can't find the AgentBridge AI and exits. Returns the installed
+/// library's SHA-256.
+pub async fn ensure_sai_installed(write_dir: &Path, source: &SaiBridgeSource) -> Result<String, String> {
+    install_sai_bridge(write_dir, &source.lib, &source.data)
+        .map_err(|e| format!("Failed to install the SAI bridge into {}: {}", write_dir.display(), e))?;
+    let ai_dir = write_dir.join(SAI_DATA_DIR);
+    let installed = ai_dir.join(SAI_LIB);
+    if !installed.exists() {
+        return Err(match std::env::var_os("SAI_BRIDGE_LIB") {
+            Some(_) => format!("{} missing and SAI_BRIDGE_LIB ({}) does not exist", SAI_LIB, source.lib.display()),
+            None => format!(
+                "{} missing and SAI_BRIDGE_LIB not set (no build at {})",
+                SAI_LIB,
+                source.lib.display()
+            ),
+        });
+    }
+    if !ai_dir.join("AIInfo.lua").exists() {
+        return Err(format!(
+            "AIInfo.lua missing from {} (SAI_BRIDGE_DATA is {})",
+            ai_dir.display(),
+            source.data.display()
+        ));
+    }
+    let digest = crate::engine_install::sha256(&installed).await?;
+    if source.lib.exists() && crate::engine_install::sha256(&source.lib).await? != digest {
+        return Err(format!("Installed {} differs from {}", installed.display(), source.lib.display()));
+    }
+    Ok(digest)
+}
+
+/// Write connection.json into the SAI bridge's data directory: the socket
+/// to connect to, plus any keys in `extra` (an object). The bridge reads it
+/// before the startscript's AI options, so it also covers AIs created by
+/// /aicontrol, which have no [Options] block.
+pub fn write_connection_json(
+    data_dir: &Path,
+    socket_path: &str,
+    extra: &serde_json::Value,
+) -> anyhow::Result<PathBuf> {
+    let mut config = serde_json::json!({ "socket_path": socket_path });
+    if let Some(extra) = extra.as_object() {
+        for (key, value) in extra {
+            config[key] = value.clone();
+        }
+    }
+    let path = data_dir.join("connection.json");
+    std::fs::write(&path, serde_json::to_string_pretty(&config)?)?;
+    Ok(path)
+}
+
+/// Whether process `pid` is running, to tell what a live GameManager holds
+/// (its socket dir, a write dir) from what a dead one left behind.
+pub fn pid_alive(pid: u32) -> bool {
+    let Some(pid) = libc::pid_t::try_from(pid).ok().filter(|pid| *pid > 0) else { return false };
+    // Signal 0 only checks; EPERM means it runs under another user.
+    let sent = unsafe { libc::kill(pid, 0) } == 0;
+    sent || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
 /// Ensure a player name is whitelisted in the bootstrap config.
 /// For multiplayer, the lobby username may differ from the default agent_name
 /// that was written at write-dir init time.
@@ -373,6 +505,10 @@ impl WriteDirConfig {
         }
     }
 
+    pub fn sai_bridge(&self) -> SaiBridgeSource {
+        SaiBridgeSource { lib: self.sai_bridge_lib.clone(), data: self.sai_bridge_data.clone() }
+    }
+
     pub fn init(&self) -> anyhow::Result<()> {
         init_write_dir(
             &self.write_dir,
@@ -409,3 +545,60 @@ UnitIconDist=0
 MaxSounds=0
 snd_volmaster=0
 ";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pid_alive() {
+        assert!(pid_alive(std::process::id()));
+        assert!(!pid_alive(0) && !pid_alive(999_999_999));
+    }
+
+    #[test]
+    fn test_connection_json_and_whitelist() {
+        let dir = std::env::temp_dir().join(format!("gm-writedir-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("LuaUI/Config")).unwrap();
+        let path = write_connection_json(&dir, "/tmp/sai_3.sock", &serde_json::json!({"benchmark": true})).unwrap();
+        let written: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+        assert_eq!(written, serde_json::json!({"socket_path": "/tmp/sai_3.sock", "benchmark": true}));
+
+        ensure_player_whitelisted(&dir, "Agent").unwrap();
+        ensure_player_whitelisted(&dir, "Agent").unwrap();
+        let lua = std::fs::read_to_string(dir.join("LuaUI/Config/agent_bootstrap_config.lua")).unwrap();
+        assert_eq!(lua.matches("[\"Agent\"]").count(), 1);
+        assert!(lua.contains("[\"ai\"] = \"AgentBridge\""), "{}", lua);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_ensure_sai_installed() {
+        let dir = std::env::temp_dir().join(format!("gm-sai-install-{}", uuid::Uuid::new_v4()));
+        let source = SaiBridgeSource { lib: dir.join("build").join(SAI_LIB), data: dir.join("data") };
+        let write_dir = dir.join("write");
+        let err = ensure_sai_installed(&write_dir, &source).await.unwrap_err();
+        assert!(err.starts_with("libSkirmishAI.so missing and SAI_BRIDGE_LIB"), "{}", err);
+
+        std::fs::create_dir_all(dir.join("build")).unwrap();
+        std::fs::create_dir_all(&source.data).unwrap();
+        std::fs::write(&source.lib, "bridge v1").unwrap();
+        assert!(ensure_sai_installed(&write_dir, &source).await.unwrap_err().starts_with("AIInfo.lua missing"));
+
+        std::fs::write(source.data.join("AIInfo.lua"), "return {}").unwrap();
+        let v1 = ensure_sai_installed(&write_dir, &source).await.unwrap();
+        assert_eq!(std::fs::read_to_string(write_dir.join(SAI_DATA_DIR).join(SAI_LIB)).unwrap(), "bridge v1");
+        // A rebuilt bridge replaces the installed one.
+        assert_eq!(installed_bridge(&write_dir).unwrap().build, None);
+        // A rebuilt bridge replaces the installed one, and its build is recorded.
+        std::fs::write(&source.lib, b"\x7fELF...sai-bridge-build:0.2.0+abc1234\0rest").unwrap();
+        let v2 = ensure_sai_installed(&write_dir, &source).await.unwrap();
+        assert_ne!(v1, v2);
+        assert_eq!(v2, crate::engine_install::sha256(&source.lib).await.unwrap());
+        let installed = installed_bridge(&write_dir).unwrap();
+        assert_eq!(installed.source, source.lib);
+        let build = installed.build.unwrap();
+        assert_eq!((build.bridge_version.as_str(), build.git_hash.as_deref()), ("0.2.0", Some("abc1234")));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
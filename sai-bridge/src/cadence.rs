@@ -0,0 +1,173 @@
+//! Frames between forwarded updates.
+//!
+//! Fixed updates come every [`crate::UPDATE_INTERVAL`] frames. Adaptive
+//! ones (connection.json `update_cadence`) follow the fighting: each update
+//! measures the combat events per game second since the previous one. A
+//! busy rate halves the interval down to `min_frames`; a quiet rate held for
+//! [`QUIET_UPDATES`] updates grows it by half up to `max_frames`. A rate in
+//! between holds it, so a skirmish at the threshold doesn't make it swing.
+//!
+//! `set_update_interval` pins an interval over either mode until cleared,
+//! and can force the mode.
+
+use sai_protocol::{GameEvent, UpdateCadence, UpdateMode, MAX_UPDATE_INTERVAL, QUIET_UPDATES};
+
+/// Game frames per second.
+const FRAMES_PER_SECOND: f32 = 30.0;
+
+/// The adaptive controller: the next interval and quiet streak, given the
+/// current ones and the combat rate measured over the last interval.
+pub fn adapt(config: &UpdateCadence, interval: u32, quiet_updates: u32, rate: f32) -> (u32, u32) {
+    if rate >= config.busy_rate {
+        return ((interval / 2).max(config.min_frames), 0);
+    }
+    if rate > config.quiet_rate {
+        return (interval, 0);
+    }
+    if quiet_updates + 1 < QUIET_UPDATES {
+        return (interval, quiet_updates + 1);
+    }
+    ((interval + interval.div_ceil(2)).min(config.max_frames), 0)
+}
+
+/// Check a `set_update_interval` pin.
+pub fn validate_pin(frames: Option<u32>) -> Result<(), String> {
+    match frames {
+        Some(frames) if !(1..=MAX_UPDATE_INTERVAL).contains(&frames) => {
+            Err(format!("set_update_interval: {} frames is outside 1-{}", frames, MAX_UPDATE_INTERVAL))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[derive(Debug)]
+pub struct Cadence {
+    config: UpdateCadence,
+    /// The fixed mode's interval.
+    fixed: u32,
+    /// The adaptive mode's interval, adapted even while pinned.
+    adaptive: u32,
+    quiet_updates: u32,
+    /// `set_update_interval` overrides.
+    forced_mode: Option<UpdateMode>,
+    pinned: Option<u32>,
+    /// Combat events since the last update.
+    combat_events: u32,
+}
+
+impl Cadence {
+    pub fn new(config: UpdateCadence, fixed: u32) -> Self {
+        let adaptive = fixed.clamp(config.min_frames, config.max_frames);
+        Self { config, fixed, adaptive, quiet_updates: 0, forced_mode: None, pinned: None, combat_events: 0 }
+    }
+
+    pub fn mode(&self) -> UpdateMode {
+        self.forced_mode.unwrap_or(self.config.mode)
+    }
+
+    /// Frames from one update to the next.
+    pub fn interval(&self) -> u32 {
+        match (self.pinned, self.mode()) {
+            (Some(frames), _) => frames,
+            (None, UpdateMode::Fixed) => self.fixed,
+            (None, UpdateMode::Adaptive) => self.adaptive,
+        }
+    }
+
+    /// Count a parsed event, before it is filtered or aggregated.
+    pub fn observe(&mut self, event: &GameEvent) {
+        if matches!(
+            event,
+            GameEvent::UnitDamaged { .. }
+                | GameEvent::UnitDestroyed { .. }
+                | GameEvent::EnemyDamaged { .. }
+                | GameEvent::EnemyDestroyed { .. }
+        ) {
+            self.combat_events += 1;
+        }
+    }
+
+    /// An update goes out `frames` after the previous one: adapt to the
+    /// rate since then and return the interval to the next.
+    pub fn updated(&mut self, frames: u32) -> u32 {
+        let rate = self.combat_events as f32 * FRAMES_PER_SECOND / frames.max(1) as f32;
+        self.combat_events = 0;
+        (self.adaptive, self.quiet_updates) = adapt(&self.config, self.adaptive, self.quiet_updates, rate);
+        self.interval()
+    }
+
+    /// Apply `set_update_interval`.
+    pub fn set(&mut self, frames: Option<u32>, mode: Option<UpdateMode>) -> Result<(), String> {
+        validate_pin(frames)?;
+        self.pinned = frames;
+        if mode.is_some() {
+            self.forced_mode = mode;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Run the controller over a series of rates, from `interval`.
+    fn intervals(config: &UpdateCadence, mut interval: u32, rates: &[f32]) -> Vec<u32> {
+        let mut quiet = 0;
+        rates
+            .iter()
+            .map(|&rate| {
+                (interval, quiet) = adapt(config, interval, quiet, rate);
+                interval
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_battle_then_calm() {
+        let config = UpdateCadence { mode: UpdateMode::Adaptive, ..Default::default() };
+        // A battle breaks out: down to the minimum and no further.
+        assert_eq!(intervals(&config, 30, &[6.0, 9.0, 12.0, 5.0]), [15, 10, 10, 10]);
+        // Calm returns: three quiet updates per step up, to the maximum.
+        let calm = intervals(&config, 10, &[0.0; 18]);
+        assert_eq!(calm, [10, 10, 15, 15, 15, 23, 23, 23, 35, 35, 35, 53, 53, 53, 80, 80, 80, 90]);
+    }
+
+    #[test]
+    fn test_hysteresis() {
+        let config = UpdateCadence { mode: UpdateMode::Adaptive, ..Default::default() };
+        // Rates between the thresholds hold the interval, and break a quiet
+        // streak: a rate wavering around the quiet threshold never grows it.
+        assert_eq!(intervals(&config, 30, &[2.0, 3.9, 1.0, 2.5]), [30; 4]);
+        assert_eq!(intervals(&config, 30, &[0.4, 0.6, 0.4, 0.6, 0.4, 0.6]), [30; 6]);
+        // Nor does one busy update followed by calm snap back at once.
+        assert_eq!(intervals(&config, 30, &[4.0, 0.0, 0.0, 0.0]), [15, 15, 15, 23]);
+    }
+
+    #[test]
+    fn test_pin_and_force() {
+        let config = UpdateCadence { mode: UpdateMode::Adaptive, ..Default::default() };
+        let mut cadence = Cadence::new(config, 30);
+        let damage: GameEvent =
+            serde_json::from_value(serde_json::json!({"type": "enemy_destroyed", "enemy": 9, "attacker": 1})).unwrap();
+        for _ in 0..5 {
+            cadence.observe(&damage);
+        }
+        // 5 events over 30 frames is busy.
+        assert_eq!(cadence.updated(30), 15);
+
+        cadence.set(Some(60), None).unwrap();
+        cadence.observe(&damage);
+        cadence.observe(&damage);
+        assert_eq!(cadence.updated(15), 60);
+        // Adaptation went on underneath: clearing the pin picks it up.
+        cadence.set(None, None).unwrap();
+        assert_eq!(cadence.interval(), 10);
+
+        cadence.set(None, Some(UpdateMode::Fixed)).unwrap();
+        assert_eq!(cadence.updated(10), 30);
+        assert_eq!(cadence.mode(), UpdateMode::Fixed);
+        assert!(cadence.set(Some(0), None).unwrap_err().contains("0 frames is outside 1-9000"));
+        assert_eq!(cadence.interval(), 30);
+    }
+}
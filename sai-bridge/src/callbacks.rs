@@ -11,9 +11,13 @@ mod bindings {
 }
 pub use bindings::SSkirmishAICallback;
 
-use std::ffi::{c_char, c_float, c_int, c_void, CStr, CString};
+use std::ffi::{c_char, c_float, c_int, c_void};
 use std::os::raw::c_short;
 
+use sai_protocol::{FeatureId, QueuedCommand, TeamId, UnitDefId, UnitId};
+
+use crate::strings;
+
 /// Safe wrapper around the raw callback pointer table.
 pub struct EngineCallbacks {
     pub(crate) ai_id: c_int,
@@ -46,18 +50,46 @@ impl EngineCallbacks {
         call!(self, Game_getCurrentFrame, self.ai_id)
     }
 
-    pub fn get_my_team(&self) -> i32 {
-        call!(self, Game_getMyTeam, self.ai_id)
+    pub fn get_my_team(&self) -> TeamId {
+        TeamId(call!(self, Game_getMyTeam, self.ai_id))
     }
 
     pub fn get_my_ally_team(&self) -> i32 {
         call!(self, Game_getMyAllyTeam, self.ai_id)
     }
 
+    /// Number of teams in the game, gaia included.
+    pub fn get_teams(&self) -> i32 {
+        call!(self, Game_getTeams, self.ai_id)
+    }
+
+    /// The ally team `team` belongs to.
+    pub fn get_team_ally_team(&self, team: TeamId) -> i32 {
+        call!(self, Game_getTeamAllyTeam, self.ai_id, team.0)
+    }
+
     pub fn is_paused(&self) -> bool {
         call!(self, Game_isPaused, self.ai_id)
     }
 
+    /// The speed the game runs at: 1.0 is real time.
+    pub fn get_speed_factor(&self) -> f32 {
+        call!(self, Game_getSpeedFactor, self.ai_id)
+    }
+
+    /// The engine's version string, e.g. `105.1.1-2511-g747f18b`.
+    pub fn engine_version(&self) -> Option<String> {
+        let ptr = call!(self, Engine_Version_getNormal, self.ai_id);
+        unsafe { strings::from_c_lossy(ptr) }
+    }
+
+    /// The startscript the game was launched with. Player names live only
+    /// here — the interface has no per-player name callback.
+    pub fn get_setup_script(&self) -> Option<String> {
+        let ptr = call!(self, Game_getSetupScript, self.ai_id);
+        unsafe { strings::from_c_lossy(ptr) }
+    }
+
     // ── Economy ──
 
     pub fn economy_current(&self, resource_id: i32) -> f32 {
@@ -76,45 +108,204 @@ impl EngineCallbacks {
         call!(self, Economy_getStorage, self.ai_id, resource_id)
     }
 
+    /// Resources allies shared to us in the last second.
+    pub fn economy_received(&self, resource_id: i32) -> f32 {
+        call!(self, Economy_getReceived, self.ai_id, resource_id)
+    }
+
+    // The engine answers these only for allied teams: -1 for any other.
+
+    pub fn team_resource_current(&self, team: TeamId, resource_id: i32) -> f32 {
+        call!(self, Game_getTeamResourceCurrent, self.ai_id, team.0, resource_id)
+    }
+
+    pub fn team_resource_income(&self, team: TeamId, resource_id: i32) -> f32 {
+        call!(self, Game_getTeamResourceIncome, self.ai_id, team.0, resource_id)
+    }
+
+    pub fn team_resource_usage(&self, team: TeamId, resource_id: i32) -> f32 {
+        call!(self, Game_getTeamResourceUsage, self.ai_id, team.0, resource_id)
+    }
+
+    pub fn team_resource_storage(&self, team: TeamId, resource_id: i32) -> f32 {
+        call!(self, Game_getTeamResourceStorage, self.ai_id, team.0, resource_id)
+    }
+
+    pub fn team_resource_received(&self, team: TeamId, resource_id: i32) -> f32 {
+        call!(self, Game_getTeamResourceReceived, self.ai_id, team.0, resource_id)
+    }
+
     // ── Unit queries ──
 
     /// Resolve a unit definition name (e.g. "cloakraid") to its numeric ID.
-    pub fn get_unit_def_by_name(&self, name: &str) -> Option<i32> {
-        let c_name = CString::new(name).ok()?;
+    pub fn get_unit_def_by_name(&self, name: &str) -> Option<UnitDefId> {
+        let c_name = strings::name(name)?;
         let id = call!(self, getUnitDefByName, self.ai_id, c_name.as_ptr());
-        if id < 0 { None } else { Some(id) }
+        if id < 0 { None } else { Some(UnitDefId(id)) }
+    }
+
+    /// Team of a unit; -1 if it doesn't exist or isn't visible to us.
+    pub fn unit_get_team(&self, unit_id: UnitId) -> TeamId {
+        TeamId(call!(self, Unit_getTeam, self.ai_id, unit_id.0))
+    }
+
+    /// IDs of all units owned by this AI's team.
+    pub fn get_team_units(&self) -> Vec<UnitId> {
+        // A null array asks the engine for the count only.
+        let count = call!(self, getTeamUnits, self.ai_id, std::ptr::null_mut(), 0);
+        if count <= 0 {
+            return Vec::new();
+        }
+        let mut ids = vec![0 as c_int; count as usize];
+        let n = call!(self, getTeamUnits, self.ai_id, ids.as_mut_ptr(), count);
+        ids.truncate(n.clamp(0, count) as usize);
+        ids.into_iter().map(UnitId).collect()
+    }
+
+    /// IDs of every unit definition in the game.
+    pub fn get_unit_defs(&self) -> Vec<UnitDefId> {
+        let count = call!(self, getUnitDefs, self.ai_id, std::ptr::null_mut(), 0);
+        if count <= 0 {
+            return Vec::new();
+        }
+        let mut ids = vec![0 as c_int; count as usize];
+        let n = call!(self, getUnitDefs, self.ai_id, ids.as_mut_ptr(), count);
+        ids.truncate(n.clamp(0, count) as usize);
+        ids.into_iter().map(UnitDefId).collect()
     }
 
     /// Get the unit definition ID for a given unit instance.
-    pub fn unit_get_def(&self, unit_id: i32) -> i32 {
-        call!(self, Unit_getDef, self.ai_id, unit_id)
+    pub fn unit_get_def(&self, unit_id: UnitId) -> UnitDefId {
+        UnitDefId(call!(self, Unit_getDef, self.ai_id, unit_id.0))
+    }
+
+    /// The engine's limit on unit ids. An order that takes a unit or a
+    /// feature (reclaim) reads ids from this one up as features.
+    pub fn unit_get_max(&self) -> i32 {
+        call!(self, Unit_getMax, self.ai_id)
     }
 
     /// Get a unit's current position as [x, y, z].
-    pub fn unit_get_pos(&self, unit_id: i32) -> [f32; 3] {
+    pub fn unit_get_pos(&self, unit_id: UnitId) -> [f32; 3] {
         let mut pos = [0.0f32; 3];
-        call!(self, Unit_getPos, self.ai_id, unit_id, pos.as_mut_ptr());
+        call!(self, Unit_getPos, self.ai_id, unit_id.0, pos.as_mut_ptr());
         pos
     }
 
+    /// Current health of a unit; 0 if it doesn't exist or isn't visible.
+    pub fn unit_get_health(&self, unit_id: UnitId) -> f32 {
+        call!(self, Unit_getHealth, self.ai_id, unit_id.0)
+    }
+
+    pub fn unit_get_max_health(&self, unit_id: UnitId) -> f32 {
+        call!(self, Unit_getMaxHealth, self.ai_id, unit_id.0)
+    }
+
+    /// Paralyze (EMP) damage a unit has taken and not yet shaken off.
+    pub fn unit_get_paralyze_damage(&self, unit_id: UnitId) -> f32 {
+        call!(self, Unit_getParalyzeDamage, self.ai_id, unit_id.0)
+    }
+
+    /// The commands in a unit's queue, oldest first. A factory's queue
+    /// holds its build orders.
+    pub fn unit_get_current_commands(&self, unit_id: UnitId) -> Vec<QueuedCommand> {
+        let count = call!(self, Unit_getCurrentCommands, self.ai_id, unit_id.0);
+        (0..count.max(0))
+            .map(|index| {
+                let id = call!(self, Unit_CurrentCommand_getId, self.ai_id, unit_id.0, index);
+                let mut params = vec![0.0 as c_float; MAX_COMMAND_PARAMS];
+                let n = call!(
+                    self,
+                    Unit_CurrentCommand_getParams,
+                    self.ai_id,
+                    unit_id.0,
+                    index,
+                    params.as_mut_ptr(),
+                    MAX_COMMAND_PARAMS as c_int
+                );
+                params.truncate(n.clamp(0, MAX_COMMAND_PARAMS as c_int) as usize);
+                QueuedCommand { id, params }
+            })
+            .collect()
+    }
+
+    /// How far along a unit under construction is, 0.0 to 1.0.
+    pub fn unit_get_build_progress(&self, unit_id: UnitId) -> f32 {
+        call!(self, Unit_getBuildProgress, self.ai_id, unit_id.0)
+    }
+
     /// Get the internal name of a unit definition (e.g. "cloakraid").
-    pub fn unit_def_get_name(&self, unit_def_id: i32) -> Option<String> {
-        let ptr = call!(self, UnitDef_getName, self.ai_id, unit_def_id);
-        if ptr.is_null() {
-            None
-        } else {
-            Some(unsafe { CStr::from_ptr(ptr) }.to_string_lossy().into_owned())
-        }
+    pub fn unit_def_get_name(&self, unit_def_id: UnitDefId) -> Option<String> {
+        let ptr = call!(self, UnitDef_getName, self.ai_id, unit_def_id.0);
+        unsafe { strings::from_c_lossy(ptr) }
     }
 
     /// Get the human-readable name of a unit definition (e.g. "Glaive").
-    pub fn unit_def_get_human_name(&self, unit_def_id: i32) -> Option<String> {
-        let ptr = call!(self, UnitDef_getHumanName, self.ai_id, unit_def_id);
-        if ptr.is_null() {
-            None
-        } else {
-            Some(unsafe { CStr::from_ptr(ptr) }.to_string_lossy().into_owned())
+    pub fn unit_def_get_human_name(&self, unit_def_id: UnitDefId) -> Option<String> {
+        let ptr = call!(self, UnitDef_getHumanName, self.ai_id, unit_def_id.0);
+        unsafe { strings::from_c_lossy(ptr) }
+    }
+
+    /// Get the tooltip of a unit definition (e.g. "Cloaked Anti-Air Bot").
+    pub fn unit_def_get_tooltip(&self, unit_def_id: UnitDefId) -> Option<String> {
+        let ptr = call!(self, UnitDef_getTooltip, self.ai_id, unit_def_id.0);
+        unsafe { strings::from_c_lossy(ptr) }
+    }
+
+    /// Cost of a unit definition in one resource.
+    pub fn unit_def_get_cost(&self, unit_def_id: UnitDefId, resource_id: i32) -> f32 {
+        call!(self, UnitDef_getCost, self.ai_id, unit_def_id.0, resource_id)
+    }
+
+    pub fn unit_def_get_build_time(&self, unit_def_id: UnitDefId) -> f32 {
+        call!(self, UnitDef_getBuildTime, self.ai_id, unit_def_id.0)
+    }
+
+    pub fn unit_def_get_health(&self, unit_def_id: UnitDefId) -> f32 {
+        call!(self, UnitDef_getHealth, self.ai_id, unit_def_id.0)
+    }
+
+    pub fn unit_def_get_speed(&self, unit_def_id: UnitDefId) -> f32 {
+        call!(self, UnitDef_getSpeed, self.ai_id, unit_def_id.0)
+    }
+
+    /// Resource a unit definition makes per second just by existing.
+    pub fn unit_def_get_resource_make(&self, unit_def_id: UnitDefId, resource_id: i32) -> f32 {
+        call!(self, UnitDef_getResourceMake, self.ai_id, unit_def_id.0, resource_id)
+    }
+
+    /// How much of a resource a unit definition extracts from the map.
+    pub fn unit_def_get_extracts_resource(&self, unit_def_id: UnitDefId, resource_id: i32) -> f32 {
+        call!(self, UnitDef_getExtractsResource, self.ai_id, unit_def_id.0, resource_id)
+    }
+
+    /// Build power of a unit definition, in build time per second.
+    pub fn unit_def_get_build_speed(&self, unit_def_id: UnitDefId) -> f32 {
+        call!(self, UnitDef_getBuildSpeed, self.ai_id, unit_def_id.0)
+    }
+
+    pub fn unit_def_is_builder(&self, unit_def_id: UnitDefId) -> bool {
+        call!(self, UnitDef_isBuilder, self.ai_id, unit_def_id.0)
+    }
+
+    /// IDs of the unit definitions a unit definition can build.
+    pub fn unit_def_get_build_options(&self, unit_def_id: UnitDefId) -> Vec<UnitDefId> {
+        let count = call!(self, UnitDef_getBuildOptions, self.ai_id, unit_def_id.0, std::ptr::null_mut(), 0);
+        if count <= 0 {
+            return Vec::new();
         }
+        let mut ids = vec![0 as c_int; count as usize];
+        let n = call!(self, UnitDef_getBuildOptions, self.ai_id, unit_def_id.0, ids.as_mut_ptr(), count);
+        ids.truncate(n.clamp(0, count) as usize);
+        ids.into_iter().map(UnitDefId).collect()
+    }
+
+    // ── Features ──
+
+    /// The def of a feature (wreck, tree, rock); -1 if it doesn't exist or
+    /// isn't visible to us.
+    pub fn feature_get_def(&self, feature_id: FeatureId) -> i32 {
+        call!(self, Feature_getDef, self.ai_id, feature_id.0)
     }
 
     // ── Map ──
@@ -127,14 +318,27 @@ impl EngineCallbacks {
         call!(self, Map_getHeight, self.ai_id)
     }
 
+    /// The AI team's start position; None while the engine hasn't placed
+    /// it (x is negative then).
+    pub fn map_get_start_pos(&self) -> Option<[f32; 3]> {
+        let mut pos = [0.0f32; 3];
+        call!(self, Map_getStartPos, self.ai_id, pos.as_mut_ptr());
+        if pos[0] < 0.0 { None } else { Some(pos) }
+    }
+
+    /// Ground height at a map position, in elmos.
+    pub fn map_elevation_at(&self, x: f32, z: f32) -> f32 {
+        call!(self, Map_getElevationAt, self.ai_id, x, z)
+    }
+
     /// Check if a building can be placed at a given position.
-    pub fn map_can_build_at(&self, unit_def_id: i32, pos: &[f32; 3], facing: i32) -> bool {
+    pub fn map_can_build_at(&self, unit_def_id: UnitDefId, pos: &[f32; 3], facing: i32) -> bool {
         let mut pos_copy = *pos;
         call!(
             self,
             Map_isPossibleToBuildAt,
             self.ai_id,
-            unit_def_id,
+            unit_def_id.0,
             pos_copy.as_mut_ptr(),
             facing
         )
@@ -144,7 +348,7 @@ impl EngineCallbacks {
     /// Returns None if no valid position found (engine returns {-1, 0, 0}).
     pub fn map_find_closest_build_site(
         &self,
-        unit_def_id: i32,
+        unit_def_id: UnitDefId,
         pos: &[f32; 3],
         search_radius: f32,
         min_dist: i32,
@@ -156,7 +360,7 @@ impl EngineCallbacks {
             self,
             Map_findClosestBuildSite,
             self.ai_id,
-            unit_def_id,
+            unit_def_id.0,
             pos_copy.as_mut_ptr(),
             search_radius,
             min_dist,
@@ -174,7 +378,7 @@ impl EngineCallbacks {
     // ── GameRulesParams ──
 
     pub fn game_rules_param_float(&self, name: &str, default: f32) -> f32 {
-        let c_name = CString::new(name).ok().unwrap();
+        let Some(c_name) = strings::name(name) else { return default };
         call!(self, Game_getRulesParamFloat, self.ai_id, c_name.as_ptr(), default)
     }
 
@@ -199,9 +403,9 @@ impl EngineCallbacks {
     // ── Logging ──
 
     pub fn log(&self, msg: &str) {
-        if let Ok(c_msg) = CString::new(msg) {
-            call!(self, Log_log, self.ai_id, c_msg.as_ptr());
-        }
+        // NULs are dropped without a note: the note would be logged too.
+        let c_msg = strings::to_c(msg, usize::MAX).c_string;
+        call!(self, Log_log, self.ai_id, c_msg.as_ptr());
     }
 
     // ── Commands ──
@@ -213,7 +417,8 @@ impl EngineCallbacks {
     ) -> c_int {
         // commandId must be -1 to use NETMSG_AICOMMAND (14).
         // Any other value triggers NETMSG_AICOMMAND_TRACKED (76)
-        // which this engine version's server does not handle.
+        // which this engine version's server does not handle. The engine
+        // echoes the id in command_finished, so that is always -1 too.
         call!(
             self,
             Engine_handleCommand,
@@ -228,33 +433,25 @@ impl EngineCallbacks {
     // ── AI info / options ──
 
     pub fn get_info_value(&self, key: &str) -> Option<String> {
-        let c_key = CString::new(key).ok()?;
+        let c_key = strings::name(key)?;
         let ptr = call!(
             self,
             SkirmishAI_Info_getValueByKey,
             self.ai_id,
             c_key.as_ptr()
         );
-        if ptr.is_null() {
-            None
-        } else {
-            Some(unsafe { CStr::from_ptr(ptr) }.to_string_lossy().into_owned())
-        }
+        unsafe { strings::from_c_lossy(ptr) }
     }
 
     pub fn get_option_value(&self, key: &str) -> Option<String> {
-        let c_key = CString::new(key).ok()?;
+        let c_key = strings::name(key)?;
         let ptr = call!(
             self,
             SkirmishAI_OptionValues_getValueByKey,
             self.ai_id,
             c_key.as_ptr()
         );
-        if ptr.is_null() {
-            None
-        } else {
-            Some(unsafe { CStr::from_ptr(ptr) }.to_string_lossy().into_owned())
-        }
+        unsafe { strings::from_c_lossy(ptr) }
     }
 }
 
@@ -262,11 +459,18 @@ impl EngineCallbacks {
 
 pub const COMMAND_TO_ID_ENGINE: c_int = -1;
 
+/// Most parameters read back per queued command. Area and build orders
+/// take four or five.
+pub const MAX_COMMAND_PARAMS: usize = 16;
+
 // Engine-level command topics (from AISCommands.h CommandTopic enum)
+pub const COMMAND_DRAWER_POINT_ADD: c_int = 1;
 pub const COMMAND_SEND_TEXT_MESSAGE: c_int = 6;
 pub const COMMAND_PAUSE: c_int = 81;
 pub const COMMAND_UNIT_BUILD: c_int = 35;
 pub const COMMAND_UNIT_STOP: c_int = 36;
+pub const COMMAND_UNIT_WAIT: c_int = 37;
+pub const COMMAND_UNIT_WAIT_TIME: c_int = 38;
 pub const COMMAND_UNIT_MOVE: c_int = 42;
 pub const COMMAND_UNIT_PATROL: c_int = 43;
 pub const COMMAND_UNIT_FIGHT: c_int = 44;
@@ -276,8 +480,20 @@ pub const COMMAND_UNIT_GUARD: c_int = 47;
 pub const COMMAND_UNIT_REPAIR: c_int = 51;
 pub const COMMAND_UNIT_SET_FIRE_STATE: c_int = 52;
 pub const COMMAND_UNIT_SET_MOVE_STATE: c_int = 53;
+pub const COMMAND_UNIT_SELF_DESTROY: c_int = 55;
+pub const COMMAND_UNIT_LOAD_UNITS: c_int = 57;
+pub const COMMAND_UNIT_LOAD_ONTO: c_int = 59;
+pub const COMMAND_UNIT_UNLOAD_UNITS_AREA: c_int = 60;
 pub const COMMAND_UNIT_RECLAIM_UNIT: c_int = 63;
 pub const COMMAND_UNIT_RECLAIM_AREA: c_int = 64;
+pub const COMMAND_UNIT_D_GUN: c_int = 67;
+pub const COMMAND_UNIT_D_GUN_POS: c_int = 68;
+pub const COMMAND_UNIT_CUSTOM: c_int = 78;
+
+/// The engine's own attack command id (CMD_ATTACK). The AI interface has
+/// no topic for attacking a position, so that goes out as a custom command
+/// with this id and the position as its params.
+pub const CMD_ATTACK: c_int = 20;
 
 // Command option flags
 pub const UNIT_COMMAND_OPTION_SHIFT_KEY: c_short = 1 << 5;
@@ -301,6 +517,24 @@ pub struct SStopUnitCommand {
     pub time_out: c_int,
 }
 
+#[repr(C)]
+pub struct SWaitUnitCommand {
+    pub unit_id: c_int,
+    pub group_id: c_int,
+    pub options: c_short,
+    pub time_out: c_int,
+}
+
+#[repr(C)]
+pub struct STimeWaitUnitCommand {
+    pub unit_id: c_int,
+    pub group_id: c_int,
+    pub options: c_short,
+    pub time_out: c_int,
+    /// Seconds of game time.
+    pub time: c_int,
+}
+
 #[repr(C)]
 pub struct SAttackUnitCommand {
     pub unit_id: c_int,
@@ -310,6 +544,16 @@ pub struct SAttackUnitCommand {
     pub to_attack_unit_id: c_int,
 }
 
+#[repr(C)]
+pub struct SAttackAreaUnitCommand {
+    pub unit_id: c_int,
+    pub group_id: c_int,
+    pub options: c_short,
+    pub time_out: c_int,
+    pub to_attack_pos: *mut [c_float; 3],
+    pub radius: c_float,
+}
+
 #[repr(C)]
 pub struct SBuildUnitCommand {
     pub unit_id: c_int,
@@ -357,6 +601,91 @@ pub struct SRepairUnitCommand {
     pub to_repair_unit_id: c_int,
 }
 
+#[repr(C)]
+pub struct SReclaimUnitUnitCommand {
+    pub unit_id: c_int,
+    pub group_id: c_int,
+    pub options: c_short,
+    pub time_out: c_int,
+    pub to_reclaim_unit_id_or_feature_id: c_int,
+}
+
+#[repr(C)]
+pub struct SReclaimAreaUnitCommand {
+    pub unit_id: c_int,
+    pub group_id: c_int,
+    pub options: c_short,
+    pub time_out: c_int,
+    pub pos: *mut [c_float; 3],
+    pub radius: c_float,
+}
+
+#[repr(C)]
+pub struct SSelfDestroyUnitCommand {
+    pub unit_id: c_int,
+    pub group_id: c_int,
+    pub options: c_short,
+    pub time_out: c_int,
+}
+
+#[repr(C)]
+pub struct SDGunUnitCommand {
+    pub unit_id: c_int,
+    pub group_id: c_int,
+    pub options: c_short,
+    pub time_out: c_int,
+    pub to_attack_unit_id: c_int,
+}
+
+#[repr(C)]
+pub struct SDGunPosUnitCommand {
+    pub unit_id: c_int,
+    pub group_id: c_int,
+    pub options: c_short,
+    pub time_out: c_int,
+    pub pos: *mut [c_float; 3],
+}
+
+#[repr(C)]
+pub struct SLoadUnitsUnitCommand {
+    pub unit_id: c_int,
+    pub group_id: c_int,
+    pub options: c_short,
+    pub time_out: c_int,
+    pub to_load_unit_ids: *mut c_int,
+    pub to_load_unit_ids_size: c_int,
+}
+
+#[repr(C)]
+pub struct SLoadOntoUnitCommand {
+    pub unit_id: c_int,
+    pub group_id: c_int,
+    pub options: c_short,
+    pub time_out: c_int,
+    pub transporter_unit_id: c_int,
+}
+
+#[repr(C)]
+pub struct SUnloadUnitsAreaUnitCommand {
+    pub unit_id: c_int,
+    pub group_id: c_int,
+    pub options: c_short,
+    pub time_out: c_int,
+    pub to_pos: *mut [c_float; 3],
+    pub radius: c_float,
+}
+
+#[repr(C)]
+pub struct SCustomUnitCommand {
+    pub unit_id: c_int,
+    pub group_id: c_int,
+    pub options: c_short,
+    pub time_out: c_int,
+    pub cmd_id: c_int,
+    pub params: *mut c_float,
+    pub params_size: c_int,
+}
+
 #[repr(C)]
 pub struct SSendTextMessageCommand {
     pub text: *const c_char,
@@ -381,6 +710,13 @@ pub struct SSetMoveStateUnitCommand {
     pub move_state: c_int,
 }
 
+#[repr(C)]
+pub struct SAddPointDrawCommand {
+    /// Only x and z reach the map; markers sit on the ground.
+    pub pos: *mut [c_float; 3],
+    pub label: *const c_char,
+}
+
 #[repr(C)]
 pub struct SPauseCommand {
     pub enable: bool,
@@ -2,107 +2,12 @@
 //! converts them to C structs, and calls Engine_handleCommand.
 
 use crate::callbacks::*;
-use serde::Deserialize;
+use crate::strings;
 use std::ffi::{c_float, c_int, c_void, CString};
 
 /// Commands received from GameManager over IPC.
-#[derive(Debug, Deserialize)]
-#[serde(tag = "type")]
-pub enum GameCommand {
-    #[serde(rename = "move")]
-    Move {
-        unit_id: i32,
-        x: f32,
-        y: f32,
-        z: f32,
-        #[serde(default)]
-        queue: bool,
-    },
-
-    #[serde(rename = "stop")]
-    Stop { unit_id: i32 },
-
-    #[serde(rename = "attack")]
-    Attack {
-        unit_id: i32,
-        target_id: i32,
-        #[serde(default)]
-        queue: bool,
-    },
-
-    #[serde(rename = "build")]
-    Build {
-        unit_id: i32,
-        #[serde(default)]
-        build_def_id: i32,
-        #[serde(default)]
-        build_def_name: Option<String>,
-        #[serde(default)]
-        x: f32,
-        #[serde(default)]
-        y: f32,
-        #[serde(default)]
-        z: f32,
-        #[serde(default)]
-        facing: i32,
-        #[serde(default)]
-        queue: bool,
-    },
-
-    #[serde(rename = "patrol")]
-    Patrol {
-        unit_id: i32,
-        x: f32,
-        y: f32,
-        z: f32,
-        #[serde(default)]
-        queue: bool,
-    },
-
-    #[serde(rename = "fight")]
-    Fight {
-        unit_id: i32,
-        x: f32,
-        y: f32,
-        z: f32,
-        #[serde(default)]
-        queue: bool,
-    },
-
-    #[serde(rename = "guard")]
-    Guard {
-        unit_id: i32,
-        guard_id: i32,
-        #[serde(default)]
-        queue: bool,
-    },
-
-    #[serde(rename = "repair")]
-    Repair {
-        unit_id: i32,
-        repair_id: i32,
-        #[serde(default)]
-        queue: bool,
-    },
-
-    #[serde(rename = "set_fire_state")]
-    SetFireState { unit_id: i32, state: i32 },
-
-    #[serde(rename = "set_move_state")]
-    SetMoveState { unit_id: i32, state: i32 },
-
-    #[serde(rename = "send_chat")]
-    SendChat { text: String },
-
-    #[serde(rename = "pause")]
-    Pause,
-
-    #[serde(rename = "unpause")]
-    Unpause,
-
-    #[serde(rename = "set_speed")]
-    SetSpeed { speed: f32 },
-}
+pub use sai_protocol::{ChatDestination, GameCommand};
+use sai_protocol::{ReclaimTarget, UnitId};
 
 /// Translate engine return codes to human-readable errors.
 fn describe_error(code: c_int) -> &'static str {
@@ -118,9 +23,9 @@ fn describe_error(code: c_int) -> &'static str {
 
 /// Validate that a unit_id refers to an existing unit owned by the AI.
 /// Returns a descriptive error if not.
-fn validate_unit(cb: &EngineCallbacks, unit_id: i32) -> Result<(), String> {
+fn validate_unit(cb: &EngineCallbacks, unit_id: UnitId) -> Result<(), String> {
     let def_id = cb.unit_get_def(unit_id);
-    if def_id < 0 {
+    if def_id.0 < 0 {
         return Err(format!(
             "unit {} does not exist (unit_get_def returned {})",
             unit_id, def_id
@@ -129,6 +34,212 @@ fn validate_unit(cb: &EngineCallbacks, unit_id: i32) -> Result<(), String> {
     Ok(())
 }
 
+/// Map squares are this many elmos across.
+pub(crate) const SQUARE_SIZE: f32 = 8.0;
+
+/// Reject positions off the map. Map width and height are in map squares.
+fn validate_pos(cb: &EngineCallbacks, x: f32, z: f32) -> Result<(), String> {
+    let (width, height) = (cb.map_width() as f32 * SQUARE_SIZE, cb.map_height() as f32 * SQUARE_SIZE);
+    if !(0.0..=width).contains(&x) || !(0.0..=height).contains(&z) {
+        return Err(format!("position ({}, {}) is outside the {}x{} map", x, z, width, height));
+    }
+    Ok(())
+}
+
+/// Area orders need a circle to work in.
+fn validate_radius(radius: f32) -> Result<(), String> {
+    if radius > 0.0 {
+        Ok(())
+    } else {
+        Err(format!("radius must be positive, got {}", radius))
+    }
+}
+
+/// A transport and the units it is to pick up: all of them there, each
+/// once, and none of them the transport.
+fn validate_load(cb: &EngineCallbacks, unit_id: UnitId, target_ids: &[UnitId]) -> Result<(), String> {
+    validate_unit(cb, unit_id)?;
+    if target_ids.is_empty() {
+        return Err("load_units needs at least one unit in target_ids".into());
+    }
+    for (i, &target) in target_ids.iter().enumerate() {
+        if target == unit_id {
+            return Err(format!("load_units: transport {} can't load itself", unit_id));
+        }
+        if target_ids[..i].contains(&target) {
+            return Err(format!("load_units: unit {} is in target_ids twice", target));
+        }
+        validate_unit(cb, target)?;
+    }
+    Ok(())
+}
+
+/// What a manual-fire is aimed at.
+enum FireAt {
+    Unit(UnitId),
+    Ground(f32, f32),
+}
+
+/// Where a manual-fire goes: the target unit if there is one, else the
+/// ground position, which must be whole and on the map.
+fn manual_fire_target(cb: &EngineCallbacks, target_id: Option<UnitId>, x: Option<f32>, z: Option<f32>) -> Result<FireAt, String> {
+    match (target_id, x, z) {
+        (Some(target), _, _) => Ok(FireAt::Unit(target)),
+        (None, Some(x), Some(z)) => {
+            validate_pos(cb, x, z)?;
+            Ok(FireAt::Ground(x, z))
+        }
+        _ => Err("manual_fire needs a target_id, or x and z".into()),
+    }
+}
+
+/// The reclaim order's one parameter: a unit id as it is, or a feature id
+/// counted on from the engine's last unit id, which is how the engine
+/// tells the two apart.
+fn reclaim_param(cb: &EngineCallbacks, target: ReclaimTarget) -> Result<c_int, String> {
+    match target {
+        ReclaimTarget::Unit(unit) => Ok(unit.0),
+        ReclaimTarget::Feature(feature) => {
+            let def_id = cb.feature_get_def(feature);
+            if def_id < 0 {
+                return Err(format!("feature {} does not exist (feature_get_def returned {})", feature, def_id));
+            }
+            Ok(cb.unit_get_max() + feature.0)
+        }
+    }
+}
+
+/// Self-destruct can't be taken back once it goes off, so beyond the unit
+/// existing it has to be a real id of one of our own: a mistyped id that
+/// happens to name an ally's or a visible enemy's unit is refused here
+/// rather than left to the engine.
+fn validate_self_destruct(cb: &EngineCallbacks, unit_id: UnitId) -> Result<(), String> {
+    if unit_id.0 <= 0 {
+        return Err(format!("self_destruct: {} is not a unit id", unit_id));
+    }
+    validate_unit(cb, unit_id)?;
+    let team = cb.unit_get_team(unit_id);
+    if team != cb.get_my_team() {
+        return Err(format!("self_destruct: unit {} belongs to team {}, not ours", unit_id, team));
+    }
+    Ok(())
+}
+
+/// A timed wait of no time would be an endless one.
+fn validate_wait(seconds: u32) -> Result<(), String> {
+    match seconds {
+        0 => Err("time_wait needs at least 1 second".into()),
+        s if s > i32::MAX as u32 => Err(format!("time_wait of {} seconds is too long", s)),
+        _ => Ok(()),
+    }
+}
+
+/// Everything `dispatch` would check before calling the engine, plus map
+/// bounds, without calling it: the answer to a dry run.
+pub fn validate(cb: &EngineCallbacks, cmd: &GameCommand) -> Result<(), String> {
+    match cmd {
+        GameCommand::Move { unit_id, x, z, .. }
+        | GameCommand::Patrol { unit_id, x, z, .. }
+        | GameCommand::Fight { unit_id, x, z, .. }
+        | GameCommand::AttackGround { unit_id, x, z, .. } => {
+            validate_unit(cb, *unit_id)?;
+            validate_pos(cb, *x, *z)
+        }
+        GameCommand::LoadUnits { unit_id, target_ids, .. } => validate_load(cb, *unit_id, target_ids),
+        GameCommand::LoadOntoTransport { unit_id, transport_id } => {
+            validate_unit(cb, *unit_id)?;
+            validate_unit(cb, *transport_id)
+        }
+        GameCommand::ReclaimArea { unit_id, x, z, radius, .. }
+        | GameCommand::AttackArea { unit_id, x, z, radius, .. }
+        | GameCommand::UnloadUnits { unit_id, x, z, radius, .. } => {
+            validate_unit(cb, *unit_id)?;
+            validate_radius(*radius)?;
+            validate_pos(cb, *x, *z)
+        }
+        GameCommand::Build { unit_id, build_def_id, build_def_name, x, y, z, .. } => {
+            validate_unit(cb, *unit_id)?;
+            match build_def_name {
+                Some(name) => {
+                    cb.get_unit_def_by_name(name)
+                        .ok_or_else(|| format!("Unknown unit def name: {}", name))?;
+                }
+                None if cb.unit_def_get_name(*build_def_id).is_none() => {
+                    return Err(format!("Unknown unit def id: {}", build_def_id));
+                }
+                None => {}
+            }
+            // Factory production has no position.
+            if *x == 0.0 && *y == 0.0 && *z == 0.0 {
+                Ok(())
+            } else {
+                validate_pos(cb, *x, *z)
+            }
+        }
+        GameCommand::TimeWait { unit_id, seconds, .. } => {
+            validate_unit(cb, *unit_id)?;
+            validate_wait(*seconds)
+        }
+        GameCommand::SelfDestruct { unit_id } => validate_self_destruct(cb, *unit_id),
+        GameCommand::Reclaim { unit_id, target, .. } => {
+            validate_unit(cb, *unit_id)?;
+            reclaim_param(cb, *target).map(|_| ())
+        }
+        GameCommand::ManualFire { unit_id, target_id, x, z, .. } => {
+            validate_unit(cb, *unit_id)?;
+            manual_fire_target(cb, *target_id, *x, *z).map(|_| ())
+        }
+        GameCommand::Stop { unit_id }
+        | GameCommand::Wait { unit_id, .. }
+        | GameCommand::Attack { unit_id, .. }
+        | GameCommand::Guard { unit_id, .. }
+        | GameCommand::Repair { unit_id, .. }
+        | GameCommand::SetFireState { unit_id, .. }
+        | GameCommand::SetMoveState { unit_id, .. }
+        | GameCommand::Custom { unit_id, .. } => validate_unit(cb, *unit_id),
+        // Text is made to fit rather than refused (see `strings`).
+        GameCommand::SendChat { .. } => Ok(()),
+        GameCommand::DrawPoint { x, z, .. } => validate_pos(cb, *x, *z),
+        GameCommand::SetUpdateInterval { frames, .. } => crate::cadence::validate_pin(*frames),
+        GameCommand::Pause
+        | GameCommand::Unpause
+        | GameCommand::SetTurnMode { .. }
+        | GameCommand::EndTurn
+        | GameCommand::QueryUnitDefs { .. }
+        | GameCommand::QueryMapGrid { .. }
+        | GameCommand::QueryUnitQueues { .. } => Ok(()),
+        GameCommand::SetSpeed { .. } => Err(SET_SPEED_UNSUPPORTED.into()),
+        GameCommand::Unknown { raw } => Err(unrecognized(raw)),
+    }
+}
+
+const SET_SPEED_UNSUPPORTED: &str = "set_speed is not supported by the engine AI interface";
+
+fn unrecognized(raw: &serde_json::Value) -> String {
+    format!("unrecognized command type '{}'", raw.get("type").and_then(|t| t.as_str()).unwrap_or("?"))
+}
+
+/// Pause or resume the engine. Only turn mode uses this: a paused engine
+/// sends no UPDATE events, so resuming relies on other events (the bootstrap
+/// widget's heartbeat) reaching the bridge.
+pub fn set_paused(cb: &EngineCallbacks, paused: bool) -> Result<(), String> {
+    let reason = strings::to_c("agent turn", usize::MAX);
+    let mut data = SPauseCommand {
+        enable: paused,
+        reason: reason.c_string.as_ptr(),
+    };
+    let result = cb.handle_command(COMMAND_PAUSE, &mut data as *mut _ as *mut c_void);
+    if result >= 0 {
+        Ok(())
+    } else {
+        Err(format!(
+            "Engine rejected {} (code {})",
+            if paused { "pause" } else { "unpause" },
+            result
+        ))
+    }
+}
+
 /// Dispatch a GameCommand to the engine via callbacks.
 /// Returns Ok(()) on success, Err with description on failure.
 pub fn dispatch(cb: &EngineCallbacks, cmd: &GameCommand) -> Result<(), String> {
@@ -143,7 +254,7 @@ pub fn dispatch(cb: &EngineCallbacks, cmd: &GameCommand) -> Result<(), String> {
             validate_unit(cb, *unit_id)?;
             let mut pos: [c_float; 3] = [*x, *y, *z];
             let mut data = SMoveUnitCommand {
-                unit_id: *unit_id as c_int,
+                unit_id: unit_id.0,
                 group_id: -1,
                 options: if *queue { UNIT_COMMAND_OPTION_SHIFT_KEY } else { 0 },
                 time_out: i32::MAX,
@@ -155,7 +266,7 @@ pub fn dispatch(cb: &EngineCallbacks, cmd: &GameCommand) -> Result<(), String> {
         GameCommand::Stop { unit_id } => {
             validate_unit(cb, *unit_id)?;
             let mut data = SStopUnitCommand {
-                unit_id: *unit_id as c_int,
+                unit_id: unit_id.0,
                 group_id: -1,
                 options: 0,
                 time_out: i32::MAX,
@@ -163,6 +274,34 @@ pub fn dispatch(cb: &EngineCallbacks, cmd: &GameCommand) -> Result<(), String> {
             cb.handle_command(COMMAND_UNIT_STOP, &mut data as *mut _ as *mut c_void)
         }
 
+        GameCommand::Wait { unit_id, queue } => {
+            validate_unit(cb, *unit_id)?;
+            let mut data = SWaitUnitCommand {
+                unit_id: unit_id.0,
+                group_id: -1,
+                options: if *queue { UNIT_COMMAND_OPTION_SHIFT_KEY } else { 0 },
+                time_out: i32::MAX,
+            };
+            cb.handle_command(COMMAND_UNIT_WAIT, &mut data as *mut _ as *mut c_void)
+        }
+
+        GameCommand::TimeWait {
+            unit_id,
+            seconds,
+            queue,
+        } => {
+            validate_unit(cb, *unit_id)?;
+            validate_wait(*seconds)?;
+            let mut data = STimeWaitUnitCommand {
+                unit_id: unit_id.0,
+                group_id: -1,
+                options: if *queue { UNIT_COMMAND_OPTION_SHIFT_KEY } else { 0 },
+                time_out: i32::MAX,
+                time: *seconds as c_int,
+            };
+            cb.handle_command(COMMAND_UNIT_WAIT_TIME, &mut data as *mut _ as *mut c_void)
+        }
+
         GameCommand::Attack {
             unit_id,
             target_id,
@@ -170,15 +309,96 @@ pub fn dispatch(cb: &EngineCallbacks, cmd: &GameCommand) -> Result<(), String> {
         } => {
             validate_unit(cb, *unit_id)?;
             let mut data = SAttackUnitCommand {
-                unit_id: *unit_id as c_int,
+                unit_id: unit_id.0,
                 group_id: -1,
                 options: if *queue { UNIT_COMMAND_OPTION_SHIFT_KEY } else { 0 },
                 time_out: i32::MAX,
-                to_attack_unit_id: *target_id as c_int,
+                to_attack_unit_id: target_id.0,
             };
             cb.handle_command(COMMAND_UNIT_ATTACK, &mut data as *mut _ as *mut c_void)
         }
 
+        GameCommand::AttackGround {
+            unit_id,
+            x,
+            y,
+            z,
+            queue,
+        } => {
+            // The engine would take an off-map position as it is.
+            validate_unit(cb, *unit_id)?;
+            validate_pos(cb, *x, *z)?;
+            let mut params = [*x, *y, *z];
+            let mut data = SCustomUnitCommand {
+                unit_id: unit_id.0,
+                group_id: -1,
+                options: if *queue { UNIT_COMMAND_OPTION_SHIFT_KEY } else { 0 },
+                time_out: i32::MAX,
+                cmd_id: CMD_ATTACK,
+                params: params.as_mut_ptr(),
+                params_size: params.len() as c_int,
+            };
+            cb.handle_command(COMMAND_UNIT_CUSTOM, &mut data as *mut _ as *mut c_void)
+        }
+
+        GameCommand::AttackArea {
+            unit_id,
+            x,
+            y,
+            z,
+            radius,
+            queue,
+        } => {
+            validate_unit(cb, *unit_id)?;
+            validate_radius(*radius)?;
+            validate_pos(cb, *x, *z)?;
+            let mut pos: [c_float; 3] = [*x, *y, *z];
+            let mut data = SAttackAreaUnitCommand {
+                unit_id: unit_id.0,
+                group_id: -1,
+                options: if *queue { UNIT_COMMAND_OPTION_SHIFT_KEY } else { 0 },
+                time_out: i32::MAX,
+                to_attack_pos: &mut pos as *mut [c_float; 3],
+                radius: *radius,
+            };
+            cb.handle_command(COMMAND_UNIT_ATTACK_AREA, &mut data as *mut _ as *mut c_void)
+        }
+
+        GameCommand::ManualFire {
+            unit_id,
+            target_id,
+            x,
+            y,
+            z,
+            queue,
+        } => {
+            validate_unit(cb, *unit_id)?;
+            let options = if *queue { UNIT_COMMAND_OPTION_SHIFT_KEY } else { 0 };
+            match manual_fire_target(cb, *target_id, *x, *z)? {
+                FireAt::Unit(target) => {
+                    let mut data = SDGunUnitCommand {
+                        unit_id: unit_id.0,
+                        group_id: -1,
+                        options,
+                        time_out: i32::MAX,
+                        to_attack_unit_id: target.0,
+                    };
+                    cb.handle_command(COMMAND_UNIT_D_GUN, &mut data as *mut _ as *mut c_void)
+                }
+                FireAt::Ground(x, z) => {
+                    let mut pos: [c_float; 3] = [x, *y, z];
+                    let mut data = SDGunPosUnitCommand {
+                        unit_id: unit_id.0,
+                        group_id: -1,
+                        options,
+                        time_out: i32::MAX,
+                        pos: &mut pos as *mut [c_float; 3],
+                    };
+                    cb.handle_command(COMMAND_UNIT_D_GUN_POS, &mut data as *mut _ as *mut c_void)
+                }
+            }
+        }
+
         GameCommand::Build {
             unit_id,
             build_def_id,
@@ -213,11 +433,11 @@ pub fn dispatch(cb: &EngineCallbacks, cmd: &GameCommand) -> Result<(), String> {
                     })?
             };
             let mut data = SBuildUnitCommand {
-                unit_id: *unit_id as c_int,
+                unit_id: unit_id.0,
                 group_id: -1,
                 options: if *queue { UNIT_COMMAND_OPTION_SHIFT_KEY } else { 0 },
                 time_out: i32::MAX,
-                to_build_unit_def_id: def_id as c_int,
+                to_build_unit_def_id: def_id.0,
                 build_pos: &mut pos as *mut [c_float; 3],
                 facing: *facing as c_int,
             };
@@ -234,7 +454,7 @@ pub fn dispatch(cb: &EngineCallbacks, cmd: &GameCommand) -> Result<(), String> {
             validate_unit(cb, *unit_id)?;
             let mut pos: [c_float; 3] = [*x, *y, *z];
             let mut data = SPatrolUnitCommand {
-                unit_id: *unit_id as c_int,
+                unit_id: unit_id.0,
                 group_id: -1,
                 options: if *queue { UNIT_COMMAND_OPTION_SHIFT_KEY } else { 0 },
                 time_out: i32::MAX,
@@ -253,7 +473,7 @@ pub fn dispatch(cb: &EngineCallbacks, cmd: &GameCommand) -> Result<(), String> {
             validate_unit(cb, *unit_id)?;
             let mut pos: [c_float; 3] = [*x, *y, *z];
             let mut data = SFightUnitCommand {
-                unit_id: *unit_id as c_int,
+                unit_id: unit_id.0,
                 group_id: -1,
                 options: if *queue { UNIT_COMMAND_OPTION_SHIFT_KEY } else { 0 },
                 time_out: i32::MAX,
@@ -269,11 +489,11 @@ pub fn dispatch(cb: &EngineCallbacks, cmd: &GameCommand) -> Result<(), String> {
         } => {
             validate_unit(cb, *unit_id)?;
             let mut data = SGuardUnitCommand {
-                unit_id: *unit_id as c_int,
+                unit_id: unit_id.0,
                 group_id: -1,
                 options: if *queue { UNIT_COMMAND_OPTION_SHIFT_KEY } else { 0 },
                 time_out: i32::MAX,
-                to_guard_unit_id: *guard_id as c_int,
+                to_guard_unit_id: guard_id.0,
             };
             cb.handle_command(COMMAND_UNIT_GUARD, &mut data as *mut _ as *mut c_void)
         }
@@ -285,19 +505,143 @@ pub fn dispatch(cb: &EngineCallbacks, cmd: &GameCommand) -> Result<(), String> {
         } => {
             validate_unit(cb, *unit_id)?;
             let mut data = SRepairUnitCommand {
-                unit_id: *unit_id as c_int,
+                unit_id: unit_id.0,
                 group_id: -1,
                 options: if *queue { UNIT_COMMAND_OPTION_SHIFT_KEY } else { 0 },
                 time_out: i32::MAX,
-                to_repair_unit_id: *repair_id as c_int,
+                to_repair_unit_id: repair_id.0,
             };
             cb.handle_command(COMMAND_UNIT_REPAIR, &mut data as *mut _ as *mut c_void)
         }
 
+        GameCommand::Reclaim {
+            unit_id,
+            target,
+            queue,
+        } => {
+            validate_unit(cb, *unit_id)?;
+            let mut data = SReclaimUnitUnitCommand {
+                unit_id: unit_id.0,
+                group_id: -1,
+                options: if *queue { UNIT_COMMAND_OPTION_SHIFT_KEY } else { 0 },
+                time_out: i32::MAX,
+                to_reclaim_unit_id_or_feature_id: reclaim_param(cb, *target)?,
+            };
+            cb.handle_command(COMMAND_UNIT_RECLAIM_UNIT, &mut data as *mut _ as *mut c_void)
+        }
+
+        GameCommand::ReclaimArea {
+            unit_id,
+            x,
+            y,
+            z,
+            radius,
+            queue,
+        } => {
+            validate_unit(cb, *unit_id)?;
+            validate_radius(*radius)?;
+            let mut pos: [c_float; 3] = [*x, *y, *z];
+            let mut data = SReclaimAreaUnitCommand {
+                unit_id: unit_id.0,
+                group_id: -1,
+                options: if *queue { UNIT_COMMAND_OPTION_SHIFT_KEY } else { 0 },
+                time_out: i32::MAX,
+                pos: &mut pos as *mut [c_float; 3],
+                radius: *radius,
+            };
+            cb.handle_command(COMMAND_UNIT_RECLAIM_AREA, &mut data as *mut _ as *mut c_void)
+        }
+
+        GameCommand::LoadUnits {
+            unit_id,
+            target_ids,
+            queue,
+        } => {
+            // Every target is checked before the first order goes out, so
+            // a bad one refuses the whole load instead of leaving part of
+            // it queued (`OrderRefs` keeps a slot per target).
+            validate_load(cb, *unit_id, target_ids)?;
+            // The engine reads a load order's params as one unit, or as an
+            // area when there are four, so each unit gets its own order,
+            // queued after the first.
+            let mut result = 0;
+            for (i, target) in target_ids.iter().enumerate() {
+                let mut ids: [c_int; 1] = [target.0];
+                let mut data = SLoadUnitsUnitCommand {
+                    unit_id: unit_id.0,
+                    group_id: -1,
+                    options: if *queue || i > 0 { UNIT_COMMAND_OPTION_SHIFT_KEY } else { 0 },
+                    time_out: i32::MAX,
+                    to_load_unit_ids: ids.as_mut_ptr(),
+                    to_load_unit_ids_size: ids.len() as c_int,
+                };
+                result = cb.handle_command(COMMAND_UNIT_LOAD_UNITS, &mut data as *mut _ as *mut c_void);
+                if result < 0 && i > 0 {
+                    return Err(format!(
+                        "load_units: engine refused the load of unit {} after queuing {} ({})",
+                        target,
+                        i,
+                        describe_error(result)
+                    ));
+                }
+                if result < 0 {
+                    break;
+                }
+            }
+            result
+        }
+
+        GameCommand::LoadOntoTransport { unit_id, transport_id } => {
+            validate_unit(cb, *unit_id)?;
+            validate_unit(cb, *transport_id)?;
+            let mut data = SLoadOntoUnitCommand {
+                unit_id: unit_id.0,
+                group_id: -1,
+                options: 0,
+                time_out: i32::MAX,
+                transporter_unit_id: transport_id.0,
+            };
+            cb.handle_command(COMMAND_UNIT_LOAD_ONTO, &mut data as *mut _ as *mut c_void)
+        }
+
+        GameCommand::UnloadUnits {
+            unit_id,
+            x,
+            y,
+            z,
+            radius,
+            queue,
+        } => {
+            validate_unit(cb, *unit_id)?;
+            validate_radius(*radius)?;
+            validate_pos(cb, *x, *z)?;
+            let mut pos: [c_float; 3] = [*x, *y, *z];
+            let mut data = SUnloadUnitsAreaUnitCommand {
+                unit_id: unit_id.0,
+                group_id: -1,
+                options: if *queue { UNIT_COMMAND_OPTION_SHIFT_KEY } else { 0 },
+                time_out: i32::MAX,
+                to_pos: &mut pos as *mut [c_float; 3],
+                radius: *radius,
+            };
+            cb.handle_command(COMMAND_UNIT_UNLOAD_UNITS_AREA, &mut data as *mut _ as *mut c_void)
+        }
+
+        GameCommand::SelfDestruct { unit_id } => {
+            validate_self_destruct(cb, *unit_id)?;
+            let mut data = SSelfDestroyUnitCommand {
+                unit_id: unit_id.0,
+                group_id: -1,
+                options: 0,
+                time_out: i32::MAX,
+            };
+            cb.handle_command(COMMAND_UNIT_SELF_DESTROY, &mut data as *mut _ as *mut c_void)
+        }
+
         GameCommand::SetFireState { unit_id, state } => {
             validate_unit(cb, *unit_id)?;
             let mut data = SSetFireStateUnitCommand {
-                unit_id: *unit_id as c_int,
+                unit_id: unit_id.0,
                 group_id: -1,
                 options: 0,
                 time_out: i32::MAX,
@@ -312,7 +656,7 @@ pub fn dispatch(cb: &EngineCallbacks, cmd: &GameCommand) -> Result<(), String> {
         GameCommand::SetMoveState { unit_id, state } => {
             validate_unit(cb, *unit_id)?;
             let mut data = SSetMoveStateUnitCommand {
-                unit_id: *unit_id as c_int,
+                unit_id: unit_id.0,
                 group_id: -1,
                 options: 0,
                 time_out: i32::MAX,
@@ -324,14 +668,41 @@ pub fn dispatch(cb: &EngineCallbacks, cmd: &GameCommand) -> Result<(), String> {
             )
         }
 
-        GameCommand::SendChat { text } => {
+        GameCommand::Custom { unit_id, command_id, params, queue } => {
+            validate_unit(cb, *unit_id)?;
+            // The engine copies the params before handle_command returns.
+            let mut params = params.clone();
+            let mut data = SCustomUnitCommand {
+                unit_id: unit_id.0,
+                group_id: -1,
+                options: if *queue { UNIT_COMMAND_OPTION_SHIFT_KEY } else { 0 },
+                time_out: i32::MAX,
+                cmd_id: *command_id as c_int,
+                params: params.as_mut_ptr(),
+                params_size: params.len() as c_int,
+            };
+            cb.handle_command(COMMAND_UNIT_CUSTOM, &mut data as *mut _ as *mut c_void)
+        }
+
+        GameCommand::SendChat { text, destination } => {
             // SendTextMsg only handles /commands — plain text is ignored.
-            // Prepend /say to send actual network chat visible to all players.
-            let say_text = format!("/say {}", text);
-            let c_text = CString::new(say_text.as_str()).map_err(|e| e.to_string())?;
+            // Prepend /say to send actual network chat. The engine routes
+            // /say chat by its a:/s: prefix; the zone is set to match.
+            let (zone, prefix) = match destination {
+                ChatDestination::All => (0, ""),
+                ChatDestination::Allies => (1, "a:"),
+                ChatDestination::Spectators => (2, "s:"),
+            };
+            let chat = strings::to_c(text, strings::MAX_CHAT_BYTES);
+            if let Some(warning) = chat.warning("send_chat text") {
+                log_warn!(Some(cb), "{}", warning);
+            }
+            let mut say_text = format!("/say {}", prefix).into_bytes();
+            say_text.extend_from_slice(chat.c_string.as_bytes());
+            let c_text = CString::new(say_text).expect("no NULs in chat");
             let mut data = SSendTextMessageCommand {
                 text: c_text.as_ptr(),
-                zone: 0,
+                zone,
             };
             cb.handle_command(
                 COMMAND_SEND_TEXT_MESSAGE,
@@ -339,6 +710,22 @@ pub fn dispatch(cb: &EngineCallbacks, cmd: &GameCommand) -> Result<(), String> {
             )
         }
 
+        GameCommand::DrawPoint { x, z, label } => {
+            let c_label = strings::to_c(label, strings::MAX_LABEL_BYTES);
+            if let Some(warning) = c_label.warning("draw_point label") {
+                log_warn!(Some(cb), "{}", warning);
+            }
+            let mut pos = [*x, 0.0, *z];
+            let mut data = SAddPointDrawCommand {
+                pos: &mut pos,
+                label: c_label.c_string.as_ptr(),
+            };
+            cb.handle_command(
+                COMMAND_DRAWER_POINT_ADD,
+                &mut data as *mut _ as *mut c_void,
+            )
+        }
+
         GameCommand::Pause | GameCommand::Unpause => {
             // No-op: pausing the engine deadlocks the AI (UPDATE events stop,
             // so the bridge can never poll the unpause command).
@@ -347,7 +734,21 @@ pub fn dispatch(cb: &EngineCallbacks, cmd: &GameCommand) -> Result<(), String> {
         }
 
         GameCommand::SetSpeed { .. } => {
-            return Err("set_speed is not supported by the engine AI interface".into());
+            return Err(SET_SPEED_UNSUPPORTED.into());
+        }
+
+        GameCommand::SetTurnMode { .. }
+        | GameCommand::SetUpdateInterval { .. }
+        | GameCommand::EndTurn
+        | GameCommand::QueryUnitDefs { .. }
+        | GameCommand::QueryMapGrid { .. }
+        | GameCommand::QueryUnitQueues { .. } => {
+            // Bridge state and queries, not engine commands — handled in lib.rs.
+            return Ok(());
+        }
+
+        GameCommand::Unknown { raw } => {
+            return Err(unrecognized(raw));
         }
     };
 
@@ -362,3 +763,365 @@ pub fn dispatch(cb: &EngineCallbacks, cmd: &GameCommand) -> Result<(), String> {
         ))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_engine::MockEngine;
+    use serde_json::json;
+
+    fn engine() -> MockEngine {
+        let engine = MockEngine::new();
+        engine.with_game(|g| {
+            g.add_unit(10, "cloakcon", [100.0, 5.0, 200.0], 0);
+            g.add_def("staticmex", "Metal Extractor");
+        });
+        engine
+    }
+
+    fn cmd(v: serde_json::Value) -> GameCommand {
+        GameCommand::from_line(&v.to_string()).unwrap()
+    }
+
+    #[test]
+    fn test_move_struct_layout() {
+        let engine = engine();
+        dispatch(&engine.callbacks(), &cmd(json!({
+            "type": "move", "unit_id": 10, "x": 1000.0, "y": 0.0, "z": 1500.0, "queue": true
+        })))
+        .unwrap();
+
+        let sent = engine.take_commands();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].topic, COMMAND_UNIT_MOVE);
+        assert_eq!(sent[0].to_id, COMMAND_TO_ID_ENGINE);
+        assert_eq!(sent[0].command_id, -1);
+        assert_eq!(
+            sent[0].fields,
+            json!({
+                "unit_id": 10,
+                "group_id": -1,
+                "options": UNIT_COMMAND_OPTION_SHIFT_KEY,
+                "time_out": i32::MAX,
+                "to_pos": [1000.0, 0.0, 1500.0],
+            })
+        );
+    }
+
+    #[test]
+    fn test_build_by_name_snaps_to_build_site() {
+        let engine = engine();
+        engine.with_game(|g| g.build_site = Some([1016.0, 20.0, 1496.0]));
+        dispatch(&engine.callbacks(), &cmd(json!({
+            "type": "build", "unit_id": 10, "build_def_name": "staticmex",
+            "x": 1000.0, "y": 0.0, "z": 1500.0, "facing": 2
+        })))
+        .unwrap();
+
+        let sent = engine.take_commands();
+        assert_eq!(sent[0].topic, COMMAND_UNIT_BUILD);
+        assert_eq!(sent[0].fields["def_id"], json!(engine.with_game(|g| g.def_id("staticmex"))));
+        assert_eq!(sent[0].fields["build_pos"], json!([1016.0, 20.0, 1496.0]));
+        assert_eq!(sent[0].fields["facing"], 2);
+        assert_eq!(sent[0].fields["options"], 0);
+    }
+
+    #[test]
+    fn test_targeted_and_state_commands() {
+        let engine = engine();
+        let cb = engine.callbacks();
+        dispatch(&cb, &cmd(json!({"type": "attack", "unit_id": 10, "target_id": 77}))).unwrap();
+        dispatch(&cb, &cmd(json!({"type": "guard", "unit_id": 10, "guard_id": 78}))).unwrap();
+        dispatch(&cb, &cmd(json!({"type": "repair", "unit_id": 10, "repair_id": 79}))).unwrap();
+        dispatch(&cb, &cmd(json!({"type": "set_fire_state", "unit_id": 10, "state": 1}))).unwrap();
+        dispatch(&cb, &cmd(json!({"type": "set_move_state", "unit_id": 10, "state": 0}))).unwrap();
+        dispatch(&cb, &cmd(json!({"type": "stop", "unit_id": 10}))).unwrap();
+        dispatch(&cb, &cmd(json!({"type": "custom", "unit_id": 10, "command_id": 34223, "params": [2], "queue": true}))).unwrap();
+
+        let sent: Vec<(c_int, serde_json::Value)> = engine
+            .take_commands()
+            .into_iter()
+            .map(|c| (c.topic, c.fields))
+            .collect();
+        assert_eq!(sent[0].0, COMMAND_UNIT_ATTACK);
+        assert_eq!(sent[0].1["target"], 77);
+        assert_eq!(sent[1].0, COMMAND_UNIT_GUARD);
+        assert_eq!(sent[1].1["target"], 78);
+        assert_eq!(sent[2].0, COMMAND_UNIT_REPAIR);
+        assert_eq!(sent[2].1["target"], 79);
+        assert_eq!(sent[3].0, COMMAND_UNIT_SET_FIRE_STATE);
+        assert_eq!(sent[3].1["state"], 1);
+        assert_eq!(sent[4].0, COMMAND_UNIT_SET_MOVE_STATE);
+        assert_eq!(sent[4].1["state"], 0);
+        assert_eq!(sent[5].0, COMMAND_UNIT_STOP);
+        assert_eq!(sent[5].1["unit_id"], 10);
+        assert_eq!(sent[6].0, COMMAND_UNIT_CUSTOM);
+        assert_eq!((&sent[6].1["cmd_id"], &sent[6].1["params"]), (&json!(34223), &json!([2.0])));
+        assert_eq!(sent[6].1["options"], UNIT_COMMAND_OPTION_SHIFT_KEY);
+    }
+
+    #[test]
+    fn test_transport_orders() {
+        let engine = engine();
+        engine.with_game(|g| {
+            g.add_unit(30, "gunshiptrans", [0.0, 0.0, 0.0], 0);
+            g.add_unit(12, "cloakraid", [0.0, 0.0, 0.0], 0);
+            g.add_unit(13, "cloakraid", [0.0, 0.0, 0.0], 0);
+        });
+        let cb = engine.callbacks();
+        dispatch(&cb, &cmd(json!({"type": "load_units", "unit_id": 30, "target_ids": [12, 13]}))).unwrap();
+        dispatch(&cb, &cmd(json!({"type": "load_onto_transport", "unit_id": 10, "transport_id": 30}))).unwrap();
+        let unload = json!({"type": "unload_units", "unit_id": 30, "x": 900, "z": 400, "radius": 100, "queue": true});
+        dispatch(&cb, &cmd(unload)).unwrap();
+
+        let sent: Vec<(c_int, serde_json::Value)> = engine.take_commands().into_iter().map(|c| (c.topic, c.fields)).collect();
+        // One load order per unit, the later ones queued.
+        assert_eq!(sent[0].0, COMMAND_UNIT_LOAD_UNITS);
+        assert_eq!((&sent[0].1["targets"], &sent[0].1["options"]), (&json!([12]), &json!(0)));
+        assert_eq!((&sent[1].1["targets"], &sent[1].1["options"]), (&json!([13]), &json!(UNIT_COMMAND_OPTION_SHIFT_KEY)));
+        assert_eq!((sent[2].0, &sent[2].1["transport"]), (COMMAND_UNIT_LOAD_ONTO, &json!(30)));
+        assert_eq!(sent[3].0, COMMAND_UNIT_UNLOAD_UNITS_AREA);
+        assert_eq!((&sent[3].1["pos"], &sent[3].1["radius"]), (&json!([900.0, 0.0, 400.0]), &json!(100.0)));
+
+        let empty = json!({"type": "load_units", "unit_id": 30, "target_ids": []});
+        assert_eq!(dispatch(&cb, &cmd(empty)).unwrap_err(), "load_units needs at least one unit in target_ids");
+        let missing = json!({"type": "load_units", "unit_id": 30, "target_ids": [12, 999]});
+        assert!(validate(&cb, &cmd(missing.clone())).unwrap_err().contains("unit 999 does not exist"));
+        assert!(dispatch(&cb, &cmd(missing)).is_err());
+        // A bad target anywhere in the list stops the load before any of it goes out.
+        let twice = json!({"type": "load_units", "unit_id": 30, "target_ids": [12, 13, 12]});
+        assert_eq!(dispatch(&cb, &cmd(twice)).unwrap_err(), "load_units: unit 12 is in target_ids twice");
+        let itself = json!({"type": "load_units", "unit_id": 30, "target_ids": [12, 30]});
+        assert_eq!(validate(&cb, &cmd(itself)).unwrap_err(), "load_units: transport 30 can't load itself");
+        let no_radius = json!({"type": "unload_units", "unit_id": 30, "x": 900, "z": 400, "radius": 0});
+        assert!(dispatch(&cb, &cmd(no_radius)).is_err());
+        assert!(engine.take_commands().is_empty());
+    }
+
+    #[test]
+    fn test_self_destruct_only_our_units() {
+        let engine = engine();
+        engine.with_game(|g| g.add_unit(11, "cloakcon", [0.0, 0.0, 0.0], 1));
+        let cb = engine.callbacks();
+        dispatch(&cb, &cmd(json!({"type": "self_destruct", "unit_id": 10}))).unwrap();
+        let sent = engine.take_commands();
+        assert_eq!((sent[0].topic, sent[0].fields["unit_id"].as_i64()), (COMMAND_UNIT_SELF_DESTROY, Some(10)));
+
+        let theirs = json!({"type": "self_destruct", "unit_id": 11});
+        assert_eq!(dispatch(&cb, &cmd(theirs.clone())).unwrap_err(), "self_destruct: unit 11 belongs to team 1, not ours");
+        assert!(validate(&cb, &cmd(theirs)).is_err());
+        let err = dispatch(&cb, &cmd(json!({"type": "self_destruct", "unit_id": -4}))).unwrap_err();
+        assert_eq!(err, "self_destruct: -4 is not a unit id");
+        let err = validate(&cb, &cmd(json!({"type": "self_destruct", "unit_id": 0}))).unwrap_err();
+        assert_eq!(err, "self_destruct: 0 is not a unit id");
+        let err = dispatch(&cb, &cmd(json!({"type": "self_destruct", "unit_id": 999}))).unwrap_err();
+        assert!(err.contains("unit 999 does not exist"), "{}", err);
+        assert!(engine.take_commands().is_empty());
+    }
+
+    #[test]
+    fn test_manual_fire() {
+        let engine = engine();
+        let cb = engine.callbacks();
+        // A target unit wins over a position.
+        let both = json!({"type": "manual_fire", "unit_id": 10, "target_id": 90, "x": 900, "z": 400});
+        dispatch(&cb, &cmd(both)).unwrap();
+        let ground = json!({"type": "manual_fire", "unit_id": 10, "x": 900, "y": 12, "z": 400, "queue": true});
+        dispatch(&cb, &cmd(ground)).unwrap();
+
+        let sent = engine.take_commands();
+        assert_eq!((sent[0].topic, &sent[0].fields["target"]), (COMMAND_UNIT_D_GUN, &json!(90)));
+        assert_eq!(sent[1].topic, COMMAND_UNIT_D_GUN_POS);
+        assert_eq!((&sent[1].fields["pos"], &sent[1].fields["options"]), (&json!([900.0, 12.0, 400.0]), &json!(UNIT_COMMAND_OPTION_SHIFT_KEY)));
+
+        let nowhere = json!({"type": "manual_fire", "unit_id": 10, "x": 900});
+        assert_eq!(dispatch(&cb, &cmd(nowhere.clone())).unwrap_err(), "manual_fire needs a target_id, or x and z");
+        assert!(validate(&cb, &cmd(nowhere)).is_err());
+        let off_map = json!({"type": "manual_fire", "unit_id": 10, "x": 900, "z": 5000});
+        assert!(dispatch(&cb, &cmd(off_map)).unwrap_err().contains("outside"));
+        assert!(engine.take_commands().is_empty());
+    }
+
+    #[test]
+    fn test_waits() {
+        let engine = engine();
+        let cb = engine.callbacks();
+        dispatch(&cb, &cmd(json!({"type": "wait", "unit_id": 10}))).unwrap();
+        dispatch(&cb, &cmd(json!({"type": "time_wait", "unit_id": 10, "seconds": 10, "queue": true}))).unwrap();
+
+        let sent = engine.take_commands();
+        assert_eq!((sent[0].topic, &sent[0].fields["options"]), (COMMAND_UNIT_WAIT, &json!(0)));
+        assert_eq!(sent[1].topic, COMMAND_UNIT_WAIT_TIME);
+        assert_eq!((&sent[1].fields["time"], &sent[1].fields["options"]), (&json!(10), &json!(UNIT_COMMAND_OPTION_SHIFT_KEY)));
+
+        let endless = json!({"type": "time_wait", "unit_id": 10, "seconds": 0});
+        assert_eq!(validate(&cb, &cmd(endless.clone())).unwrap_err(), "time_wait needs at least 1 second");
+        assert_eq!(dispatch(&cb, &cmd(endless)).unwrap_err(), "time_wait needs at least 1 second");
+        assert!(engine.take_commands().is_empty());
+    }
+
+    #[test]
+    fn test_attack_ground_and_area() {
+        let engine = engine();
+        let cb = engine.callbacks();
+        let ground = json!({"type": "attack_ground", "unit_id": 10, "x": 900, "y": 30, "z": 400, "queue": true});
+        dispatch(&cb, &cmd(ground)).unwrap();
+        dispatch(&cb, &cmd(json!({"type": "attack_area", "unit_id": 10, "x": 900, "z": 400, "radius": 150}))).unwrap();
+
+        let sent = engine.take_commands();
+        assert_eq!(sent[0].topic, COMMAND_UNIT_CUSTOM);
+        assert_eq!((&sent[0].fields["cmd_id"], &sent[0].fields["params"]), (&json!(CMD_ATTACK), &json!([900.0, 30.0, 400.0])));
+        assert_eq!(sent[0].fields["options"], UNIT_COMMAND_OPTION_SHIFT_KEY);
+        assert_eq!(sent[1].topic, COMMAND_UNIT_ATTACK_AREA);
+        assert_eq!((&sent[1].fields["pos"], &sent[1].fields["radius"]), (&json!([900.0, 0.0, 400.0]), &json!(150.0)));
+
+        // Off the map: refused before the engine sees it, dry run or not.
+        let off_map = json!({"type": "attack_ground", "unit_id": 10, "x": -50, "z": 400});
+        assert_eq!(dispatch(&cb, &cmd(off_map.clone())).unwrap_err(), "position (-50, 400) is outside the 4096x4096 map");
+        assert!(validate(&cb, &cmd(off_map)).is_err());
+        let off_map = json!({"type": "attack_area", "unit_id": 10, "x": 900, "z": 9000, "radius": 150});
+        assert_eq!(dispatch(&cb, &cmd(off_map)).unwrap_err(), "position (900, 9000) is outside the 4096x4096 map");
+        let no_radius = json!({"type": "attack_area", "unit_id": 10, "x": 900, "z": 400, "radius": -1});
+        assert_eq!(dispatch(&cb, &cmd(no_radius)).unwrap_err(), "radius must be positive, got -1");
+        assert!(engine.take_commands().is_empty());
+    }
+
+    #[test]
+    fn test_reclaim() {
+        let engine = engine();
+        let cb = engine.callbacks();
+        dispatch(&cb, &cmd(json!({"type": "reclaim", "unit_id": 10, "target_id": 80, "queue": true}))).unwrap();
+        let area = json!({"type": "reclaim_area", "unit_id": 10, "x": 1000, "y": 0, "z": 1500, "radius": 300});
+        dispatch(&cb, &cmd(area)).unwrap();
+
+        let sent = engine.take_commands();
+        assert_eq!(sent[0].topic, COMMAND_UNIT_RECLAIM_UNIT);
+        assert_eq!((&sent[0].fields["target"], &sent[0].fields["options"]), (&json!(80), &json!(UNIT_COMMAND_OPTION_SHIFT_KEY)));
+        assert_eq!(sent[1].topic, COMMAND_UNIT_RECLAIM_AREA);
+        assert_eq!(
+            sent[1].fields,
+            json!({
+                "unit_id": 10,
+                "group_id": -1,
+                "options": 0,
+                "time_out": i32::MAX,
+                "pos": [1000.0, 0.0, 1500.0],
+                "radius": 300.0,
+            })
+        );
+
+        // A feature id goes past the engine's unit ids, so it can't be read as a unit.
+        engine.with_game(|g| {
+            g.max_units = 5000;
+            g.features.insert(7, 3);
+        });
+        dispatch(&cb, &cmd(json!({"type": "reclaim", "unit_id": 10, "feature_id": 7}))).unwrap();
+        let sent = engine.take_commands();
+        assert_eq!((sent[0].topic, &sent[0].fields["target"]), (COMMAND_UNIT_RECLAIM_UNIT, &json!(5007)));
+        let gone = json!({"type": "reclaim", "unit_id": 10, "feature_id": 8});
+        assert_eq!(validate(&cb, &cmd(gone.clone())).unwrap_err(), "feature 8 does not exist (feature_get_def returned -1)");
+        assert!(dispatch(&cb, &cmd(gone)).is_err());
+        assert!(engine.take_commands().is_empty());
+
+        let flat = json!({"type": "reclaim_area", "unit_id": 10, "x": 1000, "z": 1500, "radius": 0});
+        assert_eq!(validate(&cb, &cmd(flat.clone())).unwrap_err(), "radius must be positive, got 0");
+        assert_eq!(dispatch(&cb, &cmd(flat)).unwrap_err(), "radius must be positive, got 0");
+        let off_map = json!({"type": "reclaim_area", "unit_id": 10, "x": 5000, "z": 1500, "radius": 300});
+        assert!(validate(&cb, &cmd(off_map)).is_err());
+        assert!(engine.take_commands().is_empty());
+    }
+
+    #[test]
+    fn test_send_chat_prefixes_say() {
+        let engine = engine();
+        dispatch(&engine.callbacks(), &cmd(json!({"type": "send_chat", "text": "gl hf"}))).unwrap();
+        let sent = engine.take_commands();
+        assert_eq!(sent[0].topic, COMMAND_SEND_TEXT_MESSAGE);
+        assert_eq!(sent[0].fields, json!({"text": "/say gl hf", "zone": 0}));
+
+        let allies = json!({"type": "send_chat", "text": "push mid", "destination": "allies"});
+        dispatch(&engine.callbacks(), &cmd(allies)).unwrap();
+        let sent = engine.take_commands();
+        assert_eq!(sent[0].fields, json!({"text": "/say a:push mid", "zone": 1}));
+    }
+
+    #[test]
+    fn test_draw_point() {
+        let engine = engine();
+        let point = json!({"type": "draw_point", "x": 1200, "z": 800, "label": "on my way"});
+        dispatch(&engine.callbacks(), &cmd(point)).unwrap();
+        let sent = engine.take_commands();
+        assert_eq!(sent[0].topic, COMMAND_DRAWER_POINT_ADD);
+        assert_eq!(sent[0].fields, json!({"pos": [1200.0, 0.0, 800.0], "label": "on my way"}));
+    }
+
+    #[test]
+    fn test_chat_made_to_fit() {
+        let engine = engine();
+        let cb = engine.callbacks();
+        let nul = json!({"type": "send_chat", "text": "gl\u{0} hf", "destination": "allies"});
+        validate(&cb, &cmd(nul.clone())).unwrap();
+        dispatch(&cb, &cmd(nul)).unwrap();
+        assert_eq!(engine.take_commands()[0].fields["text"], "/say a:gl hf");
+
+        dispatch(&cb, &cmd(json!({"type": "send_chat", "text": "x".repeat(10_000)}))).unwrap();
+        let said = engine.take_commands()[0].fields["text"].as_str().unwrap().to_string();
+        assert_eq!(said, format!("/say {}", "x".repeat(strings::MAX_CHAT_BYTES)));
+
+        dispatch(&cb, &cmd(json!({"type": "draw_point", "x": 1200, "z": 800, "label": "\u{0}base"}))).unwrap();
+        assert_eq!(engine.take_commands()[0].fields["label"], "base");
+        let logs = engine.logs();
+        assert!(logs.iter().any(|l| l.contains("send_chat text: dropped 1 NUL byte")), "{:?}", logs);
+        assert!(logs.iter().any(|l| l.contains("send_chat text: cut from 10000 to 200 bytes")), "{:?}", logs);
+        assert!(logs.iter().any(|l| l.contains("draw_point label: dropped 1 NUL byte")), "{:?}", logs);
+    }
+
+    #[test]
+    fn test_validate_sends_nothing() {
+        let engine = engine();
+        let cb = engine.callbacks();
+        let build = json!({"type": "build", "unit_id": 10, "build_def_name": "staticmex", "x": 1000.0, "z": 1500.0});
+        validate(&cb, &cmd(build)).unwrap();
+        validate(&cb, &cmd(json!({"type": "move", "unit_id": 10, "x": 4096.0, "z": 0.0}))).unwrap();
+        validate(&cb, &cmd(json!({"type": "send_chat", "text": "gl hf"}))).unwrap();
+
+        let err = validate(&cb, &cmd(json!({"type": "fight", "unit_id": 10, "x": 5000.0, "z": 100.0}))).unwrap_err();
+        assert_eq!(err, "position (5000, 100) is outside the 4096x4096 map");
+        let err = validate(&cb, &cmd(json!({"type": "stop", "unit_id": 999}))).unwrap_err();
+        assert!(err.contains("unit 999 does not exist"), "{}", err);
+        let err = validate(&cb, &cmd(json!({"type": "build", "unit_id": 10, "build_def_id": 40}))).unwrap_err();
+        assert_eq!(err, "Unknown unit def id: 40");
+        let err = validate(&cb, &cmd(json!({"type": "set_speed", "speed": 2.0}))).unwrap_err();
+        assert_eq!(err, "set_speed is not supported by the engine AI interface");
+        let err = validate(&cb, &cmd(json!({"type": "teleport", "unit_id": 10}))).unwrap_err();
+        assert_eq!(err, "unrecognized command type 'teleport'");
+        assert!(engine.take_commands().is_empty());
+    }
+
+    #[test]
+    fn test_errors() {
+        let engine = engine();
+        let cb = engine.callbacks();
+
+        let err = dispatch(&cb, &cmd(json!({"type": "stop", "unit_id": 999}))).unwrap_err();
+        assert!(err.contains("unit 999 does not exist"), "{}", err);
+
+        let err = dispatch(&cb, &cmd(json!({
+            "type": "build", "unit_id": 10, "build_def_name": "nosuchdef", "x": 1.0, "z": 1.0
+        })))
+        .unwrap_err();
+        assert_eq!(err, "Unknown unit def name: nosuchdef");
+
+        let err = dispatch(&cb, &cmd(json!({"type": "teleport", "unit_id": 10}))).unwrap_err();
+        assert_eq!(err, "unrecognized command type 'teleport'");
+
+        engine.with_game(|g| g.command_result = -5);
+        let err = dispatch(&cb, &cmd(json!({"type": "stop", "unit_id": 10}))).unwrap_err();
+        assert_eq!(
+            err,
+            "Engine rejected command (code -5): unit does not belong to this AI's team"
+        );
+        // Nothing reaches the engine for commands rejected before dispatch
+        assert_eq!(engine.take_commands().len(), 1);
+    }
+}
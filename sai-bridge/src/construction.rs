@@ -0,0 +1,159 @@
+//! Build progress of the AI's units under construction.
+//!
+//! A unit is tracked from `unit_created` until it is finished, destroyed
+//! or changes hands. Each forwarded update samples its progress for the
+//! update's `under_construction`; a unit whose progress stood still since
+//! the last update while builders were on it raises `construction_stalled`.
+//!
+//! Which builders are on a unit is what the bridge saw, not what the engine
+//! knows: the builder that started it, and units the GameManager sent to
+//! repair or guard it. A builder leaves when it goes idle, dies or gets an
+//! order that replaces its queue. Asking the engine instead would mean
+//! reading every unit's command queue each update.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use sai_protocol::{Construction, GameCommand, GameEvent, UnitId};
+
+use crate::callbacks::EngineCallbacks;
+
+#[derive(Debug, Default)]
+struct Site {
+    unit_name: Option<String>,
+    builders: BTreeSet<UnitId>,
+    /// Progress at the last sample, 0.0 to 1.0.
+    progress: Option<f32>,
+    /// A stall was reported and progress hasn't moved since.
+    stall_reported: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct ConstructionTracker {
+    sites: BTreeMap<UnitId, Site>,
+}
+
+impl ConstructionTracker {
+    /// Follow units and builders through an (enriched) event.
+    pub fn observe(&mut self, event: &GameEvent) {
+        match event {
+            GameEvent::UnitCreated { unit, unit_name, builder, .. } => {
+                let site = self.sites.entry(*unit).or_default();
+                site.unit_name = unit_name.clone();
+                if builder.0 > 0 {
+                    site.builders.insert(*builder);
+                }
+            }
+            GameEvent::UnitFinished { unit, .. }
+            | GameEvent::UnitDestroyed { unit, .. }
+            | GameEvent::UnitGiven { unit, .. }
+            | GameEvent::UnitCaptured { unit, .. } => {
+                self.sites.remove(unit);
+                self.release_builder(*unit);
+            }
+            GameEvent::UnitIdle { unit, .. } => self.release_builder(*unit),
+            _ => {}
+        }
+    }
+
+    /// Follow builders through a command that reached the engine.
+    pub fn observe_command(&mut self, cmd: &GameCommand) {
+        let (unit, target, queue) = match cmd {
+            GameCommand::Repair { unit_id, repair_id, queue } => (*unit_id, Some(*repair_id), *queue),
+            GameCommand::Guard { unit_id, guard_id, queue } => (*unit_id, Some(*guard_id), *queue),
+            GameCommand::Move { unit_id, queue, .. }
+            | GameCommand::Attack { unit_id, queue, .. }
+            | GameCommand::Build { unit_id, queue, .. }
+            | GameCommand::Patrol { unit_id, queue, .. }
+            | GameCommand::Fight { unit_id, queue, .. } => (*unit_id, None, *queue),
+            GameCommand::Stop { unit_id } => (*unit_id, None, false),
+            _ => return,
+        };
+        if !queue {
+            self.release_builder(unit);
+        }
+        if let Some(site) = target.and_then(|target| self.sites.get_mut(&target)) {
+            site.builders.insert(unit);
+        }
+    }
+
+    fn release_builder(&mut self, builder: UnitId) {
+        for site in self.sites.values_mut() {
+            site.builders.remove(&builder);
+        }
+    }
+
+    /// Read every tracked unit's progress: the update's
+    /// `under_construction`, and a `construction_stalled` event for each
+    /// unit newly stuck with builders on it. Units the engine reports as
+    /// complete are dropped.
+    pub fn sample(&mut self, cb: &EngineCallbacks) -> (Vec<Construction>, Vec<GameEvent>) {
+        let mut under_construction = Vec::new();
+        let mut stalled = Vec::new();
+        self.sites.retain(|unit, site| {
+            let progress = cb.unit_get_build_progress(*unit);
+            if progress >= 1.0 {
+                return false;
+            }
+            let percent = (progress * 1000.0).round() / 10.0;
+            let builders = site.builders.len();
+            if site.progress != Some(progress) {
+                site.stall_reported = false;
+            } else if builders > 0 && !site.stall_reported {
+                site.stall_reported = true;
+                stalled.push(GameEvent::ConstructionStalled {
+                    unit: *unit,
+                    unit_name: site.unit_name.clone(),
+                    progress: percent,
+                    builders,
+                });
+            }
+            site.progress = Some(progress);
+            under_construction.push(Construction { unit: *unit, unit_name: site.unit_name.clone(), progress: percent, builders });
+            true
+        });
+        (under_construction, stalled)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_engine::MockEngine;
+
+    fn created(unit: i32, builder: i32) -> GameEvent {
+        GameEvent::UnitCreated {
+            unit: UnitId(unit),
+            unit_name: Some("energyfusion".into()),
+            builder: UnitId(builder),
+            builder_name: None,
+            pos: None,
+        }
+    }
+
+    fn builders(tracker: &mut ConstructionTracker, engine: &MockEngine) -> Vec<usize> {
+        tracker.sample(&engine.callbacks()).0.iter().map(|c| c.builders).collect()
+    }
+
+    #[test]
+    fn test_builders_follow_orders() {
+        let engine = MockEngine::new();
+        engine.with_game(|g| {
+            g.add_unit(20, "energyfusion", [0.0; 3], 0);
+            g.units.get_mut(&20).unwrap().build_progress = 0.4;
+        });
+        let mut tracker = ConstructionTracker::default();
+        tracker.observe(&created(20, 10));
+        tracker.observe_command(&GameCommand::Repair { unit_id: UnitId(11), repair_id: UnitId(20), queue: false });
+        tracker.observe_command(&GameCommand::Guard { unit_id: UnitId(12), guard_id: UnitId(20), queue: true });
+        assert_eq!(builders(&mut tracker, &engine), [3]);
+
+        // Queued orders keep a builder on it; replacing ones and idling don't.
+        tracker.observe_command(&GameCommand::Move { unit_id: UnitId(12), x: 0.0, y: 0.0, z: 0.0, queue: true });
+        tracker.observe_command(&GameCommand::Stop { unit_id: UnitId(11) });
+        tracker.observe(&GameEvent::UnitIdle { unit: UnitId(10), unit_name: None });
+        assert_eq!(builders(&mut tracker, &engine), [1]);
+
+        tracker.observe(&GameEvent::UnitFinished { unit: UnitId(20), unit_name: None, pos: None });
+        assert!(tracker.sample(&engine.callbacks()).0.is_empty());
+    }
+}
@@ -2,8 +2,11 @@
 //! Maps from the C `topicId` + `data` pointer to serializable Rust types.
 
 use crate::callbacks::EngineCallbacks;
-use serde::Serialize;
-use std::ffi::{c_char, c_float, c_int, c_void, CStr};
+use crate::commands::SQUARE_SIZE;
+use crate::strings;
+use sai_protocol::{TeamId, UnitDefId, UnitId, WeaponDefId, MAX_MAP_GRID_CELLS};
+use std::collections::HashMap;
+use std::ffi::{c_char, c_float, c_int, c_void};
 
 // ── Event topic constants ──
 
@@ -36,6 +39,15 @@ pub const EVENT_ENEMY_CREATED: c_int = 25;
 pub const EVENT_ENEMY_FINISHED: c_int = 26;
 pub const EVENT_LUA_MESSAGE: c_int = 27;
 
+// ── Engine limits, for checking event fields ──
+
+/// Unit ids are below this in any game (the engine's MAX_UNITS).
+pub const MAX_UNITS: c_int = 32000;
+/// Player numbers fit a byte, the server's included.
+pub const MAX_PLAYERS: c_int = 256;
+/// Team ids are below this (the engine's MAX_TEAMS).
+pub const MAX_TEAMS: c_int = 255;
+
 // ── C event data structs (repr(C), read-only) ──
 
 #[repr(C)]
@@ -177,399 +189,585 @@ pub struct SEnemyFinishedEvent {
     pub enemy: c_int,
 }
 
-// ── Metal spot data (from GameRulesParams) ──
-
-#[derive(Debug, Serialize)]
-pub struct MetalSpot {
-    pub x: f32,
-    pub y: f32,
-    pub z: f32,
-    pub metal: f32,
-}
-
 // ── Serializable game event (sent over IPC to GameManager) ──
 
-#[derive(Debug, Serialize)]
-#[serde(tag = "type")]
-pub enum GameEvent {
-    #[serde(rename = "init")]
-    Init {
-        frame: i32,
-        saved_game: bool,
-        #[serde(skip_serializing_if = "Option::is_none")]
-        metal_spots: Option<Vec<MetalSpot>>,
-        #[serde(skip_serializing_if = "Option::is_none")]
-        map_width: Option<i32>,
-        #[serde(skip_serializing_if = "Option::is_none")]
-        map_height: Option<i32>,
-    },
-
-    #[serde(rename = "release")]
-    Release { reason: i32 },
-
-    #[serde(rename = "update")]
-    Update { frame: i32 },
-
-    #[serde(rename = "message")]
-    Message { player: i32, text: String },
-
-    #[serde(rename = "unit_created")]
-    UnitCreated {
-        unit: i32,
-        #[serde(skip_serializing_if = "Option::is_none")]
-        unit_name: Option<String>,
-        builder: i32,
-        #[serde(skip_serializing_if = "Option::is_none")]
-        builder_name: Option<String>,
-        #[serde(skip_serializing_if = "Option::is_none")]
-        pos: Option<[f32; 3]>,
-    },
-
-    #[serde(rename = "unit_finished")]
-    UnitFinished {
-        unit: i32,
-        #[serde(skip_serializing_if = "Option::is_none")]
-        unit_name: Option<String>,
-        #[serde(skip_serializing_if = "Option::is_none")]
-        pos: Option<[f32; 3]>,
-    },
-
-    #[serde(rename = "unit_idle")]
-    UnitIdle {
-        unit: i32,
-        #[serde(skip_serializing_if = "Option::is_none")]
-        unit_name: Option<String>,
-    },
-
-    #[serde(rename = "unit_move_failed")]
-    UnitMoveFailed {
-        unit: i32,
-        #[serde(skip_serializing_if = "Option::is_none")]
-        unit_name: Option<String>,
-    },
-
-    #[serde(rename = "unit_damaged")]
-    UnitDamaged {
-        unit: i32,
-        #[serde(skip_serializing_if = "Option::is_none")]
-        unit_name: Option<String>,
-        attacker: i32,
-        #[serde(skip_serializing_if = "Option::is_none")]
-        attacker_name: Option<String>,
-        damage: f32,
-        weapon_def_id: i32,
-        paralyzer: bool,
-    },
-
-    #[serde(rename = "unit_destroyed")]
-    UnitDestroyed {
-        unit: i32,
-        #[serde(skip_serializing_if = "Option::is_none")]
-        unit_name: Option<String>,
-        attacker: i32,
-        #[serde(skip_serializing_if = "Option::is_none")]
-        attacker_name: Option<String>,
-        weapon_def_id: i32,
-    },
-
-    #[serde(rename = "unit_given")]
-    UnitGiven {
-        unit: i32,
-        #[serde(skip_serializing_if = "Option::is_none")]
-        unit_name: Option<String>,
-        old_team: i32,
-        new_team: i32,
-    },
-
-    #[serde(rename = "unit_captured")]
-    UnitCaptured {
-        unit: i32,
-        #[serde(skip_serializing_if = "Option::is_none")]
-        unit_name: Option<String>,
-        old_team: i32,
-        new_team: i32,
-    },
-
-    #[serde(rename = "enemy_enter_los")]
-    EnemyEnterLos {
-        enemy: i32,
-        #[serde(skip_serializing_if = "Option::is_none")]
-        enemy_name: Option<String>,
-        #[serde(skip_serializing_if = "Option::is_none")]
-        pos: Option<[f32; 3]>,
-    },
-
-    #[serde(rename = "enemy_leave_los")]
-    EnemyLeaveLos {
-        enemy: i32,
-        #[serde(skip_serializing_if = "Option::is_none")]
-        enemy_name: Option<String>,
-    },
-
-    #[serde(rename = "enemy_enter_radar")]
-    EnemyEnterRadar {
-        enemy: i32,
-        #[serde(skip_serializing_if = "Option::is_none")]
-        enemy_name: Option<String>,
-    },
-
-    #[serde(rename = "enemy_leave_radar")]
-    EnemyLeaveRadar {
-        enemy: i32,
-        #[serde(skip_serializing_if = "Option::is_none")]
-        enemy_name: Option<String>,
-    },
-
-    #[serde(rename = "enemy_damaged")]
-    EnemyDamaged {
-        enemy: i32,
-        #[serde(skip_serializing_if = "Option::is_none")]
-        enemy_name: Option<String>,
-        attacker: i32,
-        #[serde(skip_serializing_if = "Option::is_none")]
-        attacker_name: Option<String>,
-        damage: f32,
-        weapon_def_id: i32,
-        paralyzer: bool,
-    },
-
-    #[serde(rename = "enemy_destroyed")]
-    EnemyDestroyed {
-        enemy: i32,
-        #[serde(skip_serializing_if = "Option::is_none")]
-        enemy_name: Option<String>,
-        attacker: i32,
-        #[serde(skip_serializing_if = "Option::is_none")]
-        attacker_name: Option<String>,
-    },
-
-    #[serde(rename = "enemy_created")]
-    EnemyCreated {
-        enemy: i32,
-        #[serde(skip_serializing_if = "Option::is_none")]
-        enemy_name: Option<String>,
-    },
-
-    #[serde(rename = "enemy_finished")]
-    EnemyFinished {
-        enemy: i32,
-        #[serde(skip_serializing_if = "Option::is_none")]
-        enemy_name: Option<String>,
-    },
-
-    #[serde(rename = "weapon_fired")]
-    WeaponFired {
-        unit: i32,
-        #[serde(skip_serializing_if = "Option::is_none")]
-        unit_name: Option<String>,
-        weapon_def_id: i32,
-    },
-
-    #[serde(rename = "command_finished")]
-    CommandFinished {
-        unit: i32,
-        #[serde(skip_serializing_if = "Option::is_none")]
-        unit_name: Option<String>,
-        command_id: i32,
-        command_topic: i32,
-    },
-
-    #[serde(rename = "lua_message")]
-    LuaMessage { data: String },
-
-    #[serde(rename = "command_error")]
-    CommandError { error: String, command: String },
-}
+pub use sai_protocol::{
+    Economy, GameEvent, MetalSpot, Paralysis, Relation, ResourceState, RosterUnit, TeamEconomy, TeamSlot, UnitDefInfo,
+    UnitQueue,
+};
 
 /// Convert a raw C event (topic + data pointer) into a serializable GameEvent.
+/// An event whose data is null, or whose fields fail [`check_event`], comes
+/// back as a `malformed_event` instead: a struct of another layout than its
+/// topic's reads as ids and amounts no engine sends.
 ///
 /// # Safety
 /// `data` must be a valid pointer to the correct struct for the given `topic`.
 pub unsafe fn parse_event(topic: c_int, data: *const c_void) -> Option<GameEvent> {
+    let parsed = matches!(topic, EVENT_INIT..=EVENT_LUA_MESSAGE)
+        && !matches!(topic, EVENT_PLAYER_COMMAND | EVENT_SEISMIC_PING | EVENT_LOAD | EVENT_SAVE);
+    if !parsed {
+        return None;
+    }
+    if data.is_null() {
+        return Some(GameEvent::MalformedEvent { topic, reason: "no event data".into() });
+    }
+    let event = read_event(topic, data)?;
+    Some(match check_event(&event) {
+        Ok(()) => event,
+        Err(reason) => GameEvent::MalformedEvent { topic, reason },
+    })
+}
+
+/// The event in `data`, read as `topic`'s struct.
+///
+/// # Safety
+/// `data` is non-null and points to the struct for `topic`.
+unsafe fn read_event(topic: c_int, data: *const c_void) -> Option<GameEvent> {
     match topic {
         EVENT_INIT => {
             let e = &*(data as *const SInitEvent);
             Some(GameEvent::Init {
                 frame: 0,
                 saved_game: e.saved_game,
+                protocol_version: Some(sai_protocol::PROTOCOL_VERSION),
                 metal_spots: None,
                 map_width: None,
                 map_height: None,
+                start_pos: None,
+                teams: Vec::new(),
+                build: None,
             })
         }
         EVENT_RELEASE => {
             let e = &*(data as *const SReleaseEvent);
-            Some(GameEvent::Release { reason: e.reason })
+            Some(GameEvent::Release { reason: e.reason, stats: None })
         }
         EVENT_UPDATE => {
             let e = &*(data as *const SUpdateEvent);
-            Some(GameEvent::Update { frame: e.frame })
+            Some(GameEvent::Update { frame: e.frame, awaiting_commands: false, economy: None, allies: Vec::new(), counters: Default::default(), command_backlog: 0, under_construction: Vec::new(), update_interval: None })
         }
         EVENT_MESSAGE => {
             let e = &*(data as *const SMessageEvent);
-            let text = if e.message.is_null() {
-                String::new()
-            } else {
-                CStr::from_ptr(e.message).to_string_lossy().into_owned()
-            };
+            let (text, text_lossy) = strings::from_c(e.message).unwrap_or_default();
             Some(GameEvent::Message {
                 player: e.player,
+                player_name: None,
                 text,
+                text_lossy,
             })
         }
         EVENT_UNIT_CREATED => {
             let e = &*(data as *const SUnitCreatedEvent);
             Some(GameEvent::UnitCreated {
-                unit: e.unit,
+                unit: UnitId(e.unit),
                 unit_name: None,
-                builder: e.builder,
+                builder: UnitId(e.builder),
                 builder_name: None,
                 pos: None,
             })
         }
         EVENT_UNIT_FINISHED => {
             let e = &*(data as *const SUnitFinishedEvent);
-            Some(GameEvent::UnitFinished { unit: e.unit, unit_name: None, pos: None })
+            Some(GameEvent::UnitFinished { unit: UnitId(e.unit), unit_name: None, pos: None })
         }
         EVENT_UNIT_IDLE => {
             let e = &*(data as *const SUnitIdleEvent);
-            Some(GameEvent::UnitIdle { unit: e.unit, unit_name: None })
+            Some(GameEvent::UnitIdle { unit: UnitId(e.unit), unit_name: None })
         }
         EVENT_UNIT_MOVE_FAILED => {
             let e = &*(data as *const SUnitMoveFailedEvent);
-            Some(GameEvent::UnitMoveFailed { unit: e.unit, unit_name: None })
+            Some(GameEvent::UnitMoveFailed { unit: UnitId(e.unit), unit_name: None })
         }
         EVENT_UNIT_DAMAGED => {
             let e = &*(data as *const SUnitDamagedEvent);
             Some(GameEvent::UnitDamaged {
-                unit: e.unit,
+                unit: UnitId(e.unit),
                 unit_name: None,
-                attacker: e.attacker,
+                attacker: UnitId(e.attacker),
                 attacker_name: None,
+                attacker_team: None,
+                attacker_relation: None,
                 damage: e.damage,
-                weapon_def_id: e.weapon_def_id,
+                weapon_def_id: WeaponDefId(e.weapon_def_id),
                 paralyzer: e.paralyzer,
+                paralysis: None,
+                pos: None,
             })
         }
         EVENT_UNIT_DESTROYED => {
             let e = &*(data as *const SUnitDestroyedEvent);
             Some(GameEvent::UnitDestroyed {
-                unit: e.unit,
+                unit: UnitId(e.unit),
                 unit_name: None,
-                attacker: e.attacker,
+                attacker: UnitId(e.attacker),
                 attacker_name: None,
-                weapon_def_id: e.weapon_def_id,
+                attacker_team: None,
+                attacker_relation: None,
+                weapon_def_id: WeaponDefId(e.weapon_def_id),
             })
         }
         EVENT_UNIT_GIVEN => {
             let e = &*(data as *const SUnitGivenEvent);
             Some(GameEvent::UnitGiven {
-                unit: e.unit_id,
+                unit: UnitId(e.unit_id),
                 unit_name: None,
-                old_team: e.old_team_id,
-                new_team: e.new_team_id,
+                old_team: TeamId(e.old_team_id),
+                new_team: TeamId(e.new_team_id),
+                old_relation: None,
+                pos: None,
             })
         }
         EVENT_UNIT_CAPTURED => {
             let e = &*(data as *const SUnitCapturedEvent);
             Some(GameEvent::UnitCaptured {
-                unit: e.unit_id,
+                unit: UnitId(e.unit_id),
                 unit_name: None,
-                old_team: e.old_team_id,
-                new_team: e.new_team_id,
+                old_team: TeamId(e.old_team_id),
+                new_team: TeamId(e.new_team_id),
+                new_relation: None,
             })
         }
         EVENT_ENEMY_ENTER_LOS => {
             let e = &*(data as *const SEnemyEnterLOSEvent);
-            Some(GameEvent::EnemyEnterLos { enemy: e.enemy, enemy_name: None, pos: None })
+            Some(GameEvent::EnemyEnterLos { enemy: UnitId(e.enemy), enemy_name: None, team: None, relation: None, pos: None })
         }
         EVENT_ENEMY_LEAVE_LOS => {
             let e = &*(data as *const SEnemyLeaveLOSEvent);
-            Some(GameEvent::EnemyLeaveLos { enemy: e.enemy, enemy_name: None })
+            Some(GameEvent::EnemyLeaveLos { enemy: UnitId(e.enemy), enemy_name: None, team: None, relation: None })
         }
         EVENT_ENEMY_ENTER_RADAR => {
             let e = &*(data as *const SEnemyEnterRadarEvent);
-            Some(GameEvent::EnemyEnterRadar { enemy: e.enemy, enemy_name: None })
+            Some(GameEvent::EnemyEnterRadar { enemy: UnitId(e.enemy), enemy_name: None, team: None, relation: None })
         }
         EVENT_ENEMY_LEAVE_RADAR => {
             let e = &*(data as *const SEnemyLeaveRadarEvent);
-            Some(GameEvent::EnemyLeaveRadar { enemy: e.enemy, enemy_name: None })
+            Some(GameEvent::EnemyLeaveRadar { enemy: UnitId(e.enemy), enemy_name: None, team: None, relation: None })
         }
         EVENT_ENEMY_DAMAGED => {
             let e = &*(data as *const SEnemyDamagedEvent);
             Some(GameEvent::EnemyDamaged {
-                enemy: e.enemy,
+                enemy: UnitId(e.enemy),
                 enemy_name: None,
-                attacker: e.attacker,
+                team: None,
+                relation: None,
+                attacker: UnitId(e.attacker),
                 attacker_name: None,
+                attacker_team: None,
+                attacker_relation: None,
                 damage: e.damage,
-                weapon_def_id: e.weapon_def_id,
+                weapon_def_id: WeaponDefId(e.weapon_def_id),
                 paralyzer: e.paralyzer,
+                paralysis: None,
             })
         }
         EVENT_ENEMY_DESTROYED => {
             let e = &*(data as *const SEnemyDestroyedEvent);
             Some(GameEvent::EnemyDestroyed {
-                enemy: e.enemy,
+                enemy: UnitId(e.enemy),
                 enemy_name: None,
-                attacker: e.attacker,
+                team: None,
+                relation: None,
+                attacker: UnitId(e.attacker),
                 attacker_name: None,
+                attacker_team: None,
+                attacker_relation: None,
             })
         }
         EVENT_ENEMY_CREATED => {
             let e = &*(data as *const SEnemyCreatedEvent);
-            Some(GameEvent::EnemyCreated { enemy: e.enemy, enemy_name: None })
+            Some(GameEvent::EnemyCreated { enemy: UnitId(e.enemy), enemy_name: None, team: None, relation: None })
         }
         EVENT_ENEMY_FINISHED => {
             let e = &*(data as *const SEnemyFinishedEvent);
-            Some(GameEvent::EnemyFinished { enemy: e.enemy, enemy_name: None })
+            Some(GameEvent::EnemyFinished { enemy: UnitId(e.enemy), enemy_name: None, team: None, relation: None })
         }
         EVENT_WEAPON_FIRED => {
             let e = &*(data as *const SWeaponFiredEvent);
             Some(GameEvent::WeaponFired {
-                unit: e.unit_id,
+                unit: UnitId(e.unit_id),
                 unit_name: None,
-                weapon_def_id: e.weapon_def_id,
+                weapon_def_id: WeaponDefId(e.weapon_def_id),
             })
         }
         EVENT_COMMAND_FINISHED => {
             let e = &*(data as *const SCommandFinishedEvent);
             Some(GameEvent::CommandFinished {
-                unit: e.unit_id,
+                unit: UnitId(e.unit_id),
                 unit_name: None,
                 command_id: e.command_id,
                 command_topic: e.command_topic_id,
+                command_ref: None,
             })
         }
         EVENT_LUA_MESSAGE => {
             let e = &*(data as *const SLuaMessageEvent);
-            let data_str = if e.in_data.is_null() {
-                String::new()
-            } else {
-                CStr::from_ptr(e.in_data).to_string_lossy().into_owned()
-            };
-            Some(GameEvent::LuaMessage { data: data_str })
+            let (data, data_lossy) = strings::from_c(e.in_data).unwrap_or_default();
+            Some(GameEvent::LuaMessage { data, data_lossy })
         }
         _ => None,
     }
 }
 
+/// Check a parsed event's ids and amounts against what the engine can send.
+fn check_event(event: &GameEvent) -> Result<(), String> {
+    let unit = |what: &str, id: UnitId| {
+        if (0..MAX_UNITS).contains(&id.0) {
+            Ok(())
+        } else {
+            Err(format!("{} id {} is out of range", what, id))
+        }
+    };
+    // -1 where there is none.
+    let other = |what: &str, id: UnitId| if id.0 == -1 { Ok(()) } else { unit(what, id) };
+    let team = |id: TeamId| {
+        if (0..MAX_TEAMS).contains(&id.0) {
+            Ok(())
+        } else {
+            Err(format!("team {} is out of range", id))
+        }
+    };
+    let damage = |amount: f32| match amount {
+        a if a.is_finite() && a >= 0.0 => Ok(()),
+        a => Err(format!("damage {} is not a finite amount", a)),
+    };
+    match event {
+        GameEvent::Update { frame, .. } if *frame < 0 => Err(format!("frame {} is negative", frame)),
+        GameEvent::Message { player, .. } if !(0..MAX_PLAYERS).contains(player) => {
+            Err(format!("player {} is out of range", player))
+        }
+        GameEvent::UnitCreated { unit: id, builder, .. } => unit("unit", *id).and(other("builder", *builder)),
+        GameEvent::UnitFinished { unit: id, .. }
+        | GameEvent::UnitIdle { unit: id, .. }
+        | GameEvent::UnitMoveFailed { unit: id, .. }
+        | GameEvent::WeaponFired { unit: id, .. }
+        | GameEvent::CommandFinished { unit: id, .. } => unit("unit", *id),
+        GameEvent::UnitDamaged { unit: id, attacker, damage: amount, .. } => {
+            unit("unit", *id).and(other("attacker", *attacker)).and(damage(*amount))
+        }
+        GameEvent::UnitDestroyed { unit: id, attacker, .. } => unit("unit", *id).and(other("attacker", *attacker)),
+        GameEvent::UnitGiven { unit: id, old_team, new_team, .. }
+        | GameEvent::UnitCaptured { unit: id, old_team, new_team, .. } => {
+            unit("unit", *id).and(team(*old_team)).and(team(*new_team))
+        }
+        GameEvent::EnemyEnterLos { enemy, .. }
+        | GameEvent::EnemyLeaveLos { enemy, .. }
+        | GameEvent::EnemyEnterRadar { enemy, .. }
+        | GameEvent::EnemyLeaveRadar { enemy, .. }
+        | GameEvent::EnemyCreated { enemy, .. }
+        | GameEvent::EnemyFinished { enemy, .. } => unit("enemy", *enemy),
+        GameEvent::EnemyDamaged { enemy, attacker, damage: amount, .. } => {
+            unit("enemy", *enemy).and(other("attacker", *attacker)).and(damage(*amount))
+        }
+        GameEvent::EnemyDestroyed { enemy, attacker, .. } => unit("enemy", *enemy).and(other("attacker", *attacker)),
+        _ => Ok(()),
+    }
+}
+
 /// Resolve a unit instance ID to its definition name via engine callbacks.
 /// Returns None for invalid IDs (e.g. 0 or -1 for "no attacker").
-fn resolve_unit_name(cb: &EngineCallbacks, unit_id: i32) -> Option<String> {
-    if unit_id <= 0 {
+fn resolve_unit_name(cb: &EngineCallbacks, unit_id: UnitId) -> Option<String> {
+    if unit_id.0 <= 0 {
         return None;
     }
     let def_id = cb.unit_get_def(unit_id);
-    if def_id < 0 {
-        cb.log(&format!("[SAI enrich] unit_get_def({}) returned {}", unit_id, def_id));
+    if def_id.0 < 0 {
+        log_debug!(Some(cb), "enrich: unit_get_def({}) returned {}", unit_id, def_id);
         return None;
     }
     let name = cb.unit_def_get_name(def_id);
-    cb.log(&format!("[SAI enrich] unit {} -> def {} -> {:?}", unit_id, def_id, name));
+    log_debug!(Some(cb), "enrich: unit {} -> def {} -> {:?}", unit_id, def_id, name);
     name
 }
 
+/// List the AI's own units, for the roster sent after `init`.
+pub fn build_roster(cb: &EngineCallbacks) -> GameEvent {
+    let units = cb
+        .get_team_units()
+        .into_iter()
+        .map(|unit| RosterUnit {
+            unit,
+            unit_name: resolve_unit_name(cb, unit),
+            pos: cb.unit_get_pos(unit),
+        })
+        .collect();
+    GameEvent::Roster {
+        frame: cb.get_current_frame(),
+        units,
+    }
+}
+
+/// Player names by player number, read from the setup script on first use.
+#[derive(Default)]
+pub struct PlayerNames {
+    names: Option<HashMap<i32, String>>,
+}
+
+impl PlayerNames {
+    pub fn get(&mut self, cb: &EngineCallbacks, player: i32) -> Option<String> {
+        self.names
+            .get_or_insert_with(|| {
+                cb.get_setup_script()
+                    .map(|script| parse_player_names(&script))
+                    .unwrap_or_default()
+            })
+            .get(&player)
+            .cloned()
+    }
+}
+
+/// The `Name=` of each `[PLAYERn]` section of a startscript.
+fn parse_player_names(script: &str) -> HashMap<i32, String> {
+    let mut names = HashMap::new();
+    let mut player = None;
+    for line in script.lines().map(str::trim) {
+        if let Some(header) = line.strip_prefix('[') {
+            player = header
+                .trim_end_matches(']')
+                .to_ascii_lowercase()
+                .strip_prefix("player")
+                .and_then(|n| n.parse::<i32>().ok());
+        } else if let (Some(player), Some((key, value))) = (player, line.split_once('=')) {
+            if key.trim().eq_ignore_ascii_case("name") {
+                names.insert(player, value.trim().trim_end_matches(';').trim_end().to_string());
+            }
+        }
+    }
+    names
+}
+
+/// Who is allied with whom, read at init, to tell the agent whose unit an
+/// event is about.
+#[derive(Default)]
+pub struct TeamRelations {
+    my_team: TeamId,
+    my_ally_team: i32,
+    /// Ally team by team id.
+    ally_teams: HashMap<TeamId, i32>,
+    gaia: Option<TeamId>,
+}
+
+impl TeamRelations {
+    pub fn read(cb: &EngineCallbacks) -> Self {
+        let teams = cb.get_teams();
+        let ally_teams = (0..teams).map(TeamId).map(|team| (team, cb.get_team_ally_team(team))).filter(|(_, a)| *a >= 0).collect();
+        // The engine adds gaia after the teams the setup script names.
+        let scripted = cb.get_setup_script().map(|script| count_script_teams(&script));
+        Self {
+            my_team: cb.get_my_team(),
+            my_ally_team: cb.get_my_ally_team(),
+            ally_teams,
+            gaia: scripted.filter(|&n| n < teams).map(TeamId),
+        }
+    }
+
+    pub fn teams(&self) -> usize {
+        self.ally_teams.len()
+    }
+
+    /// Every team the engine listed and its relation, in team order.
+    pub fn slots(&self) -> Vec<TeamSlot> {
+        let mut slots: Vec<TeamSlot> = self
+            .ally_teams
+            .iter()
+            .filter_map(|(&team, &ally_team)| Some(TeamSlot { team, ally_team, relation: self.relation(team)? }))
+            .collect();
+        slots.sort_by_key(|slot| slot.team);
+        slots
+    }
+
+    /// The relation of `team`; None for -1 (unknown) or a team the engine
+    /// didn't list.
+    pub fn relation(&self, team: TeamId) -> Option<Relation> {
+        if team.0 < 0 {
+            None
+        } else if team == self.my_team {
+            Some(Relation::Mine)
+        } else if Some(team) == self.gaia {
+            Some(Relation::Gaia)
+        } else {
+            let ally_team = *self.ally_teams.get(&team)?;
+            Some(if ally_team == self.my_ally_team { Relation::Ally } else { Relation::Enemy })
+        }
+    }
+
+    /// The economy of each allied team, for updates; empty in a 1v1. The
+    /// engine hides other teams' resources (-1), so only allies are asked,
+    /// and one it still won't answer for is left out.
+    pub fn ally_economies(&self, cb: &EngineCallbacks) -> Vec<TeamEconomy> {
+        self.slots()
+            .into_iter()
+            .filter(|slot| slot.relation == Relation::Ally)
+            .filter_map(|slot| read_team_economy(cb, slot.team))
+            .collect()
+    }
+
+    /// `unit`'s team and relation, as far as the engine knows them.
+    fn of_unit(&self, cb: &EngineCallbacks, unit: UnitId) -> (Option<TeamId>, Option<Relation>) {
+        let team = cb.unit_get_team(unit);
+        if team.0 < 0 {
+            return (None, None);
+        }
+        (Some(team), self.relation(team))
+    }
+
+    /// Fill in the team and relation of enemies and attackers, and whose
+    /// the other side of a unit transfer is.
+    pub fn annotate(&self, event: &mut GameEvent, cb: &EngineCallbacks) {
+        match event {
+            GameEvent::EnemyEnterLos { enemy, team, relation, .. }
+            | GameEvent::EnemyLeaveLos { enemy, team, relation, .. }
+            | GameEvent::EnemyEnterRadar { enemy, team, relation, .. }
+            | GameEvent::EnemyLeaveRadar { enemy, team, relation, .. }
+            | GameEvent::EnemyCreated { enemy, team, relation, .. }
+            | GameEvent::EnemyFinished { enemy, team, relation, .. } => {
+                (*team, *relation) = self.of_unit(cb, *enemy);
+            }
+            GameEvent::EnemyDamaged { enemy, team, relation, attacker, attacker_team, attacker_relation, .. }
+            | GameEvent::EnemyDestroyed { enemy, team, relation, attacker, attacker_team, attacker_relation, .. } => {
+                (*team, *relation) = self.of_unit(cb, *enemy);
+                (*attacker_team, *attacker_relation) = self.of_unit(cb, *attacker);
+            }
+            GameEvent::UnitDamaged { attacker, attacker_team, attacker_relation, .. }
+            | GameEvent::UnitDestroyed { attacker, attacker_team, attacker_relation, .. } => {
+                (*attacker_team, *attacker_relation) = self.of_unit(cb, *attacker);
+            }
+            GameEvent::UnitGiven { old_team, old_relation, .. } => *old_relation = self.relation(*old_team),
+            GameEvent::UnitCaptured { new_team, new_relation, .. } => *new_relation = self.relation(*new_team),
+            _ => {}
+        }
+    }
+}
+
+/// How many `[TEAMn]` sections a startscript has.
+fn count_script_teams(script: &str) -> i32 {
+    script
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix('['))
+        .filter(|header| {
+            header
+                .trim_end_matches(']')
+                .to_ascii_lowercase()
+                .strip_prefix("team")
+                .is_some_and(|n| n.parse::<i32>().is_ok())
+        })
+        .count() as i32
+}
+
+/// Resource ids in Zero-K's resource list.
+const RESOURCE_METAL: i32 = 0;
+const RESOURCE_ENERGY: i32 = 1;
+
+/// Read the AI team's current economy.
+pub fn read_economy(cb: &EngineCallbacks) -> Economy {
+    let resource = |id| ResourceState {
+        current: cb.economy_current(id),
+        income: cb.economy_income(id),
+        usage: cb.economy_usage(id),
+        storage: cb.economy_storage(id),
+        received: cb.economy_received(id),
+    };
+    Economy {
+        metal: resource(RESOURCE_METAL),
+        energy: resource(RESOURCE_ENERGY),
+    }
+}
+
+/// Read an allied team's economy; None if the engine won't say.
+fn read_team_economy(cb: &EngineCallbacks, team: TeamId) -> Option<TeamEconomy> {
+    let resource = |id| {
+        let current = cb.team_resource_current(team, id);
+        (current >= 0.0).then(|| ResourceState {
+            current,
+            income: cb.team_resource_income(team, id),
+            usage: cb.team_resource_usage(team, id),
+            storage: cb.team_resource_storage(team, id),
+            received: cb.team_resource_received(team, id).max(0.0),
+        })
+    };
+    Some(TeamEconomy { team, metal: resource(RESOURCE_METAL)?, energy: resource(RESOURCE_ENERGY)? })
+}
+
+/// Read every unit def the game has, for a `query_unit_defs` command.
+pub fn read_unit_defs(cb: &EngineCallbacks) -> Vec<UnitDefInfo> {
+    cb.get_unit_defs()
+        .into_iter()
+        .map(|id| UnitDefInfo {
+            id,
+            name: cb.unit_def_get_name(id).unwrap_or_default(),
+            human_name: cb.unit_def_get_human_name(id).unwrap_or_default(),
+            description: cb.unit_def_get_tooltip(id).filter(|t| !t.is_empty()),
+            metal_cost: cb.unit_def_get_cost(id, RESOURCE_METAL),
+            energy_cost: cb.unit_def_get_cost(id, RESOURCE_ENERGY),
+            build_time: cb.unit_def_get_build_time(id),
+            health: cb.unit_def_get_health(id),
+            speed: cb.unit_def_get_speed(id),
+            builder: cb.unit_def_is_builder(id),
+            build_options: cb.unit_def_get_build_options(id),
+            metal_make: cb.unit_def_get_resource_make(id, RESOURCE_METAL),
+            energy_make: cb.unit_def_get_resource_make(id, RESOURCE_ENERGY),
+            extracts_metal: cb.unit_def_get_extracts_resource(id, RESOURCE_METAL),
+            build_speed: cb.unit_def_get_build_speed(id),
+        })
+        .collect()
+}
+
+/// Read the command queues of `units`, for a `query_unit_queues` command.
+/// Units that died since the GameManager asked, or were never ours, are
+/// left out.
+pub fn read_unit_queues(cb: &EngineCallbacks, units: &[UnitId]) -> Vec<UnitQueue> {
+    let team = cb.get_my_team();
+    units
+        .iter()
+        .filter(|&&unit| cb.unit_get_def(unit).0 >= 0 && cb.unit_get_team(unit) == team)
+        .map(|&unit| UnitQueue { unit, commands: cb.unit_get_current_commands(unit) })
+        .collect()
+}
+
+/// Cell size and grid dimensions for a `query_map_grid` asking for
+/// `cell_size`-elmo cells: coarsened until neither side has more than
+/// [`MAX_MAP_GRID_CELLS`] cells.
+pub fn map_grid_size(cb: &EngineCallbacks, cell_size: f32) -> (f32, usize, usize) {
+    let (width, height) = (cb.map_width() as f32 * SQUARE_SIZE, cb.map_height() as f32 * SQUARE_SIZE);
+    let cell_size = cell_size.max(width.max(height) / MAX_MAP_GRID_CELLS as f32).max(SQUARE_SIZE);
+    let cells = |extent: f32| ((extent / cell_size).ceil() as usize).max(1);
+    (cell_size, cells(width), cells(height))
+}
+
+/// One row of a map grid: ground height at each cell centre, and whether
+/// `build_def` fits there as a `0`/`1` string.
+pub fn read_map_grid_row(
+    cb: &EngineCallbacks,
+    cell_size: f32,
+    width: usize,
+    row: usize,
+    build_def: Option<UnitDefId>,
+) -> (Vec<i32>, Option<String>) {
+    let z = (row as f32 + 0.5) * cell_size;
+    let centres: Vec<[f32; 3]> = (0..width)
+        .map(|col| {
+            let x = (col as f32 + 0.5) * cell_size;
+            [x, cb.map_elevation_at(x, z), z]
+        })
+        .collect();
+    let elevation = centres.iter().map(|pos| pos[1].round() as i32).collect();
+    let buildable = build_def.map(|def| {
+        centres.iter().map(|pos| if cb.map_can_build_at(def, pos, 0) { '1' } else { '0' }).collect()
+    });
+    (elevation, buildable)
+}
+
+/// A unit's paralysis as the engine reports it; None when it can't see the
+/// unit (no max health).
+fn read_paralysis(cb: &EngineCallbacks, unit: UnitId) -> Option<Paralysis> {
+    let max_health = cb.unit_get_max_health(unit);
+    (max_health > 0.0).then(|| Paralysis {
+        paralyze_damage: cb.unit_get_paralyze_damage(unit),
+        health: cb.unit_get_health(unit),
+        max_health,
+    })
+}
+
 /// Enrich a parsed event with human-readable unit names from the engine.
 pub fn enrich_event(event: &mut GameEvent, cb: &EngineCallbacks) {
     match event {
@@ -586,12 +784,22 @@ pub fn enrich_event(event: &mut GameEvent, cb: &EngineCallbacks) {
         GameEvent::UnitMoveFailed { unit, unit_name, .. } => {
             *unit_name = resolve_unit_name(cb, *unit);
         }
-        GameEvent::UnitDamaged { unit, unit_name, attacker, attacker_name, .. } |
+        GameEvent::UnitDamaged { unit, unit_name, attacker, attacker_name, paralyzer, paralysis, pos, .. } => {
+            *unit_name = resolve_unit_name(cb, *unit);
+            *attacker_name = resolve_unit_name(cb, *attacker);
+            *pos = Some(cb.unit_get_pos(*unit));
+            if *paralyzer {
+                *paralysis = read_paralysis(cb, *unit);
+            }
+        }
         GameEvent::UnitDestroyed { unit, unit_name, attacker, attacker_name, .. } => {
             *unit_name = resolve_unit_name(cb, *unit);
             *attacker_name = resolve_unit_name(cb, *attacker);
         }
-        GameEvent::UnitGiven { unit, unit_name, .. } |
+        GameEvent::UnitGiven { unit, unit_name, pos, .. } => {
+            *unit_name = resolve_unit_name(cb, *unit);
+            *pos = Some(cb.unit_get_pos(*unit));
+        }
         GameEvent::UnitCaptured { unit, unit_name, .. } => {
             *unit_name = resolve_unit_name(cb, *unit);
         }
@@ -606,7 +814,13 @@ pub fn enrich_event(event: &mut GameEvent, cb: &EngineCallbacks) {
         GameEvent::EnemyFinished { enemy, enemy_name, .. } => {
             *enemy_name = resolve_unit_name(cb, *enemy);
         }
-        GameEvent::EnemyDamaged { enemy, enemy_name, attacker, attacker_name, .. } |
+        GameEvent::EnemyDamaged { enemy, enemy_name, attacker, attacker_name, paralyzer, paralysis, .. } => {
+            *enemy_name = resolve_unit_name(cb, *enemy);
+            *attacker_name = resolve_unit_name(cb, *attacker);
+            if *paralyzer {
+                *paralysis = read_paralysis(cb, *enemy);
+            }
+        }
         GameEvent::EnemyDestroyed { enemy, enemy_name, attacker, attacker_name, .. } => {
             *enemy_name = resolve_unit_name(cb, *enemy);
             *attacker_name = resolve_unit_name(cb, *attacker);
@@ -615,6 +829,341 @@ pub fn enrich_event(event: &mut GameEvent, cb: &EngineCallbacks) {
         GameEvent::CommandFinished { unit, unit_name, .. } => {
             *unit_name = resolve_unit_name(cb, *unit);
         }
+        GameEvent::Update { economy, .. } => {
+            *economy = Some(read_economy(cb));
+        }
         _ => {}
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_engine::MockEngine;
+    use std::ffi::CString;
+    use std::ptr;
+
+    unsafe fn parse<T>(topic: c_int, data: &T) -> GameEvent {
+        parse_event(topic, data as *const T as *const c_void).expect("event parsed")
+    }
+
+    fn engine_with_units() -> MockEngine {
+        let engine = MockEngine::new();
+        engine.with_game(|g| {
+            g.add_unit(10, "cloakraid", [100.0, 5.0, 200.0], 0);
+            g.add_unit(11, "factorycloak", [300.0, 5.0, 400.0], 0);
+            g.add_unit(90, "vehassault", [900.0, 5.0, 800.0], 1);
+        });
+        engine
+    }
+
+    #[test]
+    fn test_parse_simple_topics() {
+        unsafe {
+            assert_eq!(parse(EVENT_RELEASE, &SReleaseEvent { reason: 2 }), GameEvent::Release { reason: 2, stats: None });
+            assert_eq!(parse(EVENT_UPDATE, &SUpdateEvent { frame: 90 }), GameEvent::Update { frame: 90, awaiting_commands: false, economy: None, allies: Vec::new(), counters: Default::default(), command_backlog: 0, under_construction: Vec::new(), update_interval: None });
+            let text = CString::new("gl hf").unwrap();
+            assert_eq!(
+                parse(EVENT_MESSAGE, &SMessageEvent { player: 1, message: text.as_ptr() }),
+                GameEvent::Message { player: 1, player_name: None, text: "gl hf".into(), text_lossy: false }
+            );
+            assert_eq!(
+                parse(EVENT_MESSAGE, &SMessageEvent { player: 1, message: ptr::null() }),
+                GameEvent::Message { player: 1, player_name: None, text: String::new(), text_lossy: false }
+            );
+            // A player typing in another code page.
+            let latin1 = CString::new(b"caf\xe9".to_vec()).unwrap();
+            assert_eq!(
+                parse(EVENT_MESSAGE, &SMessageEvent { player: 1, message: latin1.as_ptr() }),
+                GameEvent::Message { player: 1, player_name: None, text: "caf\u{fffd}".into(), text_lossy: true }
+            );
+            let lua = CString::new("mex_claimed 3").unwrap();
+            assert_eq!(
+                parse(EVENT_LUA_MESSAGE, &SLuaMessageEvent { in_data: lua.as_ptr() }),
+                GameEvent::LuaMessage { data: "mex_claimed 3".into(), data_lossy: false }
+            );
+            assert_eq!(
+                parse(EVENT_COMMAND_FINISHED, &SCommandFinishedEvent { unit_id: 10, command_id: 4, command_topic_id: 42 }),
+                GameEvent::CommandFinished { unit: UnitId(10), unit_name: None, command_id: 4, command_topic: 42, command_ref: None }
+            );
+            assert_eq!(
+                parse(EVENT_WEAPON_FIRED, &SWeaponFiredEvent { unit_id: 10, weapon_def_id: 7 }),
+                GameEvent::WeaponFired { unit: UnitId(10), unit_name: None, weapon_def_id: WeaponDefId(7) }
+            );
+            assert!(parse_event(EVENT_NULL, ptr::null()).is_none());
+        }
+    }
+
+    #[test]
+    fn test_parse_unit_topics() {
+        unsafe {
+            assert!(matches!(
+                parse(EVENT_UNIT_CREATED, &SUnitCreatedEvent { unit: 10, builder: 11 }),
+                GameEvent::UnitCreated { unit: UnitId(10), builder: UnitId(11), .. }
+            ));
+            assert!(matches!(parse(EVENT_UNIT_FINISHED, &SUnitFinishedEvent { unit: 10 }), GameEvent::UnitFinished { unit: UnitId(10), .. }));
+            assert!(matches!(parse(EVENT_UNIT_IDLE, &SUnitIdleEvent { unit: 10 }), GameEvent::UnitIdle { unit: UnitId(10), .. }));
+            assert!(matches!(parse(EVENT_UNIT_MOVE_FAILED, &SUnitMoveFailedEvent { unit: 10 }), GameEvent::UnitMoveFailed { unit: UnitId(10), .. }));
+            let dir = [0.0f32, 0.0, 1.0];
+            assert_eq!(
+                parse(EVENT_UNIT_DAMAGED, &SUnitDamagedEvent {
+                    unit: 10, attacker: 90, damage: 35.5, dir: &dir, weapon_def_id: 3, paralyzer: true,
+                }),
+                GameEvent::UnitDamaged {
+                    unit: UnitId(10), unit_name: None, attacker: UnitId(90), attacker_name: None,
+                    attacker_team: None, attacker_relation: None,
+                    damage: 35.5, weapon_def_id: WeaponDefId(3), paralyzer: true, paralysis: None, pos: None,
+                }
+            );
+            assert!(matches!(
+                parse(EVENT_UNIT_DESTROYED, &SUnitDestroyedEvent { unit: 10, attacker: 90, weapon_def_id: 3 }),
+                GameEvent::UnitDestroyed { unit: UnitId(10), attacker: UnitId(90), weapon_def_id: WeaponDefId(3), .. }
+            ));
+            assert!(matches!(
+                parse(EVENT_UNIT_GIVEN, &SUnitGivenEvent { unit_id: 10, old_team_id: 0, new_team_id: 1 }),
+                GameEvent::UnitGiven { unit: UnitId(10), old_team: TeamId(0), new_team: TeamId(1), .. }
+            ));
+            assert!(matches!(
+                parse(EVENT_UNIT_CAPTURED, &SUnitCapturedEvent { unit_id: 10, old_team_id: 1, new_team_id: 0 }),
+                GameEvent::UnitCaptured { unit: UnitId(10), old_team: TeamId(1), new_team: TeamId(0), .. }
+            ));
+        }
+    }
+
+    #[test]
+    fn test_parse_enemy_topics() {
+        unsafe {
+            assert!(matches!(parse(EVENT_ENEMY_ENTER_LOS, &SEnemyEnterLOSEvent { enemy: 90 }), GameEvent::EnemyEnterLos { enemy: UnitId(90), .. }));
+            assert!(matches!(parse(EVENT_ENEMY_LEAVE_LOS, &SEnemyLeaveLOSEvent { enemy: 90 }), GameEvent::EnemyLeaveLos { enemy: UnitId(90), .. }));
+            assert!(matches!(parse(EVENT_ENEMY_ENTER_RADAR, &SEnemyEnterRadarEvent { enemy: 90 }), GameEvent::EnemyEnterRadar { enemy: UnitId(90), .. }));
+            assert!(matches!(parse(EVENT_ENEMY_LEAVE_RADAR, &SEnemyLeaveRadarEvent { enemy: 90 }), GameEvent::EnemyLeaveRadar { enemy: UnitId(90), .. }));
+            assert!(matches!(parse(EVENT_ENEMY_CREATED, &SEnemyCreatedEvent { enemy: 90 }), GameEvent::EnemyCreated { enemy: UnitId(90), .. }));
+            assert!(matches!(parse(EVENT_ENEMY_FINISHED, &SEnemyFinishedEvent { enemy: 90 }), GameEvent::EnemyFinished { enemy: UnitId(90), .. }));
+            assert!(matches!(
+                parse(EVENT_ENEMY_DAMAGED, &SEnemyDamagedEvent {
+                    enemy: 90, attacker: 10, damage: 12.0, dir: ptr::null(), weapon_def_id: 1, paralyzer: false,
+                }),
+                GameEvent::EnemyDamaged { enemy: UnitId(90), attacker: UnitId(10), paralyzer: false, .. }
+            ));
+            assert!(matches!(
+                parse(EVENT_ENEMY_DESTROYED, &SEnemyDestroyedEvent { enemy: 90, attacker: 10 }),
+                GameEvent::EnemyDestroyed { enemy: UnitId(90), attacker: UnitId(10), .. }
+            ));
+        }
+    }
+
+    #[test]
+    fn test_malformed_events_quarantined() {
+        let reason = |event| match event {
+            GameEvent::MalformedEvent { reason, .. } => reason,
+            other => panic!("not quarantined: {:?}", other),
+        };
+        unsafe {
+            assert_eq!(parse_event(EVENT_UNIT_IDLE, ptr::null()), Some(GameEvent::MalformedEvent { topic: EVENT_UNIT_IDLE, reason: "no event data".into() }));
+            // Topics the bridge doesn't read stay unread, data or not.
+            assert!(parse_event(EVENT_SEISMIC_PING, ptr::null()).is_none());
+
+            // A damage event misreported as a given unit: its damage reads as a team.
+            let damaged = SUnitDamagedEvent { unit: 10, attacker: 90, damage: 35.5, dir: ptr::null(), weapon_def_id: 3, paralyzer: false };
+            assert_eq!(reason(parse(EVENT_UNIT_GIVEN, &damaged)), format!("team {} is out of range", 35.5f32.to_bits()));
+            // A position misreported as a unit event: floats read as ids.
+            let pos = [1500.0f32, 20.0, 900.0];
+            assert_eq!(reason(parse(EVENT_UNIT_CREATED, &pos)), format!("unit id {} is out of range", 1500.0f32.to_bits()));
+
+            let nan = SEnemyDamagedEvent { enemy: 90, attacker: -1, damage: f32::NAN, dir: ptr::null(), weapon_def_id: 1, paralyzer: false };
+            assert_eq!(reason(parse(EVENT_ENEMY_DAMAGED, &nan)), "damage NaN is not a finite amount");
+            let negative = SUnitDamagedEvent { damage: -4.0, ..damaged };
+            assert_eq!(reason(parse(EVENT_UNIT_DAMAGED, &negative)), "damage -4 is not a finite amount");
+            assert_eq!(reason(parse(EVENT_UNIT_DESTROYED, &SUnitDestroyedEvent { unit: 10, attacker: MAX_UNITS, weapon_def_id: 0 })), "attacker id 32000 is out of range");
+            assert_eq!(reason(parse(EVENT_UPDATE, &SUpdateEvent { frame: -30 })), "frame -30 is negative");
+            let text = CString::new("hi").unwrap();
+            assert_eq!(reason(parse(EVENT_MESSAGE, &SMessageEvent { player: 4096, message: text.as_ptr() })), "player 4096 is out of range");
+
+            // No attacker is fine.
+            assert!(matches!(parse(EVENT_UNIT_DESTROYED, &SUnitDestroyedEvent { unit: 10, attacker: -1, weapon_def_id: -1 }), GameEvent::UnitDestroyed { .. }));
+        }
+    }
+
+    #[test]
+    fn test_enrich_names_and_positions() {
+        let engine = engine_with_units();
+        let cb = engine.callbacks();
+
+        let mut created = unsafe { parse(EVENT_UNIT_CREATED, &SUnitCreatedEvent { unit: 10, builder: 11 }) };
+        enrich_event(&mut created, &cb);
+        assert_eq!(
+            created,
+            GameEvent::UnitCreated {
+                unit: UnitId(10),
+                unit_name: Some("cloakraid".into()),
+                builder: UnitId(11),
+                builder_name: Some("factorycloak".into()),
+                pos: Some([100.0, 5.0, 200.0]),
+            }
+        );
+
+        let mut los = unsafe { parse(EVENT_ENEMY_ENTER_LOS, &SEnemyEnterLOSEvent { enemy: 90 }) };
+        enrich_event(&mut los, &cb);
+        assert_eq!(
+            los,
+            GameEvent::EnemyEnterLos {
+                enemy: UnitId(90),
+                enemy_name: Some("vehassault".into()),
+                team: None,
+                relation: None,
+                pos: Some([900.0, 5.0, 800.0]),
+            }
+        );
+
+        let mut damaged = unsafe {
+            parse(EVENT_UNIT_DAMAGED, &SUnitDamagedEvent {
+                unit: 10, attacker: 90, damage: 35.5, dir: &[0.0, 0.0, 1.0], weapon_def_id: 3, paralyzer: false,
+            })
+        };
+        enrich_event(&mut damaged, &cb);
+        assert!(matches!(
+            damaged,
+            GameEvent::UnitDamaged { attacker_name: Some(ref n), pos: Some([100.0, 5.0, 200.0]), .. } if n == "vehassault"
+        ));
+
+        // EMP hits carry how close the unit is to being stunned.
+        engine.with_game(|g| {
+            let unit = g.units.get_mut(&10).unwrap();
+            (unit.health, unit.max_health, unit.paralyze_damage) = (80.0, 200.0, 120.0);
+        });
+        let mut stunned = unsafe {
+            parse(EVENT_UNIT_DAMAGED, &SUnitDamagedEvent {
+                unit: 10, attacker: 90, damage: 60.0, dir: ptr::null(), weapon_def_id: 4, paralyzer: true,
+            })
+        };
+        enrich_event(&mut stunned, &cb);
+        let expected = Paralysis { paralyze_damage: 120.0, health: 80.0, max_health: 200.0 };
+        assert!(matches!(stunned, GameEvent::UnitDamaged { paralysis: Some(p), .. } if p == expected));
+        let mut out_of_sight = unsafe {
+            parse(EVENT_ENEMY_DAMAGED, &SEnemyDamagedEvent {
+                enemy: 55, attacker: 10, damage: 60.0, dir: ptr::null(), weapon_def_id: 4, paralyzer: true,
+            })
+        };
+        enrich_event(&mut out_of_sight, &cb);
+        assert!(matches!(out_of_sight, GameEvent::EnemyDamaged { paralysis: None, .. }));
+
+        // Unknown and "no attacker" ids stay unenriched
+        let mut destroyed = unsafe {
+            parse(EVENT_UNIT_DESTROYED, &SUnitDestroyedEvent { unit: 55, attacker: -1, weapon_def_id: -1 })
+        };
+        enrich_event(&mut destroyed, &cb);
+        assert!(matches!(
+            destroyed,
+            GameEvent::UnitDestroyed { unit_name: None, attacker_name: None, .. }
+        ));
+    }
+
+    #[test]
+    fn test_player_names_from_setup_script() {
+        let engine = MockEngine::new();
+        let script = "[GAME]\n{\n    [PLAYER0]\n    {\n        Name=GameManager;\n    }\n    [AI0]\n    {\n        Name=AgentBridge;\n    }\n    [player1]\n    {\n        name = Godde ;\n    }\n}";
+        engine.with_game(|g| g.setup_script = Some(CString::new(script).unwrap()));
+        let mut names = PlayerNames::default();
+        assert_eq!(names.get(&engine.callbacks(), 1).as_deref(), Some("Godde"));
+        assert_eq!(names.get(&engine.callbacks(), 0).as_deref(), Some("GameManager"));
+        assert_eq!(names.get(&engine.callbacks(), 2), None);
+
+        // Read once: later script changes don't matter.
+        engine.with_game(|g| g.setup_script = None);
+        assert_eq!(names.get(&engine.callbacks(), 1).as_deref(), Some("Godde"));
+    }
+
+    #[test]
+    fn test_team_relations() {
+        let engine = engine_with_units();
+        // 2v1: teams 0 (us) and 1 against 2, then gaia, which the script doesn't name.
+        let script = "[GAME]\n{\n    [TEAM0]\n    {\n    }\n    [team1]\n    {\n    }\n    [TEAM2]\n    {\n    }\n}";
+        engine.with_game(|g| {
+            g.setup_script = Some(CString::new(script).unwrap());
+            g.ally_teams = vec![0, 0, 1, 2];
+            g.add_unit(20, "cloakriot", [0.0; 3], 1);
+            g.add_unit(91, "vehassault", [0.0; 3], 2);
+            g.add_unit(95, "chicken_dodo", [0.0; 3], 3);
+        });
+        let cb = engine.callbacks();
+        let teams = TeamRelations::read(&cb);
+        assert_eq!(
+            [0, 1, 2, 3, -1, 7].map(|team| teams.relation(TeamId(team))),
+            [Some(Relation::Mine), Some(Relation::Ally), Some(Relation::Enemy), Some(Relation::Gaia), None, None]
+        );
+
+        let mut destroyed = unsafe { parse(EVENT_ENEMY_DESTROYED, &SEnemyDestroyedEvent { enemy: 95, attacker: 20 }) };
+        teams.annotate(&mut destroyed, &cb);
+        assert!(matches!(
+            destroyed,
+            GameEvent::EnemyDestroyed {
+                team: Some(TeamId(3)),
+                relation: Some(Relation::Gaia),
+                attacker_team: Some(TeamId(1)),
+                attacker_relation: Some(Relation::Ally),
+                ..
+            }
+        ));
+        let mut captured = unsafe { parse(EVENT_UNIT_CAPTURED, &SUnitCapturedEvent { unit_id: 10, old_team_id: 0, new_team_id: 2 }) };
+        teams.annotate(&mut captured, &cb);
+        assert!(matches!(captured, GameEvent::UnitCaptured { new_relation: Some(Relation::Enemy), .. }));
+        let mut given = unsafe { parse(EVENT_UNIT_GIVEN, &SUnitGivenEvent { unit_id: 20, old_team_id: 1, new_team_id: 0 }) };
+        teams.annotate(&mut given, &cb);
+        assert!(matches!(given, GameEvent::UnitGiven { old_relation: Some(Relation::Ally), .. }));
+        // Units the engine can't see have no team.
+        let mut damaged = unsafe {
+            parse(EVENT_UNIT_DAMAGED, &SUnitDamagedEvent {
+                unit: 10, attacker: 999, damage: 5.0, dir: ptr::null(), weapon_def_id: 1, paralyzer: false,
+            })
+        };
+        teams.annotate(&mut damaged, &cb);
+        assert!(matches!(damaged, GameEvent::UnitDamaged { attacker_team: None, attacker_relation: None, .. }));
+
+        // Without a setup script, gaia is just another enemy team.
+        engine.with_game(|g| g.setup_script = None);
+        assert_eq!(TeamRelations::read(&cb).relation(TeamId(3)), Some(Relation::Enemy));
+    }
+
+    #[test]
+    fn test_enrich_update_with_economy() {
+        let engine = MockEngine::new();
+        engine.with_game(|g| {
+            g.economy.insert(RESOURCE_METAL, [150.0, 4.0, 3.5, 500.0]);
+            g.economy.insert(RESOURCE_ENERGY, [20.0, 11.0, 12.5, 100.0]);
+        });
+        let mut update = unsafe { parse(EVENT_UPDATE, &SUpdateEvent { frame: 300 }) };
+        enrich_event(&mut update, &engine.callbacks());
+        let GameEvent::Update { economy: Some(economy), .. } = update else {
+            panic!("update not enriched: {:?}", update);
+        };
+        assert_eq!(
+            economy.metal,
+            ResourceState { current: 150.0, income: 4.0, usage: 3.5, storage: 500.0, received: 0.0 }
+        );
+        assert_eq!(economy.energy.usage, 12.5);
+    }
+
+    #[test]
+    fn test_ally_economies() {
+        let engine = MockEngine::new();
+        let cb = engine.callbacks();
+        // 1v1: nobody to ask about.
+        assert!(TeamRelations::read(&cb).ally_economies(&cb).is_empty());
+
+        // 2v2: teams 0 (us) and 2 against 1 and 3.
+        engine.with_game(|g| {
+            g.ally_teams = vec![0, 1, 0, 1];
+            g.received.insert(RESOURCE_ENERGY, 7.5);
+            g.team_economy.insert((2, RESOURCE_METAL), [80.0, 9.0, 6.0, 500.0, 0.0]);
+            g.team_economy.insert((2, RESOURCE_ENERGY), [300.0, 40.0, 25.0, 1000.0, 2.0]);
+            // An enemy's, which the engine answers -1 for.
+            g.team_economy.insert((1, RESOURCE_METAL), [500.0, 30.0, 0.0, 500.0, 0.0]);
+        });
+        let allies = TeamRelations::read(&cb).ally_economies(&cb);
+        assert_eq!(allies.len(), 1);
+        assert_eq!(allies[0].team, TeamId(2));
+        assert_eq!(allies[0].metal, ResourceState { current: 80.0, income: 9.0, usage: 6.0, storage: 500.0, received: 0.0 });
+        assert_eq!(allies[0].energy.received, 2.0);
+        assert_eq!(read_economy(&cb).energy.received, 7.5);
+    }
+}
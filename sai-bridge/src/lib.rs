@@ -4,22 +4,98 @@
 //! Routes engine events to GameManager via Unix socket IPC,
 //! receives commands back, and dispatches them to the engine.
 
+#[macro_use]
+pub mod logging;
+pub mod cadence;
 pub mod callbacks;
 pub mod commands;
+pub mod construction;
 pub mod events;
 pub mod ipc;
+#[cfg(test)]
+mod mock_engine;
+pub mod orders;
+pub mod strings;
 
 use callbacks::{EngineCallbacks, SSkirmishAICallback};
-use events::{enrich_event, parse_event, GameEvent, EVENT_INIT, EVENT_UPDATE};
+use commands::GameCommand;
+use events::{enrich_event, parse_event, GameEvent, PlayerNames, TeamRelations, EVENT_INIT, EVENT_UPDATE};
 use ipc::IpcClient;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use sai_protocol::{BridgeStats, BuildInfo, UnitId, UpdateCadence};
 use std::ffi::{c_int, c_void};
+use std::panic::AssertUnwindSafe;
 use std::sync::Mutex;
 
+/// Commit the library was built from (build.rs); empty outside a git
+/// checkout.
+const GIT_HASH: &str = env!("SAI_BRIDGE_GIT_HASH");
+
+/// The build, findable in the library's bytes: the GameManager reads it
+/// when installing the bridge, to tell which build is installed.
+#[used]
+static BUILD_MARKER: &str = concat!("sai-bridge-build:", env!("CARGO_PKG_VERSION"), "+", env!("SAI_BRIDGE_GIT_HASH"), "\0");
+
+/// This build and the engine running it, for the init event.
+fn build_info(cb: &EngineCallbacks) -> BuildInfo {
+    BuildInfo {
+        bridge_version: env!("CARGO_PKG_VERSION").to_string(),
+        git_hash: Some(GIT_HASH.to_string()).filter(|h| !h.is_empty()),
+        engine_version: cb.engine_version(),
+    }
+}
+
 /// Per-AI instance state.
 struct AiInstance {
     callbacks: EngineCallbacks,
     ipc: Option<IpcClient>,
     frame_counter: u32,
+    /// Lockstep play: pause at every throttled update (set_turn_mode).
+    turn_mode: bool,
+    /// The engine is paused waiting for the GameManager's end_turn.
+    awaiting_turn: bool,
+    /// Pause state and speed as last reported to the GameManager.
+    paused: bool,
+    speed: f32,
+    /// Frames between forwarded UPDATE events.
+    cadence: cadence::Cadence,
+    /// `frame_counter` at the last forwarded UPDATE.
+    last_update: u32,
+    /// Benchmark game (connection.json): only game-level events are forwarded.
+    benchmark: bool,
+    /// Names for chat senders.
+    player_names: PlayerNames,
+    /// Whose units events are about; read at init.
+    teams: TeamRelations,
+    /// Event types counted per unit instead of forwarded (connection.json
+    /// `aggregate_events`).
+    aggregate: Vec<String>,
+    /// Counts since the last forwarded update, by unit then event type.
+    counters: HashMap<UnitId, BTreeMap<String, u32>>,
+    /// Our units under construction, sampled at each forwarded update.
+    construction: construction::ConstructionTracker,
+    /// Our units' dispatched orders, to tell which one a `command_finished`
+    /// is for.
+    orders: orders::OrderRefs,
+    /// Where the GameManager listens; retried while `ipc` is None.
+    socket_path: String,
+    /// The init event and starting roster, sent first on every connect.
+    opening: Vec<GameEvent>,
+    /// Events raised while no GameManager was connected, oldest first.
+    backlog: VecDeque<GameEvent>,
+    /// Events dropped from a full backlog since the last connect.
+    backlog_dropped: usize,
+    /// Commands received beyond this frame's MAX_COMMANDS_PER_FRAME, for
+    /// the next frames, with their `command_ref`.
+    pending_commands: VecDeque<(GameCommand, Option<u64>)>,
+    /// Counters for the final release, less those of the live connection
+    /// (`frames` is filled in when sending).
+    stats: BridgeStats,
+    /// The engine's release reason, once EVENT_RELEASE has arrived.
+    release_reason: Option<i32>,
+    /// release() came before EVENT_RELEASE: the instance is kept only to
+    /// pass the reason on when it arrives.
+    released: bool,
 }
 
 /// Global AI instance storage. Recoil supports up to 255 AIs,
@@ -30,37 +106,79 @@ static INSTANCES: Mutex<Vec<Option<AiInstance>>> = Mutex::new(Vec::new());
 /// At 30 fps, every 30 frames = ~1 second.
 const UPDATE_INTERVAL: u32 = 30;
 
-fn get_socket_path(cb: &EngineCallbacks) -> String {
-    // 1. connection.json in AI data dir (written by GM before each launch).
+/// Benchmark games run far faster than real time with nobody watching
+/// them live: update every 30 game seconds instead.
+const BENCHMARK_UPDATE_INTERVAL: u32 = 900;
+
+/// Lua message the bootstrap widget sends every draw frame while the game
+/// is paused. A paused engine sends no UPDATE, so this is what lets a
+/// turn-mode bridge poll for end_turn. Not forwarded to the GameManager.
+const TURN_HEARTBEAT: &str = "agent_turn_poll";
+
+/// Unit defs per `unit_defs` event. A full Zero-K catalog is several
+/// hundred defs; chunks keep each IPC line to tens of kilobytes.
+const UNIT_DEFS_PER_EVENT: usize = 50;
+
+/// Cells per `map_grid` event, rounded down to whole rows: at most a few
+/// dozen kilobytes per IPC line.
+const MAP_GRID_CELLS_PER_EVENT: usize = 4096;
+
+/// Speed changes smaller than this aren't reported: the engine nudges the
+/// speed factor on its own to keep lagging players in step.
+const SPEED_TOLERANCE: f32 = 0.05;
+
+/// Update frames between attempts to reach a GameManager that isn't
+/// connected: about once a second at normal speed.
+const RECONNECT_INTERVAL: u32 = 30;
+
+/// Events kept for a GameManager that isn't connected yet. Beyond this the
+/// oldest are dropped; a backlog this size stays well under the IPC
+/// client's send buffer.
+const BACKLOG_CAP: usize = 2000;
+
+/// Read connection.json from the AI data dir (written by GM before each launch).
+/// Returns the parsed config and the path it was read from.
+fn read_connection_config(cb: &EngineCallbacks) -> Option<(serde_json::Value, String)> {
+    let data_dir = cb.get_info_value("dataDir")?;
+    let config_path = format!("{}/connection.json", data_dir.trim_end_matches('/'));
+    let contents = std::fs::read_to_string(&config_path).ok()?;
+    let config = serde_json::from_str::<serde_json::Value>(&contents).ok()?;
+    Some((config, config_path))
+}
+
+fn get_socket_path(cb: &EngineCallbacks, connection: Option<&(serde_json::Value, String)>) -> String {
+    // 1. connection.json in AI data dir.
     //    Checked first because AIOptions.lua declares a default for socket_path,
     //    so get_option_value always returns *something* — even for dynamically
     //    created AIs via /aicontrol that have no startscript [Options] block.
-    if let Some(data_dir) = cb.get_info_value("dataDir") {
-        let config_path = format!("{}/connection.json", data_dir.trim_end_matches('/'));
-        if let Ok(contents) = std::fs::read_to_string(&config_path) {
-            if let Ok(config) = serde_json::from_str::<serde_json::Value>(&contents) {
-                if let Some(path) = config.get("socket_path").and_then(|v| v.as_str()) {
-                    cb.log(&format!("[SAI Bridge] Socket path from {}", config_path));
-                    return path.to_string();
-                }
-            }
+    //    Several AIs in one engine read the same file: `socket_paths` gives
+    //    each its own socket, by team or by AI id.
+    if let Some((config, config_path)) = connection {
+        let own = [format!("team:{}", cb.get_my_team()), format!("ai:{}", cb.ai_id)];
+        if let Some(path) = own.iter().find_map(|key| config.get("socket_paths")?.get(key)?.as_str()) {
+            log_info!(Some(cb), "Socket path from {} socket_paths", config_path);
+            return path.to_string();
+        }
+        if let Some(path) = config.get("socket_path").and_then(|v| v.as_str()) {
+            log_info!(Some(cb), "Socket path from {}", config_path);
+            return path.to_string();
         }
     }
 
     // 2. AI option (startscript [Options] — AI-slot mode fallback)
     if let Some(path) = cb.get_option_value("socket_path") {
-        cb.log("[SAI Bridge] Socket path from AI option");
+        log_info!(Some(cb), "Socket path from AI option");
         return path;
     }
 
     // 3. Environment variable
     if let Ok(path) = std::env::var("SAI_SOCKET_PATH") {
-        cb.log("[SAI Bridge] Socket path from SAI_SOCKET_PATH env");
+        log_info!(Some(cb), "Socket path from SAI_SOCKET_PATH env");
         return path;
     }
 
     // 4. Default
-    cb.log("[SAI Bridge] Using default socket path");
+    log_info!(Some(cb), "Using default socket path");
     "/tmp/game-manager.sock".to_string()
 }
 
@@ -74,32 +192,74 @@ pub unsafe extern "C" fn init(
     callback: *const SSkirmishAICallback,
 ) -> c_int {
     let cb = unsafe { EngineCallbacks::new(skirmish_ai_id, callback) };
-    cb.log("[SAI Bridge] Initializing... (v2 — enrichment + name commands)");
+    let connection = read_connection_config(&cb);
+    if let Some((config, _)) = &connection {
+        if let Err(e) = logging::configure(config) {
+            log_warn!(Some(&cb), "Logging config ignored: {}", e);
+        }
+    }
+    log_info!(Some(&cb), "Initializing... (v2 — enrichment + name commands)");
 
     // Connect to GameManager
-    let socket_path = get_socket_path(&cb);
+    let socket_path = get_socket_path(&cb, connection.as_ref());
     let ipc = match IpcClient::connect(&socket_path) {
         Ok(client) => {
-            cb.log(&format!(
-                "[SAI Bridge] Connected to GameManager at {}",
-                socket_path
-            ));
+            log_info!(Some(&cb), "Connected to GameManager at {}", socket_path);
             // Don't send init here — wait for handleEvent(EVENT_INIT) which has game data
             Some(client)
         }
         Err(e) => {
-            cb.log(&format!(
-                "[SAI Bridge] Failed to connect to GameManager at {}: {}",
-                socket_path, e
-            ));
+            log_warn!(
+                Some(&cb),
+                "Failed to connect to GameManager at {}: {}",
+                socket_path,
+                e
+            );
             None
         }
     };
 
+    let benchmark = connection
+        .as_ref()
+        .and_then(|(config, _)| config.get("benchmark"))
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    if benchmark {
+        log_info!(Some(&cb), "Benchmark mode: per-unit events suppressed");
+    }
+    let aggregate = aggregate_events(&cb, connection.as_ref());
+    // Benchmarks update rarely whatever the config says.
+    let cadence = if benchmark {
+        cadence::Cadence::new(UpdateCadence::default(), BENCHMARK_UPDATE_INTERVAL)
+    } else {
+        cadence::Cadence::new(update_cadence(&cb, connection.as_ref()), UPDATE_INTERVAL)
+    };
+
     let instance = AiInstance {
         callbacks: cb,
         ipc,
         frame_counter: 0,
+        turn_mode: false,
+        awaiting_turn: false,
+        paused: false,
+        speed: 1.0,
+        cadence,
+        last_update: 0,
+        benchmark,
+        player_names: PlayerNames::default(),
+        teams: TeamRelations::default(),
+        aggregate,
+        counters: HashMap::new(),
+        construction: Default::default(),
+        orders: Default::default(),
+        socket_path,
+        opening: Vec::new(),
+        backlog: VecDeque::new(),
+        backlog_dropped: 0,
+        pending_commands: VecDeque::new(),
+        stats: BridgeStats::default(),
+        release_reason: None,
+        released: false,
     };
 
     // Store instance
@@ -115,6 +275,11 @@ pub unsafe extern "C" fn init(
 
 /// Called by the engine when this AI is removed.
 ///
+/// The GameManager gets a final release with the engine's reason and our
+/// counters. The engine normally sends EVENT_RELEASE first; if it hasn't
+/// yet, the release goes out without a reason and the instance stays
+/// until EVENT_RELEASE brings one.
+///
 /// # Safety
 /// Called by the Recoil engine with valid parameters.
 #[unsafe(no_mangle)]
@@ -122,18 +287,40 @@ pub unsafe extern "C" fn release(skirmish_ai_id: c_int) -> c_int {
     let mut instances = INSTANCES.lock().unwrap();
     let id = skirmish_ai_id as usize;
     if let Some(Some(instance)) = instances.get_mut(id) {
-        instance.callbacks.log("[SAI Bridge] Releasing...");
-
-        // Send release event
-        if let Some(ref mut ipc) = instance.ipc {
-            let _ = ipc.send_event(&GameEvent::Release { reason: 0 });
+        log_info!(Some(&instance.callbacks), "Releasing...");
+        send_release(instance);
+        if instance.release_reason.is_some() {
+            instances[id] = None;
+        } else {
+            instance.released = true;
         }
-
-        instances[id] = None;
     }
     0
 }
 
+/// Send the final release event.
+fn send_release(instance: &mut AiInstance) {
+    let event = GameEvent::Release {
+        reason: instance.release_reason.unwrap_or(0),
+        stats: Some(bridge_stats(instance)),
+    };
+    log_info!(Some(&instance.callbacks), "Final release: {:?}", event);
+    if let Some(ref mut ipc) = instance.ipc {
+        let _ = ipc.send_event(&event);
+    }
+}
+
+/// Counters over the whole game, across connections.
+fn bridge_stats(instance: &AiInstance) -> BridgeStats {
+    let (sent, dropped) = instance.ipc.as_ref().map_or((0, 0), IpcClient::event_counts);
+    BridgeStats {
+        frames: instance.frame_counter,
+        events_sent: instance.stats.events_sent + sent,
+        events_dropped: instance.stats.events_dropped + dropped,
+        panics: instance.stats.panics,
+    }
+}
+
 /// Main event handler — called by the engine for every game event.
 ///
 /// # Safety
@@ -152,33 +339,75 @@ pub unsafe extern "C" fn handleEvent(
         None => return -1,
     };
 
+    if instance.released {
+        if topic == events::EVENT_RELEASE {
+            let release = unsafe { (data as *const events::SReleaseEvent).as_ref() };
+            instance.release_reason = Some(release.map_or(0, |r| r.reason));
+            send_release(instance);
+            instances[id] = None;
+        }
+        return 0;
+    }
+
+    // A panic must not unwind into the engine: the event is skipped and
+    // counted, and the game goes on.
+    let handled = std::panic::catch_unwind(AssertUnwindSafe(|| unsafe {
+        handle_event(instance, skirmish_ai_id, topic, data)
+    }));
+    handled.unwrap_or_else(|_| {
+        instance.stats.panics += 1;
+        log_warn!(Some(&instance.callbacks), "Handling event {} panicked; skipped", topic);
+        -1
+    })
+}
+
+/// handleEvent for a live instance.
+///
+/// # Safety
+/// `data` points to the struct for `topic`.
+unsafe fn handle_event(
+    instance: &mut AiInstance,
+    skirmish_ai_id: c_int,
+    topic: c_int,
+    data: *const c_void,
+) -> c_int {
+    // The reason goes out with the final release.
+    if topic == events::EVENT_RELEASE {
+        let release = unsafe { (data as *const events::SReleaseEvent).as_ref() };
+        instance.release_reason = Some(release.map_or(0, |r| r.reason));
+        return 0;
+    }
+
     // Handle EVENT_INIT specially — it also carries the callback pointer
     if topic == EVENT_INIT {
-        let init_data = unsafe { &*(data as *const events::SInitEvent) };
+        // Without the callback table nothing can be asked of the engine.
+        let Some(init_data) = (unsafe { (data as *const events::SInitEvent).as_ref() }).filter(|e| !e.callback.is_null())
+        else {
+            return -1;
+        };
         instance.callbacks =
             unsafe { EngineCallbacks::new(skirmish_ai_id, init_data.callback) };
 
         // Query map data and metal spots from GameRulesParams
         let map_width = instance.callbacks.map_width();
         let map_height = instance.callbacks.map_height();
-        instance.callbacks.log(&format!(
-            "[SAI Bridge] EVENT_INIT: map {}x{}", map_width, map_height
-        ));
+        log_info!(Some(&instance.callbacks), "EVENT_INIT: map {}x{}", map_width, map_height);
+        instance.teams = TeamRelations::read(&instance.callbacks);
+        log_info!(Some(&instance.callbacks), "EVENT_INIT: {} teams", instance.teams.teams());
 
         let mex_count = instance.callbacks.game_rules_param_float("mex_count", -1.0);
-        instance.callbacks.log(&format!(
-            "[SAI Bridge] mex_count from GameRulesParams = {}", mex_count
-        ));
+        log_debug!(Some(&instance.callbacks), "mex_count from GameRulesParams = {}", mex_count);
 
         let raw_spots = instance.callbacks.get_metal_spots();
         let metal_spots = if raw_spots.is_empty() {
-            instance.callbacks.log("[SAI Bridge] No metal spots found");
+            log_info!(Some(&instance.callbacks), "No metal spots found");
             None
         } else {
-            instance.callbacks.log(&format!(
-                "[SAI Bridge] Found {} metal spots from GameRulesParams",
+            log_info!(
+                Some(&instance.callbacks),
+                "Found {} metal spots from GameRulesParams",
                 raw_spots.len()
-            ));
+            );
             Some(
                 raw_spots
                     .into_iter()
@@ -187,60 +416,1247 @@ pub unsafe extern "C" fn handleEvent(
             )
         };
 
+        let event = GameEvent::Init {
+            frame: 0,
+            saved_game: init_data.saved_game,
+            protocol_version: Some(sai_protocol::PROTOCOL_VERSION),
+            metal_spots,
+            map_width: Some(map_width),
+            map_height: Some(map_height),
+            start_pos: instance.callbacks.map_get_start_pos(),
+            teams: instance.teams.slots(),
+            build: Some(build_info(&instance.callbacks)),
+        };
+        // Units that exist before we connected (commander, facplop)
+        // produce no events of their own.
+        let roster = events::build_roster(&instance.callbacks);
+        if let GameEvent::Roster { units, .. } = &roster {
+            log_info!(Some(&instance.callbacks), "Roster: {} units", units.len());
+        }
+        instance.opening = vec![event, roster];
+        instance.paused = instance.callbacks.is_paused();
+        instance.speed = instance.callbacks.get_speed_factor();
         if let Some(ref mut ipc) = instance.ipc {
-            let event = GameEvent::Init {
-                frame: 0,
-                saved_game: init_data.saved_game,
-                metal_spots,
-                map_width: Some(map_width),
-                map_height: Some(map_height),
-            };
-            let _ = ipc.send_event(&event);
+            for event in &instance.opening {
+                let _ = ipc.send_event(event);
+            }
         }
         return 0;
     }
 
-    // For UPDATE events, throttle and poll for incoming commands
+    // Poll for commands from GameManager every frame. While a turn is
+    // pending no UPDATE arrives, so any event (normally the widget
+    // heartbeat) polls instead.
+    if topic == EVENT_UPDATE || instance.awaiting_turn {
+        poll_game_manager(instance);
+    }
+
+    // Updates stop while paused, but the widget heartbeat keeps coming.
+    if (topic == EVENT_UPDATE || topic == events::EVENT_LUA_MESSAGE) && !instance.benchmark {
+        watch_game_speed(instance);
+    }
+
+    // For UPDATE events, throttle
+    let mut next_update = None;
     if topic == EVENT_UPDATE {
         instance.frame_counter += 1;
-
-        // Poll for commands from GameManager every frame
-        if let Some(ref mut ipc) = instance.ipc {
-            let cmds = ipc.poll_commands();
-            for cmd in &cmds {
-                instance.callbacks.log(&format!("[SAI Bridge] Dispatching: {:?}", cmd));
-                if let Err(e) = commands::dispatch(&instance.callbacks, cmd) {
-                    instance
-                        .callbacks
-                        .log(&format!("[SAI Bridge] Command error: {}", e));
-                    let error_event = GameEvent::CommandError {
-                        error: e,
-                        command: format!("{:?}", cmd),
-                    };
-                    let _ = ipc.send_event(&error_event);
-                }
-            }
+        if instance.ipc.is_none() && instance.frame_counter.is_multiple_of(RECONNECT_INTERVAL) {
+            reconnect(instance);
         }
 
         // Only send update events at throttled rate
-        if instance.frame_counter % UPDATE_INTERVAL != 0 {
+        let frames = instance.frame_counter - instance.last_update;
+        if frames < instance.cadence.interval() {
             return 0;
         }
+        instance.last_update = instance.frame_counter;
+        next_update = Some(instance.cadence.updated(frames));
     }
 
     // Parse, enrich with unit names, and forward the event
     if let Some(mut event) = unsafe { parse_event(topic, data) } {
-        enrich_event(&mut event, &instance.callbacks);
-        if let Some(ref mut ipc) = instance.ipc {
-            if let Err(e) = ipc.send_event(&event) {
-                instance
-                    .callbacks
-                    .log(&format!("[SAI Bridge] IPC send error: {}", e));
-                // Connection lost — clear it
-                instance.ipc = None;
+        // Quarantined: nothing in it is worth enriching or counting.
+        if let GameEvent::MalformedEvent { reason, .. } = &event {
+            log_warn!(Some(&instance.callbacks), "Event {} is malformed ({}); sent as malformed_event", topic, reason);
+            forward(instance, event);
+            return 0;
+        }
+        instance.cadence.observe(&event);
+        // Before anything drops or counts it: the unit's order is done
+        // either way.
+        instance.orders.observe(&mut event);
+        if instance.benchmark && is_unit_event(&event) {
+            return 0;
+        }
+        if let Some(unit) = aggregated_unit(&event) {
+            let name = event.type_name();
+            if instance.aggregate.iter().any(|t| t == name) {
+                let counter = match event {
+                    GameEvent::UnitDamaged { paralyzer: true, .. } => sai_protocol::PARALYZED_COUNTER,
+                    _ => name,
+                };
+                *instance.counters.entry(unit).or_default().entry(counter.to_string()).or_default() += 1;
+                return 0;
             }
         }
+        let mut stalled = Vec::new();
+        if let GameEvent::Update { allies, counters, command_backlog, under_construction, update_interval, .. } = &mut event {
+            *update_interval = next_update;
+            *allies = instance.teams.ally_economies(&instance.callbacks);
+            *counters = busiest_units(std::mem::take(&mut instance.counters));
+            *command_backlog = instance.pending_commands.len();
+            (*under_construction, stalled) = instance.construction.sample(&instance.callbacks);
+        }
+        match &mut event {
+            GameEvent::LuaMessage { data, .. } if data == TURN_HEARTBEAT => return 0,
+            GameEvent::Update { awaiting_commands, .. }
+                if instance.turn_mode && instance.ipc.is_some() =>
+            {
+                match commands::set_paused(&instance.callbacks, true) {
+                    Ok(()) => {
+                        instance.awaiting_turn = true;
+                        *awaiting_commands = true;
+                    }
+                    Err(e) => log_warn!(Some(&instance.callbacks), "Turn pause failed: {}", e),
+                }
+            }
+            _ => {}
+        }
+        enrich_event(&mut event, &instance.callbacks);
+        instance.teams.annotate(&mut event, &instance.callbacks);
+        if let GameEvent::Message { player, player_name, .. } = &mut event {
+            *player_name = instance.player_names.get(&instance.callbacks, *player);
+        }
+        instance.construction.observe(&event);
+        forward(instance, event);
+        for event in stalled {
+            forward(instance, event);
+        }
     }
 
     0
 }
+
+/// Send an event to the GameManager, or hold it for the next one.
+fn forward(instance: &mut AiInstance, event: GameEvent) {
+    if let Some(ref mut ipc) = instance.ipc {
+        if let Err(e) = ipc.send_event(&event) {
+            log_warn!(Some(&instance.callbacks), "IPC send error: {}", e);
+            disconnected(instance);
+        }
+    } else if !instance.opening.is_empty() {
+        if instance.backlog.len() >= BACKLOG_CAP {
+            instance.backlog.pop_front();
+            instance.backlog_dropped += 1;
+            instance.stats.events_dropped += 1;
+        }
+        instance.backlog.push_back(event);
+    } else {
+        instance.stats.events_dropped += 1;
+    }
+}
+
+/// Report pauses and speed changes, ours or a player's, so a frame counter
+/// that stopped isn't mistaken for a stalled game.
+fn watch_game_speed(instance: &mut AiInstance) {
+    let paused = instance.callbacks.is_paused();
+    if paused != instance.paused {
+        instance.paused = paused;
+        let event = if paused {
+            GameEvent::GamePaused { by_us: instance.awaiting_turn }
+        } else {
+            GameEvent::GameResumed
+        };
+        forward(instance, event);
+    }
+    let speed = instance.callbacks.get_speed_factor();
+    if (speed - instance.speed).abs() >= SPEED_TOLERANCE {
+        instance.speed = speed;
+        forward(instance, GameEvent::SpeedChanged { speed });
+    }
+}
+
+/// Try to reach the GameManager again. On success it gets the opening
+/// events, then the backlog in order.
+fn reconnect(instance: &mut AiInstance) {
+    let Ok(mut ipc) = IpcClient::connect(&instance.socket_path) else {
+        return;
+    };
+    log_info!(
+        Some(&instance.callbacks),
+        "Connected to GameManager at {}; sending {} held events",
+        instance.socket_path,
+        instance.backlog.len()
+    );
+    if instance.backlog_dropped > 0 {
+        log_warn!(
+            Some(&instance.callbacks),
+            "Backlog full: dropped the {} oldest events",
+            instance.backlog_dropped
+        );
+    }
+    for event in &instance.opening {
+        if let Err(e) = ipc.send_event(event) {
+            log_warn!(Some(&instance.callbacks), "IPC send error: {}", e);
+            return;
+        }
+    }
+    // Each held event leaves the backlog once sent, so a send failing
+    // partway leaves only the unsent ones for the next attempt.
+    while let Some(event) = instance.backlog.front() {
+        if let Err(e) = ipc.send_event(event) {
+            log_warn!(Some(&instance.callbacks), "IPC send error: {}", e);
+            return;
+        }
+        instance.backlog.pop_front();
+    }
+    instance.backlog_dropped = 0;
+    instance.ipc = Some(ipc);
+}
+
+/// Events about individual units, dropped in benchmark mode.
+fn is_unit_event(event: &GameEvent) -> bool {
+    let name = event.type_name();
+    name.starts_with("unit_")
+        || name.starts_with("enemy_")
+        || matches!(event, GameEvent::WeaponFired { .. } | GameEvent::CommandFinished { .. })
+}
+
+/// The unit an event of an aggregatable type is counted against.
+fn aggregated_unit(event: &GameEvent) -> Option<UnitId> {
+    match event {
+        GameEvent::WeaponFired { unit, .. }
+        | GameEvent::CommandFinished { unit, .. }
+        | GameEvent::UnitDamaged { unit, .. } => Some(*unit),
+        _ => None,
+    }
+}
+
+/// connection.json `aggregate_events`, less the types that can't be
+/// aggregated.
+fn aggregate_events(cb: &EngineCallbacks, connection: Option<&(serde_json::Value, String)>) -> Vec<String> {
+    let Some(names) = connection.and_then(|(config, _)| config.get("aggregate_events")?.as_array()) else {
+        return Vec::new();
+    };
+    let mut aggregate = Vec::new();
+    for name in names.iter().filter_map(|n| n.as_str()) {
+        if sai_protocol::AGGREGATABLE_EVENTS.contains(&name) {
+            aggregate.push(name.to_string());
+        } else {
+            log_warn!(Some(cb), "Can't aggregate '{}' events; forwarding them", name);
+        }
+    }
+    if !aggregate.is_empty() {
+        log_info!(Some(cb), "Counting per unit instead of forwarding: {}", aggregate.join(", "));
+    }
+    aggregate
+}
+
+/// connection.json `update_cadence`; the default (fixed updates) if it is
+/// missing or doesn't parse.
+fn update_cadence(cb: &EngineCallbacks, connection: Option<&(serde_json::Value, String)>) -> UpdateCadence {
+    let Some(value) = connection.and_then(|(config, _)| config.get("update_cadence")) else {
+        return UpdateCadence::default();
+    };
+    match serde_json::from_value::<UpdateCadence>(value.clone()).map_err(|e| e.to_string()).and_then(|c| c.validate().map(|()| c)) {
+        Ok(cadence) => {
+            log_info!(Some(cb), "Update cadence: {:?}", cadence);
+            cadence
+        }
+        Err(e) => {
+            log_warn!(Some(cb), "update_cadence ignored: {}", e);
+            UpdateCadence::default()
+        }
+    }
+}
+
+/// The update's `counters`: the units with the most counted events.
+fn busiest_units(counters: HashMap<UnitId, BTreeMap<String, u32>>) -> sai_protocol::UnitCounters {
+    let mut units: Vec<_> = counters.into_iter().collect();
+    units.sort_by_key(|(unit, counts)| (std::cmp::Reverse(counts.values().sum::<u32>()), *unit));
+    units.truncate(sai_protocol::MAX_COUNTED_UNITS);
+    units.into_iter().map(|(unit, counts)| (unit.to_string(), counts)).collect()
+}
+
+/// Poll the GameManager's commands and dispatch them, at most
+/// MAX_COMMANDS_PER_FRAME a call; the rest wait for the next. Turn-mode
+/// commands change bridge state; the rest go to the engine. Dry runs are
+/// only validated, and answered with their verdict.
+fn poll_game_manager(instance: &mut AiInstance) {
+    let Some(ipc) = instance.ipc.as_mut() else {
+        return;
+    };
+    instance.pending_commands.extend(ipc.poll_tracked_commands());
+    for e in ipc.take_errors() {
+        log_warn!(Some(&instance.callbacks), "{}", e);
+    }
+    let count = instance.pending_commands.len().min(sai_protocol::MAX_COMMANDS_PER_FRAME);
+    let cmds: Vec<(GameCommand, Option<u64>)> = instance.pending_commands.drain(..count).collect();
+    if !instance.pending_commands.is_empty() {
+        log_debug!(Some(&instance.callbacks), "{} commands wait for the next frame", instance.pending_commands.len());
+    }
+    for (cmd, command_ref) in &cmds {
+        log_debug!(Some(&instance.callbacks), "Dispatching: {:?}", cmd);
+        let result = match cmd {
+            GameCommand::SetTurnMode { enabled } => {
+                instance.turn_mode = *enabled;
+                if *enabled || !instance.awaiting_turn {
+                    Ok(())
+                } else {
+                    end_turn(&instance.callbacks, &mut instance.awaiting_turn)
+                }
+            }
+            GameCommand::SetUpdateInterval { frames, mode } => instance.cadence.set(*frames, *mode),
+            GameCommand::EndTurn if !instance.awaiting_turn => {
+                Err("end_turn: no turn is pending".to_string())
+            }
+            GameCommand::EndTurn => end_turn(&instance.callbacks, &mut instance.awaiting_turn),
+            GameCommand::QueryUnitDefs { request_id } => send_unit_defs(&instance.callbacks, ipc, *request_id),
+            GameCommand::QueryMapGrid { request_id, cell_size, build_def } => {
+                send_map_grid(&instance.callbacks, ipc, *request_id, *cell_size, build_def.as_deref())
+            }
+            GameCommand::QueryUnitQueues { request_id, units } => {
+                let queues = events::read_unit_queues(&instance.callbacks, units);
+                ipc.send_event(&GameEvent::UnitQueues { request_id: *request_id, queues })
+                    .map_err(|e| format!("query_unit_queues: {}", e))
+            }
+            _ => commands::dispatch(&instance.callbacks, cmd),
+        };
+        if result.is_ok() {
+            instance.construction.observe_command(cmd);
+            instance.orders.observe_command(cmd, *command_ref);
+        } else if let GameCommand::LoadUnits { unit_id, .. } = cmd {
+            // Some of the load's orders may have gone out before the
+            // engine refused one, and their slots were never kept.
+            instance.orders.forget(*unit_id);
+        }
+        if let Err(e) = result {
+            log_warn!(Some(&instance.callbacks), "Command error: {}", e);
+            let error_event = GameEvent::CommandError {
+                error: e,
+                command: format!("{:?}", cmd),
+                command_ref: *command_ref,
+            };
+            let _ = ipc.send_event(&error_event);
+        }
+    }
+    for dry_run in ipc.take_dry_runs() {
+        let result = match &dry_run.command {
+            GameCommand::EndTurn if !instance.awaiting_turn => Err("end_turn: no turn is pending".to_string()),
+            cmd => commands::validate(&instance.callbacks, cmd),
+        };
+        log_debug!(Some(&instance.callbacks), "Dry run {:?}: {:?}", dry_run.command, result);
+        let _ = ipc.send_event(&GameEvent::DryRunResult { request_id: dry_run.request_id, error: result.err() });
+    }
+    if !ipc.is_connected() {
+        disconnected(instance);
+    }
+}
+
+/// Answer a `query_unit_defs` command with every unit def, in chunks.
+fn send_unit_defs(cb: &EngineCallbacks, ipc: &mut IpcClient, request_id: u64) -> Result<(), String> {
+    let defs = events::read_unit_defs(cb);
+    let total = defs.len();
+    let mut offset = 0;
+    // An empty catalog still gets one (empty) chunk, so the query is answered.
+    loop {
+        let chunk: Vec<_> = defs[offset..].iter().take(UNIT_DEFS_PER_EVENT).cloned().collect();
+        let len = chunk.len();
+        ipc.send_event(&GameEvent::UnitDefs { request_id, offset, total, defs: chunk })
+            .map_err(|e| format!("query_unit_defs: {}", e))?;
+        offset += len;
+        if offset >= total {
+            return Ok(());
+        }
+    }
+}
+
+/// Answer a `query_map_grid` command with the sampled grid, a band of rows
+/// per event. A query that can't be sampled is answered with one event
+/// carrying the error, so the GameManager isn't left waiting.
+fn send_map_grid(
+    cb: &EngineCallbacks,
+    ipc: &mut IpcClient,
+    request_id: u64,
+    cell_size: f32,
+    build_def: Option<&str>,
+) -> Result<(), String> {
+    let (cell, width, height) = events::map_grid_size(cb, cell_size);
+    let mut send = |row_offset, elevation, buildable, error| {
+        ipc.send_event(&GameEvent::MapGrid {
+            request_id,
+            cell_size: cell,
+            width,
+            height,
+            row_offset,
+            elevation,
+            buildable,
+            error,
+        })
+        .map_err(|e| format!("query_map_grid: {}", e))
+    };
+    if cell_size.is_nan() || cell_size <= 0.0 {
+        return send(0, Vec::new(), Vec::new(), Some(format!("cell_size must be positive, got {}", cell_size)));
+    }
+    let build_def = match build_def.map(|name| cb.get_unit_def_by_name(name).ok_or(name)).transpose() {
+        Ok(def) => def,
+        Err(name) => return send(0, Vec::new(), Vec::new(), Some(format!("unknown unit def '{}'", name))),
+    };
+    let rows_per_event = (MAP_GRID_CELLS_PER_EVENT / width).max(1);
+    for row_offset in (0..height).step_by(rows_per_event) {
+        let (elevation, buildable): (Vec<_>, Vec<_>) = (row_offset..height.min(row_offset + rows_per_event))
+            .map(|row| events::read_map_grid_row(cb, cell, width, row, build_def))
+            .unzip();
+        send(row_offset, elevation, buildable.into_iter().flatten().collect(), None)?;
+    }
+    Ok(())
+}
+
+/// Resume the engine after a turn-mode pause.
+fn end_turn(cb: &EngineCallbacks, awaiting_turn: &mut bool) -> Result<(), String> {
+    commands::set_paused(cb, false)?;
+    *awaiting_turn = false;
+    Ok(())
+}
+
+/// The GameManager went away. Events are held for the next one from here
+/// on, and it gets the units we have now as its roster.
+fn disconnected(instance: &mut AiInstance) {
+    log_warn!(Some(&instance.callbacks), "GameManager disconnected; holding events until it's back");
+    if let Some(ipc) = instance.ipc.take() {
+        let (sent, dropped) = ipc.event_counts();
+        instance.stats.events_sent += sent;
+        instance.stats.events_dropped += dropped;
+    }
+    // Orders from the lost connection would run against a GameManager that
+    // no longer knows it sent them.
+    if !instance.pending_commands.is_empty() {
+        log_warn!(Some(&instance.callbacks), "Dropping {} unrun commands", instance.pending_commands.len());
+        instance.pending_commands.clear();
+    }
+    if !instance.opening.is_empty() {
+        instance.opening.truncate(1);
+        instance.opening.push(events::build_roster(&instance.callbacks));
+    }
+    resume_orphaned_turn(instance);
+}
+
+/// The GameManager went away: nobody will ever send end_turn, so don't
+/// leave the game frozen. Turn mode stays off until a GameManager asks again.
+fn resume_orphaned_turn(instance: &mut AiInstance) {
+    instance.turn_mode = false;
+    if instance.awaiting_turn {
+        log_warn!(Some(&instance.callbacks), "GameManager disconnected during a turn; resuming");
+        if let Err(e) = end_turn(&instance.callbacks, &mut instance.awaiting_turn) {
+            log_warn!(Some(&instance.callbacks), "{}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mock_engine::MockEngine;
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::time::Duration;
+
+    /// A GameManager stand-in: listens where connection.json points the bridge.
+    struct FakeGm {
+        dir: std::path::PathBuf,
+        listener: UnixListener,
+    }
+
+    impl FakeGm {
+        fn new(engine: &MockEngine) -> Self {
+            Self::with_config(engine, serde_json::json!({}))
+        }
+
+        /// Like `new`, with extra connection.json keys.
+        fn with_config(engine: &MockEngine, mut config: serde_json::Value) -> Self {
+            let dir = std::env::temp_dir()
+                .join(format!("sai-bridge-test-{}-{}", std::process::id(), engine.ai_id));
+            std::fs::create_dir_all(&dir).unwrap();
+            let socket = dir.join("gm.sock");
+            let _ = std::fs::remove_file(&socket);
+            let listener = UnixListener::bind(&socket).unwrap();
+            config["socket_path"] = serde_json::json!(socket);
+            std::fs::write(dir.join("connection.json"), config.to_string()).unwrap();
+            engine.with_game(|g| g.set_info("dataDir", dir.to_str().unwrap()));
+            Self { dir, listener }
+        }
+
+        fn accept(&self) -> (BufReader<UnixStream>, UnixStream) {
+            let (stream, _) = self.listener.accept().unwrap();
+            stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            (BufReader::new(stream.try_clone().unwrap()), stream)
+        }
+    }
+
+    impl Drop for FakeGm {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    fn next_event(reader: &mut BufReader<UnixStream>) -> serde_json::Value {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        serde_json::from_str(&line).unwrap()
+    }
+
+    unsafe fn send<T>(engine: &MockEngine, topic: c_int, data: &T) -> c_int {
+        handleEvent(engine.ai_id, topic, data as *const T as *const c_void)
+    }
+
+    #[test]
+    fn test_session_end_to_end() {
+        let engine = MockEngine::new();
+        engine.with_game(|g| {
+            g.add_unit(10, "cloakcon", [100.0, 5.0, 200.0], 0);
+            g.add_unit(900, "shieldraid", [400.0, 5.0, 400.0], 1);
+            g.rules_params.insert("mex_count".into(), 1.0);
+            g.rules_params.insert("mex_x1".into(), 1000.0);
+            g.rules_params.insert("mex_z1".into(), 1500.0);
+            g.rules_params.insert("mex_metal1".into(), 2.0);
+            g.start_pos = [100.0, 5.0, 200.0];
+        });
+        let gm = FakeGm::new(&engine);
+
+        unsafe {
+            assert_eq!(init(engine.ai_id, engine.table()), 0);
+            let (mut reader, mut writer) = gm.accept();
+            assert!(engine.logs().iter().any(|l| l.contains("Socket path from")));
+
+            let init_event = events::SInitEvent {
+                skirmish_ai_id: engine.ai_id,
+                callback: engine.table(),
+                saved_game: false,
+            };
+            assert_eq!(send(&engine, EVENT_INIT, &init_event), 0);
+            let ev = next_event(&mut reader);
+            assert_eq!(ev["type"], "init");
+            assert_eq!(ev["protocol_version"], sai_protocol::PROTOCOL_VERSION);
+            assert_eq!(ev["build"]["bridge_version"], env!("CARGO_PKG_VERSION"));
+            assert_eq!(ev["build"]["engine_version"], "105.1.1-2511-g747f18b");
+            assert!(BUILD_MARKER.starts_with("sai-bridge-build:") && BUILD_MARKER.ends_with('\0'));
+            assert_eq!(ev["map_width"], 512);
+            assert_eq!(ev["metal_spots"][0]["x"], 1000.0);
+            assert_eq!(ev["start_pos"], serde_json::json!([100.0, 5.0, 200.0]));
+            assert_eq!(
+                ev["teams"],
+                serde_json::json!([
+                    {"team": 0, "ally_team": 0, "relation": "mine"},
+                    {"team": 1, "ally_team": 1, "relation": "enemy"}
+                ])
+            );
+            let roster = next_event(&mut reader);
+            assert_eq!(
+                roster,
+                serde_json::json!({"type": "roster", "frame": 0, "units": [
+                    {"unit": 10, "unit_name": "cloakcon", "pos": [100.0, 5.0, 200.0]}
+                ]})
+            );
+
+            // Commands are polled on every update frame
+            writer.write_all(b"{\"type\":\"stop\",\"unit_id\":10}\n").unwrap();
+            writer.write_all(b"{\"type\":\"warp\",\"unit_id\":10}\n").unwrap();
+            send(&engine, EVENT_UPDATE, &events::SUpdateEvent { frame: 1 });
+            let sent = engine.take_commands();
+            assert_eq!(sent.len(), 1);
+            assert_eq!(sent[0].topic, callbacks::COMMAND_UNIT_STOP);
+            let err = next_event(&mut reader);
+            assert_eq!(err["type"], "command_error");
+            assert_eq!(err["error"], "unrecognized command type 'warp'");
+
+            // Enriched events are forwarded as they happen
+            send(&engine, events::EVENT_UNIT_IDLE, &events::SUnitIdleEvent { unit: 10 });
+            let idle = next_event(&mut reader);
+            assert_eq!(idle, serde_json::json!({"type": "unit_idle", "unit": 10, "unit_name": "cloakcon"}));
+
+            // Update events are throttled to every UPDATE_INTERVAL frames
+            for frame in 2..=UPDATE_INTERVAL as c_int {
+                send(&engine, EVENT_UPDATE, &events::SUpdateEvent { frame });
+            }
+            let update = next_event(&mut reader);
+            assert_eq!(update["type"], "update");
+            assert_eq!(update["frame"], UPDATE_INTERVAL);
+            assert_eq!(update["economy"]["metal"]["income"], 0.0);
+            // No allies in a 1v1, so no allies part.
+            assert!(update.get("allies").is_none());
+
+            // The reason waits for the final release.
+            send(&engine, events::EVENT_RELEASE, &events::SReleaseEvent { reason: 1 });
+            assert_eq!(release(engine.ai_id), 0);
+            let last = next_event(&mut reader);
+            assert_eq!((last["type"].as_str(), last["reason"].as_i64()), (Some("release"), Some(1)));
+            assert_eq!(last["stats"]["frames"], UPDATE_INTERVAL);
+            assert_eq!(send(&engine, EVENT_UPDATE, &events::SUpdateEvent { frame: 99 }), -1);
+        }
+    }
+
+    #[test]
+    fn test_init_without_game_manager() {
+        let engine = MockEngine::new();
+        engine.with_game(|g| g.set_option("socket_path", "/nonexistent/sai-bridge-test.sock"));
+        unsafe {
+            // The AI still loads; events are simply not forwarded
+            assert_eq!(init(engine.ai_id, engine.table()), 0);
+            assert!(engine
+                .logs()
+                .iter()
+                .any(|l| l.contains("Failed to connect to GameManager at /nonexistent/sai-bridge-test.sock")));
+            assert_eq!(send(&engine, events::EVENT_UNIT_IDLE, &events::SUnitIdleEvent { unit: 1 }), 0);
+            release(engine.ai_id);
+        }
+    }
+
+    #[test]
+    fn test_socket_path_of_own_team() {
+        let engine = MockEngine::new();
+        engine.with_game(|g| g.my_team = 2);
+        let gm = FakeGm::new(&engine);
+        // The shared socket_path belongs to another AI; ours is listed.
+        let socket = gm.dir.join("gm.sock");
+        let config = serde_json::json!({
+            "socket_path": "/nonexistent/sai-bridge-test.sock",
+            "socket_paths": {"team:0": "/nonexistent/sai-bridge-test.sock", "team:2": socket},
+        });
+        std::fs::write(gm.dir.join("connection.json"), config.to_string()).unwrap();
+
+        unsafe {
+            assert_eq!(init(engine.ai_id, engine.table()), 0);
+            let _connected = gm.accept();
+            assert!(engine.logs().iter().any(|l| l.contains("socket_paths")));
+            release(engine.ai_id);
+        }
+    }
+
+    #[test]
+    fn test_events_held_until_game_manager_listens() {
+        let engine = MockEngine::new();
+        engine.with_game(|g| g.add_unit(10, "cloakcon", [100.0, 5.0, 200.0], 0));
+        let mut gm = FakeGm::new(&engine);
+        let socket = gm.dir.join("gm.sock");
+        std::fs::remove_file(&socket).unwrap();
+
+        unsafe {
+            assert_eq!(init(engine.ai_id, engine.table()), 0);
+            let init_event = events::SInitEvent {
+                skirmish_ai_id: engine.ai_id,
+                callback: engine.table(),
+                saved_game: false,
+            };
+            send(&engine, EVENT_INIT, &init_event);
+            send(&engine, events::EVENT_UNIT_IDLE, &events::SUnitIdleEvent { unit: 10 });
+            send(&engine, events::EVENT_UNIT_FINISHED, &events::SUnitFinishedEvent { unit: 10 });
+
+            // The GameManager shows up; the next reconnect attempt finds it.
+            gm.listener = UnixListener::bind(&socket).unwrap();
+            for frame in 1..=RECONNECT_INTERVAL as c_int {
+                send(&engine, EVENT_UPDATE, &events::SUpdateEvent { frame });
+            }
+            let (mut reader, _writer) = gm.accept();
+            assert_eq!(next_event(&mut reader)["type"], "init");
+            assert_eq!(next_event(&mut reader)["units"][0]["unit"], 10);
+            assert_eq!(next_event(&mut reader)["type"], "unit_idle");
+            assert_eq!(next_event(&mut reader)["type"], "unit_finished");
+            // Then it's live.
+            assert_eq!(next_event(&mut reader)["type"], "update");
+            release(engine.ai_id);
+        }
+    }
+
+    /// Run init and EVENT_INIT, consuming the init and roster events.
+    unsafe fn start_session(engine: &MockEngine, gm: &FakeGm) -> (BufReader<UnixStream>, UnixStream) {
+        assert_eq!(init(engine.ai_id, engine.table()), 0);
+        let (mut reader, writer) = gm.accept();
+        let init_event = events::SInitEvent {
+            skirmish_ai_id: engine.ai_id,
+            callback: engine.table(),
+            saved_game: false,
+        };
+        send(engine, EVENT_INIT, &init_event);
+        assert_eq!(next_event(&mut reader)["type"], "init");
+        assert_eq!(next_event(&mut reader)["type"], "roster");
+        (reader, writer)
+    }
+
+    unsafe fn heartbeat(engine: &MockEngine) -> c_int {
+        let msg = std::ffi::CString::new(TURN_HEARTBEAT).unwrap();
+        send(engine, events::EVENT_LUA_MESSAGE, &events::SLuaMessageEvent { in_data: msg.as_ptr() })
+    }
+
+    fn pause_commands(engine: &MockEngine) -> Vec<serde_json::Value> {
+        engine
+            .take_commands()
+            .into_iter()
+            .filter(|c| c.topic == callbacks::COMMAND_PAUSE)
+            .map(|c| c.fields["enable"].clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_turn_mode_cycle() {
+        let engine = MockEngine::new();
+        engine.with_game(|g| g.add_unit(10, "cloakcon", [100.0, 5.0, 200.0], 0));
+        let gm = FakeGm::new(&engine);
+
+        unsafe {
+            let (mut reader, mut writer) = start_session(&engine, &gm);
+            writer.write_all(b"{\"type\":\"set_turn_mode\",\"enabled\":true}\n").unwrap();
+
+            // The throttled update pauses the engine and says so.
+            for frame in 1..=UPDATE_INTERVAL as c_int {
+                send(&engine, EVENT_UPDATE, &events::SUpdateEvent { frame });
+            }
+            let update = next_event(&mut reader);
+            assert_eq!(update["frame"], UPDATE_INTERVAL);
+            assert_eq!(update["awaiting_commands"], true);
+            assert_eq!(pause_commands(&engine), vec![serde_json::json!(true)]);
+            assert!(engine.with_game(|g| g.paused));
+
+            // Paused: only the widget heartbeat calls in. Commands issued
+            // during the turn are dispatched, the heartbeat isn't forwarded.
+            writer.write_all(b"{\"type\":\"stop\",\"unit_id\":10}\n").unwrap();
+            assert_eq!(heartbeat(&engine), 0);
+            let sent = engine.take_commands();
+            assert_eq!(sent.len(), 1);
+            assert_eq!(sent[0].topic, callbacks::COMMAND_UNIT_STOP);
+            assert!(engine.with_game(|g| g.paused));
+            assert_eq!(next_event(&mut reader), serde_json::json!({"type": "game_paused", "by_us": true}));
+
+            writer.write_all(b"{\"type\":\"end_turn\"}\n").unwrap();
+            heartbeat(&engine);
+            assert_eq!(pause_commands(&engine), vec![serde_json::json!(false)]);
+            assert!(!engine.with_game(|g| g.paused));
+            assert_eq!(next_event(&mut reader)["type"], "game_resumed");
+
+            // A second end_turn has nothing to resume.
+            writer.write_all(b"{\"type\":\"end_turn\"}\n").unwrap();
+            send(&engine, EVENT_UPDATE, &events::SUpdateEvent { frame: 31 });
+            let err = next_event(&mut reader);
+            assert_eq!(err["type"], "command_error");
+            assert_eq!(err["error"], "end_turn: no turn is pending");
+
+            // The next interval pauses again; turning turn mode off resumes.
+            for frame in 32..=2 * UPDATE_INTERVAL as c_int {
+                send(&engine, EVENT_UPDATE, &events::SUpdateEvent { frame });
+            }
+            assert_eq!(next_event(&mut reader)["awaiting_commands"], true);
+            writer.write_all(b"{\"type\":\"set_turn_mode\",\"enabled\":false}\n").unwrap();
+            heartbeat(&engine);
+            assert_eq!(pause_commands(&engine), vec![serde_json::json!(true), serde_json::json!(false)]);
+            for frame in 61..=3 * UPDATE_INTERVAL as c_int {
+                send(&engine, EVENT_UPDATE, &events::SUpdateEvent { frame });
+            }
+            let update = next_event(&mut reader);
+            assert_eq!(update["frame"], 3 * UPDATE_INTERVAL);
+            assert_eq!(update.get("awaiting_commands"), None);
+            assert!(pause_commands(&engine).is_empty());
+            release(engine.ai_id);
+        }
+    }
+
+    #[test]
+    fn test_pause_and_speed_changes_by_players() {
+        let engine = MockEngine::new();
+        let gm = FakeGm::new(&engine);
+
+        unsafe {
+            let (mut reader, _writer) = start_session(&engine, &gm);
+            engine.with_game(|g| g.paused = true);
+            heartbeat(&engine);
+            assert_eq!(next_event(&mut reader), serde_json::json!({"type": "game_paused", "by_us": false}));
+
+            engine.with_game(|g| (g.paused, g.speed) = (false, 2.0));
+            send(&engine, EVENT_UPDATE, &events::SUpdateEvent { frame: 1 });
+            assert_eq!(next_event(&mut reader)["type"], "game_resumed");
+            assert_eq!(next_event(&mut reader), serde_json::json!({"type": "speed_changed", "speed": 2.0}));
+
+            // The engine's own small corrections aren't news.
+            engine.with_game(|g| g.speed = 1.98);
+            for frame in 2..=UPDATE_INTERVAL as c_int {
+                send(&engine, EVENT_UPDATE, &events::SUpdateEvent { frame });
+            }
+            assert_eq!(next_event(&mut reader)["type"], "update");
+            release(engine.ai_id);
+        }
+    }
+
+    #[test]
+    fn test_dry_run_validates_without_dispatch() {
+        let engine = MockEngine::new();
+        engine.with_game(|g| g.add_unit(10, "cloakcon", [100.0, 5.0, 200.0], 0));
+        let gm = FakeGm::new(&engine);
+
+        unsafe {
+            let (mut reader, mut writer) = start_session(&engine, &gm);
+            writer
+                .write_all(b"{\"type\":\"stop\",\"unit_id\":10,\"dry_run\":true,\"request_id\":1}\n")
+                .unwrap();
+            writer
+                .write_all(b"{\"type\":\"move\",\"unit_id\":11,\"x\":1,\"z\":1,\"dry_run\":true,\"request_id\":2}\n")
+                .unwrap();
+            writer.write_all(b"{\"type\":\"end_turn\",\"dry_run\":true,\"request_id\":3}\n").unwrap();
+            send(&engine, EVENT_UPDATE, &events::SUpdateEvent { frame: 1 });
+
+            assert!(engine.take_commands().is_empty(), "dry runs never reach the engine");
+            assert_eq!(next_event(&mut reader), serde_json::json!({"type": "dry_run_result", "request_id": 1}));
+            let missing = next_event(&mut reader);
+            assert_eq!(missing["request_id"], 2);
+            assert!(missing["error"].as_str().unwrap().contains("unit 11 does not exist"), "{}", missing);
+            assert_eq!(next_event(&mut reader)["error"], "end_turn: no turn is pending");
+            release(engine.ai_id);
+        }
+    }
+
+    #[test]
+    fn test_command_refs_echoed() {
+        let engine = MockEngine::new();
+        engine.with_game(|g| g.add_unit(10, "cloakcon", [100.0, 5.0, 200.0], 0));
+        let gm = FakeGm::new(&engine);
+
+        unsafe {
+            let (mut reader, mut writer) = start_session(&engine, &gm);
+            writer
+                .write_all(b"{\"type\":\"move\",\"unit_id\":10,\"x\":1,\"z\":1,\"command_ref\":5}\n")
+                .unwrap();
+            writer.write_all(b"{\"type\":\"stop\",\"unit_id\":11,\"command_ref\":6}\n").unwrap();
+            send(&engine, EVENT_UPDATE, &events::SUpdateEvent { frame: 1 });
+            assert_eq!(engine.take_commands().len(), 1);
+            let error = next_event(&mut reader);
+            assert_eq!((error["type"].as_str(), error["command_ref"].as_u64()), (Some("command_error"), Some(6)));
+
+            // The engine's own id is no help; the unit's queue is.
+            let finished = events::SCommandFinishedEvent { unit_id: 10, command_id: -1, command_topic_id: 0 };
+            send(&engine, events::EVENT_COMMAND_FINISHED, &finished);
+            assert_eq!(next_event(&mut reader)["command_ref"], 5);
+            send(&engine, events::EVENT_COMMAND_FINISHED, &finished);
+            assert_eq!(next_event(&mut reader).get("command_ref"), None);
+            release(engine.ai_id);
+        }
+    }
+
+    #[test]
+    fn test_commands_capped_per_frame() {
+        let engine = MockEngine::new();
+        engine.with_game(|g| g.add_unit(10, "cloakcon", [100.0, 5.0, 200.0], 0));
+        let gm = FakeGm::new(&engine);
+
+        unsafe {
+            let (mut reader, mut writer) = start_session(&engine, &gm);
+            for frame in 1..UPDATE_INTERVAL as c_int {
+                send(&engine, EVENT_UPDATE, &events::SUpdateEvent { frame });
+            }
+            let flood = sai_protocol::MAX_COMMANDS_PER_FRAME * 5 / 2;
+            writer.write_all(&b"{\"type\":\"stop\",\"unit_id\":10}\n".repeat(flood)).unwrap();
+
+            // The update reports what's left after this frame's share.
+            send(&engine, EVENT_UPDATE, &events::SUpdateEvent { frame: UPDATE_INTERVAL as c_int });
+            assert_eq!(engine.take_commands().len(), sai_protocol::MAX_COMMANDS_PER_FRAME);
+            let update = next_event(&mut reader);
+            assert_eq!(update["command_backlog"], flood - sai_protocol::MAX_COMMANDS_PER_FRAME);
+
+            // The rest run on the next frames, in order.
+            send(&engine, EVENT_UPDATE, &events::SUpdateEvent { frame: UPDATE_INTERVAL as c_int + 1 });
+            assert_eq!(engine.take_commands().len(), sai_protocol::MAX_COMMANDS_PER_FRAME);
+            send(&engine, EVENT_UPDATE, &events::SUpdateEvent { frame: UPDATE_INTERVAL as c_int + 2 });
+            assert_eq!(engine.take_commands().len(), flood - 2 * sai_protocol::MAX_COMMANDS_PER_FRAME);
+            send(&engine, EVENT_UPDATE, &events::SUpdateEvent { frame: UPDATE_INTERVAL as c_int + 3 });
+            assert!(engine.take_commands().is_empty());
+            release(engine.ai_id);
+        }
+    }
+
+    #[test]
+    fn test_unit_defs_answered_in_chunks() {
+        let engine = MockEngine::new();
+        engine.with_game(|g| {
+            for i in 0..UNIT_DEFS_PER_EVENT + 2 {
+                g.add_def(&format!("def{}", i), &format!("Def {}", i));
+            }
+            let aa = g.add_def("cloakaa", "Gremlin");
+            let factory = g.add_def("factorycloak", "Cloakbot Factory");
+            let def = &mut g.defs[factory as usize];
+            def.tooltip = std::ffi::CString::new("Produces Cloaked Robots").unwrap();
+            def.cost = [400.0, 400.0];
+            def.health = 4000.0;
+            def.builder = true;
+            def.build_speed = 10.0;
+            def.build_options = vec![aa];
+            let mex = g.add_def("staticmex", "Metal Extractor");
+            g.defs[mex as usize].extracts = [0.5, 0.0];
+        });
+        let gm = FakeGm::new(&engine);
+
+        unsafe {
+            let (mut reader, mut writer) = start_session(&engine, &gm);
+            writer.write_all(b"{\"type\":\"query_unit_defs\",\"request_id\":9}\n").unwrap();
+            send(&engine, EVENT_UPDATE, &events::SUpdateEvent { frame: 1 });
+
+            assert!(engine.take_commands().is_empty(), "queries never reach the engine");
+            let first = next_event(&mut reader);
+            assert_eq!((first["type"].as_str(), first["request_id"].as_u64()), (Some("unit_defs"), Some(9)));
+            assert_eq!((first["offset"].as_u64(), first["total"].as_u64()), (Some(0), Some(UNIT_DEFS_PER_EVENT as u64 + 5)));
+            assert_eq!(first["defs"].as_array().unwrap().len(), UNIT_DEFS_PER_EVENT);
+            assert_eq!(first["defs"][0]["name"], "def0");
+            let last = next_event(&mut reader);
+            assert_eq!(last["offset"].as_u64(), Some(UNIT_DEFS_PER_EVENT as u64));
+            let defs = last["defs"].as_array().unwrap();
+            assert_eq!(defs.len(), 5);
+            let factory = &defs[3];
+            assert_eq!(factory["human_name"], "Cloakbot Factory");
+            assert_eq!(factory["description"], "Produces Cloaked Robots");
+            assert_eq!((factory["metal_cost"].as_f64(), factory["builder"].as_bool()), (Some(400.0), Some(true)));
+            assert_eq!(factory["build_options"], serde_json::json!([defs[2]["id"]]));
+            assert!(factory.get("extracts_metal").is_none());
+            assert_eq!(factory["build_speed"].as_f64(), Some(10.0));
+            assert_eq!(defs[4]["extracts_metal"].as_f64(), Some(0.5));
+            release(engine.ai_id);
+        }
+    }
+
+    #[test]
+    fn test_unit_queues_answered() {
+        let engine = MockEngine::new();
+        engine.with_game(|g| {
+            g.add_unit(40, "factorycloak", [100.0, 0.0, 100.0], 0);
+            g.add_unit(41, "factoryshield", [300.0, 0.0, 100.0], 1);
+            let raider = g.def_id("cloakraid").unwrap_or_else(|| g.add_def("cloakraid", "Glaive"));
+            g.queues.insert(40, vec![(-raider, Vec::new()), (-raider, Vec::new()), (10, vec![500.0, 0.0, 200.0])]);
+            g.queues.insert(41, vec![(-raider, Vec::new())]);
+        });
+        let gm = FakeGm::new(&engine);
+
+        unsafe {
+            let (mut reader, mut writer) = start_session(&engine, &gm);
+            // 41 is another team's, 42 died before the query arrived.
+            writer.write_all(b"{\"type\":\"query_unit_queues\",\"request_id\":7,\"units\":[40,41,42]}\n").unwrap();
+            send(&engine, EVENT_UPDATE, &events::SUpdateEvent { frame: 1 });
+
+            assert!(engine.take_commands().is_empty(), "queries never reach the engine");
+            let answer = next_event(&mut reader);
+            assert_eq!((answer["type"].as_str(), answer["request_id"].as_u64()), (Some("unit_queues"), Some(7)));
+            let raider = engine.with_game(|g| g.def_id("cloakraid").unwrap());
+            assert_eq!(
+                answer["queues"],
+                serde_json::json!([{"unit": 40, "commands": [
+                    {"id": -raider},
+                    {"id": -raider},
+                    {"id": 10, "params": [500.0, 0.0, 200.0]},
+                ]}])
+            );
+            release(engine.ai_id);
+        }
+    }
+
+    #[test]
+    fn test_malformed_event_quarantined() {
+        let engine = MockEngine::new();
+        engine.with_game(|g| g.add_unit(10, "cloakcon", [100.0, 5.0, 200.0], 0));
+        let gm = FakeGm::new(&engine);
+
+        unsafe {
+            let (mut reader, _writer) = start_session(&engine, &gm);
+            // The engine says a unit changed hands, but sends a damage struct.
+            let damaged = events::SUnitDamagedEvent {
+                unit: 10, attacker: 90, damage: 35.5, dir: std::ptr::null(), weapon_def_id: 3, paralyzer: false,
+            };
+            assert_eq!(send(&engine, events::EVENT_UNIT_GIVEN, &damaged), 0);
+            let malformed = next_event(&mut reader);
+            assert_eq!((malformed["type"].as_str(), malformed["topic"].as_i64()), (Some("malformed_event"), Some(11)));
+            assert!(engine.logs().iter().any(|l| l.contains("Event 11 is malformed")));
+            assert_eq!(handleEvent(engine.ai_id, events::EVENT_UNIT_IDLE, std::ptr::null()), 0);
+            assert_eq!(next_event(&mut reader)["reason"], "no event data");
+
+            // The stream goes on.
+            send(&engine, events::EVENT_UNIT_IDLE, &events::SUnitIdleEvent { unit: 10 });
+            assert_eq!(next_event(&mut reader), serde_json::json!({"type": "unit_idle", "unit": 10, "unit_name": "cloakcon"}));
+            release(engine.ai_id);
+        }
+    }
+
+    #[test]
+    fn test_map_grid_answered_in_bands() {
+        let engine = MockEngine::new();
+        engine.with_game(|g| {
+            // 2048x1024 elmos: 64x32 cells of 32 elmos, or 256x128 at the cap.
+            // Kept small so every answer fits the socket buffer unread.
+            g.map_width = 256;
+            g.map_height = 128;
+            g.elevation = |x, z| if z < 64.0 { x / 32.0 } else { 0.0 };
+            g.buildable = |x, _| x < 32.0;
+            g.add_def("staticmex", "Metal Extractor");
+        });
+        let gm = FakeGm::new(&engine);
+
+        unsafe {
+            let (mut reader, mut writer) = start_session(&engine, &gm);
+            writer.write_all(b"{\"type\":\"query_map_grid\",\"request_id\":3,\"cell_size\":32}\n").unwrap();
+            writer
+                .write_all(b"{\"type\":\"query_map_grid\",\"request_id\":4,\"cell_size\":1,\"build_def\":\"staticmex\"}\n")
+                .unwrap();
+            writer
+                .write_all(b"{\"type\":\"query_map_grid\",\"request_id\":5,\"cell_size\":32,\"build_def\":\"nope\"}\n")
+                .unwrap();
+            send(&engine, EVENT_UPDATE, &events::SUpdateEvent { frame: 1 });
+
+            assert!(engine.take_commands().is_empty(), "queries never reach the engine");
+            let first = next_event(&mut reader);
+            assert_eq!((first["type"].as_str(), first["request_id"].as_u64()), (Some("map_grid"), Some(3)));
+            assert_eq!((first["width"].as_u64(), first["height"].as_u64()), (Some(64), Some(32)));
+            let rows = first["elevation"].as_array().unwrap();
+            assert_eq!((rows.len(), rows[0].as_array().unwrap().len()), (32, 64));
+            // Cell (1, 0) is centred on (48, 16).
+            assert_eq!((rows[0][1].as_i64(), rows[2][1].as_i64()), (Some(2), Some(0)));
+            assert!(first.get("buildable").is_none());
+
+            // Too fine a grid is coarsened to the cap, and sent in bands.
+            let capped = next_event(&mut reader);
+            assert_eq!((capped["request_id"].as_u64(), capped["cell_size"].as_f64()), (Some(4), Some(8.0)));
+            assert_eq!(capped["width"].as_u64(), Some(sai_protocol::MAX_MAP_GRID_CELLS as u64));
+            assert_eq!(&capped["buildable"][0].as_str().unwrap()[..6], "111100");
+            let rows_per_event = MAP_GRID_CELLS_PER_EVENT / 256;
+            assert_eq!(capped["elevation"].as_array().unwrap().len(), rows_per_event);
+            for band in 1..128 / rows_per_event {
+                assert_eq!(next_event(&mut reader)["row_offset"].as_u64(), Some((band * rows_per_event) as u64));
+            }
+
+            let unknown = next_event(&mut reader);
+            assert_eq!((unknown["request_id"].as_u64(), unknown["error"].as_str()), (Some(5), Some("unknown unit def 'nope'")));
+            assert_eq!(unknown["elevation"], serde_json::json!([]));
+            release(engine.ai_id);
+        }
+    }
+
+    #[test]
+    fn test_turn_mode_resumes_when_game_manager_disconnects() {
+        let engine = MockEngine::new();
+        let gm = FakeGm::new(&engine);
+
+        unsafe {
+            let (mut reader, mut writer) = start_session(&engine, &gm);
+            writer.write_all(b"{\"type\":\"set_turn_mode\",\"enabled\":true}\n").unwrap();
+            for frame in 1..=UPDATE_INTERVAL as c_int {
+                send(&engine, EVENT_UPDATE, &events::SUpdateEvent { frame });
+            }
+            assert_eq!(next_event(&mut reader)["awaiting_commands"], true);
+            assert!(engine.with_game(|g| g.paused));
+
+            // Nobody is left to send end_turn.
+            drop(reader);
+            drop(writer);
+            heartbeat(&engine);
+            assert!(!engine.with_game(|g| g.paused));
+            assert!(engine.logs().iter().any(|l| l.contains("disconnected during a turn")));
+
+            // Turn mode is off: later updates don't pause again.
+            for frame in 31..=2 * UPDATE_INTERVAL as c_int {
+                send(&engine, EVENT_UPDATE, &events::SUpdateEvent { frame });
+            }
+            assert!(!engine.with_game(|g| g.paused));
+            release(engine.ai_id);
+        }
+    }
+
+    #[test]
+    fn test_benchmark_mode_forwards_only_game_events() {
+        let engine = MockEngine::new();
+        engine.with_game(|g| g.add_unit(10, "cloakcon", [100.0, 5.0, 200.0], 0));
+        let gm = FakeGm::with_config(&engine, serde_json::json!({"benchmark": true}));
+
+        unsafe {
+            let (mut reader, _writer) = start_session(&engine, &gm);
+            send(&engine, events::EVENT_UNIT_IDLE, &events::SUnitIdleEvent { unit: 10 });
+            for frame in 1..=BENCHMARK_UPDATE_INTERVAL as c_int {
+                send(&engine, EVENT_UPDATE, &events::SUpdateEvent { frame });
+            }
+            send(&engine, events::EVENT_RELEASE, &events::SReleaseEvent { reason: 2 });
+
+            // The idle event and the regular-interval updates never went out.
+            let update = next_event(&mut reader);
+            assert_eq!(update["type"], "update");
+            assert_eq!(update["frame"], BENCHMARK_UPDATE_INTERVAL);
+            assert!(update["economy"].is_object());
+            release(engine.ai_id);
+            assert_eq!(
+                next_event(&mut reader),
+                serde_json::json!({"type": "release", "reason": 2, "stats": {
+                    "frames": BENCHMARK_UPDATE_INTERVAL, "events_sent": 3, "events_dropped": 0, "panics": 0
+                }})
+            );
+        }
+    }
+
+    #[test]
+    fn test_release_before_its_reason() {
+        let engine = MockEngine::new();
+        let gm = FakeGm::new(&engine);
+
+        unsafe {
+            let (mut reader, _writer) = start_session(&engine, &gm);
+            release(engine.ai_id);
+            let early = next_event(&mut reader);
+            assert_eq!((early["reason"].as_i64(), early["stats"]["events_sent"].as_u64()), (Some(0), Some(2)));
+
+            // The instance stays for the reason alone, then goes.
+            assert_eq!(send(&engine, events::EVENT_UNIT_IDLE, &events::SUnitIdleEvent { unit: 10 }), 0);
+            assert_eq!(send(&engine, events::EVENT_RELEASE, &events::SReleaseEvent { reason: 1 }), 0);
+            assert_eq!(next_event(&mut reader)["reason"], 1);
+            assert_eq!(send(&engine, events::EVENT_RELEASE, &events::SReleaseEvent { reason: 1 }), -1);
+        }
+    }
+
+    #[test]
+    fn test_aggregated_events_counted_per_unit() {
+        let engine = MockEngine::new();
+        engine.with_game(|g| g.add_unit(10, "cloakraid", [100.0, 5.0, 200.0], 0));
+        let config =
+            serde_json::json!({"aggregate_events": ["weapon_fired", "command_finished", "unit_damaged", "unit_idle"]});
+        let gm = FakeGm::with_config(&engine, config);
+
+        unsafe {
+            let (mut reader, _writer) = start_session(&engine, &gm);
+            for _ in 0..3 {
+                send(&engine, events::EVENT_WEAPON_FIRED, &events::SWeaponFiredEvent { unit_id: 10, weapon_def_id: 7 });
+            }
+            let finished = events::SCommandFinishedEvent { unit_id: 11, command_id: 4, command_topic_id: 42 };
+            send(&engine, events::EVENT_COMMAND_FINISHED, &finished);
+            // EMP hits count apart from real damage.
+            for paralyzer in [false, true, true] {
+                let hit = events::SUnitDamagedEvent {
+                    unit: 10, attacker: 90, damage: 20.0, dir: std::ptr::null(), weapon_def_id: 2, paralyzer,
+                };
+                send(&engine, events::EVENT_UNIT_DAMAGED, &hit);
+            }
+            // Not aggregatable: still forwarded.
+            send(&engine, events::EVENT_UNIT_IDLE, &events::SUnitIdleEvent { unit: 10 });
+            for frame in 1..=2 * UPDATE_INTERVAL as c_int {
+                send(&engine, EVENT_UPDATE, &events::SUpdateEvent { frame });
+            }
+
+            assert_eq!(next_event(&mut reader)["type"], "unit_idle");
+            let update = next_event(&mut reader);
+            assert_eq!(
+                update["counters"],
+                serde_json::json!({
+                    "10": {"weapon_fired": 3, "unit_damaged": 1, "unit_paralyzed": 2},
+                    "11": {"command_finished": 1}
+                })
+            );
+            // Counting starts over after each update.
+            let update = next_event(&mut reader);
+            assert_eq!(update["type"], "update");
+            assert!(update.get("counters").is_none());
+            release(engine.ai_id);
+        }
+    }
+
+    #[test]
+    fn test_construction_progress_and_stalls() {
+        let engine = MockEngine::new();
+        engine.with_game(|g| {
+            g.add_unit(10, "cloakcon", [100.0, 5.0, 200.0], 0);
+            g.add_unit(20, "energyfusion", [150.0, 5.0, 200.0], 0);
+            g.units.get_mut(&20).unwrap().build_progress = 0.25;
+        });
+        let gm = FakeGm::new(&engine);
+        let set_progress = |progress| engine.with_game(|g| g.units.get_mut(&20).unwrap().build_progress = progress);
+        let interval = |reader: &mut BufReader<UnixStream>, n: c_int| {
+            for frame in (n - 1) * UPDATE_INTERVAL as c_int + 1..=n * UPDATE_INTERVAL as c_int {
+                unsafe { send(&engine, EVENT_UPDATE, &events::SUpdateEvent { frame }) };
+            }
+            next_event(reader)
+        };
+
+        unsafe {
+            let (mut reader, _writer) = start_session(&engine, &gm);
+            send(&engine, events::EVENT_UNIT_CREATED, &events::SUnitCreatedEvent { unit: 20, builder: 10 });
+            assert_eq!(next_event(&mut reader)["type"], "unit_created");
+
+            let update = interval(&mut reader, 1);
+            assert_eq!(
+                update["under_construction"],
+                serde_json::json!([{"unit": 20, "unit_name": "energyfusion", "progress": 25.0, "builders": 1}])
+            );
+            // No progress with the builder on it: one stall event, after the update.
+            assert_eq!(interval(&mut reader, 2)["under_construction"][0]["progress"], 25.0);
+            assert_eq!(
+                next_event(&mut reader),
+                serde_json::json!({"type": "construction_stalled", "unit": 20, "unit_name": "energyfusion", "progress": 25.0, "builders": 1})
+            );
+            assert_eq!(interval(&mut reader, 3)["type"], "update");
+
+            // The builder wanders off: still stuck, but nobody is building it.
+            set_progress(0.5);
+            interval(&mut reader, 4);
+            send(&engine, events::EVENT_UNIT_IDLE, &events::SUnitIdleEvent { unit: 10 });
+            assert_eq!(next_event(&mut reader)["type"], "unit_idle");
+            let update = interval(&mut reader, 5);
+            assert_eq!(update["under_construction"][0]["progress"], 50.0);
+            assert!(update["under_construction"][0].get("builders").is_none());
+
+            send(&engine, events::EVENT_UNIT_FINISHED, &events::SUnitFinishedEvent { unit: 20 });
+            assert_eq!(next_event(&mut reader)["type"], "unit_finished");
+            assert!(interval(&mut reader, 6).get("under_construction").is_none());
+            release(engine.ai_id);
+        }
+    }
+
+    #[test]
+    fn test_adaptive_updates_and_pin() {
+        let engine = MockEngine::new();
+        engine.with_game(|g| g.add_unit(10, "cloakraid", [100.0, 5.0, 200.0], 0));
+        // Damage counted into updates still counts as fighting.
+        let config = serde_json::json!({"update_cadence": {"mode": "adaptive"}, "aggregate_events": ["unit_damaged"]});
+        let gm = FakeGm::with_config(&engine, config);
+        let frames = |from: c_int, to: c_int| {
+            for frame in from..=to {
+                unsafe { send(&engine, EVENT_UPDATE, &events::SUpdateEvent { frame }) };
+            }
+        };
+
+        unsafe {
+            let (mut reader, mut writer) = start_session(&engine, &gm);
+            for _ in 0..5 {
+                let hit = events::SUnitDamagedEvent {
+                    unit: 10, attacker: 90, damage: 20.0, dir: std::ptr::null(), weapon_def_id: 2, paralyzer: false,
+                };
+                send(&engine, events::EVENT_UNIT_DAMAGED, &hit);
+            }
+            frames(1, 30);
+            let update = next_event(&mut reader);
+            assert_eq!((update["frame"].clone(), update["update_interval"].clone()), (30.into(), 15.into()));
+            frames(31, 45);
+            assert_eq!(next_event(&mut reader)["frame"], 45);
+
+            // Pinned, however quiet it gets; adaptation goes on underneath.
+            writer.write_all(b"{\"type\":\"set_update_interval\",\"frames\":60}\n").unwrap();
+            frames(46, 105);
+            let update = next_event(&mut reader);
+            assert_eq!((update["frame"].clone(), update["update_interval"].clone()), (105.into(), 60.into()));
+            writer.write_all(b"{\"type\":\"set_update_interval\"}\n").unwrap();
+            frames(106, 120);
+            let update = next_event(&mut reader);
+            assert_eq!((update["frame"].clone(), update["update_interval"].clone()), (120.into(), 23.into()));
+
+            writer.write_all(b"{\"type\":\"set_update_interval\",\"frames\":0}\n").unwrap();
+            frames(121, 121);
+            assert_eq!(next_event(&mut reader)["error"], "set_update_interval: 0 frames is outside 1-9000");
+            release(engine.ai_id);
+        }
+    }
+
+    #[test]
+    fn test_busiest_units_kept() {
+        let counters = (0..sai_protocol::MAX_COUNTED_UNITS as i32 + 5)
+            .map(|unit| (UnitId(unit), BTreeMap::from([("weapon_fired".to_string(), unit as u32 % 7)])))
+            .collect();
+        let kept = busiest_units(counters);
+        assert_eq!(kept.len(), sai_protocol::MAX_COUNTED_UNITS);
+        // Every unit with 6 (the most) is kept, none with 0.
+        assert!(kept.contains_key("6") && kept.contains_key("13") && kept.contains_key("20"));
+        assert!(!kept.contains_key("0") && !kept.contains_key("7"));
+    }
+}
@@ -0,0 +1,197 @@
+//! Leveled logging for the bridge.
+//!
+//! Messages go to the engine's log callback (infolog.txt), to an optional
+//! log file, or both. The file sink is configured from connection.json so
+//! diagnostics are available even when the engine log is unusable and
+//! don't drown in the engine's own output.
+//!
+//! Use the `log_debug!` / `log_info!` / `log_warn!` macros, which take an
+//! `Option<&EngineCallbacks>` first (None when no callback is at hand, e.g.
+//! inside the IPC client).
+
+use crate::callbacks::EngineCallbacks;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Debug = 0,
+    Info = 1,
+    Warn = 2,
+}
+
+impl Level {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "debug" => Some(Level::Debug),
+            "info" => Some(Level::Info),
+            "warn" | "warning" => Some(Level::Warn),
+            _ => None,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Level::Debug => "DEBUG",
+            Level::Info => "INFO",
+            Level::Warn => "WARN",
+        }
+    }
+
+    fn from_u8(v: u8) -> Self {
+        match v {
+            0 => Level::Debug,
+            1 => Level::Info,
+            _ => Level::Warn,
+        }
+    }
+}
+
+/// Where log lines are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Engine,
+    File,
+    Both,
+}
+
+impl Target {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "engine" => Some(Target::Engine),
+            "file" => Some(Target::File),
+            "both" => Some(Target::Both),
+            _ => None,
+        }
+    }
+}
+
+struct Sinks {
+    target: Target,
+    file: Option<File>,
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(Level::Info as u8);
+static SINKS: Mutex<Sinks> = Mutex::new(Sinks {
+    target: Target::Engine,
+    file: None,
+});
+
+/// Current minimum level.
+pub fn level() -> Level {
+    Level::from_u8(LEVEL.load(Ordering::Relaxed))
+}
+
+/// Change the minimum level at runtime.
+pub fn set_level(level: Level) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn enabled(level: Level) -> bool {
+    level >= self::level()
+}
+
+/// Apply logging settings from connection.json.
+///
+/// Recognized keys: `log_level` ("debug" | "info" | "warn"),
+/// `log_file` (path), `log_target` ("engine" | "file" | "both").
+/// When `log_file` is set without `log_target`, both sinks are used.
+/// The `SAI_LOG_LEVEL` environment variable overrides `log_level`.
+pub fn configure(config: &serde_json::Value) -> Result<(), String> {
+    let level = std::env::var("SAI_LOG_LEVEL")
+        .ok()
+        .or_else(|| config.get("log_level").and_then(|v| v.as_str()).map(String::from));
+    if let Some(level) = level {
+        set_level(Level::parse(&level).ok_or_else(|| format!("Unknown log level: {}", level))?);
+    }
+
+    let file_path = config.get("log_file").and_then(|v| v.as_str());
+    let target = match config.get("log_target").and_then(|v| v.as_str()) {
+        Some(t) => Target::parse(t).ok_or_else(|| format!("Unknown log target: {}", t))?,
+        None if file_path.is_some() => Target::Both,
+        None => Target::Engine,
+    };
+
+    let file = match file_path {
+        Some(path) => Some(
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|e| format!("Failed to open log file {}: {}", path, e))?,
+        ),
+        None => None,
+    };
+
+    let mut sinks = SINKS.lock().unwrap();
+    sinks.target = if file.is_none() { Target::Engine } else { target };
+    sinks.file = file;
+    Ok(())
+}
+
+/// Write one message. Prefer the macros, which skip formatting when the
+/// level is filtered out.
+pub fn write(level: Level, cb: Option<&EngineCallbacks>, msg: std::fmt::Arguments) {
+    if !enabled(level) {
+        return;
+    }
+    let mut sinks = SINKS.lock().unwrap();
+    let to_engine = sinks.target != Target::File;
+    let to_file = sinks.target != Target::Engine;
+
+    if to_engine {
+        match cb {
+            Some(cb) => cb.log(&format!("[SAI Bridge] [{}] {}", level.as_str(), msg)),
+            // No engine callback available — stderr still ends up in the
+            // engine's console output.
+            None if sinks.file.is_none() => {
+                eprintln!("[SAI Bridge] [{}] {}", level.as_str(), msg)
+            }
+            None => {}
+        }
+    }
+    if to_file || cb.is_none() {
+        if let Some(file) = sinks.file.as_mut() {
+            let ts = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default();
+            let ai = cb.map(|c| c.ai_id).unwrap_or(-1);
+            let _ = writeln!(
+                file,
+                "{}.{:03} [{}] [ai {}] {}",
+                ts.as_secs(),
+                ts.subsec_millis(),
+                level.as_str(),
+                ai,
+                msg
+            );
+        }
+    }
+}
+
+macro_rules! log_debug {
+    ($cb:expr, $($arg:tt)+) => {
+        if $crate::logging::enabled($crate::logging::Level::Debug) {
+            $crate::logging::write($crate::logging::Level::Debug, $cb, format_args!($($arg)+))
+        }
+    };
+}
+
+macro_rules! log_info {
+    ($cb:expr, $($arg:tt)+) => {
+        if $crate::logging::enabled($crate::logging::Level::Info) {
+            $crate::logging::write($crate::logging::Level::Info, $cb, format_args!($($arg)+))
+        }
+    };
+}
+
+macro_rules! log_warn {
+    ($cb:expr, $($arg:tt)+) => {
+        if $crate::logging::enabled($crate::logging::Level::Warn) {
+            $crate::logging::write($crate::logging::Level::Warn, $cb, format_args!($($arg)+))
+        }
+    };
+}
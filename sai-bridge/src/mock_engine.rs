@@ -0,0 +1,783 @@
+//! In-process fake of the engine side of the AI interface, for tests.
+//!
+//! [`MockEngine`] builds an `SSkirmishAICallback` table whose entries point
+//! at the shim functions below. The shims read and write a scriptable
+//! [`FakeGame`] (units, defs, economy, rules params, AI info/options) keyed
+//! by skirmish AI id, so tests can run in parallel as long as each uses its
+//! own engine. `Engine_handleCommand` decodes the command struct it receives
+//! into a [`RecordedCommand`], which lets tests check the exact fields the
+//! bridge put on the wire to the engine.
+
+use crate::callbacks::*;
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::{c_char, c_float, c_int, c_void, CStr, CString};
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::Mutex;
+
+static GAMES: Mutex<BTreeMap<c_int, FakeGame>> = Mutex::new(BTreeMap::new());
+
+/// AI ids handed out to mock engines. Starts high so tests never collide
+/// with anything else touching the global instance table.
+static NEXT_AI_ID: AtomicI32 = AtomicI32::new(100);
+
+#[derive(Default)]
+pub struct FakeDef {
+    pub name: CString,
+    pub human_name: CString,
+    pub tooltip: CString,
+    /// [metal, energy]
+    pub cost: [f32; 2],
+    pub build_time: f32,
+    pub health: f32,
+    pub speed: f32,
+    pub builder: bool,
+    pub build_speed: f32,
+    pub build_options: Vec<c_int>,
+    /// [metal, energy] made per second.
+    pub resource_make: [f32; 2],
+    /// [metal, energy] extracted.
+    pub extracts: [f32; 2],
+}
+
+#[derive(Clone, Copy)]
+pub struct FakeUnit {
+    pub def_id: c_int,
+    pub pos: [f32; 3],
+    pub team: c_int,
+    pub health: f32,
+    pub max_health: f32,
+    pub paralyze_damage: f32,
+    /// 1.0 once built.
+    pub build_progress: f32,
+}
+
+/// A command as received by `Engine_handleCommand`.
+#[derive(Debug, Clone)]
+pub struct RecordedCommand {
+    pub topic: c_int,
+    pub to_id: c_int,
+    pub command_id: c_int,
+    /// The command struct's fields, decoded by topic.
+    pub fields: serde_json::Value,
+}
+
+pub struct FakeGame {
+    pub frame: c_int,
+    pub paused: bool,
+    pub speed: f32,
+    pub my_team: c_int,
+    pub my_ally_team: c_int,
+    /// Ally team of each team, by team id.
+    pub ally_teams: Vec<c_int>,
+    pub map_width: c_int,
+    pub map_height: c_int,
+    /// `Map_getStartPos`; a negative x means not placed yet.
+    pub start_pos: [f32; 3],
+    /// Indexed by def id; id 0 is unused, as in the engine.
+    pub defs: Vec<FakeDef>,
+    pub units: HashMap<c_int, FakeUnit>,
+    /// Feature id -> feature def id.
+    pub features: HashMap<c_int, c_int>,
+    /// `Unit_getMax`.
+    pub max_units: c_int,
+    /// Each unit's command queue: command id and parameters.
+    pub queues: HashMap<c_int, Vec<(c_int, Vec<f32>)>>,
+    /// resource id -> [current, income, usage, storage]
+    pub economy: HashMap<c_int, [f32; 4]>,
+    /// resource id -> received from allies
+    pub received: HashMap<c_int, f32>,
+    /// (team, resource id) -> [current, income, usage, storage, received]
+    /// for `Game_getTeamResource*`, which answer -1 for teams not allied
+    /// with ours.
+    pub team_economy: HashMap<(c_int, c_int), [f32; 5]>,
+    pub rules_params: HashMap<String, f32>,
+    pub info: HashMap<String, CString>,
+    pub options: HashMap<String, CString>,
+    pub setup_script: Option<CString>,
+    pub engine_version: Option<CString>,
+    /// Ground height at (x, z), for `Map_getElevationAt`.
+    pub elevation: fn(f32, f32) -> f32,
+    /// Answer of `Map_isPossibleToBuildAt` at (x, z).
+    pub buildable: fn(f32, f32) -> bool,
+    /// Result of `Map_findClosestBuildSite`; None echoes the requested position.
+    pub build_site: Option<[f32; 3]>,
+    /// Return value of `Engine_handleCommand`.
+    pub command_result: c_int,
+    pub logs: Vec<String>,
+    pub commands: Vec<RecordedCommand>,
+}
+
+impl Default for FakeGame {
+    fn default() -> Self {
+        Self {
+            frame: 0,
+            paused: false,
+            speed: 1.0,
+            my_team: 0,
+            my_ally_team: 0,
+            ally_teams: vec![0, 1],
+            map_width: 512,
+            map_height: 512,
+            start_pos: [-1.0, 0.0, 0.0],
+            defs: vec![FakeDef::default()],
+            units: HashMap::new(),
+            features: HashMap::new(),
+            max_units: crate::events::MAX_UNITS,
+            queues: HashMap::new(),
+            economy: HashMap::new(),
+            received: HashMap::new(),
+            team_economy: HashMap::new(),
+            rules_params: HashMap::new(),
+            info: HashMap::new(),
+            options: HashMap::new(),
+            setup_script: None,
+            engine_version: Some(CString::new("105.1.1-2511-g747f18b").unwrap()),
+            elevation: |_, _| 0.0,
+            buildable: |_, _| true,
+            build_site: None,
+            command_result: 0,
+            logs: Vec::new(),
+            commands: Vec::new(),
+        }
+    }
+}
+
+impl FakeGame {
+    /// Register a unit def and return its id.
+    pub fn add_def(&mut self, name: &str, human_name: &str) -> c_int {
+        self.defs.push(FakeDef {
+            name: CString::new(name).unwrap(),
+            human_name: CString::new(human_name).unwrap(),
+            ..Default::default()
+        });
+        (self.defs.len() - 1) as c_int
+    }
+
+    /// Place a unit of the named def (registered on first use).
+    pub fn add_unit(&mut self, unit_id: c_int, def_name: &str, pos: [f32; 3], team: c_int) {
+        let def_id = match self.def_id(def_name) {
+            Some(id) => id,
+            None => self.add_def(def_name, def_name),
+        };
+        let unit = FakeUnit { def_id, pos, team, health: 100.0, max_health: 100.0, paralyze_damage: 0.0, build_progress: 1.0 };
+        self.units.insert(unit_id, unit);
+    }
+
+    pub fn def_id(&self, name: &str) -> Option<c_int> {
+        self.defs
+            .iter()
+            .position(|d| d.name.to_bytes() == name.as_bytes())
+            .filter(|&i| i > 0)
+            .map(|i| i as c_int)
+    }
+
+    pub fn set_info(&mut self, key: &str, value: &str) {
+        self.info.insert(key.into(), CString::new(value).unwrap());
+    }
+
+    pub fn set_option(&mut self, key: &str, value: &str) {
+        self.options.insert(key.into(), CString::new(value).unwrap());
+    }
+}
+
+/// A fake engine: owns the callback table and the game state behind it.
+pub struct MockEngine {
+    pub ai_id: c_int,
+    table: Box<SSkirmishAICallback>,
+}
+
+impl MockEngine {
+    pub fn new() -> Self {
+        let ai_id = NEXT_AI_ID.fetch_add(1, Ordering::Relaxed);
+        GAMES.lock().unwrap().insert(ai_id, FakeGame::default());
+
+        // SAFETY: every field of the bindgen struct is an Option<fn>, for
+        // which all-zeroes is None. Entries the bridge never calls stay None.
+        let mut table: Box<SSkirmishAICallback> = Box::new(unsafe { std::mem::zeroed() });
+        table.Engine_handleCommand = Some(engine_handle_command);
+        table.SkirmishAI_Info_getValueByKey = Some(info_get_value);
+        table.SkirmishAI_OptionValues_getValueByKey = Some(option_get_value);
+        table.Log_log = Some(log_log);
+        table.Game_getCurrentFrame = Some(game_get_current_frame);
+        table.Game_getMyTeam = Some(game_get_my_team);
+        table.Game_getMyAllyTeam = Some(game_get_my_ally_team);
+        table.Game_getTeams = Some(game_get_teams);
+        table.Game_getTeamAllyTeam = Some(game_get_team_ally_team);
+        table.Game_isPaused = Some(game_is_paused);
+        table.Game_getSpeedFactor = Some(game_get_speed_factor);
+        table.Game_getSetupScript = Some(game_get_setup_script);
+        table.Engine_Version_getNormal = Some(engine_version_get_normal);
+        table.Game_getRulesParamFloat = Some(game_get_rules_param_float);
+        table.Economy_getCurrent = Some(economy_get_current);
+        table.Economy_getIncome = Some(economy_get_income);
+        table.Economy_getUsage = Some(economy_get_usage);
+        table.Economy_getStorage = Some(economy_get_storage);
+        table.Economy_getReceived = Some(economy_get_received);
+        table.Game_getTeamResourceCurrent = Some(game_get_team_resource_current);
+        table.Game_getTeamResourceIncome = Some(game_get_team_resource_income);
+        table.Game_getTeamResourceUsage = Some(game_get_team_resource_usage);
+        table.Game_getTeamResourceStorage = Some(game_get_team_resource_storage);
+        table.Game_getTeamResourceReceived = Some(game_get_team_resource_received);
+        table.getUnitDefByName = Some(get_unit_def_by_name);
+        table.getUnitDefs = Some(get_unit_defs);
+        table.getTeamUnits = Some(get_team_units);
+        table.UnitDef_getName = Some(unit_def_get_name);
+        table.UnitDef_getHumanName = Some(unit_def_get_human_name);
+        table.UnitDef_getTooltip = Some(unit_def_get_tooltip);
+        table.UnitDef_getCost = Some(unit_def_get_cost);
+        table.UnitDef_getBuildTime = Some(unit_def_get_build_time);
+        table.UnitDef_getHealth = Some(unit_def_get_health);
+        table.UnitDef_getSpeed = Some(unit_def_get_speed);
+        table.UnitDef_isBuilder = Some(unit_def_is_builder);
+        table.UnitDef_getBuildSpeed = Some(unit_def_get_build_speed);
+        table.UnitDef_getResourceMake = Some(unit_def_get_resource_make);
+        table.UnitDef_getExtractsResource = Some(unit_def_get_extracts_resource);
+        table.UnitDef_getBuildOptions = Some(unit_def_get_build_options);
+        table.Unit_getDef = Some(unit_get_def);
+        table.Unit_getPos = Some(unit_get_pos);
+        table.Unit_getTeam = Some(unit_get_team);
+        table.Unit_getHealth = Some(unit_get_health);
+        table.Unit_getMaxHealth = Some(unit_get_max_health);
+        table.Unit_getParalyzeDamage = Some(unit_get_paralyze_damage);
+        table.Unit_getBuildProgress = Some(unit_get_build_progress);
+        table.Unit_getCurrentCommands = Some(unit_get_current_commands);
+        table.Unit_CurrentCommand_getId = Some(unit_current_command_get_id);
+        table.Unit_CurrentCommand_getParams = Some(unit_current_command_get_params);
+        table.Unit_getMax = Some(unit_get_max);
+        table.Feature_getDef = Some(feature_get_def);
+        table.Map_getWidth = Some(map_get_width);
+        table.Map_getHeight = Some(map_get_height);
+        table.Map_getStartPos = Some(map_get_start_pos);
+        table.Map_getElevationAt = Some(map_get_elevation_at);
+        table.Map_isPossibleToBuildAt = Some(map_is_possible_to_build_at);
+        table.Map_findClosestBuildSite = Some(map_find_closest_build_site);
+
+        Self { ai_id, table }
+    }
+
+    pub fn table(&self) -> *const SSkirmishAICallback {
+        &*self.table
+    }
+
+    pub fn callbacks(&self) -> EngineCallbacks {
+        unsafe { EngineCallbacks::new(self.ai_id, self.table()) }
+    }
+
+    /// Inspect or script the game state. Don't call into the bridge from
+    /// inside `f` — the shims take the same lock.
+    pub fn with_game<R>(&self, f: impl FnOnce(&mut FakeGame) -> R) -> R {
+        f(GAMES.lock().unwrap().get_mut(&self.ai_id).unwrap())
+    }
+
+    /// Drain the commands received so far.
+    pub fn take_commands(&self) -> Vec<RecordedCommand> {
+        self.with_game(|g| std::mem::take(&mut g.commands))
+    }
+
+    pub fn logs(&self) -> Vec<String> {
+        self.with_game(|g| g.logs.clone())
+    }
+}
+
+impl Drop for MockEngine {
+    fn drop(&mut self) {
+        GAMES.lock().unwrap().remove(&self.ai_id);
+    }
+}
+
+fn with<R: Default>(ai_id: c_int, f: impl FnOnce(&mut FakeGame) -> R) -> R {
+    GAMES.lock().unwrap().get_mut(&ai_id).map(f).unwrap_or_default()
+}
+
+/// Like [`with`], for shims returning a string owned by the game state.
+fn with_str(ai_id: c_int, f: impl FnOnce(&FakeGame) -> Option<&CString>) -> *const c_char {
+    GAMES
+        .lock()
+        .unwrap()
+        .get(&ai_id)
+        .and_then(f)
+        .map(|s| s.as_ptr())
+        .unwrap_or(std::ptr::null())
+}
+
+unsafe fn key(ptr: *const c_char) -> String {
+    CStr::from_ptr(ptr).to_string_lossy().into_owned()
+}
+
+unsafe fn read_pos(ptr: *const [c_float; 3]) -> serde_json::Value {
+    if ptr.is_null() {
+        serde_json::Value::Null
+    } else {
+        serde_json::json!(*ptr)
+    }
+}
+
+/// Decode a command struct by topic, the way the engine would read it.
+unsafe fn decode_command(topic: c_int, data: *const c_void) -> serde_json::Value {
+    use serde_json::json;
+    if data.is_null() {
+        return serde_json::Value::Null;
+    }
+    let unit_header = |h: &SStopUnitCommand| {
+        json!({
+            "unit_id": h.unit_id,
+            "group_id": h.group_id,
+            "options": h.options,
+            "time_out": h.time_out,
+        })
+    };
+    let mut fields = match topic {
+        COMMAND_SEND_TEXT_MESSAGE => {
+            let c = &*(data as *const SSendTextMessageCommand);
+            return json!({ "text": key(c.text), "zone": c.zone });
+        }
+        COMMAND_DRAWER_POINT_ADD => {
+            let c = &*(data as *const SAddPointDrawCommand);
+            return json!({ "pos": read_pos(c.pos), "label": key(c.label) });
+        }
+        COMMAND_PAUSE => {
+            let c = &*(data as *const SPauseCommand);
+            let reason = if c.reason.is_null() { None } else { Some(key(c.reason)) };
+            return json!({ "enable": c.enable, "reason": reason });
+        }
+        _ => unit_header(&*(data as *const SStopUnitCommand)),
+    };
+    let extra = match topic {
+        COMMAND_UNIT_MOVE | COMMAND_UNIT_PATROL | COMMAND_UNIT_FIGHT => {
+            let c = &*(data as *const SMoveUnitCommand);
+            json!({ "to_pos": read_pos(c.to_pos) })
+        }
+        COMMAND_UNIT_ATTACK => {
+            let c = &*(data as *const SAttackUnitCommand);
+            json!({ "target": c.to_attack_unit_id })
+        }
+        COMMAND_UNIT_WAIT_TIME => {
+            let c = &*(data as *const STimeWaitUnitCommand);
+            json!({ "time": c.time })
+        }
+        COMMAND_UNIT_ATTACK_AREA => {
+            let c = &*(data as *const SAttackAreaUnitCommand);
+            json!({ "pos": read_pos(c.to_attack_pos), "radius": c.radius })
+        }
+        COMMAND_UNIT_D_GUN => {
+            let c = &*(data as *const SDGunUnitCommand);
+            json!({ "target": c.to_attack_unit_id })
+        }
+        COMMAND_UNIT_D_GUN_POS => {
+            let c = &*(data as *const SDGunPosUnitCommand);
+            json!({ "pos": read_pos(c.pos) })
+        }
+        COMMAND_UNIT_LOAD_UNITS => {
+            let c = &*(data as *const SLoadUnitsUnitCommand);
+            let ids = if c.to_load_unit_ids.is_null() {
+                &[][..]
+            } else {
+                std::slice::from_raw_parts(c.to_load_unit_ids, c.to_load_unit_ids_size as usize)
+            };
+            json!({ "targets": ids })
+        }
+        COMMAND_UNIT_LOAD_ONTO => {
+            let c = &*(data as *const SLoadOntoUnitCommand);
+            json!({ "transport": c.transporter_unit_id })
+        }
+        COMMAND_UNIT_UNLOAD_UNITS_AREA => {
+            let c = &*(data as *const SUnloadUnitsAreaUnitCommand);
+            json!({ "pos": read_pos(c.to_pos), "radius": c.radius })
+        }
+        COMMAND_UNIT_GUARD => {
+            let c = &*(data as *const SGuardUnitCommand);
+            json!({ "target": c.to_guard_unit_id })
+        }
+        COMMAND_UNIT_REPAIR => {
+            let c = &*(data as *const SRepairUnitCommand);
+            json!({ "target": c.to_repair_unit_id })
+        }
+        COMMAND_UNIT_RECLAIM_UNIT => {
+            let c = &*(data as *const SReclaimUnitUnitCommand);
+            json!({ "target": c.to_reclaim_unit_id_or_feature_id })
+        }
+        COMMAND_UNIT_RECLAIM_AREA => {
+            let c = &*(data as *const SReclaimAreaUnitCommand);
+            json!({ "pos": read_pos(c.pos), "radius": c.radius })
+        }
+        COMMAND_UNIT_BUILD => {
+            let c = &*(data as *const SBuildUnitCommand);
+            json!({
+                "def_id": c.to_build_unit_def_id,
+                "build_pos": read_pos(c.build_pos),
+                "facing": c.facing,
+            })
+        }
+        COMMAND_UNIT_CUSTOM => {
+            let c = &*(data as *const SCustomUnitCommand);
+            let params = if c.params.is_null() {
+                &[][..]
+            } else {
+                std::slice::from_raw_parts(c.params, c.params_size as usize)
+            };
+            json!({ "cmd_id": c.cmd_id, "params": params })
+        }
+        COMMAND_UNIT_SET_FIRE_STATE => {
+            let c = &*(data as *const SSetFireStateUnitCommand);
+            json!({ "state": c.fire_state })
+        }
+        COMMAND_UNIT_SET_MOVE_STATE => {
+            let c = &*(data as *const SSetMoveStateUnitCommand);
+            json!({ "state": c.move_state })
+        }
+        _ => json!({}),
+    };
+    if let (Some(f), Some(e)) = (fields.as_object_mut(), extra.as_object()) {
+        f.extend(e.clone());
+    }
+    fields
+}
+
+// ── Shims ──
+
+unsafe extern "C" fn engine_handle_command(
+    ai_id: c_int,
+    to_id: c_int,
+    command_id: c_int,
+    topic: c_int,
+    data: *mut c_void,
+) -> c_int {
+    let fields = decode_command(topic, data);
+    with(ai_id, |g| {
+        if topic == COMMAND_PAUSE {
+            g.paused = fields["enable"].as_bool().unwrap_or(false);
+        }
+        g.commands.push(RecordedCommand { topic, to_id, command_id, fields });
+        g.command_result
+    })
+}
+
+unsafe extern "C" fn info_get_value(ai_id: c_int, k: *const c_char) -> *const c_char {
+    let k = key(k);
+    with_str(ai_id, |g| g.info.get(&k))
+}
+
+unsafe extern "C" fn option_get_value(ai_id: c_int, k: *const c_char) -> *const c_char {
+    let k = key(k);
+    with_str(ai_id, |g| g.options.get(&k))
+}
+
+unsafe extern "C" fn log_log(ai_id: c_int, msg: *const c_char) {
+    let msg = key(msg);
+    with(ai_id, |g| g.logs.push(msg));
+}
+
+unsafe extern "C" fn game_get_current_frame(ai_id: c_int) -> c_int {
+    with(ai_id, |g| g.frame)
+}
+
+unsafe extern "C" fn game_get_my_team(ai_id: c_int) -> c_int {
+    with(ai_id, |g| g.my_team)
+}
+
+unsafe extern "C" fn game_get_my_ally_team(ai_id: c_int) -> c_int {
+    with(ai_id, |g| g.my_ally_team)
+}
+
+unsafe extern "C" fn game_get_teams(ai_id: c_int) -> c_int {
+    with(ai_id, |g| g.ally_teams.len() as c_int)
+}
+
+unsafe extern "C" fn game_get_team_ally_team(ai_id: c_int, team: c_int) -> c_int {
+    with(ai_id, |g| usize::try_from(team).ok().and_then(|t| g.ally_teams.get(t).copied()).unwrap_or(-1))
+}
+
+unsafe extern "C" fn game_is_paused(ai_id: c_int) -> bool {
+    with(ai_id, |g| g.paused)
+}
+
+unsafe extern "C" fn game_get_speed_factor(ai_id: c_int) -> c_float {
+    with(ai_id, |g| g.speed)
+}
+
+unsafe extern "C" fn game_get_setup_script(ai_id: c_int) -> *const c_char {
+    with_str(ai_id, |g| g.setup_script.as_ref())
+}
+
+unsafe extern "C" fn engine_version_get_normal(ai_id: c_int) -> *const c_char {
+    with_str(ai_id, |g| g.engine_version.as_ref())
+}
+
+unsafe extern "C" fn game_get_rules_param_float(
+    ai_id: c_int,
+    name: *const c_char,
+    default: c_float,
+) -> c_float {
+    let name = key(name);
+    GAMES
+        .lock()
+        .unwrap()
+        .get(&ai_id)
+        .and_then(|g| g.rules_params.get(&name).copied())
+        .unwrap_or(default)
+}
+
+fn economy(ai_id: c_int, resource: c_int, slot: usize) -> c_float {
+    with(ai_id, |g| g.economy.get(&resource).map(|e| e[slot]).unwrap_or(0.0))
+}
+
+unsafe extern "C" fn economy_get_current(ai_id: c_int, resource: c_int) -> c_float {
+    economy(ai_id, resource, 0)
+}
+
+unsafe extern "C" fn economy_get_income(ai_id: c_int, resource: c_int) -> c_float {
+    economy(ai_id, resource, 1)
+}
+
+unsafe extern "C" fn economy_get_usage(ai_id: c_int, resource: c_int) -> c_float {
+    economy(ai_id, resource, 2)
+}
+
+unsafe extern "C" fn economy_get_storage(ai_id: c_int, resource: c_int) -> c_float {
+    economy(ai_id, resource, 3)
+}
+
+unsafe extern "C" fn economy_get_received(ai_id: c_int, resource: c_int) -> c_float {
+    with(ai_id, |g| g.received.get(&resource).copied().unwrap_or(0.0))
+}
+
+fn team_economy(ai_id: c_int, team: c_int, resource: c_int, slot: usize) -> c_float {
+    with(ai_id, |g| {
+        let allied = usize::try_from(team).ok().and_then(|t| g.ally_teams.get(t)) == Some(&g.my_ally_team);
+        if !allied {
+            return -1.0;
+        }
+        g.team_economy.get(&(team, resource)).map(|e| e[slot]).unwrap_or(0.0)
+    })
+}
+
+unsafe extern "C" fn game_get_team_resource_current(ai_id: c_int, team: c_int, resource: c_int) -> c_float {
+    team_economy(ai_id, team, resource, 0)
+}
+
+unsafe extern "C" fn game_get_team_resource_income(ai_id: c_int, team: c_int, resource: c_int) -> c_float {
+    team_economy(ai_id, team, resource, 1)
+}
+
+unsafe extern "C" fn game_get_team_resource_usage(ai_id: c_int, team: c_int, resource: c_int) -> c_float {
+    team_economy(ai_id, team, resource, 2)
+}
+
+unsafe extern "C" fn game_get_team_resource_storage(ai_id: c_int, team: c_int, resource: c_int) -> c_float {
+    team_economy(ai_id, team, resource, 3)
+}
+
+unsafe extern "C" fn game_get_team_resource_received(ai_id: c_int, team: c_int, resource: c_int) -> c_float {
+    team_economy(ai_id, team, resource, 4)
+}
+
+unsafe extern "C" fn get_unit_def_by_name(ai_id: c_int, name: *const c_char) -> c_int {
+    let name = key(name);
+    GAMES
+        .lock()
+        .unwrap()
+        .get(&ai_id)
+        .and_then(|g| g.def_id(&name))
+        .unwrap_or(-1)
+}
+
+unsafe extern "C" fn get_team_units(ai_id: c_int, out: *mut c_int, max: c_int) -> c_int {
+    let mut ids: Vec<c_int> = with(ai_id, |g| {
+        g.units
+            .iter()
+            .filter(|(_, u)| u.team == g.my_team)
+            .map(|(&id, _)| id)
+            .collect()
+    });
+    ids.sort_unstable();
+    write_ids(&ids, out, max)
+}
+
+unsafe extern "C" fn unit_def_get_name(ai_id: c_int, def_id: c_int) -> *const c_char {
+    with_str(ai_id, |g| g.defs.get(def_id as usize).filter(|_| def_id > 0).map(|d| &d.name))
+}
+
+unsafe extern "C" fn unit_def_get_human_name(ai_id: c_int, def_id: c_int) -> *const c_char {
+    with_str(ai_id, |g| g.defs.get(def_id as usize).filter(|_| def_id > 0).map(|d| &d.human_name))
+}
+
+unsafe extern "C" fn unit_def_get_tooltip(ai_id: c_int, def_id: c_int) -> *const c_char {
+    with_str(ai_id, |g| g.defs.get(def_id as usize).filter(|_| def_id > 0).map(|d| &d.tooltip))
+}
+
+/// A field of a def; 0 (or false) for unknown ids.
+fn def_field<R: Default>(ai_id: c_int, def_id: c_int, f: impl FnOnce(&FakeDef) -> R) -> R {
+    with(ai_id, |g| g.defs.get(def_id as usize).filter(|_| def_id > 0).map(f).unwrap_or_default())
+}
+
+unsafe extern "C" fn unit_def_get_cost(ai_id: c_int, def_id: c_int, resource: c_int) -> c_float {
+    def_field(ai_id, def_id, |d| d.cost.get(resource as usize).copied().unwrap_or(0.0))
+}
+
+unsafe extern "C" fn unit_def_get_build_time(ai_id: c_int, def_id: c_int) -> c_float {
+    def_field(ai_id, def_id, |d| d.build_time)
+}
+
+unsafe extern "C" fn unit_def_get_health(ai_id: c_int, def_id: c_int) -> c_float {
+    def_field(ai_id, def_id, |d| d.health)
+}
+
+unsafe extern "C" fn unit_def_get_speed(ai_id: c_int, def_id: c_int) -> c_float {
+    def_field(ai_id, def_id, |d| d.speed)
+}
+
+unsafe extern "C" fn unit_def_get_resource_make(ai_id: c_int, def_id: c_int, resource: c_int) -> c_float {
+    def_field(ai_id, def_id, |d| d.resource_make.get(resource as usize).copied().unwrap_or(0.0))
+}
+
+unsafe extern "C" fn unit_def_get_extracts_resource(ai_id: c_int, def_id: c_int, resource: c_int) -> c_float {
+    def_field(ai_id, def_id, |d| d.extracts.get(resource as usize).copied().unwrap_or(0.0))
+}
+
+unsafe extern "C" fn unit_def_is_builder(ai_id: c_int, def_id: c_int) -> bool {
+    def_field(ai_id, def_id, |d| d.builder)
+}
+
+unsafe extern "C" fn unit_def_get_build_speed(ai_id: c_int, def_id: c_int) -> c_float {
+    def_field(ai_id, def_id, |d| d.build_speed)
+}
+
+/// Copy `ids` into an engine-style out array: a null array asks for the count.
+unsafe fn write_ids(ids: &[c_int], out: *mut c_int, max: c_int) -> c_int {
+    if out.is_null() {
+        return ids.len() as c_int;
+    }
+    let n = ids.len().min(max.max(0) as usize);
+    std::ptr::copy_nonoverlapping(ids.as_ptr(), out, n);
+    n as c_int
+}
+
+unsafe extern "C" fn get_unit_defs(ai_id: c_int, out: *mut c_int, max: c_int) -> c_int {
+    let ids: Vec<c_int> = with(ai_id, |g| (1..g.defs.len() as c_int).collect());
+    write_ids(&ids, out, max)
+}
+
+unsafe extern "C" fn unit_def_get_build_options(ai_id: c_int, def_id: c_int, out: *mut c_int, max: c_int) -> c_int {
+    let ids = def_field(ai_id, def_id, |d| d.build_options.clone());
+    write_ids(&ids, out, max)
+}
+
+unsafe extern "C" fn unit_get_def(ai_id: c_int, unit_id: c_int) -> c_int {
+    GAMES
+        .lock()
+        .unwrap()
+        .get(&ai_id)
+        .and_then(|g| g.units.get(&unit_id).map(|u| u.def_id))
+        .unwrap_or(-1)
+}
+
+unsafe extern "C" fn unit_get_max(ai_id: c_int) -> c_int {
+    with(ai_id, |g| g.max_units)
+}
+
+unsafe extern "C" fn feature_get_def(ai_id: c_int, feature_id: c_int) -> c_int {
+    GAMES.lock().unwrap().get(&ai_id).and_then(|g| g.features.get(&feature_id).copied()).unwrap_or(-1)
+}
+
+unsafe extern "C" fn unit_get_pos(ai_id: c_int, unit_id: c_int, out: *mut c_float) {
+    let pos = with(ai_id, |g| g.units.get(&unit_id).map(|u| u.pos).unwrap_or_default());
+    std::ptr::copy_nonoverlapping(pos.as_ptr(), out, 3);
+}
+
+unsafe extern "C" fn unit_get_team(ai_id: c_int, unit_id: c_int) -> c_int {
+    GAMES
+        .lock()
+        .unwrap()
+        .get(&ai_id)
+        .and_then(|g| g.units.get(&unit_id).map(|u| u.team))
+        .unwrap_or(-1)
+}
+
+fn unit_field(ai_id: c_int, unit_id: c_int, f: impl FnOnce(&FakeUnit) -> f32) -> f32 {
+    with(ai_id, |g| g.units.get(&unit_id).map(f).unwrap_or(0.0))
+}
+
+unsafe extern "C" fn unit_get_health(ai_id: c_int, unit_id: c_int) -> c_float {
+    unit_field(ai_id, unit_id, |u| u.health)
+}
+
+unsafe extern "C" fn unit_get_max_health(ai_id: c_int, unit_id: c_int) -> c_float {
+    unit_field(ai_id, unit_id, |u| u.max_health)
+}
+
+unsafe extern "C" fn unit_get_paralyze_damage(ai_id: c_int, unit_id: c_int) -> c_float {
+    unit_field(ai_id, unit_id, |u| u.paralyze_damage)
+}
+
+unsafe extern "C" fn unit_get_build_progress(ai_id: c_int, unit_id: c_int) -> c_float {
+    unit_field(ai_id, unit_id, |u| u.build_progress)
+}
+
+/// A queued command of a unit; None past the end of its queue.
+fn queued(ai_id: c_int, unit_id: c_int, index: c_int) -> Option<(c_int, Vec<f32>)> {
+    with(ai_id, |g| g.queues.get(&unit_id).and_then(|q| q.get(index as usize)).cloned())
+}
+
+unsafe extern "C" fn unit_get_current_commands(ai_id: c_int, unit_id: c_int) -> c_int {
+    with(ai_id, |g| g.queues.get(&unit_id).map_or(0, |q| q.len() as c_int))
+}
+
+unsafe extern "C" fn unit_current_command_get_id(ai_id: c_int, unit_id: c_int, index: c_int) -> c_int {
+    queued(ai_id, unit_id, index).map_or(0, |(id, _)| id)
+}
+
+unsafe extern "C" fn unit_current_command_get_params(
+    ai_id: c_int,
+    unit_id: c_int,
+    index: c_int,
+    out: *mut c_float,
+    max: c_int,
+) -> c_int {
+    let params = queued(ai_id, unit_id, index).map(|(_, params)| params).unwrap_or_default();
+    let n = params.len().min(max.max(0) as usize);
+    std::ptr::copy_nonoverlapping(params.as_ptr(), out, n);
+    n as c_int
+}
+
+unsafe extern "C" fn map_get_width(ai_id: c_int) -> c_int {
+    with(ai_id, |g| g.map_width)
+}
+
+unsafe extern "C" fn map_get_height(ai_id: c_int) -> c_int {
+    with(ai_id, |g| g.map_height)
+}
+
+unsafe extern "C" fn map_get_start_pos(ai_id: c_int, out: *mut c_float) {
+    let start = with(ai_id, |g| g.start_pos);
+    std::ptr::copy_nonoverlapping(start.as_ptr(), out, 3);
+}
+
+unsafe extern "C" fn map_get_elevation_at(ai_id: c_int, x: c_float, z: c_float) -> c_float {
+    with(ai_id, |g| (g.elevation)(x, z))
+}
+
+unsafe extern "C" fn map_is_possible_to_build_at(
+    ai_id: c_int,
+    _def_id: c_int,
+    pos: *mut c_float,
+    _facing: c_int,
+) -> bool {
+    let (x, z) = (*pos, *pos.add(2));
+    with(ai_id, |g| (g.buildable)(x, z))
+}
+
+unsafe extern "C" fn map_find_closest_build_site(
+    ai_id: c_int,
+    _def_id: c_int,
+    pos: *mut c_float,
+    _search_radius: c_float,
+    _min_dist: c_int,
+    _facing: c_int,
+    out: *mut c_float,
+) {
+    let site = with(ai_id, |g| g.build_site);
+    match site {
+        Some(site) => std::ptr::copy_nonoverlapping(site.as_ptr(), out, 3),
+        None => std::ptr::copy_nonoverlapping(pos, out, 3),
+    }
+}
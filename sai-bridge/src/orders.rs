@@ -0,0 +1,154 @@
+//! Which GameManager command each `command_finished` is for.
+//!
+//! The engine can't carry our ids: `handle_command` has to pass command id
+//! -1 (see there), so `command_finished` says only which unit finished an
+//! order. A unit works through its queue in order, though, so the bridge
+//! keeps each unit's orders as it dispatched them and takes the oldest off
+//! when one finishes. Orders the unit got elsewhere (a factory's rally
+//! point, a Lua widget) throw this off until the unit's queue is next
+//! replaced or it goes idle, which start it afresh.
+
+use std::collections::{HashMap, VecDeque};
+
+use sai_protocol::{GameCommand, GameEvent, UnitId};
+
+/// Orders remembered per unit; a longer queue forgets its oldest.
+const MAX_ORDERS_PER_UNIT: usize = 64;
+
+/// Units with tracked orders. Beyond this, new units' orders aren't
+/// tracked until others finish.
+const MAX_UNITS: usize = 1000;
+
+#[derive(Debug, Default)]
+pub struct OrderRefs {
+    /// Each unit's orders, oldest first: their `command_ref`, None for
+    /// those without one. Only units with a ref in there are kept.
+    units: HashMap<UnitId, VecDeque<Option<u64>>>,
+}
+
+impl OrderRefs {
+    /// Follow a unit's queue through a command that reached the engine.
+    pub fn observe_command(&mut self, cmd: &GameCommand, command_ref: Option<u64>) {
+        let Some((unit, queue)) = cmd.order() else { return };
+        if !queue {
+            self.units.remove(&unit);
+        }
+        // Stop empties the queue without joining it.
+        if matches!(cmd, GameCommand::Stop { .. }) {
+            return;
+        }
+        if command_ref.is_none() && !self.units.contains_key(&unit) {
+            return;
+        }
+        if !self.units.contains_key(&unit) && self.units.len() >= MAX_UNITS {
+            return;
+        }
+        // A load of several units goes out as one engine order per unit
+        // (see `commands::dispatch`); the ref goes with the last.
+        let engine_orders = match cmd {
+            GameCommand::LoadUnits { target_ids, .. } => target_ids.len().max(1),
+            _ => 1,
+        };
+        let orders = self.units.entry(unit).or_default();
+        for i in 1..=engine_orders {
+            if orders.len() >= MAX_ORDERS_PER_UNIT {
+                orders.pop_front();
+            }
+            orders.push_back(if i == engine_orders { command_ref } else { None });
+        }
+        self.prune(unit);
+    }
+
+    /// Fill in the `command_ref` of a `command_finished`, and forget units
+    /// that went idle or aren't ours any more.
+    pub fn observe(&mut self, event: &mut GameEvent) {
+        match event {
+            GameEvent::CommandFinished { unit, command_ref, .. } => {
+                *command_ref = self.units.get_mut(unit).and_then(|orders| orders.pop_front()).flatten();
+                self.prune(*unit);
+            }
+            GameEvent::UnitIdle { unit, .. }
+            | GameEvent::UnitDestroyed { unit, .. }
+            | GameEvent::UnitGiven { unit, .. }
+            | GameEvent::UnitCaptured { unit, .. } => {
+                self.units.remove(unit);
+            }
+            _ => {}
+        }
+    }
+
+    /// Stop following a unit whose queue can't be told any more, such as
+    /// a transport the engine took only part of a load from.
+    pub fn forget(&mut self, unit: UnitId) {
+        self.units.remove(&unit);
+    }
+
+    fn prune(&mut self, unit: UnitId) {
+        if self.units.get(&unit).is_some_and(|orders| orders.iter().all(Option::is_none)) {
+            self.units.remove(&unit);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn moved(queue: bool) -> GameCommand {
+        GameCommand::Move { unit_id: UnitId(10), x: 100.0, y: 0.0, z: 100.0, queue }
+    }
+
+    fn finished(orders: &mut OrderRefs) -> Option<u64> {
+        let mut event = GameEvent::CommandFinished {
+            unit: UnitId(10),
+            unit_name: None,
+            command_id: -1,
+            command_topic: 0,
+            command_ref: None,
+        };
+        orders.observe(&mut event);
+        let GameEvent::CommandFinished { command_ref, .. } = event else { unreachable!() };
+        command_ref
+    }
+
+    #[test]
+    fn test_finished_orders_in_queue_order() {
+        let mut orders = OrderRefs::default();
+        orders.observe_command(&moved(false), Some(1));
+        orders.observe_command(&moved(true), None);
+        orders.observe_command(&moved(true), Some(2));
+        assert_eq!([finished(&mut orders), finished(&mut orders), finished(&mut orders)], [Some(1), None, Some(2)]);
+        assert_eq!(finished(&mut orders), None);
+        assert!(orders.units.is_empty());
+    }
+
+    #[test]
+    fn test_load_finishes_with_its_last_unit() {
+        let mut orders = OrderRefs::default();
+        let load = GameCommand::LoadUnits { unit_id: UnitId(10), target_ids: vec![UnitId(12), UnitId(13)], queue: false };
+        orders.observe_command(&load, Some(1));
+        orders.observe_command(&moved(true), Some(2));
+        assert_eq!([finished(&mut orders), finished(&mut orders), finished(&mut orders)], [None, Some(1), Some(2)]);
+    }
+
+    #[test]
+    fn test_replaced_queue_starts_afresh() {
+        let mut orders = OrderRefs::default();
+        orders.observe_command(&moved(false), Some(1));
+        orders.observe_command(&moved(false), Some(2));
+        assert_eq!(finished(&mut orders), Some(2));
+
+        orders.observe_command(&moved(false), Some(3));
+        orders.observe_command(&GameCommand::Stop { unit_id: UnitId(10) }, Some(4));
+        assert_eq!(finished(&mut orders), None);
+
+        orders.observe_command(&moved(false), Some(5));
+        orders.observe(&mut GameEvent::UnitIdle { unit: UnitId(10), unit_name: None });
+        assert_eq!(finished(&mut orders), None);
+
+        // Untracked orders of a unit with none tracked aren't kept.
+        orders.observe_command(&moved(false), None);
+        orders.observe_command(&GameCommand::SetFireState { unit_id: UnitId(10), state: 2 }, Some(6));
+        assert!(orders.units.is_empty());
+    }
+}
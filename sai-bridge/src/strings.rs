@@ -0,0 +1,122 @@
+//! Text crossing into and out of the engine.
+//!
+//! The engine takes C strings, which can't hold a NUL, and sends chat and
+//! map draws in packets whose size fits in a byte. Text going in has its
+//! NULs dropped and is cut to fit rather than failing the command; the
+//! caller logs what changed. Text coming out isn't always UTF-8 (old maps,
+//! widgets sending raw bytes): it is converted lossily, and events say so.
+
+use std::ffi::{c_char, CStr, CString};
+
+/// Most bytes of chat text, after the `/say` and destination prefix.
+pub const MAX_CHAT_BYTES: usize = 200;
+
+/// Most bytes of a map point's label.
+pub const MAX_LABEL_BYTES: usize = 200;
+
+/// Text made fit for the engine.
+#[derive(Debug)]
+pub struct CText {
+    pub c_string: CString,
+    /// NULs dropped.
+    pub nuls: usize,
+    /// The length in bytes before it was cut, if it was.
+    pub truncated_from: Option<usize>,
+}
+
+impl CText {
+    /// What was changed, for the log; None if nothing was.
+    pub fn warning(&self, what: &str) -> Option<String> {
+        let mut changes = Vec::new();
+        if self.nuls > 0 {
+            changes.push(format!("dropped {} NUL byte{}", self.nuls, if self.nuls == 1 { "" } else { "s" }));
+        }
+        if let Some(len) = self.truncated_from {
+            changes.push(format!("cut from {} to {} bytes", len, self.c_string.as_bytes().len()));
+        }
+        (!changes.is_empty()).then(|| format!("{}: {}", what, changes.join(", ")))
+    }
+}
+
+/// `text` without NULs, cut at a character boundary to at most `max_bytes`.
+pub fn to_c(text: &str, max_bytes: usize) -> CText {
+    let nuls = text.matches('\0').count();
+    let mut clean = if nuls > 0 { text.replace('\0', "") } else { text.to_string() };
+    let mut truncated_from = None;
+    if clean.len() > max_bytes {
+        truncated_from = Some(clean.len());
+        let mut end = max_bytes;
+        while !clean.is_char_boundary(end) {
+            end -= 1;
+        }
+        clean.truncate(end);
+    }
+    let c_string = CString::new(clean).expect("NULs were dropped");
+    CText { c_string, nuls, truncated_from }
+}
+
+/// A name to look up as is: None if it has a NUL, as no engine name does.
+pub fn name(text: &str) -> Option<CString> {
+    CString::new(text).ok()
+}
+
+/// A C string from the engine, and whether it wasn't valid UTF-8 (invalid
+/// sequences become U+FFFD). None for a null pointer.
+///
+/// # Safety
+/// `ptr` must be null or point to a NUL-terminated string.
+pub unsafe fn from_c(ptr: *const c_char) -> Option<(String, bool)> {
+    if ptr.is_null() {
+        return None;
+    }
+    let c_str = CStr::from_ptr(ptr);
+    Some(match c_str.to_str() {
+        Ok(s) => (s.to_string(), false),
+        Err(_) => (c_str.to_string_lossy().into_owned(), true),
+    })
+}
+
+/// Like [`from_c`], for text where a lossy conversion isn't worth noting
+/// (names, versions, the setup script).
+///
+/// # Safety
+/// `ptr` must be null or point to a NUL-terminated string.
+pub unsafe fn from_c_lossy(ptr: *const c_char) -> Option<String> {
+    from_c(ptr).map(|(s, _)| s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nuls_dropped_and_long_text_cut() {
+        let plain = to_c("gl hf", MAX_CHAT_BYTES);
+        assert_eq!((plain.c_string.to_str().unwrap(), plain.warning("chat")), ("gl hf", None));
+
+        let nuls = to_c("gl\0 hf\0", MAX_CHAT_BYTES);
+        assert_eq!(nuls.c_string.to_str().unwrap(), "gl hf");
+        assert_eq!(nuls.warning("chat").unwrap(), "chat: dropped 2 NUL bytes");
+
+        let long = to_c(&"a".repeat(10_000), MAX_CHAT_BYTES);
+        assert_eq!(long.c_string.as_bytes().len(), MAX_CHAT_BYTES);
+        assert_eq!(long.warning("chat").unwrap(), "chat: cut from 10000 to 200 bytes");
+
+        // Never inside a character: "é" is two bytes.
+        let accents = to_c(&"é".repeat(150), 201);
+        assert_eq!(accents.c_string.to_str().unwrap(), "é".repeat(100));
+
+        assert!(name("cloakraid").is_some());
+        assert!(name("cloak\0raid").is_none());
+    }
+
+    #[test]
+    fn test_invalid_utf8_flagged() {
+        let valid = CString::new("mex_claimed 3").unwrap();
+        assert_eq!(unsafe { from_c(valid.as_ptr()) }, Some(("mex_claimed 3".to_string(), false)));
+        let latin1 = CString::new(b"caf\xe9 \xff".to_vec()).unwrap();
+        assert_eq!(unsafe { from_c(latin1.as_ptr()) }, Some(("caf\u{fffd} \u{fffd}".to_string(), true)));
+        assert_eq!(unsafe { from_c_lossy(latin1.as_ptr()) }.unwrap(), "caf\u{fffd} \u{fffd}");
+        assert_eq!(unsafe { from_c(std::ptr::null()) }, None);
+    }
+}
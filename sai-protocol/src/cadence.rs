@@ -0,0 +1,88 @@
+//! How often the bridge sends `update` events: connection.json
+//! `update_cadence`, written by the GameManager from its config.
+
+use serde::{Deserialize, Serialize};
+
+/// How the bridge picks the frames between updates.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UpdateMode {
+    /// The bridge's fixed interval (30 frames, 900 in benchmarks).
+    #[default]
+    Fixed,
+    /// Shorter intervals while fighting, longer ones while it's quiet.
+    Adaptive,
+}
+
+/// Update cadence settings. Rates count `unit_damaged`, `unit_destroyed`,
+/// `enemy_damaged` and `enemy_destroyed` events per game second.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UpdateCadence {
+    #[serde(default)]
+    pub mode: UpdateMode,
+    /// Shortest adaptive interval, in frames.
+    #[serde(default = "default_min_frames")]
+    pub min_frames: u32,
+    /// Longest adaptive interval, in frames.
+    #[serde(default = "default_max_frames")]
+    pub max_frames: u32,
+    /// At or above this rate the interval halves.
+    #[serde(default = "default_busy_rate")]
+    pub busy_rate: f32,
+    /// At or below this rate, for [`QUIET_UPDATES`] updates in a row, the
+    /// interval grows by half.
+    #[serde(default = "default_quiet_rate")]
+    pub quiet_rate: f32,
+}
+
+/// Quiet updates in a row before an adaptive interval grows.
+pub const QUIET_UPDATES: u32 = 3;
+
+/// Longest interval `set_update_interval` can pin: five game minutes.
+pub const MAX_UPDATE_INTERVAL: u32 = 9000;
+
+fn default_min_frames() -> u32 {
+    10
+}
+
+fn default_max_frames() -> u32 {
+    90
+}
+
+fn default_busy_rate() -> f32 {
+    4.0
+}
+
+fn default_quiet_rate() -> f32 {
+    0.5
+}
+
+impl Default for UpdateCadence {
+    fn default() -> Self {
+        Self {
+            mode: UpdateMode::default(),
+            min_frames: default_min_frames(),
+            max_frames: default_max_frames(),
+            busy_rate: default_busy_rate(),
+            quiet_rate: default_quiet_rate(),
+        }
+    }
+}
+
+impl UpdateCadence {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.min_frames == 0 || self.min_frames > self.max_frames || self.max_frames > MAX_UPDATE_INTERVAL {
+            return Err(format!(
+                "update_cadence: min_frames and max_frames must satisfy 1 <= min_frames <= max_frames <= {}",
+                MAX_UPDATE_INTERVAL
+            ));
+        }
+        // The gap between the two rates is the hysteresis: a rate between
+        // them keeps the interval where it is.
+        if !(self.quiet_rate >= 0.0 && self.quiet_rate < self.busy_rate) {
+            return Err("update_cadence: quiet_rate must be at least 0 and below busy_rate".into());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,265 @@
+//! Bridge-side Unix socket client to GameManager.
+//!
+//! No async runtime — this runs inside the engine's thread.
+//! Uses non-blocking mode with temporary blocking for writes.
+//!
+//! Note: `UnixStream::try_clone()` creates a new FD pointing to the same
+//! socket description. `set_nonblocking()` operates on the description, not
+//! the FD — so setting blocking on one clone affects the other. We use a
+//! single stream and toggle between blocking/non-blocking as needed.
+
+use crate::{DryRun, Envelope, GameCommand, GameEvent};
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+
+/// IPC connection to GameManager via Unix socket.
+pub struct IpcClient {
+    stream: UnixStream,
+    reader: BufReader<UnixStream>,
+    read_buf: String,
+    /// Problems seen while polling, for the caller to log.
+    errors: Vec<String>,
+    /// Dry runs seen while polling, for the caller to validate.
+    dry_runs: Vec<DryRun>,
+    /// Outbound buffer for events that couldn't be written immediately.
+    write_buf: Vec<u8>,
+    /// Set once a poll reads EOF: the GameManager hung up.
+    closed: bool,
+    /// Events passed to `send_event`, and those of them since dropped
+    /// from a full write buffer.
+    queued: u64,
+    dropped: u64,
+}
+
+impl IpcClient {
+    /// Connect to the GameManager's Unix socket.
+    pub fn connect(path: &str) -> io::Result<Self> {
+        Self::from_stream(UnixStream::connect(path)?)
+    }
+
+    /// Wrap an already-connected stream (e.g. one end of a socketpair).
+    pub fn from_stream(stream: UnixStream) -> io::Result<Self> {
+        let reader_stream = stream.try_clone()?;
+
+        // Start in non-blocking mode (poll_commands is called every frame)
+        stream.set_nonblocking(true)?;
+
+        Ok(Self {
+            stream,
+            reader: BufReader::new(reader_stream),
+            read_buf: String::new(),
+            errors: Vec::new(),
+            dry_runs: Vec::new(),
+            write_buf: Vec::new(),
+            closed: false,
+            queued: 0,
+            dropped: 0,
+        })
+    }
+
+    /// Send a game event to GameManager (non-blocking).
+    /// Appends to an internal buffer and drains as much as the socket will accept.
+    /// Never blocks the engine thread — drops oldest data if buffer exceeds 256KB.
+    pub fn send_event(&mut self, event: &GameEvent) -> io::Result<()> {
+        let json = serde_json::to_string(event).map_err(|e| io::Error::other(e.to_string()))?;
+        self.write_buf.extend_from_slice(json.as_bytes());
+        self.write_buf.push(b'\n');
+        self.queued += 1;
+
+        // Cap buffer at 1MB — if downstream is that far behind, drop oldest data
+        const MAX_BUF: usize = 1024 * 1024;
+        if self.write_buf.len() > MAX_BUF {
+            let drop = self.write_buf.len() - MAX_BUF;
+            self.dropped += self.write_buf.drain(..drop).filter(|&b| b == b'\n').count() as u64;
+        }
+
+        self.flush_write_buf();
+        Ok(())
+    }
+
+    /// Try to drain the write buffer without blocking.
+    /// Called from send_event and poll_commands.
+    fn flush_write_buf(&mut self) {
+        while !self.write_buf.is_empty() {
+            match self.stream.write(&self.write_buf) {
+                Ok(0) => break, // socket closed
+                Ok(n) => {
+                    self.write_buf.drain(..n);
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(_) => break,
+            }
+        }
+    }
+
+    /// Poll for commands from GameManager (non-blocking).
+    /// Returns any complete commands received since last poll; dry runs
+    /// are set aside for `take_dry_runs`.
+    /// Also drains the outbound write buffer.
+    pub fn poll_commands(&mut self) -> Vec<GameCommand> {
+        self.poll_tracked_commands().into_iter().map(|(cmd, _)| cmd).collect()
+    }
+
+    /// Like `poll_commands`, with each command's `command_ref` if it had
+    /// one (see [`crate::Tracked`]).
+    pub fn poll_tracked_commands(&mut self) -> Vec<(GameCommand, Option<u64>)> {
+        // Opportunistically flush pending writes
+        self.flush_write_buf();
+
+        let mut commands = Vec::new();
+
+        loop {
+            self.read_buf.clear();
+            match self.reader.read_line(&mut self.read_buf) {
+                Ok(0) => {
+                    self.closed = true;
+                    break;
+                }
+                Ok(_) => {
+                    let trimmed = self.read_buf.trim();
+                    if trimmed.is_empty() {
+                        continue;
+                    }
+                    match GameCommand::from_envelope(trimmed) {
+                        Ok((command, Envelope { dry_run: Some(request_id), .. })) => {
+                            self.dry_runs.push(DryRun { request_id, command })
+                        }
+                        Ok((cmd, Envelope { command_ref, .. })) => commands.push((cmd, command_ref)),
+                        Err(e) => self
+                            .errors
+                            .push(format!("Failed to parse command: {} — {:?}", e, trimmed)),
+                    }
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    self.errors.push(format!("IPC read error: {}", e));
+                    break;
+                }
+            }
+        }
+
+        commands
+    }
+
+    /// Drain the errors collected by `poll_commands` (unparseable lines,
+    /// read failures). The client has no logger of its own.
+    pub fn take_errors(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.errors)
+    }
+
+    /// Drain the dry runs collected by `poll_commands`. None of them may be
+    /// executed: the GameManager only wants to know whether they would be.
+    pub fn take_dry_runs(&mut self) -> Vec<DryRun> {
+        std::mem::take(&mut self.dry_runs)
+    }
+
+    /// Bytes queued for the GameManager but not yet accepted by the socket.
+    pub fn pending_bytes(&self) -> usize {
+        self.write_buf.len()
+    }
+
+    /// Events sent so far, and events dropped whole from a full write
+    /// buffer (never sent).
+    pub fn event_counts(&self) -> (u64, u64) {
+        (self.queued - self.dropped, self.dropped)
+    }
+
+    /// Check if the connection is still alive. A hang-up is only noticed
+    /// by `poll_commands`.
+    pub fn is_connected(&self) -> bool {
+        !self.closed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::UnitId;
+    use std::io::Read;
+
+    fn pair() -> (IpcClient, UnixStream) {
+        let (bridge, gm) = UnixStream::pair().unwrap();
+        (IpcClient::from_stream(bridge).unwrap(), gm)
+    }
+
+    #[test]
+    fn test_events_are_json_lines() {
+        let (mut client, gm) = pair();
+        client.send_event(&GameEvent::Update { frame: 30, awaiting_commands: false, economy: None, allies: Vec::new(), counters: Default::default(), command_backlog: 0, under_construction: Vec::new(), update_interval: None }).unwrap();
+        client.send_event(&GameEvent::Release { reason: 0, stats: None }).unwrap();
+        assert_eq!(client.pending_bytes(), 0);
+
+        let mut lines = BufReader::new(gm).lines();
+        assert_eq!(lines.next().unwrap().unwrap(), r#"{"type":"update","frame":30}"#);
+        assert_eq!(lines.next().unwrap().unwrap(), r#"{"type":"release","reason":0}"#);
+    }
+
+    #[test]
+    fn test_poll_commands_skips_bad_lines() {
+        let (mut client, mut gm) = pair();
+        assert!(client.poll_commands().is_empty());
+
+        gm.write_all(b"{\"type\":\"stop\",\"unit_id\":1}\n\nnot json\n{\"type\":\"teleport\",\"unit_id\":2}\n")
+            .unwrap();
+        let cmds = client.poll_commands();
+        assert_eq!(cmds.len(), 2);
+        assert!(matches!(cmds[0], GameCommand::Stop { unit_id: UnitId(1) }));
+        assert_eq!(cmds[1].type_name(), "teleport");
+        let errors = client.take_errors();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("Failed to parse command"), "{}", errors[0]);
+        assert!(client.take_errors().is_empty());
+
+        let dry_run = DryRun { request_id: 3, command: GameCommand::Stop { unit_id: UnitId(5) } };
+        gm.write_all(format!("{}\n", dry_run.to_line()).as_bytes()).unwrap();
+        assert!(client.poll_commands().is_empty(), "dry runs are never returned for dispatch");
+        assert_eq!(client.take_dry_runs(), [dry_run]);
+    }
+
+    #[test]
+    fn test_buffers_when_gm_is_slow_and_drains_later() {
+        let (mut client, mut gm) = pair();
+        let big = GameEvent::LuaMessage { data: "x".repeat(64 * 1024), data_lossy: false };
+
+        // The GameManager isn't reading: sends must not block, the
+        // overflow stays queued, capped at 1MB.
+        for _ in 0..40 {
+            client.send_event(&big).unwrap();
+        }
+        let pending = client.pending_bytes();
+        assert!(pending > 0);
+        assert!(pending <= 1024 * 1024);
+        let (sent, dropped) = client.event_counts();
+        assert!(dropped > 0);
+        assert_eq!(sent + dropped, 40);
+
+        // Once the GameManager reads, polling flushes the backlog.
+        gm.set_nonblocking(true).unwrap();
+        let mut received = 0usize;
+        let mut buf = vec![0u8; 256 * 1024];
+        for _ in 0..1000 {
+            client.poll_commands();
+            match gm.read(&mut buf) {
+                Ok(n) => received += n,
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => panic!("{}", e),
+            }
+            if client.pending_bytes() == 0 {
+                break;
+            }
+        }
+        assert_eq!(client.pending_bytes(), 0);
+        assert!(received > 0);
+    }
+
+    #[test]
+    fn test_gm_disconnect_is_not_fatal() {
+        let (mut client, gm) = pair();
+        assert!(client.is_connected());
+        drop(gm);
+        assert!(client.poll_commands().is_empty());
+        assert!(!client.is_connected());
+        // Writes to a closed peer are dropped rather than panicking
+        let _ = client.send_event(&GameEvent::Update { frame: 1, awaiting_commands: false, economy: None, allies: Vec::new(), counters: Default::default(), command_backlog: 0, under_construction: Vec::new(), update_interval: None });
+    }
+}
@@ -0,0 +1,446 @@
+//! Commands sent from the GameManager to the SAI bridge.
+
+use serde::{Deserialize, Serialize};
+
+use crate::cadence::UpdateMode;
+use crate::ids::{FeatureId, UnitDefId, UnitId};
+
+/// Who sees a chat message sent with [`GameCommand::SendChat`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChatDestination {
+    #[default]
+    All,
+    Allies,
+    Spectators,
+}
+
+impl ChatDestination {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "all" => Some(ChatDestination::All),
+            "allies" => Some(ChatDestination::Allies),
+            "spectators" => Some(ChatDestination::Spectators),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ChatDestination::All => "all",
+            ChatDestination::Allies => "allies",
+            ChatDestination::Spectators => "spectators",
+        }
+    }
+}
+
+/// What a [`GameCommand::Reclaim`] takes apart. On the wire it is the
+/// command's `target_id` for a unit or `feature_id` for a feature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ReclaimTarget {
+    #[serde(rename = "target_id")]
+    Unit(UnitId),
+    #[serde(rename = "feature_id")]
+    Feature(FeatureId),
+}
+
+/// A command sent by the GameManager to the SAI bridge.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum GameCommand {
+    #[serde(rename = "move")]
+    Move {
+        unit_id: UnitId,
+        x: f32,
+        #[serde(default)]
+        y: f32,
+        z: f32,
+        #[serde(default)]
+        queue: bool,
+    },
+    #[serde(rename = "stop")]
+    Stop { unit_id: UnitId },
+    /// Fire the unit's manual-fire weapon (a commander's D-gun) at a unit,
+    /// or at a spot on the ground when there's no `target_id`. With both,
+    /// the unit is the target.
+    #[serde(rename = "manual_fire")]
+    ManualFire {
+        unit_id: UnitId,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        target_id: Option<UnitId>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        x: Option<f32>,
+        #[serde(default)]
+        y: f32,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        z: Option<f32>,
+        #[serde(default)]
+        queue: bool,
+    },
+    /// Hold the unit's queue until another wait lifts it. Unqueued it
+    /// goes in front of the queue, or lifts a wait already there; queued
+    /// it waits once the orders before it are done.
+    #[serde(rename = "wait")]
+    Wait {
+        unit_id: UnitId,
+        #[serde(default)]
+        queue: bool,
+    },
+    /// Hold the unit's queue for `seconds` of game time, then go on.
+    #[serde(rename = "time_wait")]
+    TimeWait {
+        unit_id: UnitId,
+        seconds: u32,
+        #[serde(default)]
+        queue: bool,
+    },
+    #[serde(rename = "attack")]
+    Attack {
+        unit_id: UnitId,
+        target_id: UnitId,
+        #[serde(default)]
+        queue: bool,
+    },
+    /// Fire at a spot on the ground rather than a unit: artillery
+    /// shelling a position.
+    #[serde(rename = "attack_ground")]
+    AttackGround {
+        unit_id: UnitId,
+        x: f32,
+        #[serde(default)]
+        y: f32,
+        z: f32,
+        #[serde(default)]
+        queue: bool,
+    },
+    /// Attack whatever is within `radius` elmos of a point.
+    #[serde(rename = "attack_area")]
+    AttackArea {
+        unit_id: UnitId,
+        x: f32,
+        #[serde(default)]
+        y: f32,
+        z: f32,
+        radius: f32,
+        #[serde(default)]
+        queue: bool,
+    },
+    #[serde(rename = "build")]
+    Build {
+        unit_id: UnitId,
+        #[serde(default)]
+        build_def_id: UnitDefId,
+        #[serde(default)]
+        build_def_name: Option<String>,
+        #[serde(default)]
+        x: f32,
+        #[serde(default)]
+        y: f32,
+        #[serde(default)]
+        z: f32,
+        #[serde(default)]
+        facing: i32,
+        #[serde(default)]
+        queue: bool,
+    },
+    #[serde(rename = "patrol")]
+    Patrol {
+        unit_id: UnitId,
+        x: f32,
+        #[serde(default)]
+        y: f32,
+        z: f32,
+        #[serde(default)]
+        queue: bool,
+    },
+    #[serde(rename = "fight")]
+    Fight {
+        unit_id: UnitId,
+        x: f32,
+        #[serde(default)]
+        y: f32,
+        z: f32,
+        #[serde(default)]
+        queue: bool,
+    },
+    #[serde(rename = "guard")]
+    Guard {
+        unit_id: UnitId,
+        guard_id: UnitId,
+        #[serde(default)]
+        queue: bool,
+    },
+    #[serde(rename = "repair")]
+    Repair {
+        unit_id: UnitId,
+        repair_id: UnitId,
+        #[serde(default)]
+        queue: bool,
+    },
+    /// Reclaim one unit, such as one of ours to recycle, or one feature,
+    /// such as a wreck.
+    #[serde(rename = "reclaim")]
+    Reclaim {
+        unit_id: UnitId,
+        #[serde(flatten)]
+        target: ReclaimTarget,
+        #[serde(default)]
+        queue: bool,
+    },
+    /// Reclaim everything reclaimable, wrecks and trees included, within
+    /// `radius` elmos of a point.
+    #[serde(rename = "reclaim_area")]
+    ReclaimArea {
+        unit_id: UnitId,
+        x: f32,
+        #[serde(default)]
+        y: f32,
+        z: f32,
+        radius: f32,
+        #[serde(default)]
+        queue: bool,
+    },
+    /// Have a transport pick up `target_ids`, in order.
+    #[serde(rename = "load_units")]
+    LoadUnits {
+        unit_id: UnitId,
+        target_ids: Vec<UnitId>,
+        #[serde(default)]
+        queue: bool,
+    },
+    /// Have a unit board `transport_id`.
+    #[serde(rename = "load_onto_transport")]
+    LoadOntoTransport { unit_id: UnitId, transport_id: UnitId },
+    /// Have a transport set its cargo down within `radius` elmos of a
+    /// point, spread out.
+    #[serde(rename = "unload_units")]
+    UnloadUnits {
+        unit_id: UnitId,
+        x: f32,
+        #[serde(default)]
+        y: f32,
+        z: f32,
+        radius: f32,
+        #[serde(default)]
+        queue: bool,
+    },
+    /// Start the unit's self-destruct countdown; sent again while it
+    /// counts down, it calls it off. Not an order: the queue is left be.
+    #[serde(rename = "self_destruct")]
+    SelfDestruct { unit_id: UnitId },
+    #[serde(rename = "set_fire_state")]
+    SetFireState { unit_id: UnitId, state: i32 },
+    #[serde(rename = "set_move_state")]
+    SetMoveState { unit_id: UnitId, state: i32 },
+    /// A command the game defines itself (Lua custom commands such as
+    /// Zero-K's morph), by its numeric id and raw params.
+    #[serde(rename = "custom")]
+    Custom {
+        unit_id: UnitId,
+        command_id: i32,
+        #[serde(default)]
+        params: Vec<f32>,
+        #[serde(default)]
+        queue: bool,
+    },
+    #[serde(rename = "send_chat")]
+    SendChat {
+        text: String,
+        #[serde(default)]
+        destination: ChatDestination,
+    },
+    /// Place a map marker (a point with a label) visible to allies.
+    #[serde(rename = "draw_point")]
+    DrawPoint {
+        x: f32,
+        z: f32,
+        #[serde(default)]
+        label: String,
+    },
+    #[serde(rename = "pause")]
+    Pause,
+    #[serde(rename = "unpause")]
+    Unpause,
+    #[serde(rename = "set_speed")]
+    SetSpeed { speed: f32 },
+    /// Lockstep play: pause the engine at every throttled update until
+    /// `end_turn` arrives.
+    #[serde(rename = "set_turn_mode")]
+    SetTurnMode { enabled: bool },
+    /// Pin the frames between updates, overriding adaptation, or with
+    /// `frames: None` clear the pin. `mode` forces fixed or adaptive
+    /// updates; None keeps the mode connection.json set.
+    #[serde(rename = "set_update_interval")]
+    SetUpdateInterval {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        frames: Option<u32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        mode: Option<UpdateMode>,
+    },
+    /// Resume a turn-mode pause until the next update interval.
+    #[serde(rename = "end_turn")]
+    EndTurn,
+    /// List every unit def. Not an order: the bridge answers with
+    /// `unit_defs` events, a chunk of defs per line.
+    #[serde(rename = "query_unit_defs")]
+    QueryUnitDefs { request_id: u64 },
+    /// Sample the map on a grid of `cell_size`-elmo cells: ground height at
+    /// each cell centre and, with `build_def`, whether that def can be built
+    /// there. Not an order: the bridge answers with `map_grid` events, a
+    /// band of rows per line. The bridge coarsens `cell_size` so neither
+    /// side of the grid exceeds [`crate::MAX_MAP_GRID_CELLS`].
+    #[serde(rename = "query_map_grid")]
+    QueryMapGrid {
+        request_id: u64,
+        cell_size: f32,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        build_def: Option<String>,
+    },
+    /// Read the command queues of `units`. Not an order: the bridge
+    /// answers with one `unit_queues` event.
+    #[serde(rename = "query_unit_queues")]
+    QueryUnitQueues { request_id: u64, units: Vec<UnitId> },
+    /// A command whose `type` this build doesn't know (newer GameManager).
+    /// Never produced by deserialization directly — see [`GameCommand::from_line`].
+    #[serde(rename = "unknown", skip_deserializing)]
+    Unknown { raw: serde_json::Value },
+}
+
+impl GameCommand {
+    /// The unit an order is for and whether it was queued. Orders go in
+    /// the unit's command queue: one that isn't queued replaces the queue,
+    /// superseding the orders in it. State changes, chat and the rest
+    /// aren't orders, and neither is an unqueued wait, which goes in front
+    /// of the queue and replaces nothing.
+    pub fn order(&self) -> Option<(UnitId, bool)> {
+        match self {
+            GameCommand::Move { unit_id, queue, .. }
+            | GameCommand::Attack { unit_id, queue, .. }
+            | GameCommand::AttackGround { unit_id, queue, .. }
+            | GameCommand::AttackArea { unit_id, queue, .. }
+            | GameCommand::ManualFire { unit_id, queue, .. }
+            | GameCommand::Build { unit_id, queue, .. }
+            | GameCommand::Patrol { unit_id, queue, .. }
+            | GameCommand::Fight { unit_id, queue, .. }
+            | GameCommand::Guard { unit_id, queue, .. }
+            | GameCommand::Repair { unit_id, queue, .. }
+            | GameCommand::Reclaim { unit_id, queue, .. }
+            | GameCommand::ReclaimArea { unit_id, queue, .. }
+            | GameCommand::LoadUnits { unit_id, queue, .. }
+            | GameCommand::UnloadUnits { unit_id, queue, .. }
+            | GameCommand::Custom { unit_id, queue, .. } => Some((*unit_id, *queue)),
+            GameCommand::Stop { unit_id } | GameCommand::LoadOntoTransport { unit_id, .. } => Some((*unit_id, false)),
+            GameCommand::Wait { unit_id, queue: true } | GameCommand::TimeWait { unit_id, queue: true, .. } => {
+                Some((*unit_id, true))
+            }
+            _ => None,
+        }
+    }
+
+    /// The wire `type` tag of this command (the raw tag for unknown commands).
+    pub fn type_name(&self) -> &str {
+        match self {
+            GameCommand::Move { .. } => "move",
+            GameCommand::Stop { .. } => "stop",
+            GameCommand::Wait { .. } => "wait",
+            GameCommand::TimeWait { .. } => "time_wait",
+            GameCommand::Attack { .. } => "attack",
+            GameCommand::AttackGround { .. } => "attack_ground",
+            GameCommand::AttackArea { .. } => "attack_area",
+            GameCommand::ManualFire { .. } => "manual_fire",
+            GameCommand::Build { .. } => "build",
+            GameCommand::Patrol { .. } => "patrol",
+            GameCommand::Fight { .. } => "fight",
+            GameCommand::Guard { .. } => "guard",
+            GameCommand::Repair { .. } => "repair",
+            GameCommand::Reclaim { .. } => "reclaim",
+            GameCommand::ReclaimArea { .. } => "reclaim_area",
+            GameCommand::LoadUnits { .. } => "load_units",
+            GameCommand::LoadOntoTransport { .. } => "load_onto_transport",
+            GameCommand::UnloadUnits { .. } => "unload_units",
+            GameCommand::SelfDestruct { .. } => "self_destruct",
+            GameCommand::SetFireState { .. } => "set_fire_state",
+            GameCommand::SetMoveState { .. } => "set_move_state",
+            GameCommand::Custom { .. } => "custom",
+            GameCommand::SendChat { .. } => "send_chat",
+            GameCommand::DrawPoint { .. } => "draw_point",
+            GameCommand::Pause => "pause",
+            GameCommand::Unpause => "unpause",
+            GameCommand::SetSpeed { .. } => "set_speed",
+            GameCommand::SetTurnMode { .. } => "set_turn_mode",
+            GameCommand::SetUpdateInterval { .. } => "set_update_interval",
+            GameCommand::EndTurn => "end_turn",
+            GameCommand::QueryUnitDefs { .. } => "query_unit_defs",
+            GameCommand::QueryMapGrid { .. } => "query_map_grid",
+            GameCommand::QueryUnitQueues { .. } => "query_unit_queues",
+            GameCommand::Unknown { raw } => raw.get("type").and_then(|t| t.as_str()).unwrap_or("unknown"),
+        }
+    }
+
+    /// Parse one IPC line. Well-formed lines with an unrecognized `type`
+    /// become [`GameCommand::Unknown`] so the bridge can report them back.
+    pub fn from_line(line: &str) -> Result<Self, serde_json::Error> {
+        Ok(Self::from_envelope(line)?.0)
+    }
+
+    /// Parse one IPC line along with its envelope.
+    pub fn from_envelope(line: &str) -> Result<(Self, Envelope), serde_json::Error> {
+        let raw: serde_json::Value = serde_json::from_str(line)?;
+        let envelope = Envelope {
+            dry_run: (raw.get("dry_run") == Some(&serde_json::Value::Bool(true)))
+                .then(|| raw.get("request_id").and_then(|v| v.as_u64()).unwrap_or(0)),
+            command_ref: raw.get("command_ref").and_then(|v| v.as_u64()),
+        };
+        let cmd = crate::parse_tagged(raw, |raw| GameCommand::Unknown { raw })?;
+        Ok((cmd, envelope))
+    }
+}
+
+/// What a command line carries besides the command.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Envelope {
+    /// The request id when the line is a dry run (see [`DryRun`]).
+    pub dry_run: Option<u64>,
+    /// The GameManager's id for the command (see [`Tracked`]).
+    pub command_ref: Option<u64>,
+}
+
+/// A command the GameManager wants to hear back about. On the wire it is
+/// the command's own JSON plus a `command_ref`, which the bridge echoes in
+/// the `command_error` the command fails with, or in the engine's
+/// `command_finished` for it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tracked {
+    pub command_ref: u64,
+    pub command: GameCommand,
+}
+
+impl Tracked {
+    pub fn to_line(&self) -> String {
+        let mut line = serde_json::to_value(&self.command).unwrap_or_default();
+        if let Some(fields) = line.as_object_mut() {
+            fields.insert("command_ref".into(), self.command_ref.into());
+        }
+        line.to_string()
+    }
+}
+
+/// A command to validate without executing. On the wire it is the
+/// command's own JSON plus `dry_run: true` and a `request_id`, which the
+/// bridge answers with a `dry_run_result` event.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DryRun {
+    pub request_id: u64,
+    pub command: GameCommand,
+}
+
+impl DryRun {
+    pub fn to_line(&self) -> String {
+        let mut line = serde_json::to_value(&self.command).unwrap_or_default();
+        if let Some(fields) = line.as_object_mut() {
+            fields.insert("dry_run".into(), true.into());
+            fields.insert("request_id".into(), self.request_id.into());
+        }
+        line.to_string()
+    }
+}
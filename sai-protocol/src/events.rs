@@ -0,0 +1,685 @@
+//! Events sent from the SAI bridge to the GameManager.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::ids::{TeamId, UnitDefId, UnitId, WeaponDefId};
+
+fn is_zero(n: &usize) -> bool {
+    *n == 0
+}
+
+/// A metal spot read from the map's GameRulesParams.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MetalSpot {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub metal: f32,
+}
+
+/// Event counts by unit id, then event type. Unit ids are strings, as
+/// JSON object keys are; internally tagged events can't parse them back
+/// into integers.
+pub type UnitCounters = BTreeMap<String, BTreeMap<String, u32>>;
+
+/// Whose a unit is, from the AI's point of view. Enemy events carry the
+/// enemy's `team` and `relation`, damage and destruction events the
+/// attacker's; both are absent when the engine didn't know the unit's
+/// team (out of sight, or already gone).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Relation {
+    /// The AI's own team.
+    Mine,
+    /// Another team of the AI's ally team.
+    Ally,
+    Enemy,
+    /// The neutral gaia team: features, critters, map-placed units.
+    Gaia,
+}
+
+impl Relation {
+    /// How a unit of this relation is introduced in prose ("Allied Ripper").
+    pub fn adjective(self) -> &'static str {
+        match self {
+            Relation::Mine => "your",
+            Relation::Ally => "allied",
+            Relation::Enemy => "enemy",
+            Relation::Gaia => "neutral",
+        }
+    }
+}
+
+/// A team in the game and whose it is, as listed in [`GameEvent::Init`].
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TeamSlot {
+    pub team: TeamId,
+    pub ally_team: i32,
+    pub relation: Relation,
+}
+
+/// Which bridge build runs a game, and in which engine, as sent in
+/// [`GameEvent::Init`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BuildInfo {
+    /// The sai-bridge crate version.
+    pub bridge_version: String,
+    /// Commit the bridge was built from; None outside a git checkout.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git_hash: Option<String>,
+    /// The engine's version string, e.g. `105.1.1-2511-g747f18b`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub engine_version: Option<String>,
+}
+
+impl BuildInfo {
+    /// `0.1.0 (abc1234), engine 105.1.1-2511-g747f18b`.
+    pub fn describe(&self) -> String {
+        let mut text = self.bridge_version.clone();
+        if let Some(hash) = &self.git_hash {
+            text += &format!(" ({})", hash);
+        }
+        if let Some(engine) = &self.engine_version {
+            text += &format!(", engine {}", engine);
+        }
+        text
+    }
+}
+
+/// One of the AI's own units, as listed in a [`GameEvent::Roster`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RosterUnit {
+    pub unit: UnitId,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unit_name: Option<String>,
+    pub pos: [f32; 3],
+}
+
+/// One unit's command queue, as listed in [`GameEvent::UnitQueues`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UnitQueue {
+    pub unit: UnitId,
+    /// Oldest first. A factory's queue holds its build orders.
+    pub commands: Vec<QueuedCommand>,
+}
+
+/// A command waiting in a unit's queue: the engine's command id, negative
+/// for building the def with that id, and its parameters.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueuedCommand {
+    pub id: i32,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub params: Vec<f32>,
+}
+
+impl QueuedCommand {
+    /// The def a build order is for.
+    pub fn build_def(&self) -> Option<UnitDefId> {
+        (self.id < 0).then_some(UnitDefId(-self.id))
+    }
+}
+
+/// One of the AI's units still being built, as an [`GameEvent::Update`]
+/// samples it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Construction {
+    pub unit: UnitId,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unit_name: Option<String>,
+    /// Build progress in percent.
+    pub progress: f32,
+    /// Builders the bridge knows are on it: the one that started it and
+    /// any sent to repair or guard it since, until they go idle or get
+    /// other orders.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub builders: usize,
+}
+
+/// One resource as the engine's Economy callbacks report it.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct ResourceState {
+    pub current: f32,
+    pub income: f32,
+    pub usage: f32,
+    pub storage: f32,
+    /// Shared to the team by allies (overdrive payouts, excess sharing,
+    /// gifts) per second, on top of `income`. Absent from bridges
+    /// predating it.
+    #[serde(default, skip_serializing_if = "is_zero_rate")]
+    pub received: f32,
+}
+
+/// The AI team's economy, attached to throttled [`GameEvent::Update`]s.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct Economy {
+    pub metal: ResourceState,
+    pub energy: ResourceState,
+}
+
+/// An allied team's economy, as far as the engine shares it with us.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TeamEconomy {
+    pub team: TeamId,
+    pub metal: ResourceState,
+    pub energy: ResourceState,
+}
+
+/// The bridge's own counters over a game, sent with its final
+/// [`GameEvent::Release`].
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct BridgeStats {
+    /// UPDATE frames the bridge processed.
+    pub frames: u32,
+    pub events_sent: u64,
+    /// Events lost for want of a GameManager: raised before init, or
+    /// pushed out of a full backlog.
+    pub events_dropped: u64,
+    /// Engine events whose handling panicked; each was skipped.
+    pub panics: u32,
+}
+
+/// How far EMP has got toward stunning a unit, read after a paralyzer hit.
+/// Zero-K stuns a unit while its paralyze damage exceeds its max health.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Paralysis {
+    pub paralyze_damage: f32,
+    pub health: f32,
+    pub max_health: f32,
+}
+
+impl Paralysis {
+    /// Progress toward the stun: 1.0 and over is stunned.
+    pub fn level(&self) -> f32 {
+        if self.max_health > 0.0 {
+            self.paralyze_damage / self.max_health
+        } else {
+            0.0
+        }
+    }
+
+    pub fn stunned(&self) -> bool {
+        self.level() >= 1.0
+    }
+}
+
+/// A unit definition, as listed in [`GameEvent::UnitDefs`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UnitDefInfo {
+    pub id: UnitDefId,
+    pub name: String,
+    pub human_name: String,
+    /// The def's tooltip, e.g. "Cloaked Anti-Air Bot".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub metal_cost: f32,
+    pub energy_cost: f32,
+    pub build_time: f32,
+    pub health: f32,
+    /// Zero for structures.
+    pub speed: f32,
+    pub builder: bool,
+    /// Def ids this def can build.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub build_options: Vec<UnitDefId>,
+    /// Metal and energy made per second just by existing (solars, fusions,
+    /// commanders). Absent from bridges predating it.
+    #[serde(default, skip_serializing_if = "is_zero_rate")]
+    pub metal_make: f32,
+    #[serde(default, skip_serializing_if = "is_zero_rate")]
+    pub energy_make: f32,
+    /// Metal extraction rate; nonzero for metal extractors.
+    #[serde(default, skip_serializing_if = "is_zero_rate")]
+    pub extracts_metal: f32,
+    /// Build power, in build time per second; nonzero for builders. Absent
+    /// from bridges predating it.
+    #[serde(default, skip_serializing_if = "is_zero_rate")]
+    pub build_speed: f32,
+}
+
+fn is_zero_rate(v: &f32) -> bool {
+    *v == 0.0
+}
+
+/// An event sent by the SAI bridge to the GameManager.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum GameEvent {
+    #[serde(rename = "init")]
+    Init {
+        frame: i32,
+        saved_game: bool,
+        /// Wire protocol version of the bridge ([`crate::PROTOCOL_VERSION`]).
+        /// Absent from bridges predating the shared protocol crate.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        protocol_version: Option<u32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        metal_spots: Option<Vec<MetalSpot>>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        map_width: Option<i32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        map_height: Option<i32>,
+        /// The AI team's start position, as the engine placed it.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        start_pos: Option<[f32; 3]>,
+        /// Every team in the game, the AI's own included.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        teams: Vec<TeamSlot>,
+        /// The bridge's build and the engine's version; absent from older
+        /// bridges.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        build: Option<BuildInfo>,
+    },
+    /// The bridge is shutting down. `reason` is the engine's (0 when it
+    /// gave none); the bridge's counters come along when it has them.
+    #[serde(rename = "release")]
+    Release {
+        reason: i32,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        stats: Option<BridgeStats>,
+    },
+    #[serde(rename = "update")]
+    Update {
+        frame: i32,
+        /// Turn mode: the bridge paused the engine and waits for `end_turn`.
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        awaiting_commands: bool,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        economy: Option<Economy>,
+        /// Allied teams' economies, in team order; empty without allies.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        allies: Vec<TeamEconomy>,
+        /// Aggregated event types (connection.json `aggregate_events`):
+        /// how often each unit raised each since the last update, for the
+        /// [`crate::MAX_COUNTED_UNITS`] busiest units.
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        counters: UnitCounters,
+        /// Commands received but not run yet, beyond the
+        /// [`crate::MAX_COMMANDS_PER_FRAME`] a frame.
+        #[serde(default, skip_serializing_if = "is_zero")]
+        command_backlog: usize,
+        /// The AI's units under construction and their progress.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        under_construction: Vec<Construction>,
+        /// Frames until the next update, as the bridge picked them (see
+        /// [`crate::UpdateCadence`]); absent from older bridges.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        update_interval: Option<u32>,
+    },
+    #[serde(rename = "message")]
+    Message {
+        player: i32,
+        /// The player's name from the game's setup script.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        player_name: Option<String>,
+        text: String,
+        /// The engine's text wasn't valid UTF-8; invalid bytes became U+FFFD.
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        text_lossy: bool,
+    },
+    #[serde(rename = "unit_created")]
+    UnitCreated {
+        unit: UnitId,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        unit_name: Option<String>,
+        builder: UnitId,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        builder_name: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pos: Option<[f32; 3]>,
+    },
+    /// A unit under construction made no progress since the last update
+    /// although builders are on it: the economy is stalled, or the
+    /// builders can't reach it. Sent once per stall.
+    #[serde(rename = "construction_stalled")]
+    ConstructionStalled {
+        unit: UnitId,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        unit_name: Option<String>,
+        progress: f32,
+        builders: usize,
+    },
+    #[serde(rename = "unit_finished")]
+    UnitFinished {
+        unit: UnitId,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        unit_name: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pos: Option<[f32; 3]>,
+    },
+    #[serde(rename = "unit_idle")]
+    UnitIdle {
+        unit: UnitId,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        unit_name: Option<String>,
+    },
+    #[serde(rename = "unit_move_failed")]
+    UnitMoveFailed {
+        unit: UnitId,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        unit_name: Option<String>,
+    },
+    #[serde(rename = "unit_damaged")]
+    UnitDamaged {
+        unit: UnitId,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        unit_name: Option<String>,
+        attacker: UnitId,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        attacker_name: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        attacker_team: Option<TeamId>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        attacker_relation: Option<Relation>,
+        damage: f32,
+        weapon_def_id: WeaponDefId,
+        paralyzer: bool,
+        /// With `paralyzer`: the unit's paralysis after the hit.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        paralysis: Option<Paralysis>,
+        /// Where the damaged unit is.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pos: Option<[f32; 3]>,
+    },
+    #[serde(rename = "unit_destroyed")]
+    UnitDestroyed {
+        unit: UnitId,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        unit_name: Option<String>,
+        attacker: UnitId,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        attacker_name: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        attacker_team: Option<TeamId>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        attacker_relation: Option<Relation>,
+        weapon_def_id: WeaponDefId,
+    },
+    /// A unit changed hands to our team: shared by a teammate, or taken
+    /// from an enemy.
+    #[serde(rename = "unit_given")]
+    UnitGiven {
+        unit: UnitId,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        unit_name: Option<String>,
+        old_team: TeamId,
+        new_team: TeamId,
+        /// Whose the team it came from is.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        old_relation: Option<Relation>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pos: Option<[f32; 3]>,
+    },
+    /// One of our units changed hands: shared away, or captured.
+    #[serde(rename = "unit_captured")]
+    UnitCaptured {
+        unit: UnitId,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        unit_name: Option<String>,
+        old_team: TeamId,
+        new_team: TeamId,
+        /// Whose the team it went to is.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        new_relation: Option<Relation>,
+    },
+    #[serde(rename = "enemy_enter_los")]
+    EnemyEnterLos {
+        enemy: UnitId,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        enemy_name: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        team: Option<TeamId>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        relation: Option<Relation>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pos: Option<[f32; 3]>,
+    },
+    #[serde(rename = "enemy_leave_los")]
+    EnemyLeaveLos {
+        enemy: UnitId,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        enemy_name: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        team: Option<TeamId>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        relation: Option<Relation>,
+    },
+    #[serde(rename = "enemy_enter_radar")]
+    EnemyEnterRadar {
+        enemy: UnitId,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        enemy_name: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        team: Option<TeamId>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        relation: Option<Relation>,
+    },
+    #[serde(rename = "enemy_leave_radar")]
+    EnemyLeaveRadar {
+        enemy: UnitId,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        enemy_name: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        team: Option<TeamId>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        relation: Option<Relation>,
+    },
+    #[serde(rename = "enemy_damaged")]
+    EnemyDamaged {
+        enemy: UnitId,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        enemy_name: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        team: Option<TeamId>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        relation: Option<Relation>,
+        attacker: UnitId,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        attacker_name: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        attacker_team: Option<TeamId>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        attacker_relation: Option<Relation>,
+        damage: f32,
+        weapon_def_id: WeaponDefId,
+        paralyzer: bool,
+        /// With `paralyzer`: the enemy's paralysis after the hit, when it's
+        /// in sight.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        paralysis: Option<Paralysis>,
+    },
+    #[serde(rename = "enemy_destroyed")]
+    EnemyDestroyed {
+        enemy: UnitId,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        enemy_name: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        team: Option<TeamId>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        relation: Option<Relation>,
+        attacker: UnitId,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        attacker_name: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        attacker_team: Option<TeamId>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        attacker_relation: Option<Relation>,
+    },
+    #[serde(rename = "enemy_created")]
+    EnemyCreated {
+        enemy: UnitId,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        enemy_name: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        team: Option<TeamId>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        relation: Option<Relation>,
+    },
+    #[serde(rename = "enemy_finished")]
+    EnemyFinished {
+        enemy: UnitId,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        enemy_name: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        team: Option<TeamId>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        relation: Option<Relation>,
+    },
+    #[serde(rename = "weapon_fired")]
+    WeaponFired {
+        unit: UnitId,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        unit_name: Option<String>,
+        weapon_def_id: WeaponDefId,
+    },
+    #[serde(rename = "command_finished")]
+    CommandFinished {
+        unit: UnitId,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        unit_name: Option<String>,
+        command_id: i32,
+        command_topic: i32,
+        /// The `command_ref` of the order that finished, when the bridge
+        /// can tell which it was.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        command_ref: Option<u64>,
+    },
+    #[serde(rename = "lua_message")]
+    LuaMessage {
+        data: String,
+        /// As `text_lossy` of a `message`.
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        data_lossy: bool,
+    },
+    #[serde(rename = "command_error")]
+    CommandError {
+        error: String,
+        command: String,
+        /// The failed command's `command_ref`, if it had one.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        command_ref: Option<u64>,
+    },
+    /// An engine event the bridge didn't trust, sent in its place: its data
+    /// pointer was null or its fields out of range, as when the engine's
+    /// event structs don't match the bridge's.
+    #[serde(rename = "malformed_event")]
+    MalformedEvent { topic: i32, reason: String },
+    /// Every unit the AI's team owns, sent right after `init` so units that
+    /// existed before the bridge connected (starting commander, facplop)
+    /// are known without waiting for events about them.
+    #[serde(rename = "roster")]
+    Roster { frame: i32, units: Vec<RosterUnit> },
+    /// The verdict on a dry-run command: `error` is what executing it would
+    /// have failed with, absent when it would have been dispatched.
+    #[serde(rename = "dry_run_result")]
+    DryRunResult {
+        request_id: u64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+    /// One chunk of the answer to a `query_unit_defs` command: `defs`
+    /// starts at index `offset` of the `total` defs.
+    #[serde(rename = "unit_defs")]
+    UnitDefs {
+        request_id: u64,
+        offset: usize,
+        total: usize,
+        defs: Vec<UnitDefInfo>,
+    },
+    /// One band of the answer to a `query_map_grid` command: `elevation`
+    /// holds rows `row_offset..` of a `width`×`height` grid of `cell_size`
+    /// cells, heights rounded to whole elmos. `buildable` has a string of
+    /// `0`/`1` per row when a build def was asked for; `error` is set (and
+    /// the rows empty) when the grid couldn't be sampled.
+    #[serde(rename = "map_grid")]
+    MapGrid {
+        request_id: u64,
+        cell_size: f32,
+        width: usize,
+        height: usize,
+        row_offset: usize,
+        elevation: Vec<Vec<i32>>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        buildable: Vec<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+    /// The answer to a `query_unit_queues` command. Units that no longer
+    /// exist or aren't ours are left out.
+    #[serde(rename = "unit_queues")]
+    UnitQueues { request_id: u64, queues: Vec<UnitQueue> },
+    /// The engine paused. `by_us` is set for the bridge's own turn-mode
+    /// pause; otherwise a player (or the host) paused the game.
+    #[serde(rename = "game_paused")]
+    GamePaused { by_us: bool },
+    #[serde(rename = "game_resumed")]
+    GameResumed,
+    /// The game speed changed, whoever changed it.
+    #[serde(rename = "speed_changed")]
+    SpeedChanged { speed: f32 },
+    /// An event whose `type` this build doesn't know (newer bridge).
+    /// Never produced by deserialization directly — see [`GameEvent::from_line`].
+    #[serde(rename = "unknown", skip_deserializing)]
+    Unknown { raw: serde_json::Value },
+}
+
+impl GameEvent {
+    /// The wire `type` tag of this event (the raw tag for unknown events).
+    pub fn type_name(&self) -> &str {
+        match self {
+            GameEvent::Init { .. } => "init",
+            GameEvent::Release { .. } => "release",
+            GameEvent::Update { .. } => "update",
+            GameEvent::Message { .. } => "message",
+            GameEvent::UnitCreated { .. } => "unit_created",
+            GameEvent::ConstructionStalled { .. } => "construction_stalled",
+            GameEvent::UnitFinished { .. } => "unit_finished",
+            GameEvent::UnitIdle { .. } => "unit_idle",
+            GameEvent::UnitMoveFailed { .. } => "unit_move_failed",
+            GameEvent::UnitDamaged { .. } => "unit_damaged",
+            GameEvent::UnitDestroyed { .. } => "unit_destroyed",
+            GameEvent::UnitGiven { .. } => "unit_given",
+            GameEvent::UnitCaptured { .. } => "unit_captured",
+            GameEvent::EnemyEnterLos { .. } => "enemy_enter_los",
+            GameEvent::EnemyLeaveLos { .. } => "enemy_leave_los",
+            GameEvent::EnemyEnterRadar { .. } => "enemy_enter_radar",
+            GameEvent::EnemyLeaveRadar { .. } => "enemy_leave_radar",
+            GameEvent::EnemyDamaged { .. } => "enemy_damaged",
+            GameEvent::EnemyDestroyed { .. } => "enemy_destroyed",
+            GameEvent::EnemyCreated { .. } => "enemy_created",
+            GameEvent::EnemyFinished { .. } => "enemy_finished",
+            GameEvent::WeaponFired { .. } => "weapon_fired",
+            GameEvent::CommandFinished { .. } => "command_finished",
+            GameEvent::LuaMessage { .. } => "lua_message",
+            GameEvent::CommandError { .. } => "command_error",
+            GameEvent::MalformedEvent { .. } => "malformed_event",
+            GameEvent::Roster { .. } => "roster",
+            GameEvent::DryRunResult { .. } => "dry_run_result",
+            GameEvent::UnitDefs { .. } => "unit_defs",
+            GameEvent::MapGrid { .. } => "map_grid",
+            GameEvent::UnitQueues { .. } => "unit_queues",
+            GameEvent::GamePaused { .. } => "game_paused",
+            GameEvent::GameResumed => "game_resumed",
+            GameEvent::SpeedChanged { .. } => "speed_changed",
+            GameEvent::Unknown { raw } => raw.get("type").and_then(|t| t.as_str()).unwrap_or("unknown"),
+        }
+    }
+
+    /// Parse one IPC line. Well-formed lines with an unrecognized `type`
+    /// become [`GameEvent::Unknown`] instead of an error, so a newer bridge
+    /// can talk to an older GameManager.
+    pub fn from_line(line: &str) -> Result<Self, serde_json::Error> {
+        let raw: serde_json::Value = serde_json::from_str(line)?;
+        crate::parse_tagged(raw, |raw| GameEvent::Unknown { raw })
+    }
+
+
+    /// The `type` tag of an [`GameEvent::Unknown`] event.
+    pub fn unknown_type(&self) -> Option<&str> {
+        match self {
+            GameEvent::Unknown { raw } => raw.get("type").and_then(|t| t.as_str()),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,49 @@
+//! Typed engine ids. On the wire each is the bare integer the engine uses;
+//! in code they keep a def id from being passed where a unit id belongs.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+macro_rules! engine_id {
+    ($(#[$doc:meta])* $name:ident) => {
+        $(#[$doc])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize)]
+        #[serde(transparent)]
+        pub struct $name(pub i32);
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                self.0.fmt(f)
+            }
+        }
+
+        impl From<i32> for $name {
+            fn from(id: i32) -> Self {
+                Self(id)
+            }
+        }
+    };
+}
+
+engine_id!(
+    /// A unit, ours or anyone's.
+    UnitId
+);
+engine_id!(
+    /// A unit def (unit type). The engine's def ids start at 1; 0 means none.
+    UnitDefId
+);
+engine_id!(
+    /// A team (one player's or AI's units), gaia included.
+    TeamId
+);
+engine_id!(
+    /// A weapon def.
+    WeaponDefId
+);
+engine_id!(
+    /// A feature: a wreck, a tree, a rock. Feature ids are counted apart
+    /// from unit ids, so the same number can name one of each.
+    FeatureId
+);
@@ -0,0 +1,443 @@
+//! Wire types shared by the SAI bridge and the GameManager.
+//!
+//! Both sides exchange newline-delimited JSON over a Unix socket:
+//! the bridge sends [`GameEvent`]s, the GameManager sends [`GameCommand`]s.
+//! Keeping the serde definitions in one crate guarantees both processes
+//! agree on the wire format; the bridge keeps its repr(C) structs and
+//! dispatch code, the GameManager its routing and formatting.
+//! The bridge's blocking socket client lives here too, so the GameManager's
+//! tests can drive the real client end to end.
+
+mod cadence;
+mod client;
+mod commands;
+mod events;
+mod ids;
+
+pub use cadence::{UpdateCadence, UpdateMode, MAX_UPDATE_INTERVAL, QUIET_UPDATES};
+pub use client::IpcClient;
+pub use commands::{ChatDestination, DryRun, Envelope, GameCommand, ReclaimTarget, Tracked};
+pub use ids::{FeatureId, TeamId, UnitDefId, UnitId, WeaponDefId};
+pub use events::{
+    BridgeStats, BuildInfo, Construction, Economy, GameEvent, MetalSpot, Paralysis, QueuedCommand, Relation, ResourceState, RosterUnit, TeamEconomy, TeamSlot,
+    UnitCounters, UnitDefInfo, UnitQueue,
+};
+
+/// Version of the IPC protocol. Bump on any incompatible change to
+/// [`GameEvent`] or [`GameCommand`]. Sent by the bridge in the init event.
+/// Version 2 added dry runs, which older bridges would execute; version 3
+/// added unit def queries, version 4 map grid queries, version 5 unit
+/// queue queries, version 6 command refs, version 7 reclaiming features.
+pub const PROTOCOL_VERSION: u32 = 7;
+
+/// Most cells along either side of a `map_grid` answer.
+pub const MAX_MAP_GRID_CELLS: usize = 256;
+
+/// Event types a bridge can aggregate into per-unit `update` counters
+/// instead of sending one event each.
+pub const AGGREGATABLE_EVENTS: &[&str] = &["weapon_fired", "command_finished", "unit_damaged"];
+
+/// The counter aggregated paralyzer `unit_damaged` events count under, apart
+/// from real damage.
+pub const PARALYZED_COUNTER: &str = "unit_paralyzed";
+
+/// Most units in an `update` event's `counters`; the busiest are kept.
+pub const MAX_COUNTED_UNITS: usize = 20;
+
+/// Most commands the bridge runs per frame; the rest wait for the next
+/// frames, counted in `update`'s `command_backlog`.
+pub const MAX_COMMANDS_PER_FRAME: usize = 100;
+
+/// Deserialize a tagged message, falling back to `unknown` when the `type`
+/// tag isn't one of `T`'s variants. Other errors (missing fields, wrong
+/// types on a known variant) are still reported.
+fn parse_tagged<T: serde::de::DeserializeOwned>(
+    raw: serde_json::Value,
+    unknown: impl FnOnce(serde_json::Value) -> T,
+) -> Result<T, serde_json::Error> {
+    match T::deserialize(&raw) {
+        Ok(v) => Ok(v),
+        Err(e) if raw.get("type").is_some_and(|t| t.is_string())
+            && e.to_string().starts_with("unknown variant") =>
+        {
+            Ok(unknown(raw))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn round_trip_event(event: GameEvent) {
+        let line = serde_json::to_string(&event).unwrap();
+        let back: GameEvent = serde_json::from_str(&line).unwrap();
+        assert_eq!(back, event, "round trip of {}", line);
+    }
+
+    fn round_trip_command(cmd: GameCommand) {
+        let line = serde_json::to_string(&cmd).unwrap();
+        let back: GameCommand = serde_json::from_str(&line).unwrap();
+        assert_eq!(back, cmd, "round trip of {}", line);
+    }
+
+    #[test]
+    fn test_event_round_trip() {
+        round_trip_event(GameEvent::Init {
+            frame: 0,
+            saved_game: false,
+            protocol_version: Some(PROTOCOL_VERSION),
+            metal_spots: Some(vec![MetalSpot { x: 1.0, y: 2.0, z: 3.0, metal: 2.5 }]),
+            map_width: Some(512),
+            map_height: Some(256),
+            start_pos: Some([100.0, 12.0, 300.0]),
+            teams: vec![
+                TeamSlot { team: TeamId(0), ally_team: 0, relation: Relation::Mine },
+                TeamSlot { team: TeamId(1), ally_team: 1, relation: Relation::Enemy },
+            ],
+            build: Some(BuildInfo {
+                bridge_version: "0.1.0".into(),
+                git_hash: Some("abc1234".into()),
+                engine_version: Some("105.1.1-2511-g747f18b".into()),
+            }),
+        });
+        round_trip_event(GameEvent::UnitDamaged {
+            unit: UnitId(5),
+            unit_name: Some("cloakraid".into()),
+            attacker: UnitId(9),
+            attacker_name: None,
+            attacker_team: Some(TeamId(1)),
+            attacker_relation: Some(Relation::Enemy),
+            damage: 12.5,
+            weapon_def_id: WeaponDefId(3),
+            paralyzer: false,
+            paralysis: None,
+            pos: Some([100.0, 5.0, 200.0]),
+        });
+        round_trip_event(GameEvent::UnitDamaged {
+            unit: UnitId(5),
+            unit_name: None,
+            attacker: UnitId(9),
+            attacker_name: None,
+            attacker_team: None,
+            attacker_relation: None,
+            damage: 400.0,
+            weapon_def_id: WeaponDefId(4),
+            paralyzer: true,
+            paralysis: Some(Paralysis { paralyze_damage: 510.0, health: 340.0, max_health: 850.0 }),
+            pos: None,
+        });
+        let stun = Paralysis { paralyze_damage: 510.0, health: 340.0, max_health: 850.0 };
+        assert!((stun.level() - 0.6).abs() < 1e-6 && !stun.stunned());
+        assert!(Paralysis { paralyze_damage: 900.0, ..stun }.stunned());
+        round_trip_event(GameEvent::EnemyDestroyed {
+            enemy: UnitId(90),
+            enemy_name: Some("critter_crab".into()),
+            team: Some(TeamId(3)),
+            relation: Some(Relation::Gaia),
+            attacker: UnitId(5),
+            attacker_name: Some("cloakraid".into()),
+            attacker_team: Some(TeamId(2)),
+            attacker_relation: Some(Relation::Ally),
+        });
+        assert_eq!(serde_json::to_value(Relation::Gaia).unwrap(), json!("gaia"));
+        round_trip_event(GameEvent::CommandError {
+            error: "unit 4 does not exist".into(),
+            command: "Stop { unit_id: 4 }".into(),
+            command_ref: Some(3),
+        });
+        round_trip_event(GameEvent::Message { player: 1, player_name: None, text: "caf\u{fffd}".into(), text_lossy: true });
+        assert_eq!(
+            serde_json::from_value::<GameEvent>(json!({"type": "lua_message", "data": "mex_claimed 3"})).unwrap(),
+            GameEvent::LuaMessage { data: "mex_claimed 3".into(), data_lossy: false }
+        );
+        round_trip_event(GameEvent::MalformedEvent { topic: 9, reason: "damage NaN is not a finite amount".into() });
+        round_trip_event(GameEvent::Release { reason: 0, stats: None });
+        round_trip_event(GameEvent::Release {
+            reason: 2,
+            stats: Some(BridgeStats { frames: 9000, events_sent: 412, events_dropped: 3, panics: 1 }),
+        });
+        round_trip_event(GameEvent::Update {
+            frame: 90,
+            awaiting_commands: true,
+            economy: Some(Economy {
+                metal: ResourceState { current: 120.0, income: 4.5, usage: 3.0, storage: 500.0, received: 0.0 },
+                energy: ResourceState { current: 80.0, income: 12.0, usage: 9.5, storage: 500.0, received: 6.0 },
+            }),
+            allies: vec![TeamEconomy {
+                team: TeamId(2),
+                metal: ResourceState { current: 40.0, income: 9.0, usage: 8.0, storage: 500.0, received: 0.0 },
+                energy: ResourceState::default(),
+            }],
+            counters: [("12".to_string(), [("weapon_fired".to_string(), 14)].into())].into(),
+            command_backlog: 40,
+            under_construction: vec![Construction { unit: UnitId(30), unit_name: Some("energyfusion".into()), progress: 42.5, builders: 2 }],
+            update_interval: None,
+        });
+        round_trip_event(GameEvent::ConstructionStalled { unit: UnitId(30), unit_name: None, progress: 42.5, builders: 1 });
+        round_trip_event(GameEvent::Roster {
+            frame: 0,
+            units: vec![RosterUnit { unit: UnitId(5), unit_name: Some("dyntrainer_strike_base".into()), pos: [100.0, 8.0, 200.0] }],
+        });
+        round_trip_event(GameEvent::UnitDefs {
+            request_id: 4,
+            offset: 50,
+            total: 51,
+            defs: vec![UnitDefInfo {
+                id: UnitDefId(51),
+                name: "factorycloak".into(),
+                human_name: "Cloakbot Factory".into(),
+                description: Some("Produces Cloaked Robots".into()),
+                metal_cost: 400.0,
+                energy_cost: 400.0,
+                build_time: 400.0,
+                health: 4000.0,
+                speed: 0.0,
+                builder: true,
+                build_options: vec![UnitDefId(12), UnitDefId(13)],
+                metal_make: 0.0,
+                energy_make: 0.0,
+                extracts_metal: 0.0,
+                build_speed: 10.0,
+            }, UnitDefInfo {
+                id: UnitDefId(52),
+                name: "energysolar".into(),
+                human_name: "Solar Collector".into(),
+                description: None,
+                metal_cost: 70.0,
+                energy_cost: 0.0,
+                build_time: 70.0,
+                health: 500.0,
+                speed: 0.0,
+                builder: false,
+                build_options: Vec::new(),
+                metal_make: 0.0,
+                energy_make: 2.0,
+                extracts_metal: 0.0,
+                build_speed: 0.0,
+            }],
+        });
+        round_trip_event(GameEvent::UnitQueues {
+            request_id: 7,
+            queues: vec![UnitQueue {
+                unit: UnitId(40),
+                commands: vec![QueuedCommand { id: -12, params: Vec::new() }, QueuedCommand { id: 10, params: vec![100.0, 0.0, 200.0] }],
+            }],
+        });
+        round_trip_event(GameEvent::MapGrid {
+            request_id: 5,
+            cell_size: 256.0,
+            width: 3,
+            height: 2,
+            row_offset: 0,
+            elevation: vec![vec![10, 12, -4], vec![11, 40, 90]],
+            buildable: vec!["110".into(), "100".into()],
+            error: None,
+        });
+        round_trip_event(GameEvent::GamePaused { by_us: false });
+        round_trip_event(GameEvent::GameResumed);
+        round_trip_event(GameEvent::SpeedChanged { speed: 2.5 });
+        assert_eq!(serde_json::to_value(GameEvent::GameResumed).unwrap(), json!({"type": "game_resumed"}));
+    }
+
+    #[test]
+    fn test_command_round_trip() {
+        round_trip_command(GameCommand::Move { unit_id: UnitId(1), x: 10.0, y: 0.0, z: 20.0, queue: true });
+        // Typed ids are bare integers on the wire.
+        assert_eq!(
+            serde_json::to_value(GameCommand::Guard { unit_id: UnitId(1), guard_id: UnitId(2), queue: false }).unwrap(),
+            json!({"type": "guard", "unit_id": 1, "guard_id": 2, "queue": false})
+        );
+        round_trip_command(GameCommand::Build {
+            unit_id: UnitId(1),
+            build_def_id: UnitDefId(0),
+            build_def_name: Some("staticmex".into()),
+            x: 100.0,
+            y: 0.0,
+            z: 200.0,
+            facing: 1,
+            queue: false,
+        });
+        round_trip_command(GameCommand::SendChat {
+            text: "gl hf".into(),
+            destination: ChatDestination::Allies,
+        });
+        // Older GameManagers send no destination: chat goes to everyone.
+        let chat: GameCommand = serde_json::from_value(json!({"type": "send_chat", "text": "gl hf"})).unwrap();
+        assert_eq!(
+            chat,
+            GameCommand::SendChat { text: "gl hf".into(), destination: ChatDestination::All }
+        );
+        round_trip_command(GameCommand::Custom { unit_id: UnitId(3), command_id: 34223, params: vec![2.0], queue: false });
+        round_trip_command(GameCommand::Wait { unit_id: UnitId(12), queue: true });
+        round_trip_command(GameCommand::TimeWait { unit_id: UnitId(12), seconds: 10, queue: true });
+        // An unqueued wait sits in front of the queue instead of replacing it.
+        assert_eq!(GameCommand::Wait { unit_id: UnitId(12), queue: false }.order(), None);
+        assert_eq!(GameCommand::TimeWait { unit_id: UnitId(12), seconds: 10, queue: true }.order(), Some((UnitId(12), true)));
+        round_trip_command(GameCommand::SelfDestruct { unit_id: UnitId(12) });
+        // Unit ids go over the line as a plain array of numbers.
+        let load = GameCommand::LoadUnits { unit_id: UnitId(30), target_ids: vec![UnitId(12), UnitId(13)], queue: true };
+        let line = serde_json::to_value(&load).unwrap();
+        assert_eq!(line, json!({"type": "load_units", "unit_id": 30, "target_ids": [12, 13], "queue": true}));
+        assert_eq!(GameCommand::from_line(&line.to_string()).unwrap(), load);
+        assert!(serde_json::from_value::<GameCommand>(json!({"type": "load_units", "unit_id": 30, "target_ids": 12})).is_err());
+        round_trip_command(GameCommand::LoadOntoTransport { unit_id: UnitId(12), transport_id: UnitId(30) });
+        round_trip_command(GameCommand::UnloadUnits { unit_id: UnitId(30), x: 900.0, y: 0.0, z: 400.0, radius: 100.0, queue: false });
+        let at_unit = GameCommand::ManualFire { unit_id: UnitId(1), target_id: Some(UnitId(90)), x: None, y: 0.0, z: None, queue: false };
+        assert_eq!(serde_json::to_value(&at_unit).unwrap(), json!({"type": "manual_fire", "unit_id": 1, "target_id": 90, "y": 0.0, "queue": false}));
+        round_trip_command(at_unit);
+        let at_ground: GameCommand =
+            serde_json::from_value(json!({"type": "manual_fire", "unit_id": 1, "x": 900, "z": 400, "queue": true})).unwrap();
+        assert_eq!(
+            at_ground,
+            GameCommand::ManualFire { unit_id: UnitId(1), target_id: None, x: Some(900.0), y: 0.0, z: Some(400.0), queue: true }
+        );
+        round_trip_command(at_ground);
+        round_trip_command(GameCommand::AttackGround { unit_id: UnitId(12), x: 900.0, y: 30.0, z: 400.0, queue: false });
+        round_trip_command(GameCommand::AttackArea { unit_id: UnitId(12), x: 900.0, y: 0.0, z: 400.0, radius: 150.0, queue: true });
+        // A reclaim names a unit or a feature, by the key its id goes under.
+        let unit = GameCommand::Reclaim { unit_id: UnitId(12), target: ReclaimTarget::Unit(UnitId(40)), queue: true };
+        let line = serde_json::to_value(&unit).unwrap();
+        assert_eq!(line, json!({"type": "reclaim", "unit_id": 12, "target_id": 40, "queue": true}));
+        round_trip_command(unit);
+        let wreck: GameCommand = serde_json::from_value(json!({"type": "reclaim", "unit_id": 12, "feature_id": 40})).unwrap();
+        assert_eq!(wreck, GameCommand::Reclaim { unit_id: UnitId(12), target: ReclaimTarget::Feature(FeatureId(40)), queue: false });
+        round_trip_command(wreck);
+        assert!(serde_json::from_value::<GameCommand>(json!({"type": "reclaim", "unit_id": 12})).is_err());
+        let area: GameCommand =
+            serde_json::from_value(json!({"type": "reclaim_area", "unit_id": 12, "x": 1000, "z": 1500, "radius": 300})).unwrap();
+        assert_eq!(
+            area,
+            GameCommand::ReclaimArea { unit_id: UnitId(12), x: 1000.0, y: 0.0, z: 1500.0, radius: 300.0, queue: false }
+        );
+        round_trip_command(area);
+        round_trip_command(GameCommand::DrawPoint { x: 1200.0, z: 800.0, label: "here".into() });
+        round_trip_command(GameCommand::Pause);
+        round_trip_command(GameCommand::SetTurnMode { enabled: true });
+        round_trip_command(GameCommand::SetUpdateInterval { frames: Some(15), mode: None });
+        round_trip_command(GameCommand::SetUpdateInterval { frames: None, mode: Some(UpdateMode::Adaptive) });
+        round_trip_command(GameCommand::EndTurn);
+        round_trip_command(GameCommand::QueryUnitDefs { request_id: 4 });
+        round_trip_command(GameCommand::QueryMapGrid { request_id: 5, cell_size: 128.0, build_def: None });
+        round_trip_command(GameCommand::QueryMapGrid {
+            request_id: 6,
+            cell_size: 128.0,
+            build_def: Some("staticmex".into()),
+        });
+        round_trip_command(GameCommand::QueryUnitQueues { request_id: 7, units: vec![UnitId(40), UnitId(41)] });
+    }
+
+    #[test]
+    fn test_tracked_envelope() {
+        let tracked = Tracked { command_ref: 12, command: GameCommand::Stop { unit_id: UnitId(4) } };
+        let line = tracked.to_line();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&line).unwrap(),
+            json!({"type": "stop", "unit_id": 4, "command_ref": 12})
+        );
+        assert_eq!(GameCommand::from_envelope(&line).unwrap(), (tracked.command, Envelope { dry_run: None, command_ref: Some(12) }));
+
+        round_trip_event(GameEvent::CommandFinished {
+            unit: UnitId(4),
+            unit_name: None,
+            command_id: -1,
+            command_topic: 10,
+            command_ref: Some(12),
+        });
+        let unref = json!({"type": "command_error", "error": "unit 4 does not exist", "command": "Stop"});
+        assert_eq!(
+            serde_json::from_value::<GameEvent>(unref).unwrap(),
+            GameEvent::CommandError { error: "unit 4 does not exist".into(), command: "Stop".into(), command_ref: None }
+        );
+    }
+
+    #[test]
+    fn test_order_supersedes_unless_queued() {
+        let moved = |queue| GameCommand::Move { unit_id: UnitId(4), x: 1.0, y: 0.0, z: 1.0, queue };
+        assert_eq!(moved(true).order(), Some((UnitId(4), true)));
+        assert_eq!(moved(false).order(), Some((UnitId(4), false)));
+        assert_eq!(GameCommand::Stop { unit_id: UnitId(4) }.order(), Some((UnitId(4), false)));
+        assert_eq!(GameCommand::SetFireState { unit_id: UnitId(4), state: 2 }.order(), None);
+        assert_eq!(GameCommand::Pause.order(), None);
+    }
+
+    #[test]
+    fn test_dry_run_envelope() {
+        let dry_run = DryRun { request_id: 7, command: GameCommand::Stop { unit_id: UnitId(4) } };
+        let line = dry_run.to_line();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&line).unwrap(),
+            json!({"type": "stop", "unit_id": 4, "dry_run": true, "request_id": 7})
+        );
+        assert_eq!(GameCommand::from_envelope(&line).unwrap(), (dry_run.command, Envelope { dry_run: Some(7), command_ref: None }));
+        let plain = r#"{"type":"stop","unit_id":4,"dry_run":false}"#;
+        assert_eq!(GameCommand::from_envelope(plain).unwrap().1, Envelope::default());
+
+        round_trip_event(GameEvent::DryRunResult { request_id: 7, error: None });
+        round_trip_event(GameEvent::DryRunResult { request_id: 8, error: Some("unit 4 does not exist".into()) });
+    }
+
+    #[test]
+    fn test_enrichment_fields_optional() {
+        // Unenriched events omit the optional fields on the wire...
+        let line = serde_json::to_value(GameEvent::UnitIdle { unit: UnitId(7), unit_name: None }).unwrap();
+        assert_eq!(line, json!({"type": "unit_idle", "unit": 7}));
+        let update = serde_json::to_value(GameEvent::Update { frame: 30, awaiting_commands: false, economy: None, allies: Vec::new(), counters: Default::default(), command_backlog: 0, under_construction: Vec::new(), update_interval: None }).unwrap();
+        assert_eq!(update, json!({"type": "update", "frame": 30}));
+        // ...and bridges predating protocol_version still parse.
+        let init: GameEvent =
+            serde_json::from_value(json!({"type": "init", "frame": 0, "saved_game": false})).unwrap();
+        assert!(matches!(init, GameEvent::Init { protocol_version: None, .. }));
+    }
+
+    #[test]
+    fn test_unknown_type_preserved() {
+        let line = r#"{"type":"unit_teleported","unit":4,"to":[1,2,3]}"#;
+        let event = GameEvent::from_line(line).unwrap();
+        assert_eq!(event.unknown_type(), Some("unit_teleported"));
+        match &event {
+            GameEvent::Unknown { raw } => assert_eq!(raw["to"], json!([1, 2, 3])),
+            other => panic!("expected Unknown, got {:?}", other),
+        }
+
+        let cmd = GameCommand::from_line(r#"{"type":"teleport","unit_id":4}"#).unwrap();
+        assert!(matches!(cmd, GameCommand::Unknown { .. }));
+    }
+
+    #[test]
+    fn test_malformed_known_type_is_error() {
+        // A known type with missing fields is a real error, not Unknown
+        assert!(GameEvent::from_line(r#"{"type":"unit_idle"}"#).is_err());
+        assert!(GameCommand::from_line(r#"{"type":"move","unit_id":1}"#).is_err());
+        assert!(GameEvent::from_line(r#"{"unit":1}"#).is_err());
+        assert!(GameEvent::from_line("not json").is_err());
+    }
+
+    #[test]
+    fn test_type_name_matches_wire_tag() {
+        let events = [
+            GameEvent::Update { frame: 30, awaiting_commands: false, economy: None, allies: Vec::new(), counters: Default::default(), command_backlog: 0, under_construction: Vec::new(), update_interval: None },
+            GameEvent::UnitIdle { unit: UnitId(1), unit_name: None },
+            GameEvent::CommandError { error: String::new(), command: String::new(), command_ref: None },
+            GameEvent::MalformedEvent { topic: 5, reason: String::new() },
+        ];
+        for event in &events {
+            assert_eq!(serde_json::to_value(event).unwrap()["type"], event.type_name());
+        }
+        let cmds = [GameCommand::Pause, GameCommand::Stop { unit_id: UnitId(1) }];
+        for cmd in &cmds {
+            assert_eq!(serde_json::to_value(cmd).unwrap()["type"], cmd.type_name());
+        }
+        let unknown = GameEvent::from_line(r#"{"type":"future_thing"}"#).unwrap();
+        assert_eq!(unknown.type_name(), "future_thing");
+    }
+
+    #[test]
+    fn test_command_defaults() {
+        let cmd: GameCommand =
+            serde_json::from_value(json!({"type": "move", "unit_id": 3, "x": 1.0, "z": 2.0})).unwrap();
+        assert_eq!(cmd, GameCommand::Move { unit_id: UnitId(3), x: 1.0, y: 0.0, z: 2.0, queue: false });
+    }
+}